@@ -0,0 +1,152 @@
+//! Append-only record of state-changing operations (arming, deletes, config writes,
+//! liveview, login/logout). The `BlinkClient` wrappers call `track`, so anything that goes
+//! through the shared client is logged whichever front end issued it.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+pub const AUDIT_FILE: &str = "audit.log";
+/// Once the log grows past this it is moved to `audit.log.1`, replacing the previous one.
+pub const MAX_LOG_BYTES: u64 = 2 * 1024 * 1024;
+pub const DEFAULT_LIMIT: usize = 100;
+pub const MAX_LIMIT: usize = 5000;
+
+static LOG: OnceLock<AuditLog> = OnceLock::new();
+
+tokio::task_local! {
+    /// Who issued the operations running in this task, e.g. `api-key:1a2b3c4d`.
+    static SOURCE: String;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub ts: DateTime<Utc>,
+    pub action: String,
+    pub target: String,
+    pub ok: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub source: String,
+}
+
+/// Every field narrows the result; `action` and `source` match exactly, `target` by substring.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub source: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub failed_only: bool,
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.action.as_ref().is_none_or(|a| &entry.action == a)
+            && self.target.as_ref().is_none_or(|t| entry.target.contains(t.as_str()))
+            && self.source.as_ref().is_none_or(|s| &entry.source == s)
+            && self.since.is_none_or(|since| entry.ts >= since)
+            && self.until.is_none_or(|until| entry.ts <= until)
+            && (!self.failed_only || !entry.ok)
+    }
+}
+
+struct AuditLog {
+    path: PathBuf,
+    default_source: String,
+    write: Mutex<()>,
+}
+
+fn rotated(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+impl AuditLog {
+    fn append(&self, entry: &AuditEntry) -> Result<()> {
+        let _guard = self.write.lock().unwrap();
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() >= MAX_LOG_BYTES) {
+            fs::rename(&self.path, rotated(&self.path))?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    fn read(&self, limit: usize, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let mut entries = Vec::new();
+        for path in [rotated(&self.path), self.path.clone()] {
+            let file = match fs::File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            // A torn final line from a crash is skipped rather than failing the whole read.
+            entries.extend(BufReader::new(file).lines()
+                .map_while(|line| line.ok())
+                .filter_map(|line| serde_json::from_str::<AuditEntry>(&line).ok())
+                .filter(|entry| filter.matches(entry)));
+        }
+        entries.reverse();
+        entries.truncate(limit);
+        Ok(entries)
+    }
+}
+
+/// Sets where entries are written and the source used outside `with_source`. Until this
+/// is called, `track` and `record` do nothing.
+pub fn init(path: PathBuf, default_source: &str) {
+    let _ = LOG.set(AuditLog { path, default_source: default_source.to_string(), write: Mutex::new(()) });
+}
+
+/// Runs `fut` with its audited operations attributed to `source`.
+pub async fn with_source<F: Future>(source: String, fut: F) -> F::Output {
+    SOURCE.scope(source, fut).await
+}
+
+fn current_source(log: &AuditLog) -> String {
+    SOURCE.try_with(Clone::clone).unwrap_or_else(|_| log.default_source.clone())
+}
+
+/// Writes one entry. Failing to write the log never fails the operation itself.
+pub fn record(action: &str, target: impl Display, error: Option<String>) {
+    let Some(log) = LOG.get() else { return };
+    let entry = AuditEntry {
+        ts: Utc::now(),
+        action: action.to_string(),
+        target: target.to_string(),
+        ok: error.is_none(),
+        error,
+        source: current_source(log),
+    };
+    if let Err(e) = log.append(&entry) {
+        eprintln!("Failed to write audit log: {}", e);
+    }
+}
+
+/// Awaits `fut` and records its outcome.
+pub async fn track<T>(action: &str, target: impl Display, fut: impl Future<Output = Result<T>>) -> Result<T> {
+    let result = fut.await;
+    record(action, target, result.as_ref().err().map(|e| e.to_string()));
+    result
+}
+
+/// Newest entries first, at most `limit` (default `DEFAULT_LIMIT`, capped at `MAX_LIMIT`).
+pub fn read(limit: Option<usize>, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+    let Some(log) = LOG.get() else { return Ok(Vec::new()) };
+    log.read(limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT), filter)
+}
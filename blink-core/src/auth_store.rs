@@ -0,0 +1,683 @@
+//! Auth state persistence shared by the desktop app and the server. State goes to the OS
+//! keychain when enabled (and the crate is built with the `keychain` feature), otherwise
+//! to an encrypted-at-rest `auth.json`.
+//!
+//! Files are a versioned envelope `{"v":1,"kdf":..,"salt":..,"nonce":..,"ct":..}` holding the
+//! ChaCha20-Poly1305-sealed `BlinkAuthState`. Files written before encryption existed are
+//! the bare state JSON and are still read; the next save replaces them with an envelope.
+//! Whatever the container, the state inside goes through `BlinkAuthState::from_json`, which
+//! upgrades older layouts and turns an unreadable one into an error saying how to recover.
+
+use crate::blink::BlinkAuthState;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+#[cfg(feature = "keychain")]
+use keyring::Entry;
+#[cfg(not(feature = "keychain"))]
+use no_keychain::Entry;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
+
+pub const PASSPHRASE_ENV: &str = "BLINK_AUTH_PASSPHRASE";
+const KEYRING_SERVICE: &str = "blink-monitor";
+const KEYRING_USERNAME: &str = "auth";
+/// How errors name the keychain entry holding the state.
+const KEYCHAIN_SOURCE: &str = "the \"blink-monitor\" keychain entry";
+/// Random key for `auth.json` when the state itself can't go in the keyring.
+const KEYRING_FILE_KEY_USERNAME: &str = "auth-file-key";
+const ENVELOPE_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Where the file key comes from. Both kinds are wiped from memory when dropped, as are
+/// the derived keys and every plaintext state passing through this module.
+pub enum AuthKey {
+    /// User-supplied passphrase, stretched with Argon2id.
+    Passphrase(SecretString),
+    /// Random 32-byte key kept somewhere safer than the file (the OS keyring).
+    Raw(Zeroizing<[u8; 32]>),
+}
+
+impl AuthKey {
+    pub fn from_env() -> Option<AuthKey> {
+        std::env::var(PASSPHRASE_ENV)
+            .ok()
+            .filter(|p| !p.is_empty())
+            .map(|p| AuthKey::Passphrase(p.into()))
+    }
+
+    fn kdf_name(&self) -> &'static str {
+        match self {
+            AuthKey::Passphrase(_) => "argon2id",
+            AuthKey::Raw(_) => "raw-sha256",
+        }
+    }
+
+    fn derive(&self, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        match self {
+            AuthKey::Passphrase(passphrase) => {
+                argon2::Argon2::default()
+                    .hash_password_into(passphrase.expose_secret().as_bytes(), salt, key.as_mut())
+                    .map_err(|e| anyhow!("Key derivation failed: {}", e))?;
+            }
+            AuthKey::Raw(raw) => {
+                let mut hasher = Sha256::new();
+                hasher.update(raw.as_ref());
+                hasher.update(salt);
+                key.copy_from_slice(&hasher.finalize());
+            }
+        }
+        Ok(key)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    v: u32,
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ct: String,
+}
+
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// `BLINK_DISABLE_KEYCHAIN` / `BLINK_USE_KEYCHAIN` override the caller's default. Always
+/// false without the `keychain` feature.
+pub fn keychain_enabled(default: bool) -> bool {
+    if !cfg!(feature = "keychain") || env_flag("BLINK_DISABLE_KEYCHAIN") {
+        return false;
+    }
+    if env_flag("BLINK_USE_KEYCHAIN") {
+        return true;
+    }
+    default
+}
+
+fn allow_plaintext_auth() -> bool {
+    env_flag("BLINK_ALLOW_PLAINTEXT_AUTH")
+}
+
+fn keyring_entry(username: &str) -> Result<Entry> {
+    Entry::new(KEYRING_SERVICE, username)
+        .map_err(|e| anyhow!("Failed to open keychain entry: {}", e))
+}
+
+/// Stand-in for `keyring::Entry` in builds without the `keychain` feature: no entry can be
+/// opened, so `AuthStore` always ends up on the file.
+#[cfg(not(feature = "keychain"))]
+mod no_keychain {
+    use std::io::{Error, Result};
+
+    pub struct Entry;
+
+    fn unsupported() -> Error {
+        Error::other("built without keychain support")
+    }
+
+    impl Entry {
+        pub fn new(_service: &str, _username: &str) -> Result<Self> {
+            Err(unsupported())
+        }
+
+        pub fn get_password(&self) -> Result<String> {
+            Err(unsupported())
+        }
+
+        pub fn set_password(&self, _password: &str) -> Result<()> {
+            Err(unsupported())
+        }
+
+        pub fn delete_password(&self) -> Result<()> {
+            Err(unsupported())
+        }
+    }
+}
+
+/// Serializes and seals `state` into an envelope document.
+pub fn seal(state: &BlinkAuthState, key: &AuthKey) -> Result<String> {
+    seal_bytes(&Zeroizing::new(serde_json::to_vec(state)?), key)
+}
+
+/// Seals arbitrary bytes into an envelope document; `backup` archives use it too.
+pub(crate) fn seal_bytes(plaintext: &[u8], key: &AuthKey) -> Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.derive(&salt)?.as_ref()));
+    let ct = cipher
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| anyhow!("Failed to encrypt"))?;
+
+    Ok(serde_json::to_string(&Envelope {
+        v: ENVELOPE_VERSION,
+        kdf: key.kdf_name().to_string(),
+        salt: STANDARD.encode(salt),
+        nonce: STANDARD.encode(nonce),
+        ct: STANDARD.encode(ct),
+    })?)
+}
+
+/// Parses an auth file's contents: an envelope (which needs `key`) or legacy plaintext.
+pub fn open(contents: &str, key: Option<&AuthKey>) -> Result<BlinkAuthState> {
+    let Ok(envelope) = serde_json::from_str::<Envelope>(contents) else {
+        return BlinkAuthState::from_json(contents, "auth.json");
+    };
+    let key = key.ok_or_else(|| anyhow!("auth.json is encrypted; set {} to unlock it", PASSPHRASE_ENV))?;
+    let plaintext = open_envelope(envelope, key, "auth.json")?;
+    let json = std::str::from_utf8(&plaintext).map_err(|_| anyhow!("auth.json does not hold text once decrypted"))?;
+    BlinkAuthState::from_json(json, "auth.json")
+}
+
+/// Opens an envelope written by `seal_bytes`; `what` names the file in errors.
+pub(crate) fn open_bytes(contents: &str, key: &AuthKey, what: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let envelope = serde_json::from_str::<Envelope>(contents).map_err(|_| anyhow!("{} is not an encrypted envelope", what))?;
+    open_envelope(envelope, key, what)
+}
+
+fn open_envelope(envelope: Envelope, key: &AuthKey, what: &str) -> Result<Zeroizing<Vec<u8>>> {
+    if envelope.v != ENVELOPE_VERSION {
+        return Err(anyhow!("Unsupported {} envelope version {}", what, envelope.v));
+    }
+    if envelope.kdf != key.kdf_name() {
+        return Err(anyhow!("{} was encrypted with a {} key, but a {} key is configured", what, envelope.kdf, key.kdf_name()));
+    }
+
+    let salt = STANDARD.decode(&envelope.salt)?;
+    let nonce = STANDARD.decode(&envelope.nonce)?;
+    let ct = STANDARD.decode(&envelope.ct)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(anyhow!("Malformed auth file nonce"));
+    }
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key.derive(&salt)?.as_ref()));
+    cipher
+        .decrypt(Nonce::from_slice(&nonce), ct.as_slice())
+        .map(Zeroizing::new)
+        .map_err(|_| anyhow!("Failed to decrypt {} (wrong passphrase or corrupted file)", what))
+}
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(|n| n.to_os_string()).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Limits the file to the current user: 0600 on Unix, an owner-only ACL on Windows.
+#[cfg(unix)]
+pub(crate) fn restrict_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
+
+#[cfg(windows)]
+pub(crate) fn restrict_permissions(path: &Path) -> Result<()> {
+    let user = std::env::var("USERNAME").map_err(|_| anyhow!("USERNAME is not set"))?;
+    let status = std::process::Command::new("icacls")
+        .arg(path)
+        .args(["/inheritance:r", "/grant:r"])
+        .arg(format!("{}:F", user))
+        .stdout(std::process::Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("icacls exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn restrict_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Replaces `path` so that a crash at any point leaves either the old or the new contents:
+/// write a temp file beside it, fsync, rename over, fsync the directory. The previous file
+/// is kept as `.bak` first so a later corruption still has something to fall back to.
+pub(crate) fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+
+    let tmp = sibling(path, ".tmp");
+    {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+    }
+    if let Err(e) = restrict_permissions(&tmp) {
+        eprintln!("Failed to restrict permissions on {}: {}", tmp.display(), e);
+    }
+
+    // Only a parseable file is worth keeping as the fallback.
+    if let Ok(previous) = fs::read_to_string(path) {
+        if serde_json::from_str::<serde_json::Value>(&previous).is_ok() {
+            let bak = sibling(path, ".bak");
+            fs::copy(path, &bak)?;
+            let _ = restrict_permissions(&bak);
+        }
+    }
+
+    fs::rename(&tmp, path)?;
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Writes `state` to `path`, encrypted when a key is available and as plaintext otherwise.
+fn write(path: &Path, state: &BlinkAuthState, key: Option<&AuthKey>) -> Result<()> {
+    let contents = Zeroizing::new(match key {
+        Some(key) => seal(state, key)?,
+        None => serde_json::to_string(state)?,
+    });
+    write_atomic(path, contents.as_bytes())
+}
+
+/// Reads `path`, falling back to the `.bak` written before the last save when the file
+/// is unreadable (e.g. truncated by a power loss). A missing file is reported as-is.
+fn read(path: &Path, key: Option<&AuthKey>) -> Result<BlinkAuthState> {
+    let error = match fs::read_to_string(path).map(Zeroizing::new) {
+        Ok(contents) => match open(&contents, key) {
+            Ok(state) => return Ok(state),
+            Err(e) => e,
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(e.into()),
+        Err(e) => e.into(),
+    };
+    let bak = sibling(path, ".bak");
+    match fs::read_to_string(&bak).map(Zeroizing::new).map_err(anyhow::Error::from).and_then(|c| open(&c, key)) {
+        Ok(state) => {
+            eprintln!("{} is unreadable ({}); using {}", path.display(), error, bak.display());
+            Ok(state)
+        }
+        Err(_) => Err(error),
+    }
+}
+
+/// Deletes the auth file together with its backup and any leftover temp file.
+fn remove(path: &Path) -> Result<()> {
+    for file in [sibling(path, ".bak"), sibling(path, ".tmp"), path.to_path_buf()] {
+        match fs::remove_file(&file) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Where auth state lives: the keychain when `keychain` is set, with `file` as the
+/// fallback (and the only store when it isn't).
+pub struct AuthStore {
+    file: PathBuf,
+    keychain: bool,
+}
+
+impl AuthStore {
+    pub fn new(file: PathBuf, keychain: bool) -> Self {
+        Self { file, keychain }
+    }
+
+    /// Key for the auth file: `BLINK_AUTH_PASSPHRASE` if set, otherwise a random key kept in
+    /// the keyring. `create` generates and stores that key when it doesn't exist yet.
+    fn file_key(&self, create: bool) -> Option<AuthKey> {
+        if let Some(key) = AuthKey::from_env() {
+            return Some(key);
+        }
+        if !self.keychain {
+            return None;
+        }
+        let entry = keyring_entry(KEYRING_FILE_KEY_USERNAME).ok()?;
+        if let Ok(encoded) = entry.get_password().map(Zeroizing::new) {
+            let decoded = Zeroizing::new(STANDARD.decode(encoded.as_str()).ok()?);
+            let mut raw = Zeroizing::new([0u8; 32]);
+            if decoded.len() != raw.len() {
+                return None;
+            }
+            raw.copy_from_slice(&decoded);
+            return Some(AuthKey::Raw(raw));
+        }
+        if !create {
+            return None;
+        }
+        let mut raw = Zeroizing::new([0u8; 32]);
+        rand::thread_rng().fill_bytes(raw.as_mut());
+        entry.set_password(&Zeroizing::new(STANDARD.encode(raw.as_ref()))).ok()?;
+        Some(AuthKey::Raw(raw))
+    }
+
+    fn write_file(&self, state: &BlinkAuthState) -> Result<()> {
+        let key = self.file_key(true);
+        if key.is_none() {
+            eprintln!("Writing {} unencrypted; set {} to encrypt it.", self.file.display(), PASSPHRASE_ENV);
+        }
+        write(&self.file, state, key.as_ref())
+    }
+
+    pub fn save(&self, state: &BlinkAuthState) -> Result<()> {
+        if !self.keychain {
+            return self.write_file(state);
+        }
+        let json = Zeroizing::new(serde_json::to_string(state)?);
+        match keyring_entry(KEYRING_USERNAME)?.set_password(&json) {
+            // A file left from before the keychain was available would otherwise go stale.
+            Ok(()) => remove(&self.file),
+            Err(e) if allow_plaintext_auth() => {
+                eprintln!("Keychain storage failed, falling back to {}: {}", self.file.display(), e);
+                self.write_file(state)
+            }
+            Err(e) => Err(anyhow!(
+                "Keychain storage failed: {}. Set BLINK_ALLOW_PLAINTEXT_AUTH=1 to allow insecure fallback.",
+                e
+            )),
+        }
+    }
+
+    pub fn load(&self) -> Result<BlinkAuthState> {
+        if self.keychain {
+            if let Ok(json) = keyring_entry(KEYRING_USERNAME).and_then(|entry| Ok(Zeroizing::new(entry.get_password()?))) {
+                return BlinkAuthState::from_json(&json, KEYCHAIN_SOURCE);
+            }
+        }
+
+        let state = read(&self.file, self.file_key(false).as_ref())?;
+
+        // Move file-stored state into the keychain once it is available; `save` deletes the file.
+        if self.keychain {
+            if let Err(e) = self.save(&state) {
+                eprintln!("Keeping auth in {}: {}", self.file.display(), e);
+            }
+        }
+        Ok(state)
+    }
+
+    pub fn clear(&self) -> Result<()> {
+        if self.keychain {
+            if let Ok(entry) = keyring_entry(KEYRING_USERNAME) {
+                let _ = entry.delete_password();
+            }
+        }
+        remove(&self.file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(token: &str) -> BlinkAuthState {
+        let json = serde_json::json!({ "token": token, "refresh_token": "refresh", "account_id": 7, "token_expiry": 1_900_000_000 });
+        BlinkAuthState::from_json(&json.to_string(), "test").unwrap()
+    }
+
+    fn token(state: &BlinkAuthState) -> &str {
+        state.token.as_ref().unwrap().expose_secret()
+    }
+
+    #[test]
+    fn sealed_state_round_trips_with_either_key() {
+        for key in [AuthKey::Passphrase("correct horse".into()), AuthKey::Raw(Zeroizing::new([9u8; 32]))] {
+            let sealed = seal(&state("secret-token"), &key).unwrap();
+            assert!(!sealed.contains("secret-token"));
+            let opened = open(&sealed, Some(&key)).unwrap();
+            assert_eq!(token(&opened), "secret-token");
+            assert_eq!(opened.account_id, Some(7));
+        }
+    }
+
+    #[test]
+    fn wrong_passphrase_is_reported_as_such() {
+        let sealed = seal(&state("t"), &AuthKey::Passphrase("right".into())).unwrap();
+        let error = open(&sealed, Some(&AuthKey::Passphrase("wrong".into()))).err().unwrap().to_string();
+        assert!(error.contains("wrong passphrase"), "{}", error);
+    }
+
+    #[test]
+    fn encrypted_file_without_a_key_names_the_variable() {
+        let sealed = seal(&state("t"), &AuthKey::Passphrase("p".into())).unwrap();
+        let error = open(&sealed, None).err().unwrap().to_string();
+        assert!(error.contains(PASSPHRASE_ENV), "{}", error);
+    }
+
+    #[test]
+    fn key_kind_mismatch_is_refused_before_decrypting() {
+        let sealed = seal(&state("t"), &AuthKey::Raw(Zeroizing::new([1u8; 32]))).unwrap();
+        let error = open(&sealed, Some(&AuthKey::Passphrase("p".into()))).err().unwrap().to_string();
+        assert!(error.contains("raw-sha256"), "{}", error);
+    }
+
+    #[test]
+    fn tampered_ciphertext_fails_to_open() {
+        let key = AuthKey::Raw(Zeroizing::new([2u8; 32]));
+        let mut envelope: serde_json::Value = serde_json::from_str(&seal(&state("t"), &key).unwrap()).unwrap();
+        let mut ct = STANDARD.decode(envelope["ct"].as_str().unwrap()).unwrap();
+        ct[0] ^= 1;
+        envelope["ct"] = STANDARD.encode(ct).into();
+        assert!(open(&envelope.to_string(), Some(&key)).is_err());
+    }
+
+    #[test]
+    fn legacy_plaintext_still_opens() {
+        let plaintext = serde_json::to_string(&state("legacy")).unwrap();
+        assert_eq!(token(&open(&plaintext, None).unwrap()), "legacy");
+        assert_eq!(token(&open(&plaintext, Some(&AuthKey::Raw(Zeroizing::new([3u8; 32])))).unwrap()), "legacy");
+    }
+
+    #[test]
+    fn leftover_partial_temp_file_is_replaced_on_the_next_save() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        write(&path, &state("first"), None).unwrap();
+        // A crash mid-write leaves a truncated temp file beside the real one.
+        fs::write(sibling(&path, ".tmp"), b"{\"token\":\"half").unwrap();
+
+        assert_eq!(token(&read(&path, None).unwrap()), "first");
+        write(&path, &state("second"), None).unwrap();
+        assert_eq!(token(&read(&path, None).unwrap()), "second");
+        assert!(!sibling(&path, ".tmp").exists());
+    }
+
+    #[test]
+    fn truncated_file_falls_back_to_the_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        let key = AuthKey::Raw(Zeroizing::new([4u8; 32]));
+        write(&path, &state("older"), Some(&key)).unwrap();
+        write(&path, &state("newer"), Some(&key)).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+        assert_eq!(token(&read(&path, Some(&key)).unwrap()), "older");
+    }
+
+    #[test]
+    fn unreadable_file_is_never_kept_as_the_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        fs::write(&path, b"{\"tok").unwrap();
+        write(&path, &state("fresh"), None).unwrap();
+        assert!(!sibling(&path, ".bak").exists());
+
+        fs::write(&path, b"garbage").unwrap();
+        assert!(read(&path, None).is_err());
+    }
+
+    #[test]
+    fn missing_file_is_reported_as_not_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let error = read(&dir.path().join("auth.json"), None).err().unwrap();
+        assert!(error.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn auth_file_and_backup_are_owner_only() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        write(&path, &state("a"), None).unwrap();
+        write(&path, &state("b"), None).unwrap();
+        for file in [path.clone(), sibling(&path, ".bak")] {
+            assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o600, "{}", file.display());
+        }
+    }
+
+    #[test]
+    fn remove_clears_file_backup_and_temp() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("auth.json");
+        write(&path, &state("a"), None).unwrap();
+        write(&path, &state("b"), None).unwrap();
+        fs::write(sibling(&path, ".tmp"), b"partial").unwrap();
+        remove(&path).unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    /// `AuthStore` against an in-memory keyring. keyring's own mock keeps each password
+    /// in the entry object, and the store opens a fresh entry per call, so this backend
+    /// keys passwords by service and user instead.
+    #[cfg(feature = "keychain")]
+    mod keychain {
+        use super::*;
+        use keyring::credential::{Credential, CredentialApi, CredentialBuilder, CredentialBuilderApi};
+        use std::any::Any;
+        use std::collections::HashMap;
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::{Mutex, MutexGuard, Once};
+
+        static PASSWORDS: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+        static FAIL_WRITES: AtomicBool = AtomicBool::new(false);
+        /// The keyring is process-wide, so these tests take turns.
+        static SERIAL: Mutex<()> = Mutex::new(());
+
+        struct SharedCredential(String);
+
+        impl CredentialApi for SharedCredential {
+            fn set_password(&self, password: &str) -> keyring::Result<()> {
+                if FAIL_WRITES.load(Ordering::SeqCst) {
+                    return Err(keyring::Error::NoStorageAccess("locked".into()));
+                }
+                PASSWORDS.lock().unwrap().get_or_insert_with(HashMap::new).insert(self.0.clone(), password.to_string());
+                Ok(())
+            }
+
+            fn get_password(&self) -> keyring::Result<String> {
+                PASSWORDS.lock().unwrap().get_or_insert_with(HashMap::new).get(&self.0).cloned().ok_or(keyring::Error::NoEntry)
+            }
+
+            fn delete_password(&self) -> keyring::Result<()> {
+                PASSWORDS.lock().unwrap().get_or_insert_with(HashMap::new).remove(&self.0).map(|_| ()).ok_or(keyring::Error::NoEntry)
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        struct SharedBuilder;
+
+        impl CredentialBuilderApi for SharedBuilder {
+            fn build(&self, _target: Option<&str>, service: &str, user: &str) -> keyring::Result<Box<Credential>> {
+                Ok(Box::new(SharedCredential(format!("{}/{}", service, user))))
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+        }
+
+        fn mock_keyring() -> MutexGuard<'static, ()> {
+            static INSTALL: Once = Once::new();
+            INSTALL.call_once(|| keyring::set_default_credential_builder(Box::new(SharedBuilder) as Box<CredentialBuilder>));
+            let guard = SERIAL.lock().unwrap_or_else(|e| e.into_inner());
+            *PASSWORDS.lock().unwrap() = Some(HashMap::new());
+            FAIL_WRITES.store(false, Ordering::SeqCst);
+            guard
+        }
+
+        fn stored(username: &str) -> Option<String> {
+            PASSWORDS.lock().unwrap().as_ref().and_then(|p| p.get(&format!("{}/{}", KEYRING_SERVICE, username)).cloned())
+        }
+
+        #[test]
+        fn state_saved_to_the_keychain_loads_back_and_replaces_the_file() {
+            let _keyring = mock_keyring();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("auth.json");
+            write(&path, &state("from-file"), None).unwrap();
+
+            let store = AuthStore::new(path.clone(), true);
+            store.save(&state("in-keychain")).unwrap();
+            assert!(stored(KEYRING_USERNAME).is_some_and(|json| json.contains("in-keychain")));
+            assert!(!path.exists());
+            assert_eq!(token(&store.load().unwrap()), "in-keychain");
+        }
+
+        #[test]
+        fn file_state_moves_into_the_keychain_on_load() {
+            let _keyring = mock_keyring();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("auth.json");
+            write(&path, &state("legacy-file"), None).unwrap();
+
+            let store = AuthStore::new(path.clone(), true);
+            assert_eq!(token(&store.load().unwrap()), "legacy-file");
+            assert!(stored(KEYRING_USERNAME).is_some_and(|json| json.contains("legacy-file")));
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn clear_removes_the_keychain_entry_and_file() {
+            let _keyring = mock_keyring();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("auth.json");
+            let store = AuthStore::new(path.clone(), true);
+            store.save(&state("t")).unwrap();
+            store.clear().unwrap();
+            assert!(stored(KEYRING_USERNAME).is_none());
+            assert!(store.load().is_err());
+        }
+
+        #[test]
+        fn failing_keychain_refuses_a_silent_plaintext_fallback() {
+            let _keyring = mock_keyring();
+            FAIL_WRITES.store(true, Ordering::SeqCst);
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("auth.json");
+            let error = AuthStore::new(path.clone(), true).save(&state("t")).err().unwrap().to_string();
+            assert!(error.contains("BLINK_ALLOW_PLAINTEXT_AUTH"), "{}", error);
+            assert!(!path.exists());
+        }
+
+        #[test]
+        fn keychain_file_key_encrypts_the_fallback_file() {
+            let _keyring = mock_keyring();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("auth.json");
+            let store = AuthStore::new(path.clone(), true);
+            store.write_file(&state("sealed")).unwrap();
+
+            assert!(stored(KEYRING_FILE_KEY_USERNAME).is_some());
+            let contents = fs::read_to_string(&path).unwrap();
+            assert!(!contents.contains("sealed"));
+            assert_eq!(token(&read(&path, store.file_key(false).as_ref()).unwrap()), "sealed");
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Encrypted export of everything needed to move the app to another machine: the Blink
+//! session, settings (camera preferences, schedules, webhooks live there) and, optionally,
+//! the media index. The archive is one `auth_store` envelope sealed with a passphrase.
+//!
+//! The session's `device_id` travels with it, so Blink keeps treating the new machine as
+//! the trusted client that signed in and no new 2FA round is needed. Archives from a newer
+//! build are rejected rather than half-imported.
+
+use crate::auth_store::{self, AuthKey};
+use crate::blink::BlinkAuthState;
+use crate::media_index::MediaIndex;
+use crate::settings::Settings;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+
+pub const ARCHIVE_VERSION: u32 = 1;
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+#[derive(Serialize, Deserialize)]
+struct Contents {
+    version: u32,
+    exported_at: DateTime<Utc>,
+    /// Version of the build that wrote it, for error messages.
+    app_version: String,
+    auth: Option<BlinkAuthState>,
+    /// Raw so older settings documents go through the usual migration on import.
+    settings: Value,
+    /// Base64 of the SQLite file.
+    media_index: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSummary {
+    pub path: String,
+    pub bytes: u64,
+    pub auth: bool,
+    pub media_index: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub exported_at: DateTime<Utc>,
+    pub auth: bool,
+    pub media_index: bool,
+    /// Folders in the archive's settings that were left out because this machine hasn't
+    /// granted them; the desktop app fills this in.
+    pub dropped_folders: Vec<String>,
+}
+
+/// A decrypted and validated archive, ready to `restore`.
+pub struct Archive {
+    pub exported_at: DateTime<Utc>,
+    pub auth: Option<BlinkAuthState>,
+    pub settings: Settings,
+    media_index: Option<Vec<u8>>,
+}
+
+fn passphrase_key(passphrase: &str) -> Result<AuthKey> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(anyhow!("The passphrase must be at least {} characters", MIN_PASSPHRASE_LEN));
+    }
+    Ok(AuthKey::Passphrase(passphrase.into()))
+}
+
+fn index_snapshot(index: &MediaIndex) -> Result<Option<Vec<u8>>> {
+    let tmp = std::env::temp_dir().join(format!("blink-export-{}.sqlite", uuid::Uuid::new_v4()));
+    let bytes = match index.backup(&tmp) {
+        Ok(true) => std::fs::read(&tmp).map(Some).map_err(Into::into),
+        other => other.map(|_| None),
+    };
+    let _ = std::fs::remove_file(&tmp);
+    bytes
+}
+
+/// Writes the archive to `dest` through a temp file. The media index is included when
+/// `media_index` is given and has a database.
+pub fn export(
+    dest: &Path,
+    passphrase: &str,
+    auth: Option<BlinkAuthState>,
+    settings: &Settings,
+    media_index: Option<&MediaIndex>,
+) -> Result<ExportSummary> {
+    let key = passphrase_key(passphrase)?;
+    let index_bytes = match media_index {
+        Some(index) => index_snapshot(index)?,
+        None => None,
+    };
+    let contents = Contents {
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        auth,
+        settings: serde_json::to_value(settings)?,
+        media_index: index_bytes.as_deref().map(|b| STANDARD.encode(b)),
+    };
+    let sealed = auth_store::seal_bytes(&zeroize::Zeroizing::new(serde_json::to_vec(&contents)?), &key)?;
+    if let Some(parent) = dest.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let tmp = dest.with_extension("tmp");
+    std::fs::write(&tmp, sealed.as_bytes())?;
+    std::fs::rename(&tmp, dest)?;
+    Ok(ExportSummary {
+        path: dest.display().to_string(),
+        bytes: sealed.len() as u64,
+        auth: contents.auth.is_some(),
+        media_index: contents.media_index.is_some(),
+    })
+}
+
+/// Decrypts and validates an archive without touching anything on disk.
+pub fn read(src: &Path, passphrase: &str) -> Result<Archive> {
+    let key = passphrase_key(passphrase)?;
+    let sealed = std::fs::read_to_string(src)?;
+    let plaintext = auth_store::open_bytes(&sealed, &key, "the archive")?;
+    let raw: Value = serde_json::from_slice(&plaintext)?;
+    let version = raw.get("version").and_then(Value::as_u64).unwrap_or(0);
+    if version == 0 || version > ARCHIVE_VERSION as u64 {
+        let written_by = raw.get("app_version").and_then(Value::as_str).unwrap_or("an unknown version");
+        return Err(anyhow!(
+            "Archive version {} (written by {}) is not supported by this build, which reads up to version {}",
+            version, written_by, ARCHIVE_VERSION
+        ));
+    }
+    let contents: Contents = serde_json::from_value(raw)?;
+    let settings = Settings::from_value(contents.settings)?;
+    let media_index = contents.media_index.as_deref().map(|b| STANDARD.decode(b)).transpose()?;
+    Ok(Archive { exported_at: contents.exported_at, auth: contents.auth.map(BlinkAuthState::migrated), settings, media_index })
+}
+
+impl Archive {
+    /// Puts the archive in place. The media index is staged next to the live one first so
+    /// a failed write changes nothing; then the session goes through `save_auth` (each
+    /// front end stores it differently), the index is swapped in and settings are written
+    /// last. Callers then load the session into their running client and apply the settings.
+    pub fn restore(
+        &self,
+        settings_path: &Path,
+        media_index: &MediaIndex,
+        save_auth: impl FnOnce(&BlinkAuthState) -> Result<()>,
+    ) -> Result<ImportSummary> {
+        let staged = media_index.path().with_extension("sqlite.import");
+        if let Some(bytes) = &self.media_index {
+            if let Some(parent) = staged.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&staged, bytes)?;
+        }
+        if let Some(auth) = &self.auth {
+            if let Err(e) = save_auth(auth) {
+                let _ = std::fs::remove_file(&staged);
+                return Err(e);
+            }
+        }
+        if self.media_index.is_some() {
+            media_index.replace_with(&staged)?;
+        }
+        self.settings.save(settings_path)?;
+        Ok(ImportSummary {
+            exported_at: self.exported_at,
+            auth: self.auth.is_some(),
+            media_index: self.media_index.is_some(),
+            dropped_folders: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use secrecy::ExposeSecret;
+
+    const PASSPHRASE: &str = "correct horse";
+
+    fn auth() -> BlinkAuthState {
+        let json = serde_json::json!({
+            "token": "token",
+            "refresh_token": "refresh",
+            "account_id": 42,
+            "base_url": "https://rest-u011.immedia-semi.com",
+            "device_id": "old-laptop",
+            "token_expiry": 4_102_444_800i64,
+        });
+        BlinkAuthState::from_json(&json.to_string(), "test").unwrap()
+    }
+
+    /// Seals `contents` the way `export` does, so tests can write archives it never would.
+    fn write_sealed(dir: &Path, contents: &Value) -> std::path::PathBuf {
+        let path = dir.join("crafted.blinkstate");
+        let key = passphrase_key(PASSPHRASE).unwrap();
+        std::fs::write(&path, auth_store::seal_bytes(&serde_json::to_vec(contents).unwrap(), &key).unwrap()).unwrap();
+        path
+    }
+
+    fn archive_of_version(version: Value) -> Value {
+        serde_json::json!({
+            "version": version,
+            "exported_at": "2026-10-15T12:00:00Z",
+            "app_version": "9.9.9",
+            "auth": null,
+            "settings": {},
+            "media_index": null,
+        })
+    }
+
+    #[test]
+    fn an_export_reads_back_with_its_device_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("nested").join("state.blinkstate");
+        let settings = Settings { close_to_tray: true, motion_interval_secs: 45, ..Settings::default() };
+        let summary = export(&dest, PASSPHRASE, Some(auth()), &settings, None).unwrap();
+        assert!(summary.auth && !summary.media_index);
+        assert_eq!(summary.bytes, std::fs::metadata(&dest).unwrap().len());
+        assert!(!dest.with_extension("tmp").exists());
+
+        let archive = read(&dest, PASSPHRASE).unwrap();
+        let auth = archive.auth.as_ref().unwrap();
+        assert_eq!(auth.device_id, "old-laptop");
+        assert_eq!(auth.refresh_token.as_ref().unwrap().expose_secret(), "refresh");
+        assert!(archive.settings.close_to_tray);
+        assert_eq!(archive.settings.motion_interval_secs, 45);
+    }
+
+    #[test]
+    fn a_newer_archive_is_rejected_naming_both_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_sealed(dir.path(), &archive_of_version((ARCHIVE_VERSION + 1).into()));
+        let err = read(&path, PASSPHRASE).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "Archive version {} (written by 9.9.9) is not supported by this build, which reads up to version {}",
+                ARCHIVE_VERSION + 1, ARCHIVE_VERSION
+            )
+        );
+    }
+
+    #[test]
+    fn a_missing_or_zero_version_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        for version in [Value::Null, 0.into(), "1".into()] {
+            let path = write_sealed(dir.path(), &archive_of_version(version.clone()));
+            let err = read(&path, PASSPHRASE).err().unwrap().to_string();
+            assert!(err.starts_with("Archive version 0 (written by 9.9.9)"), "{:?}: {}", version, err);
+        }
+        let path = write_sealed(dir.path(), &serde_json::json!({ "settings": {} }));
+        assert!(read(&path, PASSPHRASE).err().unwrap().to_string().contains("written by an unknown version"));
+    }
+
+    #[test]
+    fn the_current_version_is_accepted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_sealed(dir.path(), &archive_of_version(ARCHIVE_VERSION.into()));
+        let archive = read(&path, PASSPHRASE).unwrap();
+        assert!(archive.auth.is_none());
+        assert_eq!(archive.exported_at.to_rfc3339(), "2026-10-15T12:00:00+00:00");
+    }
+
+    #[test]
+    fn a_wrong_or_short_passphrase_reads_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let dest = dir.path().join("state.blinkstate");
+        assert!(export(&dest, "short", None, &Settings::default(), None).is_err());
+        assert!(!dest.exists());
+        export(&dest, PASSPHRASE, None, &Settings::default(), None).unwrap();
+        assert!(read(&dest, "incorrect horse").is_err());
+        assert!(read(&dest, "short").is_err());
+    }
+
+    #[test]
+    fn a_failed_auth_save_leaves_settings_and_index_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = MediaIndex::new(dir.path().join("media.sqlite"), true);
+        let dest = dir.path().join("state.blinkstate");
+        export(&dest, PASSPHRASE, Some(auth()), &Settings { close_to_tray: true, ..Settings::default() }, Some(&index)).unwrap();
+        let archive = read(&dest, PASSPHRASE).unwrap();
+
+        let settings_path = dir.path().join("settings.json");
+        let result = archive.restore(&settings_path, &index, |_| Err(anyhow!("keychain locked")));
+        assert_eq!(result.err().unwrap().to_string(), "keychain locked");
+        assert!(!settings_path.exists());
+        assert!(!index.path().with_extension("sqlite.import").exists());
+
+        let mut saved = None;
+        let summary = archive.restore(&settings_path, &index, |auth| {
+            saved = Some(auth.device_id.clone());
+            Ok(())
+        }).unwrap();
+        assert!(summary.auth && summary.media_index);
+        assert_eq!(saved.as_deref(), Some("old-laptop"));
+        assert!(Settings::load(&settings_path).close_to_tray);
+        assert!(index.is_enabled());
+    }
+}
@@ -0,0 +1,474 @@
+//! Battery history per camera, sampled from the homescreen's battery signal and the voltage
+//! in each camera's config. Samples are kept as taken for `RAW_RETENTION_DAYS`, then averaged
+//! into one point per hour, and dropped after `MAX_RETENTION_DAYS`, so the table stays small
+//! however long the app runs.
+
+use crate::blink::{BlinkClient, HomescreenResponse};
+use anyhow::Result;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const BATTERY_FILE: &str = "battery.sqlite";
+pub const SAMPLE_INTERVAL_SECS: u64 = 30 * 60;
+pub const DEFAULT_RANGE_DAYS: u32 = 7;
+pub const RAW_RETENTION_DAYS: i64 = 7;
+pub const MAX_RETENTION_DAYS: u32 = 365;
+/// The projection only looks at this much recent history, so a battery swap weeks ago
+/// doesn't flatten the trend.
+const PROJECTION_WINDOW_DAYS: i64 = 7;
+/// Fewer points than this give no projection.
+const MIN_PROJECTION_POINTS: usize = 3;
+/// Blink's battery signal is a bar count from 0 to this, not a percentage.
+pub const FULL_BARS: i64 = 3;
+
+/// Applied in order; the database's `user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS battery_samples (
+        camera_id INTEGER NOT NULL,
+        ts INTEGER NOT NULL,
+        level REAL NOT NULL,
+        voltage REAL,
+        low INTEGER NOT NULL,
+        hourly INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE INDEX IF NOT EXISTS battery_samples_camera_ts ON battery_samples(camera_id, ts);",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatteryPoint {
+    pub ts: DateTime<Utc>,
+    /// Blink's battery bars as a percentage of `FULL_BARS`; an average when `hourly` is set.
+    pub level: f64,
+    /// Battery voltage from the camera's config, as Blink reports it; `None` when the
+    /// config had none.
+    pub voltage: Option<f64>,
+    /// Blink reported the battery as low.
+    pub low: bool,
+    /// This point averages an hour of samples older than `RAW_RETENTION_DAYS`.
+    pub hourly: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatteryHistory {
+    pub camera_id: i64,
+    /// Oldest first.
+    pub points: Vec<BatteryPoint>,
+    /// Days until the level reaches zero at the recent rate of decline; `None` when the
+    /// level isn't falling or there is too little data.
+    pub days_remaining: Option<f64>,
+}
+
+/// How one camera's battery moved over a period.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatteryChange {
+    pub camera_id: i64,
+    pub first_level: f64,
+    pub last_level: f64,
+    /// Blink reported the battery as low at some point in the period.
+    pub low: bool,
+}
+
+/// A no-op if the database can't be opened, like the event log.
+pub struct BatteryStore {
+    path: PathBuf,
+    conn: std::sync::Mutex<Option<Connection>>,
+}
+
+fn open(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(conn)
+}
+
+/// Bars as a percentage of `FULL_BARS`, so the projection runs to an empty battery.
+pub fn bars_to_level(bars: i64) -> f64 {
+    bars.clamp(0, FULL_BARS) as f64 * 100.0 / FULL_BARS as f64
+}
+
+/// The battery voltage in a camera config, which some models nest in a `camera` array.
+pub fn config_voltage(config: &Value) -> Option<f64> {
+    config["camera"][0]["battery_voltage"].as_f64()
+        .or_else(|| config["battery_voltage"].as_f64())
+        .filter(|v| *v > 0.0)
+}
+
+/// Reads the voltage from the config of every camera that reports a battery signal. Cameras
+/// whose config fails or has no voltage are left out.
+pub async fn sample_voltages(client: &BlinkClient, homescreen: &HomescreenResponse) -> HashMap<i64, f64> {
+    let mut voltages = HashMap::new();
+    for camera in homescreen.cameras.iter().filter(|c| c.signals.as_ref().is_some_and(|s| s.battery.is_some())) {
+        let Some(network_id) = camera.network_id else { continue };
+        match client.get_camera_config(network_id, camera.id, &camera.product_type).await {
+            Ok(config) => {
+                if let Some(voltage) = config_voltage(&config) {
+                    voltages.insert(camera.id, voltage);
+                }
+            }
+            Err(e) => eprintln!("Battery voltage for camera {} failed: {}", camera.id, e),
+        }
+    }
+    voltages
+}
+
+/// Least-squares slope of level over time, in level per day, and the fitted level at the
+/// latest point.
+fn trend(points: &[&BatteryPoint]) -> Option<(f64, f64)> {
+    if points.len() < MIN_PROJECTION_POINTS {
+        return None;
+    }
+    let origin = points[0].ts;
+    let xs: Vec<f64> = points.iter().map(|p| (p.ts - origin).num_seconds() as f64 / 86_400.0).collect();
+    let n = points.len() as f64;
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.level).sum::<f64>() / n;
+    let var_x: f64 = xs.iter().map(|x| (x - mean_x).powi(2)).sum();
+    if var_x == 0.0 {
+        return None;
+    }
+    let cov: f64 = xs.iter().zip(points).map(|(x, p)| (x - mean_x) * (p.level - mean_y)).sum();
+    let slope = cov / var_x;
+    let latest = mean_y + slope * (xs[xs.len() - 1] - mean_x);
+    Some((slope, latest))
+}
+
+/// Days until the recent trend reaches zero.
+pub fn project_days_remaining(points: &[BatteryPoint], now: DateTime<Utc>) -> Option<f64> {
+    let cutoff = now - Duration::days(PROJECTION_WINDOW_DAYS);
+    let recent: Vec<&BatteryPoint> = points.iter().filter(|p| p.ts >= cutoff).collect();
+    let (slope, latest) = trend(&recent)?;
+    if slope >= 0.0 || latest <= 0.0 {
+        return None;
+    }
+    Some(latest / -slope)
+}
+
+impl BatteryStore {
+    pub fn new(path: PathBuf) -> Self {
+        let conn = match open(&path) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                eprintln!("Failed to open battery history {}: {}", path.display(), e);
+                None
+            }
+        };
+        Self { path, conn: std::sync::Mutex::new(conn) }
+    }
+
+    /// Stores one sample per camera that reports a battery signal, with its voltage from
+    /// `voltages` when there is one. Returns how many were stored.
+    pub fn record(&self, homescreen: &HomescreenResponse, voltages: &HashMap<i64, f64>) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return Ok(0) };
+        let now = Utc::now().timestamp();
+        let mut stored = 0;
+        for camera in &homescreen.cameras {
+            let Some(bars) = camera.signals.as_ref().and_then(|s| s.battery) else { continue };
+            let low = camera.battery.as_deref().is_some_and(|b| b.eq_ignore_ascii_case("low"));
+            conn.execute(
+                "INSERT INTO battery_samples (camera_id, ts, level, voltage, low) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![camera.id, now, bars_to_level(bars), voltages.get(&camera.id), low],
+            )?;
+            stored += 1;
+        }
+        Ok(stored)
+    }
+
+    /// Points for one camera over the last `days`, with the projection.
+    pub fn history(&self, camera_id: i64, days: u32) -> Result<BatteryHistory> {
+        let now = Utc::now();
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else {
+            return Ok(BatteryHistory { camera_id, points: Vec::new(), days_remaining: None });
+        };
+        let since = (now - Duration::days(i64::from(days.clamp(1, MAX_RETENTION_DAYS)))).timestamp();
+        let mut stmt = conn.prepare(
+            "SELECT ts, level, voltage, low, hourly FROM battery_samples WHERE camera_id = ?1 AND ts >= ?2 ORDER BY ts",
+        )?;
+        let rows = stmt.query_map(params![camera_id, since], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, Option<f64>>(2)?, row.get::<_, bool>(3)?, row.get::<_, bool>(4)?))
+        })?;
+        let mut points = Vec::new();
+        for row in rows {
+            let (ts, level, voltage, low, hourly) = row?;
+            let Some(ts) = Utc.timestamp_opt(ts, 0).single() else { continue };
+            points.push(BatteryPoint { ts, level, voltage, low, hourly });
+        }
+        let days_remaining = project_days_remaining(&points, now);
+        Ok(BatteryHistory { camera_id, points, days_remaining })
+    }
+
+    /// First and last level per camera among the samples from `from` up to `to`.
+    pub fn changes(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Vec<BatteryChange>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return Ok(Vec::new()) };
+        let mut stmt = conn.prepare(
+            "SELECT camera_id, level, low FROM battery_samples WHERE ts >= ?1 AND ts < ?2 ORDER BY camera_id, ts",
+        )?;
+        let rows = stmt.query_map(params![from.timestamp(), to.timestamp()], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, f64>(1)?, row.get::<_, bool>(2)?))
+        })?;
+        let mut changes: Vec<BatteryChange> = Vec::new();
+        for row in rows {
+            let (camera_id, level, low) = row?;
+            match changes.last_mut().filter(|c| c.camera_id == camera_id) {
+                Some(change) => {
+                    change.last_level = level;
+                    change.low |= low;
+                }
+                None => changes.push(BatteryChange { camera_id, first_level: level, last_level: level, low }),
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Averages raw samples older than `RAW_RETENTION_DAYS` into hourly points and drops
+    /// everything older than `MAX_RETENTION_DAYS`. Returns how many rows were removed.
+    pub fn compact(&self, now: DateTime<Utc>) -> Result<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_mut() else { return Ok(0) };
+        // On an hour boundary, so no hour is averaged in two halves.
+        let raw_cutoff = (now - Duration::days(RAW_RETENTION_DAYS)).timestamp() / 3600 * 3600;
+        let max_cutoff = (now - Duration::days(i64::from(MAX_RETENTION_DAYS))).timestamp();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO battery_samples (camera_id, ts, level, voltage, low, hourly)
+             SELECT camera_id, (ts / 3600) * 3600, AVG(level), AVG(voltage), MAX(low), 1
+             FROM battery_samples WHERE hourly = 0 AND ts < ?1
+             GROUP BY camera_id, ts / 3600",
+            params![raw_cutoff],
+        )?;
+        let mut removed = tx.execute("DELETE FROM battery_samples WHERE hourly = 0 AND ts < ?1", params![raw_cutoff])?;
+        removed += tx.execute("DELETE FROM battery_samples WHERE ts < ?1", params![max_cutoff])?;
+        tx.commit()?;
+        Ok(removed)
+    }
+
+    /// Days left for one camera at the recent rate, for alerts; `None` when unknown.
+    pub fn days_remaining(&self, camera_id: i64) -> Option<f64> {
+        self.history(camera_id, PROJECTION_WINDOW_DAYS as u32).ok()?.days_remaining
+    }
+
+    /// Samples the homescreen and compacts old history; failures are logged.
+    pub fn observe(&self, homescreen: &HomescreenResponse, voltages: &HashMap<i64, f64>) {
+        if let Err(e) = self.record(homescreen, voltages) {
+            eprintln!("Failed to record battery levels in {}: {}", self.path.display(), e);
+        }
+        if let Err(e) = self.compact(Utc::now()) {
+            eprintln!("Failed to compact battery history in {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> (tempfile::TempDir, BatteryStore) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = BatteryStore::new(dir.path().join(BATTERY_FILE));
+        (dir, store)
+    }
+
+    fn at(ts: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(ts, 0).unwrap()
+    }
+
+    fn insert(store: &BatteryStore, samples: &[(i64, i64, f64, bool)]) {
+        let mut conn = store.conn.lock().unwrap();
+        let tx = conn.as_mut().unwrap().transaction().unwrap();
+        for &(camera_id, ts, level, low) in samples {
+            tx.execute(
+                "INSERT INTO battery_samples (camera_id, ts, level, low) VALUES (?1, ?2, ?3, ?4)",
+                params![camera_id, ts, level, low],
+            ).unwrap();
+        }
+        tx.commit().unwrap();
+    }
+
+    fn rows(store: &BatteryStore) -> Vec<(i64, i64, f64, bool, bool)> {
+        let conn = store.conn.lock().unwrap();
+        let conn = conn.as_ref().unwrap();
+        let mut stmt = conn.prepare("SELECT camera_id, ts, level, low, hourly FROM battery_samples ORDER BY camera_id, ts, hourly").unwrap();
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?))).unwrap();
+        rows.collect::<rusqlite::Result<_>>().unwrap()
+    }
+
+    fn point(ts: DateTime<Utc>, level: f64) -> BatteryPoint {
+        BatteryPoint { ts, level, voltage: None, low: false, hourly: false }
+    }
+
+    // Midnight UTC, so hour boundaries are easy to read.
+    const NOW: i64 = 1_791_936_000;
+
+    #[test]
+    fn old_samples_are_averaged_per_hour() {
+        let (_dir, store) = store();
+        let hour = (NOW - 10 * 86_400) / 3600 * 3600;
+        let recent = NOW - 3600;
+        insert(&store, &[
+            (1, hour + 60, 80.0, false),
+            (1, hour + 1800, 82.0, true),
+            (1, hour + 3599, 84.0, false),
+            (1, hour + 3600, 70.0, false),
+            (2, hour + 120, 50.0, false),
+            (1, recent, 60.0, false),
+        ]);
+
+        assert_eq!(store.compact(at(NOW)).unwrap(), 5);
+        assert_eq!(rows(&store), vec![
+            (1, hour, 82.0, true, true),
+            (1, hour + 3600, 70.0, false, true),
+            (1, recent, 60.0, false, false),
+            (2, hour, 50.0, false, true),
+        ]);
+    }
+
+    #[test]
+    fn compacting_again_changes_nothing() {
+        let (_dir, store) = store();
+        let old = NOW - 10 * 86_400;
+        insert(&store, &[(1, old, 80.0, false), (1, old + 60, 90.0, false), (1, NOW - 60, 50.0, false)]);
+        store.compact(at(NOW)).unwrap();
+        let once = rows(&store);
+        // Hourly points are never averaged a second time, so their values don't drift.
+        assert_eq!(store.compact(at(NOW)).unwrap(), 0);
+        assert_eq!(rows(&store), once);
+    }
+
+    #[test]
+    fn history_past_the_retention_limit_is_dropped() {
+        let (_dir, store) = store();
+        let limit = NOW - i64::from(MAX_RETENTION_DAYS) * 86_400;
+        insert(&store, &[(1, limit - 7200, 10.0, false), (1, limit + 7200, 20.0, false)]);
+        store.compact(at(NOW)).unwrap();
+        assert_eq!(rows(&store), vec![(1, (limit + 7200) / 3600 * 3600, 20.0, false, true)]);
+    }
+
+    #[test]
+    fn a_year_and_more_of_sampling_stays_bounded() {
+        let (_dir, store) = store();
+        let start = NOW - 400 * 86_400;
+        let per_day = 86_400 / SAMPLE_INTERVAL_SECS as i64;
+        for day in 0..400 {
+            let samples: Vec<_> = (0..per_day)
+                .map(|i| (1, start + day * 86_400 + i * SAMPLE_INTERVAL_SECS as i64, 100.0 - day as f64 / 4.0, false))
+                .collect();
+            insert(&store, &samples);
+            store.compact(at(start + (day + 1) * 86_400)).unwrap();
+        }
+        let rows = rows(&store);
+        let raw = rows.iter().filter(|r| !r.4).count() as i64;
+        let hourly = rows.iter().filter(|r| r.4).count() as i64;
+        assert!(raw <= (RAW_RETENTION_DAYS + 1) * per_day, "{} raw rows", raw);
+        assert!(hourly <= i64::from(MAX_RETENTION_DAYS) * 24, "{} hourly rows", hourly);
+        assert!(rows.iter().all(|r| r.1 >= NOW - i64::from(MAX_RETENTION_DAYS) * 86_400 - 86_400));
+    }
+
+    #[test]
+    fn a_steady_decline_projects_when_it_hits_zero() {
+        let now = at(NOW);
+        // 60 at the latest point, falling 5 a day.
+        let points: Vec<_> = (0..6).map(|d| point(now - Duration::days(5 - d), 85.0 - 5.0 * d as f64)).collect();
+        let days = project_days_remaining(&points, now).unwrap();
+        assert!((days - 12.0).abs() < 1e-9, "{}", days);
+    }
+
+    #[test]
+    fn no_projection_without_a_decline_or_enough_points() {
+        let now = at(NOW);
+        let rising: Vec<_> = (0..5).map(|d| point(now - Duration::days(5 - d), 50.0 + d as f64)).collect();
+        assert_eq!(project_days_remaining(&rising, now), None);
+        let flat: Vec<_> = (0..5).map(|d| point(now - Duration::days(5 - d), 50.0)).collect();
+        assert_eq!(project_days_remaining(&flat, now), None);
+        let two = [point(now - Duration::days(1), 80.0), point(now, 70.0)];
+        assert_eq!(project_days_remaining(&two, now), None);
+        let same_time = [point(now, 80.0), point(now, 70.0), point(now, 60.0)];
+        assert_eq!(project_days_remaining(&same_time, now), None);
+    }
+
+    #[test]
+    fn only_the_recent_window_drives_the_projection() {
+        let now = at(NOW);
+        // A battery swap three weeks ago: a steep drop then, a slow one since.
+        let mut points: Vec<_> = (0..5).map(|d| point(now - Duration::days(25 - d), 40.0 - 8.0 * d as f64)).collect();
+        points.extend((0..5).map(|d| point(now - Duration::days(4 - d), 100.0 - d as f64)));
+        let days = project_days_remaining(&points, now).unwrap();
+        assert!((days - 96.0).abs() < 1e-9, "{}", days);
+    }
+
+    #[test]
+    fn history_and_changes_are_per_camera() {
+        let (_dir, store) = store();
+        let now = Utc::now().timestamp();
+        insert(&store, &[
+            (1, now - 3 * 86_400, 90.0, false),
+            (1, now - 2 * 86_400, 80.0, true),
+            (2, now - 2 * 86_400, 40.0, false),
+            (1, now - 86_400, 70.0, false),
+            (1, now - 20 * 86_400, 100.0, false),
+        ]);
+        let history = store.history(1, 7).unwrap();
+        let levels: Vec<_> = history.points.iter().map(|p| p.level).collect();
+        assert_eq!(levels, vec![90.0, 80.0, 70.0]);
+        assert!((history.days_remaining.unwrap() - 7.0).abs() < 0.01);
+
+        let changes = store.changes(at(now - 7 * 86_400), at(now)).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].camera_id, changes[0].first_level, changes[0].last_level, changes[0].low), (1, 90.0, 70.0, true));
+        assert_eq!((changes[1].camera_id, changes[1].first_level, changes[1].last_level), (2, 40.0, 40.0));
+    }
+
+    #[test]
+    fn homescreen_samples_come_from_the_battery_bars_and_config_voltage() {
+        let (_dir, store) = store();
+        let homescreen: HomescreenResponse = serde_json::from_value(serde_json::json!({
+            "account": {},
+            "networks": [],
+            "cameras": [
+                { "id": 1, "name": "Porch", "thumbnail": "", "status": "done", "type": "catalina", "battery": "low", "signals": { "battery": 1 } },
+                { "id": 2, "name": "Wired", "thumbnail": "", "status": "done", "type": "white" },
+                { "id": 3, "name": "Yard", "thumbnail": "", "status": "done", "type": "catalina", "battery": "ok", "signals": { "battery": 3 } }
+            ]
+        })).unwrap();
+        let voltages = HashMap::from([(1, 163.0)]);
+        assert_eq!(store.record(&homescreen, &voltages).unwrap(), 2);
+        let rows = rows(&store);
+        assert_eq!((rows[0].0, rows[0].3, rows[0].4), (1, true, false));
+        assert!((rows[0].2 - 100.0 / 3.0).abs() < 1e-9, "{}", rows[0].2);
+        assert_eq!((rows[1].0, rows[1].2), (3, 100.0));
+
+        let history = store.history(1, 1).unwrap();
+        assert_eq!(history.points[0].voltage, Some(163.0));
+        assert_eq!(store.history(3, 1).unwrap().points[0].voltage, None);
+    }
+
+    #[test]
+    fn bars_scale_to_a_percentage() {
+        assert_eq!(bars_to_level(0), 0.0);
+        assert_eq!(bars_to_level(FULL_BARS), 100.0);
+        assert_eq!(bars_to_level(5), 100.0);
+        assert_eq!(bars_to_level(-1), 0.0);
+    }
+
+    #[test]
+    fn voltage_is_read_from_either_config_shape() {
+        assert_eq!(config_voltage(&serde_json::json!({ "camera": [{ "battery_voltage": 163 }] })), Some(163.0));
+        assert_eq!(config_voltage(&serde_json::json!({ "battery_voltage": 2.9 })), Some(2.9));
+        assert_eq!(config_voltage(&serde_json::json!({ "camera": [{ "battery_voltage": 0 }] })), None);
+        assert_eq!(config_voltage(&serde_json::json!({ "name": "Porch" })), None);
+    }
+}
@@ -0,0 +1,4134 @@
+use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderMap, HeaderValue, LOCATION, REFERER, ORIGIN};
+use anyhow::{Result, anyhow};
+use uuid::Uuid;
+use sha2::{Sha256, Digest};
+use base64::{Engine as _, engine::general_purpose};
+use rand::{thread_rng, Rng};
+use zeroize::Zeroize;
+use regex::Regex;
+use chrono::{Utc, Duration};
+use cookie_store::Cookie;
+use secrecy::{ExposeSecret, SecretString};
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+use std::collections::HashMap;
+use std::sync::Arc;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use crate::audit;
+use crate::device_routes::{self, RouteAttempt};
+
+pub const OAUTH_BASE_URL: &str = "https://api.oauth.blink.com";
+pub const BASE_URL: &str = "https://rest-prod.immedia-semi.com";
+/// REST host for an account's tier; `{tier}` is replaced with the tier from `tier_info`.
+pub const TIER_URL_TEMPLATE: &str = "https://rest-{tier}.immedia-semi.com";
+/// Least time between two tier re-discoveries, so a route that 404s for other reasons
+/// doesn't send every request through `tier_info` first.
+pub const TIER_HEAL_COOLDOWN_SECS: u64 = 10 * 60;
+
+const DELETE_VERIFY_ATTEMPTS: u32 = 3;
+const DELETE_VERIFY_MAX_PAGES: i64 = 10;
+// Used when none of the items being deleted carry a parseable created_at.
+const DELETE_VERIFY_FALLBACK_DAYS: i64 = 365;
+
+/// How long `set_arm` polls an arm/disarm command, once a second, before giving up.
+const ARM_COMMAND_TIMEOUT_SECS: u64 = 15;
+/// Default wait for `set_arm_verified`, command and homescreen confirmation together.
+pub const ARM_VERIFY_TIMEOUT_SECS: u64 = 30;
+/// Callers may not ask `set_arm_verified` to wait longer than this.
+pub const MAX_ARM_VERIFY_TIMEOUT_SECS: u64 = 120;
+/// How long `eject_usb` polls the unmount command; flushing a large drive takes a while.
+const USB_EJECT_TIMEOUT_SECS: u64 = 60;
+const ARM_VERIFY_INTERVAL_SECS: u64 = 2;
+
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Whole-request limit for JSON API calls.
+const API_TIMEOUT_SECS: u64 = 30;
+/// Media transfers have no total limit, only a cap on how long one read may stall.
+const MEDIA_READ_TIMEOUT_SECS: u64 = 60;
+const MEDIA_WINDOW_BYTES: u32 = 4 * 1024 * 1024;
+
+/// Time limits for the two HTTP clients; see `http_clients`.
+#[derive(Debug, Clone, Copy)]
+struct HttpTimeouts {
+    connect: std::time::Duration,
+    api: std::time::Duration,
+    media_read: std::time::Duration,
+}
+
+impl Default for HttpTimeouts {
+    fn default() -> Self {
+        Self {
+            connect: std::time::Duration::from_secs(CONNECT_TIMEOUT_SECS),
+            api: std::time::Duration::from_secs(API_TIMEOUT_SECS),
+            media_read: std::time::Duration::from_secs(MEDIA_READ_TIMEOUT_SECS),
+        }
+    }
+}
+
+/// The JSON API client and the media client. Both send the same headers and share the
+/// cookie store; only the API client has a whole-request timeout.
+fn http_clients(cookies: &Arc<CookieStoreMutex>, timeouts: HttpTimeouts) -> (reqwest::Client, reqwest::Client) {
+    let mut headers = HeaderMap::new();
+    headers.insert("User-Agent", HeaderValue::from_static("Mozilla/5.0 (iPhone; CPU iPhone OS 18_2 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/18.2 Mobile/15E148 Safari/604.1"));
+    let builder = || reqwest::Client::builder()
+        .default_headers(headers.clone())
+        .cookie_provider(cookies.clone())
+        .connect_timeout(timeouts.connect)
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.url().scheme() == "immedia-blink" {
+                attempt.stop()
+            } else {
+                attempt.follow()
+            }
+        }));
+
+    let api = builder()
+        .timeout(timeouts.api)
+        .build()
+        .unwrap();
+    let media = builder()
+        .read_timeout(timeouts.media_read)
+        .http2_initial_stream_window_size(MEDIA_WINDOW_BYTES)
+        .http2_initial_connection_window_size(MEDIA_WINDOW_BYTES)
+        .build()
+        .unwrap();
+    (api, media)
+}
+
+/// Parses the assortment of timestamp formats Blink uses (RFC 3339, naive, epoch secs/ms).
+pub fn parse_blink_timestamp(value: &str) -> Option<chrono::DateTime<Utc>> {
+    let parsed = chrono::DateTime::parse_from_rfc3339(value)
+        .or_else(|_| chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%z"))
+        .or_else(|_| chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%z"))
+        .or_else(|_| chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%:z"))
+        .or_else(|_| chrono::DateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f%:z"))
+        .map(|dt| dt.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S"))
+                .ok()
+                .map(|dt| chrono::DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc))
+        });
+    if parsed.is_some() {
+        return parsed;
+    }
+    let epoch = value.parse::<i64>().ok()?;
+    let ts = if value.len() > 10 { epoch / 1000 } else { epoch };
+    chrono::DateTime::<Utc>::from_timestamp(ts, 0)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuthResponse {
+    pub access_token: SecretString,
+    pub refresh_token: SecretString,
+    pub expires_in: i64,
+}
+
+/// Serde for `Option<SecretString>`: `secrecy` deliberately doesn't serialize secrets, but
+/// the auth state has to round-trip through storage.
+mod secret_opt {
+    use secrecy::{ExposeSecret, SecretString};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<SecretString>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(secret) => serializer.serialize_some(secret.expose_secret()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<SecretString>, D::Error> {
+        Ok(Option::<String>::deserialize(deserializer)?.map(SecretString::from))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HomescreenResponse {
+    pub account: serde_json::Value,
+    pub networks: Vec<Network>,
+    #[serde(default)]
+    pub cameras: Vec<Camera>,
+    #[serde(default)]
+    pub owls: Vec<Camera>,
+    #[serde(default)]
+    pub doorbells: Vec<Camera>,
+    #[serde(default)]
+    pub sync_modules: Vec<SyncModule>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SyncModule {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub network_id: Option<i64>,
+    #[serde(default)]
+    pub serial: Option<String>,
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub fw_version: Option<String>,
+    /// Whether clips are saved to the module's USB drive.
+    #[serde(default)]
+    pub local_storage_enabled: bool,
+    /// Whether the module has a USB port at all.
+    #[serde(default)]
+    pub local_storage_compatible: bool,
+    #[serde(default)]
+    pub local_storage_status: UsbStorageState,
+}
+
+/// The USB drive in a sync module, from Blink's `local_storage_status` string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsbStorageState {
+    Active,
+    NotInserted,
+    Formatting,
+    Full,
+    Error,
+    Ejected,
+    #[default]
+    Unknown,
+}
+
+impl UsbStorageState {
+    pub fn parse(value: &str) -> Self {
+        match value.trim().to_ascii_lowercase().replace([' ', '-'], "_").as_str() {
+            "active" | "ok" | "inserted" | "mounted" | "ready" => Self::Active,
+            "not_inserted" | "none" | "missing" | "unavailable" | "no_usb" | "removed" => Self::NotInserted,
+            "format" | "formatting" | "format_in_progress" => Self::Formatting,
+            "full" | "storage_full" | "usb_full" => Self::Full,
+            "error" | "failed" | "corrupt" | "corrupted" | "unsupported" | "format_required" => Self::Error,
+            "eject" | "ejected" | "ejecting" | "unmounted" | "safe_to_remove" => Self::Ejected,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// A drive that has stopped taking clips, or soon will.
+    pub fn needs_attention(self) -> bool {
+        matches!(self, Self::Full | Self::Error)
+    }
+}
+
+impl<'de> Deserialize<'de> for UsbStorageState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Option::<String>::deserialize(deserializer)?;
+        Ok(value.as_deref().map_or(Self::Unknown, Self::parse))
+    }
+}
+
+/// The detailed USB storage report for one sync module. The endpoint's field names vary by
+/// firmware, so the known spellings are read leniently and the response is kept in `raw`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LocalStorageStatus {
+    pub network_id: i64,
+    pub sync_module_id: i64,
+    pub state: UsbStorageState,
+    pub present: bool,
+    pub formatting: bool,
+    pub full: bool,
+    pub used_bytes: Option<u64>,
+    pub capacity_bytes: Option<u64>,
+    /// When Blink last backed clips up to the drive, as Blink reported it.
+    pub last_backup: Option<String>,
+    pub raw: serde_json::Value,
+}
+
+impl LocalStorageStatus {
+    fn from_response(network_id: i64, sync_module_id: i64, raw: serde_json::Value) -> Self {
+        // Some firmware nests the fields under `local_storage`.
+        let body = raw.get("local_storage").filter(|v| v.is_object()).unwrap_or(&raw);
+        let field = |keys: &[&str]| keys.iter().find_map(|k| body.get(*k).filter(|v| !v.is_null()));
+        let flag = |keys: &[&str]| field(keys).and_then(|v| v.as_bool().or_else(|| v.as_i64().map(|n| n != 0)));
+        let bytes = |keys: &[&str]| field(keys).and_then(|v| v.as_u64().or_else(|| v.as_str().and_then(|s| s.parse().ok())));
+
+        let state = field(&["local_storage_status", "status", "storage_status", "state"])
+            .and_then(|v| v.as_str())
+            .map_or(UsbStorageState::Unknown, UsbStorageState::parse);
+        let used_bytes = bytes(&["used_bytes", "used", "usb_used_bytes", "used_size"]);
+        let capacity_bytes = bytes(&["capacity_bytes", "total_bytes", "capacity", "usb_size", "total_size"]);
+        let at_capacity = matches!((used_bytes, capacity_bytes), (Some(used), Some(total)) if total > 0 && used >= total);
+        Self {
+            network_id,
+            sync_module_id,
+            present: flag(&["usb_present", "present", "inserted", "usb_inserted"])
+                .unwrap_or(!matches!(state, UsbStorageState::NotInserted | UsbStorageState::Ejected | UsbStorageState::Unknown)),
+            formatting: flag(&["formatting", "format_in_progress"]).unwrap_or(state == UsbStorageState::Formatting),
+            full: flag(&["full", "storage_full", "usb_full"]).unwrap_or(state == UsbStorageState::Full) || at_capacity,
+            state,
+            used_bytes,
+            capacity_bytes,
+            last_backup: field(&["last_backup", "last_backup_time", "last_backup_at", "last_manifest_time"])
+                .map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string())),
+            raw,
+        }
+    }
+}
+
+/// What `eject_usb` saw. `finished` is false when Blink's command was still running at the
+/// deadline; the drive isn't safe to pull until it is true.
+#[derive(Debug, Clone, Serialize)]
+pub struct UsbEjectResult {
+    pub network_id: i64,
+    pub sync_module_id: i64,
+    pub command_id: Option<i64>,
+    pub finished: bool,
+    /// The drive as read back afterwards; `None` if that read failed.
+    pub status: Option<LocalStorageStatus>,
+}
+
+/// Turns a media or thumbnail path from a Blink payload into a URL on `base`. Paths come
+/// in several shapes: absolute URLs (sometimes on another region's host), `//host/...`
+/// protocol-relative ones, and host-relative paths with or without a leading slash, doubled
+/// slashes, or unescaped spaces from camera names. Slash runs are collapsed and unsafe
+/// characters percent-encoded; the query string is kept as is.
+pub fn resolve_path(base: &str, path: &str) -> Result<reqwest::Url, BlinkError> {
+    let invalid = || BlinkError::InvalidPath(path.to_string());
+    let trimmed = path.trim();
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+    let (raw, suffix) = match trimmed.find(['?', '#']) {
+        Some(i) => trimmed.split_at(i),
+        None => (trimmed, ""),
+    };
+    // `//name.domain/...` names a host; `//api/...` is just a doubled slash.
+    let protocol_relative = raw.strip_prefix("//")
+        .and_then(|rest| rest.split('/').next())
+        .is_some_and(|host| host.contains('.'));
+    let (origin, raw_path) = if raw.starts_with("http://") || raw.starts_with("https://") {
+        let after_scheme = raw.find("://").map(|i| i + 3).unwrap_or_default();
+        let path_start = raw[after_scheme..].find('/').map(|i| after_scheme + i).unwrap_or(raw.len());
+        (raw[..path_start].to_string(), &raw[path_start..])
+    } else if protocol_relative {
+        let path_start = raw[2..].find('/').map(|i| 2 + i).unwrap_or(raw.len());
+        (format!("https:{}", &raw[..path_start]), &raw[path_start..])
+    } else {
+        (base.trim_end_matches('/').to_string(), raw)
+    };
+    let mut normalized = String::with_capacity(raw_path.len() + 1);
+    for segment in raw_path.split('/').filter(|s| !s.is_empty()) {
+        normalized.push('/');
+        normalized.push_str(segment);
+    }
+    if normalized.is_empty() || raw_path.ends_with('/') {
+        normalized.push('/');
+    }
+    let origin = reqwest::Url::parse(&origin).map_err(|_| invalid())?;
+    if !matches!(origin.scheme(), "http" | "https") || origin.host().is_none() {
+        return Err(invalid());
+    }
+    origin.join(&format!("{}{}", normalized, suffix)).map_err(|_| invalid())
+}
+
+/// A device the account trusts; every sign-in with a new `device_id` adds one.
+#[derive(Debug, Clone, Serialize)]
+pub struct TrustedClient {
+    pub id: i64,
+    pub name: String,
+    pub created_at: Option<String>,
+    /// The client this app is signed in as.
+    pub current: bool,
+}
+
+/// Client list paths, newest first; not every tier serves both.
+const CLIENT_LIST_PATHS: [&str; 2] = ["api/v1/accounts/{account}/clients", "api/v2/accounts/{account}/clients"];
+
+/// Reads a client list in any of the shapes Blink uses: a bare array, or one under
+/// `clients` or `data`. Field names differ between tiers too.
+fn parse_trusted_clients(body: &serde_json::Value, device_id: &str) -> Vec<TrustedClient> {
+    let list = body.as_array()
+        .or_else(|| body["clients"].as_array())
+        .or_else(|| body["data"].as_array())
+        .or_else(|| body["data"]["clients"].as_array());
+    let Some(list) = list else { return Vec::new() };
+    let text = |entry: &serde_json::Value, keys: &[&str]| keys.iter().find_map(|k| entry[*k].as_str().map(str::to_string));
+    list.iter()
+        .filter_map(|entry| {
+            let id = entry["id"].as_i64().or_else(|| entry["client_id"].as_i64())?;
+            let hardware_id = text(entry, &["hardware_id", "unique_id", "device_identifier"]);
+            let current = entry["current"].as_bool()
+                .or_else(|| entry["is_current"].as_bool())
+                .unwrap_or_else(|| hardware_id.is_some_and(|h| h.eq_ignore_ascii_case(device_id)));
+            Some(TrustedClient {
+                id,
+                name: text(entry, &["name", "client_name", "device_name"]).unwrap_or_else(|| format!("Client {}", id)),
+                created_at: text(entry, &["created_at", "created"]),
+                current,
+            })
+        })
+        .collect()
+}
+
+/// Recording time Blink counted per camera over the last `range_days`. Accounts without a
+/// subscription get an empty report.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UsageReport {
+    #[serde(default)]
+    pub range_days: Option<i64>,
+    #[serde(default)]
+    pub networks: Vec<NetworkUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkUsage {
+    pub network_id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub cameras: Vec<CameraUsage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraUsage {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    /// Seconds counted against the plan: clips plus saved liveviews.
+    #[serde(default)]
+    pub usage: Option<i64>,
+    #[serde(default)]
+    pub clip_seconds: Option<i64>,
+    #[serde(default)]
+    pub lv_seconds: Option<i64>,
+    /// Bytes of this camera's clips in the local archive; only set when the media index
+    /// is enabled.
+    #[serde(default)]
+    pub local_bytes: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Network {
+    pub id: i64,
+    pub name: String,
+    pub armed: bool,
+    /// When a temporary disarm will arm the network again; never set by Blink.
+    #[serde(default)]
+    pub rearm_at: Option<chrono::DateTime<Utc>>,
+}
+
+/// Network-wide settings, merged from the homescreen entry and the network describe
+/// endpoint. Keys without a typed field are kept in `other`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct NetworkSettings {
+    #[serde(default)]
+    pub id: i64,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// IANA zone name, e.g. `Europe/Berlin`.
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub dst: Option<bool>,
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Save every liveview as a clip.
+    #[serde(default)]
+    pub lv_save: Option<bool>,
+    #[serde(default)]
+    pub video_quality: Option<String>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+impl NetworkSettings {
+    /// The network's time zone, if Blink reported one chrono-tz knows.
+    pub fn tz(&self) -> Option<chrono_tz::Tz> {
+        self.time_zone.as_deref().and_then(|tz| tz.parse().ok())
+    }
+}
+
+/// Fields to change on a network; `None` leaves a setting as it is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct NetworkSettingsPatch {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_zone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dst: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lv_save: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_quality: Option<String>,
+}
+
+impl NetworkSettingsPatch {
+    /// Rejects values Blink would store but nothing could use, such as unknown zones.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(tz) = &self.time_zone {
+            tz.parse::<chrono_tz::Tz>().map_err(|_| anyhow!("Unknown time zone: {}", tz))?;
+        }
+        if self.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+            return Err(anyhow!("Network name must not be empty"));
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CameraSignals {
+    pub wifi: Option<i64>,
+    pub battery: Option<i64>,
+    pub temp: Option<i64>,
+}
+
+/// Which homescreen list a device comes from. Each family has its own API routes.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceFamily {
+    #[default]
+    Camera,
+    Owl,
+    Doorbell,
+}
+
+impl DeviceFamily {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceFamily::Camera => "camera",
+            DeviceFamily::Owl => "owl",
+            DeviceFamily::Doorbell => "doorbell",
+        }
+    }
+
+    /// The family a `type` string belongs to; unknown types count as standard cameras.
+    pub fn of_product(product_type: &str) -> Self {
+        lookup_product(product_type).family
+    }
+
+    /// Path segment for the family's v1 device routes; standard cameras use the legacy ones.
+    fn v1_segment(self) -> Option<&'static str> {
+        match self {
+            DeviceFamily::Camera => None,
+            DeviceFamily::Owl => Some("owls"),
+            DeviceFamily::Doorbell => Some("doorbells"),
+        }
+    }
+}
+
+struct ProductType {
+    name: &'static str,
+    family: DeviceFamily,
+    /// Liveview goes through `/api/v1/.../{family}/{id}/liveview` rather than v5.
+    v1_liveview: bool,
+}
+
+/// Every `type` string Blink is known to report, plus the family names older clients send
+/// in its place. Unknown types are treated as standard cameras on the v5 liveview route.
+const PRODUCT_TYPES: &[ProductType] = &[
+    ProductType { name: "camera", family: DeviceFamily::Camera, v1_liveview: false },
+    ProductType { name: "white", family: DeviceFamily::Camera, v1_liveview: false },
+    ProductType { name: "xt", family: DeviceFamily::Camera, v1_liveview: false },
+    ProductType { name: "xt2", family: DeviceFamily::Camera, v1_liveview: true },
+    ProductType { name: "indoor", family: DeviceFamily::Camera, v1_liveview: true },
+    ProductType { name: "catalina", family: DeviceFamily::Camera, v1_liveview: false },
+    ProductType { name: "sedona", family: DeviceFamily::Camera, v1_liveview: false },
+    ProductType { name: "owl", family: DeviceFamily::Owl, v1_liveview: true },
+    ProductType { name: "mini", family: DeviceFamily::Owl, v1_liveview: true },
+    ProductType { name: "mini2", family: DeviceFamily::Owl, v1_liveview: true },
+    ProductType { name: "hawk", family: DeviceFamily::Owl, v1_liveview: true },
+    ProductType { name: "superior", family: DeviceFamily::Owl, v1_liveview: true },
+    ProductType { name: "doorbell", family: DeviceFamily::Doorbell, v1_liveview: true },
+    ProductType { name: "lotus", family: DeviceFamily::Doorbell, v1_liveview: true },
+    ProductType { name: "tulip", family: DeviceFamily::Doorbell, v1_liveview: true },
+];
+
+const UNKNOWN_PRODUCT: ProductType = ProductType { name: "", family: DeviceFamily::Camera, v1_liveview: false };
+
+fn lookup_product(name: &str) -> &'static ProductType {
+    PRODUCT_TYPES.iter().find(|p| p.name == name).unwrap_or(&UNKNOWN_PRODUCT)
+}
+
+/// Device URLs for one camera, chosen from its product type.
+#[derive(Clone, Copy)]
+struct DeviceEndpoints<'a> {
+    base_url: &'a str,
+    account_id: i64,
+    network_id: i64,
+    camera_id: i64,
+    family: DeviceFamily,
+    /// Liveview goes through `/api/v1/.../{family}/{id}/liveview` rather than v5.
+    v1_liveview: bool,
+}
+
+impl DeviceEndpoints<'_> {
+    /// The same device addressed through `family`'s routes, for when its own don't answer.
+    /// Owls and doorbells take liveview on v1, standard cameras on v5.
+    fn as_family(&self, family: DeviceFamily) -> Self {
+        let v1_liveview = if family == self.family { self.v1_liveview } else { family != DeviceFamily::Camera };
+        DeviceEndpoints { family, v1_liveview, ..*self }
+    }
+
+    /// Quality and audio are only known to be accepted on the v5 liveview route; v1
+    /// devices just get the intent.
+    fn liveview_options(&self) -> bool {
+        !self.v1_liveview
+    }
+
+    fn v1(&self, segment: &str, action: &str) -> String {
+        format!("{}/api/v1/accounts/{}/networks/{}/{}/{}/{}", self.base_url, self.account_id, self.network_id, segment, self.camera_id, action)
+    }
+
+    fn legacy(&self, action: &str) -> String {
+        format!("{}/network/{}/camera/{}/{}", self.base_url, self.network_id, self.camera_id, action)
+    }
+
+    fn liveview(&self) -> String {
+        if self.v1_liveview {
+            self.v1(self.family.v1_segment().unwrap_or("cameras"), "liveview")
+        } else {
+            format!("{}/api/v5/accounts/{}/networks/{}/cameras/{}/liveview", self.base_url, self.account_id, self.network_id, self.camera_id)
+        }
+    }
+
+    fn config(&self) -> String {
+        match self.family.v1_segment() {
+            Some(segment) => self.v1(segment, "config"),
+            None => self.legacy("config"),
+        }
+    }
+
+    fn config_update(&self) -> String {
+        match self.family.v1_segment() {
+            Some(segment) => self.v1(segment, "config"),
+            None => self.legacy("update"),
+        }
+    }
+
+    fn thumbnail(&self) -> String {
+        match self.family.v1_segment() {
+            Some(segment) => self.v1(segment, "thumbnail"),
+            None => self.legacy("thumbnail"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Camera {
+    pub id: i64,
+    pub name: String,
+    pub thumbnail: String,
+    pub status: String,
+    pub battery: Option<String>,
+    pub signals: Option<CameraSignals>,
+    pub network_id: Option<i64>,
+    /// Blink's model codename, e.g. `catalina`, `hawk` or `lotus`.
+    #[serde(rename = "type")]
+    pub product_type: String,
+    #[serde(default)]
+    pub device_family: DeviceFamily,
+    pub serial: Option<String>,
+    #[serde(default)]
+    pub fw_version: Option<String>,
+    /// Blink's notification snooze is on for the camera.
+    #[serde(default, rename(deserialize = "snooze"), alias = "snoozed", deserialize_with = "snooze_flag")]
+    pub snoozed: bool,
+    /// The fields below come from local camera preferences, not from Blink.
+    #[serde(default)]
+    pub display_name: String,
+    #[serde(default)]
+    pub sort_order: Option<i32>,
+    #[serde(default)]
+    pub hidden: bool,
+    #[serde(default)]
+    pub liveview_quality: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaItem {
+    pub id: i64,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub device_id: Option<i64>,
+    #[serde(default)]
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub network_id: Option<i64>,
+    #[serde(default)]
+    pub network_name: Option<String>,
+    #[serde(default, rename = "type")]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub thumbnail: Option<String>,
+    #[serde(default)]
+    pub media: Option<String>,
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    /// Set once the clip has been viewed in any Blink client; drives the unread badge.
+    #[serde(default)]
+    pub watched: bool,
+}
+
+/// Which push notifications the account sends. Flags Blink adds later, or that only some
+/// accounts have, are kept in `other` so an update never drops them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct NotificationConfig {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub motion: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doorbell: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub low_battery: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub battery_dead: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_offline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_module_offline: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduling: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub camera_usage: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wifi: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub local_storage: Option<bool>,
+    #[serde(flatten)]
+    pub other: serde_json::Map<String, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NotificationConfigBody {
+    notifications: NotificationConfig,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaPage {
+    #[serde(default)]
+    media: Vec<MediaItem>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteStatus {
+    Deleted,
+    StillPresent,
+    NotFound,
+    /// Hidden locally; Blink deletes it once the undo window has passed.
+    Pending,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeleteResult {
+    pub id: i64,
+    pub status: DeleteStatus,
+}
+
+#[derive(Clone)]
+pub struct Thumbnail {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+}
+
+/// Recognises the image formats Blink serves from their magic bytes.
+pub fn sniff_image_type(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+        _ => None,
+    }
+}
+
+/// The upstream `Content-Type` when it names an image, otherwise whatever the bytes look
+/// like, falling back to JPEG, which is what older cameras send.
+pub fn image_content_type(header: Option<&str>, bytes: &[u8]) -> String {
+    match header {
+        Some(header) if header.trim().to_ascii_lowercase().starts_with("image/") => header.trim().to_string(),
+        _ => sniff_image_type(bytes).unwrap_or("image/jpeg").to_string(),
+    }
+}
+
+/// Failures callers need to tell apart; everything else stays a plain `anyhow` error.
+/// Recover it with `err.downcast_ref::<BlinkError>()`.
+#[derive(Debug, Clone)]
+pub enum BlinkError {
+    NotLoggedIn,
+    /// Blink answered 401; the token is no longer accepted.
+    AuthExpired,
+    /// The refresh token was revoked (password change, too many devices), so the session
+    /// cannot be renewed; the user has to sign in again.
+    ReauthRequired,
+    /// Blink is throttling us. `retry_after_secs` comes from `Retry-After` or, for
+    /// logins, the lockout duration in the body, when Blink gave one.
+    RateLimited { message: String, retry_after_secs: Option<u64> },
+    /// The camera is serving another session or command. `retry_after` is Blink's
+    /// suggested wait in seconds, when it gave one.
+    CameraBusy { message: String, retry_after: Option<u64> },
+    NotFound(String),
+    /// Any other failure; `code` is Blink's own error code from the response body, if any.
+    Http { status: u16, code: Option<i64>, message: String },
+    /// The OAuth signin page no longer carries a CSRF token where we look for one; the
+    /// string says what was missing so bug reports are actionable.
+    LoginPageChanged(String),
+    /// A media or thumbnail path that can't be made into a URL.
+    InvalidPath(String),
+    /// Blink's final OAuth redirect carried no usable authorization code.
+    NoAuthCode(String),
+    /// A response body was larger than `max_response_bytes` allows.
+    ResponseTooLarge { context: String, limit: usize },
+    /// The token endpoint refused the authorization code.
+    AuthCodeRejected { status: u16, message: String },
+    /// A caller asked for something outside the range we send to Blink, like media page 0
+    /// or a since-window past `max_since_days`.
+    OutOfRange(String),
+}
+
+impl BlinkError {
+    /// Builds the error for a failed response from its body, which is usually Blink's
+    /// `{"message": "...", "code": 101}` envelope. Blink's own codes for an expired session
+    /// and a busy camera win over the HTTP status.
+    pub fn from_response(status: reqwest::StatusCode, body: &str, context: &str, retry_after: Option<u64>) -> Self {
+        let envelope: ErrorEnvelope = serde_json::from_str(body).unwrap_or_default();
+        let detail = envelope.message.as_deref()
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .unwrap_or_else(|| body.trim());
+        let mut message = format!("{}: {}", context, status);
+        if !detail.is_empty() {
+            message.push_str(": ");
+            message.extend(detail.chars().take(MAX_ERROR_DETAIL_CHARS));
+        }
+        if let Some(code) = envelope.code {
+            message.push_str(&format!(" (code {})", code));
+        }
+
+        match (status.as_u16(), envelope.code) {
+            (401, _) | (_, Some(CODE_UNAUTHORIZED)) => BlinkError::AuthExpired,
+            (307 | 409, _) | (_, Some(CODE_BUSY)) => {
+                BlinkError::CameraBusy { message, retry_after: retry_after.or(envelope.retry_after) }
+            }
+            (429, _) => BlinkError::RateLimited { message, retry_after_secs: retry_after.or(envelope.retry_after) },
+            (404, _) => BlinkError::NotFound(message),
+            (status, code) => BlinkError::Http { status, code, message },
+        }
+    }
+}
+
+/// `Retry-After` in seconds, from either the delay or the HTTP-date form.
+pub fn retry_after_header(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64)
+}
+
+/// The lockout Blink's OAuth pages report after too many failed attempts, in seconds.
+/// Seen under a few names; any of them counts.
+fn lockout_secs(body: &str) -> Option<u64> {
+    let body: serde_json::Value = serde_json::from_str(body).ok()?;
+    ["retry_after", "lockout_time_remaining", "lockout_remaining", "lockout_time"]
+        .iter()
+        .find_map(|key| body[*key].as_u64().or_else(|| body[*key].as_f64().map(|secs| secs.ceil() as u64)))
+}
+
+/// Turns a 429 or a lockout body from the sign-in or PIN endpoint into `RateLimited`.
+fn login_rate_limit(status: reqwest::StatusCode, headers: &HeaderMap, body: &str, context: &str) -> Option<BlinkError> {
+    let lockout = lockout_secs(body);
+    if status.as_u16() != 429 && lockout.is_none() {
+        return None;
+    }
+    let retry_after_secs = retry_after_header(headers).or(lockout);
+    let message = match retry_after_secs {
+        Some(secs) => format!("{}: too many attempts, try again in {}s", context, secs),
+        None => format!("{}: too many attempts, try again later", context),
+    };
+    Some(BlinkError::RateLimited { message, retry_after_secs })
+}
+
+/// Blink's usual error body.
+#[derive(Debug, Default, Deserialize)]
+struct ErrorEnvelope {
+    #[serde(default)]
+    message: Option<String>,
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    retry_after: Option<u64>,
+}
+
+/// OAuth error body from the token endpoint.
+#[derive(Debug, Default, Deserialize)]
+struct TokenErrorBody {
+    #[serde(default)]
+    error: String,
+}
+
+/// Whether a failed token response means the refresh token itself is no longer valid.
+fn is_invalid_grant(body: &str) -> bool {
+    serde_json::from_str::<TokenErrorBody>(body).is_ok_and(|b| b.error == "invalid_grant")
+}
+
+/// Blink error codes with a meaning of their own, whatever the HTTP status.
+const CODE_UNAUTHORIZED: i64 = 101;
+const CODE_BUSY: i64 = 307;
+/// Non-JSON error bodies (HTML error pages) are cut down to this much in messages.
+const MAX_ERROR_DETAIL_CHARS: usize = 300;
+
+impl std::fmt::Display for BlinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlinkError::NotLoggedIn => write!(f, "Not logged in"),
+            // Existing callers and older frontends match on this exact string.
+            BlinkError::AuthExpired => write!(f, "AUTH_EXPIRED"),
+            BlinkError::ReauthRequired => write!(f, "Blink revoked the saved session; sign in again"),
+            BlinkError::LoginPageChanged(detail) => write!(f, "Blink changed their login page ({})", detail),
+            BlinkError::InvalidPath(path) => write!(f, "Can't resolve media path {:?}", path),
+            BlinkError::ResponseTooLarge { context, limit } => {
+                write!(f, "{}: response is larger than the {} byte limit", context, limit)
+            }
+            BlinkError::NoAuthCode(detail) => write!(f, "No authorization code in Blink's redirect ({})", detail),
+            BlinkError::OutOfRange(detail) => write!(f, "{}", detail),
+            BlinkError::AuthCodeRejected { status, message } => {
+                write!(f, "Blink rejected the authorization code ({}): {}", status, message)
+            }
+            BlinkError::RateLimited { message, .. }
+            | BlinkError::CameraBusy { message, .. }
+            | BlinkError::NotFound(message)
+            | BlinkError::Http { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for BlinkError {}
+
+/// Largest response body read from Blink, unless `BLINK_MAX_RESPONSE_BYTES` says otherwise.
+/// Homescreens of big accounts run to a few hundred KB; this only stops a broken or hostile
+/// upstream from making us buffer without bound.
+pub const DEFAULT_MAX_RESPONSE_BYTES: usize = 10 * 1024 * 1024;
+
+static MAX_RESPONSE_BYTES: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+
+pub fn max_response_bytes() -> usize {
+    *MAX_RESPONSE_BYTES.get_or_init(|| {
+        std::env::var("BLINK_MAX_RESPONSE_BYTES")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&limit| limit > 0)
+            .unwrap_or(DEFAULT_MAX_RESPONSE_BYTES)
+    })
+}
+
+/// Longest media window, in days, unless `BLINK_MAX_SINCE_DAYS` says otherwise. Asking
+/// Blink for decades of changes makes it walk every tombstone on the account and time out.
+pub const DEFAULT_MAX_SINCE_DAYS: i64 = 365;
+
+/// Largest media page we ask Blink for with `page_size`.
+pub const MAX_MEDIA_PAGE_SIZE: u32 = 100;
+
+static MAX_SINCE_DAYS: std::sync::OnceLock<i64> = std::sync::OnceLock::new();
+
+pub fn max_since_days() -> i64 {
+    *MAX_SINCE_DAYS.get_or_init(|| {
+        std::env::var("BLINK_MAX_SINCE_DAYS")
+            .ok()
+            .and_then(|v| v.trim().parse().ok())
+            .filter(|&days| days > 0)
+            .unwrap_or(DEFAULT_MAX_SINCE_DAYS)
+    })
+}
+
+/// Rejects a media page request we shouldn't pass upstream: pages start at 1, the window
+/// runs from 1 to `max_since_days` days, and `page_size` from 1 to `MAX_MEDIA_PAGE_SIZE`.
+pub fn check_media_page(page: i64, since_days: i64, page_size: Option<u32>) -> Result<(), BlinkError> {
+    if page < 1 {
+        return Err(BlinkError::OutOfRange(format!("page must be 1 or more, not {}", page)));
+    }
+    let max_days = max_since_days();
+    if !(1..=max_days).contains(&since_days) {
+        return Err(BlinkError::OutOfRange(format!("sinceDays must be between 1 and {}, not {}", max_days, since_days)));
+    }
+    if let Some(size) = page_size.filter(|size| !(1..=MAX_MEDIA_PAGE_SIZE).contains(size)) {
+        return Err(BlinkError::OutOfRange(format!("pageSize must be between 1 and {}, not {}", MAX_MEDIA_PAGE_SIZE, size)));
+    }
+    Ok(())
+}
+
+/// `since_days` pulled into the range `check_media_page` accepts, for internal callers
+/// that derive the window from dates rather than take it from a user.
+pub fn clamp_since_days(since_days: i64) -> i64 {
+    since_days.clamp(1, max_since_days())
+}
+
+/// Reads the body, failing with `BlinkError::ResponseTooLarge` as soon as it passes
+/// `max_response_bytes` rather than buffering all of it.
+async fn read_body(mut res: reqwest::Response, context: &str) -> Result<Vec<u8>> {
+    crate::clock_skew::observe(res.headers());
+    let limit = max_response_bytes();
+    let too_large = || BlinkError::ResponseTooLarge { context: context.to_string(), limit };
+    if res.content_length().is_some_and(|len| len > limit as u64) {
+        return Err(too_large().into());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        if body.len() + chunk.len() > limit {
+            return Err(too_large().into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+async fn read_text(res: reqwest::Response, context: &str) -> Result<String> {
+    Ok(String::from_utf8_lossy(&read_body(res, context).await?).into_owned())
+}
+
+async fn read_json<T: serde::de::DeserializeOwned>(res: reqwest::Response, context: &str) -> Result<T> {
+    Ok(serde_json::from_slice(&read_body(res, context).await?)?)
+}
+
+/// Passes successful responses through and turns the rest into a `BlinkError` carrying
+/// the message and code from the response body and any `Retry-After`.
+async fn ensure_success(res: reqwest::Response, context: &str) -> Result<reqwest::Response> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(res);
+    }
+    let retry_after = retry_after_header(res.headers());
+    let body = read_text(res, context).await.unwrap_or_default();
+    Err(BlinkError::from_response(status, &body, context, retry_after).into())
+}
+
+/// Blink reports a snooze as a flag on some models and as the time it ends, or the seconds
+/// left, on others; any of those set means snoozed.
+fn is_snoozed(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Bool(b) => *b,
+        serde_json::Value::Number(n) => n.as_f64().is_some_and(|n| n > 0.0),
+        serde_json::Value::String(s) => !s.is_empty() && s != "false",
+        _ => false,
+    }
+}
+
+fn snooze_flag<'de, D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<bool, D::Error> {
+    Ok(is_snoozed(&serde_json::Value::deserialize(deserializer)?))
+}
+
+/// Builds a `Camera` from a per-network listing entry. Owl and doorbell entries report
+/// `enabled` instead of a status and may omit `type`.
+fn camera_from_listing(d: &serde_json::Value, network_id: i64, family: DeviceFamily) -> Camera {
+    let name = d["name"].as_str().unwrap_or("Unknown").to_string();
+    let product_type = match d["type"].as_str() {
+        Some(t) if !t.is_empty() => t.to_string(),
+        _ => family.as_str().to_string(),
+    };
+    let status = match (d["status"].as_str(), d["enabled"].as_bool()) {
+        (Some(status), _) => status.to_string(),
+        (None, Some(true)) => "online".to_string(),
+        (None, Some(false)) => "disabled".to_string(),
+        (None, None) => String::new(),
+    };
+    Camera {
+        id: d["id"].as_i64().unwrap_or(0),
+        name: name.clone(),
+        thumbnail: d["thumbnail"].as_str().unwrap_or("").to_string(),
+        status,
+        battery: d["battery"].as_str().map(|s| s.to_string()),
+        signals: serde_json::from_value(d["signals"].clone()).ok(),
+        network_id: Some(network_id),
+        product_type,
+        device_family: family,
+        serial: d["serial"].as_str().map(|s| s.to_string()),
+        fw_version: d["fw_version"].as_str().map(|s| s.to_string()),
+        snoozed: is_snoozed(&d["snooze"]),
+        display_name: name,
+        sort_order: None,
+        hidden: false,
+        liveview_quality: None,
+    }
+}
+
+/// Finds the CSRF token on the OAuth signin page. Blink embeds it as JSON in
+/// `<script id="oauth-args">`, but attribute order, whitespace and entity-escaping have
+/// all shifted before, so this parses the document properly and, if that script is gone,
+/// looks through every other inline script for a `csrf-token` key.
+fn extract_csrf_token(html: &str) -> Option<String> {
+    let document = scraper::Html::parse_document(html);
+    let oauth_args = scraper::Selector::parse("script#oauth-args").ok()?;
+    let inline = scraper::Selector::parse("script:not([src])").ok()?;
+    document.select(&oauth_args)
+        .chain(document.select(&inline))
+        .find_map(|script| csrf_from_script(&script.text().collect::<String>()))
+}
+
+/// Whether `url` is on one of Amazon's sign-in domains: `amazon.<tld>`, `amazon.co.<cc>` or
+/// `amazon.com.<cc>`, or a subdomain of one. Only the end of the host counts, so
+/// `amazon.example.com` is not Amazon.
+fn is_amazon_host(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else { return false };
+    let labels: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+    let registrable = match labels.as_slice() {
+        [.., "amazon", "co" | "com", cc] if cc.len() == 2 => true,
+        [.., "amazon", tld] => tld.len() >= 2 && tld.chars().all(|c| c.is_ascii_alphabetic()),
+        _ => false,
+    };
+    registrable && url.scheme() == "https"
+}
+
+/// Where a sign-in response sent us: its `Location`, or the page it ended on when the
+/// client followed the redirects itself.
+fn redirect_target(res: &reqwest::Response) -> Result<reqwest::Url, BlinkError> {
+    match res.headers().get(LOCATION) {
+        Some(loc) => loc.to_str().ok()
+            .and_then(|loc| res.url().join(loc).ok())
+            .ok_or_else(|| BlinkError::NoAuthCode(format!("unreadable Location header {:?}", loc))),
+        None => Ok(res.url().clone()),
+    }
+}
+
+/// The Amazon sign-in form on `html`: where it posts and its fields, hidden ones included,
+/// with `email` and `password` left for the caller. The form is `signIn` on every Amazon
+/// locale seen so far; any form with a password field is the fallback.
+fn parse_amazon_form(html: &str, page: &reqwest::Url) -> Option<(reqwest::Url, Vec<(String, String)>)> {
+    let document = scraper::Html::parse_document(html);
+    let named = scraper::Selector::parse("form[name=signIn]").ok()?;
+    let any = scraper::Selector::parse("form").ok()?;
+    let inputs = scraper::Selector::parse("input[name]").ok()?;
+    let password = scraper::Selector::parse("input[type=password]").ok()?;
+    let form = document.select(&named).next()
+        .or_else(|| document.select(&any).find(|form| form.select(&password).next().is_some()))?;
+    let action = page.join(form.value().attr("action").unwrap_or("")).ok()?;
+    let fields = form.select(&inputs)
+        .filter_map(|input| {
+            let name = input.value().attr("name")?;
+            let kind = input.value().attr("type").unwrap_or("text");
+            (kind != "submit" && kind != "checkbox").then(|| (name.to_string(), input.value().attr("value").unwrap_or("").to_string()))
+        })
+        .collect();
+    Some((action, fields))
+}
+
+fn csrf_from_script(text: &str) -> Option<String> {
+    let text = text.trim();
+    let json = serde_json::from_str::<serde_json::Value>(text)
+        .or_else(|_| serde_json::from_str(&unescape_html(text)))
+        .ok();
+    if let Some(token) = json.as_ref().and_then(|json| find_string(json, "csrf-token")) {
+        return Some(token);
+    }
+    // Scripts that assign the args to a variable instead of holding bare JSON.
+    let re = Regex::new(r#"["']csrf-token["']\s*:\s*["']([^"']+)["']"#).ok()?;
+    re.captures(&unescape_html(text)).map(|caps| caps[1].to_string())
+}
+
+fn find_string(value: &serde_json::Value, key: &str) -> Option<String> {
+    match value {
+        serde_json::Value::Object(map) => map.get(key)
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .or_else(|| map.values().find_map(|v| find_string(v, key))),
+        serde_json::Value::Array(items) => items.iter().find_map(|v| find_string(v, key)),
+        _ => None,
+    }
+}
+
+/// Undoes the entity escaping Blink has applied to script contents; `&amp;` goes last so
+/// an escaped entity is not decoded twice.
+fn unescape_html(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#34;", "\"")
+        .replace("&#x22;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Classifies a failed liveview request. On top of the usual busy signals, some firmware
+/// answers other 4xx codes with a `message` saying the device is busy.
+fn liveview_error(status: reqwest::StatusCode, retry_after: Option<u64>, body: &str) -> BlinkError {
+    match BlinkError::from_response(status, body, "Liveview API returned", retry_after) {
+        BlinkError::Http { status: 400..=499, message, .. } if message.to_ascii_lowercase().contains("busy") => {
+            BlinkError::CameraBusy { message, retry_after }
+        }
+        other => other,
+    }
+}
+
+/// Ids per `mark_as_viewed` request; larger selections are sent in several batches.
+const WATCHED_BATCH_SIZE: usize = 50;
+
+/// Outcome of arming or disarming one network as part of `set_arm_all`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkArmResult {
+    pub network_id: i64,
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// What `set_arm_verified` saw. `confirmed` is set only once the homescreen shows the
+/// requested state; until then `warning` says why it isn't.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmVerification {
+    /// The arm state asked for.
+    pub requested: bool,
+    pub confirmed: bool,
+    /// The network's arm state as last read from the homescreen; `None` if it couldn't be read.
+    pub state: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Reads a command status response. `None` while the command is still running; commands
+/// missing from the response count as finished once Blink reports it `complete`.
+fn command_outcome(status: &serde_json::Value, command_id: i64) -> Option<Result<(), String>> {
+    let command = status["commands"].as_array()
+        .into_iter()
+        .flatten()
+        .find(|c| c["id"].as_i64() == Some(command_id));
+    match command {
+        Some(command) => match command["state_condition"].as_str() {
+            Some("new" | "running") => None,
+            Some("done") | None => Some(Ok(())),
+            Some(other) => {
+                let message = command["state_message"].as_str().unwrap_or(other);
+                Some(Err(format!("Command {} ended as {}", command_id, message)))
+            }
+        },
+        None => match status["complete"].as_bool() {
+            Some(false) => None,
+            _ => Some(Ok(())),
+        },
+    }
+}
+
+fn join_ids(ids: impl Iterator<Item = i64>) -> String {
+    ids.map(|id| id.to_string()).collect::<Vec<_>>().join(",")
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LiveViewResponse {
+    pub server: String,
+    pub command_id: i64,
+    pub polling_interval: i64,
+    pub params: LiveviewParams,
+    /// Seconds Blink runs the command before ending it, keepalives or not.
+    #[serde(default)]
+    pub duration: Option<i64>,
+    /// Seconds before the end at which the app asks the viewer whether to keep watching.
+    #[serde(default)]
+    pub continue_warning: Option<i64>,
+}
+
+/// Stream quality some cameras let a liveview request choose.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveviewQuality {
+    Saver,
+    Best,
+}
+
+impl std::str::FromStr for LiveviewQuality {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "saver" => Ok(LiveviewQuality::Saver),
+            "best" => Ok(LiveviewQuality::Best),
+            _ => Err(anyhow!("Unknown liveview quality '{}' (expected saver or best)", s)),
+        }
+    }
+}
+
+/// Optional liveview request parameters; `None` leaves Blink's default, which is what
+/// every request got before these existed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LiveviewOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quality: Option<LiveviewQuality>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub intent: Option<String>,
+}
+
+/// What a liveview request sent and what Blink echoed back. A parameter missing from
+/// `honored` was either not sent or not acknowledged by the camera.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LiveviewParams {
+    pub requested: LiveviewOptions,
+    pub honored: LiveviewOptions,
+}
+
+/// Where the client's session stands, as reported by `check_auth`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthState {
+    LoggedOut,
+    LoggedIn,
+    /// Was logged in until Blink rejected the refresh token; see `BlinkError::ReauthRequired`.
+    ReauthRequired,
+}
+
+/// Hosts a client talks to. `Default` is Blink's production; pointing a client at a mock
+/// server or a recording proxy only takes `BlinkClient::with_urls`.
+#[derive(Debug, Clone)]
+pub struct BlinkUrls {
+    pub oauth: String,
+    /// Answers `tier_info` before the account's own tier is known.
+    pub rest: String,
+    /// See `TIER_URL_TEMPLATE`; a template without `{tier}` sends every tier to one host.
+    pub tier_template: String,
+}
+
+impl Default for BlinkUrls {
+    fn default() -> Self {
+        Self {
+            oauth: OAUTH_BASE_URL.to_string(),
+            rest: BASE_URL.to_string(),
+            tier_template: TIER_URL_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl BlinkUrls {
+    fn for_tier(&self, tier: &str) -> String {
+        self.tier_template.replace("{tier}", tier)
+    }
+}
+
+/// The account moved to another tier: `base_url` answered 404 or 426 while the token was
+/// still good, and `tier_info` named a different host.
+#[derive(Debug, Clone, Serialize)]
+pub struct TierHeal {
+    pub old_base_url: String,
+    pub new_base_url: String,
+    /// The request that ran into the old host.
+    pub trigger: String,
+    pub healed_at: chrono::DateTime<Utc>,
+}
+
+/// Shared between clones: the last re-discovery, and a heal not yet applied to the client
+/// that owns the session (see `take_tier_heal`).
+#[derive(Default)]
+struct TierHealState {
+    last_attempt: Option<std::time::Instant>,
+    pending: Option<TierHeal>,
+}
+
+/// Blink's answer from a tier the account has left.
+fn is_wrong_tier(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::NotFound(_) | BlinkError::Http { status: 426, .. }))
+}
+
+/// A body fetched by `get_shared`, handed to every caller coalesced onto the request.
+/// Its JSON is parsed the first time someone asks and reused after that.
+struct FetchedBody {
+    text: String,
+    json: std::sync::OnceLock<std::result::Result<Arc<serde_json::Value>, String>>,
+}
+
+impl FetchedBody {
+    fn json(&self) -> Result<Arc<serde_json::Value>> {
+        self.json
+            .get_or_init(|| serde_json::from_str(&self.text).map(Arc::new).map_err(|e| e.to_string()))
+            .clone()
+            .map_err(|e| anyhow!("Invalid JSON from Blink: {}", e))
+    }
+}
+
+/// A GET in flight; the error is shared because every waiting caller receives it.
+type InflightGet = Shared<BoxFuture<'static, Result<Arc<FetchedBody>, Arc<anyhow::Error>>>>;
+
+/// Rebuilds an error handed to several coalesced callers, keeping a `BlinkError` intact so
+/// each of them can still match on it.
+fn shared_error(e: &anyhow::Error) -> anyhow::Error {
+    match e.downcast_ref::<BlinkError>() {
+        Some(blink) => blink.clone().into(),
+        None => anyhow!("{:#}", e),
+    }
+}
+
+/// Cloning is cheap: the HTTP client and cookie store are shared, so a clone can make
+/// requests after the original's lock has been released.
+#[derive(Clone)]
+pub struct BlinkClient {
+    /// JSON API calls, with a whole-request timeout.
+    pub client: reqwest::Client,
+    /// Clips and thumbnails: no total timeout and its own connection pool, so a long
+    /// download neither gets cut off nor holds up API calls.
+    pub media_client: reqwest::Client,
+    /// Shared with `client`; read back when persisting the OAuth session cookies.
+    cookies: Arc<CookieStoreMutex>,
+    pub token: Option<SecretString>,
+    pub refresh_token: Option<SecretString>,
+    pub account_id: Option<i64>,
+    pub base_url: String,
+    pub device_id: String,
+    pub code_verifier: SecretString,
+    pub csrf_token: Option<String>,
+    /// Sent with the authorize request; a `state` echoed in the redirect must match it.
+    oauth_state: Option<String>,
+    /// Unix time, by Blink's clock (see `clock_skew`), at which the token expires.
+    pub token_expiry: Option<i64>,
+    /// Blink's time at the last successful refresh; refreshes are spaced at least
+    /// `clock_skew::MIN_REFRESH_INTERVAL_SECS` apart.
+    last_refresh: Option<i64>,
+    /// Set when the token endpoint rejected our refresh token; cleared by a new login.
+    reauth_required: bool,
+    /// Blink rate-limited the last login or PIN attempt; no new one is sent before this.
+    login_retry_at: Option<chrono::DateTime<Utc>>,
+    /// The Login with Amazon page Blink sent an Amazon-linked account to; see `login_amazon`.
+    amazon_signin: Option<reqwest::Url>,
+    urls: BlinkUrls,
+    /// Coalesced GETs by URL, shared between clones so identical concurrent requests go
+    /// upstream once.
+    inflight: Arc<std::sync::Mutex<HashMap<String, InflightGet>>>,
+    tier_heal: Arc<std::sync::Mutex<TierHealState>>,
+    /// Version and fields of the loaded state this build doesn't know, written back by
+    /// `get_state` so a newer build sharing the store loses nothing.
+    saved_version: u32,
+    saved_unknown: serde_json::Map<String, serde_json::Value>,
+}
+
+/// Layout of the saved `BlinkAuthState`. States without a `version` are v0, written before
+/// the field existed; some of those also lack `token_expiry`, `cookies` or the tokens.
+pub const AUTH_STATE_VERSION: u32 = 1;
+
+fn default_base_url() -> String {
+    BlinkUrls::default().rest
+}
+
+/// The session as saved to the keychain or `auth.json`. Every field has a default so that
+/// older states still load, and fields this build doesn't know (written by a newer one
+/// sharing the same keychain entry) are carried through `unknown` and saved back as they
+/// were.
+#[derive(Serialize, Deserialize)]
+pub struct BlinkAuthState {
+    #[serde(default)]
+    pub version: u32,
+    #[serde(default, with = "secret_opt")]
+    pub token: Option<SecretString>,
+    #[serde(default, with = "secret_opt")]
+    pub refresh_token: Option<SecretString>,
+    #[serde(default)]
+    pub account_id: Option<i64>,
+    #[serde(default = "default_base_url")]
+    pub base_url: String,
+    #[serde(default)]
+    pub device_id: String,
+    #[serde(default)]
+    pub token_expiry: Option<i64>,
+    /// Unexpired `api.oauth.blink.com` cookies; the OAuth endpoints and, on some accounts,
+    /// token refresh expect the session cookie set during sign-in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<Cookie<'static>>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reauth_required: bool,
+    #[serde(flatten)]
+    pub unknown: serde_json::Map<String, serde_json::Value>,
+}
+
+impl BlinkAuthState {
+    /// Reads a saved state of any version and brings it up to `AUTH_STATE_VERSION`. `source`
+    /// names where it came from, so a state that can't be read says what to remove.
+    pub fn from_json(json: &str, source: &str) -> Result<Self> {
+        serde_json::from_str::<Self>(json).map(Self::migrated).map_err(|e| anyhow!(
+            "The saved Blink session in {} can't be read ({}). Sign in again to replace it, or delete {} and restart.",
+            source, e, source
+        ))
+    }
+
+    /// Upgrades an older state in place. States from a newer build keep their version, so
+    /// saving them back doesn't make that build migrate again.
+    pub fn migrated(mut self) -> Self {
+        // 0 -> 1: the defaults above fill in what v0 lacked; a state without a device id
+        // gets a new one, as a fresh client would.
+        if self.version < 1 {
+            if self.device_id.is_empty() {
+                self.device_id = Uuid::new_v4().to_string().to_uppercase();
+            }
+            if self.base_url.is_empty() {
+                self.base_url = default_base_url();
+            }
+        }
+        self.version = self.version.max(AUTH_STATE_VERSION);
+        self
+    }
+}
+
+/// Tokens are already redacted by `SecretString`; cookie values are dropped the same way.
+impl std::fmt::Debug for BlinkAuthState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlinkAuthState")
+            .field("version", &self.version)
+            .field("token", &self.token)
+            .field("refresh_token", &self.refresh_token)
+            .field("account_id", &self.account_id)
+            .field("base_url", &self.base_url)
+            .field("device_id", &self.device_id)
+            .field("token_expiry", &self.token_expiry)
+            .field("cookies", &self.cookies.iter().map(|c| c.name()).collect::<Vec<_>>())
+            .field("reauth_required", &self.reauth_required)
+            .field("unknown", &self.unknown.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Default for BlinkClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlinkClient {
+    pub fn new() -> Self {
+        Self::with_urls(BlinkUrls::default())
+    }
+
+    /// A logged-out client that talks to `urls` instead of Blink's production hosts.
+    pub fn with_urls(urls: BlinkUrls) -> Self {
+        Self::with_cookies(CookieStore::default(), urls)
+    }
+
+    fn with_cookies(store: CookieStore, urls: BlinkUrls) -> Self {
+        let cookies = Arc::new(CookieStoreMutex::new(store));
+        let device_id = Uuid::new_v4().to_string().to_uppercase();
+        let (client, media_client) = http_clients(&cookies, HttpTimeouts::default());
+
+        Self {
+            client,
+            media_client,
+            cookies,
+            token: None,
+            refresh_token: None,
+            account_id: None,
+            base_url: urls.rest.clone(),
+            device_id,
+            code_verifier: Self::generate_verifier(),
+            csrf_token: None,
+            oauth_state: None,
+            token_expiry: None,
+            last_refresh: None,
+            reauth_required: false,
+            login_retry_at: None,
+            amazon_signin: None,
+            urls,
+            inflight: Arc::default(),
+            tier_heal: Arc::default(),
+            saved_version: AUTH_STATE_VERSION,
+            saved_unknown: serde_json::Map::new(),
+        }
+    }
+
+    pub fn from_state(state: BlinkAuthState) -> Self {
+        Self::from_state_with_urls(state, BlinkUrls::default())
+    }
+
+    /// Restores a saved session; `urls` are not part of the saved state, so a client
+    /// pointed elsewhere has to be told again.
+    pub fn from_state_with_urls(state: BlinkAuthState, urls: BlinkUrls) -> Self {
+        let state = state.migrated();
+        // `from_cookies` drops anything that expired while the state sat on disk.
+        let store = CookieStore::from_cookies(state.cookies.into_iter().map(Ok::<_, std::convert::Infallible>), false)
+            .unwrap_or_default();
+        let mut client = Self::with_cookies(store, urls);
+        client.token = state.token;
+        client.refresh_token = state.refresh_token;
+        client.account_id = state.account_id;
+        client.base_url = state.base_url;
+        client.device_id = state.device_id;
+        client.token_expiry = state.token_expiry;
+        client.reauth_required = state.reauth_required;
+        client.saved_version = state.version;
+        client.saved_unknown = state.unknown;
+        client
+    }
+
+    pub fn get_state(&self) -> BlinkAuthState {
+        BlinkAuthState {
+            version: self.saved_version,
+            token: self.token.clone(),
+            refresh_token: self.refresh_token.clone(),
+            account_id: self.account_id,
+            base_url: self.base_url.clone(),
+            device_id: self.device_id.clone(),
+            token_expiry: self.token_expiry,
+            cookies: self.oauth_cookies(),
+            reauth_required: self.reauth_required,
+            unknown: self.saved_unknown.clone(),
+        }
+    }
+
+    fn oauth_cookies(&self) -> Vec<Cookie<'static>> {
+        let Ok(oauth) = reqwest::Url::parse(&self.urls.oauth) else { return Vec::new() };
+        let Ok(store) = self.cookies.lock() else { return Vec::new() };
+        store.iter_unexpired().filter(|c| c.matches(&oauth)).cloned().collect()
+    }
+
+    /// Adopts a stored auth state only when it is at least as useful as what is in memory:
+    /// either nothing is loaded yet, or the stored token expires later than ours.
+    /// Returns true when the stored state replaced the in-memory one.
+    pub fn hydrate_from(&mut self, saved: BlinkAuthState) -> bool {
+        let replace = match (&self.token, saved.token.is_some()) {
+            (None, _) => true,
+            (Some(_), false) => false,
+            (Some(_), true) => saved.token_expiry.unwrap_or(i64::MIN) > self.token_expiry.unwrap_or(i64::MIN),
+        };
+        if replace {
+            *self = Self::from_state_with_urls(saved, self.urls.clone());
+        }
+        replace
+    }
+
+    pub fn auth_state(&self) -> AuthState {
+        if self.reauth_required {
+            AuthState::ReauthRequired
+        } else if self.token.is_some() {
+            AuthState::LoggedIn
+        } else {
+            AuthState::LoggedOut
+        }
+    }
+
+    /// Drops the dead session but remembers why, so callers can ask the user to sign in
+    /// again instead of treating them as never logged in.
+    fn require_reauth(&mut self) {
+        self.token = None;
+        self.refresh_token = None;
+        self.token_expiry = None;
+        self.reauth_required = true;
+    }
+
+    fn no_session(&self) -> BlinkError {
+        if self.reauth_required { BlinkError::ReauthRequired } else { BlinkError::NotLoggedIn }
+    }
+
+    // Helper to get token reference; callers must `expose_secret()` to use it
+    pub fn token(&self) -> Result<&SecretString> {
+        self.token.as_ref().ok_or_else(|| self.no_session().into())
+    }
+
+    /// Forgets the session in memory; callers also clear the persisted auth.
+    pub fn logout(&mut self) {
+        self.token = None;
+        self.refresh_token = None;
+        self.account_id = None;
+        self.token_expiry = None;
+        self.reauth_required = false;
+        self.saved_unknown.clear();
+        audit::record("logout", "account", None);
+    }
+
+    // Helper to get both token and account_id
+    pub fn auth(&self) -> Result<(&SecretString, i64)> {
+        let token = self.token()?;
+        let account_id = self.account_id.ok_or_else(|| self.no_session())?;
+        Ok((token, account_id))
+    }
+
+    /// The URL for a media or thumbnail path on this account's REST host; see `resolve_path`.
+    pub fn resolve_url(&self, path: &str) -> Result<reqwest::Url, BlinkError> {
+        resolve_path(&self.base_url, path)
+    }
+
+    fn generate_verifier() -> SecretString {
+        let mut rng = thread_rng();
+        let mut bytes: Vec<u8> = (0..32).map(|_| rng.gen::<u8>()).collect();
+        let verifier = general_purpose::URL_SAFE_NO_PAD.encode(&bytes);
+        bytes.zeroize();
+        SecretString::from(verifier)
+    }
+
+    fn get_challenge(verifier: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(verifier.as_bytes());
+        let hash = hasher.finalize();
+        general_purpose::URL_SAFE_NO_PAD.encode(hash)
+    }
+
+    /// Refuses to contact the sign-in endpoints while a known rate limit is in force, so
+    /// retrying early doesn't extend the lockout.
+    fn check_login_allowed(&self) -> Result<(), BlinkError> {
+        let Some(at) = self.login_retry_at else { return Ok(()) };
+        let remaining = (at - Utc::now()).num_seconds();
+        if remaining <= 0 {
+            return Ok(());
+        }
+        Err(BlinkError::RateLimited {
+            message: format!("Too many login attempts, try again in {}s", remaining),
+            retry_after_secs: Some(remaining as u64),
+        })
+    }
+
+    /// The rate limit a sign-in or PIN response reports, if any, remembered so
+    /// `check_login_allowed` holds off until it passes.
+    fn note_login_limit(&mut self, status: reqwest::StatusCode, headers: &HeaderMap, body: &str, context: &str) -> Option<BlinkError> {
+        let e = login_rate_limit(status, headers, body, context)?;
+        if let BlinkError::RateLimited { retry_after_secs: Some(secs), .. } = &e {
+            self.login_retry_at = Some(Utc::now() + Duration::seconds(*secs as i64));
+        }
+        Some(e)
+    }
+
+    /// Builds the error for a failed sign-in or PIN response, remembering any rate limit.
+    async fn login_failure(&mut self, res: reqwest::Response, context: &str) -> anyhow::Error {
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = read_text(res, context).await.unwrap_or_default();
+        match self.note_login_limit(status, &headers, &body, context) {
+            Some(e) => e.into(),
+            None => anyhow!("{}: {}", context, status),
+        }
+    }
+
+    pub async fn start_oauth_flow(&mut self) -> Result<()> {
+        self.check_login_allowed()?;
+        let challenge = Self::get_challenge(self.code_verifier.expose_secret());
+        let state = Uuid::new_v4().to_string();
+        let url = format!("{}/oauth/v2/authorize", self.urls.oauth);
+
+        let params = [
+            ("app_brand", "blink"),
+            ("app_version", "30.0.0"),
+            ("client_id", "ios"),
+            ("code_challenge", &challenge),
+            ("code_challenge_method", "S256"),
+            ("device_brand", "Apple"),
+            ("device_model", "iPhone16,1"),
+            ("device_os_version", "18.2"),
+            ("hardware_id", &self.device_id),
+            ("redirect_uri", "immedia-blink://applinks.blink.com/signin/callback"),
+            ("response_type", "code"),
+            ("scope", "client"),
+            ("state", &state),
+        ];
+
+        let _res = self.client.get(&url).query(&params).send().await?;
+        self.oauth_state = Some(state);
+        let signin_url = format!("{}/oauth/v2/signin", self.urls.oauth);
+        let res = self.client.get(&signin_url).send().await?;
+        let html = read_text(res, "Signin page request failed").await?;
+
+        let token = extract_csrf_token(&html).ok_or_else(|| {
+            BlinkError::LoginPageChanged("no csrf-token in script#oauth-args or any inline script".to_string())
+        })?;
+        self.csrf_token = Some(token);
+        Ok(())
+    }
+
+    /// Takes the password by value so it is wiped as soon as the form has been sent.
+    pub async fn login_oauth(&mut self, email: &str, password: SecretString) -> Result<String> {
+        audit::track("login", format!("account {}", email), async {
+            self.check_login_allowed()?;
+            let csrf = self.csrf_token.as_ref().ok_or(anyhow!("No CSRF token"))?;
+            let url = format!("{}/oauth/v2/signin", self.urls.oauth);
+        
+            let params = [
+                ("username", email),
+                ("password", password.expose_secret()),
+                ("csrf-token", csrf),
+            ];
+
+            let res = self.client.post(&url)
+                .header(ORIGIN, &self.urls.oauth)
+                .header(REFERER, &url)
+                .form(&params)
+                .send()
+                .await;
+            drop(password);
+            let res = res?;
+
+            if res.status().as_u16() == 412 {
+                return Ok("2FA_REQUIRED".to_string());
+            }
+
+            // Accounts moved to "Sign in with Amazon" are sent to Login with Amazon instead.
+            if let Some(target) = redirect_target(&res).ok().filter(is_amazon_host) {
+                self.amazon_signin = Some(target);
+                return Ok("AMAZON_LOGIN_REQUIRED".to_string());
+            }
+
+            if res.status().is_redirection() {
+                return self.exchange_code().await;
+            }
+
+            // A locked-out account can get the sign-in page back with a 200 and a lockout body.
+            if res.status().is_success() {
+                let (status, headers) = (res.status(), res.headers().clone());
+                let body = read_text(res, "Login failed with status").await.unwrap_or_default();
+                if let Some(e) = self.note_login_limit(status, &headers, &body, "Login failed with status") {
+                    return Err(e.into());
+                }
+                return self.exchange_code().await;
+            }
+
+            Err(self.login_failure(res, "Login failed with status").await)
+        }).await
+    }
+
+    /// The Login with Amazon page to finish signing in on, after `login_oauth` returned
+    /// `AMAZON_LOGIN_REQUIRED`.
+    pub fn amazon_signin_url(&self) -> Option<String> {
+        self.amazon_signin.as_ref().map(|url| url.to_string())
+    }
+
+    /// Signs in on the Amazon page `login_oauth` was sent to and resumes Blink's flow with
+    /// the callback. Returns `SUCCESS`, or `2FA_REQUIRED` when Blink still wants its PIN.
+    /// Amazon asking for anything beyond the password (OTP, captcha) fails; the user then
+    /// signs in on `amazon_signin_url` in a browser and passes where it ended up to
+    /// `complete_amazon_login`.
+    pub async fn login_amazon(&mut self, email: &str, password: SecretString) -> Result<String> {
+        audit::track("login_amazon", format!("account {}", email), async {
+            self.check_login_allowed()?;
+            let page_url = self.amazon_signin.clone().ok_or(anyhow!("No Amazon sign-in in progress"))?;
+            let res = self.client.get(page_url.clone()).send().await?;
+            let page_url = res.url().clone();
+            let html = read_text(ensure_success(res, "Amazon sign-in page request failed").await?, "Amazon sign-in page request failed").await?;
+            let (action, mut fields) = parse_amazon_form(&html, &page_url)
+                .ok_or_else(|| BlinkError::LoginPageChanged("no sign-in form on the Amazon page".to_string()))?;
+            fields.retain(|(name, _)| name != "email" && name != "password");
+            fields.push(("email".to_string(), email.to_string()));
+            fields.push(("password".to_string(), password.expose_secret().to_string()));
+
+            let res = self.client.post(action)
+                .header(REFERER, page_url.as_str())
+                .form(&fields)
+                .send()
+                .await;
+            for (_, value) in fields.iter_mut() {
+                value.zeroize();
+            }
+            drop(password);
+            let res = res?;
+            self.resume_after_amazon(res).await
+        }).await
+    }
+
+    /// Finishes an Amazon sign-in done in a browser. `callback` is the URL the browser
+    /// ended on: Blink's callback page, or the app's `immedia-blink://` link carrying the
+    /// code. Only those two are accepted, so this can't be pointed at another host.
+    pub async fn complete_amazon_login(&mut self, callback: &str) -> Result<String> {
+        audit::track("login_amazon", "account", async {
+            let url = reqwest::Url::parse(callback.trim()).map_err(|e| anyhow!("Not a URL: {}", e))?;
+            if url.scheme() == "immedia-blink" {
+                let code = self.auth_code_from_redirect(&url)?;
+                self.amazon_signin = None;
+                return self.redeem_code(&code).await;
+            }
+            let oauth_host = reqwest::Url::parse(&self.urls.oauth).ok().and_then(|u| u.host_str().map(str::to_string));
+            if url.scheme() != "https" || url.host_str().map(str::to_string) != oauth_host {
+                return Err(anyhow!("Paste the Blink URL the browser ended on, not {}", url.host_str().unwrap_or("this address")));
+            }
+            let res = self.client.get(url).send().await?;
+            self.resume_after_amazon(res).await
+        }).await
+    }
+
+    /// Picks Blink's flow back up from the response to the Amazon sign-in.
+    async fn resume_after_amazon(&mut self, res: reqwest::Response) -> Result<String> {
+        if res.status().as_u16() == 412 {
+            self.amazon_signin = None;
+            return Ok("2FA_REQUIRED".to_string());
+        }
+        let target = redirect_target(&res)?;
+        if target.scheme() == "immedia-blink" {
+            let code = self.auth_code_from_redirect(&target)?;
+            self.amazon_signin = None;
+            return self.redeem_code(&code).await;
+        }
+        if is_amazon_host(&target) {
+            return Err(anyhow!(
+                "Amazon wants more than a password (a one-time code or a captcha). Sign in at {} in a browser and paste the address it ends on",
+                self.amazon_signin_url().unwrap_or_default()
+            ));
+        }
+        if !res.status().is_success() && !res.status().is_redirection() {
+            return Err(self.login_failure(res, "Amazon sign-in failed with status").await);
+        }
+        self.amazon_signin = None;
+        self.exchange_code().await
+    }
+
+    pub async fn verify_pin_oauth(&mut self, pin: SecretString) -> Result<String> {
+        audit::track("verify_pin", "account", async {
+            self.check_login_allowed()?;
+            let csrf = self.csrf_token.as_ref().ok_or(anyhow!("No CSRF token"))?;
+            let url = format!("{}/oauth/v2/2fa/verify", self.urls.oauth);
+        
+            let params = [
+                ("2fa_code", pin.expose_secret()),
+                ("csrf-token", csrf),
+                ("remember_me", "false"),
+            ];
+
+            let res = self.client.post(&url)
+                .header(ORIGIN, &self.urls.oauth)
+                .header(REFERER, format!("{}/oauth/v2/signin", self.urls.oauth))
+                .form(&params)
+                .send()
+                .await;
+            drop(pin);
+            let res = res?;
+
+            if res.status().as_u16() == 201 {
+                return self.exchange_code().await;
+            }
+
+            Err(self.login_failure(res, "PIN verification failed").await)
+        }).await
+    }
+
+    /// Percent-decodes `code` from the redirect and checks any `state` against the one sent.
+    fn auth_code_from_redirect(&self, redirect: &reqwest::Url) -> Result<String, BlinkError> {
+        let mut code = None;
+        let mut state = None;
+        for (key, value) in redirect.query_pairs() {
+            match key.as_ref() {
+                "code" => code = Some(value.into_owned()),
+                "state" => state = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+        // The query holds the code itself, so only the rest of the URL goes into errors.
+        let mut shown = redirect.clone();
+        shown.set_query(None);
+
+        if let (Some(state), Some(expected)) = (&state, &self.oauth_state) {
+            if state != expected {
+                return Err(BlinkError::NoAuthCode(format!("state mismatch in redirect to {}", shown)));
+            }
+        }
+        code.filter(|code| !code.is_empty())
+            .ok_or_else(|| BlinkError::NoAuthCode(format!("no code parameter in redirect to {}", shown)))
+    }
+
+    async fn exchange_code(&mut self) -> Result<String> {
+        let url = format!("{}/oauth/v2/authorize", self.urls.oauth);
+        let res = self.client.get(&url).send().await?;
+
+        // The redirect policy stops at the app's custom scheme, so the code is normally in
+        // the Location header of the last response; otherwise look at where we ended up.
+        let redirect = redirect_target(&res)?;
+        let code = self.auth_code_from_redirect(&redirect)?;
+        self.redeem_code(&code).await
+    }
+
+    /// Trades an authorization code for tokens.
+    async fn redeem_code(&mut self, code: &str) -> Result<String> {
+        let token_url = format!("{}/oauth/token", self.urls.oauth);
+        let body = [
+            ("app_brand", "blink"),
+            ("client_id", "ios"),
+            ("code", code),
+            ("code_verifier", self.code_verifier.expose_secret()),
+            ("grant_type", "authorization_code"),
+            ("hardware_id", &self.device_id),
+            ("redirect_uri", "immedia-blink://applinks.blink.com/signin/callback"),
+            ("scope", "client"),
+        ];
+
+        let res = self.client.post(&token_url)
+            .header("User-Agent", "Blink/2511191620 CFNetwork/3860.200.71 Darwin/25.1.0")
+            .form(&body)
+            .send()
+            .await?;
+        if !res.status().is_success() {
+            let status = res.status().as_u16();
+            let message = read_text(res, "Token exchange failed").await.unwrap_or_default();
+            return Err(BlinkError::AuthCodeRejected { status, message }.into());
+        }
+
+        let auth_data = read_json::<AuthResponse>(res, "Token exchange failed").await?;
+        self.token = Some(auth_data.access_token);
+        self.refresh_token = Some(auth_data.refresh_token);
+        self.token_expiry = Some(crate::clock_skew::server_now() + auth_data.expires_in);
+        self.reauth_required = false;
+
+        self.fetch_tier_info().await?;
+        Ok("SUCCESS".to_string())
+    }
+
+    pub async fn refresh_token_if_needed(&mut self) -> Result<bool> {
+        if self.token_expiry.is_some()
+            && !crate::clock_skew::needs_refresh(self.token_expiry, self.last_refresh, Utc::now(), crate::clock_skew::skew_secs())
+        {
+            return Ok(false);
+        }
+
+        let refresh = match &self.refresh_token {
+            Some(r) => r.clone(),
+            None if self.reauth_required => return Err(BlinkError::ReauthRequired.into()),
+            None => return Err(anyhow!("No refresh token available")),
+        };
+
+        let token_url = format!("{}/oauth/token", self.urls.oauth);
+        let body = [
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh.expose_secret()),
+            ("client_id", "ios"),
+            ("app_brand", "blink"),
+        ];
+
+        let res = self.client.post(&token_url)
+            .header("User-Agent", "Blink/2511191620 CFNetwork/3860.200.71 Darwin/25.1.0")
+            .form(&body)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = read_text(res, "Token refresh failed").await.unwrap_or_default();
+            if is_invalid_grant(&body) {
+                self.require_reauth();
+                return Err(BlinkError::ReauthRequired.into());
+            }
+            return Err(BlinkError::from_response(status, &body, "Token refresh failed", None).into());
+        }
+
+        let auth_data = read_json::<AuthResponse>(res, "Token refresh failed").await?;
+        self.token = Some(auth_data.access_token);
+        self.refresh_token = Some(auth_data.refresh_token);
+        let now = crate::clock_skew::server_now();
+        self.token_expiry = Some(now + auth_data.expires_in);
+        self.last_refresh = Some(now);
+
+        Ok(true)
+    }
+
+    pub async fn fetch_tier_info(&mut self) -> Result<()> {
+        let token = self.token()?;
+        let url = format!("{}/api/v1/users/tier_info", self.urls.rest);
+
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        let data: serde_json::Value = read_json(res, "Tier info request failed").await?;
+        let tier = data["tier"].as_str().ok_or(anyhow!("No tier in response"))?;
+        self.account_id = data["account_id"].as_i64();
+        self.base_url = self.urls.for_tier(tier);
+        
+        Ok(())
+    }
+
+    /// Cheap authenticated call used for health checks; returns the account tier.
+    pub async fn probe_tier(&self) -> Result<String> {
+        let token = self.token()?;
+        let url = format!("{}/api/v1/users/tier_info", self.urls.rest);
+
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        let res = ensure_success(res, "Tier info request failed").await?;
+
+        let data: serde_json::Value = read_json(res, "Tier info request failed").await?;
+        Ok(data["tier"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Per-camera recording time. Blink answers 404 for accounts without a plan, which is
+    /// reported as an empty usage report rather than an error.
+    pub async fn get_usage(&self) -> Result<UsageReport> {
+        let token = self.token()?;
+        let url = format!("{}/api/v1/camera/usage", self.base_url);
+
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+        if res.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(UsageReport::default());
+        }
+
+        read_json(ensure_success(res, "Usage request failed").await?, "Usage request failed").await
+    }
+
+    /// Devices trusted for this account, from whichever clients endpoint the tier serves.
+    pub async fn list_trusted_clients(&self) -> Result<Vec<TrustedClient>> {
+        let (token, account_id) = self.auth()?;
+        for path in CLIENT_LIST_PATHS {
+            let url = format!("{}/{}", self.base_url, path.replace("{account}", &account_id.to_string()));
+            let res = self.client.get(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            if matches!(res.status().as_u16(), 404 | 405) {
+                continue;
+            }
+            let body: serde_json::Value = read_json(ensure_success(res, "Trusted clients request failed").await?, "Trusted clients request failed").await?;
+            return Ok(parse_trusted_clients(&body, &self.device_id));
+        }
+        Err(BlinkError::NotFound("This account's tier has no trusted clients endpoint".to_string()).into())
+    }
+
+    /// Removes a trusted device. Tiers differ: some take `DELETE` on the client, others a
+    /// `POST` to its `delete` action, so the second is tried when the first isn't served.
+    pub async fn revoke_trusted_client(&self, client_id: i64) -> Result<()> {
+        audit::track("revoke_trusted_client", format!("client {}", client_id), async {
+            let (token, account_id) = self.auth()?;
+            let base = format!("{}/api/v1/accounts/{}/clients/{}", self.base_url, account_id, client_id);
+            let res = self.client.delete(&base)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            if !matches!(res.status().as_u16(), 404 | 405) {
+                ensure_success(res, "Revoking trusted client failed").await?;
+                return Ok(());
+            }
+            let res = self.client.post(format!("{}/delete", base))
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            ensure_success(res, "Revoking trusted client failed").await?;
+            Ok(())
+        }).await
+    }
+
+    pub async fn get_homescreen(&self) -> Result<HomescreenResponse> {
+        let json = self.get_homescreen_json().await?;
+        let mut response = HomescreenResponse::deserialize(&*json)?;
+
+        // Merge the three lists, remembering which one each device came from
+        let lists = [
+            (DeviceFamily::Camera, std::mem::take(&mut response.cameras)),
+            (DeviceFamily::Owl, std::mem::take(&mut response.owls)),
+            (DeviceFamily::Doorbell, std::mem::take(&mut response.doorbells)),
+        ];
+        for (family, cams) in lists {
+            response.cameras.extend(cams.into_iter().map(|mut cam| {
+                cam.device_family = family;
+                cam
+            }));
+        }
+
+        if response.cameras.is_empty() {
+            let fetches = response.networks.iter().map(|network| self.get_network_devices(network.id));
+            for devices in futures_util::future::join_all(fetches).await {
+                response.cameras.extend(devices);
+            }
+        }
+        
+        Ok(response)
+    }
+
+    pub async fn get_raw_homescreen(&self) -> Result<String> {
+        Ok(self.fetch_homescreen().await?.text.clone())
+    }
+
+    /// The homescreen, parsed once per fetch: callers coalesced onto the same request
+    /// share one `Value` instead of each parsing the body.
+    pub async fn get_homescreen_json(&self) -> Result<Arc<serde_json::Value>> {
+        self.fetch_homescreen().await?.json()
+    }
+
+    async fn fetch_homescreen(&self) -> Result<Arc<FetchedBody>> {
+        let (token, account_id) = self.auth()?;
+        let path = format!("/api/v3/accounts/{}/homescreen", account_id);
+        self.get_tiered(&path, token, "Homescreen request failed").await
+    }
+
+    pub async fn get_raw_media(&self) -> Result<String> {
+        self.get_raw_media_page(1, 30, None).await
+    }
+
+    /// One page of media changed in the last `since_days` days. Out-of-range arguments fail
+    /// with `BlinkError::OutOfRange` (see `check_media_page`); `page_size` of `None` leaves
+    /// Blink's default.
+    pub async fn get_raw_media_page(&self, page: i64, since_days: i64, page_size: Option<u32>) -> Result<String> {
+        check_media_page(page, since_days, page_size)?;
+        let since = Utc::now() - Duration::days(since_days);
+        self.get_raw_media_since(since, page, page_size).await
+    }
+
+    pub async fn get_raw_media_since(&self, since: chrono::DateTime<Utc>, page: i64, page_size: Option<u32>) -> Result<String> {
+        let (token, account_id) = self.auth()?;
+        if page < 1 {
+            return Err(BlinkError::OutOfRange(format!("page must be 1 or more, not {}", page)).into());
+        }
+        let timestamp = since.format("%Y-%m-%dT%H:%M:%S+00:00").to_string();
+        let mut path = format!("/api/v1/accounts/{}/media/changed?since={}&page={}", account_id, timestamp, page);
+        if let Some(size) = page_size {
+            path.push_str(&format!("&page_size={}", size));
+        }
+        Ok(self.get_tiered(&path, token, "Media request failed").await?.text.clone())
+    }
+
+    /// `get_shared` on `base_url` joined with `path`. When the answer says the account has
+    /// left this tier, the tier is looked up again and the request retried once on the new
+    /// host.
+    async fn get_tiered(&self, path: &str, token: &SecretString, context: &'static str) -> Result<Arc<FetchedBody>> {
+        match self.get_shared(format!("{}{}", self.base_url, path), token, context).await {
+            Err(e) if is_wrong_tier(&e) => match self.heal_tier(context).await {
+                Some(base_url) => self.get_shared(format!("{}{}", base_url, path), token, context).await,
+                None => Err(e),
+            },
+            result => result,
+        }
+    }
+
+    /// Asks `tier_info` for the account's host and returns it if it differs from
+    /// `base_url`, leaving a `TierHeal` for `take_tier_heal`. At most once per
+    /// `TIER_HEAL_COOLDOWN_SECS` across clones; within it, a heal already found is reused.
+    async fn heal_tier(&self, trigger: &str) -> Option<String> {
+        {
+            let mut state = self.tier_heal.lock().unwrap();
+            let cooldown = std::time::Duration::from_secs(TIER_HEAL_COOLDOWN_SECS);
+            if state.last_attempt.is_some_and(|at| at.elapsed() < cooldown) {
+                return state.pending.as_ref()
+                    .filter(|heal| heal.old_base_url == self.base_url)
+                    .map(|heal| heal.new_base_url.clone());
+            }
+            state.last_attempt = Some(std::time::Instant::now());
+        }
+        let tier = match self.probe_tier().await {
+            Ok(tier) if !tier.is_empty() => tier,
+            Ok(_) => return None,
+            Err(e) => {
+                crate::log_control::warn("blink", || format!("Tier re-discovery after {} failed: {}", trigger, e));
+                return None;
+            }
+        };
+        let new_base_url = self.urls.for_tier(&tier);
+        if new_base_url == self.base_url {
+            return None;
+        }
+        crate::log_control::info("blink", || format!("Account moved from {} to {}; switching hosts", self.base_url, new_base_url));
+        self.tier_heal.lock().unwrap().pending = Some(TierHeal {
+            old_base_url: self.base_url.clone(),
+            new_base_url: new_base_url.clone(),
+            trigger: trigger.to_string(),
+            healed_at: Utc::now(),
+        });
+        Some(new_base_url)
+    }
+
+    /// A tier change found by this client or any clone since the last call, now applied to
+    /// `base_url`. The caller persists the state.
+    pub fn take_tier_heal(&mut self) -> Option<TierHeal> {
+        let heal = self.tier_heal.lock().unwrap().pending.take()?;
+        if heal.old_base_url != self.base_url {
+            return None;
+        }
+        self.base_url = heal.new_base_url.clone();
+        Some(heal)
+    }
+
+    /// GETs `url` and returns the body. Callers asking for the same URL while a request is
+    /// in flight, from this client or any clone, wait for that request instead of sending
+    /// their own.
+    async fn get_shared(&self, url: String, token: &SecretString, context: &'static str) -> Result<Arc<FetchedBody>> {
+        let shared = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(&url) {
+                Some(pending) => pending.clone(),
+                None => {
+                    let client = self.client.clone();
+                    let registry = self.inflight.clone();
+                    let authorization = format!("Bearer {}", token.expose_secret());
+                    let key = url.clone();
+                    let request = async move {
+                        let result = async {
+                            crate::log_control::debug("blink", || format!("GET {}", key));
+                            let res = client.get(&key).header("Authorization", authorization).send().await?;
+                            let text = read_text(ensure_success(res, context).await?, context).await?;
+                            Ok(Arc::new(FetchedBody { text, json: Default::default() }))
+                        }.await;
+                        registry.lock().unwrap().remove(&key);
+                        result.map_err(Arc::new)
+                    }.boxed().shared();
+                    inflight.insert(url, request.clone());
+                    request
+                }
+            }
+        };
+        shared.await.map_err(|e| shared_error(&e))
+    }
+
+    /// Like `get_raw_media_page`, parsed, with `since_days` clamped rather than rejected.
+    pub async fn get_media_page(&self, page: i64, since_days: i64) -> Result<Vec<MediaItem>> {
+        let text = self.get_raw_media_page(page, clamp_since_days(since_days), None).await?;
+        let parsed: MediaPage = serde_json::from_str(&text)?;
+        Ok(parsed.media)
+    }
+
+    /// Walks media pages until Blink returns an empty page or `max_pages` is reached.
+    pub async fn get_all_media(&self, since_days: i64, max_pages: i64) -> Result<Vec<MediaItem>> {
+        let mut all = Vec::new();
+        for page in 1..=max_pages.max(1) {
+            let items = self.get_media_page(page, since_days).await?;
+            if items.is_empty() {
+                break;
+            }
+            all.extend(items.into_iter().filter(|item| !item.deleted));
+        }
+        Ok(all)
+    }
+
+    /// Every device on a network from the per-network listings, for accounts whose
+    /// homescreen comes back without devices. A listing that fails is skipped.
+    pub async fn get_network_devices(&self, network_id: i64) -> Vec<Camera> {
+        let (cameras, owls, doorbells) = tokio::join!(
+            self.get_network_cameras(network_id),
+            self.get_network_family(network_id, DeviceFamily::Owl),
+            self.get_network_family(network_id, DeviceFamily::Doorbell),
+        );
+        let mut devices = Vec::new();
+        for (family, listing) in [(DeviceFamily::Camera, cameras), (DeviceFamily::Owl, owls), (DeviceFamily::Doorbell, doorbells)] {
+            match listing {
+                Ok(list) => devices.extend(list),
+                Err(e) => eprintln!("Failed to list {} devices for network {}: {}", family.as_str(), network_id, e),
+            }
+        }
+        devices
+    }
+
+    pub async fn get_network_cameras(&self, network_id: i64) -> Result<Vec<Camera>> {
+        let token = self.token()?;
+        let url = format!("{}/network/{}/cameras", self.base_url, network_id);
+        
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        let data: serde_json::Value = read_json(res, "Camera listing failed").await?;
+        let dev = data["dev"].as_array().ok_or(anyhow!("No dev in network response"))?;
+        Ok(dev.iter().map(|d| camera_from_listing(d, network_id, DeviceFamily::Camera)).collect())
+    }
+
+    /// Minis or doorbells on a network; `/network/{id}/cameras` only lists standard cameras.
+    async fn get_network_family(&self, network_id: i64, family: DeviceFamily) -> Result<Vec<Camera>> {
+        let (token, account_id) = self.auth()?;
+        let segment = family.v1_segment().ok_or(anyhow!("No v1 listing for {} devices", family.as_str()))?;
+        let url = format!("{}/api/v1/accounts/{}/networks/{}/{}", self.base_url, account_id, network_id, segment);
+
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+        let res = ensure_success(res, "Device listing failed").await?;
+
+        // Seen both as a bare array and wrapped in an object keyed by the segment.
+        let data: serde_json::Value = read_json(res, "Device listing failed").await?;
+        let list = data.as_array().or_else(|| data[segment].as_array()).ok_or(anyhow!("No {} in listing response", segment))?;
+        Ok(list.iter().map(|d| camera_from_listing(d, network_id, family)).collect())
+    }
+
+    fn endpoints(&self, account_id: i64, network_id: i64, camera_id: i64, product_type: &str) -> DeviceEndpoints<'_> {
+        let product = lookup_product(product_type);
+        DeviceEndpoints { base_url: &self.base_url, account_id, network_id, camera_id, family: product.family, v1_liveview: product.v1_liveview }
+    }
+
+    /// Runs `call` against the device's routes: the family remembered for it first, then
+    /// its product type's own, then the rest. A 404 or 422 moves on to the next family; any
+    /// other failure is returned as is. When a family other than the device's own answers
+    /// it is remembered in `device_routes`; if every family fails, the first error is
+    /// returned.
+    async fn on_device_routes<'a, T, F, Fut>(&self, endpoints: DeviceEndpoints<'a>, product_type: &str, call: F) -> Result<T>
+    where
+        F: Fn(DeviceEndpoints<'a>) -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let remembered = device_routes::lookup(endpoints.camera_id, product_type).map(|r| r.family);
+        let mut order = Vec::new();
+        for family in remembered.into_iter().chain([endpoints.family, DeviceFamily::Camera, DeviceFamily::Owl, DeviceFamily::Doorbell]) {
+            if !order.contains(&family) {
+                order.push(family);
+            }
+        }
+
+        let mut attempts = Vec::new();
+        let mut first_error = None;
+        for family in order {
+            match call(endpoints.as_family(family)).await {
+                Ok(value) => {
+                    if family == endpoints.family && remembered.is_some_and(|r| r != family) {
+                        device_routes::forget(endpoints.camera_id);
+                    } else if family != endpoints.family && remembered != Some(family) {
+                        attempts.push(RouteAttempt { family, status: None });
+                        device_routes::remember(endpoints.camera_id, product_type, family, attempts);
+                    }
+                    return Ok(value);
+                }
+                Err(e) => {
+                    let status = match e.downcast_ref::<BlinkError>() {
+                        Some(BlinkError::NotFound(_)) => Some(404),
+                        Some(BlinkError::Http { status, .. }) => Some(*status),
+                        _ => None,
+                    };
+                    if !matches!(status, Some(404 | 422)) {
+                        return Err(e);
+                    }
+                    attempts.push(RouteAttempt { family, status });
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        Err(first_error.unwrap_or_else(|| anyhow!("No routes to try for camera {}", endpoints.camera_id)))
+    }
+
+    /// Quality and audio in `options` are dropped for product types that don't take them;
+    /// the response records what was actually sent. Devices that don't answer on their
+    /// family's route are retried on the others (see `on_device_routes`).
+    pub async fn request_liveview(&self, network_id: i64, camera_id: i64, product_type: &str, options: &LiveviewOptions) -> Result<LiveViewResponse> {
+        audit::track("liveview_start", format!("camera {} (network {})", camera_id, network_id), async {
+            let (token, account_id) = self.auth()?;
+            let endpoints = self.endpoints(account_id, network_id, camera_id, product_type);
+            self.on_device_routes(endpoints, product_type, |endpoints| self.send_liveview(endpoints, token, options)).await
+        }).await
+    }
+
+    async fn send_liveview(&self, endpoints: DeviceEndpoints<'_>, token: &SecretString, options: &LiveviewOptions) -> Result<LiveViewResponse> {
+        let url = endpoints.liveview();
+
+        let mut requested = LiveviewOptions {
+            intent: Some(options.intent.clone().unwrap_or_else(|| "liveview".to_string())),
+            ..Default::default()
+        };
+        if endpoints.liveview_options() {
+            requested.quality = options.quality;
+            requested.audio = options.audio;
+        }
+        let body = serde_json::to_value(&requested)?;
+
+        let res = self.client.post(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .json(&body)
+            .send()
+            .await?;
+
+        if !res.status().is_success() {
+            let status = res.status();
+            let retry_after = retry_after_header(res.headers());
+            let text = read_text(res, "Liveview request failed").await.unwrap_or_default();
+            return Err(liveview_error(status, retry_after, &text).into());
+        }
+
+        let data: serde_json::Value = read_json(res, "Liveview request failed").await?;
+        let server = data["server"].as_str().ok_or(anyhow!("No 'server' field in liveview response. Response: {}", data))?.to_string();
+        let command_id = data["command_id"].as_i64().ok_or(anyhow!("No 'command_id' field in response. Response: {}", data))?;
+        let polling_interval = data["polling_interval"].as_i64().unwrap_or(1);
+        let honored = LiveviewOptions {
+            quality: data["quality"].as_str().and_then(|q| q.parse().ok()),
+            audio: data["audio"].as_bool(),
+            intent: data["intent"].as_str().map(str::to_string),
+        };
+
+        Ok(LiveViewResponse {
+            server,
+            command_id,
+            polling_interval,
+            params: LiveviewParams { requested, honored },
+            duration: data["duration"].as_i64(),
+            continue_warning: data["continue_warning"].as_i64(),
+        })
+    }
+
+    /// Ids of the camera's clips created between `after` and `before` that Blink still has.
+    /// Media for one camera changed since `after`, deleted items left out. Items come back
+    /// raw so callers can apply their own rules.
+    pub async fn camera_media_since(&self, camera_id: i64, after: chrono::DateTime<Utc>) -> Result<Vec<serde_json::Value>> {
+        let (token, account_id) = self.auth()?;
+        // Format as ISO8601 for Blink API
+        let timestamp = after.format("%Y-%m-%dT%H:%M:%S+00:00").to_string();
+        let url = format!("{}/api/v1/accounts/{}/media/changed?since={}&page=1", self.base_url, account_id, timestamp);
+        
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        let res = ensure_success(res, "Failed to fetch media").await?;
+
+        let mut data: serde_json::Value = read_json(res, "Failed to fetch media").await?;
+        let Some(media_list) = data["media"].as_array_mut() else {
+            return Ok(Vec::new());
+        };
+        let items = media_list.drain(..).filter(|item| {
+            let device_match = ["device_id", "camera_id", "sensor_id"].iter().any(|key| {
+                item[key].as_i64().or_else(|| item[key].as_str().and_then(|s| s.parse::<i64>().ok())) == Some(camera_id)
+            });
+            device_match && item["deleted"].as_bool() != Some(true)
+        }).collect();
+        Ok(items)
+    }
+
+    pub async fn delete_media(&self, media_ids: Vec<i64>) -> Result<()> {
+        let target = format!("media {}", join_ids(media_ids.iter().copied()));
+        audit::track("delete_media", target, self.send_delete(media_ids)).await
+    }
+
+    async fn send_delete(&self, media_ids: Vec<i64>) -> Result<()> {
+        let (token, account_id) = self.auth()?;
+        let url = format!("{}/api/v1/accounts/{}/media/delete", self.base_url, account_id);
+        let payloads = vec![
+            serde_json::json!({ "media_list": media_ids }),
+            serde_json::json!({ "media_list": media_ids.iter().map(|id| serde_json::json!({ "id": id })).collect::<Vec<_>>() }),
+            serde_json::json!({ "media_list": media_ids.iter().map(|id| serde_json::json!({ "media_id": id })).collect::<Vec<_>>() }),
+        ];
+
+        let mut last_error: Option<anyhow::Error> = None;
+        for body in payloads {
+            let res = self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .json(&body)
+                .send()
+                .await;
+
+            match res {
+                Ok(response) => match ensure_success(response, "Delete failed").await {
+                    Ok(_) => return Ok(()),
+                    Err(e) => last_error = Some(e),
+                },
+                Err(e) => last_error = Some(e.into()),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("Delete failed")))
+    }
+
+    async fn delete_media_with_payloads(&self, media_ids: Vec<i64>, entries: Vec<serde_json::Value>) -> Result<()> {
+        let (token, account_id) = self.auth()?;
+        let url = format!("{}/api/v1/accounts/{}/media/delete", self.base_url, account_id);
+
+        let payloads = vec![
+            serde_json::json!({ "media_list": media_ids }),
+            serde_json::json!({ "media_list": media_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>() }),
+            serde_json::json!({ "media_list": entries }),
+        ];
+
+        let mut last_error: Option<anyhow::Error> = None;
+        for body in payloads {
+            let res = self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .json(&body)
+                .send()
+                .await;
+
+            match res {
+                Ok(response) => match ensure_success(response, "Delete failed").await {
+                    Ok(_) => return Ok(()),
+                    Err(e) => last_error = Some(e),
+                },
+                Err(e) => last_error = Some(e.into()),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("Delete failed")))
+    }
+
+    /// Marks clips as viewed, in batches of `WATCHED_BATCH_SIZE`. Blink accepts ids that
+    /// are already watched, so callers don't need to filter them out first.
+    pub async fn mark_media_watched(&self, media_ids: &[i64]) -> Result<()> {
+        let mut ids = media_ids.to_vec();
+        ids.sort_unstable();
+        ids.dedup();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let target = format!("media {}", join_ids(ids.iter().copied()));
+        audit::track("mark_watched", target, async {
+            for batch in ids.chunks(WATCHED_BATCH_SIZE) {
+                self.send_mark_watched(batch).await?;
+            }
+            Ok(())
+        }).await
+    }
+
+    async fn send_mark_watched(&self, media_ids: &[i64]) -> Result<()> {
+        let (token, account_id) = self.auth()?;
+        let url = format!("{}/api/v1/accounts/{}/media/mark_as_viewed", self.base_url, account_id);
+        let res = self.client.post(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .json(&serde_json::json!({ "media_list": media_ids }))
+            .send()
+            .await?;
+        ensure_success(res, "Mark watched failed").await?;
+        Ok(())
+    }
+
+    /// Deletes the given media items (as sent by the frontend) and confirms the result
+    /// against `media/changed`, starting from the oldest item's timestamp so clips on later
+    /// pages or outside the default window are still found.
+    pub async fn delete_media_verified(&self, items: &[serde_json::Value]) -> Result<Vec<DeleteResult>> {
+        audit::track("delete_media", format!("media {}", join_ids(items.iter().filter_map(|item| item.get("id").and_then(|v| v.as_i64())))), async {
+            let media_ids: Vec<i64> = items.iter()
+                .filter_map(|item| item.get("id").and_then(|v| v.as_i64()))
+                .collect();
+            if media_ids.is_empty() {
+                return Ok(Vec::new());
+            }
+
+            let delete_result = self.send_delete(media_ids.clone()).await;
+
+            // Try richer payloads if the basic delete fails
+            if delete_result.is_err() {
+                let entries: Vec<serde_json::Value> = items.iter()
+                    .map(|item| match item.as_object() {
+                        Some(obj) => {
+                            let mut map = obj.clone();
+                            if let Some(id) = map.get("id").and_then(|v| v.as_i64()) {
+                                map.insert("media_id".to_string(), serde_json::json!(id.to_string()));
+                            }
+                            serde_json::Value::Object(map)
+                        }
+                        None => item.clone(),
+                    })
+                    .collect();
+                let _ = self.delete_media_with_payloads(media_ids.clone(), entries).await;
+            }
+
+            let since = items.iter()
+                .filter_map(|item| item.get("created_at").and_then(|v| v.as_str()).and_then(parse_blink_timestamp))
+                .min()
+                .map(|oldest| oldest - Duration::minutes(1))
+                .unwrap_or_else(|| Utc::now() - Duration::days(DELETE_VERIFY_FALLBACK_DAYS));
+
+            let mut results = Vec::new();
+            for attempt in 0..DELETE_VERIFY_ATTEMPTS {
+                if attempt > 0 {
+                    tokio::time::sleep(std::time::Duration::from_millis(500 << (attempt - 1))).await;
+                }
+                results = self.media_delete_status(&media_ids, since).await?;
+                if !results.iter().any(|r| r.status == DeleteStatus::StillPresent) {
+                    break;
+                }
+            }
+
+            if let Err(e) = delete_result {
+                if results.iter().any(|r| r.status == DeleteStatus::StillPresent) {
+                    return Err(e);
+                }
+            }
+            Ok(results)
+        }).await
+    }
+
+    async fn media_delete_status(&self, media_ids: &[i64], since: chrono::DateTime<Utc>) -> Result<Vec<DeleteResult>> {
+        let mut seen: std::collections::HashMap<i64, bool> = std::collections::HashMap::new();
+        for page in 1..=DELETE_VERIFY_MAX_PAGES {
+            let text = self.get_raw_media_since(since, page, None).await?;
+            let parsed: MediaPage = serde_json::from_str(&text)?;
+            if parsed.media.is_empty() {
+                break;
+            }
+            for item in parsed.media {
+                if media_ids.contains(&item.id) {
+                    seen.insert(item.id, item.deleted);
+                }
+            }
+            if seen.len() == media_ids.len() {
+                break;
+            }
+        }
+
+        Ok(media_ids.iter()
+            .map(|&id| DeleteResult {
+                id,
+                status: match seen.get(&id) {
+                    Some(true) => DeleteStatus::Deleted,
+                    Some(false) => DeleteStatus::StillPresent,
+                    None => DeleteStatus::NotFound,
+                },
+            })
+            .collect())
+    }
+
+    pub async fn get_command_status(&self, network_id: i64, command_id: i64) -> Result<serde_json::Value> {
+        let token = self.token()?;
+        let url = format!("{}/network/{}/command/{}", self.base_url, network_id, command_id);
+        
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        read_json(res, "Command status request failed").await
+    }
+
+    pub async fn stop_command(&self, network_id: i64, command_id: i64) -> Result<()> {
+        audit::track("liveview_stop", format!("command {} (network {})", command_id, network_id), async {
+            let token = self.token()?;
+            let url = format!("{}/network/{}/command/{}/done", self.base_url, network_id, command_id);
+
+            let res = self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+
+            ensure_success(res, "Failed to stop command").await?;
+            Ok(())
+        }).await
+    }
+
+    /// Asks Blink to keep a liveview command running past its `duration`. Returns the new
+    /// duration in seconds when Blink says; an error once the command can't be extended.
+    pub async fn extend_liveview(&self, network_id: i64, command_id: i64) -> Result<Option<i64>> {
+        audit::track("liveview_extend", format!("command {} (network {})", command_id, network_id), async {
+            let token = self.token()?;
+            let url = format!("{}/network/{}/command/{}/update", self.base_url, network_id, command_id);
+
+            let res = self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+
+            let res = ensure_success(res, "Failed to extend liveview").await?;
+            let data: serde_json::Value = read_json(res, "Failed to extend liveview").await.unwrap_or_default();
+            Ok(data["duration"].as_i64())
+        }).await
+    }
+
+    pub async fn get_thumbnail(&self, path: &str) -> Result<Thumbnail> {
+        let token = self.token()?;
+        let mut url = self.resolve_url(path)?;
+        // Owl thumbnails without an extension are only served with a `ts` query.
+        let last_segment = url.path().rsplit('/').next().unwrap_or_default();
+        if url.query().is_none() && !last_segment.contains('.') {
+            url.set_query(Some(&format!("ts={}", Utc::now().timestamp())));
+        }
+        let mut req = self.media_client.get(url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .header("Accept", "image/*");
+
+        if let Ok(origin) = HeaderValue::from_str(&self.base_url) {
+            req = req.header(ORIGIN, origin.clone());
+            req = req.header(REFERER, origin);
+        }
+
+        let res = ensure_success(req.send().await?, "Thumbnail request failed").await?;
+        let header = res.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let bytes = read_body(res, "Thumbnail request failed").await?;
+        let content_type = image_content_type(header.as_deref(), &bytes);
+        Ok(Thumbnail { bytes, content_type })
+    }
+
+    /// Arms or disarms a network and waits for Blink's command to finish.
+    pub async fn set_arm(&self, network_id: i64, arm: bool) -> Result<()> {
+        audit::track(if arm { "arm" } else { "disarm" }, format!("network {}", network_id), async {
+            let Some(command_id) = self.send_arm(network_id, arm).await? else {
+                return Ok(());
+            };
+            let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(ARM_COMMAND_TIMEOUT_SECS);
+            if self.poll_command(network_id, command_id, deadline).await? {
+                Ok(())
+            } else {
+                Err(anyhow!("Blink had not finished command {} after {}s", command_id, ARM_COMMAND_TIMEOUT_SECS))
+            }
+        }).await
+    }
+
+    /// Like `set_arm`, then re-reads the homescreen until it shows the new state. Blink
+    /// rejecting the request or the command is an `Err`; running out of `timeout` before
+    /// the state is confirmed is not, and comes back unconfirmed with a warning.
+    pub async fn set_arm_verified(&self, network_id: i64, arm: bool, timeout: std::time::Duration) -> Result<ArmVerification> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        audit::track(if arm { "arm" } else { "disarm" }, format!("network {}", network_id), async {
+            let finished = match self.send_arm(network_id, arm).await? {
+                Some(command_id) => self.poll_command(network_id, command_id, deadline).await?,
+                None => true,
+            };
+
+            let interval = std::time::Duration::from_secs(ARM_VERIFY_INTERVAL_SECS);
+            let state = loop {
+                let state = match self.get_homescreen().await {
+                    Ok(homescreen) => homescreen.networks.iter().find(|n| n.id == network_id).map(|n| n.armed),
+                    Err(e) => {
+                        eprintln!("Failed to read network {} to confirm arm state: {}", network_id, e);
+                        None
+                    }
+                };
+                if state == Some(arm) || tokio::time::Instant::now() + interval >= deadline {
+                    break state;
+                }
+                tokio::time::sleep(interval).await;
+            };
+
+            let confirmed = state == Some(arm);
+            let warning = match (confirmed, finished, state) {
+                (true, _, _) => None,
+                (false, false, _) => Some(format!("Blink had not finished the {} command after {}s; the sync module may be offline", if arm { "arm" } else { "disarm" }, timeout.as_secs())),
+                (false, true, None) => Some("Couldn't read the network back to confirm its state".to_string()),
+                (false, true, Some(_)) => Some(format!("Blink still shows the network {}", if arm { "disarmed" } else { "armed" })),
+            };
+            Ok(ArmVerification { requested: arm, confirmed, state, warning })
+        }).await
+    }
+
+    /// Sends the arm or disarm request and returns the id of the command Blink queued for
+    /// it, when the response carries one.
+    async fn send_arm(&self, network_id: i64, arm: bool) -> Result<Option<i64>> {
+        let (token, account_id) = self.auth()?;
+        let action = if arm { "arm" } else { "disarm" };
+        let url = format!("{}/api/v1/accounts/{}/networks/{}/state/{}", self.base_url, account_id, network_id, action);
+
+        let res = self.client.post(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+
+        let res = ensure_success(res, "Arm request failed").await?;
+        let data: serde_json::Value = read_json(res, "Arm request failed").await.unwrap_or_default();
+        Ok(data["id"].as_i64().or_else(|| data["command_id"].as_i64()))
+    }
+
+    /// Polls a network command once a second until Blink reports it finished (`true`) or
+    /// `deadline` passes (`false`). A command that ends in an error is an `Err`.
+    async fn poll_command(&self, network_id: i64, command_id: i64, deadline: tokio::time::Instant) -> Result<bool> {
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+            let status = self.get_command_status(network_id, command_id).await?;
+            if let Some(outcome) = command_outcome(&status, command_id) {
+                return outcome.map(|()| true).map_err(|e| anyhow!(e));
+            }
+        }
+        Ok(false)
+    }
+
+    fn local_storage_url(&self, account_id: i64, network_id: i64, sync_module_id: i64, action: &str) -> String {
+        format!("{}/api/v1/accounts/{}/networks/{}/sync_modules/{}/local_storage/{}", self.base_url, account_id, network_id, sync_module_id, action)
+    }
+
+    /// The detailed state of a sync module's USB drive.
+    pub async fn get_local_storage_status(&self, network_id: i64, sync_module_id: i64) -> Result<LocalStorageStatus> {
+        let (token, account_id) = self.auth()?;
+        let res = self.client.get(self.local_storage_url(account_id, network_id, sync_module_id, "status"))
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+        let res = ensure_success(res, "Local storage status request failed").await?;
+        let raw = read_json(res, "Local storage status request failed").await?;
+        Ok(LocalStorageStatus::from_response(network_id, sync_module_id, raw))
+    }
+
+    /// Safely unmounts the sync module's USB drive: sends the eject command, polls it for up
+    /// to `USB_EJECT_TIMEOUT_SECS`, then reads the drive back. Blink rejecting the request or
+    /// the command is an `Err`; a command still running at the deadline is not.
+    pub async fn eject_usb(&self, network_id: i64, sync_module_id: i64) -> Result<UsbEjectResult> {
+        audit::track("eject_usb", format!("sync module {} (network {})", sync_module_id, network_id), async {
+            let (token, account_id) = self.auth()?;
+            let res = self.client.post(self.local_storage_url(account_id, network_id, sync_module_id, "eject"))
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            let res = ensure_success(res, "USB eject request failed").await?;
+            let data: serde_json::Value = read_json(res, "USB eject request failed").await.unwrap_or_default();
+            let command_id = data["id"].as_i64().or_else(|| data["command_id"].as_i64());
+
+            let finished = match command_id {
+                Some(command_id) => {
+                    let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(USB_EJECT_TIMEOUT_SECS);
+                    self.poll_command(network_id, command_id, deadline).await?
+                }
+                None => true,
+            };
+            let status = match self.get_local_storage_status(network_id, sync_module_id).await {
+                Ok(status) => Some(status),
+                Err(e) => {
+                    eprintln!("Failed to read USB storage back after ejecting on sync module {}: {}", sync_module_id, e);
+                    None
+                }
+            };
+            Ok(UsbEjectResult { network_id, sync_module_id, command_id, finished, status })
+        }).await
+    }
+
+    /// Arms or disarms every network at once. Each network gets its own result, so a
+    /// partial failure is visible; only failing to list the networks is an `Err`.
+    pub async fn set_arm_all(&self, arm: bool) -> Result<Vec<NetworkArmResult>> {
+        let networks = self.get_homescreen().await?.networks;
+        let calls = networks.iter().map(|network| async move {
+            let result = self.set_arm(network.id, arm).await;
+            NetworkArmResult {
+                network_id: network.id,
+                ok: result.is_ok(),
+                error: result.err().map(|e| e.to_string()),
+            }
+        });
+        Ok(futures_util::future::join_all(calls).await)
+    }
+
+    pub async fn get_camera_config(&self, network_id: i64, camera_id: i64, product_type: &str) -> Result<serde_json::Value> {
+        let token = self.token()?;
+        let endpoints = self.endpoints(self.account_id.unwrap_or(0), network_id, camera_id, product_type);
+        self.on_device_routes(endpoints, product_type, |endpoints| async move {
+            let res = self.client.get(endpoints.config())
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            read_json(ensure_success(res, "Camera config request failed").await?, "Camera config request failed").await
+        }).await
+    }
+
+    pub async fn update_camera_config(&self, network_id: i64, camera_id: i64, product_type: &str, config: serde_json::Value) -> Result<()> {
+        audit::track("update_camera_config", format!("camera {} (network {})", camera_id, network_id), async {
+            let token = self.token()?;
+            let endpoints = self.endpoints(self.account_id.unwrap_or(0), network_id, camera_id, product_type);
+            let config = &config;
+            self.on_device_routes(endpoints, product_type, |endpoints| async move {
+                let res = self.client.post(endpoints.config_update())
+                    .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                    .json(config)
+                    .send()
+                    .await?;
+                ensure_success(res, "Camera config update failed").await?;
+                Ok(())
+            }).await
+        }).await
+    }
+
+    pub async fn get_notification_config(&self) -> Result<NotificationConfig> {
+        let (token, account_id) = self.auth()?;
+        let url = format!("{}/api/v1/accounts/{}/notifications/configuration", self.base_url, account_id);
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+        let body: NotificationConfigBody = read_json(ensure_success(res, "Notification settings request failed").await?, "Notification settings request failed").await?;
+        Ok(body.notifications)
+    }
+
+    /// Replaces the account's notification settings. Like the official app, this sends the
+    /// whole object, so start from `get_notification_config` and change only what's needed.
+    pub async fn update_notification_config(&self, config: &NotificationConfig) -> Result<()> {
+        audit::track("update_notification_config", "account", async {
+            let (token, account_id) = self.auth()?;
+            let url = format!("{}/api/v1/accounts/{}/notifications/configuration", self.base_url, account_id);
+            let res = self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .json(&NotificationConfigBody { notifications: config.clone() })
+                .send()
+                .await?;
+            ensure_success(res, "Notification settings update failed").await?;
+            Ok(())
+        }).await
+    }
+
+    /// Asks the camera to take a new thumbnail. The homescreen reports the new path once it lands.
+    pub async fn request_thumbnail(&self, network_id: i64, camera_id: i64, product_type: &str) -> Result<()> {
+        let (token, account_id) = self.auth()?;
+        let endpoints = self.endpoints(account_id, network_id, camera_id, product_type);
+        self.on_device_routes(endpoints, product_type, |endpoints| async move {
+            let res = self.client.post(endpoints.thumbnail())
+                .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                .send()
+                .await?;
+            ensure_success(res, "Thumbnail request failed").await?;
+            Ok(())
+        }).await
+    }
+
+    /// Settings for one network: the homescreen entry, overlaid with whatever the describe
+    /// endpoint adds. A failing describe call still returns the homescreen view.
+    pub async fn get_network_settings(&self, network_id: i64) -> Result<NetworkSettings> {
+        let homescreen = self.get_homescreen_json().await?;
+        let mut merged = homescreen.get("networks")
+            .and_then(|v| v.as_array())
+            .and_then(|networks| networks.iter().find(|n| n.get("id").and_then(|v| v.as_i64()) == Some(network_id)))
+            .cloned()
+            .ok_or_else(|| anyhow!("Network {} not found", network_id))?;
+
+        let described = match self.describe_network(network_id).await {
+            Ok(json) => Some(json.get("network").cloned().unwrap_or(json)),
+            Err(e) => {
+                eprintln!("Network {} describe failed: {}", network_id, e);
+                None
+            }
+        };
+        if let (Some(target), Some(serde_json::Value::Object(extra))) = (merged.as_object_mut(), described) {
+            for (key, value) in extra {
+                if !value.is_null() {
+                    target.insert(key, value);
+                }
+            }
+        }
+        Ok(serde_json::from_value(merged)?)
+    }
+
+    async fn describe_network(&self, network_id: i64) -> Result<serde_json::Value> {
+        let token = self.token()?;
+        let url = format!("{}/network/{}", self.base_url, network_id);
+        let res = self.client.get(&url)
+            .header("Authorization", format!("Bearer {}", token.expose_secret()))
+            .send()
+            .await?;
+        read_json(ensure_success(res, "Network describe failed").await?, "Network describe failed").await
+    }
+
+    /// Applies the set fields of `patch` through the network `update` endpoint. Unknown
+    /// time zones are rejected before anything is sent.
+    pub async fn update_network_settings(&self, network_id: i64, patch: &NetworkSettingsPatch) -> Result<()> {
+        patch.validate()?;
+        if patch.is_empty() {
+            return Ok(());
+        }
+        audit::track("update_network_settings", format!("network {}", network_id), async {
+            self.post_network_update(network_id, serde_json::to_value(patch)?, "Network settings update failed").await
+        }).await
+    }
+
+    /// Sends a single-key patch to the network `update` endpoint.
+    pub async fn set_network_field(&self, network_id: i64, key: &str, value: serde_json::Value) -> Result<()> {
+        audit::track("set_network_field", format!("network {} {}", network_id, key), async {
+            self.post_network_update(network_id, serde_json::json!({ key: value }), "Network update failed").await
+        }).await
+    }
+
+    /// Blink has moved the network update route over time and accepts the fields either
+    /// flat or wrapped in `network`, so every combination is tried until one succeeds.
+    async fn post_network_update(&self, network_id: i64, fields: serde_json::Value, context: &str) -> Result<()> {
+        let (token, account_id) = self.auth()?;
+        let endpoints = [
+            format!("{}/api/v1/accounts/{}/networks/{}/update", self.base_url, account_id, network_id),
+            format!("{}/network/{}/update", self.base_url, network_id),
+        ];
+        let payloads = [
+            fields.clone(),
+            serde_json::json!({ "network": fields }),
+        ];
+
+        let mut last_error: Option<anyhow::Error> = None;
+
+        for url in endpoints {
+            for payload in payloads.iter() {
+                let res = self.client.post(&url)
+                    .header("Authorization", format!("Bearer {}", token.expose_secret()))
+                    .json(payload)
+                    .send()
+                    .await;
+
+                match res {
+                    Ok(response) => match ensure_success(response, context).await {
+                        Ok(_) => return Ok(()),
+                        Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::AuthExpired)) => return Err(e),
+                        Err(e) => last_error = Some(e),
+                    },
+                    Err(e) => last_error = Some(e.into()),
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("{}", context)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn saved(token: &str, expiry: i64) -> BlinkAuthState {
+        let json = serde_json::json!({
+            "token": token,
+            "refresh_token": format!("{}-refresh", token),
+            "account_id": 42,
+            "base_url": "https://rest-u011.immedia-semi.com",
+            "device_id": "device",
+            "token_expiry": expiry,
+        });
+        BlinkAuthState::from_json(&json.to_string(), "test").unwrap()
+    }
+
+    fn token_of(client: &BlinkClient) -> Option<String> {
+        client.token.as_ref().map(|t| t.expose_secret().to_string())
+    }
+
+    #[test]
+    fn stale_disk_state_does_not_clobber_a_fresher_token() {
+        let now = Utc::now().timestamp();
+        let mut client = BlinkClient::from_state(saved("fresh", now + 3600));
+        assert!(!client.hydrate_from(saved("stale", now + 60)));
+        assert_eq!(token_of(&client).as_deref(), Some("fresh"));
+        assert_eq!(client.token_expiry, Some(now + 3600));
+    }
+
+    #[test]
+    fn disk_state_without_a_token_is_ignored_while_logged_in() {
+        let now = Utc::now().timestamp();
+        let mut client = BlinkClient::from_state(saved("fresh", now + 3600));
+        let empty = BlinkAuthState::from_json("{}", "test").unwrap();
+        assert!(!client.hydrate_from(empty));
+        assert_eq!(token_of(&client).as_deref(), Some("fresh"));
+    }
+
+    #[test]
+    fn newer_disk_state_or_an_empty_client_takes_the_saved_session() {
+        let now = Utc::now().timestamp();
+        let mut client = BlinkClient::from_state(saved("old", now + 60));
+        assert!(client.hydrate_from(saved("new", now + 3600)));
+        assert_eq!(token_of(&client).as_deref(), Some("new"));
+
+        let mut empty = BlinkClient::new();
+        assert!(empty.hydrate_from(saved("disk", now + 60)));
+        assert_eq!(token_of(&empty).as_deref(), Some("disk"));
+    }
+
+    /// Saves a client restored from `json` and reads the result back, the way a restart does.
+    fn restart(json: serde_json::Value) -> (BlinkAuthState, serde_json::Value) {
+        let client = BlinkClient::from_state(BlinkAuthState::from_json(&json.to_string(), "test").unwrap());
+        let saved = serde_json::to_value(client.get_state()).unwrap();
+        (BlinkAuthState::from_json(&saved.to_string(), "test").unwrap(), saved)
+    }
+
+    #[test]
+    fn an_early_v0_state_with_only_a_token_loads_and_gains_a_device_id() {
+        let (state, saved) = restart(serde_json::json!({ "token": "t", "account_id": 42 }));
+        assert_eq!(state.version, AUTH_STATE_VERSION);
+        assert_eq!(state.token.as_ref().map(|t| t.expose_secret().to_string()).as_deref(), Some("t"));
+        assert!(state.refresh_token.is_none() && state.token_expiry.is_none());
+        assert_eq!(state.account_id, Some(42));
+        assert_eq!(state.base_url, default_base_url());
+        assert!(!state.device_id.is_empty());
+        assert_eq!(saved["version"], AUTH_STATE_VERSION);
+        // Once saved, the generated device id is kept rather than replaced on every load.
+        assert_eq!(state.device_id, saved["device_id"]);
+        assert_eq!(BlinkAuthState::from_json(&saved.to_string(), "test").unwrap().migrated().device_id, state.device_id);
+    }
+
+    #[test]
+    fn a_v0_state_from_before_token_expiry_round_trips() {
+        let v0 = serde_json::json!({
+            "token": "t",
+            "refresh_token": "r",
+            "account_id": 42,
+            "base_url": "https://rest-u011.immedia-semi.com",
+            "device_id": "DEVICE",
+        });
+        let (state, saved) = restart(v0);
+        assert_eq!(state.version, AUTH_STATE_VERSION);
+        assert_eq!(state.refresh_token.as_ref().map(|t| t.expose_secret().to_string()).as_deref(), Some("r"));
+        assert_eq!(state.base_url, "https://rest-u011.immedia-semi.com");
+        assert_eq!(state.device_id, "DEVICE");
+        assert!(state.token_expiry.is_none());
+        assert!(saved.get("cookies").is_none() && saved.get("reauth_required").is_none());
+    }
+
+    #[test]
+    fn a_v0_state_with_expiry_and_cookies_round_trips() {
+        let oauth = reqwest::Url::parse(&BlinkUrls::default().oauth).unwrap();
+        let cookie = Cookie::parse("session=abc; Max-Age=3600", &oauth).unwrap();
+        let v0 = serde_json::json!({
+            "token": "t",
+            "refresh_token": "r",
+            "account_id": 42,
+            "base_url": "https://rest-u011.immedia-semi.com",
+            "device_id": "DEVICE",
+            "token_expiry": 1_900_000_000,
+            "cookies": [cookie],
+        });
+        let (state, _) = restart(v0);
+        assert_eq!(state.version, AUTH_STATE_VERSION);
+        assert_eq!(state.token_expiry, Some(1_900_000_000));
+        assert_eq!(state.cookies.len(), 1);
+        assert_eq!((state.cookies[0].name(), state.cookies[0].value()), ("session", "abc"));
+    }
+
+    #[test]
+    fn a_current_state_round_trips_unchanged() {
+        let v1 = serde_json::json!({
+            "version": 1,
+            "token": "t",
+            "refresh_token": "r",
+            "account_id": 42,
+            "base_url": "https://rest-e006.immedia-semi.com",
+            "device_id": "DEVICE",
+            "token_expiry": 1_900_000_000,
+            "reauth_required": true,
+        });
+        let (_, saved) = restart(v1.clone());
+        assert_eq!(saved, v1);
+    }
+
+    #[test]
+    fn a_newer_state_keeps_its_version_and_unknown_fields() {
+        let v2 = serde_json::json!({
+            "version": 2,
+            "token": "t",
+            "refresh_token": "r",
+            "account_id": 42,
+            "base_url": "https://rest-u011.immedia-semi.com",
+            "device_id": "DEVICE",
+            "token_expiry": 1_900_000_000,
+            "region_hint": "eu",
+            "passkeys": [{ "id": "k1" }],
+        });
+        let (state, saved) = restart(v2.clone());
+        assert_eq!(state.version, 2);
+        assert_eq!(state.unknown.get("region_hint"), Some(&serde_json::json!("eu")));
+        assert_eq!(saved, v2);
+    }
+
+    #[test]
+    fn a_corrupted_state_says_how_to_recover() {
+        for blob in ["{\"token\": \"t\", \"account_id\":", "not json", "{\"account_id\": \"forty-two\"}", "[]"] {
+            let error = BlinkAuthState::from_json(blob, "/home/me/.config/blink/auth.json").unwrap_err().to_string();
+            assert!(error.starts_with("The saved Blink session in /home/me/.config/blink/auth.json can't be read ("), "{}", error);
+            assert!(error.ends_with("Sign in again to replace it, or delete /home/me/.config/blink/auth.json and restart."), "{}", error);
+        }
+    }
+
+    fn status(code: u16) -> reqwest::StatusCode {
+        reqwest::StatusCode::from_u16(code).unwrap()
+    }
+
+    #[test]
+    fn a_307_is_a_busy_camera() {
+        let err = liveview_error(status(307), None, "");
+        assert!(matches!(err, BlinkError::CameraBusy { retry_after: None, .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn a_409_busy_keeps_the_retry_after() {
+        let body = r#"{"message":"Device busy, try again later"}"#;
+        let err = liveview_error(status(409), Some(7), body);
+        match err {
+            BlinkError::CameraBusy { message, retry_after } => {
+                assert_eq!(retry_after, Some(7));
+                assert!(message.contains("Device busy"), "{}", message);
+            }
+            other => panic!("expected CameraBusy, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn busy_code_or_message_marks_other_statuses_busy() {
+        let coded = liveview_error(status(400), None, r#"{"message":"nope","code":307}"#);
+        assert!(matches!(coded, BlinkError::CameraBusy { .. }), "{:?}", coded);
+        let worded = liveview_error(status(400), None, r#"{"message":"Camera is busy"}"#);
+        assert!(matches!(worded, BlinkError::CameraBusy { .. }), "{:?}", worded);
+        let envelope = liveview_error(status(409), None, r#"{"message":"busy","retry_after":12}"#);
+        assert!(matches!(envelope, BlinkError::CameraBusy { retry_after: Some(12), .. }), "{:?}", envelope);
+    }
+
+    #[test]
+    fn a_genuine_500_is_not_busy() {
+        // Neither the status nor a "307" or "busy" in the text of a server error makes it busy.
+        let err = liveview_error(status(500), None, r#"{"message":"upstream 307 busy timeout"}"#);
+        assert!(matches!(err, BlinkError::Http { status: 500, .. }), "{:?}", err);
+        let plain = liveview_error(status(500), None, "Internal Server Error");
+        assert!(matches!(plain, BlinkError::Http { status: 500, code: None, .. }), "{:?}", plain);
+    }
+
+    #[test]
+    fn csrf_token_is_found_in_every_saved_signin_page() {
+        let pages = [
+            (include_str!("../tests/fixtures/signin_oauth_args.html"), "csrf-plain-7f3a9c"),
+            (include_str!("../tests/fixtures/signin_escaped.html"), "csrf-escaped-41be"),
+            (include_str!("../tests/fixtures/signin_inline_assignment.html"), "csrf-inline-d20e"),
+        ];
+        for (html, expected) in pages {
+            assert_eq!(extract_csrf_token(html).as_deref(), Some(expected));
+        }
+    }
+
+    fn clients(fixture: &str) -> Vec<(i64, String, Option<String>, bool)> {
+        let body: serde_json::Value = serde_json::from_str(fixture).unwrap();
+        parse_trusted_clients(&body, "BLINK-MONITOR-3F8A")
+            .into_iter()
+            .map(|c| (c.id, c.name, c.created_at, c.current))
+            .collect()
+    }
+
+    #[test]
+    fn a_bare_client_array_finds_the_current_client_by_hardware_id() {
+        assert_eq!(clients(include_str!("../tests/fixtures/trusted_clients_array.json")), vec![
+            (7101, "Living room iPad".to_string(), Some("2025-03-02T18:11:04+00:00".to_string()), false),
+            (7102, "Blink Monitor".to_string(), Some("2026-09-30T07:45:12+00:00".to_string()), true),
+        ]);
+    }
+
+    #[test]
+    fn a_clients_wrapped_list_trusts_its_own_current_flag() {
+        // The flag wins over the hardware id, which matches case-insensitively here.
+        let body = include_str!("../tests/fixtures/trusted_clients_wrapped.json").replace("\"is_current\": true", "\"is_current\": false");
+        assert!(clients(&body).iter().all(|c| !c.3));
+        assert_eq!(clients(include_str!("../tests/fixtures/trusted_clients_wrapped.json")), vec![
+            (8201, "Pixel 8".to_string(), Some("2024-11-19T09:02:51+00:00".to_string()), false),
+            (8202, "Blink Monitor".to_string(), Some("2026-10-01T21:30:00+00:00".to_string()), true),
+        ]);
+    }
+
+    #[test]
+    fn a_data_wrapped_list_fills_in_missing_names_and_skips_entries_without_an_id() {
+        assert_eq!(clients(include_str!("../tests/fixtures/trusted_clients_data.json")), vec![
+            (9301, "Kitchen Echo Show".to_string(), Some("2025-06-07T12:00:00+00:00".to_string()), false),
+            (9302, "Client 9302".to_string(), None, true),
+        ]);
+        let nested = serde_json::json!({ "data": { "clients": [{ "id": 1, "name": "Phone" }] } });
+        assert_eq!(parse_trusted_clients(&nested, "x").len(), 1);
+        assert!(parse_trusted_clients(&serde_json::json!({ "message": "ok" }), "x").is_empty());
+    }
+
+    #[test]
+    fn only_amazon_registrable_domains_count_as_amazon() {
+        let amazon = |url: &str| is_amazon_host(&reqwest::Url::parse(url).unwrap());
+        for url in ["https://www.amazon.com/ap/signin", "https://amazon.de/ap/signin", "https://www.amazon.co.uk/ap/signin", "https://www.amazon.com.au/ap/signin", "https://na.account.amazon.com/ap/oa"] {
+            assert!(amazon(url), "{}", url);
+        }
+        for url in ["https://amazon.evil.com/ap/signin", "https://www.amazon.co.uk.evil.com/", "https://notamazon.com/", "https://amazon.com.evil/", "http://www.amazon.com/ap/signin", "https://api.oauth.blink.com/oauth/v2/signin"] {
+            assert!(!amazon(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn the_amazon_signin_form_keeps_its_hidden_fields() {
+        let page = reqwest::Url::parse("https://www.amazon.com/ap/signin?openid.mode=checkid_setup").unwrap();
+        let (action, fields) = parse_amazon_form(include_str!("../tests/fixtures/amazon_signin.html"), &page).unwrap();
+        assert_eq!(action.as_str(), "https://www.amazon.com/ap/signin");
+        let names: Vec<&str> = fields.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["appActionToken", "appAction", "subPageType", "openid.return_to", "prevRID", "workflowState", "email", "password", "metadata1"]);
+        assert_eq!(fields[5].1, "eyJ6aXAiOiJERUYiLCJlbmMiOiJBMjU2R0NNIiwiYWxnIjoiQTI1NktXIn0.wf-7c1e");
+        // The search form ahead of it is never picked.
+        assert!(!names.contains(&"field-keywords"));
+    }
+
+    #[test]
+    fn an_unnamed_amazon_form_is_found_by_its_password_field() {
+        let page = reqwest::Url::parse("https://www.amazon.co.uk/ap/signin").unwrap();
+        let (action, fields) = parse_amazon_form(include_str!("../tests/fixtures/amazon_signin_unnamed.html"), &page).unwrap();
+        assert_eq!(action.as_str(), "https://www.amazon.co.uk/ap/signin?arb=5e1c2d7a");
+        assert_eq!(fields[0], ("appActionToken".to_string(), "Zx9Lr4Ta0Qm2Wn7Ub3Kc".to_string()));
+        assert_eq!(fields.len(), 4);
+    }
+
+    #[test]
+    fn the_amazon_otp_page_has_no_signin_form() {
+        let page = reqwest::Url::parse("https://www.amazon.com/ap/mfa").unwrap();
+        assert!(parse_amazon_form(include_str!("../tests/fixtures/amazon_otp.html"), &page).is_none());
+    }
+
+    #[test]
+    fn signin_page_without_a_token_yields_nothing() {
+        assert_eq!(extract_csrf_token(include_str!("../tests/fixtures/signin_no_token.html")), None);
+        assert_eq!(extract_csrf_token(""), None);
+    }
+
+    #[test]
+    fn oauth_args_wins_over_other_inline_scripts() {
+        let html = r#"<script>var x = {"csrf-token": "decoy"};</script>
+            <script nonce="n" id="oauth-args" type="application/json">{"csrf-token":"real"}</script>"#;
+        assert_eq!(extract_csrf_token(html).as_deref(), Some("real"));
+    }
+
+    #[test]
+    fn login_page_change_is_reported_as_such() {
+        let err = BlinkError::LoginPageChanged("no csrf-token".to_string());
+        assert!(err.to_string().starts_with("Blink changed their login page"), "{}", err);
+    }
+
+    fn redirect(url: &str) -> reqwest::Url {
+        reqwest::Url::parse(url).unwrap()
+    }
+
+    #[test]
+    fn plain_and_percent_encoded_codes_are_decoded() {
+        let client = BlinkClient::new();
+        let plain = redirect("immedia-blink://applinks.blink.com/signin/callback?code=abc123");
+        assert_eq!(client.auth_code_from_redirect(&plain).unwrap(), "abc123");
+        let encoded = redirect("immedia-blink://applinks.blink.com/signin/callback?code=a%2Fb%2Bc%3D%3D&scope=client");
+        assert_eq!(client.auth_code_from_redirect(&encoded).unwrap(), "a/b+c==");
+        let https = redirect("https://applinks.blink.com/signin/callback?state=s&code=x%20y");
+        assert_eq!(client.auth_code_from_redirect(&https).unwrap(), "x y");
+    }
+
+    #[test]
+    fn missing_or_empty_code_is_no_auth_code() {
+        let client = BlinkClient::new();
+        for url in [
+            "immedia-blink://applinks.blink.com/signin/callback",
+            "immedia-blink://applinks.blink.com/signin/callback?code=",
+            "immedia-blink://applinks.blink.com/signin/callback?error=access_denied",
+        ] {
+            let err = client.auth_code_from_redirect(&redirect(url)).unwrap_err();
+            assert!(matches!(err, BlinkError::NoAuthCode(_)), "{}: {:?}", url, err);
+        }
+    }
+
+    #[test]
+    fn state_is_checked_and_the_code_kept_out_of_errors() {
+        let mut client = BlinkClient::new();
+        client.oauth_state = Some("expected".to_string());
+        let good = redirect("immedia-blink://applinks.blink.com/signin/callback?code=c1&state=expected");
+        assert_eq!(client.auth_code_from_redirect(&good).unwrap(), "c1");
+        let bad = redirect("immedia-blink://applinks.blink.com/signin/callback?code=secret-code&state=forged");
+        let err = client.auth_code_from_redirect(&bad).unwrap_err();
+        assert!(matches!(err, BlinkError::NoAuthCode(ref m) if m.contains("state mismatch")), "{:?}", err);
+        assert!(!err.to_string().contains("secret-code"), "{}", err);
+    }
+
+    fn mock_client(server: &wiremock::MockServer) -> BlinkClient {
+        let uri = server.uri();
+        BlinkClient::with_urls(BlinkUrls { oauth: uri.clone(), rest: uri.clone(), tier_template: uri })
+    }
+
+    #[tokio::test]
+    async fn redirect_without_a_code_is_not_a_rejected_code() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/oauth/v2/authorize"))
+            .respond_with(ResponseTemplate::new(302)
+                .insert_header("Location", "immedia-blink://applinks.blink.com/signin/callback?error=denied"))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/oauth/token"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let err = mock_client(&server).exchange_code().await.unwrap_err();
+        let err = err.downcast::<BlinkError>().unwrap();
+        assert!(matches!(err, BlinkError::NoAuthCode(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn token_endpoint_refusal_is_a_rejected_code() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("GET"))
+            .and(matchers::path("/oauth/v2/authorize"))
+            .respond_with(ResponseTemplate::new(302)
+                .insert_header("Location", "immedia-blink://applinks.blink.com/signin/callback?code=a%2Bb"))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/token"))
+            .and(matchers::body_string_contains("code=a%2Bb"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(r#"{"error":"invalid_grant"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let err = mock_client(&server).exchange_code().await.unwrap_err();
+        let err = err.downcast::<BlinkError>().unwrap();
+        assert!(matches!(err, BlinkError::AuthCodeRejected { status: 400, .. }), "{:?}", err);
+    }
+
+    /// A client with a CSRF token, as `start_oauth_flow` leaves it.
+    fn signing_in(server: &wiremock::MockServer) -> BlinkClient {
+        let mut client = mock_client(server);
+        client.csrf_token = Some("csrf".to_string());
+        client
+    }
+
+    fn retry_after_secs(err: anyhow::Error) -> Option<u64> {
+        match err.downcast::<BlinkError>() {
+            Ok(BlinkError::RateLimited { retry_after_secs, .. }) => retry_after_secs,
+            other => panic!("expected RateLimited, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_429_on_sign_in_is_rate_limited_until_retry_after() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/v2/signin"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+            .mount(&server)
+            .await;
+
+        let mut client = signing_in(&server);
+        let err = client.login_oauth("a@example.com", SecretString::from("pw")).await.unwrap_err();
+        assert_eq!(retry_after_secs(err), Some(120));
+        let wait = (client.login_retry_at.unwrap() - Utc::now()).num_seconds();
+        assert!((118..=120).contains(&wait), "{}", wait);
+    }
+
+    #[tokio::test]
+    async fn a_lockout_body_is_rate_limited_whatever_the_status() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/v2/signin"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"lockout_time_remaining":90}"#))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/v2/2fa/verify"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"message":"Locked","retry_after":29.2}"#))
+            .mount(&server)
+            .await;
+        // A lockout is not a signed-in session, so no code is asked for.
+        Mock::given(matchers::path("/oauth/v2/authorize"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let err = signing_in(&server).login_oauth("a@example.com", SecretString::from("pw")).await.unwrap_err();
+        assert_eq!(retry_after_secs(err), Some(90));
+        let err = signing_in(&server).verify_pin_oauth(SecretString::from("123456")).await.unwrap_err();
+        assert_eq!(retry_after_secs(err), Some(30));
+    }
+
+    #[tokio::test]
+    async fn sign_in_is_not_retried_before_the_deadline() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/v2/signin"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "60"))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = signing_in(&server);
+        client.login_oauth("a@example.com", SecretString::from("pw")).await.unwrap_err();
+        let err = client.login_oauth("a@example.com", SecretString::from("pw")).await.unwrap_err();
+        let remaining = retry_after_secs(err).unwrap();
+        assert!((58..=60).contains(&remaining), "{}", remaining);
+        let err = client.verify_pin_oauth(SecretString::from("123456")).await.unwrap_err();
+        assert!(retry_after_secs(err).is_some());
+        assert_eq!(server.received_requests().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sign_in_goes_through_once_the_deadline_passes() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/oauth/v2/signin"))
+            .respond_with(ResponseTemplate::new(412))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut client = signing_in(&server);
+        client.login_retry_at = Some(Utc::now() - Duration::seconds(1));
+        let outcome = client.login_oauth("a@example.com", SecretString::from("pw")).await.unwrap();
+        assert_eq!(outcome, "2FA_REQUIRED");
+    }
+
+    #[test]
+    fn every_product_type_maps_to_its_url_shapes() {
+        const B: &str = "https://rest-u011.immedia-semi.com";
+        let v5 = format!("{B}/api/v5/accounts/1/networks/2/cameras/3/liveview");
+        let legacy = |action: &str| format!("{B}/network/2/camera/3/{action}");
+        let v1 = |segment: &str, action: &str| format!("{B}/api/v1/accounts/1/networks/2/{segment}/3/{action}");
+        // type, family, liveview, config, config update, thumbnail
+        let table = [
+            ("camera", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("white", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("xt", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("xt2", DeviceFamily::Camera, v1("cameras", "liveview"), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("indoor", DeviceFamily::Camera, v1("cameras", "liveview"), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("catalina", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("sedona", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+            ("owl", DeviceFamily::Owl, v1("owls", "liveview"), v1("owls", "config"), v1("owls", "config"), v1("owls", "thumbnail")),
+            ("mini", DeviceFamily::Owl, v1("owls", "liveview"), v1("owls", "config"), v1("owls", "config"), v1("owls", "thumbnail")),
+            ("mini2", DeviceFamily::Owl, v1("owls", "liveview"), v1("owls", "config"), v1("owls", "config"), v1("owls", "thumbnail")),
+            ("hawk", DeviceFamily::Owl, v1("owls", "liveview"), v1("owls", "config"), v1("owls", "config"), v1("owls", "thumbnail")),
+            ("superior", DeviceFamily::Owl, v1("owls", "liveview"), v1("owls", "config"), v1("owls", "config"), v1("owls", "thumbnail")),
+            ("doorbell", DeviceFamily::Doorbell, v1("doorbells", "liveview"), v1("doorbells", "config"), v1("doorbells", "config"), v1("doorbells", "thumbnail")),
+            ("lotus", DeviceFamily::Doorbell, v1("doorbells", "liveview"), v1("doorbells", "config"), v1("doorbells", "config"), v1("doorbells", "thumbnail")),
+            ("tulip", DeviceFamily::Doorbell, v1("doorbells", "liveview"), v1("doorbells", "config"), v1("doorbells", "config"), v1("doorbells", "thumbnail")),
+            ("some-future-model", DeviceFamily::Camera, v5.clone(), legacy("config"), legacy("update"), legacy("thumbnail")),
+        ];
+        // Every known type is in the table above, so a new entry can't go untested.
+        assert_eq!(table.len(), PRODUCT_TYPES.len() + 1);
+
+        let mut client = BlinkClient::new();
+        client.base_url = B.to_string();
+        for (name, family, liveview, config, update, thumbnail) in table {
+            let endpoints = client.endpoints(1, 2, 3, name);
+            assert_eq!(DeviceFamily::of_product(name), family, "{}", name);
+            assert_eq!(endpoints.family, family, "{}", name);
+            assert_eq!(endpoints.liveview(), liveview, "{}", name);
+            assert_eq!(endpoints.config(), config, "{}", name);
+            assert_eq!(endpoints.config_update(), update, "{}", name);
+            assert_eq!(endpoints.thumbnail(), thumbnail, "{}", name);
+            assert_eq!(endpoints.liveview_options(), !endpoints.v1_liveview, "{}", name);
+        }
+    }
+
+    #[test]
+    fn falling_back_to_another_family_switches_liveview_route() {
+        let client = BlinkClient::new();
+        let camera = client.endpoints(1, 2, 3, "catalina");
+        assert!(camera.as_family(DeviceFamily::Owl).liveview().contains("/owls/3/liveview"));
+        let owl = client.endpoints(1, 2, 3, "hawk");
+        assert!(owl.as_family(DeviceFamily::Camera).liveview().contains("/api/v5/"));
+    }
+
+    const JPEG: &[u8] = &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+    #[test]
+    fn jpeg_png_and_unknown_bytes_are_sniffed() {
+        assert_eq!(sniff_image_type(JPEG), Some("image/jpeg"));
+        assert_eq!(sniff_image_type(PNG), Some("image/png"));
+        assert_eq!(sniff_image_type(b"RIFF\x10\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(sniff_image_type(b"<html>not an image"), None);
+        assert_eq!(sniff_image_type(&[]), None);
+        assert_eq!(sniff_image_type(&[0xFF, 0xD8]), None);
+    }
+
+    #[test]
+    fn image_headers_win_and_others_fall_back_to_the_bytes() {
+        assert_eq!(image_content_type(Some("image/webp"), JPEG), "image/webp");
+        assert_eq!(image_content_type(Some(" Image/PNG "), JPEG), "Image/PNG");
+        assert_eq!(image_content_type(Some("application/octet-stream"), PNG), "image/png");
+        assert_eq!(image_content_type(None, PNG), "image/png");
+        assert_eq!(image_content_type(None, b"???"), "image/jpeg");
+        assert_eq!(image_content_type(Some("text/html"), b"???"), "image/jpeg");
+    }
+
+    #[tokio::test]
+    async fn thumbnail_type_comes_from_the_bytes_when_the_header_is_generic() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/media/thumb.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(PNG.to_vec(), "application/octet-stream"))
+            .mount(&server)
+            .await;
+        // Owl thumbnails have no extension and need `ts` to be served.
+        Mock::given(matchers::path("/api/v3/media/accounts/42/networks/1/owl/5/thumbnail/thumbnail"))
+            .and(matchers::query_param_contains("ts", ""))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(JPEG.to_vec(), "image/jpeg"))
+            .mount(&server)
+            .await;
+
+        let mut client = BlinkClient::from_state(saved("token", Utc::now().timestamp() + 3600));
+        client.base_url = server.uri();
+        let png = client.get_thumbnail("/media/thumb.png").await.unwrap();
+        assert_eq!(png.content_type, "image/png");
+        assert_eq!(png.bytes, PNG);
+        let owl = client.get_thumbnail("/api/v3/media/accounts/42/networks/1/owl/5/thumbnail/thumbnail").await.unwrap();
+        assert_eq!(owl.content_type, "image/jpeg");
+    }
+
+    /// Serves one 2 MiB body per connection in 64 KiB pieces a quarter second apart, so
+    /// each read is quick but the whole transfer takes eight seconds.
+    async fn trickle_server() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        const CHUNK: usize = 64 * 1024;
+        const CHUNKS: usize = 32;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0u8; 4096];
+                    let _ = socket.read(&mut request).await;
+                    let head = format!("HTTP/1.1 200 OK\r\nContent-Type: video/mp4\r\nContent-Length: {}\r\n\r\n", CHUNK * CHUNKS);
+                    if socket.write_all(head.as_bytes()).await.is_err() {
+                        return;
+                    }
+                    for _ in 0..CHUNKS {
+                        if socket.write_all(&[0u8; CHUNK]).await.is_err() {
+                            return;
+                        }
+                        tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+                    }
+                });
+            }
+        });
+        format!("http://{}/clip.mp4", addr)
+    }
+
+    #[tokio::test]
+    async fn a_slow_large_download_outlives_the_api_timeout() {
+        let url = trickle_server().await;
+        let cookies = Arc::new(CookieStoreMutex::new(CookieStore::default()));
+        let timeouts = HttpTimeouts {
+            connect: std::time::Duration::from_secs(1),
+            api: std::time::Duration::from_secs(2),
+            media_read: std::time::Duration::from_secs(2),
+        };
+        let (api, media) = http_clients(&cookies, timeouts);
+
+        let err = async { api.get(&url).send().await?.bytes().await }.await.unwrap_err();
+        assert!(err.is_timeout(), "{:?}", err);
+
+        let started = std::time::Instant::now();
+        let body = media.get(&url).send().await.unwrap().bytes().await.unwrap();
+        assert_eq!(body.len(), 2 * 1024 * 1024);
+        assert!(started.elapsed() > timeouts.api * 2);
+    }
+
+    #[test]
+    fn only_the_api_client_has_a_total_timeout() {
+        let defaults = HttpTimeouts::default();
+        assert_eq!(defaults.api, std::time::Duration::from_secs(API_TIMEOUT_SECS));
+        // A download may stall this long between reads, but may take as long as it needs.
+        assert!(defaults.media_read > defaults.api);
+    }
+
+    #[tokio::test]
+    async fn both_clients_share_cookies_and_headers() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/login"))
+            .respond_with(ResponseTemplate::new(200).insert_header("Set-Cookie", "session=abc; Path=/"))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/clip.mp4"))
+            .and(matchers::header("Cookie", "session=abc"))
+            .and(matchers::header_regex("User-Agent", "^Mozilla/5.0 \\(iPhone"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![1u8; 16]))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = BlinkClient::new();
+        client.client.get(format!("{}/login", server.uri())).send().await.unwrap();
+        let res = client.media_client.get(format!("{}/clip.mp4", server.uri())).send().await.unwrap();
+        assert_eq!(res.status(), 200);
+    }
+
+    fn logged_in_at(server: &wiremock::MockServer) -> BlinkClient {
+        let mut client = BlinkClient::from_state(saved("token", Utc::now().timestamp() + 3600));
+        client.base_url = server.uri();
+        client
+    }
+
+    #[tokio::test]
+    async fn concurrent_homescreen_calls_share_one_request() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(r#"{"networks":[]}"#)
+                .set_delay(std::time::Duration::from_millis(300)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // Clones, as the server's handlers and the poller each hold their own.
+        let client = logged_in_at(&server);
+        let calls = (0..10).map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move { client.get_raw_homescreen().await.unwrap() })
+        });
+        for body in futures_util::future::join_all(calls).await {
+            assert_eq!(body.unwrap(), r#"{"networks":[]}"#);
+        }
+        assert!(client.inflight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_finished_request_is_not_reused() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}"))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = logged_in_at(&server);
+        client.get_raw_homescreen().await.unwrap();
+        client.get_raw_homescreen().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_a_failure_too() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(401)
+                .set_body_string(r#"{"message":"Unauthorized Access","code":101}"#)
+                .set_delay(std::time::Duration::from_millis(300)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = logged_in_at(&server);
+        let calls = (0..5).map(|_| client.get_raw_homescreen());
+        for result in futures_util::future::join_all(calls).await {
+            let err = result.unwrap_err();
+            // Each caller gets its own copy of the typed error.
+            assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::AuthExpired)), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn a_fetched_body_is_parsed_once() {
+        let body = FetchedBody { text: r#"{"networks":[{"id":1}]}"#.to_string(), json: Default::default() };
+        let first = body.json().unwrap();
+        let second = body.json().unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(first["networks"][0]["id"], 1);
+
+        let broken = FetchedBody { text: "{".to_string(), json: Default::default() };
+        let first = broken.json().unwrap_err().to_string();
+        assert!(first.starts_with("Invalid JSON from Blink: "), "{}", first);
+        assert_eq!(broken.json().unwrap_err().to_string(), first);
+    }
+
+    #[tokio::test]
+    async fn every_homescreen_consumer_shares_one_parse_per_fetch() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        let cameras: Vec<_> = (0..200).map(|id| serde_json::json!({
+            "id": id, "name": format!("Camera {}", id), "network_id": 1, "type": "catalina", "status": "done",
+            "thumbnail": format!("/media/{}.jpg", id),
+        })).collect();
+        let homescreen = serde_json::json!({ "account": {}, "networks": [{ "id": 1, "name": "Home", "armed": false }], "cameras": cameras });
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_string(homescreen.to_string())
+                .set_delay(std::time::Duration::from_millis(300)))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        // The cache, the candidates scan and the typed conversion, all on one fetch.
+        let client = logged_in_at(&server);
+        let (raw, first, second, typed) = tokio::join!(
+            client.get_raw_homescreen(),
+            client.get_homescreen_json(),
+            client.get_homescreen_json(),
+            client.get_homescreen(),
+        );
+        let (first, second) = (first.unwrap(), second.unwrap());
+        assert!(Arc::ptr_eq(&first, &second), "the body was parsed more than once");
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&raw.unwrap()).unwrap(), *first);
+        assert_eq!(typed.unwrap().cameras.len(), 200);
+    }
+
+    #[tokio::test]
+    async fn an_oversized_response_is_refused() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        let limit = max_response_bytes();
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(format!("[{}0]", "0,".repeat(limit / 2))))
+            .mount(&server)
+            .await;
+
+        let err = logged_in_at(&server).get_homescreen_json().await.unwrap_err();
+        match err.downcast_ref::<BlinkError>() {
+            Some(BlinkError::ResponseTooLarge { context, limit: reported }) => {
+                assert_eq!(context, "Homescreen request failed");
+                assert_eq!(*reported, limit);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn an_unannounced_oversized_body_is_cut_off_while_streaming() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let sent = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = sent.clone();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            // No Content-Length: the body runs until the connection closes, or forever.
+            socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n").await.unwrap();
+            let chunk = vec![b' '; 64 * 1024];
+            while socket.write_all(&chunk).await.is_ok() {
+                counter.fetch_add(chunk.len(), std::sync::atomic::Ordering::Relaxed);
+            }
+        });
+
+        let res = reqwest::get(format!("http://{}/", addr)).await.unwrap();
+        let err = read_body(res, "Streaming").await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::ResponseTooLarge { .. })), "{:?}", err);
+        // Reading stopped soon after the limit instead of waiting for the end.
+        assert!(sent.load(std::sync::atomic::Ordering::Relaxed) < max_response_bytes() * 2);
+    }
+
+    #[tokio::test]
+    async fn different_urls_are_not_coalesced() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{}").set_delay(std::time::Duration::from_millis(200)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"media":[]}"#).set_delay(std::time::Duration::from_millis(200)))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let client = logged_in_at(&server);
+        let (homescreen, page_1, page_2) = tokio::join!(
+            client.get_raw_homescreen(),
+            client.get_raw_media_page(1, 30, None),
+            client.get_raw_media_page(2, 30, None),
+        );
+        assert_eq!(homescreen.unwrap(), "{}");
+        assert_eq!(page_1.unwrap(), r#"{"media":[]}"#);
+        assert_eq!(page_2.unwrap(), r#"{"media":[]}"#);
+    }
+
+    #[test]
+    fn resolves_every_path_shape_blink_sends() {
+        const BASE: &str = "https://rest-u011.immedia-semi.com";
+        let cases = [
+            // Homescreen thumbnails, with and without the extension or a leading slash.
+            ("/media/u011/account/42/network/1/camera/5/clip.jpg", "https://rest-u011.immedia-semi.com/media/u011/account/42/network/1/camera/5/clip.jpg"),
+            ("media/u011/thumb", "https://rest-u011.immedia-semi.com/media/u011/thumb"),
+            ("/api/v3/media/accounts/42/networks/1/catalina/5/thumbnail/thumbnail.jpg?ts=1700000000&ext=",
+             "https://rest-u011.immedia-semi.com/api/v3/media/accounts/42/networks/1/catalina/5/thumbnail/thumbnail.jpg?ts=1700000000&ext="),
+            // Doubled slashes, leading and inside.
+            ("//api/v2/accounts/42/media/clip/1.mp4", "https://rest-u011.immedia-semi.com/api/v2/accounts/42/media/clip/1.mp4"),
+            ("/api//v2///clip.mp4", "https://rest-u011.immedia-semi.com/api/v2/clip.mp4"),
+            // Camera names leaking into paths.
+            ("/media/Front Door/clip 1.mp4", "https://rest-u011.immedia-semi.com/media/Front%20Door/clip%201.mp4"),
+            ("/media/Garage \"Left\"/t.jpg", "https://rest-u011.immedia-semi.com/media/Garage%20%22Left%22/t.jpg"),
+            // Another region's host, absolute or protocol-relative.
+            ("https://rest-e006.immedia-semi.com/api/v2/clip.mp4", "https://rest-e006.immedia-semi.com/api/v2/clip.mp4"),
+            ("https://rest-e006.immedia-semi.com//api//clip.mp4?sig=a%2Fb", "https://rest-e006.immedia-semi.com/api/clip.mp4?sig=a%2Fb"),
+            ("//rest-e006.immedia-semi.com/api/clip.mp4", "https://rest-e006.immedia-semi.com/api/clip.mp4"),
+            // The query is kept verbatim, even when it holds slashes.
+            ("/clip.mp4?path=//a/b", "https://rest-u011.immedia-semi.com/clip.mp4?path=//a/b"),
+            ("  /padded.jpg \n", "https://rest-u011.immedia-semi.com/padded.jpg"),
+            ("/dir/", "https://rest-u011.immedia-semi.com/dir/"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(resolve_path(BASE, path).map(|u| u.to_string()).ok().as_deref(), Some(expected), "{:?}", path);
+            assert_eq!(resolve_path(&format!("{}/", BASE), path).unwrap().as_str(), expected, "{:?} with a trailing slash on the base", path);
+        }
+    }
+
+    #[test]
+    fn unjoinable_paths_are_a_typed_error() {
+        for (base, path) in [
+            ("https://rest-u011.immedia-semi.com", ""),
+            ("https://rest-u011.immedia-semi.com", "   "),
+            ("https://rest-u011.immedia-semi.com", "https:///no-host"),
+            ("https://rest-u011.immedia-semi.com", "http://bad host/clip.mp4"),
+            ("not a url", "/media/clip.mp4"),
+            ("ftp://rest-u011.immedia-semi.com", "/media/clip.mp4"),
+        ] {
+            match resolve_path(base, path) {
+                Err(BlinkError::InvalidPath(p)) => assert_eq!(p, path),
+                other => panic!("{:?} on {:?} gave {:?}", path, base, other),
+            }
+        }
+    }
+
+    #[test]
+    fn the_client_resolves_against_its_own_host() {
+        let mut client = BlinkClient::from_state(saved("t", Utc::now().timestamp() + 3600));
+        assert_eq!(client.resolve_url("/media/a.jpg").unwrap().as_str(), "https://rest-u011.immedia-semi.com/media/a.jpg");
+        client.base_url = "https://rest-e006.immedia-semi.com".to_string();
+        assert_eq!(client.resolve_url("media/a.jpg").unwrap().as_str(), "https://rest-e006.immedia-semi.com/media/a.jpg");
+    }
+
+    #[test]
+    fn media_page_arguments_are_checked_at_both_ends() {
+        let max = max_since_days();
+        for (page, since_days, page_size) in [(1, 1, None), (1, max, None), (7, 30, Some(1)), (1, 30, Some(MAX_MEDIA_PAGE_SIZE))] {
+            assert!(check_media_page(page, since_days, page_size).is_ok(), "{} {} {:?}", page, since_days, page_size);
+        }
+        let cases = [
+            (0, 30, None, "page must be 1 or more, not 0".to_string()),
+            (-3, 30, None, "page must be 1 or more, not -3".to_string()),
+            (1, 0, None, format!("sinceDays must be between 1 and {}, not 0", max)),
+            (1, max + 1, None, format!("sinceDays must be between 1 and {}, not {}", max, max + 1)),
+            (1, 36500, None, format!("sinceDays must be between 1 and {}, not 36500", max)),
+            (1, 30, Some(0), format!("pageSize must be between 1 and {}, not 0", MAX_MEDIA_PAGE_SIZE)),
+            (1, 30, Some(MAX_MEDIA_PAGE_SIZE + 1), format!("pageSize must be between 1 and {}, not {}", MAX_MEDIA_PAGE_SIZE, MAX_MEDIA_PAGE_SIZE + 1)),
+        ];
+        for (page, since_days, page_size, message) in cases {
+            match check_media_page(page, since_days, page_size) {
+                Err(BlinkError::OutOfRange(m)) => assert_eq!(m, message),
+                other => panic!("{} {} {:?} gave {:?}", page, since_days, page_size, other),
+            }
+        }
+    }
+
+    #[test]
+    fn since_days_clamp_into_the_accepted_range() {
+        let max = max_since_days();
+        for (asked, clamped) in [(i64::MIN, 1), (-5, 1), (0, 1), (1, 1), (30, 30), (max, max), (max + 1, max), (36500, max), (i64::MAX, max)] {
+            assert_eq!(clamp_since_days(asked), clamped, "{}", asked);
+            assert!(check_media_page(1, clamp_since_days(asked), None).is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn a_page_below_one_is_refused_rather_than_read_as_page_one() {
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(wiremock::matchers::any()).respond_with(ResponseTemplate::new(200).set_body_string(r#"{"media":[]}"#)).expect(0).mount(&server).await;
+        let client = logged_in_at(&server);
+
+        let errors = [
+            client.get_raw_media_page(0, 30, None).await.unwrap_err(),
+            client.get_raw_media_page(-1, 30, None).await.unwrap_err(),
+            client.get_raw_media_since(Utc::now(), 0, None).await.unwrap_err(),
+            // `get_media_page` clamps the window but not the page.
+            client.get_media_page(0, 36500).await.unwrap_err(),
+        ];
+        for err in errors {
+            assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::OutOfRange(m)) if m.starts_with("page must be 1 or more")), "{:?}", err);
+        }
+    }
+
+    #[tokio::test]
+    async fn the_page_size_and_clamped_window_reach_blink() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"media":[]}"#))
+            .mount(&server)
+            .await;
+        let client = logged_in_at(&server);
+
+        client.get_raw_media_page(2, 7, Some(50)).await.unwrap();
+        client.get_media_page(1, 36500).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let query = |i: usize| -> std::collections::HashMap<String, String> { requests[i].url.query_pairs().into_owned().collect() };
+        let days_back = |q: &std::collections::HashMap<String, String>| {
+            // The offset's `+` goes out unescaped, so it reads back as a space.
+            let since = chrono::DateTime::parse_from_rfc3339(&q["since"].replace(' ', "+")).unwrap();
+            (Utc::now() - since.with_timezone(&Utc)).num_minutes() as f64 / (24.0 * 60.0)
+        };
+        let (paged, clamped) = (query(0), query(1));
+        assert_eq!((paged["page"].as_str(), paged["page_size"].as_str()), ("2", "50"));
+        assert!((days_back(&paged) - 7.0).abs() < 0.01);
+        assert_eq!(clamped["page"], "1");
+        assert!(!clamped.contains_key("page_size"));
+        assert!((days_back(&clamped) - max_since_days() as f64).abs() < 0.01);
+    }
+
+    /// A session on tier `u011` of `server`, whose tiers live under `/tier-{tier}`.
+    fn on_tier_u011(server: &wiremock::MockServer) -> BlinkClient {
+        let urls = BlinkUrls { oauth: server.uri(), rest: server.uri(), tier_template: format!("{}/tier-{{tier}}", server.uri()) };
+        let mut client = BlinkClient::from_state_with_urls(saved("token", Utc::now().timestamp() + 3600), urls);
+        client.base_url = format!("{}/tier-u011", server.uri());
+        client
+    }
+
+    async fn mount_tier_info(server: &wiremock::MockServer, tier: &str, calls: u64) {
+        use wiremock::{matchers, Mock, ResponseTemplate};
+        Mock::given(matchers::path("/api/v1/users/tier_info"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "tier": tier, "account_id": 42 })))
+            .expect(calls)
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn a_404_from_the_old_tier_moves_the_session_to_the_new_one() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/tier-u011/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(404).set_body_string(r#"{"message":"Not found"}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/tier-e006/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"networks":[]}"#))
+            .expect(2)
+            .mount(&server)
+            .await;
+        mount_tier_info(&server, "e006", 1).await;
+
+        let owner = tokio::sync::Mutex::new(on_tier_u011(&server));
+        // A snapshot makes the request, as the server's handlers do.
+        let snapshot = crate::shared::snapshot(&owner).await;
+        assert_eq!(snapshot.get_raw_homescreen().await.unwrap(), r#"{"networks":[]}"#);
+
+        // The owner picks the heal up once, and from then on goes straight to the new host.
+        let mut client = owner.lock().await;
+        let heal = client.take_tier_heal().unwrap();
+        assert_eq!(heal.old_base_url, format!("{}/tier-u011", server.uri()));
+        assert_eq!(heal.new_base_url, format!("{}/tier-e006", server.uri()));
+        assert_eq!(heal.trigger, "Homescreen request failed");
+        assert_eq!(client.base_url, heal.new_base_url);
+        assert!(client.take_tier_heal().is_none());
+        client.get_raw_homescreen().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn a_426_from_media_heals_too() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/tier-u011/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(426))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/tier-e006/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"media":[]}"#))
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_tier_info(&server, "e006", 1).await;
+
+        let mut client = on_tier_u011(&server);
+        assert_eq!(client.get_raw_media_page(1, 1, None).await.unwrap(), r#"{"media":[]}"#);
+        assert!(crate::shared::apply_tier_heal(&mut client));
+        assert!(client.base_url.ends_with("/tier-e006"));
+    }
+
+    #[tokio::test]
+    async fn the_same_tier_or_other_errors_leave_the_session_alone() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/tier-u011/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/tier-u011/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&server)
+            .await;
+        mount_tier_info(&server, "u011", 1).await;
+
+        let mut client = on_tier_u011(&server);
+        let err = client.get_raw_homescreen().await.unwrap_err();
+        assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::NotFound(_))), "{:?}", err);
+        assert!(client.get_raw_media_page(1, 1, None).await.is_err());
+        assert!(client.take_tier_heal().is_none());
+        assert!(client.base_url.ends_with("/tier-u011"));
+    }
+
+    #[tokio::test]
+    async fn rediscovery_waits_out_the_cooldown() {
+        use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+        let server = MockServer::start().await;
+        // Both tiers refuse, so without the cooldown every call would ask tier_info again.
+        Mock::given(matchers::path_regex("^/tier-[a-z0-9]+/api/v3/accounts/42/homescreen$"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        mount_tier_info(&server, "e006", 1).await;
+
+        let client = on_tier_u011(&server);
+        for attempt in 0..3 {
+            // Clones share the cooldown.
+            assert!(client.clone().get_raw_homescreen().await.is_err(), "attempt {}", attempt);
+        }
+        let homescreen_calls = server.received_requests().await.unwrap().iter()
+            .filter(|r| r.url.path().ends_with("/homescreen"))
+            .map(|r| r.url.path().split('/').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>();
+        // The first call found e006; within the cooldown later ones reuse it without asking.
+        assert_eq!(homescreen_calls, vec!["tier-u011", "tier-e006", "tier-u011", "tier-e006", "tier-u011", "tier-e006"]);
+    }
+}
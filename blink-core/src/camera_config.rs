@@ -0,0 +1,234 @@
+//! Checks a camera config update before it goes to Blink. Blink ignores keys it doesn't
+//! know and values of the wrong type without saying so, so `"motion_sensitivy": 7` would
+//! look like a success. Each device family has a list of keys we know it takes and what
+//! values they accept; anything else is reported unless the caller allows unknown keys.
+
+use crate::blink::DeviceFamily;
+use serde::Serialize;
+use serde_json::Value;
+
+/// What a known key accepts. A key may list several rules; a value passing any of them
+/// is fine.
+#[derive(Debug, Clone, Copy)]
+enum Rule {
+    Bool,
+    Int { min: i64, max: i64 },
+    Choice(&'static [&'static str]),
+    Text { max_len: usize },
+}
+
+impl Rule {
+    fn check(self, value: &Value) -> Result<(), String> {
+        match self {
+            Rule::Bool => value.as_bool().map(|_| ()).ok_or_else(|| format!("expected true or false, got {}", value)),
+            Rule::Int { min, max } => match value.as_i64() {
+                Some(n) if (min..=max).contains(&n) => Ok(()),
+                Some(n) => Err(format!("must be between {} and {}, got {}", min, max, n)),
+                None => Err(format!("expected a whole number, got {}", value)),
+            },
+            Rule::Choice(choices) => match value.as_str() {
+                Some(s) if choices.contains(&s) => Ok(()),
+                Some(s) => Err(format!("must be one of {}, got {:?}", choices.join(", "), s)),
+                None => Err(format!("expected one of {}, got {}", choices.join(", "), value)),
+            },
+            Rule::Text { max_len } => match value.as_str() {
+                Some(s) if !s.trim().is_empty() && s.chars().count() <= max_len => Ok(()),
+                Some(_) => Err(format!("must be 1 to {} characters", max_len)),
+                None => Err(format!("expected text, got {}", value)),
+            },
+        }
+    }
+}
+
+type KeyRules = (&'static str, &'static [Rule]);
+
+/// Keys every family takes. `video_quality` is the 1-3 level the settings dialog sends or
+/// the name Blink reports back.
+const COMMON_KEYS: &[KeyRules] = &[
+    ("name", &[Rule::Text { max_len: 64 }]),
+    ("motion_sensitivity", &[Rule::Int { min: 1, max: 9 }]),
+    ("video_quality", &[Rule::Int { min: 1, max: 3 }, Rule::Choice(&["saver", "standard", "best"])]),
+    ("night_vision_mode", &[Rule::Choice(&["off", "on", "auto"])]),
+    ("video_length", &[Rule::Int { min: 5, max: 60 }]),
+    ("retrigger_time", &[Rule::Int { min: 10, max: 60 }]),
+    ("flip_video", &[Rule::Bool]),
+    ("motion_alert", &[Rule::Bool]),
+    ("record_audio_enable", &[Rule::Bool]),
+    ("early_termination", &[Rule::Bool]),
+];
+
+const CAMERA_KEYS: &[KeyRules] = &[
+    ("illuminator_enable", &[Rule::Int { min: 0, max: 2 }]),
+    ("illuminator_intensity", &[Rule::Int { min: 1, max: 7 }]),
+    ("temp_alert_enable", &[Rule::Bool]),
+    ("temp_min", &[Rule::Int { min: -20, max: 120 }]),
+    ("temp_max", &[Rule::Int { min: -20, max: 120 }]),
+];
+
+const OWL_KEYS: &[KeyRules] = &[
+    ("status_led_enabled", &[Rule::Bool]),
+];
+
+const DOORBELL_KEYS: &[KeyRules] = &[
+    ("status_led_enabled", &[Rule::Bool]),
+    ("chime_enabled", &[Rule::Bool]),
+];
+
+fn family_keys(family: DeviceFamily) -> &'static [KeyRules] {
+    match family {
+        DeviceFamily::Camera => CAMERA_KEYS,
+        DeviceFamily::Owl => OWL_KEYS,
+        DeviceFamily::Doorbell => DOORBELL_KEYS,
+    }
+}
+
+fn rules_for(family: DeviceFamily, key: &str) -> Option<&'static [Rule]> {
+    COMMON_KEYS.iter().chain(family_keys(family)).find(|(k, _)| *k == key).map(|(_, rules)| *rules)
+}
+
+/// One thing wrong with an update.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConfigProblem {
+    /// The key, as `camera.<key>` when the update is wrapped.
+    pub key: String,
+    pub problem: String,
+}
+
+/// An update that failed `validate`; nothing was sent.
+#[derive(Debug, Clone)]
+pub struct ConfigRejected {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl std::fmt::Display for ConfigRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Camera config rejected: ")?;
+        let problems: Vec<String> = self.problems.iter().map(|p| format!("{} {}", p.key, p.problem)).collect();
+        write!(f, "{}", problems.join("; "))
+    }
+}
+
+impl std::error::Error for ConfigRejected {}
+
+/// Checks `config` against what devices of `product_type` take. Standard cameras expect
+/// the update wrapped as `{"camera": {...}}`; that form is accepted for every family.
+/// Known keys are always checked; unknown ones are only let through with `allow_unknown`.
+pub fn validate(product_type: &str, config: &Value, allow_unknown: bool) -> Result<(), ConfigRejected> {
+    let family = DeviceFamily::of_product(product_type);
+    let Some(object) = config.as_object() else {
+        return Err(ConfigRejected {
+            problems: vec![ConfigProblem { key: String::new(), problem: "the update must be a JSON object".to_string() }],
+        });
+    };
+    let (prefix, fields) = match object.get("camera").and_then(Value::as_object) {
+        Some(inner) if object.len() == 1 => ("camera.", inner),
+        _ => ("", object),
+    };
+
+    let mut problems = Vec::new();
+    for (key, value) in fields {
+        let problem = match rules_for(family, key) {
+            Some(rules) => {
+                let results: Vec<Result<(), String>> = rules.iter().map(|rule| rule.check(value)).collect();
+                if results.iter().any(Result::is_ok) {
+                    None
+                } else {
+                    results.into_iter().find_map(Result::err)
+                }
+            }
+            None if allow_unknown => None,
+            None => Some(format!("is not a known {} setting", family.as_str())),
+        };
+        if let Some(problem) = problem {
+            problems.push(ConfigProblem { key: format!("{}{}", prefix, key), problem });
+        }
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigRejected { problems })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn problems(product_type: &str, config: Value, allow_unknown: bool) -> Vec<(String, String)> {
+        match validate(product_type, &config, allow_unknown) {
+            Ok(()) => Vec::new(),
+            Err(rejected) => rejected.problems.into_iter().map(|p| (p.key, p.problem)).collect(),
+        }
+    }
+
+    #[test]
+    fn known_keys_within_range_pass() {
+        let config = json!({
+            "name": "Driveway", "motion_sensitivity": 9, "video_quality": 3, "night_vision_mode": "auto",
+            "video_length": 5, "retrigger_time": 60, "flip_video": false, "illuminator_intensity": 1, "temp_min": -20,
+        });
+        assert!(problems("catalina", config.clone(), false).is_empty());
+        assert!(problems("catalina", json!({ "camera": config }), false).is_empty());
+        assert!(problems("catalina", json!({ "video_quality": "best" }), false).is_empty());
+    }
+
+    #[test]
+    fn range_violations_name_the_bounds() {
+        assert_eq!(problems("catalina", json!({ "motion_sensitivity": 10 }), false), vec![
+            ("motion_sensitivity".to_string(), "must be between 1 and 9, got 10".to_string()),
+        ]);
+        assert_eq!(problems("catalina", json!({ "camera": { "video_length": 4, "temp_max": 121 } }), false), vec![
+            ("camera.temp_max".to_string(), "must be between -20 and 120, got 121".to_string()),
+            ("camera.video_length".to_string(), "must be between 5 and 60, got 4".to_string()),
+        ]);
+        assert_eq!(problems("catalina", json!({ "name": "   " }), false)[0].1, "must be 1 to 64 characters");
+        assert_eq!(problems("catalina", json!({ "name": "x".repeat(65) }), false)[0].1, "must be 1 to 64 characters");
+        assert_eq!(problems("catalina", json!({ "night_vision_mode": "dim" }), false)[0].1, "must be one of off, on, auto, got \"dim\"");
+    }
+
+    #[test]
+    fn type_mismatches_are_reported() {
+        let found = problems("catalina", json!({ "flip_video": "yes", "motion_sensitivity": 5.5, "name": 7, "night_vision_mode": true }), false);
+        assert_eq!(found, vec![
+            ("flip_video".to_string(), "expected true or false, got \"yes\"".to_string()),
+            ("motion_sensitivity".to_string(), "expected a whole number, got 5.5".to_string()),
+            ("name".to_string(), "expected text, got 7".to_string()),
+            ("night_vision_mode".to_string(), "expected one of off, on, auto, got true".to_string()),
+        ]);
+        // With several rules the first one's complaint is given.
+        assert_eq!(problems("catalina", json!({ "video_quality": "ultra" }), false)[0].1, "expected a whole number, got \"ultra\"");
+        assert_eq!(problems("catalina", json!([1, 2]), false), vec![(String::new(), "the update must be a JSON object".to_string())]);
+    }
+
+    #[test]
+    fn keys_depend_on_the_device_family() {
+        assert_eq!(problems("catalina", json!({ "chime_enabled": true }), false), vec![
+            ("chime_enabled".to_string(), "is not a known camera setting".to_string()),
+        ]);
+        assert!(problems("lotus", json!({ "chime_enabled": true, "status_led_enabled": false }), false).is_empty());
+        assert_eq!(problems("owl", json!({ "illuminator_intensity": 3 }), false), vec![
+            ("illuminator_intensity".to_string(), "is not a known owl setting".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn allow_unknown_lets_unknown_keys_through_but_still_checks_known_ones() {
+        assert_eq!(problems("catalina", json!({ "motion_sensitivy": 7 }), false), vec![
+            ("motion_sensitivy".to_string(), "is not a known camera setting".to_string()),
+        ]);
+        assert!(problems("catalina", json!({ "motion_sensitivy": 7, "new_blink_flag": { "a": 1 } }), true).is_empty());
+        assert_eq!(problems("catalina", json!({ "motion_sensitivy": 7, "motion_sensitivity": 0 }), true), vec![
+            ("motion_sensitivity".to_string(), "must be between 1 and 9, got 0".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn the_message_lists_every_problem() {
+        let rejected = validate("catalina", &json!({ "flip_video": 1, "typo": true }), false).unwrap_err();
+        assert_eq!(
+            rejected.to_string(),
+            "Camera config rejected: flip_video expected true or false, got 1; typo is not a known camera setting"
+        );
+    }
+}
@@ -0,0 +1,394 @@
+//! Removal of the clips Blink records for liveviews. Finished sessions are queued on disk
+//! and checked against the media feed until nothing from the session's window is left,
+//! so a clip Blink finalizes late, or one that survived a restart, still gets deleted.
+//!
+//! Only clips that are clearly the session's own are deleted: a motion clip uploading while
+//! the liveview was open lands in the same camera and window, so see `judge` for the rules.
+
+use crate::audit;
+use crate::blink::{parse_blink_timestamp, BlinkClient};
+use crate::settings::Settings;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::{Mutex, Notify};
+
+pub const CLEANUP_FILE: &str = "liveview_cleanup.json";
+/// Entries still unresolved this long after their session ended are given up on.
+pub const MAX_AGE_SECS: i64 = 24 * 60 * 60;
+/// Blink can take up to ~90s to finalize the clip recorded for a liveview, so an empty
+/// window only counts once the session has been over this long.
+const SETTLE_SECS: i64 = 120;
+/// Clips Blink marks as liveview recordings still count as the session's when created
+/// this close to either end of it.
+const WINDOW_MARGIN_SECS: i64 = 60;
+const FIRST_CHECK_SECS: i64 = 5;
+const MAX_BACKOFF_SECS: i64 = 30 * 60;
+/// Upper bound on one sleep, so a changed clock is noticed eventually.
+const MAX_SLEEP_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CleanupEntry {
+    pub session_id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    /// Checks that failed or still found clips; drives the backoff.
+    pub attempts: u32,
+    /// Clips deleted for this session so far.
+    pub deleted: usize,
+    pub next_check_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+enum Outcome {
+    Done,
+    Retry(DateTime<Utc>),
+}
+
+/// What `judge` made of one clip from the camera's feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Marked as a liveview recording and created around the session.
+    MarkedLiveview,
+    /// Unmarked, but created during the session and no longer than it.
+    InSession,
+    /// Marked as something else, such as a motion clip.
+    MarkedOther,
+    OutsideWindow,
+    LongerThanSession,
+    NoTimestamp,
+}
+
+impl Verdict {
+    pub fn deletes(self) -> bool {
+        matches!(self, Verdict::MarkedLiveview | Verdict::InSession)
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Verdict::MarkedLiveview => "marked as a liveview recording",
+            Verdict::InSession => "created during the session",
+            Verdict::MarkedOther => "marked as another kind of clip",
+            Verdict::OutsideWindow => "created outside the session",
+            Verdict::LongerThanSession => "longer than the session",
+            Verdict::NoTimestamp => "no creation time",
+        })
+    }
+}
+
+/// Field values that only say the item is a clip, not how it was recorded.
+const GENERIC_MEDIA_TYPES: [&str; 4] = ["video", "image", "photo", "clip"];
+
+/// How Blink says the clip was recorded, from `source` or a non-generic `type`.
+fn recorded_by(item: &Value) -> Option<String> {
+    ["source", "type"].iter()
+        .filter_map(|key| item[key].as_str())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .find(|value| !value.is_empty() && !GENERIC_MEDIA_TYPES.contains(&value.as_str()))
+}
+
+fn is_liveview_marker(value: &str) -> bool {
+    value.contains("live") || value == "lv" || value.starts_with("lv_")
+}
+
+/// Decides whether a clip belongs to the session that ran from `started_at` to `ended_at`:
+/// - a clip marked as a liveview recording counts when created within `WINDOW_MARGIN_SECS`
+///   of the session;
+/// - a clip marked as anything else is never the session's;
+/// - an unmarked clip counts only when created between `started_at - lookback_secs` and
+///   `ended_at`.
+///
+/// Either way a clip longer than the session is kept.
+pub fn judge(item: &Value, started_at: DateTime<Utc>, ended_at: DateTime<Utc>, lookback_secs: u64) -> Verdict {
+    let marker = recorded_by(item);
+    if marker.as_deref().is_some_and(|m| !is_liveview_marker(m)) {
+        return Verdict::MarkedOther;
+    }
+    let created_at = ["created_at", "created_at_utc", "updated_at", "time"].iter()
+        .find_map(|key| item[key].as_str().and_then(parse_blink_timestamp));
+    let Some(created_at) = created_at else {
+        return Verdict::NoTimestamp;
+    };
+    let (from, to) = match marker {
+        Some(_) => (started_at - Duration::seconds(WINDOW_MARGIN_SECS), ended_at + Duration::seconds(WINDOW_MARGIN_SECS)),
+        None => (started_at - Duration::seconds(lookback_secs as i64), ended_at),
+    };
+    if created_at < from || created_at > to {
+        return Verdict::OutsideWindow;
+    }
+    let session_secs = (ended_at - started_at).num_milliseconds() as f64 / 1000.0;
+    if item["duration"].as_f64().is_some_and(|duration| duration > session_secs) {
+        return Verdict::LongerThanSession;
+    }
+    if marker.is_some() { Verdict::MarkedLiveview } else { Verdict::InSession }
+}
+
+pub struct CleanupQueue {
+    path: PathBuf,
+    entries: std::sync::Mutex<Vec<CleanupEntry>>,
+    wake: Notify,
+}
+
+impl CleanupQueue {
+    /// Reads the entries left by a previous run; a missing or unreadable file starts empty.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: std::sync::Mutex::new(entries),
+            wake: Notify::new(),
+        }
+    }
+
+    fn save(&self, entries: &[CleanupEntry]) {
+        let result = serde_json::to_vec_pretty(entries)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&self.path, json));
+        if let Err(e) = result {
+            eprintln!("Failed to save liveview cleanup queue: {}", e);
+        }
+    }
+
+    /// Queues the clip of a session that ran from `started_at` until now.
+    pub fn enqueue(&self, session_id: &str, network_id: i64, camera_id: i64, started_at: DateTime<Utc>) {
+        let now = Utc::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.push(CleanupEntry {
+            session_id: session_id.to_string(),
+            network_id,
+            camera_id,
+            started_at,
+            ended_at: now,
+            attempts: 0,
+            deleted: 0,
+            next_check_at: now + Duration::seconds(FIRST_CHECK_SECS),
+            last_error: None,
+        });
+        self.save(&entries);
+        drop(entries);
+        self.wake.notify_one();
+    }
+
+    /// Queued sessions, next check first.
+    pub fn entries(&self) -> Vec<CleanupEntry> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        entries.sort_by_key(|e| e.next_check_at);
+        entries
+    }
+
+    /// Checks entries as they come due, for as long as the app runs.
+    pub async fn run(&self, blink_client: &Arc<Mutex<BlinkClient>>, settings: impl Fn() -> Settings) {
+        loop {
+            let next = self.entries.lock().unwrap().iter().map(|e| e.next_check_at).min();
+            let wait = match next {
+                Some(due) => (due - Utc::now()).to_std().unwrap_or_default().min(std::time::Duration::from_secs(MAX_SLEEP_SECS)),
+                None => std::time::Duration::from_secs(MAX_SLEEP_SECS),
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.wake.notified() => continue,
+            }
+
+            let now = Utc::now();
+            let due: Vec<CleanupEntry> = self.entries.lock().unwrap().iter()
+                .filter(|e| e.next_check_at <= now)
+                .cloned()
+                .collect();
+            if due.is_empty() {
+                continue;
+            }
+            let client = crate::shared::snapshot(blink_client).await;
+            let lookback_secs = settings().liveview_cleanup_lookback_secs;
+            for mut entry in due {
+                let outcome = check(&client, &mut entry, lookback_secs).await;
+                self.settle(entry, outcome);
+            }
+        }
+    }
+
+    /// Writes a checked entry back, or drops it when it is done or too old.
+    fn settle(&self, entry: CleanupEntry, outcome: Outcome) {
+        let target = format!("camera {} (network {})", entry.camera_id, entry.network_id);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.session_id != entry.session_id);
+        match outcome {
+            Outcome::Done => audit::record("liveview_cleanup", &target, None),
+            Outcome::Retry(_) if Utc::now() - entry.ended_at > Duration::seconds(MAX_AGE_SECS) => {
+                let reason = entry.last_error.as_deref().unwrap_or("liveview clip still present");
+                eprintln!("Giving up on liveview cleanup for {}: {}", target, reason);
+                audit::record("liveview_cleanup", &target, Some(format!("Gave up after {} checks: {}", entry.attempts, reason)));
+            }
+            Outcome::Retry(at) => entries.push(CleanupEntry { next_check_at: at, ..entry }),
+        }
+        self.save(&entries);
+    }
+}
+
+/// The ids of the session's own clips in `items`, logging the verdict on each.
+fn session_clips(entry: &CleanupEntry, items: &[Value], lookback_secs: u64) -> Vec<i64> {
+    let mut ids = Vec::new();
+    for item in items {
+        let Some(id) = item["id"].as_i64() else { continue };
+        let verdict = judge(item, entry.started_at, entry.ended_at, lookback_secs);
+        let action = if verdict.deletes() { "deleting" } else { "keeping" };
+        eprintln!("Liveview cleanup for camera {}: {} media {} ({})", entry.camera_id, action, id, verdict);
+        if verdict.deletes() {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Deletes the session's clips the feed still shows. Done once there are none left and
+/// Blink has had time to finalize the clip.
+async fn check(client: &BlinkClient, entry: &mut CleanupEntry, lookback_secs: u64) -> Outcome {
+    let now = Utc::now();
+    let since = entry.started_at - Duration::seconds(WINDOW_MARGIN_SECS.max(lookback_secs as i64));
+    let listed = client.camera_media_since(entry.camera_id, since).await;
+    let result = match listed.map(|items| session_clips(entry, &items, lookback_secs)) {
+        Ok(ids) if ids.is_empty() => {
+            let settled_at = entry.ended_at + Duration::seconds(SETTLE_SECS);
+            return if now >= settled_at { Outcome::Done } else { Outcome::Retry(settled_at) };
+        }
+        Ok(ids) => {
+            let count = ids.len();
+            client.delete_media(ids).await.map(|()| count)
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(count) => {
+            entry.deleted += count;
+            entry.last_error = None;
+        }
+        Err(e) => entry.last_error = Some(e.to_string()),
+    }
+    // Deletes are re-checked too, backing off in case Blink keeps the clip around.
+    entry.attempts += 1;
+    let backoff = (FIRST_CHECK_SECS << entry.attempts.min(10)).min(MAX_BACKOFF_SECS);
+    Outcome::Retry(now + Duration::seconds(backoff))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A 45-second liveview on camera 5 that started at 12:00:00.
+    fn session() -> CleanupEntry {
+        let started_at = "2026-10-15T12:00:00Z".parse().unwrap();
+        CleanupEntry {
+            session_id: "s1".to_string(),
+            network_id: 1,
+            camera_id: 5,
+            started_at,
+            ended_at: started_at + Duration::seconds(45),
+            attempts: 0,
+            deleted: 0,
+            next_check_at: started_at,
+            last_error: None,
+        }
+    }
+
+    fn verdict(item: Value, lookback_secs: u64) -> Verdict {
+        let entry = session();
+        judge(&item, entry.started_at, entry.ended_at, lookback_secs)
+    }
+
+    #[test]
+    fn marked_clips_follow_their_marker() {
+        for source in ["liveview", "LiveView", "lv", "lv_relay", "camera_live"] {
+            assert_eq!(verdict(json!({ "source": source, "created_at": "2026-10-15T12:00:40+00:00" }), 0), Verdict::MarkedLiveview, "{}", source);
+        }
+        // Blink finalizes the liveview clip late, and sometimes stamps it a little early.
+        assert_eq!(verdict(json!({ "source": "liveview", "created_at": "2026-10-15T11:59:10Z" }), 0), Verdict::MarkedLiveview);
+        assert_eq!(verdict(json!({ "source": "liveview", "created_at": "2026-10-15T12:01:40Z" }), 0), Verdict::MarkedLiveview);
+        assert_eq!(verdict(json!({ "source": "liveview", "created_at": "2026-10-15T12:01:46Z" }), 0), Verdict::OutsideWindow);
+        for source in ["pir", "motion", "snapshot", "cv_motion"] {
+            assert_eq!(verdict(json!({ "source": source, "created_at": "2026-10-15T12:00:10Z" }), 120), Verdict::MarkedOther, "{}", source);
+        }
+    }
+
+    #[test]
+    fn a_generic_type_does_not_count_as_a_marker() {
+        assert_eq!(verdict(json!({ "type": "video", "created_at": "2026-10-15T12:00:10Z" }), 0), Verdict::InSession);
+        assert_eq!(verdict(json!({ "type": "video", "source": "pir", "created_at": "2026-10-15T12:00:10Z" }), 0), Verdict::MarkedOther);
+        assert_eq!(verdict(json!({ "type": "liveview", "created_at": "2026-10-15T12:00:10Z" }), 0), Verdict::MarkedLiveview);
+    }
+
+    #[test]
+    fn unmarked_clips_must_fall_inside_the_session() {
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:00Z" }), 0), Verdict::InSession);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:45Z" }), 0), Verdict::InSession);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:46Z" }), 0), Verdict::OutsideWindow);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T11:59:59Z" }), 0), Verdict::OutsideWindow);
+        // The configurable lookback widens the start only.
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T11:59:30Z" }), 30), Verdict::InSession);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T11:59:29Z" }), 30), Verdict::OutsideWindow);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:50Z" }), 30), Verdict::OutsideWindow);
+    }
+
+    #[test]
+    fn timestamps_and_durations_are_checked() {
+        assert_eq!(verdict(json!({ "updated_at": "2026-10-15T12:00:10Z" }), 0), Verdict::InSession);
+        assert_eq!(verdict(json!({ "created_at": "yesterday" }), 0), Verdict::NoTimestamp);
+        assert_eq!(verdict(json!({}), 0), Verdict::NoTimestamp);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:10Z", "duration": 45 }), 0), Verdict::InSession);
+        assert_eq!(verdict(json!({ "created_at": "2026-10-15T12:00:10Z", "duration": 45.5 }), 0), Verdict::LongerThanSession);
+        assert_eq!(verdict(json!({ "source": "liveview", "created_at": "2026-10-15T12:00:10Z", "duration": 60 }), 0), Verdict::LongerThanSession);
+        assert!(!Verdict::LongerThanSession.deletes() && !Verdict::NoTimestamp.deletes());
+    }
+
+    #[test]
+    fn a_motion_clip_uploaded_during_liveview_is_kept() {
+        let feed = vec![
+            // The liveview's own clip, finalized after the session.
+            json!({ "id": 1, "source": "liveview", "type": "video", "created_at": "2026-10-15T12:00:50Z", "duration": 44 }),
+            // Motion on the same camera while the liveview was open, marked and unmarked.
+            json!({ "id": 2, "source": "pir", "type": "video", "created_at": "2026-10-15T12:00:20Z", "duration": 10 }),
+            json!({ "id": 3, "type": "video", "created_at": "2026-10-15T11:59:40Z", "duration": 60 }),
+            // Unmarked and recorded while motion kept the camera going past the session.
+            json!({ "id": 4, "type": "video", "created_at": "2026-10-15T12:00:05Z", "duration": 90 }),
+            // Unmarked, inside the session and shorter than it.
+            json!({ "id": 5, "type": "video", "created_at": "2026-10-15T12:00:02Z", "duration": 43 }),
+            // No id, and from an hour earlier.
+            json!({ "source": "liveview", "created_at": "2026-10-15T12:00:10Z" }),
+            json!({ "id": 6, "type": "video", "created_at": "2026-10-15T11:00:00Z" }),
+        ];
+        assert_eq!(session_clips(&session(), &feed, 0), vec![1, 5]);
+        // With a lookback the motion clip that started just before the session is still
+        // kept: it's longer than the session was.
+        assert_eq!(session_clips(&session(), &feed, 30), vec![1, 5]);
+        assert!(session_clips(&session(), &feed[1..2], 120).is_empty());
+    }
+
+    #[test]
+    fn the_queue_survives_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(CLEANUP_FILE);
+        let queue = CleanupQueue::load(path.clone());
+        assert!(queue.entries().is_empty());
+        let started_at = Utc::now() - Duration::seconds(30);
+        queue.enqueue("s1", 1, 5, started_at);
+        queue.enqueue("s2", 1, 6, started_at);
+
+        let reloaded = CleanupQueue::load(path.clone());
+        let entries = reloaded.entries();
+        assert_eq!(entries.iter().map(|e| e.session_id.as_str()).collect::<Vec<_>>(), vec!["s1", "s2"]);
+        assert_eq!(entries[0].started_at, started_at);
+        assert!(entries[0].ended_at > started_at);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(CleanupQueue::load(path).entries().is_empty());
+    }
+}
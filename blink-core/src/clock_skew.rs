@@ -0,0 +1,250 @@
+//! How far the local clock is from Blink's. Token expiry is computed from our clock, so a
+//! VM whose clock runs ahead refreshes constantly and one that runs behind keeps using
+//! tokens Blink has already expired. The `Date` header of Blink's responses gives server
+//! time to the second; the offset is smoothed over responses, kept on disk so a restart
+//! starts from the last estimate, and applied whenever expiry is computed or checked.
+//! The pure functions take the current time as an argument so they can be driven with any
+//! clock.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+pub const CLOCK_SKEW_FILE: &str = "clock_skew.json";
+/// Refresh this long before expiry when the clocks agree.
+pub const BASE_REFRESH_MARGIN_SECS: i64 = 60;
+/// The margin never grows past this, however wrong the clock looks.
+pub const MAX_REFRESH_MARGIN_SECS: i64 = 15 * 60;
+/// A token younger than this is never refreshed, so a clock that is far off can't turn
+/// every request into a refresh.
+pub const MIN_REFRESH_INTERVAL_SECS: i64 = 60;
+/// Skew past this is logged and flagged in the self-test.
+pub const SKEW_WARN_SECS: i64 = 30;
+/// Weight of each new sample; the header only has whole seconds and includes the trip
+/// back, so single readings are noisy.
+const SMOOTHING: f64 = 0.2;
+/// The estimate is written to disk when it has moved this far since the last save.
+const SAVE_THRESHOLD_SECS: f64 = 2.0;
+
+static SKEW: OnceLock<SkewTracker> = OnceLock::new();
+
+/// Server time minus local time, as estimated so far.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClockSkew {
+    pub skew_secs: f64,
+    pub samples: u64,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// What the self-test reports about the clock.
+#[derive(Debug, Clone, Serialize)]
+pub struct ClockSkewReport {
+    /// Positive when Blink's clock is ahead of ours.
+    pub skew_secs: i64,
+    pub samples: u64,
+    pub updated_at: Option<DateTime<Utc>>,
+    /// The margin token refreshes currently use.
+    pub refresh_margin_secs: i64,
+    /// Set when the skew is past `SKEW_WARN_SECS`.
+    pub warning: Option<String>,
+}
+
+struct SkewTracker {
+    path: PathBuf,
+    state: Mutex<TrackerState>,
+}
+
+struct TrackerState {
+    skew: ClockSkew,
+    saved_skew: f64,
+    warned: bool,
+}
+
+/// Loads the last estimate from `path`. Until this is called no skew is tracked and
+/// expiry uses the local clock as is.
+pub fn init(path: PathBuf) {
+    let skew: ClockSkew = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let saved_skew = skew.skew_secs;
+    let _ = SKEW.set(SkewTracker { path, state: Mutex::new(TrackerState { skew, saved_skew, warned: false }) });
+}
+
+/// The estimate after a response stamped `server` arrived at local time `local`.
+pub fn next_estimate(current: &ClockSkew, server: DateTime<Utc>, local: DateTime<Utc>) -> ClockSkew {
+    let sample = (server - local).num_milliseconds() as f64 / 1000.0;
+    let skew_secs = if current.samples == 0 { sample } else { current.skew_secs + SMOOTHING * (sample - current.skew_secs) };
+    ClockSkew { skew_secs, samples: current.samples + 1, updated_at: Some(local) }
+}
+
+/// How long before expiry to refresh: the base margin widened by half the observed skew,
+/// since an estimate that large can itself be off by that much.
+pub fn margin_for(skew_secs: f64) -> i64 {
+    (BASE_REFRESH_MARGIN_SECS + (skew_secs.abs() / 2.0) as i64).min(MAX_REFRESH_MARGIN_SECS)
+}
+
+/// Whether a token expiring at `expiry` (server time) should be refreshed at local time
+/// `now`, given the skew and when the token was last refreshed (server time).
+pub fn needs_refresh(expiry: Option<i64>, last_refresh: Option<i64>, now: DateTime<Utc>, skew_secs: f64) -> bool {
+    let server_now = now.timestamp() + skew_secs.round() as i64;
+    if last_refresh.is_some_and(|at| server_now - at < MIN_REFRESH_INTERVAL_SECS && server_now >= at) {
+        return false;
+    }
+    expiry.is_none_or(|expiry| server_now >= expiry - margin_for(skew_secs))
+}
+
+fn current() -> ClockSkew {
+    SKEW.get().map(|t| t.state.lock().unwrap().skew.clone()).unwrap_or_default()
+}
+
+/// The current estimate in seconds; 0 before any response was seen.
+pub fn skew_secs() -> f64 {
+    current().skew_secs
+}
+
+/// Our best guess at Blink's clock, as a Unix timestamp.
+pub fn server_now() -> i64 {
+    Utc::now().timestamp() + skew_secs().round() as i64
+}
+
+/// Folds in the `Date` header of a Blink response, if it has a readable one.
+pub fn observe(headers: &reqwest::header::HeaderMap) {
+    let Some(tracker) = SKEW.get() else { return };
+    let Some(server) = headers.get(reqwest::header::DATE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+    else {
+        return;
+    };
+    let mut state = tracker.state.lock().unwrap();
+    state.skew = next_estimate(&state.skew, server.with_timezone(&Utc), Utc::now());
+    let skew = state.skew.skew_secs;
+    let past_threshold = skew.abs() > SKEW_WARN_SECS as f64;
+    if past_threshold && !state.warned {
+        crate::log_control::warn("blink", || format!(
+            "The local clock is {:.0}s {} Blink's; token expiry is adjusted for it, but fix the system clock if you can",
+            skew.abs(),
+            if skew > 0.0 { "behind" } else { "ahead of" }
+        ));
+    }
+    state.warned = past_threshold;
+    if (skew - state.saved_skew).abs() >= SAVE_THRESHOLD_SECS {
+        state.saved_skew = skew;
+        if let Err(e) = tracker.save(&state.skew) {
+            eprintln!("Failed to save clock skew: {}", e);
+        }
+    }
+}
+
+impl SkewTracker {
+    fn save(&self, skew: &ClockSkew) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(skew)?)?;
+        Ok(())
+    }
+}
+
+pub fn report() -> ClockSkewReport {
+    report_for(&current())
+}
+
+fn report_for(skew: &ClockSkew) -> ClockSkewReport {
+    let rounded = skew.skew_secs.round() as i64;
+    ClockSkewReport {
+        skew_secs: rounded,
+        samples: skew.samples,
+        updated_at: skew.updated_at,
+        refresh_margin_secs: margin_for(skew.skew_secs),
+        warning: (rounded.abs() > SKEW_WARN_SECS).then(|| format!(
+            "The local clock is {}s {} Blink's",
+            rounded.abs(),
+            if rounded > 0 { "behind" } else { "ahead of" }
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(1_700_000_000 + secs, 0).unwrap()
+    }
+
+    fn estimate(skew_secs: f64) -> ClockSkew {
+        ClockSkew { skew_secs, samples: 10, updated_at: None }
+    }
+
+    #[test]
+    fn the_first_sample_is_taken_as_is_and_later_ones_are_smoothed() {
+        let first = next_estimate(&ClockSkew::default(), at(100), at(0));
+        assert_eq!((first.skew_secs, first.samples, first.updated_at), (100.0, 1, Some(at(0))));
+        // One noisy reading moves the estimate a fifth of the way.
+        let second = next_estimate(&first, at(50), at(0));
+        assert_eq!((second.skew_secs, second.samples), (90.0, 2));
+        // A steady clock converges on the real offset.
+        let mut skew = first;
+        for local in 0..60 {
+            skew = next_estimate(&skew, at(local) - Duration::seconds(30), at(local));
+        }
+        assert!((skew.skew_secs + 30.0).abs() < 0.01, "{}", skew.skew_secs);
+    }
+
+    #[test]
+    fn the_margin_widens_with_skew_up_to_a_cap() {
+        assert_eq!(margin_for(0.0), BASE_REFRESH_MARGIN_SECS);
+        assert_eq!(margin_for(100.0), 110);
+        assert_eq!(margin_for(-100.0), 110);
+        assert_eq!(margin_for(1e9), MAX_REFRESH_MARGIN_SECS);
+    }
+
+    #[test]
+    fn a_clock_running_ahead_does_not_refresh_a_fresh_token() {
+        // Blink issued the token at its time 0, valid for an hour; our clock is 3500s ahead.
+        let expiry = Some(at(3600).timestamp());
+        let issued = Some(at(0).timestamp());
+        let local = at(3500) + Duration::seconds(120);
+        assert!(!needs_refresh(expiry, issued, local, -3500.0));
+        // Taken at face value, the same clock would refresh on every request.
+        assert!(needs_refresh(expiry, issued, local, 0.0));
+    }
+
+    #[test]
+    fn a_clock_running_behind_refreshes_before_blink_expires_the_token() {
+        let expiry = Some(at(3600).timestamp());
+        let issued = Some(at(0).timestamp());
+        // Blink's clock is at 3550, ours an hour behind it.
+        let local = at(3550) - Duration::seconds(3600);
+        assert!(needs_refresh(expiry, issued, local, 3600.0));
+        assert!(!needs_refresh(expiry, issued, local, 0.0));
+    }
+
+    #[test]
+    fn refreshes_are_spaced_out_however_wrong_the_clock_is() {
+        let expired = Some(at(0).timestamp());
+        // Refreshed 30s ago by Blink's clock: not again yet, even though the token looks expired.
+        assert!(!needs_refresh(expired, Some(at(1000).timestamp()), at(1030), 0.0));
+        assert!(needs_refresh(expired, Some(at(1000).timestamp()), at(1000 + MIN_REFRESH_INTERVAL_SECS), 0.0));
+        // A last refresh "in the future" means the estimate jumped; it doesn't block.
+        assert!(needs_refresh(expired, Some(at(5000).timestamp()), at(1000), 0.0));
+        // No known expiry always refreshes.
+        assert!(needs_refresh(None, None, at(0), 0.0));
+    }
+
+    #[test]
+    fn the_report_warns_past_the_threshold() {
+        let report = report_for(&estimate(45.4));
+        assert_eq!((report.skew_secs, report.refresh_margin_secs), (45, 82));
+        assert_eq!(report.warning.as_deref(), Some("The local clock is 45s behind Blink's"));
+        let report = report_for(&estimate(-31.0));
+        assert_eq!(report.warning.as_deref(), Some("The local clock is 31s ahead of Blink's"));
+        assert_eq!(report_for(&estimate(SKEW_WARN_SECS as f64)).warning, None);
+    }
+}
@@ -0,0 +1,219 @@
+//! Large deletions, run in batches. Sending hundreds of ids in one request either times
+//! out or looks like abuse to Blink, so big selections are queued as a job that a worker
+//! deletes `delete_batch_size` clips at a time with `delete_batch_delay_secs` between
+//! batches. Jobs are kept on disk, so a restart resumes the remainder, and each records
+//! which ids failed so the final summary is exact.
+
+use crate::blink::{BlinkClient, DeleteResult, DeleteStatus};
+use crate::media_index::MediaIndex;
+use crate::settings::Settings;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+pub const DELETE_QUEUE_FILE: &str = "delete_queue.json";
+/// Finished jobs kept for the status view.
+const KEEP_FINISHED: usize = 10;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Running,
+    Done,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DeleteJob {
+    id: String,
+    created_at: DateTime<Utc>,
+    total: usize,
+    /// Items not sent yet, as the client sent them.
+    remaining: Vec<Value>,
+    done: usize,
+    /// Ids Blink still listed after their batch, or whose batch failed outright.
+    failed: Vec<i64>,
+    state: JobState,
+    last_error: Option<String>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+/// Where a job stands; sent as the `delete-progress` event after every batch.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeleteProgress {
+    pub job_id: String,
+    pub state: JobState,
+    pub total: usize,
+    /// Items attempted so far, failed ones included.
+    pub done: usize,
+    pub failed: Vec<i64>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl DeleteJob {
+    fn progress(&self) -> DeleteProgress {
+        DeleteProgress {
+            job_id: self.id.clone(),
+            state: self.state,
+            total: self.total,
+            done: self.done,
+            failed: self.failed.clone(),
+            created_at: self.created_at,
+            finished_at: self.finished_at,
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    fn finish(&mut self, state: JobState) {
+        self.state = state;
+        self.finished_at = Some(Utc::now());
+    }
+}
+
+pub struct DeleteQueue {
+    path: PathBuf,
+    jobs: std::sync::Mutex<Vec<DeleteJob>>,
+    wake: Notify,
+}
+
+impl DeleteQueue {
+    /// Reads the jobs left by a previous run; a missing or unreadable file starts empty.
+    pub fn load(path: PathBuf) -> Self {
+        let jobs = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            jobs: std::sync::Mutex::new(jobs),
+            wake: Notify::new(),
+        }
+    }
+
+    fn save(&self, jobs: &[DeleteJob]) {
+        let result = serde_json::to_vec_pretty(jobs)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&self.path, json));
+        if let Err(e) = result {
+            eprintln!("Failed to save delete queue: {}", e);
+        }
+    }
+
+    /// Queues `items` as one job and returns `pending` for each id. Items without an id
+    /// are dropped.
+    pub fn enqueue(&self, items: &[Value]) -> (DeleteProgress, Vec<DeleteResult>) {
+        let items: Vec<Value> = items.iter().filter(|item| item.get("id").and_then(Value::as_i64).is_some()).cloned().collect();
+        let results = items.iter()
+            .filter_map(|item| item.get("id").and_then(Value::as_i64))
+            .map(|id| DeleteResult { id, status: DeleteStatus::Pending })
+            .collect();
+        let job = DeleteJob {
+            id: uuid::Uuid::new_v4().to_string(),
+            created_at: Utc::now(),
+            total: items.len(),
+            remaining: items,
+            done: 0,
+            failed: Vec::new(),
+            state: JobState::Running,
+            last_error: None,
+            finished_at: None,
+        };
+        let progress = job.progress();
+        let mut jobs = self.jobs.lock().unwrap();
+        jobs.push(job);
+        self.save(&jobs);
+        drop(jobs);
+        self.wake.notify_one();
+        (progress, results)
+    }
+
+    /// Drops what a running job hasn't sent yet. The batch in flight, if any, still completes.
+    pub fn cancel(&self, job_id: &str) -> Option<DeleteProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.id == job_id && j.state == JobState::Running)?;
+        job.remaining.clear();
+        job.finish(JobState::Cancelled);
+        let progress = job.progress();
+        self.save(&jobs);
+        Some(progress)
+    }
+
+    /// Running jobs and the most recent finished ones, oldest first.
+    pub fn status(&self) -> Vec<DeleteProgress> {
+        self.jobs.lock().unwrap().iter().map(DeleteJob::progress).collect()
+    }
+
+    /// The next batch of the oldest running job.
+    fn next_batch(&self, size: usize) -> Option<(String, Vec<Value>)> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.state == JobState::Running)?;
+        let take = size.min(job.remaining.len());
+        Some((job.id.clone(), job.remaining.drain(..take).collect()))
+    }
+
+    /// Records a batch's outcome and returns the job's progress. A job with nothing left
+    /// is finished here; old finished jobs are pruned.
+    fn record(&self, job_id: &str, batch: &[Value], outcome: Result<Vec<DeleteResult>, String>) -> Option<DeleteProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.id == job_id)?;
+        let ids: Vec<i64> = batch.iter().filter_map(|item| item.get("id").and_then(Value::as_i64)).collect();
+        job.done += ids.len();
+        match outcome {
+            Ok(results) => {
+                job.failed.extend(results.iter().filter(|r| r.status == DeleteStatus::StillPresent).map(|r| r.id));
+            }
+            Err(e) => {
+                job.failed.extend(ids);
+                job.last_error = Some(e);
+            }
+        }
+        if job.state == JobState::Running && job.remaining.is_empty() {
+            job.finish(JobState::Done);
+        }
+        let progress = job.progress();
+        let finished: Vec<String> = jobs.iter().filter(|j| j.state != JobState::Running).map(|j| j.id.clone()).collect();
+        let stale = &finished[..finished.len().saturating_sub(KEEP_FINISHED)];
+        jobs.retain(|j| !stale.contains(&j.id));
+        self.save(&jobs);
+        Some(progress)
+    }
+
+    /// Works through queued jobs for as long as the app runs, calling `on_progress`
+    /// after every batch.
+    pub async fn run(
+        &self,
+        blink_client: &Arc<Mutex<BlinkClient>>,
+        media_index: &MediaIndex,
+        settings: impl Fn() -> Settings,
+        on_progress: impl Fn(&DeleteProgress),
+    ) {
+        loop {
+            let settings = settings();
+            let Some((job_id, batch)) = self.next_batch(settings.delete_batch_size) else {
+                self.wake.notified().await;
+                continue;
+            };
+            let outcome = if batch.is_empty() {
+                Ok(Vec::new())
+            } else {
+                let client = crate::shared::snapshot(blink_client).await;
+                client.delete_media_verified(&batch).await.map_err(|e| e.to_string())
+            };
+            if let Ok(results) = &outcome {
+                if let Err(e) = media_index.mark_deleted(results) {
+                    eprintln!("Failed to mark deleted media in index: {}", e);
+                }
+            }
+            if let Some(progress) = self.record(&job_id, &batch, outcome) {
+                on_progress(&progress);
+            }
+            tokio::time::sleep(Duration::from_secs(settings.delete_batch_delay_secs)).await;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+//! Which device family's routes actually answer for a camera. Newer hardware, the Mini 2
+//! among it, can report an owl product type yet only answer on the camera routes, so when
+//! the expected route gives 404 or 422 the client tries the other families and the one that
+//! worked is remembered here per device. A firmware change forgets it, since an update can
+//! move the device back.
+
+use crate::blink::DeviceFamily;
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+pub const DEVICE_ROUTES_FILE: &str = "device_routes.json";
+
+static ROUTES: OnceLock<DeviceRoutes> = OnceLock::new();
+
+/// One family tried while probing, and what it answered.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteAttempt {
+    pub family: DeviceFamily,
+    /// Status of a failed attempt; `None` for the family that answered.
+    pub status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RouteRecord {
+    pub camera_id: i64,
+    /// The `type` the device reports.
+    pub product_type: String,
+    /// The family whose routes answered.
+    pub family: DeviceFamily,
+    /// What the probe tried, in order, ending with the one that worked.
+    pub attempts: Vec<RouteAttempt>,
+    /// RFC 3339
+    pub learned_at: String,
+}
+
+struct DeviceRoutes {
+    path: PathBuf,
+    records: Mutex<Vec<RouteRecord>>,
+}
+
+impl DeviceRoutes {
+    fn save(&self, records: &[RouteRecord]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(records)?)?;
+        Ok(())
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Vec<RouteRecord>) -> bool) {
+        let mut records = self.records.lock().unwrap();
+        if change(&mut records) {
+            if let Err(e) = self.save(&records) {
+                eprintln!("Failed to save device routes: {}", e);
+            }
+        }
+    }
+}
+
+/// Loads remembered routes from `path`. Until this is called nothing is remembered and
+/// every call probes from the product type's own family.
+pub fn init(path: PathBuf) {
+    let records = fs::read_to_string(&path)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+    let _ = ROUTES.set(DeviceRoutes { path, records: Mutex::new(records) });
+}
+
+/// The remembered route for `camera_id`, if it was learned for the same product type.
+pub fn lookup(camera_id: i64, product_type: &str) -> Option<RouteRecord> {
+    let routes = ROUTES.get()?;
+    let records = routes.records.lock().unwrap();
+    records.iter().find(|r| r.camera_id == camera_id && r.product_type == product_type).cloned()
+}
+
+/// Every remembered route.
+pub fn report() -> Vec<RouteRecord> {
+    ROUTES.get().map(|routes| routes.records.lock().unwrap().clone()).unwrap_or_default()
+}
+
+pub(crate) fn remember(camera_id: i64, product_type: &str, family: DeviceFamily, attempts: Vec<RouteAttempt>) {
+    let Some(routes) = ROUTES.get() else { return };
+    eprintln!("Camera {} ({}) answers on the {} routes; remembering that", camera_id, product_type, family.as_str());
+    let record = RouteRecord {
+        camera_id,
+        product_type: product_type.to_string(),
+        family,
+        attempts,
+        learned_at: Utc::now().to_rfc3339(),
+    };
+    routes.update(|records| {
+        records.retain(|r| r.camera_id != camera_id);
+        records.push(record);
+        true
+    });
+}
+
+/// Drops what was learned for `camera_id`, so the next call probes again.
+pub fn forget(camera_id: i64) {
+    let Some(routes) = ROUTES.get() else { return };
+    routes.update(|records| {
+        let before = records.len();
+        records.retain(|r| r.camera_id != camera_id);
+        records.len() != before
+    });
+}
@@ -0,0 +1,159 @@
+//! Support probes for "clips won't play" reports: fetches the first byte of a clip and its
+//! thumbnail the way the proxies would and reports what came back, without downloading
+//! either. Only available with the `diagnostics_enabled` setting.
+
+use crate::blink::{BlinkClient, MediaItem};
+use crate::device_routes::{self, RouteRecord};
+use crate::shared;
+use chrono::{DateTime, Utc};
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE};
+use secrecy::ExposeSecret;
+use serde::Serialize;
+
+/// What one probed URL returned. `problem` sums up the first thing that looks wrong.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    pub url: String,
+    /// Whether the host is on the media allow-list; URLs that fail it are not requested.
+    pub host_allowed: bool,
+    /// Whether a Blink access token was sent with the request.
+    pub auth_attached: bool,
+    pub status: Option<u16>,
+    pub content_type: Option<String>,
+    pub content_length: Option<u64>,
+    /// Full size from `Content-Range`, when the server honoured the range request.
+    pub total_bytes: Option<u64>,
+    /// Where the request ended up after redirects.
+    pub final_url: Option<String>,
+    /// Time until the response headers arrived.
+    pub latency_ms: Option<u64>,
+    pub problem: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaDiagnosis {
+    pub media_id: i64,
+    pub checked_at: DateTime<Utc>,
+    /// `None` when the item has no clip or thumbnail URL.
+    pub media: Option<ProbeResult>,
+    pub thumbnail: Option<ProbeResult>,
+    /// The other family's routes the clip's camera turned out to answer on, if it needed
+    /// one.
+    pub device_route: Option<RouteRecord>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ProbeKind {
+    Clip,
+    Thumbnail,
+}
+
+impl ProbeKind {
+    fn expected_type(self) -> &'static str {
+        match self {
+            ProbeKind::Clip => "video/",
+            ProbeKind::Thumbnail => "image/",
+        }
+    }
+}
+
+fn header_u64(res: &reqwest::Response, name: reqwest::header::HeaderName) -> Option<u64> {
+    res.headers().get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok())
+}
+
+/// Total size from a `Content-Range: bytes 0-0/12345` header.
+fn range_total(res: &reqwest::Response) -> Option<u64> {
+    res.headers().get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit('/').next())
+        .and_then(|total| total.parse().ok())
+}
+
+async fn probe(client: &BlinkClient, path: &str, kind: ProbeKind) -> ProbeResult {
+    let url = match client.resolve_url(path) {
+        Ok(mut url) => {
+            // Same rule as `BlinkClient::get_thumbnail`: extensionless thumbnails need a `ts` query.
+            let last_segment = url.path().rsplit('/').next().unwrap_or_default();
+            if kind == ProbeKind::Thumbnail && url.query().is_none() && !last_segment.contains('.') {
+                url.set_query(Some(&format!("ts={}", Utc::now().timestamp())));
+            }
+            url.to_string()
+        }
+        Err(_) => path.to_string(),
+    };
+    let token = client.token().ok();
+    let mut result = ProbeResult {
+        url: url.clone(),
+        host_allowed: false,
+        auth_attached: token.is_some(),
+        status: None,
+        content_type: None,
+        content_length: None,
+        total_bytes: None,
+        final_url: None,
+        latency_ms: None,
+        problem: None,
+    };
+    let parsed = match shared::validate_media_url(&url) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            result.problem = Some(e);
+            return result;
+        }
+    };
+    result.host_allowed = true;
+
+    let mut req = client.media_client.get(parsed);
+    if let Some(token) = token {
+        req = req.header("Authorization", format!("Bearer {}", token.expose_secret()));
+    }
+    req = match kind {
+        ProbeKind::Clip => req.header(RANGE, "bytes=0-0"),
+        ProbeKind::Thumbnail => req.header(ACCEPT, "image/*"),
+    };
+
+    let started = std::time::Instant::now();
+    let res = match req.send().await {
+        Ok(res) => res,
+        Err(e) => {
+            result.latency_ms = Some(started.elapsed().as_millis() as u64);
+            result.problem = Some(format!("Request failed: {}", e));
+            return result;
+        }
+    };
+    result.latency_ms = Some(started.elapsed().as_millis() as u64);
+    result.status = Some(res.status().as_u16());
+    result.content_type = res.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+    result.content_length = header_u64(&res, CONTENT_LENGTH);
+    result.total_bytes = range_total(&res);
+    result.final_url = Some(res.url().to_string());
+
+    let size = result.total_bytes.or(result.content_length);
+    result.problem = if !res.status().is_success() {
+        Some(format!("HTTP {}", res.status()))
+    } else if size == Some(0) {
+        Some("Empty response".to_string())
+    } else if result.content_type.as_deref().is_some_and(|t| !t.starts_with(kind.expected_type())) {
+        Some(format!("Unexpected content type {}", result.content_type.as_deref().unwrap_or_default()))
+    } else {
+        None
+    };
+    // A clip that ignores the range request still answers with its full body; don't read it.
+    drop(res);
+    result
+}
+
+/// Probes the clip (first byte only) and the thumbnail of `item`.
+pub async fn diagnose_media(client: &BlinkClient, item: &MediaItem) -> MediaDiagnosis {
+    let media = match item.media.as_deref().filter(|m| !m.is_empty()) {
+        Some(path) => Some(probe(client, path, ProbeKind::Clip).await),
+        None => None,
+    };
+    let thumbnail = match item.thumbnail.as_deref().filter(|t| !t.is_empty()) {
+        Some(path) => Some(probe(client, path, ProbeKind::Thumbnail).await),
+        None => None,
+    };
+    let device_route = item.device_id
+        .and_then(|id| device_routes::report().into_iter().find(|r| r.camera_id == id));
+    MediaDiagnosis { media_id: item.id, checked_at: Utc::now(), media, thumbnail, device_route }
+}
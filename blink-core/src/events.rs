@@ -0,0 +1,327 @@
+//! Persistent log of what the server noticed or did (arm changes, schedule runs, firmware
+//! updates), numbered so a consumer that was away can replay everything after the last
+//! sequence number it saw. Sequence numbers are never reused, even after pruning.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use tokio::sync::broadcast;
+
+pub const EVENTS_FILE: &str = "events.sqlite";
+pub const DEFAULT_REPLAY_LIMIT: u32 = 500;
+pub const MAX_REPLAY_LIMIT: u32 = 5000;
+pub const PRUNE_INTERVAL_SECS: u64 = 60 * 60;
+/// Events a slow subscriber can fall behind by before it starts missing them.
+const SUBSCRIBER_BUFFER: usize = 256;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub seq: i64,
+    pub ts: DateTime<Utc>,
+    /// `arm`, `rearm`, `schedule_run` or `firmware_changed`.
+    pub kind: String,
+    pub data: Value,
+}
+
+/// A no-op if the database can't be opened; the server runs fine without history.
+pub struct EventLog {
+    path: PathBuf,
+    conn: std::sync::Mutex<Option<Connection>>,
+    live: broadcast::Sender<Event>,
+}
+
+fn open(path: &Path) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS events (
+            seq INTEGER PRIMARY KEY AUTOINCREMENT,
+            ts TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            data TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS events_ts ON events(ts);",
+    )?;
+    Ok(conn)
+}
+
+fn row_to_event(row: &rusqlite::Row<'_>) -> rusqlite::Result<Event> {
+    let ts: String = row.get(1)?;
+    let data: String = row.get(3)?;
+    Ok(Event {
+        seq: row.get(0)?,
+        ts: DateTime::parse_from_rfc3339(&ts).map(|t| t.with_timezone(&Utc)).unwrap_or_default(),
+        kind: row.get(2)?,
+        data: serde_json::from_str(&data).unwrap_or(Value::Null),
+    })
+}
+
+impl EventLog {
+    pub fn new(path: PathBuf) -> Self {
+        let conn = match open(&path) {
+            Ok(conn) => Some(conn),
+            Err(e) => {
+                eprintln!("Failed to open event log {}: {}", path.display(), e);
+                None
+            }
+        };
+        let (live, _) = broadcast::channel(SUBSCRIBER_BUFFER);
+        Self { path, conn: std::sync::Mutex::new(conn), live }
+    }
+
+    /// Events as they are recorded, for pushing them elsewhere (webhooks). Only events
+    /// that made it into the log are sent, so each carries its sequence number.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.live.subscribe()
+    }
+
+    /// Everything after `after_seq`: what the log kept, then events as they are recorded.
+    /// Subscribes before reading the log so nothing recorded in between is missed.
+    pub fn feed(&self, after_seq: i64) -> EventFeed {
+        EventFeed { live: self.subscribe(), last_seq: after_seq, backlog: VecDeque::new(), caught_up: false }
+    }
+
+    /// Appends an event; failures are logged rather than returned so callers never fail
+    /// because history couldn't be written.
+    pub fn record(&self, kind: &str, data: impl Serialize) {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return };
+        let ts = Utc::now();
+        let result = serde_json::to_value(&data)
+            .map_err(anyhow::Error::from)
+            .and_then(|data| {
+                conn.execute(
+                    "INSERT INTO events (ts, kind, data) VALUES (?1, ?2, ?3)",
+                    params![ts.to_rfc3339_opts(SecondsFormat::Millis, true), kind, data.to_string()],
+                )?;
+                Ok(Event { seq: conn.last_insert_rowid(), ts, kind: kind.to_string(), data })
+            });
+        match result {
+            // No receivers is fine; nobody is listening yet.
+            Ok(event) => { let _ = self.live.send(event); }
+            Err(e) => eprintln!("Failed to write {} event to {}: {}", kind, self.path.display(), e),
+        }
+    }
+
+    /// Events with a sequence number above `after_seq`, oldest first, at most `limit`.
+    pub fn replay(&self, after_seq: i64, limit: u32) -> Result<Vec<Event>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return Ok(Vec::new()) };
+        let mut stmt = conn.prepare("SELECT seq, ts, kind, data FROM events WHERE seq > ?1 ORDER BY seq LIMIT ?2")?;
+        let rows = stmt.query_map(params![after_seq, limit.clamp(1, MAX_REPLAY_LIMIT)], row_to_event)?;
+        Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+    }
+
+    /// Events of the given kinds recorded from `from` up to (not including) `to`, oldest
+    /// first.
+    pub fn between(&self, from: DateTime<Utc>, to: DateTime<Utc>, kinds: &[&str]) -> Result<Vec<Event>> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return Ok(Vec::new()) };
+        let mut stmt = conn.prepare("SELECT seq, ts, kind, data FROM events WHERE ts >= ?1 AND ts < ?2 ORDER BY seq")?;
+        let rows = stmt.query_map(
+            params![from.to_rfc3339_opts(SecondsFormat::Millis, true), to.to_rfc3339_opts(SecondsFormat::Millis, true)],
+            row_to_event,
+        )?;
+        let mut events = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        events.retain(|event| kinds.contains(&event.kind.as_str()));
+        Ok(events)
+    }
+
+    /// Drops events older than `max_age_days` and all but the newest `max_rows`. Returns
+    /// how many were removed.
+    pub fn prune(&self, max_rows: u64, max_age_days: u32) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let Some(conn) = conn.as_ref() else { return Ok(0) };
+        let cutoff = (Utc::now() - Duration::days(i64::from(max_age_days))).to_rfc3339_opts(SecondsFormat::Millis, true);
+        let mut removed = conn.execute("DELETE FROM events WHERE ts < ?1", params![cutoff])?;
+        removed += conn.execute(
+            "DELETE FROM events WHERE seq <= (SELECT seq FROM events ORDER BY seq DESC LIMIT 1 OFFSET ?1)",
+            params![max_rows as i64],
+        )?;
+        Ok(removed)
+    }
+}
+
+/// See [`EventLog::feed`]. Each event comes out once, in `seq` order, even when it was
+/// both read from the log and broadcast.
+pub struct EventFeed {
+    live: broadcast::Receiver<Event>,
+    last_seq: i64,
+    backlog: VecDeque<Event>,
+    caught_up: bool,
+}
+
+impl EventFeed {
+    /// The next event, or `None` once the log is gone. A feed that falls behind the
+    /// broadcast goes back to the log for what it missed. Cancel safe, so it can sit in a
+    /// `select!` next to the socket it feeds.
+    pub async fn recv(&mut self, log: &EventLog) -> Result<Option<Event>> {
+        loop {
+            if let Some(event) = self.backlog.pop_front() {
+                self.last_seq = event.seq;
+                return Ok(Some(event));
+            }
+            if !self.caught_up {
+                let page = log.replay(self.last_seq, MAX_REPLAY_LIMIT)?;
+                self.caught_up = page.len() < MAX_REPLAY_LIMIT as usize;
+                self.backlog.extend(page);
+                continue;
+            }
+            match self.live.recv().await {
+                Ok(event) if event.seq > self.last_seq => {
+                    self.last_seq = event.seq;
+                    return Ok(Some(event));
+                }
+                // Already sent from the log.
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(_)) => self.caught_up = false,
+                Err(broadcast::error::RecvError::Closed) => return Ok(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> (tempfile::TempDir, EventLog) {
+        let dir = tempfile::tempdir().unwrap();
+        let log = EventLog::new(dir.path().join(EVENTS_FILE));
+        (dir, log)
+    }
+
+    fn seqs(events: &[Event]) -> Vec<i64> {
+        events.iter().map(|e| e.seq).collect()
+    }
+
+    /// Everything after `after_seq`, fetched a page at a time the way a consumer catches up.
+    fn replay_all(log: &EventLog, mut after_seq: i64, page: u32) -> Vec<Event> {
+        let mut all = Vec::new();
+        loop {
+            let batch = log.replay(after_seq, page).unwrap();
+            let Some(last) = batch.last() else { return all };
+            after_seq = last.seq;
+            all.extend(batch);
+        }
+    }
+
+    #[test]
+    fn a_thousand_events_prune_and_replay_across_the_boundary() {
+        let (_dir, log) = log();
+        for i in 1..=1000 {
+            log.record("arm", serde_json::json!({ "n": i }));
+        }
+        assert_eq!(seqs(&replay_all(&log, 0, 128)), (1..=1000).collect::<Vec<_>>());
+
+        assert_eq!(log.prune(300, 30).unwrap(), 700);
+        // A consumer that last saw an event now pruned resumes at the oldest one kept.
+        let resumed = replay_all(&log, 500, 128);
+        assert_eq!(seqs(&resumed), (701..=1000).collect::<Vec<_>>());
+        assert_eq!(resumed[0].data, serde_json::json!({ "n": 701 }));
+        // Right at the boundary and inside the kept range.
+        assert_eq!(log.replay(700, 1).unwrap()[0].seq, 701);
+        assert_eq!(seqs(&log.replay(998, 10).unwrap()), vec![999, 1000]);
+        assert!(log.replay(1000, 10).unwrap().is_empty());
+
+        // Sequence numbers keep counting after pruning, so `after_seq` stays meaningful.
+        log.record("rearm", serde_json::json!({}));
+        assert_eq!(seqs(&log.replay(1000, 10).unwrap()), vec![1001]);
+        assert_eq!(log.prune(0, 30).unwrap(), 301);
+        log.record("rearm", serde_json::json!({}));
+        assert_eq!(seqs(&log.replay(0, 10).unwrap()), vec![1002]);
+    }
+
+    #[test]
+    fn pruning_by_age_keeps_recent_events() {
+        let (_dir, log) = log();
+        {
+            let conn = log.conn.lock().unwrap();
+            let conn = conn.as_ref().unwrap();
+            for days in [40, 31, 29, 1] {
+                let ts = (Utc::now() - Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true);
+                conn.execute("INSERT INTO events (ts, kind, data) VALUES (?1, 'arm', ?2)", params![ts, days.to_string()]).unwrap();
+            }
+        }
+        assert_eq!(log.prune(1000, 30).unwrap(), 2);
+        let kept: Vec<_> = log.replay(0, 10).unwrap().into_iter().map(|e| e.data).collect();
+        assert_eq!(kept, vec![serde_json::json!(29), serde_json::json!(1)]);
+    }
+
+    #[test]
+    fn replay_limits_are_clamped() {
+        let (_dir, log) = log();
+        for _ in 0..3 {
+            log.record("arm", serde_json::json!({}));
+        }
+        assert_eq!(log.replay(0, 0).unwrap().len(), 1);
+        assert_eq!(log.replay(0, u32::MAX).unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_events_with_their_sequence_numbers() {
+        let (_dir, log) = log();
+        log.record("arm", serde_json::json!({ "before": true }));
+        let mut live = log.subscribe();
+        log.record("firmware_changed", serde_json::json!({ "camera": 5 }));
+        let event = live.recv().await.unwrap();
+        assert_eq!((event.seq, event.kind.as_str()), (2, "firmware_changed"));
+        assert_eq!(event.data["camera"], 5);
+    }
+
+    #[tokio::test]
+    async fn a_feed_sends_each_event_once_across_the_replay_seam() {
+        let (_dir, log) = log();
+        log.record("arm", serde_json::json!({ "n": 1 }));
+        let mut feed = log.feed(1);
+        // Recorded after subscribing and before the log is read, so both the replay and
+        // the broadcast carry these.
+        log.record("arm", serde_json::json!({ "n": 2 }));
+        log.record("arm", serde_json::json!({ "n": 3 }));
+        let mut seen = Vec::new();
+        for _ in 0..2 {
+            seen.push(feed.recv(&log).await.unwrap().unwrap().seq);
+        }
+        log.record("rearm", serde_json::json!({ "n": 4 }));
+        let live = feed.recv(&log).await.unwrap().unwrap();
+        seen.push(live.seq);
+        assert_eq!(seen, vec![2, 3, 4]);
+        assert_eq!(live.kind, "rearm");
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(50), feed.recv(&log)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn a_feed_that_lags_catches_up_from_the_log() {
+        let (_dir, log) = log();
+        let mut feed = log.feed(0);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(10), feed.recv(&log)).await.is_err());
+        let total = SUBSCRIBER_BUFFER as i64 + 10;
+        for n in 1..=total {
+            log.record("arm", serde_json::json!({ "n": n }));
+        }
+        let mut seen = Vec::new();
+        for _ in 0..total {
+            seen.push(feed.recv(&log).await.unwrap().unwrap().seq);
+        }
+        assert_eq!(seen, (1..=total).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn an_unopenable_log_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        // A directory where the database file should be.
+        let path = dir.path().join(EVENTS_FILE);
+        std::fs::create_dir(&path).unwrap();
+        let log = EventLog::new(path);
+        log.record("arm", serde_json::json!({}));
+        assert!(log.replay(0, 10).unwrap().is_empty());
+        assert_eq!(log.prune(0, 0).unwrap(), 0);
+    }
+}
@@ -0,0 +1,507 @@
+use crate::blink::{parse_blink_timestamp, BlinkClient, MediaItem};
+use crate::clip_store::ClipStore;
+use crate::shared;
+use anyhow::{anyhow, Result};
+use async_zip::tokio::write::ZipFileWriter;
+use async_zip::{Compression, ZipDateTime, ZipEntryBuilder};
+use futures_util::io::AsyncWriteExt as _;
+use futures_util::StreamExt;
+use secrecy::ExposeSecret;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::io::AsyncWrite;
+use tokio::sync::Mutex;
+
+/// Placeholders understood by `download_filename_template`. `{date}` and `{time}` are
+/// `YYYYMMDD` and `HHMMSS` in the clip's network time zone, falling back to UTC.
+pub const FILENAME_PLACEHOLDERS: &[&str] = &["camera", "network", "date", "time", "id"];
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "{camera}_{date}_{time}";
+const MAX_TEMPLATE_LEN: usize = 200;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportCompression {
+    /// Clips are already compressed video, so storing them is the fast default.
+    #[default]
+    Store,
+    Deflate,
+}
+
+impl From<ExportCompression> for Compression {
+    fn from(value: ExportCompression) -> Self {
+        match value {
+            ExportCompression::Store => Compression::Stored,
+            ExportCompression::Deflate => Compression::Deflate,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportEntryStatus {
+    Ok,
+    /// Blink answered 404; the clip was left out of the archive.
+    Missing,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportEntry {
+    pub file: Option<String>,
+    pub status: ExportEntryStatus,
+    pub error: Option<String>,
+    pub bytes: u64,
+    pub media: MediaItem,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportSummary {
+    pub written: usize,
+    pub skipped: usize,
+    pub entries: Vec<ExportEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportProgress {
+    pub file_index: usize,
+    pub file_count: usize,
+    pub file_name: String,
+    pub received: u64,
+    pub total: Option<u64>,
+    pub files_done: usize,
+    pub finished: bool,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    exported_at: String,
+    entries: &'a [ExportEntry],
+}
+
+fn sanitize(part: &str) -> String {
+    let cleaned: String = part.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    let trimmed = cleaned.trim_matches('-');
+    if trimmed.is_empty() { "clip".to_string() } else { trimmed.to_string() }
+}
+
+/// Splits a template into literal text and placeholder names.
+fn template_parts(template: &str) -> Result<Vec<(bool, &str)>> {
+    let mut parts = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            return Err(anyhow!("Unmatched '}}' in file name template"));
+        }
+        let close = rest[open..].find('}').ok_or_else(|| anyhow!("Unclosed '{{' in file name template"))? + open;
+        parts.push((false, &rest[..open]));
+        parts.push((true, &rest[open + 1..close]));
+        rest = &rest[close + 1..];
+    }
+    parts.push((false, rest));
+    Ok(parts)
+}
+
+/// Checks that a file name template only uses known placeholders and that its literal
+/// text can't escape the download folder.
+pub fn validate_filename_template(template: &str) -> Result<()> {
+    if template.trim().is_empty() {
+        return Err(anyhow!("File name template must not be empty"));
+    }
+    if template.len() > MAX_TEMPLATE_LEN {
+        return Err(anyhow!("File name template is longer than {} characters", MAX_TEMPLATE_LEN));
+    }
+    let mut has_placeholder = false;
+    for (is_placeholder, part) in template_parts(template)? {
+        if is_placeholder {
+            if !FILENAME_PLACEHOLDERS.contains(&part) {
+                return Err(anyhow!("Unknown placeholder {{{}}}; supported: {}", part, FILENAME_PLACEHOLDERS.iter()
+                    .map(|p| format!("{{{}}}", p))
+                    .collect::<Vec<_>>()
+                    .join(", ")));
+            }
+            has_placeholder = true;
+        } else if let Some(c) = part.chars().find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ' '))) {
+            return Err(anyhow!("File name template may not contain '{}'", c));
+        }
+    }
+    if !has_placeholder {
+        return Err(anyhow!("File name template needs at least one placeholder, or every clip gets the same name"));
+    }
+    Ok(())
+}
+
+/// When the clip was recorded, in the network's own time zone if Blink reported one.
+fn clip_time(item: &MediaItem) -> Option<(DateTime<Utc>, Option<Tz>)> {
+    let created = item.created_at.as_deref().and_then(parse_blink_timestamp)?;
+    Some((created, item.time_zone.as_deref().and_then(|tz| tz.parse::<Tz>().ok())))
+}
+
+/// Renders `template` for one clip, without extension. Every placeholder value is
+/// sanitized, so camera names containing path separators stay inside the folder. Falls
+/// back to the default template if `template` is invalid.
+pub fn file_stem(item: &MediaItem, template: &str) -> String {
+    let template = if validate_filename_template(template).is_ok() { template } else { DEFAULT_FILENAME_TEMPLATE };
+    let time = clip_time(item);
+    let format = |fmt: &str| match time {
+        Some((created, Some(tz))) => created.with_timezone(&tz).format(fmt).to_string(),
+        Some((created, None)) => created.format(fmt).to_string(),
+        None => "unknown".to_string(),
+    };
+    let rendered: String = template_parts(template)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(is_placeholder, part)| match (is_placeholder, part) {
+            (false, text) => text.to_string(),
+            (true, "camera") => sanitize(item.device_name.as_deref().unwrap_or("camera")),
+            (true, "network") => sanitize(item.network_name.as_deref().unwrap_or("network")),
+            (true, "date") => format("%Y%m%d"),
+            (true, "time") => format("%H%M%S"),
+            (true, "id") => item.id.to_string(),
+            (true, _) => String::new(),
+        })
+        .collect();
+    let trimmed = rendered.trim().trim_matches('.');
+    if trimmed.is_empty() { "clip".to_string() } else { trimmed.to_string() }
+}
+
+/// Rendered `{stem}.mp4`, with a numeric suffix when two clips collide.
+pub fn entry_name(item: &MediaItem, template: &str, used: &mut HashSet<String>) -> String {
+    let base = file_stem(item, template);
+    let mut name = format!("{}.mp4", base);
+    let mut n = 2;
+    while !used.insert(name.clone()) {
+        name = format!("{}_{}.mp4", base, n);
+        n += 1;
+    }
+    name
+}
+
+/// A path in `dir` for the clip that neither exists on disk nor is in `reserved`
+/// (downloads still in flight). The chosen path is added to `reserved`.
+pub fn unique_path(dir: &Path, item: &MediaItem, template: &str, reserved: &mut HashSet<String>) -> PathBuf {
+    let base = file_stem(item, template);
+    let mut n = 1;
+    loop {
+        let name = if n == 1 { format!("{}.mp4", base) } else { format!("{}_{}.mp4", base, n) };
+        let path = dir.join(name);
+        let key = path.to_string_lossy().to_string();
+        if !path.exists() && !reserved.contains(&key) && !Path::new(&format!("{}.part", key)).exists() {
+            reserved.insert(key);
+            return path;
+        }
+        n += 1;
+    }
+}
+
+/// Where the metadata sidecar for a downloaded clip goes: the clip path with `.json`.
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    path.with_extension("json")
+}
+
+/// Stamps a finished download with the clip's recording time as its modified time and,
+/// when `sidecar` is set, writes the full metadata next to it.
+pub fn apply_clip_metadata(path: &Path, item: &MediaItem, sidecar: bool) -> std::io::Result<()> {
+    if let Some((created, _)) = clip_time(item) {
+        let file = std::fs::File::options().write(true).open(path)?;
+        file.set_modified(SystemTime::from(created))?;
+    }
+    if sidecar {
+        let json = serde_json::to_vec_pretty(item)?;
+        std::fs::write(sidecar_path(path), json)?;
+    }
+    Ok(())
+}
+
+pub async fn fetch_clip(blink_client: &Arc<Mutex<BlinkClient>>, media_path: &str) -> Result<reqwest::Response, String> {
+    let (token, full_url, http_client) = {
+        let mut client = blink_client.lock().await;
+        let _ = shared::refresh_and_persist(&mut client).await;
+        let token = client.token().map_err(|e| e.to_string())?.clone();
+        let full_url = client.resolve_url(media_path).map_err(|e| e.to_string())?;
+        (token, full_url, client.media_client.clone())
+    };
+    let url = shared::validate_media_url(full_url.as_str())?;
+    http_client.get(url)
+        .header("Authorization", format!("Bearer {}", token.expose_secret()))
+        .send()
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Streams one clip to `path` through a `.part` file, so an interrupted download never
+/// leaves a truncated clip behind under the final name.
+pub async fn download_to_file(blink_client: &Arc<Mutex<BlinkClient>>, media_path: &str, path: &Path) -> Result<()> {
+    let res = fetch_clip(blink_client, media_path).await.map_err(|e| anyhow!(e))?;
+    if !res.status().is_success() {
+        return Err(anyhow!("Blink returned {}", res.status()));
+    }
+    let partial = PathBuf::from(format!("{}.part", path.display()));
+    let mut file = tokio::fs::File::create(&partial).await?;
+    let mut stream = res.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        tokio::io::AsyncWriteExt::write_all(&mut file, &chunk?).await?;
+    }
+    tokio::io::AsyncWriteExt::flush(&mut file).await?;
+    drop(file);
+    tokio::fs::rename(&partial, path).await?;
+    Ok(())
+}
+
+/// Streams one clip into `store` under `key` without holding it in memory. Returns the
+/// bytes stored.
+pub async fn download_to_store(blink_client: &Arc<Mutex<BlinkClient>>, media_path: &str, store: &dyn ClipStore, key: &str) -> Result<u64> {
+    let res = fetch_clip(blink_client, media_path).await.map_err(|e| anyhow!(e))?;
+    if !res.status().is_success() {
+        return Err(anyhow!("Blink returned {}", res.status()));
+    }
+    let len = res.content_length();
+    let stream = res.bytes_stream().map(|chunk| chunk.map_err(std::io::Error::other)).boxed();
+    store.put(key, stream, len).await
+}
+
+/// Downloads one clip into a new archive entry. Download problems are reported in the
+/// returned entry; only errors writing the archive itself are returned as `Err`.
+async fn write_clip_entry<W, F>(
+    zip: &mut ZipFileWriter<W>,
+    blink_client: &Arc<Mutex<BlinkClient>>,
+    item: &MediaItem,
+    name: String,
+    compression: ExportCompression,
+    progress: &mut ExportProgress,
+    on_progress: &mut F,
+) -> Result<ExportEntry>
+where
+    W: AsyncWrite + Unpin,
+    F: FnMut(&ExportProgress),
+{
+    let mut entry = ExportEntry {
+        file: None,
+        status: ExportEntryStatus::Failed,
+        error: None,
+        bytes: 0,
+        media: item.clone(),
+    };
+
+    let Some(media_path) = item.media.as_deref() else {
+        entry.error = Some("Item has no media URL".to_string());
+        return Ok(entry);
+    };
+    let res = match fetch_clip(blink_client, media_path).await {
+        Ok(res) => res,
+        Err(e) => {
+            entry.error = Some(e);
+            return Ok(entry);
+        }
+    };
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        entry.status = ExportEntryStatus::Missing;
+        entry.error = Some(res.status().to_string());
+        return Ok(entry);
+    }
+    if !res.status().is_success() {
+        entry.error = Some(format!("Download failed: {}", res.status()));
+        return Ok(entry);
+    }
+
+    progress.total = res.content_length();
+    let mut builder = ZipEntryBuilder::new(name.clone().into(), compression.into());
+    if let Some((created, _)) = clip_time(item) {
+        builder = builder.last_modification_date(ZipDateTime::from_chrono(&created));
+    }
+    let mut writer = zip.write_entry_stream(builder).await?;
+    let mut stream = res.bytes_stream();
+    let mut stream_error = None;
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => {
+                writer.write_all(&chunk).await?;
+                progress.received += chunk.len() as u64;
+                on_progress(progress);
+            }
+            Err(e) => {
+                stream_error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    // Close the entry even after a mid-stream failure so the archive stays valid.
+    writer.close().await?;
+
+    entry.file = Some(name);
+    entry.bytes = progress.received;
+    match stream_error {
+        Some(e) => entry.error = Some(format!("Truncated: {}", e)),
+        None => entry.status = ExportEntryStatus::Ok,
+    }
+    Ok(entry)
+}
+
+/// Streams each clip from Blink straight into a ZIP written to `out`, followed by a
+/// `manifest.json` with the original metadata and the outcome of every item. Entries are
+/// named by `template` (see `file_stem`).
+/// Clips that fail are recorded in the manifest instead of aborting the archive.
+pub async fn write_clips_zip<W, F>(
+    blink_client: &Arc<Mutex<BlinkClient>>,
+    items: &[MediaItem],
+    template: &str,
+    compression: ExportCompression,
+    out: W,
+    mut on_progress: F,
+) -> Result<ExportSummary>
+where
+    W: AsyncWrite + Unpin,
+    F: FnMut(&ExportProgress),
+{
+    let mut zip = ZipFileWriter::with_tokio(out);
+    let mut used_names = HashSet::new();
+    let mut entries = Vec::with_capacity(items.len());
+    let file_count = items.len();
+
+    for (file_index, item) in items.iter().enumerate() {
+        let name = entry_name(item, template, &mut used_names);
+        let mut progress = ExportProgress {
+            file_index,
+            file_count,
+            file_name: name.clone(),
+            received: 0,
+            total: None,
+            files_done: file_index,
+            finished: false,
+        };
+        let entry = write_clip_entry(&mut zip, blink_client, item, name, compression, &mut progress, &mut on_progress).await?;
+        entries.push(entry);
+        progress.files_done = file_index + 1;
+        on_progress(&progress);
+    }
+
+    let manifest = Manifest {
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        entries: &entries,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    zip.write_entry_whole(ZipEntryBuilder::new("manifest.json".to_string().into(), compression.into()), &manifest_json).await?;
+    let mut out = zip.close().await?.into_inner();
+    tokio::io::AsyncWriteExt::shutdown(&mut out).await?;
+
+    on_progress(&ExportProgress {
+        file_index: file_count,
+        file_count,
+        file_name: "manifest.json".to_string(),
+        received: manifest_json.len() as u64,
+        total: Some(manifest_json.len() as u64),
+        files_done: file_count,
+        finished: true,
+    });
+
+    let written = entries.iter().filter(|e| e.status == ExportEntryStatus::Ok).count();
+    Ok(ExportSummary {
+        written,
+        skipped: entries.len() - written,
+        entries,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(camera: &str) -> MediaItem {
+        serde_json::from_value(serde_json::json!({
+            "id": 7001,
+            "created_at": "2026-10-15T03:04:05+00:00",
+            "device_name": camera,
+            "network_name": "Home",
+        })).unwrap()
+    }
+
+    #[test]
+    fn the_default_template_is_camera_date_time() {
+        assert_eq!(file_stem(&item("Driveway"), DEFAULT_FILENAME_TEMPLATE), "Driveway_20261015_030405");
+    }
+
+    #[test]
+    fn dates_follow_the_network_time_zone() {
+        let mut clip = item("Driveway");
+        clip.time_zone = Some("America/New_York".to_string());
+        // 03:04 UTC is still the previous evening in New York.
+        assert_eq!(file_stem(&clip, "{date}_{time}"), "20261014_230405");
+        clip.time_zone = Some("Not/AZone".to_string());
+        assert_eq!(file_stem(&clip, "{date}_{time}"), "20261015_030405");
+    }
+
+    #[test]
+    fn path_separators_in_camera_names_stay_in_the_folder() {
+        for name in ["../../etc/passwd", "..\\..\\Windows\\System32", "Front/Back", "C:\\cam", "/", ".."] {
+            let stem = file_stem(&item(name), "{camera}_{id}");
+            assert!(!stem.contains('/') && !stem.contains('\\') && !stem.contains(':'), "{} -> {}", name, stem);
+            assert!(!stem.starts_with('.'), "{} -> {}", name, stem);
+            let dir = Path::new("/downloads");
+            assert_eq!(dir.join(format!("{}.mp4", stem)).parent(), Some(dir), "{} -> {}", name, stem);
+        }
+        assert_eq!(file_stem(&item("../../etc/passwd"), "{camera}"), "etc-passwd");
+        assert_eq!(file_stem(&item("///"), "{camera}"), "clip");
+    }
+
+    #[test]
+    fn templates_with_unknown_placeholders_or_unsafe_text_are_rejected() {
+        assert!(validate_filename_template("{camera}-{network}_{date} {time}.{id}").is_ok());
+        for bad in ["", "   ", "{bogus}", "{camera", "camera}", "no-placeholder", "../{camera}", "{camera}/{date}", "{camera}\\x", "{camera}:x"] {
+            assert!(validate_filename_template(bad).is_err(), "{:?}", bad);
+        }
+        assert!(validate_filename_template(&format!("{{id}}{}", "a".repeat(MAX_TEMPLATE_LEN))).is_err());
+        // A bad template in saved settings falls back to the default instead of failing.
+        assert_eq!(file_stem(&item("Driveway"), "../{bogus}"), "Driveway_20261015_030405");
+    }
+
+    #[test]
+    fn clips_without_a_timestamp_still_get_a_name() {
+        let mut clip = item("Driveway");
+        clip.created_at = None;
+        assert_eq!(file_stem(&clip, DEFAULT_FILENAME_TEMPLATE), "Driveway_unknown_unknown");
+    }
+
+    #[test]
+    fn colliding_names_get_a_numeric_suffix() {
+        let mut used = HashSet::new();
+        let names: Vec<_> = (0..3).map(|_| entry_name(&item("Driveway"), "{camera}", &mut used)).collect();
+        assert_eq!(names, ["Driveway.mp4", "Driveway_2.mp4", "Driveway_3.mp4"]);
+    }
+
+    #[test]
+    fn unique_paths_skip_existing_files_partials_and_reservations() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("Driveway.mp4"), b"").unwrap();
+        std::fs::write(dir.path().join("Driveway_2.mp4.part"), b"").unwrap();
+        let mut reserved = HashSet::new();
+        let first = unique_path(dir.path(), &item("Driveway"), "{camera}", &mut reserved);
+        let second = unique_path(dir.path(), &item("Driveway"), "{camera}", &mut reserved);
+        assert_eq!(first, dir.path().join("Driveway_3.mp4"));
+        assert_eq!(second, dir.path().join("Driveway_4.mp4"));
+    }
+
+    #[test]
+    fn downloads_keep_the_recording_time_and_a_sidecar() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("Driveway.mp4");
+        std::fs::write(&path, b"mp4").unwrap();
+        apply_clip_metadata(&path, &item("Driveway"), true).unwrap();
+
+        let modified: DateTime<Utc> = std::fs::metadata(&path).unwrap().modified().unwrap().into();
+        assert_eq!(modified.to_rfc3339(), "2026-10-15T03:04:05+00:00");
+        let sidecar: MediaItem = serde_json::from_slice(&std::fs::read(sidecar_path(&path)).unwrap()).unwrap();
+        assert_eq!(sidecar.id, 7001);
+        assert_eq!(sidecar.device_name.as_deref(), Some("Driveway"));
+
+        let other = dir.path().join("Other.mp4");
+        std::fs::write(&other, b"mp4").unwrap();
+        apply_clip_metadata(&other, &item("Other"), false).unwrap();
+        assert!(!sidecar_path(&other).exists());
+    }
+}
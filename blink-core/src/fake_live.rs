@@ -0,0 +1,341 @@
+//! A stand-in live source for working on the player without a camera or liveview minutes.
+//! With `BLINK_FAKE_LIVE=1` (or `fake` on a request in a debug build) `liveview::start`
+//! skips Blink and IMMI and relays a generated test pattern through the same channel,
+//! keepalive, stats and cleanup as a real session. The pattern is H.264 Baseline: one
+//! keyframe of colour bars a second, with a block marking the second, and skipped frames
+//! in between, so it decodes anywhere and stays small enough to loop forever.
+//! `BLINK_FAKE_LIVE_LATENCY_MS` holds every frame back that long and
+//! `BLINK_FAKE_LIVE_LOSS` drops that percentage of chunks before they reach the relay.
+
+use crate::immi::{ImmiFrame, MSG_VIDEO};
+use crate::mpegts;
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+use rand::Rng;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+pub const FAKE_LIVE_ENV: &str = "BLINK_FAKE_LIVE";
+pub const FAKE_LIVE_LATENCY_ENV: &str = "BLINK_FAKE_LIVE_LATENCY_MS";
+pub const FAKE_LIVE_LOSS_ENV: &str = "BLINK_FAKE_LIVE_LOSS";
+/// The IMMI server a fake session reports.
+pub const FAKE_SERVER: &str = "fake://test-pattern";
+
+const WIDTH_MBS: usize = 20;
+const HEIGHT_MBS: usize = 15;
+const FPS: u64 = 10;
+const VIDEO_PID: u16 = 0x100;
+/// TS packets per IMMI frame, about what cameras send.
+const PACKETS_PER_CHUNK: usize = 7;
+/// 90 kHz clock ticks per frame.
+const TICKS_PER_FRAME: u64 = 90_000 / FPS;
+/// Luma, Cb, Cr of the bars: white, yellow, cyan, green, magenta, red, blue, grey.
+const BARS: [(u8, u8, u8); 8] = [
+    (180, 128, 128),
+    (168, 44, 136),
+    (145, 147, 44),
+    (133, 63, 52),
+    (63, 193, 204),
+    (51, 109, 212),
+    (28, 212, 120),
+    (104, 128, 128),
+];
+
+/// Whether a session should use the fake source. The env var works in any build; the
+/// per-request flag only in debug builds, so a release server can't be talked into it.
+pub fn enabled(requested: bool) -> bool {
+    std::env::var(FAKE_LIVE_ENV).is_ok_and(|v| v == "1") || (cfg!(debug_assertions) && requested)
+}
+
+struct Impairment {
+    latency: Duration,
+    /// Percent of chunks dropped.
+    loss: f64,
+}
+
+impl Impairment {
+    fn from_env() -> Self {
+        let latency = std::env::var(FAKE_LIVE_LATENCY_ENV).ok().and_then(|v| v.parse().ok()).unwrap_or(0);
+        let loss = std::env::var(FAKE_LIVE_LOSS_ENV).ok().and_then(|v| v.parse::<f64>().ok()).unwrap_or(0.0);
+        Self { latency: Duration::from_millis(latency), loss: loss.clamp(0.0, 100.0) }
+    }
+}
+
+/// IMMI video frames carrying the test pattern, until the receiver is dropped.
+pub fn stream() -> BoxStream<'static, std::io::Result<ImmiFrame>> {
+    let (tx, rx) = mpsc::channel(64);
+    tokio::spawn(produce(tx, Impairment::from_env()));
+    futures_util::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|frame| (frame, rx)) }).boxed()
+}
+
+async fn produce(tx: mpsc::Sender<std::io::Result<ImmiFrame>>, impairment: Impairment) {
+    let mut muxer = Muxer::default();
+    let mut interval = tokio::time::interval(Duration::from_millis(1000 / FPS));
+    let mut sequence = 0u32;
+    tokio::time::sleep(impairment.latency).await;
+    for frame in 0u64.. {
+        interval.tick().await;
+        let ts = muxer.access_unit(frame);
+        for chunk in ts.chunks(PACKETS_PER_CHUNK * mpegts::PACKET_LEN) {
+            if impairment.loss > 0.0 && rand::thread_rng().gen_bool(impairment.loss / 100.0) {
+                continue;
+            }
+            sequence += 1;
+            let frame = ImmiFrame { msg_type: MSG_VIDEO, sequence, payload: Bytes::copy_from_slice(chunk) };
+            if tx.send(Ok(frame)).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Writes H.264 syntax elements MSB first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    current: u8,
+    used: u8,
+}
+
+impl BitWriter {
+    fn bit(&mut self, bit: bool) {
+        self.current = (self.current << 1) | bit as u8;
+        self.used += 1;
+        if self.used == 8 {
+            self.bytes.push(self.current);
+            self.current = 0;
+            self.used = 0;
+        }
+    }
+
+    fn bits(&mut self, value: u32, count: u8) {
+        for i in (0..count).rev() {
+            self.bit((value >> i) & 1 == 1);
+        }
+    }
+
+    /// Exp-Golomb, unsigned.
+    fn ue(&mut self, value: u32) {
+        let coded = value + 1;
+        let len = 32 - coded.leading_zeros() as u8;
+        self.bits(0, len - 1);
+        self.bits(coded, len);
+    }
+
+    fn align_zero(&mut self) {
+        while self.used != 0 {
+            self.bit(false);
+        }
+    }
+
+    fn byte(&mut self, value: u8) {
+        debug_assert_eq!(self.used, 0);
+        self.bytes.push(value);
+    }
+
+    /// rbsp_trailing_bits, then the NAL with emulation prevention applied.
+    fn finish(mut self, header: u8) -> Vec<u8> {
+        self.bit(true);
+        self.align_zero();
+        let mut nal = vec![0, 0, 0, 1, header];
+        let mut zeros = 0;
+        for byte in self.bytes {
+            if zeros >= 2 && byte <= 3 {
+                nal.push(3);
+                zeros = 0;
+            }
+            zeros = if byte == 0 { zeros + 1 } else { 0 };
+            nal.push(byte);
+        }
+        nal
+    }
+}
+
+fn access_unit_delimiter() -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.bits(7, 3); // primary_pic_type: any
+    w.finish(0x09)
+}
+
+fn sps() -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.bits(66, 8); // Baseline
+    w.bits(0xC0, 8); // constraint_set0 and 1
+    w.bits(30, 8); // level 3.0
+    w.ue(0); // seq_parameter_set_id
+    w.ue(0); // log2_max_frame_num_minus4
+    w.ue(2); // pic_order_cnt_type: output in decoding order
+    w.ue(1); // max_num_ref_frames
+    w.bit(false); // gaps_in_frame_num_value_allowed_flag
+    w.ue(WIDTH_MBS as u32 - 1);
+    w.ue(HEIGHT_MBS as u32 - 1);
+    w.bit(true); // frame_mbs_only_flag
+    w.bit(true); // direct_8x8_inference_flag
+    w.bit(false); // frame_cropping_flag
+    w.bit(false); // vui_parameters_present_flag
+    w.finish(0x67)
+}
+
+fn pps() -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.ue(0); // pic_parameter_set_id
+    w.ue(0); // seq_parameter_set_id
+    w.bit(false); // CAVLC
+    w.bit(false); // bottom_field_pic_order_in_frame_present_flag
+    w.ue(0); // num_slice_groups_minus1
+    w.ue(0); // num_ref_idx_l0_default_active_minus1
+    w.ue(0); // num_ref_idx_l1_default_active_minus1
+    w.bit(false); // weighted_pred_flag
+    w.bits(0, 2); // weighted_bipred_idc
+    w.ue(0); // pic_init_qp_minus26 (se 0)
+    w.ue(0); // pic_init_qs_minus26 (se 0)
+    w.ue(0); // chroma_qp_index_offset (se 0)
+    w.bit(true); // deblocking_filter_control_present_flag
+    w.bit(false); // constrained_intra_pred_flag
+    w.bit(false); // redundant_pic_cnt_present_flag
+    w.finish(0x68)
+}
+
+/// Colour bars in I_PCM macroblocks, with the macroblock in row 13 at column
+/// `second % WIDTH_MBS` painted black so each keyframe differs from the last.
+fn idr_slice(second: u64) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.ue(0); // first_mb_in_slice
+    w.ue(7); // slice_type: I, all slices
+    w.ue(0); // pic_parameter_set_id
+    w.bits(0, 4); // frame_num
+    w.ue((second % 2) as u32); // idr_pic_id
+    w.bit(false); // no_output_of_prior_pics_flag
+    w.bit(false); // long_term_reference_flag
+    w.ue(0); // slice_qp_delta (se 0)
+    w.ue(1); // disable_deblocking_filter_idc
+    let marker = (second as usize) % WIDTH_MBS;
+    for row in 0..HEIGHT_MBS {
+        for col in 0..WIDTH_MBS {
+            let (y, cb, cr) = if row == 13 && col == marker { (16, 128, 128) } else { BARS[col * BARS.len() / WIDTH_MBS] };
+            w.ue(25); // mb_type: I_PCM
+            w.align_zero();
+            for _ in 0..256 {
+                w.byte(y);
+            }
+            for _ in 0..64 {
+                w.byte(cb);
+            }
+            for _ in 0..64 {
+                w.byte(cr);
+            }
+        }
+    }
+    w.finish(0x65)
+}
+
+/// A P frame that skips every macroblock, repeating the last picture.
+fn skip_slice(frame_num: u32) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.ue(0); // first_mb_in_slice
+    w.ue(5); // slice_type: P, all slices
+    w.ue(0); // pic_parameter_set_id
+    w.bits(frame_num % 16, 4);
+    w.bit(false); // num_ref_idx_active_override_flag
+    w.bit(false); // ref_pic_list_modification_flag_l0
+    w.bit(false); // adaptive_ref_pic_marking_mode_flag
+    w.ue(0); // slice_qp_delta (se 0)
+    w.ue(1); // disable_deblocking_filter_idc
+    w.ue((WIDTH_MBS * HEIGHT_MBS) as u32); // mb_skip_run
+    w.finish(0x41)
+}
+
+/// Frames `frames` of the pattern as one TS stream, without `stream`'s pacing.
+#[cfg(test)]
+pub(crate) fn test_pattern(frames: std::ops::Range<u64>) -> Vec<u8> {
+    let mut muxer = Muxer::default();
+    frames.flat_map(|frame| muxer.access_unit(frame)).collect()
+}
+
+/// Packs access units into TS packets on `VIDEO_PID`, with a PAT and PMT ahead of each
+/// keyframe and the random access indicator on its first packet.
+#[derive(Default)]
+struct Muxer {
+    continuity: u8,
+}
+
+impl Muxer {
+    fn access_unit(&mut self, frame: u64) -> Vec<u8> {
+        let keyframe = frame.is_multiple_of(FPS);
+        let mut es = access_unit_delimiter();
+        if keyframe {
+            es.extend(sps());
+            es.extend(pps());
+            es.extend(idr_slice(frame / FPS));
+        } else {
+            es.extend(skip_slice((frame % FPS) as u32));
+        }
+
+        let pts = frame * TICKS_PER_FRAME + 90_000;
+        let mut pes = vec![0, 0, 1, 0xE0, 0, 0, 0x80, 0x80, 5];
+        pes.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0E) as u8,
+            (pts >> 22) as u8,
+            0x01 | ((pts >> 14) & 0xFE) as u8,
+            (pts >> 7) as u8,
+            0x01 | ((pts << 1) & 0xFE) as u8,
+        ]);
+        pes.extend(es);
+
+        let mut out = Vec::new();
+        if keyframe {
+            out.extend_from_slice(&mpegts::pat_packet(mpegts::PMT_PID));
+            out.extend_from_slice(&mpegts::pmt_packet(mpegts::PMT_PID, VIDEO_PID, &[(mpegts::STREAM_TYPE_H264, VIDEO_PID)]));
+        }
+        let mut rest = &pes[..];
+        let mut first = true;
+        while !rest.is_empty() {
+            let mut adaptation = Vec::new();
+            if first {
+                // Random access indicator on keyframes, and a PCR on every frame start.
+                let pcr = pts - 9000;
+                adaptation.push(if keyframe { 0x50 } else { 0x10 });
+                adaptation.extend_from_slice(&[
+                    (pcr >> 25) as u8,
+                    (pcr >> 17) as u8,
+                    (pcr >> 9) as u8,
+                    (pcr >> 1) as u8,
+                    ((pcr & 1) << 7) as u8 | 0x7E,
+                    0,
+                ]);
+            }
+            let room = mpegts::PACKET_LEN - 4 - if adaptation.is_empty() { 0 } else { 1 + adaptation.len() };
+            if rest.len() < room {
+                // Stuff the last packet through the adaptation field.
+                let fill = room - rest.len();
+                if adaptation.is_empty() {
+                    if fill > 1 {
+                        adaptation.push(0);
+                        adaptation.resize(fill - 1, 0xFF);
+                    }
+                } else {
+                    adaptation.resize(adaptation.len() + fill, 0xFF);
+                }
+            }
+            let take = rest.len().min(room);
+            let mut packet = Vec::with_capacity(mpegts::PACKET_LEN);
+            packet.push(0x47);
+            packet.push(if first { 0x40 } else { 0 } | (VIDEO_PID >> 8) as u8);
+            packet.push(VIDEO_PID as u8);
+            let has_adaptation = !adaptation.is_empty() || (rest.len() < room && room - rest.len() == 1);
+            packet.push(if has_adaptation { 0x30 } else { 0x10 } | self.continuity);
+            if has_adaptation {
+                packet.push(adaptation.len() as u8);
+                packet.extend_from_slice(&adaptation);
+            }
+            packet.extend_from_slice(&rest[..take]);
+            debug_assert_eq!(packet.len(), mpegts::PACKET_LEN);
+            out.extend(packet);
+            self.continuity = (self.continuity + 1) & 0x0F;
+            rest = &rest[take..];
+            first = false;
+        }
+        out
+    }
+}
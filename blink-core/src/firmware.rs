@@ -0,0 +1,148 @@
+//! Firmware version tracking. Blink updates cameras and sync modules without telling
+//! anyone, so each poll compares the versions on the homescreen with the last ones seen
+//! and reports the devices that changed. Observation only; nothing here triggers an update.
+
+use crate::blink::{BlinkClient, HomescreenResponse};
+use anyhow::Result;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+pub const FIRMWARE_FILE: &str = "firmware.json";
+pub const POLL_INTERVAL_SECS: u64 = 60 * 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FirmwareDeviceKind {
+    Camera,
+    SyncModule,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FirmwareRecord {
+    pub kind: FirmwareDeviceKind,
+    pub device_id: i64,
+    pub name: String,
+    pub network_id: Option<i64>,
+    pub version: String,
+    /// The version before the last observed change.
+    pub previous_version: Option<String>,
+    /// RFC 3339; when the current version was first seen.
+    pub observed_at: String,
+}
+
+/// A version change noticed between two polls.
+#[derive(Debug, Clone, Serialize)]
+pub struct FirmwareChange {
+    pub kind: FirmwareDeviceKind,
+    pub device_id: i64,
+    pub name: String,
+    pub from: String,
+    pub to: String,
+}
+
+impl std::fmt::Display for FirmwareChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} updated from {} to {}", self.name, self.from, self.to)
+    }
+}
+
+/// Last-seen firmware per device, kept in `FIRMWARE_FILE`.
+pub struct FirmwareTracker {
+    path: PathBuf,
+    records: std::sync::Mutex<Vec<FirmwareRecord>>,
+}
+
+impl FirmwareTracker {
+    /// A missing or unreadable file starts empty; the first poll then records every
+    /// device without reporting changes.
+    pub fn load(path: PathBuf) -> Self {
+        let records = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { path, records: std::sync::Mutex::new(records) }
+    }
+
+    /// Every known device, sync modules first, then by name.
+    pub fn report(&self) -> Vec<FirmwareRecord> {
+        let mut records = self.records.lock().unwrap().clone();
+        records.sort_by_key(|r| (r.kind != FirmwareDeviceKind::SyncModule, r.name.clone()));
+        records
+    }
+
+    /// Records the versions on `homescreen` and returns the devices whose version differs
+    /// from the last one seen. Devices without a reported version are left alone.
+    pub fn observe(&self, homescreen: &HomescreenResponse) -> Vec<FirmwareChange> {
+        let cameras = homescreen.cameras.iter()
+            .map(|c| (FirmwareDeviceKind::Camera, c.id, &c.name, c.network_id, c.fw_version.as_deref()));
+        let sync_modules = homescreen.sync_modules.iter()
+            .map(|s| (FirmwareDeviceKind::SyncModule, s.id, &s.name, s.network_id, s.fw_version.as_deref()));
+
+        let now = Utc::now().to_rfc3339();
+        let mut records = self.records.lock().unwrap();
+        let mut changes = Vec::new();
+        let mut dirty = false;
+        for (kind, device_id, name, network_id, version) in cameras.chain(sync_modules) {
+            let Some(version) = version.filter(|v| !v.is_empty()) else { continue };
+            match records.iter_mut().find(|r| r.kind == kind && r.device_id == device_id) {
+                Some(record) if record.version == version => {
+                    if record.name != *name {
+                        record.name = name.clone();
+                        dirty = true;
+                    }
+                }
+                Some(record) => {
+                    if kind == FirmwareDeviceKind::Camera {
+                        crate::device_routes::forget(device_id);
+                    }
+                    changes.push(FirmwareChange {
+                        kind,
+                        device_id,
+                        name: name.clone(),
+                        from: record.version.clone(),
+                        to: version.to_string(),
+                    });
+                    record.previous_version = Some(std::mem::replace(&mut record.version, version.to_string()));
+                    record.name = name.clone();
+                    record.network_id = network_id;
+                    record.observed_at = now.clone();
+                    dirty = true;
+                }
+                None => {
+                    records.push(FirmwareRecord {
+                        kind,
+                        device_id,
+                        name: name.clone(),
+                        network_id,
+                        version: version.to_string(),
+                        previous_version: None,
+                        observed_at: now.clone(),
+                    });
+                    dirty = true;
+                }
+            }
+        }
+        if dirty {
+            if let Err(e) = self.save(&records) {
+                eprintln!("Failed to save firmware versions: {}", e);
+            }
+        }
+        changes
+    }
+
+    /// Reads the homescreen and observes it.
+    pub async fn poll(&self, client: &BlinkClient) -> Result<Vec<FirmwareChange>> {
+        let homescreen = client.get_homescreen().await?;
+        Ok(self.observe(&homescreen))
+    }
+
+    fn save(&self, records: &[FirmwareRecord]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, serde_json::to_vec_pretty(records)?)?;
+        Ok(())
+    }
+}
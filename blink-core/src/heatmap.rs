@@ -0,0 +1,269 @@
+//! When a camera triggers: clip counts in a 7×24 grid by weekday and hour, plus clips per
+//! day for a sparkline, all in the local time of the camera's network so "6 pm" means the
+//! evening there, across DST changes included. Counts come from the media index when it
+//! reaches back to the start of the range and from the live media feed otherwise.
+
+use crate::blink::{parse_blink_timestamp, BlinkClient, BlinkError};
+use crate::media_index::{MediaIndex, MediaSource};
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::Mutex;
+
+pub const DEFAULT_RANGE_DAYS: i64 = 28;
+pub const MAX_RANGE_DAYS: i64 = 366;
+/// Media pages read when the index can't answer.
+const LIVE_MAX_PAGES: i64 = 50;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DayClips {
+    /// Local `YYYY-MM-DD`.
+    pub day: String,
+    pub clips: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityHeatmap {
+    pub camera_id: i64,
+    pub camera_name: Option<String>,
+    /// IANA zone the grid is in; `UTC` when the network reports none chrono-tz knows.
+    pub time_zone: String,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// `counts[weekday][hour]`, Monday first, in local time. The hour skipped when clocks
+    /// go forward gets nothing that day and the hour repeated when they go back gets both.
+    pub counts: [[u64; 24]; 7],
+    pub total: u64,
+    /// Every local day the range touches, oldest first, days without clips included.
+    pub per_day: Vec<DayClips>,
+    pub source: MediaSource,
+}
+
+/// Rejects ranges that are backwards or longer than `MAX_RANGE_DAYS`.
+pub fn check_range(from: DateTime<Utc>, to: DateTime<Utc>) -> Result<()> {
+    if from >= to {
+        return Err(anyhow!("from must be before to"));
+    }
+    if to - from > Duration::days(MAX_RANGE_DAYS) {
+        return Err(anyhow!("A heatmap covers at most {} days", MAX_RANGE_DAYS));
+    }
+    Ok(())
+}
+
+/// Weekday (Monday 0), hour and local date of `at` in `tz`.
+pub fn bucket(at: DateTime<Utc>, tz: Tz) -> (usize, usize, NaiveDate) {
+    let local = at.with_timezone(&tz);
+    (local.weekday().num_days_from_monday() as usize, local.hour() as usize, local.date_naive())
+}
+
+/// Folds `(time, count)` pairs into the grid and the per-day series for `from`..`to`.
+pub fn aggregate(clips: impl IntoIterator<Item = (DateTime<Utc>, u64)>, tz: Tz, from: DateTime<Utc>, to: DateTime<Utc>) -> ([[u64; 24]; 7], u64, Vec<DayClips>) {
+    let mut counts = [[0u64; 24]; 7];
+    let mut total = 0;
+    let mut days: BTreeMap<NaiveDate, u64> = BTreeMap::new();
+    let first = from.with_timezone(&tz).date_naive();
+    let last = (to - Duration::nanoseconds(1)).with_timezone(&tz).date_naive();
+    let mut day = first;
+    while day <= last {
+        days.insert(day, 0);
+        let Some(next) = day.succ_opt() else { break };
+        day = next;
+    }
+    for (at, count) in clips {
+        if at < from || at >= to {
+            continue;
+        }
+        let (weekday, hour, date) = bucket(at, tz);
+        counts[weekday][hour] += count;
+        total += count;
+        *days.entry(date).or_insert(0) += count;
+    }
+    let per_day = days.into_iter()
+        .map(|(day, clips)| DayClips { day: day.format("%Y-%m-%d").to_string(), clips })
+        .collect();
+    (counts, total, per_day)
+}
+
+/// The camera's name and its network's zone, from the homescreen.
+async fn camera_zone(client: &Mutex<BlinkClient>, camera_id: i64) -> Result<(Option<String>, Option<Tz>)> {
+    let json = shared::snapshot(client).await.get_homescreen_json().await?;
+    let camera = ["cameras", "owls", "doorbells"].iter()
+        .flat_map(|list| json.get(*list).and_then(|v| v.as_array()).into_iter().flatten())
+        .find(|camera| camera.get("id").and_then(|v| v.as_i64()) == Some(camera_id))
+        .ok_or_else(|| BlinkError::NotFound(format!("No camera {}", camera_id)))?;
+    let name = camera.get("name").and_then(|v| v.as_str()).map(str::to_string);
+    let network_id = camera.get("network_id").and_then(|v| v.as_i64());
+    let tz = json.get("networks").and_then(|v| v.as_array()).into_iter().flatten()
+        .find(|network| network_id.is_some() && network.get("id").and_then(|v| v.as_i64()) == network_id)
+        .and_then(|network| network.get("time_zone").and_then(|v| v.as_str()))
+        .and_then(|tz| tz.parse::<Tz>().ok());
+    Ok((name, tz))
+}
+
+/// The heatmap of `camera_id` for `from` up to (not including) `to`.
+pub async fn activity(
+    client: &Mutex<BlinkClient>,
+    index: &MediaIndex,
+    camera_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<ActivityHeatmap> {
+    check_range(from, to)?;
+    let (camera_name, tz) = camera_zone(client, camera_id).await?;
+    let tz = tz.unwrap_or(Tz::UTC);
+
+    let (clips, source): (Vec<(DateTime<Utc>, u64)>, _) = match index.clips_per_minute(camera_id, from, to)? {
+        Some(minutes) => (minutes.into_iter().map(|m| (m.minute, m.clips)).collect(), MediaSource::Index),
+        None => {
+            let since_days = (Utc::now() - from).num_days() + 1;
+            let items = shared::snapshot(client).await.get_all_media(since_days, LIVE_MAX_PAGES).await?;
+            let clips = items.into_iter()
+                .filter(|item| !item.deleted && item.device_id == Some(camera_id))
+                .filter_map(|item| item.created_at.as_deref().and_then(parse_blink_timestamp))
+                .map(|at| (at, 1))
+                .collect();
+            (clips, MediaSource::Live)
+        }
+    };
+
+    let (counts, total, per_day) = aggregate(clips, tz, from, to);
+    Ok(ActivityHeatmap {
+        camera_id,
+        camera_name,
+        time_zone: tz.name().to_string(),
+        from,
+        to,
+        counts,
+        total,
+        per_day,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blink::MediaItem;
+
+    const SUNDAY: usize = 6;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    fn ones(times: &[&str]) -> Vec<(DateTime<Utc>, u64)> {
+        times.iter().map(|t| (at(t), 1)).collect()
+    }
+
+    fn days(per_day: &[DayClips]) -> Vec<(&str, u64)> {
+        per_day.iter().map(|d| (d.day.as_str(), d.clips)).collect()
+    }
+
+    #[test]
+    fn the_hour_skipped_when_clocks_go_forward_stays_empty() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // 2024-03-10: 01:59 EST is followed by 03:00 EDT.
+        assert_eq!(bucket(at("2024-03-10T06:59:00Z"), tz).1, 1);
+        assert_eq!(bucket(at("2024-03-10T07:00:00Z"), tz).1, 3);
+
+        let clips = ones(&["2024-03-10T06:30:00Z", "2024-03-10T07:00:00Z", "2024-03-10T07:30:00Z"]);
+        let (counts, total, _) = aggregate(clips, tz, at("2024-03-10T00:00:00Z"), at("2024-03-11T00:00:00Z"));
+        assert_eq!(total, 3);
+        assert_eq!(counts[SUNDAY][1], 1);
+        assert_eq!(counts[SUNDAY][2], 0);
+        assert_eq!(counts[SUNDAY][3], 2);
+    }
+
+    #[test]
+    fn the_hour_repeated_when_clocks_go_back_gets_both() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // 2024-11-03: 01:30 EDT and, an hour later, 01:30 EST.
+        let clips = ones(&["2024-11-03T05:30:00Z", "2024-11-03T06:30:00Z", "2024-11-03T07:30:00Z"]);
+        let (counts, total, _) = aggregate(clips, tz, at("2024-11-03T00:00:00Z"), at("2024-11-04T00:00:00Z"));
+        assert_eq!(total, 3);
+        assert_eq!(counts[SUNDAY][1], 2);
+        assert_eq!(counts[SUNDAY][2], 1);
+    }
+
+    #[test]
+    fn southern_hemisphere_changes_are_bucketed_the_same_way() {
+        let tz: Tz = "Australia/Sydney".parse().unwrap();
+        // 2024-04-07: 02:30 AEDT, then 02:30 AEST once clocks go back from 03:00.
+        let clips = ones(&["2024-04-06T15:30:00Z", "2024-04-06T16:30:00Z"]);
+        let (counts, _, per_day) = aggregate(clips, tz, at("2024-04-06T13:00:00Z"), at("2024-04-07T14:00:00Z"));
+        assert_eq!(counts[SUNDAY][2], 2);
+        assert_eq!(days(&per_day), [("2024-04-07", 2)]);
+    }
+
+    #[test]
+    fn per_day_follows_local_days_of_23_and_25_hours() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        // Local midnight to midnight around the spring change; the 10th is 23 hours long.
+        let clips = ones(&["2024-03-10T04:30:00Z", "2024-03-11T03:30:00Z"]);
+        let (counts, _, per_day) = aggregate(clips, tz, at("2024-03-09T05:00:00Z"), at("2024-03-11T04:00:00Z"));
+        assert_eq!(days(&per_day), [("2024-03-09", 1), ("2024-03-10", 1)]);
+        assert_eq!(counts[5][23], 1);
+        assert_eq!(counts[SUNDAY][23], 1);
+
+        // The autumn day is 25 hours and still one day; 23:30 EST is 04:30 UTC the next day.
+        let clips = ones(&["2024-11-04T04:30:00Z"]);
+        let (counts, _, per_day) = aggregate(clips, tz, at("2024-11-03T04:00:00Z"), at("2024-11-04T05:00:00Z"));
+        assert_eq!(days(&per_day), [("2024-11-03", 1)]);
+        assert_eq!(counts[SUNDAY][23], 1);
+    }
+
+    #[test]
+    fn half_hour_offsets_move_clips_across_days() {
+        let tz: Tz = "Asia/Kolkata".parse().unwrap();
+        assert_eq!(bucket(at("2024-01-01T18:29:00Z"), tz), (0, 23, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()));
+        assert_eq!(bucket(at("2024-01-01T18:30:00Z"), tz), (1, 0, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap()));
+    }
+
+    #[test]
+    fn the_range_includes_from_and_excludes_to() {
+        let from = at("2024-06-01T00:00:00Z");
+        let to = at("2024-06-02T00:00:00Z");
+        let clips = vec![(at("2024-05-31T23:59:59Z"), 1), (from, 2), (at("2024-06-01T12:00:00Z"), 3), (to, 4)];
+        let (_, total, per_day) = aggregate(clips, Tz::UTC, from, to);
+        assert_eq!(total, 5);
+        assert_eq!(days(&per_day), [("2024-06-01", 5)]);
+
+        assert!(check_range(to, from).is_err());
+        assert!(check_range(from, from).is_err());
+        assert!(check_range(from, from + Duration::days(MAX_RANGE_DAYS + 1)).is_err());
+        assert!(check_range(from, from + Duration::days(MAX_RANGE_DAYS)).is_ok());
+    }
+
+    #[test]
+    fn index_minutes_bucket_like_raw_clips_across_a_change() {
+        let tz: Tz = "America/New_York".parse().unwrap();
+        let dir = tempfile::tempdir().unwrap().keep();
+        let index = MediaIndex::new(dir.join("media_index.sqlite"), true);
+        let clip = |id: i64, camera: i64, created_at: &str, deleted: bool| -> MediaItem {
+            serde_json::from_value(serde_json::json!({
+                "id": id, "created_at": created_at, "device_id": camera, "network_id": 1, "deleted": deleted,
+            })).unwrap()
+        };
+        index.upsert(&[
+            clip(1, 7, "2024-11-03T05:30:10+00:00", false),
+            clip(2, 7, "2024-11-03T05:30:50+00:00", false),
+            clip(3, 7, "2024-11-03T06:30:00+00:00", false),
+            clip(4, 7, "2024-11-03T06:45:00+00:00", true),
+            clip(5, 8, "2024-11-03T06:30:00+00:00", false),
+            clip(6, 7, "2024-11-02T12:00:00+00:00", false),
+        ]).unwrap();
+
+        let (from, to) = (at("2024-11-03T04:00:00Z"), at("2024-11-04T05:00:00Z"));
+        let minutes = index.clips_per_minute(7, from, to).unwrap().unwrap();
+        let (counts, total, per_day) = aggregate(minutes.into_iter().map(|m| (m.minute, m.clips)), tz, from, to);
+        assert_eq!(total, 3);
+        assert_eq!(counts[SUNDAY][1], 3);
+        assert_eq!(days(&per_day), [("2024-11-03", 3)]);
+
+        // Before the oldest indexed clip the index can't answer and the live feed is used.
+        assert!(index.clips_per_minute(7, at("2024-11-01T00:00:00Z"), to).unwrap().is_none());
+    }
+}
@@ -0,0 +1,390 @@
+use std::io;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{
+    self, 
+    ClientConfig, 
+    DigitallySignedStruct, 
+    Error as RustlsError, 
+    SignatureScheme
+};
+use tokio_rustls::rustls::client::danger::{
+    HandshakeSignatureValid, 
+    ServerCertVerified, 
+    ServerCertVerifier
+};
+use rustls_pki_types::{CertificateDer, ServerName, UnixTime};
+use tokio_rustls::TlsConnector;
+use anyhow::{Result, anyhow};
+use url::Url;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use rustls::RootCertStore;
+use tokio_util::codec::{Decoder, Encoder, FramedRead, FramedWrite};
+use crate::log_control;
+
+/// Type, sequence and payload length.
+const HEADER_LEN: usize = 9;
+/// Anything bigger means the stream is out of sync; the codec fails rather than buffer it.
+pub const MAX_PAYLOAD_LEN: usize = 1024 * 1024;
+
+pub const MSG_VIDEO: u8 = 0x00;
+pub const MSG_KEEPALIVE: u8 = 0x0A;
+pub const MSG_LATENCY_STATS: u8 = 0x12;
+
+#[derive(Debug)]
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, RustlsError> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        _message: &[u8],
+        _cert: &CertificateDer<'_>,
+        _dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, RustlsError> {
+        Ok(HandshakeSignatureValid::assertion())
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::aws_lc_rs::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+fn insecure_tls_enabled() -> bool {
+    std::env::var("BLINK_IMMI_INSECURE_TLS")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn secure_only_enabled() -> bool {
+    std::env::var("BLINK_IMMI_SECURE_ONLY")
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn build_tls_config(insecure: bool) -> Result<ClientConfig> {
+    let provider = rustls::crypto::aws_lc_rs::default_provider();
+
+    if insecure {
+        return Ok(ClientConfig::builder_with_provider(Arc::new(provider))
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(NoCertificateVerification))
+            .with_no_client_auth());
+    }
+
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for cert in native.certs {
+        roots.add(cert).map_err(|e| anyhow!("Failed to add native cert: {}", e))?;
+    }
+
+    Ok(ClientConfig::builder_with_provider(Arc::new(provider))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth())
+}
+
+type TlsStream = tokio_rustls::client::TlsStream<TcpStream>;
+
+/// One IMMI message: a 9-byte header (type, big-endian sequence and payload length)
+/// followed by the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImmiFrame {
+    pub msg_type: u8,
+    pub sequence: u32,
+    pub payload: Bytes,
+}
+
+impl ImmiFrame {
+    /// Latency stats: 24 zero bytes under the static sequence 1000, as the reference
+    /// implementation sends every second.
+    pub fn latency_stats() -> Self {
+        Self { msg_type: MSG_LATENCY_STATS, sequence: 1000, payload: Bytes::from_static(&[0; 24]) }
+    }
+
+    /// Empty keepalive; the reference implementation sends one every 10 seconds.
+    pub fn keepalive(sequence: u32) -> Self {
+        Self { msg_type: MSG_KEEPALIVE, sequence, payload: Bytes::new() }
+    }
+}
+
+/// Splits the server's byte stream into `ImmiFrame`s and serializes outbound ones.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ImmiCodec;
+
+fn payload_too_large(len: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("Payload too large: {}", len))
+}
+
+impl Decoder for ImmiCodec {
+    type Item = ImmiFrame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<ImmiFrame>> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        let mut header = &src[..HEADER_LEN];
+        let msg_type = header.get_u8();
+        let sequence = header.get_u32();
+        let payload_len = header.get_u32() as usize;
+        if payload_len > MAX_PAYLOAD_LEN {
+            return Err(payload_too_large(payload_len));
+        }
+        if src.len() < HEADER_LEN + payload_len {
+            src.reserve(HEADER_LEN + payload_len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER_LEN);
+        let payload = src.split_to(payload_len).freeze();
+        Ok(Some(ImmiFrame { msg_type, sequence, payload }))
+    }
+}
+
+impl Encoder<ImmiFrame> for ImmiCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: ImmiFrame, dst: &mut BytesMut) -> io::Result<()> {
+        if frame.payload.len() > MAX_PAYLOAD_LEN {
+            return Err(payload_too_large(frame.payload.len()));
+        }
+        dst.reserve(HEADER_LEN + frame.payload.len());
+        dst.put_u8(frame.msg_type);
+        dst.put_u32(frame.sequence);
+        dst.put_u32(frame.payload.len() as u32);
+        dst.put_slice(&frame.payload);
+        Ok(())
+    }
+}
+
+/// A connected, authenticated IMMI session, split so reading and keepalives can run in
+/// separate tasks.
+pub struct ImmiStream {
+    pub reader: FramedRead<tokio::io::ReadHalf<TlsStream>, ImmiCodec>,
+    pub writer: FramedWrite<tokio::io::WriteHalf<TlsStream>, ImmiCodec>,
+}
+
+impl ImmiStream {
+    pub async fn connect(server_url: &str, serial: &str) -> Result<Self> {
+        let fixed_url = server_url.replace("immis://", "https://");
+        let url = Url::parse(&fixed_url)?;
+        
+        let host = url.host_str().ok_or(anyhow!("Invalid host"))?;
+        let port = url.port().unwrap_or(443);
+        log_control::debug("immi", || format!("Connecting to IMMI at {}:{}", host, port));
+        let path = url.path();
+        
+        let client_id = url.query_pairs()
+            .find(|(k, _)| k == "client_id")
+            .map(|(_, v)| v.to_string())
+            .unwrap_or_else(|| "0".to_string());
+            
+        let conn_id = path.split('/')
+            .next_back()
+            .and_then(|s| s.split("__").next())
+            .ok_or(anyhow!("Could not extract connection ID"))?;
+
+        let domain = ServerName::try_from(host.to_string())
+            .map_err(|_| anyhow!("Invalid DNS name"))?;
+
+        let tls_stream = if insecure_tls_enabled() {
+            let config = build_tls_config(true)?;
+            let connector = TlsConnector::from(Arc::new(config));
+            let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+            connector.connect(domain, stream).await?
+        } else {
+            let config = build_tls_config(false)?;
+            let connector = TlsConnector::from(Arc::new(config));
+            let stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+            match connector.connect(domain.clone(), stream).await {
+                Ok(tls) => tls,
+                Err(e) => {
+                    if secure_only_enabled() {
+                        return Err(anyhow!("TLS verification failed: {}", e));
+                    }
+                    log_control::warn("immi", || format!("TLS verification failed, falling back to insecure: {}", e));
+                    let fallback_config = build_tls_config(true)?;
+                    let fallback_connector = TlsConnector::from(Arc::new(fallback_config));
+                    let fallback_stream = TcpStream::connect(format!("{}:{}", host, port)).await?;
+                    fallback_connector.connect(domain, fallback_stream).await?
+                }
+            }
+        };
+        let (reader, mut writer) = tokio::io::split(tls_stream);
+
+        // Build the 122-byte authentication header matching reference blinkpy implementation
+        let mut auth_header = BytesMut::with_capacity(122);
+        
+        // 1. Magic number (4 bytes)
+        auth_header.put_u32(0x00000028);
+        
+        // 2. Device Serial field (4-byte length prefix + 16 serial bytes)
+        let serial_bytes = serial.as_bytes();
+        let serial_len = serial_bytes.len().min(16);
+        auth_header.put_u32(16);
+        auth_header.put_slice(&serial_bytes[..serial_len]);
+        if serial_len < 16 {
+            auth_header.put_bytes(0, 16 - serial_len);
+        }
+        
+        // 3. Client ID field (4 bytes)
+        let cid: u32 = client_id.parse().unwrap_or(0);
+        auth_header.put_u32(cid);
+        
+        // 4. Static field (2 bytes)
+        auth_header.put_slice(&[0x01, 0x08]);
+        
+        // 5. Auth Token field (4-byte length prefix + 64 null bytes)
+        auth_header.put_u32(64);
+        auth_header.put_bytes(0, 64);
+        
+        // 6. Connection ID field (4-byte length prefix + 16 connection ID bytes)
+        let conn_id_bytes = conn_id.as_bytes();
+        let conn_id_len = conn_id_bytes.len().min(16);
+        auth_header.put_u32(16);
+        auth_header.put_slice(&conn_id_bytes[..conn_id_len]);
+        if conn_id_len < 16 {
+            auth_header.put_bytes(0, 16 - conn_id_len);
+        }
+        
+        // 7. Trailer (4 bytes)
+        auth_header.put_u32(0x00000001);
+
+        writer.write_all(&auth_header).await?;
+        writer.flush().await?;
+
+        Ok(Self {
+            reader: FramedRead::new(reader, ImmiCodec),
+            writer: FramedWrite::new(writer, ImmiCodec),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn frame() -> impl Strategy<Value = ImmiFrame> {
+        (any::<u8>(), any::<u32>(), proptest::collection::vec(any::<u8>(), 0..2048))
+            .prop_map(|(msg_type, sequence, payload)| ImmiFrame { msg_type, sequence, payload: Bytes::from(payload) })
+    }
+
+    fn encode_all(frames: &[ImmiFrame]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        for frame in frames {
+            ImmiCodec.encode(frame.clone(), &mut buf).unwrap();
+        }
+        buf
+    }
+
+    /// Feeds `bytes` to the decoder in `chunk`-sized pieces, the way a socket might deliver them.
+    fn decode_chunked(bytes: &[u8], chunk: usize) -> io::Result<Vec<ImmiFrame>> {
+        let mut codec = ImmiCodec;
+        let mut src = BytesMut::new();
+        let mut frames = Vec::new();
+        for piece in bytes.chunks(chunk.max(1)) {
+            src.extend_from_slice(piece);
+            while let Some(frame) = codec.decode(&mut src)? {
+                frames.push(frame);
+            }
+        }
+        Ok(frames)
+    }
+
+    proptest! {
+        #[test]
+        fn frames_round_trip(frames in proptest::collection::vec(frame(), 0..8), chunk in 1usize..64) {
+            let bytes = encode_all(&frames);
+            prop_assert_eq!(decode_chunked(&bytes, chunk).unwrap(), frames);
+        }
+
+        #[test]
+        fn a_truncated_stream_waits_for_the_rest(frames in proptest::collection::vec(frame(), 1..4), cut in any::<prop::sample::Index>()) {
+            let bytes = encode_all(&frames);
+            let cut = cut.index(bytes.len());
+            let decoded = decode_chunked(&bytes[..cut], bytes.len()).unwrap();
+            // Only whole frames come out, in order, and the partial one stays buffered.
+            prop_assert!(decoded.len() < frames.len());
+            prop_assert_eq!(&decoded[..], &frames[..decoded.len()]);
+        }
+
+        #[test]
+        fn garbage_never_panics(bytes in proptest::collection::vec(any::<u8>(), 0..4096), chunk in 1usize..512) {
+            // Either frames, a wait for more data, or a clean error; never a panic or a
+            // buffer reserved past the cap.
+            let mut codec = ImmiCodec;
+            let mut src = BytesMut::new();
+            for piece in bytes.chunks(chunk) {
+                src.extend_from_slice(piece);
+                match codec.decode(&mut src) {
+                    Ok(_) => prop_assert!(src.capacity() <= HEADER_LEN + MAX_PAYLOAD_LEN + bytes.len()),
+                    Err(e) => {
+                        prop_assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn an_oversized_length_is_an_error_not_a_wait() {
+        let mut src = BytesMut::new();
+        src.put_u8(MSG_VIDEO);
+        src.put_u32(1);
+        src.put_u32(MAX_PAYLOAD_LEN as u32 + 1);
+        let err = ImmiCodec.decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("Payload too large"));
+    }
+
+    #[test]
+    fn a_payload_at_the_cap_is_accepted() {
+        let frame = ImmiFrame { msg_type: MSG_VIDEO, sequence: 7, payload: Bytes::from(vec![0xAB; MAX_PAYLOAD_LEN]) };
+        let mut buf = encode_all(std::slice::from_ref(&frame));
+        assert_eq!(ImmiCodec.decode(&mut buf).unwrap(), Some(frame));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn encoding_an_oversized_payload_fails() {
+        let frame = ImmiFrame { msg_type: MSG_VIDEO, sequence: 7, payload: Bytes::from(vec![0; MAX_PAYLOAD_LEN + 1]) };
+        let mut buf = BytesMut::new();
+        assert!(ImmiCodec.encode(frame, &mut buf).is_err());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn outbound_frames_match_the_reference_bytes() {
+        let buf = encode_all(&[ImmiFrame::keepalive(3), ImmiFrame::latency_stats()]);
+        let mut expected = vec![MSG_KEEPALIVE, 0, 0, 0, 3, 0, 0, 0, 0];
+        expected.extend_from_slice(&[MSG_LATENCY_STATS, 0, 0, 0x03, 0xE8, 0, 0, 0, 24]);
+        expected.extend_from_slice(&[0; 24]);
+        assert_eq!(&buf[..], &expected[..]);
+    }
+}
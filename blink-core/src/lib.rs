@@ -0,0 +1,53 @@
+//! Blink API client and the pieces built on it that the desktop app and the hosted server
+//! share: auth storage, liveview over IMMI, the clip index, schedules, settings and the
+//! audit log. Front ends only add their transport (Tauri IPC or HTTP).
+//!
+//! Features:
+//! - `keychain` (default): keep auth state in the OS keychain via `keyring`. Headless
+//!   builds can turn it off and store auth only in the encrypted `auth.json`.
+
+pub mod audit;
+pub mod auth_store;
+pub mod backup;
+pub mod battery;
+pub mod blink;
+pub mod camera_config;
+pub mod cleanup;
+pub mod clip_store;
+pub mod clock_skew;
+pub mod delete_queue;
+pub mod device_routes;
+pub mod diagnostics;
+pub mod events;
+pub mod export;
+pub mod fake_live;
+pub mod firmware;
+pub mod heatmap;
+pub mod immi;
+pub mod live_sessions;
+pub mod liveview;
+pub mod liveview_settings;
+pub mod log_control;
+pub mod media_index;
+pub mod motion_notifications;
+pub mod mp4;
+pub mod mpegts;
+pub mod notify;
+pub mod rearm;
+pub mod recording;
+pub mod replay;
+pub mod report;
+pub mod schedule;
+pub mod self_test;
+pub mod settings;
+pub mod shared;
+pub mod signing;
+pub mod takeout;
+pub mod thumbnail_cache;
+pub mod thumbnail_refresh;
+pub mod timeline;
+pub mod trash;
+pub mod webhooks;
+
+pub use blink::{AuthState, BlinkAuthState, BlinkClient, BlinkError, BlinkUrls, Camera, DeviceFamily, LiveviewOptions, LiveviewQuality, MediaItem, Network, NetworkSettings, NetworkSettingsPatch, NotificationConfig};
+pub use liveview::{LiveviewError, LiveviewRequest};
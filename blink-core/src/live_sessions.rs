@@ -0,0 +1,343 @@
+use crate::blink::{BlinkClient, LiveViewResponse, LiveviewParams};
+use crate::replay::{ReplayBuffer, ReplayUsage};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+/// Keepalives unanswered for this long are forgotten rather than counted.
+const KEEPALIVE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+/// PCR ticks per millisecond (the 90 kHz base).
+const PCR_TICKS_PER_MS: f64 = 90.0;
+/// The PCR base is 33 bits and wraps about every 26.5 hours.
+const PCR_WRAP: u64 = 1 << 33;
+
+/// Last, min, average and max of a series of measurements.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct LatencySummary {
+    pub last_ms: f64,
+    pub min_ms: f64,
+    pub avg_ms: f64,
+    pub max_ms: f64,
+    pub samples: u64,
+}
+
+impl std::fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "min {:.0}ms / avg {:.0}ms / max {:.0}ms over {} samples", self.min_ms, self.avg_ms, self.max_ms, self.samples)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    last: f64,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: u64,
+}
+
+impl Series {
+    fn add(&mut self, ms: f64) {
+        if self.count == 0 {
+            self.min = ms;
+            self.max = ms;
+        }
+        self.last = ms;
+        self.min = self.min.min(ms);
+        self.max = self.max.max(ms);
+        self.sum += ms;
+        self.count += 1;
+    }
+
+    fn summary(&self) -> Option<LatencySummary> {
+        (self.count > 0).then(|| LatencySummary {
+            last_ms: self.last,
+            min_ms: self.min,
+            avg_ms: self.sum / self.count as f64,
+            max_ms: self.max,
+            samples: self.count,
+        })
+    }
+}
+
+/// How far behind the session runs, as published in `LiveSessionInfo`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LiveLatency {
+    /// Time to open and authenticate the IMMI connection.
+    pub connect_ms: Option<u64>,
+    /// From the IMMI connection to the first MPEG-TS packet.
+    pub first_packet_ms: Option<u64>,
+    /// Round trip from sending a keepalive to the server echoing its sequence number.
+    pub keepalive_rtt: Option<LatencySummary>,
+    /// Estimated end-to-end delay: the wait for the first packet plus how far the wall
+    /// clock has since run ahead of the stream's PCR, i.e. delay that built up in transit.
+    pub stream_delay: Option<LatencySummary>,
+}
+
+#[derive(Debug, Default)]
+struct LatencyTracker {
+    connect: Option<Duration>,
+    connected_at: Option<Instant>,
+    first_packet: Option<Duration>,
+    pending_keepalives: HashMap<u32, Instant>,
+    rtt: Series,
+    /// PCR base and arrival time of the first PCR seen.
+    pcr_anchor: Option<(u64, Instant)>,
+    delay: Series,
+}
+
+#[derive(Default)]
+pub struct LiveSessionStats {
+    pub bytes_sent: AtomicU64,
+    pub subscribers: AtomicUsize,
+    /// Chunks dropped because the consumer fell behind.
+    pub dropped_chunks: AtomicU64,
+    pub dropped_bytes: AtomicU64,
+    latency: std::sync::Mutex<LatencyTracker>,
+    /// The last seconds of the stream, for `replay`.
+    pub replay: ReplayBuffer,
+    /// Times the liveview command was extended past its `duration`.
+    pub extends: AtomicU64,
+    /// When Blink will end the command, once it refused to extend it again.
+    expires_at: std::sync::Mutex<Option<DateTime<Utc>>>,
+    /// When the consumer last took a chunk.
+    last_delivery: std::sync::Mutex<Option<Instant>>,
+}
+
+impl LiveSessionStats {
+    /// Counts a chunk the consumer took.
+    pub fn add_bytes(&self, n: usize) {
+        self.bytes_sent.fetch_add(n as u64, Ordering::Relaxed);
+        *self.last_delivery.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Whether the consumer took a chunk within `window`.
+    pub fn delivered_within(&self, window: Duration) -> bool {
+        self.last_delivery.lock().unwrap().is_some_and(|at| at.elapsed() < window)
+    }
+
+    pub fn extended(&self) {
+        self.extends.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn expiring(&self, at: DateTime<Utc>) {
+        *self.expires_at.lock().unwrap() = Some(at);
+    }
+
+    pub fn expires_at(&self) -> Option<DateTime<Utc>> {
+        *self.expires_at.lock().unwrap()
+    }
+
+    pub fn add_dropped(&self, n: usize) {
+        self.dropped_chunks.fetch_add(1, Ordering::Relaxed);
+        self.dropped_bytes.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Marks the IMMI connection as open after taking `elapsed`; `None` for sources that
+    /// have no connection to time.
+    pub fn connected(&self, elapsed: Option<Duration>) {
+        let mut latency = self.latency.lock().unwrap();
+        latency.connect = elapsed;
+        latency.connected_at = Some(Instant::now());
+    }
+
+    pub fn first_packet(&self) {
+        let mut latency = self.latency.lock().unwrap();
+        latency.first_packet = latency.connected_at.map(|at| at.elapsed());
+    }
+
+    pub fn keepalive_sent(&self, sequence: u32) {
+        let mut latency = self.latency.lock().unwrap();
+        latency.pending_keepalives.retain(|_, sent| sent.elapsed() < KEEPALIVE_ACK_TIMEOUT);
+        latency.pending_keepalives.insert(sequence, Instant::now());
+    }
+
+    /// Records the round trip for an echoed keepalive; unknown sequences are ignored.
+    pub fn keepalive_acked(&self, sequence: u32) {
+        let mut latency = self.latency.lock().unwrap();
+        if let Some(sent) = latency.pending_keepalives.remove(&sequence) {
+            latency.rtt.add(sent.elapsed().as_secs_f64() * 1000.0);
+        }
+    }
+
+    /// Compares a PCR (90 kHz base) with the wall clock. The first one anchors the
+    /// comparison; later ones measure how much further the wall clock has moved.
+    pub fn observe_pcr(&self, pcr_base: u64) {
+        let now = Instant::now();
+        let mut latency = self.latency.lock().unwrap();
+        let Some((anchor_pcr, anchor_at)) = latency.pcr_anchor else {
+            latency.pcr_anchor = Some((pcr_base, now));
+            return;
+        };
+        let stream_ms = (pcr_base.wrapping_sub(anchor_pcr) % PCR_WRAP) as f64 / PCR_TICKS_PER_MS;
+        let wall_ms = now.duration_since(anchor_at).as_secs_f64() * 1000.0;
+        let initial_ms = latency.first_packet.map_or(0.0, |d| d.as_secs_f64() * 1000.0);
+        // A stream briefly ahead of the wall clock (bursty delivery) counts as no extra delay.
+        latency.delay.add(initial_ms + (wall_ms - stream_ms).max(0.0));
+    }
+
+    pub fn latency(&self) -> LiveLatency {
+        let latency = self.latency.lock().unwrap();
+        LiveLatency {
+            connect_ms: latency.connect.map(|d| d.as_millis() as u64),
+            first_packet_ms: latency.first_packet.map(|d| d.as_millis() as u64),
+            keepalive_rtt: latency.rtt.summary(),
+            stream_delay: latency.delay.summary(),
+        }
+    }
+}
+
+pub struct LiveSession {
+    pub id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub command_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub recording_path: Option<String>,
+    pub params: LiveviewParams,
+    pub cancel: CancellationToken,
+    pub stats: Arc<LiveSessionStats>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LiveSessionInfo {
+    pub id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub subscriber_count: usize,
+    pub bytes_sent: u64,
+    /// Chunks (and their bytes) skipped because the consumer read too slowly.
+    pub dropped_chunks: u64,
+    pub dropped_bytes: u64,
+    pub recording_path: Option<String>,
+    /// Liveview parameters sent to Blink and the ones it confirmed.
+    pub params: LiveviewParams,
+    pub latency: LiveLatency,
+    /// What the instant replay buffer holds, counted against `replay::MAX_REPLAY_BYTES`.
+    pub replay: ReplayUsage,
+    /// Times the liveview command was extended past the duration Blink first gave it.
+    pub extends: u64,
+    /// Set once Blink refused another extension: the stream stops at this time.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+impl LiveSession {
+    pub fn info(&self) -> LiveSessionInfo {
+        LiveSessionInfo {
+            id: self.id.clone(),
+            network_id: self.network_id,
+            camera_id: self.camera_id,
+            started_at: self.started_at,
+            subscriber_count: self.stats.subscribers.load(Ordering::Relaxed),
+            bytes_sent: self.stats.bytes_sent.load(Ordering::Relaxed),
+            dropped_chunks: self.stats.dropped_chunks.load(Ordering::Relaxed),
+            dropped_bytes: self.stats.dropped_bytes.load(Ordering::Relaxed),
+            recording_path: self.recording_path.clone(),
+            params: self.params.clone(),
+            latency: self.stats.latency(),
+            replay: self.stats.replay.usage(),
+            extends: self.stats.extends.load(Ordering::Relaxed),
+            expires_at: self.stats.expires_at(),
+        }
+    }
+
+    /// One line for the teardown log: duration, traffic and the latency measured.
+    pub fn summary(&self) -> String {
+        let latency = self.stats.latency();
+        let mut line = format!(
+            "Live session {} for camera {} ended after {}s, {} bytes sent, {} chunks dropped, {} extends",
+            self.id,
+            self.camera_id,
+            (Utc::now() - self.started_at).num_seconds(),
+            self.stats.bytes_sent.load(Ordering::Relaxed),
+            self.stats.dropped_chunks.load(Ordering::Relaxed),
+            self.stats.extends.load(Ordering::Relaxed),
+        );
+        if let Some(ms) = latency.connect_ms {
+            line.push_str(&format!("; IMMI connect {}ms", ms));
+        }
+        if let Some(ms) = latency.first_packet_ms {
+            line.push_str(&format!("; first packet {}ms", ms));
+        }
+        if let Some(rtt) = latency.keepalive_rtt {
+            line.push_str(&format!("; keepalive RTT {}", rtt));
+        }
+        if let Some(delay) = latency.stream_delay {
+            line.push_str(&format!("; stream delay {}", delay));
+        }
+        line
+    }
+}
+
+/// Tracks the liveview sessions currently relayed by `proxy_live`.
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: std::sync::Mutex<HashMap<String, Arc<LiveSession>>>,
+}
+
+impl SessionRegistry {
+    /// Adds a session under `id`, a fresh UUID from the caller.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register(
+        &self,
+        id: String,
+        network_id: i64,
+        camera_id: i64,
+        liveview: &LiveViewResponse,
+        started_at: DateTime<Utc>,
+        recording_path: Option<String>,
+        cancel: CancellationToken,
+    ) -> Arc<LiveSession> {
+        let session = Arc::new(LiveSession {
+            id,
+            network_id,
+            camera_id,
+            command_id: liveview.command_id,
+            started_at,
+            recording_path,
+            params: liveview.params.clone(),
+            cancel,
+            stats: Arc::new(LiveSessionStats::default()),
+        });
+        session.stats.subscribers.store(1, Ordering::Relaxed);
+        self.sessions.lock().unwrap().insert(session.id.clone(), session.clone());
+        session
+    }
+
+    pub fn deregister(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<LiveSession>> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<LiveSessionInfo> {
+        let mut sessions: Vec<LiveSessionInfo> = self.sessions.lock().unwrap()
+            .values()
+            .map(|s| s.info())
+            .collect();
+        sessions.sort_by_key(|s| s.started_at);
+        sessions
+    }
+
+    /// Cancels the session (which triggers the relay's normal post-session cleanup)
+    /// and tells Blink to end the liveview command. Returns false for unknown ids.
+    pub async fn stop(&self, id: &str, blink_client: &Arc<Mutex<BlinkClient>>) -> Result<bool> {
+        let session = match self.get(id) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+        session.cancel.cancel();
+        let client = blink_client.lock().await;
+        client.stop_command(session.network_id, session.command_id).await?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,746 @@
+//! Liveview session plumbing: negotiating the command with Blink, connecting the IMMI
+//! stream, keepalives, command polling and extending the command before its `duration`
+//! runs out, and deleting the clip Blink records unless the caller asked to keep it. Shared by the desktop app (native command and the embedded
+//! relay) and the server; callers only decide how to deliver the chunks.
+
+use crate::blink::{BlinkClient, BlinkError, LiveViewResponse, LiveviewOptions};
+use crate::cleanup::CleanupQueue;
+use crate::fake_live;
+use crate::immi::{ImmiFrame, ImmiStream, MSG_KEEPALIVE, MSG_VIDEO};
+use crate::live_sessions::{LiveSession, LiveSessionStats, SessionRegistry};
+use crate::log_control;
+use crate::mpegts;
+use crate::recording::{self, Recorder, RecordingFormat, RecordingMeta};
+use crate::settings::Settings;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use tokio::sync::{mpsc, Mutex};
+use tokio_util::sync::CancellationToken;
+
+/// Chunks buffered between the IMMI reader and a slow consumer. Past this the relay drops
+/// chunks rather than wait: a reader stalled on a background tab stops draining IMMI and
+/// the relay disconnects us.
+const CHANNEL_CAPACITY: usize = 100;
+/// How long a keyframe or PAT chunk may wait for room before it is dropped too; about one
+/// packet interval, so the reader never stalls for longer.
+const KEY_CHUNK_WAIT_MS: u64 = 40;
+/// Dropping is logged at most this often while it lasts.
+const DROP_WARN_INTERVAL_SECS: u64 = 10;
+/// Give up if the camera has not sent any video this long after the session started.
+const FIRST_PACKET_TIMEOUT_SECS: u64 = 35;
+/// Extend the command this long before Blink would end it, unless its `continue_warning`
+/// asks for more.
+const EXTEND_LEAD_SECS: i64 = 10;
+/// The command is left to run out once the consumer has taken nothing for this long.
+const IDLE_CONSUMER_SECS: u64 = 30;
+/// PIDs announced in `StreamMode::Ffmpeg` when the first chunk has no PES header to
+/// learn them from; ffmpeg's own muxer defaults.
+const DEFAULT_VIDEO_PID: u16 = 0x100;
+const DEFAULT_AUDIO_PID: u16 = 0x101;
+
+/// What the relay sends before the camera's own MPEG-TS arrives. The web player
+/// (mpegts.js) needs `Mse`; ffmpeg, VLC and other players that probe the stream for a
+/// PAT/PMT work best with `Ffmpeg`; `Raw` suits consumers that cope with a late first byte.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamMode {
+    /// Three null packets up front, then one every two seconds until video arrives.
+    #[default]
+    Mse,
+    /// Only the camera's own packets.
+    Raw,
+    /// A PAT and PMT ahead of the first video, unless the camera already sent them.
+    Ffmpeg,
+}
+
+impl std::str::FromStr for StreamMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "mse" => Ok(StreamMode::Mse),
+            "raw" => Ok(StreamMode::Raw),
+            "ffmpeg" => Ok(StreamMode::Ffmpeg),
+            _ => Err(anyhow::anyhow!("Unknown stream mode '{}' (expected mse, raw or ffmpeg)", s)),
+        }
+    }
+}
+
+pub struct LiveviewRequest {
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub product_type: String,
+    pub serial: String,
+    /// Keep the clip Blink records for the session instead of deleting it afterwards.
+    pub record: bool,
+    pub options: LiveviewOptions,
+    pub mode: StreamMode,
+    /// Ask for the test pattern instead of the camera; honoured in debug builds only, see
+    /// `fake_live::enabled`.
+    pub fake: bool,
+}
+
+#[derive(Debug)]
+pub enum LiveviewError {
+    /// Blink rejected the liveview request.
+    Blink(anyhow::Error),
+    /// The camera was still busy after `liveview_busy_retries` attempts; callers should
+    /// not try again for `retry_after` seconds.
+    Busy { retry_after: u64 },
+    /// The liveview was granted but the IMMI server could not be reached.
+    Immi(anyhow::Error),
+}
+
+impl std::fmt::Display for LiveviewError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LiveviewError::Blink(e) => write!(f, "Blink API Error: {}", e),
+            LiveviewError::Busy { .. } => write!(f, "Camera remains busy after retries"),
+            LiveviewError::Immi(e) => write!(f, "IMMI Connection Failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LiveviewError {}
+
+/// A session whose command Blink would not extend again, so the stream is about to stop.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveExpiring {
+    pub session_id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub expires_at: DateTime<Utc>,
+    /// Seconds left when Blink refused, for a countdown.
+    pub seconds_left: i64,
+    pub extends: u64,
+}
+
+type ExpiringHook = Box<dyn Fn(&LiveExpiring) + Send + Sync>;
+
+static ON_EXPIRING: OnceLock<ExpiringHook> = OnceLock::new();
+
+/// Called when a session's command can't be extended any more, e.g. to warn the viewer.
+/// Only the first registration counts.
+pub fn on_expiring(notify: impl Fn(&LiveExpiring) + Send + Sync + 'static) {
+    let _ = ON_EXPIRING.set(Box::new(notify));
+}
+
+/// A running session. Dropping `chunks` (or cancelling the session) ends it.
+pub struct Liveview {
+    pub session: Arc<LiveSession>,
+    /// MPEG-TS data, primed according to the request's `StreamMode`.
+    pub chunks: mpsc::Receiver<Bytes>,
+}
+
+/// Starts a liveview and registers it with `registry`; the background tasks run until
+/// the session is cancelled, the consumer goes away, or Blink ends the command. The
+/// session's clip is then handed to `cleanup` unless it should be kept. With the fake
+/// source enabled nothing is asked of Blink and there is no clip to clean up.
+pub async fn start(
+    blink_client: &Arc<Mutex<BlinkClient>>,
+    registry: &Arc<SessionRegistry>,
+    cleanup_queue: &Arc<CleanupQueue>,
+    settings: &Settings,
+    request: LiveviewRequest,
+) -> Result<Liveview, LiveviewError> {
+    let started_at = Utc::now();
+    if fake_live::enabled(request.fake) {
+        log_control::info("liveview", || format!("Serving the test pattern instead of camera {}", request.camera_id));
+        let lv_res = LiveViewResponse {
+            server: fake_live::FAKE_SERVER.to_string(),
+            command_id: 0,
+            polling_interval: 0,
+            params: Default::default(),
+            duration: None,
+            continue_warning: None,
+        };
+        let writer = futures_util::sink::drain().sink_map_err(|never| match never {});
+        return Ok(run(blink_client, registry, cleanup_queue, settings, &request, started_at, &lv_res, fake_live::stream(), writer, None, true));
+    }
+
+    let lv_res = request_with_retry(blink_client, settings, &request).await?;
+    let connect_started = std::time::Instant::now();
+    let immi = ImmiStream::connect(&lv_res.server, &request.serial).await.map_err(|e| {
+        log_control::warn("immi", || format!("IMMI connection failed: {}", e));
+        LiveviewError::Immi(e)
+    })?;
+    let connect_time = connect_started.elapsed();
+    log_control::debug("immi", || format!("IMMI connected in {}ms", connect_time.as_millis()));
+    Ok(run(blink_client, registry, cleanup_queue, settings, &request, started_at, &lv_res, immi.reader, immi.writer, Some(connect_time), false))
+}
+
+/// Registers the session and spawns its keepalive, relay and cleanup tasks around an IMMI
+/// connection, or the fake source's stand-in for one.
+#[allow(clippy::too_many_arguments)]
+fn run<R, W>(
+    blink_client: &Arc<Mutex<BlinkClient>>,
+    registry: &Arc<SessionRegistry>,
+    cleanup_queue: &Arc<CleanupQueue>,
+    settings: &Settings,
+    request: &LiveviewRequest,
+    started_at: DateTime<Utc>,
+    lv_res: &LiveViewResponse,
+    reader: R,
+    writer: W,
+    connect_time: Option<std::time::Duration>,
+    fake: bool,
+) -> Liveview
+where
+    R: Stream<Item = std::io::Result<ImmiFrame>> + Unpin + Send + 'static,
+    W: Sink<ImmiFrame, Error = std::io::Error> + Unpin + Send + 'static,
+{
+    let cancel = CancellationToken::new();
+    let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let recorder = settings
+        .liveview_recording_dir
+        .as_deref()
+        .filter(|_| request.record)
+        .and_then(|dir| start_recording(Path::new(dir), settings.liveview_recording_format, &session_id, request, started_at));
+    let session = registry.register(
+        session_id,
+        request.network_id,
+        request.camera_id,
+        lv_res,
+        started_at,
+        recorder.as_ref().map(|(_, path)| path.to_string_lossy().into_owned()),
+        cancel.clone(),
+    );
+    session.stats.connected(connect_time);
+    session.stats.replay.set_window(settings.liveview_replay_secs);
+
+    tokio::spawn(keepalive(
+        cancel.clone(),
+        writer,
+        session.clone(),
+        blink_client.clone(),
+        (!fake).then_some(lv_res.command_id),
+        lv_res.polling_interval as u64,
+        if fake { None } else { Watchdog::new(lv_res) },
+    ));
+    tokio::spawn(relay(
+        cancel.clone(),
+        reader,
+        tx,
+        session.stats.clone(),
+        std::time::Duration::from_secs(settings.liveview_read_timeout_secs),
+        request.mode,
+        recorder.map(|(recorder, _)| recorder),
+    ));
+    tokio::spawn(cleanup(
+        cancel,
+        registry.clone(),
+        cleanup_queue.clone(),
+        session.id.clone(),
+        request.network_id,
+        request.camera_id,
+        fake || request.record || !settings.liveview_cleanup_enabled,
+        started_at,
+    ));
+
+    Liveview { session, chunks: rx }
+}
+
+/// Opens the recording for a session, or logs why it can't be made; the session goes
+/// ahead either way.
+fn start_recording(
+    dir: &Path,
+    format: RecordingFormat,
+    session_id: &str,
+    request: &LiveviewRequest,
+    started_at: DateTime<Utc>,
+) -> Option<(Recorder, PathBuf)> {
+    let meta = RecordingMeta {
+        session_id: session_id.to_string(),
+        network_id: request.network_id,
+        camera_id: request.camera_id,
+        started_at,
+    };
+    let started = recording::target(dir, request.camera_id, started_at, format)
+        .and_then(|path| Ok((Recorder::start(&path, format, meta)?, path)));
+    match started {
+        Ok(started) => Some(started),
+        Err(e) => {
+            log_control::warn("liveview", || format!("Not recording camera {}: {}", request.camera_id, e));
+            None
+        }
+    }
+}
+
+async fn request_with_retry(
+    blink_client: &Mutex<BlinkClient>,
+    settings: &Settings,
+    request: &LiveviewRequest,
+) -> Result<LiveViewResponse, LiveviewError> {
+    let mut delay = settings.liveview_retry_delay_secs;
+    for _ in 0..settings.liveview_busy_retries {
+        let res = {
+            let mut client = blink_client.lock().await;
+            let _ = crate::shared::refresh_and_persist(&mut client).await;
+            client.request_liveview(request.network_id, request.camera_id, &request.product_type, &request.options).await
+        };
+        let e = match res {
+            Ok(res) => return Ok(res),
+            Err(e) => e,
+        };
+        log_control::warn("liveview", || format!("Liveview request failed: {}", e));
+        let Some(BlinkError::CameraBusy { retry_after, .. }) = e.downcast_ref::<BlinkError>() else {
+            return Err(LiveviewError::Blink(e));
+        };
+        // Honour Blink's suggestion, but never hammer a busy camera faster than configured.
+        delay = retry_after.unwrap_or(settings.liveview_retry_delay_secs).max(1);
+        tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
+    }
+    Err(LiveviewError::Busy { retry_after: delay })
+}
+
+/// Keeps the command going past the `duration` Blink gave it: shortly before it runs out
+/// the command is extended, as long as the consumer is still taking chunks. When Blink
+/// refuses (the command reached its longest), the session is marked as expiring and
+/// `on_expiring` hears about it, so the viewer is warned before the picture freezes.
+struct Watchdog {
+    expires_at: DateTime<Utc>,
+    duration: i64,
+    lead: i64,
+    done: bool,
+}
+
+impl Watchdog {
+    /// `None` when Blink gave no duration to watch.
+    fn new(lv_res: &LiveViewResponse) -> Option<Self> {
+        let duration = lv_res.duration.filter(|d| *d > 0)?;
+        let lead = lv_res.continue_warning.unwrap_or(0).max(EXTEND_LEAD_SECS).min(duration / 2);
+        Some(Self { expires_at: Utc::now() + chrono::Duration::seconds(duration), duration, lead, done: false })
+    }
+
+    async fn check(&mut self, session: &LiveSession, blink_client: &Mutex<BlinkClient>) {
+        let left = (self.expires_at - Utc::now()).num_seconds();
+        if self.done || left > self.lead {
+            return;
+        }
+        if !session.stats.delivered_within(std::time::Duration::from_secs(IDLE_CONSUMER_SECS)) {
+            log_control::info("liveview", || format!("Not extending live session {}: nothing read for {}s", session.id, IDLE_CONSUMER_SECS));
+            self.done = true;
+            return;
+        }
+        let res = {
+            let client = blink_client.lock().await;
+            client.extend_liveview(session.network_id, session.command_id).await
+        };
+        match res {
+            Ok(duration) => {
+                session.stats.extended();
+                self.expires_at = Utc::now() + chrono::Duration::seconds(duration.filter(|d| *d > 0).unwrap_or(self.duration));
+                log_control::debug("liveview", || format!("Extended live session {} until {}", session.id, self.expires_at));
+            }
+            Err(e) => {
+                self.done = true;
+                log_control::warn("liveview", || format!("Blink would not extend live session {}; it ends in {}s: {}", session.id, left.max(0), e));
+                session.stats.expiring(self.expires_at);
+                if let Some(notify) = ON_EXPIRING.get() {
+                    notify(&LiveExpiring {
+                        session_id: session.id.clone(),
+                        network_id: session.network_id,
+                        camera_id: session.camera_id,
+                        expires_at: self.expires_at,
+                        seconds_left: left.max(0),
+                        extends: session.stats.extends.load(std::sync::atomic::Ordering::Relaxed),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Sends IMMI latency stats every second and a keepalive every ten, and polls the Blink
+/// command, if there is one, so the session ends when Blink does; `watchdog` extends the
+/// command meanwhile. Each keepalive's send time is noted in the session's stats so the
+/// relay can time the server's echo.
+async fn keepalive<W: Sink<ImmiFrame, Error = std::io::Error> + Unpin>(
+    cancel: CancellationToken,
+    mut immi_tx: W,
+    session: Arc<LiveSession>,
+    blink_client: Arc<Mutex<BlinkClient>>,
+    command_id: Option<i64>,
+    polling_interval: u64,
+    mut watchdog: Option<Watchdog>,
+) {
+    let stats = session.stats.clone();
+    let network_id = session.network_id;
+    let mut last_poll = std::time::Instant::now();
+    let mut keepalive_seq = 0u32;
+    let mut ticks = 0u32;
+
+    // Initial handshake packets
+    let _ = immi_tx.send(ImmiFrame::latency_stats()).await;
+    keepalive_seq += 1;
+    stats.keepalive_sent(keepalive_seq);
+    let _ = immi_tx.send(ImmiFrame::keepalive(keepalive_seq)).await;
+
+    loop {
+        tokio::select! {
+            _ = cancel.cancelled() => break,
+            _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
+                if immi_tx.send(ImmiFrame::latency_stats()).await.is_err() {
+                    break;
+                }
+
+                ticks += 1;
+                if ticks.is_multiple_of(10) {
+                    keepalive_seq += 1;
+                    stats.keepalive_sent(keepalive_seq);
+                    if immi_tx.send(ImmiFrame::keepalive(keepalive_seq)).await.is_err() {
+                        break;
+                    }
+                }
+
+                let Some(command_id) = command_id else { continue };
+                if let Some(watchdog) = watchdog.as_mut() {
+                    watchdog.check(&session, &blink_client).await;
+                }
+                if last_poll.elapsed().as_secs() >= polling_interval {
+                    let res = {
+                        let client = blink_client.lock().await;
+                        client.get_command_status(network_id, command_id).await
+                    };
+                    let Ok(status) = res else { break };
+                    let running = status["commands"].as_array()
+                        .into_iter()
+                        .flatten()
+                        .filter(|c| c["id"].as_i64() == Some(command_id))
+                        .any(|c| matches!(c["state_condition"].as_str(), Some("new" | "running")));
+                    if !running {
+                        break;
+                    }
+                    last_poll = std::time::Instant::now();
+                }
+            }
+        }
+    }
+    cancel.cancel(); // Ensure both tasks stop
+}
+
+/// The PAT and PMT `StreamMode::Ffmpeg` sends ahead of `first`, announcing the streams
+/// found in it or, failing that, H.264 video and AAC audio on the default PIDs.
+pub(crate) fn program_tables(first: &[u8]) -> [Bytes; 2] {
+    let mut streams = mpegts::elementary_streams(first);
+    if !streams.iter().any(|s| s.0 == mpegts::STREAM_TYPE_H264) {
+        streams.insert(0, (mpegts::STREAM_TYPE_H264, DEFAULT_VIDEO_PID));
+    }
+    if !streams.iter().any(|s| s.0 == mpegts::STREAM_TYPE_AAC) {
+        streams.push((mpegts::STREAM_TYPE_AAC, DEFAULT_AUDIO_PID));
+    }
+    let pcr_pid = streams.iter().find(|s| s.0 == mpegts::STREAM_TYPE_H264).map_or(DEFAULT_VIDEO_PID, |s| s.1);
+    [mpegts::pat_packet(mpegts::PMT_PID), mpegts::pmt_packet(mpegts::PMT_PID, pcr_pid, &streams)]
+}
+
+/// What became of a chunk offered to a full or closed channel.
+enum Delivery {
+    Sent,
+    Dropped,
+    Closed,
+}
+
+/// Hands chunks to the consumer without ever blocking the IMMI reader for long. When the
+/// channel is full, ordinary chunks are dropped at once and keyframe or PAT chunks get
+/// `KEY_CHUNK_WAIT_MS` to fit. After a drop, once the stream has shown keyframes, chunks
+/// are skipped until the next one so the decoder restarts on a clean picture.
+struct Backpressure {
+    tx: mpsc::Sender<Bytes>,
+    stats: Arc<LiveSessionStats>,
+    seen_keyframe: bool,
+    resyncing: bool,
+    dropped_since_warn: u64,
+    last_warn: std::time::Instant,
+}
+
+impl Backpressure {
+    fn new(tx: mpsc::Sender<Bytes>, stats: Arc<LiveSessionStats>) -> Self {
+        Self { tx, stats, seen_keyframe: false, resyncing: false, dropped_since_warn: 0, last_warn: std::time::Instant::now() }
+    }
+
+    async fn offer(&mut self, chunk: Bytes) -> Delivery {
+        let key = mpegts::has_random_access(&chunk) || mpegts::has_pat(&chunk);
+        self.seen_keyframe |= key;
+        if self.resyncing && !key {
+            return self.drop_chunk(chunk.len());
+        }
+        let len = chunk.len();
+        let sent = match self.tx.try_send(chunk) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Closed(_)) => return Delivery::Closed,
+            Err(mpsc::error::TrySendError::Full(chunk)) if key => {
+                match tokio::time::timeout(std::time::Duration::from_millis(KEY_CHUNK_WAIT_MS), self.tx.send(chunk)).await {
+                    Ok(Ok(())) => Ok(()),
+                    Ok(Err(_)) => return Delivery::Closed,
+                    Err(_) => Err(()),
+                }
+            }
+            Err(mpsc::error::TrySendError::Full(_)) => Err(()),
+        };
+        match sent {
+            Ok(()) => {
+                self.resyncing = false;
+                self.stats.add_bytes(len);
+                Delivery::Sent
+            }
+            Err(()) => self.drop_chunk(len),
+        }
+    }
+
+    fn drop_chunk(&mut self, len: usize) -> Delivery {
+        self.stats.add_dropped(len);
+        self.resyncing = self.seen_keyframe;
+        self.dropped_since_warn += 1;
+        if self.last_warn.elapsed().as_secs() >= DROP_WARN_INTERVAL_SECS {
+            log_control::warn("liveview", || format!(
+                "Live stream consumer is falling behind: dropped {} chunks in the last {}s",
+                self.dropped_since_warn,
+                self.last_warn.elapsed().as_secs()
+            ));
+            self.dropped_since_warn = 0;
+            self.last_warn = std::time::Instant::now();
+        }
+        Delivery::Dropped
+    }
+}
+
+/// Forwards MPEG-TS payloads from IMMI to `tx`. In `StreamMode::Mse` it pads with null
+/// packets while the camera wakes up so the consumer's connection stays open. A consumer
+/// that reads too slowly loses chunks (see `Backpressure`) instead of stalling the reader.
+/// Keepalive echoes and the stream's PCRs feed the session's latency figures, and every
+/// MPEG-TS payload goes into the replay buffer, and the recording if there is one, whether
+/// or not the consumer keeps up. Dropping `recorder` on the way out finishes the file.
+async fn relay<R: Stream<Item = std::io::Result<ImmiFrame>> + Unpin>(
+    cancel: CancellationToken,
+    mut immi_rx: R,
+    tx: mpsc::Sender<Bytes>,
+    stats: Arc<LiveSessionStats>,
+    read_timeout: std::time::Duration,
+    mode: StreamMode,
+    recorder: Option<Recorder>,
+) {
+    let null_packet = mpegts::null_packet();
+    if mode == StreamMode::Mse {
+        for _ in 0..3 {
+            if tx.send(null_packet.clone()).await.is_err() {
+                cancel.cancel();
+                return;
+            }
+        }
+    }
+
+    let mut mpegts_started = false;
+    let mut last_null_packet = std::time::Instant::now();
+    let stream_start_time = std::time::Instant::now();
+    let mut output = Backpressure::new(tx.clone(), stats.clone());
+
+    loop {
+        let frame_res = tokio::select! {
+            _ = cancel.cancelled() => break,
+            res = tokio::time::timeout(read_timeout, immi_rx.next()) => res,
+        };
+
+        match frame_res {
+            Ok(Some(Ok(frame))) => {
+                log_control::debug("immi", || format!("IMMI frame type {} with {} bytes", frame.msg_type, frame.payload.len()));
+                if frame.msg_type == MSG_KEEPALIVE {
+                    stats.keepalive_acked(frame.sequence);
+                } else if frame.msg_type == MSG_VIDEO && !frame.payload.is_empty() {
+                    if frame.payload[0] == 0x47 {
+                        if let Some(pcr) = mpegts::first_pcr(&frame.payload) {
+                            stats.observe_pcr(pcr);
+                        }
+                        if !mpegts_started {
+                            mpegts_started = true;
+                            stats.first_packet();
+                            log_control::info("liveview", || format!("First MPEG-TS packet received after {}ms", stream_start_time.elapsed().as_millis()));
+                            if mode == StreamMode::Ffmpeg && !mpegts::has_pat(&frame.payload) {
+                                for table in program_tables(&frame.payload) {
+                                    if tx.send(table).await.is_err() {
+                                        cancel.cancel();
+                                        return;
+                                    }
+                                }
+                            }
+                        }
+                        stats.replay.push(frame.payload.clone());
+                        if let Some(recorder) = &recorder {
+                            recorder.push(frame.payload.clone());
+                        }
+                        if let Delivery::Closed = output.offer(frame.payload).await {
+                            break;
+                        }
+                        last_null_packet = std::time::Instant::now();
+                    }
+                } else if mode == StreamMode::Mse && !mpegts_started && last_null_packet.elapsed().as_secs() >= 2 {
+                    // Send periodic null packets while waiting for real data; a full channel
+                    // already keeps the connection busy.
+                    if let Err(mpsc::error::TrySendError::Closed(_)) = tx.try_send(null_packet.clone()) {
+                        break;
+                    }
+                    last_null_packet = std::time::Instant::now();
+                }
+            }
+            Ok(Some(Err(e))) => {
+                log_control::warn("immi", || format!("IMMI read error: {}", e));
+                break;
+            }
+            Ok(None) => {
+                log_control::info("immi", || "IMMI connection closed".to_string());
+                break;
+            }
+            Err(_) => {
+                log_control::warn("immi", || "IMMI read timeout".to_string());
+                break;
+            }
+        }
+
+        if !mpegts_started && stream_start_time.elapsed().as_secs() > FIRST_PACKET_TIMEOUT_SECS {
+            log_control::warn("liveview", || "Stream timed out waiting for data".to_string());
+            break;
+        }
+    }
+    cancel.cancel();
+}
+
+/// Waits for the session to end, logs its summary, deregisters it, and unless `keep` is
+/// set queues the clip Blink saved for it for deletion.
+#[allow(clippy::too_many_arguments)]
+async fn cleanup(
+    cancel: CancellationToken,
+    registry: Arc<SessionRegistry>,
+    cleanup_queue: Arc<CleanupQueue>,
+    session_id: String,
+    network_id: i64,
+    camera_id: i64,
+    keep: bool,
+    started_at: DateTime<Utc>,
+) {
+    cancel.cancelled().await;
+    if let Some(session) = registry.get(&session_id) {
+        log_control::info("liveview", || session.summary());
+    }
+    registry.deregister(&session_id);
+    if !keep {
+        cleanup_queue.enqueue(&session_id, network_id, camera_id, started_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    /// One video packet carrying `index`, flagged as a random access point when `key`.
+    fn packet(index: u32, key: bool) -> Bytes {
+        let mut packet = vec![0xFFu8; mpegts::PACKET_LEN];
+        packet[..6].copy_from_slice(&[0x47, 0x41, 0x00, 0x30, 7, if key { 0x40 } else { 0x00 }]);
+        packet[12..16].copy_from_slice(&index.to_be_bytes());
+        Bytes::from(packet)
+    }
+
+    fn index_of(chunk: &[u8]) -> u32 {
+        u32::from_be_bytes(chunk[12..16].try_into().unwrap())
+    }
+
+    #[tokio::test]
+    async fn a_full_channel_drops_ordinary_chunks_at_once() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let stats = Arc::new(LiveSessionStats::default());
+        let mut output = Backpressure::new(tx, stats.clone());
+        assert!(matches!(output.offer(packet(0, false)).await, Delivery::Sent));
+
+        let started = Instant::now();
+        assert!(matches!(output.offer(packet(1, false)).await, Delivery::Dropped));
+        assert!(started.elapsed() < Duration::from_millis(10));
+
+        let started = Instant::now();
+        assert!(matches!(output.offer(packet(2, true)).await, Delivery::Dropped));
+        let waited = started.elapsed();
+        assert!(waited >= Duration::from_millis(KEY_CHUNK_WAIT_MS) && waited < Duration::from_millis(KEY_CHUNK_WAIT_MS * 3), "{:?}", waited);
+
+        assert_eq!(stats.dropped_chunks.load(std::sync::atomic::Ordering::Relaxed), 2);
+        assert_eq!(stats.dropped_bytes.load(std::sync::atomic::Ordering::Relaxed), 2 * mpegts::PACKET_LEN as u64);
+        assert_eq!(index_of(&rx.recv().await.unwrap()), 0);
+        drop(rx);
+        assert!(matches!(output.offer(packet(3, true)).await, Delivery::Closed));
+    }
+
+    #[tokio::test]
+    async fn a_keyframe_gets_room_freed_in_time() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut output = Backpressure::new(tx, Arc::new(LiveSessionStats::default()));
+        output.offer(packet(0, false)).await;
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(KEY_CHUNK_WAIT_MS / 4)).await;
+            let first = rx.recv().await.unwrap();
+            let second = rx.recv().await.unwrap();
+            assert_eq!((index_of(&first), index_of(&second)), (0, 1));
+        });
+        assert!(matches!(output.offer(packet(1, true)).await, Delivery::Sent));
+    }
+
+    #[tokio::test]
+    async fn after_a_drop_delivery_resumes_on_a_keyframe() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let mut output = Backpressure::new(tx, Arc::new(LiveSessionStats::default()));
+        output.offer(packet(0, true)).await;
+        output.offer(packet(1, false)).await;
+        rx.recv().await.unwrap();
+        // Room again, but the decoder would see a broken picture until the next keyframe.
+        assert!(matches!(output.offer(packet(2, false)).await, Delivery::Dropped));
+        assert!(matches!(output.offer(packet(3, true)).await, Delivery::Sent));
+        assert_eq!(index_of(&rx.recv().await.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn a_slow_consumer_never_stalls_the_reader() {
+        const FRAMES: u32 = 400;
+        const KEY_EVERY: u32 = 10;
+        // When the relay polled for each frame; the gaps are how long it spent on the one before.
+        let polls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = polls.clone();
+        let frames = futures_util::stream::unfold(0u32, move |index| {
+            let recorded = recorded.clone();
+            async move {
+                recorded.lock().unwrap().push(Instant::now());
+                // One packet interval between frames.
+                tokio::time::sleep(Duration::from_millis(2)).await;
+                (index < FRAMES).then(|| {
+                    let frame = ImmiFrame { msg_type: MSG_VIDEO, sequence: index, payload: packet(index, index % KEY_EVERY == 0) };
+                    (Ok(frame), index + 1)
+                })
+            }
+        });
+
+        let (tx, mut rx) = mpsc::channel::<Bytes>(4);
+        let stats = Arc::new(LiveSessionStats::default());
+        let consumer = tokio::spawn(async move {
+            let mut received = Vec::new();
+            while let Some(chunk) = rx.recv().await {
+                received.push(index_of(&chunk));
+                tokio::time::sleep(Duration::from_millis(25)).await;
+            }
+            received
+        });
+        relay(CancellationToken::new(), Box::pin(frames), tx, stats.clone(), Duration::from_secs(5), StreamMode::Raw, None).await;
+        let received = consumer.await.unwrap();
+
+        let polls = polls.lock().unwrap();
+        let longest = polls.windows(2).map(|w| w[1] - w[0]).max().unwrap();
+        assert!(longest < Duration::from_millis(KEY_CHUNK_WAIT_MS + 30), "the reader stalled for {:?}", longest);
+
+        let dropped = stats.dropped_chunks.load(std::sync::atomic::Ordering::Relaxed);
+        assert!(dropped > 0);
+        assert_eq!(dropped + received.len() as u64, FRAMES as u64);
+        // Every gap in what arrived is followed by a keyframe.
+        for pair in received.windows(2) {
+            assert!(pair[1] == pair[0] + 1 || pair[1] % KEY_EVERY == 0, "resumed on {} after {}", pair[1], pair[0]);
+        }
+    }
+}
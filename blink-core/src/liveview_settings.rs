@@ -0,0 +1,234 @@
+//! Typed access to the liveview toggles the homescreen exposes. Which ones show up depends
+//! on the account's plan and devices, so every flag lives in `LIVEVIEW_FLAGS` with the keys
+//! it has been seen under and where it is written; anything the scanner finds beyond these
+//! stays behind `shared::set_discovered_setting`.
+
+use crate::blink::{BlinkClient, BlinkError};
+use crate::shared::{CandidateDeviceKind, SettingWriteOutcome};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Who owns a flag, and so which update endpoint takes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagOwner {
+    /// Set on the network; written through the network update endpoint.
+    Network,
+    /// Set per device in one homescreen list (`cameras`, `owls`, `doorbells`); written
+    /// through that device's config update endpoint.
+    Device(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagKind {
+    Toggle,
+    /// A whole number of seconds.
+    Seconds,
+}
+
+#[derive(Debug, Clone)]
+pub struct LiveviewFlag {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub owner: FlagOwner,
+    pub kind: FlagKind,
+    /// Homescreen keys the flag has been seen under; it is written back under the one found.
+    pub keys: &'static [&'static str],
+}
+
+pub const LIVEVIEW_FLAGS: &[LiveviewFlag] = &[
+    LiveviewFlag {
+        name: "lv_save",
+        description: "Save every liveview as a clip",
+        owner: FlagOwner::Network,
+        kind: FlagKind::Toggle,
+        keys: &["lv_save", "liveview_save", "lv_save_enabled"],
+    },
+    LiveviewFlag {
+        name: "extended_liveview",
+        description: "Longest liveview before Blink ends it, on plans that allow extended sessions",
+        owner: FlagOwner::Network,
+        kind: FlagKind::Seconds,
+        keys: &["lv_max_duration", "extended_lv_duration", "liveview_max_duration"],
+    },
+    LiveviewFlag {
+        name: "doorbell_auto_save",
+        description: "Save the liveview when a doorbell press is answered",
+        owner: FlagOwner::Device("doorbells"),
+        kind: FlagKind::Toggle,
+        keys: &["lv_save_on_answer", "auto_save_on_answer", "answer_lv_save"],
+    },
+];
+
+/// The registry entry called `name`.
+pub fn flag(name: &str) -> Result<&'static LiveviewFlag, BlinkError> {
+    LIVEVIEW_FLAGS.iter()
+        .find(|f| f.name == name)
+        .ok_or_else(|| BlinkError::NotFound(format!("Unknown liveview setting {}", name)))
+}
+
+impl LiveviewFlag {
+    /// Rejects values the flag can't hold: toggles take a bool, durations a whole number
+    /// of seconds.
+    pub fn check(&self, value: &Value) -> Result<()> {
+        let ok = match self.kind {
+            FlagKind::Toggle => value.is_boolean(),
+            FlagKind::Seconds => value.is_u64(),
+        };
+        if !ok {
+            return Err(anyhow!("{} takes {}, not {}", self.name, match self.kind {
+                FlagKind::Toggle => "true or false",
+                FlagKind::Seconds => "a whole number of seconds",
+            }, value));
+        }
+        Ok(())
+    }
+
+    /// The key and raw value the flag has in `object`, if any.
+    fn read<'a>(&self, object: &'a Value) -> Option<(&'static str, &'a Value)> {
+        self.keys.iter().find_map(|key| Some((*key, object.get(key).filter(|v| !v.is_null())?)))
+    }
+
+    /// `value` in the shape `current` uses, so a toggle Blink stores as 0/1 stays a number.
+    fn encode(&self, value: &Value, current: &Value) -> Value {
+        match (value, current) {
+            (Value::Bool(on), Value::Number(_)) => Value::from(u8::from(*on)),
+            _ => value.clone(),
+        }
+    }
+
+    /// The raw value, normalized the way `check` expects.
+    fn decode(&self, raw: &Value) -> Value {
+        match (self.kind, raw) {
+            (FlagKind::Toggle, Value::Number(n)) => Value::Bool(n.as_i64().unwrap_or(0) != 0),
+            _ => raw.clone(),
+        }
+    }
+}
+
+/// One flag as it stands on one network or device.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveviewSetting {
+    pub flag: &'static str,
+    pub device_kind: Option<CandidateDeviceKind>,
+    /// The network id for network flags, the device id otherwise.
+    pub device_id: i64,
+    pub device_name: Option<String>,
+    /// The homescreen key it was found under.
+    pub key: &'static str,
+    pub value: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveviewSettingWrite {
+    pub flag: &'static str,
+    pub device_id: i64,
+    pub outcome: SettingWriteOutcome,
+    pub before: Value,
+    /// What the flag held after the write; `None` if it disappeared from the homescreen.
+    pub after: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// The network's homescreen entry and the devices in it that can own `flag`.
+fn owners(homescreen: &Value, network_id: i64, owner: FlagOwner) -> Vec<&Value> {
+    let list = match owner {
+        FlagOwner::Network => "networks",
+        FlagOwner::Device(list) => list,
+    };
+    let in_network = |item: &&Value| match owner {
+        FlagOwner::Network => item.get("id").and_then(Value::as_i64) == Some(network_id),
+        FlagOwner::Device(_) => item.get("network_id").and_then(Value::as_i64) == Some(network_id),
+    };
+    homescreen.get(list).and_then(Value::as_array).into_iter().flatten().filter(in_network).collect()
+}
+
+fn settings_in(homescreen: &Value, network_id: i64) -> Vec<LiveviewSetting> {
+    let mut found = Vec::new();
+    for flag in LIVEVIEW_FLAGS {
+        for object in owners(homescreen, network_id, flag.owner) {
+            let (Some(device_id), Some((key, raw))) = (object.get("id").and_then(Value::as_i64), flag.read(object)) else {
+                continue;
+            };
+            found.push(LiveviewSetting {
+                flag: flag.name,
+                device_kind: match flag.owner {
+                    FlagOwner::Network => Some(CandidateDeviceKind::Network),
+                    FlagOwner::Device(list) => CandidateDeviceKind::from_list(list),
+                },
+                device_id,
+                device_name: object.get("name").and_then(Value::as_str).map(str::to_string),
+                key,
+                value: flag.decode(raw),
+            });
+        }
+    }
+    found
+}
+
+/// Every registered flag the homescreen reports for the network and its devices.
+pub async fn get_liveview_settings(client: &BlinkClient, network_id: i64) -> Result<Vec<LiveviewSetting>> {
+    let homescreen = client.get_homescreen_json().await?;
+    if owners(&homescreen, network_id, FlagOwner::Network).is_empty() {
+        return Err(BlinkError::NotFound(format!("Network {} not found", network_id)).into());
+    }
+    Ok(settings_in(&homescreen, network_id))
+}
+
+/// Writes one flag and re-reads the homescreen to report whether it stuck. Network flags
+/// ignore `device_id`; device flags need it unless the network has only one such device.
+pub async fn set_liveview_setting(
+    client: &BlinkClient,
+    network_id: i64,
+    name: &str,
+    device_id: Option<i64>,
+    value: Value,
+) -> Result<LiveviewSettingWrite> {
+    let flag = flag(name)?;
+    flag.check(&value)?;
+    let homescreen = client.get_homescreen_json().await?;
+    let current = settings_in(&homescreen, network_id);
+    let matching: Vec<&LiveviewSetting> = current.iter()
+        .filter(|s| s.flag == flag.name)
+        .filter(|s| flag.owner == FlagOwner::Network || device_id.is_none_or(|id| s.device_id == id))
+        .collect();
+    let setting = match matching.as_slice() {
+        [one] => *one,
+        [] => return Err(BlinkError::NotFound(format!("{} is not reported for network {} on this account", flag.name, network_id)).into()),
+        _ => return Err(anyhow!("Several devices on network {} have {}; pick one with a device id", network_id, flag.name)),
+    };
+
+    let object = owners(&homescreen, network_id, flag.owner).into_iter()
+        .find(|o| o.get("id").and_then(Value::as_i64) == Some(setting.device_id));
+    let raw = object.and_then(|o| flag.read(o)).map(|(_, raw)| raw.clone()).unwrap_or(Value::Null);
+    let payload = flag.encode(&value, &raw);
+    let sent = match flag.owner {
+        FlagOwner::Network => client.set_network_field(network_id, setting.key, payload).await,
+        FlagOwner::Device(list) => {
+            let product_type = object.and_then(|o| o.get("type")).and_then(Value::as_str)
+                .unwrap_or_else(|| list.trim_end_matches('s'));
+            client.update_camera_config(network_id, setting.device_id, product_type, serde_json::json!({ setting.key: payload })).await
+        }
+    };
+
+    let after = match client.get_homescreen_json().await {
+        Ok(doc) => settings_in(&doc, network_id).into_iter()
+            .find(|s| s.flag == flag.name && s.device_id == setting.device_id)
+            .map(|s| s.value),
+        Err(e) if sent.is_ok() => return Err(e),
+        Err(_) => None,
+    };
+    let (outcome, error) = match sent {
+        Err(e) => (SettingWriteOutcome::Rejected, Some(e.to_string())),
+        Ok(()) if after.as_ref() == Some(&value) => (SettingWriteOutcome::Applied, None),
+        Ok(()) => (SettingWriteOutcome::Unchanged, None),
+    };
+    Ok(LiveviewSettingWrite {
+        flag: flag.name,
+        device_id: setting.device_id,
+        outcome,
+        before: setting.value.clone(),
+        after,
+        error,
+    })
+}
@@ -0,0 +1,265 @@
+//! Log levels that can be raised per target while the app runs, so a misbehaving liveview
+//! can be diagnosed with "turn on IMMI debug logging for five minutes" instead of a rebuild.
+//! Overrides expire on their own. A debug capture writes its targets' debug lines into a
+//! file of its own, redacted the way self-test reports are, without raising what reaches
+//! stderr. This is a small filter of its own rather than `tracing` with an env filter, since
+//! nothing else in the app logs through `tracing`.
+
+use crate::self_test;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Targets that log through here. `blink` covers requests to the Blink API, `http` the
+/// hosted server's own requests.
+pub const TARGETS: &[&str] = &["blink", "http", "immi", "liveview"];
+/// Longest a level override or capture may last.
+pub const MAX_DURATION_SECS: u64 = 3600;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// What every target logs when nothing overrides it.
+pub const DEFAULT_LEVEL: Level = Level::Info;
+
+impl std::fmt::Display for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelOverride {
+    pub target: String,
+    pub level: Level,
+    pub expires_at: DateTime<Utc>,
+}
+
+struct Override {
+    level: Level,
+    until: Instant,
+}
+
+struct Capture {
+    id: u64,
+    targets: Vec<String>,
+    file: File,
+}
+
+#[derive(Default)]
+struct State {
+    overrides: HashMap<String, Override>,
+    captures: Vec<Capture>,
+    next_capture: u64,
+}
+
+static STATE: OnceLock<Mutex<State>> = OnceLock::new();
+
+fn state() -> std::sync::MutexGuard<'static, State> {
+    STATE.get_or_init(Default::default).lock().unwrap()
+}
+
+fn check_target(target: &str) -> Result<()> {
+    if !TARGETS.contains(&target) {
+        return Err(anyhow!("Unknown log target {}; expected one of {}", target, TARGETS.join(", ")));
+    }
+    Ok(())
+}
+
+fn check_duration(duration_secs: u64) -> Result<Duration> {
+    if !(1..=MAX_DURATION_SECS).contains(&duration_secs) {
+        return Err(anyhow!("Duration must be between 1 and {} seconds", MAX_DURATION_SECS));
+    }
+    Ok(Duration::from_secs(duration_secs))
+}
+
+/// The level `target` logs to stderr at now; overrides past their time no longer count.
+/// Captures don't change it.
+pub fn level(target: &str) -> Level {
+    level_in(&state(), target)
+}
+
+fn level_in(state: &State, target: &str) -> Level {
+    state.overrides.get(target)
+        .filter(|o| o.until > Instant::now())
+        .map_or(DEFAULT_LEVEL, |o| o.level)
+}
+
+/// Logs `target` at `level` for `duration_secs`, then goes back to `DEFAULT_LEVEL`.
+pub fn set_level(target: &str, level: Level, duration_secs: u64) -> Result<LevelOverride> {
+    check_target(target)?;
+    let duration = check_duration(duration_secs)?;
+    state().overrides.insert(target.to_string(), Override { level, until: Instant::now() + duration });
+    eprintln!("Log level for {} set to {} for {}s", target, level, duration_secs);
+    Ok(LevelOverride {
+        target: target.to_string(),
+        level,
+        expires_at: Utc::now() + chrono::Duration::seconds(duration_secs as i64),
+    })
+}
+
+/// Writes `message` to stderr if `target` logs at `level`, and to every capture that
+/// includes `target` if `level` is debug or above. The closure only runs when something
+/// will see the line.
+pub fn log(target: &str, level: Level, message: impl FnOnce() -> String) {
+    let mut state = state();
+    let to_stderr = level <= level_in(&state, target);
+    let captured = level <= Level::Debug && state.captures.iter().any(|c| c.targets.iter().any(|t| t == target));
+    if !to_stderr && !captured {
+        return;
+    }
+    let message = message();
+    if captured {
+        let line = format!("{} {} {}: {}\n", Utc::now().to_rfc3339(), level, target, self_test::redact(&message));
+        for capture in state.captures.iter_mut().filter(|c| c.targets.iter().any(|t| t == target)) {
+            let _ = capture.file.write_all(line.as_bytes());
+        }
+    }
+    drop(state);
+    if to_stderr {
+        eprintln!("{}", message);
+    }
+}
+
+pub fn warn(target: &str, message: impl FnOnce() -> String) {
+    log(target, Level::Warn, message)
+}
+
+pub fn info(target: &str, message: impl FnOnce() -> String) {
+    log(target, Level::Info, message)
+}
+
+pub fn debug(target: &str, message: impl FnOnce() -> String) {
+    log(target, Level::Debug, message)
+}
+
+/// Checks the arguments of a `debug_capture` before anything is started.
+pub fn check_capture(targets: &[String], duration_secs: u64) -> Result<Duration> {
+    if targets.is_empty() {
+        return Err(anyhow!("Pick at least one log target to capture"));
+    }
+    for target in targets {
+        check_target(target)?;
+    }
+    check_duration(duration_secs)
+}
+
+/// A capture that is running. It stops when `finish` returns or when it is dropped.
+pub struct DebugCapture {
+    id: u64,
+    path: PathBuf,
+    until: tokio::time::Instant,
+}
+
+impl DebugCapture {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Waits for the capture's time to run out, then stops it and marks the file as ended.
+    pub async fn finish(self) -> PathBuf {
+        tokio::time::sleep_until(self.until).await;
+        let mut state = state();
+        if let Some(capture) = state.captures.iter_mut().find(|c| c.id == self.id) {
+            let _ = writeln!(capture.file, "# ended {}", Utc::now().to_rfc3339());
+        }
+        state.captures.retain(|c| c.id != self.id);
+        self.path.clone()
+    }
+}
+
+impl Drop for DebugCapture {
+    fn drop(&mut self) {
+        state().captures.retain(|c| c.id != self.id);
+    }
+}
+
+/// Starts logging `targets` at debug level into a new file in `dir` for `duration_secs`.
+/// Returns as soon as the file is ready; the lines arrive while the capture runs.
+pub fn start_capture(dir: &Path, targets: &[String], duration_secs: u64) -> Result<DebugCapture> {
+    let duration = check_capture(targets, duration_secs)?;
+    std::fs::create_dir_all(dir)?;
+    let id = {
+        let mut state = state();
+        state.next_capture += 1;
+        state.next_capture
+    };
+    // The id keeps captures started in the same second apart, and `create_new` never
+    // truncates a file another capture is still writing to.
+    let path = dir.join(format!("debug-capture-{}-{}.log", Utc::now().format("%Y%m%d-%H%M%S"), id));
+    let mut file = File::options().write(true).create_new(true).open(&path)?;
+    writeln!(file, "# {} at debug level for {}s from {}", targets.join(", "), duration_secs, Utc::now().to_rfc3339())?;
+    state().captures.push(Capture { id, targets: targets.to_vec(), file });
+    eprintln!("Capturing {} logs to {}", targets.join(", "), path.display());
+    Ok(DebugCapture { id, path, until: tokio::time::Instant::now() + duration })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn a_capture_takes_debug_lines_without_raising_stderr() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = start_capture(dir.path(), &["immi".to_string()], 1).unwrap();
+        let path = capture.path().to_path_buf();
+        // Starting returns straight away, with the header already on disk.
+        assert!(std::fs::read_to_string(&path).unwrap().starts_with("# immi at debug level for 1s"));
+        assert_eq!(level("immi"), DEFAULT_LEVEL);
+
+        debug("immi", || "Connecting to https://immi.example/stream?token=abc123".to_string());
+        let mut ran = false;
+        debug("http", || {
+            ran = true;
+            "not captured".to_string()
+        });
+        assert!(!ran, "a debug line nothing will see is never formatted");
+
+        assert_eq!(capture.finish().await, path);
+        debug("immi", || "after the end".to_string());
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("DEBUG immi: Connecting to https://immi.example/stream?[redacted]"), "{}", contents);
+        assert!(!contents.contains("abc123") && !contents.contains("not captured") && !contents.contains("after the end"));
+        assert!(contents.lines().last().unwrap().starts_with("# ended "));
+    }
+
+    #[test]
+    fn captures_started_together_get_their_own_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let first = start_capture(dir.path(), &["liveview".to_string()], 60).unwrap();
+        let second = start_capture(dir.path(), &["liveview".to_string()], 60).unwrap();
+        assert_ne!(first.path(), second.path());
+        for capture in [&first, &second] {
+            assert!(std::fs::read_to_string(capture.path()).unwrap().starts_with("# liveview at debug level"));
+        }
+    }
+
+    #[test]
+    fn a_dropped_capture_stops() {
+        let dir = tempfile::tempdir().unwrap();
+        let capture = start_capture(dir.path(), &["liveview".to_string()], 60).unwrap();
+        let path = capture.path().to_path_buf();
+        drop(capture);
+        debug("liveview", || "dropped".to_string());
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("dropped"));
+    }
+}
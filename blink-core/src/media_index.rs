@@ -0,0 +1,557 @@
+//! Optional SQLite index of every clip we have seen, so history can be searched (and
+//! archived clips browsed) without paging through Blink. The index is a no-op until enabled
+//! in settings.
+
+use crate::blink::{parse_blink_timestamp, BlinkClient, DeleteResult, DeleteStatus, MediaItem, UsageReport};
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rusqlite::{params, params_from_iter, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::sync::Mutex;
+
+pub const INDEX_FILE: &str = "media_index.sqlite";
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+/// Background refreshes only look this far back; `rebuild` walks the full history.
+pub const SYNC_SINCE_DAYS: i64 = 2;
+pub const SYNC_INTERVAL_SECS: u64 = 10 * 60;
+pub const SYNC_MAX_PAGES: i64 = 3;
+const REBUILD_SINCE_DAYS: i64 = 365;
+const REBUILD_MAX_PAGES: i64 = 100;
+
+/// Applied in order; the database's `user_version` records how many have run.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE media (
+        id INTEGER PRIMARY KEY,
+        camera_id INTEGER,
+        camera_name TEXT,
+        network_id INTEGER,
+        network_name TEXT,
+        created_at TEXT,
+        media_type TEXT,
+        source TEXT,
+        media_url TEXT,
+        thumbnail_url TEXT,
+        local_path TEXT,
+        watched INTEGER NOT NULL DEFAULT 0,
+        deleted INTEGER NOT NULL DEFAULT 0,
+        indexed_at TEXT NOT NULL
+    );
+    CREATE INDEX media_created ON media(created_at);
+    CREATE INDEX media_camera ON media(camera_id, created_at);
+    CREATE INDEX media_url ON media(media_url);",
+];
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexedMedia {
+    pub id: i64,
+    pub camera_id: Option<i64>,
+    pub camera_name: Option<String>,
+    pub network_id: Option<i64>,
+    pub network_name: Option<String>,
+    /// RFC 3339, UTC.
+    pub created_at: Option<String>,
+    pub media_type: Option<String>,
+    pub source: Option<String>,
+    pub media_url: Option<String>,
+    pub thumbnail_url: Option<String>,
+    pub local_path: Option<String>,
+    pub watched: bool,
+    pub deleted: bool,
+}
+
+impl From<&MediaItem> for IndexedMedia {
+    fn from(item: &MediaItem) -> Self {
+        Self {
+            id: item.id,
+            camera_id: item.device_id,
+            camera_name: item.device_name.clone(),
+            network_id: item.network_id,
+            network_name: item.network_name.clone(),
+            created_at: item.created_at.as_deref().and_then(parse_blink_timestamp).map(|t| t.to_rfc3339()),
+            media_type: item.media_type.clone(),
+            source: item.source.clone(),
+            media_url: item.media.clone(),
+            thumbnail_url: item.thumbnail.clone(),
+            local_path: None,
+            watched: item.watched,
+            deleted: item.deleted,
+        }
+    }
+}
+
+/// Search parameters; every field is optional. `from`/`to` are RFC 3339 timestamps and
+/// `text` matches camera or network names.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MediaFilter {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub camera_id: Option<i64>,
+    pub network_id: Option<i64>,
+    pub text: Option<String>,
+    pub include_deleted: bool,
+    pub page: Option<u32>,
+    pub page_size: Option<u32>,
+}
+
+impl MediaFilter {
+    fn page(&self) -> u32 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    fn page_size(&self) -> u32 {
+        self.page_size.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+    }
+
+    fn bound(value: &Option<String>) -> Result<Option<String>> {
+        value.as_deref()
+            .filter(|v| !v.is_empty())
+            .map(|v| parse_blink_timestamp(v).map(|t| t.to_rfc3339()).ok_or_else(|| anyhow!("Invalid timestamp: {}", v)))
+            .transpose()
+    }
+
+    fn matches(&self, item: &IndexedMedia, from: &Option<String>, to: &Option<String>) -> bool {
+        let text = self.text.as_deref().map(str::to_lowercase);
+        let names_match = |text: &str| {
+            [&item.camera_name, &item.network_name].iter()
+                .any(|name| name.as_deref().is_some_and(|n| n.to_lowercase().contains(text)))
+        };
+        (self.include_deleted || !item.deleted)
+            && self.camera_id.is_none_or(|id| item.camera_id == Some(id))
+            && self.network_id.is_none_or(|id| item.network_id == Some(id))
+            && from.as_ref().is_none_or(|from| item.created_at.as_ref().is_some_and(|c| c >= from))
+            && to.as_ref().is_none_or(|to| item.created_at.as_ref().is_some_and(|c| c <= to))
+            && text.as_deref().is_none_or(names_match)
+    }
+}
+
+/// When a clip was made and by which camera; see `MediaIndex::clip_times`.
+#[derive(Debug, Clone)]
+pub struct ClipTime {
+    pub camera_id: Option<i64>,
+    pub camera_name: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Clips started within one UTC minute; see `MediaIndex::clips_per_minute`.
+#[derive(Debug, Clone)]
+pub struct ClipMinute {
+    pub minute: DateTime<Utc>,
+    pub clips: u64,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MediaSource {
+    Index,
+    Live,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MediaSearchPage {
+    pub items: Vec<IndexedMedia>,
+    /// Total matches across all pages (for `live`, only within the fetched page).
+    pub total: u64,
+    pub page: u32,
+    pub page_size: u32,
+    pub source: MediaSource,
+}
+
+/// Handle to the index database. Every method is a no-op (or `None`) while disabled.
+pub struct MediaIndex {
+    path: PathBuf,
+    conn: std::sync::Mutex<Option<Connection>>,
+}
+
+fn open(path: &PathBuf) -> Result<Connection> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut conn = Connection::open(path)?;
+    conn.pragma_update(None, "journal_mode", "WAL")?;
+    let version: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+    }
+    Ok(conn)
+}
+
+fn row_to_media(row: &rusqlite::Row<'_>) -> rusqlite::Result<IndexedMedia> {
+    Ok(IndexedMedia {
+        id: row.get("id")?,
+        camera_id: row.get("camera_id")?,
+        camera_name: row.get("camera_name")?,
+        network_id: row.get("network_id")?,
+        network_name: row.get("network_name")?,
+        created_at: row.get("created_at")?,
+        media_type: row.get("media_type")?,
+        source: row.get("source")?,
+        media_url: row.get("media_url")?,
+        thumbnail_url: row.get("thumbnail_url")?,
+        local_path: row.get("local_path")?,
+        watched: row.get("watched")?,
+        deleted: row.get("deleted")?,
+    })
+}
+
+impl MediaIndex {
+    pub fn new(path: PathBuf, enabled: bool) -> Self {
+        let index = Self { path, conn: std::sync::Mutex::new(None) };
+        index.set_enabled(enabled);
+        index
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.conn.lock().unwrap().is_some()
+    }
+
+    /// Opens (migrating if needed) or closes the database. Disabling keeps the file.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut conn = self.conn.lock().unwrap();
+        if !enabled {
+            *conn = None;
+        } else if conn.is_none() {
+            match open(&self.path) {
+                Ok(opened) => *conn = Some(opened),
+                Err(e) => eprintln!("Failed to open media index {}: {}", self.path.display(), e),
+            }
+        }
+    }
+
+    /// Writes a consistent copy of the database to `dest`: `VACUUM INTO` while open,
+    /// otherwise a plain copy. Returns false when there is no database yet.
+    pub fn backup(&self, dest: &Path) -> Result<bool> {
+        let conn = self.conn.lock().unwrap();
+        let _ = std::fs::remove_file(dest);
+        match conn.as_ref() {
+            Some(conn) => {
+                conn.execute("VACUUM INTO ?1", [dest.to_string_lossy()])?;
+                Ok(true)
+            }
+            None if self.path.exists() => {
+                std::fs::copy(&self.path, dest)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Swaps the database for the file at `src`, closing and reopening it if it was open.
+    pub fn replace_with(&self, src: &Path) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let was_open = conn.take().is_some();
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = self.path.clone().into_os_string();
+            sidecar.push(suffix);
+            let _ = std::fs::remove_file(sidecar);
+        }
+        let renamed = std::fs::rename(src, &self.path);
+        if was_open {
+            *conn = Some(open(&self.path)?);
+        }
+        Ok(renamed?)
+    }
+
+    fn with_conn<T>(&self, f: impl FnOnce(&mut Connection) -> Result<T>) -> Result<Option<T>> {
+        let mut conn = self.conn.lock().unwrap();
+        match conn.as_mut() {
+            Some(conn) => f(conn).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Records what Blink reported. The local path is left alone and a clip stays watched
+    /// once either side has marked it.
+    pub fn upsert(&self, items: &[MediaItem]) -> Result<()> {
+        if items.is_empty() {
+            return Ok(());
+        }
+        let now = chrono::Utc::now().to_rfc3339();
+        self.with_conn(|conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT INTO media (id, camera_id, camera_name, network_id, network_name, created_at,
+                        media_type, source, media_url, thumbnail_url, watched, deleted, indexed_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+                     ON CONFLICT(id) DO UPDATE SET
+                        camera_id = excluded.camera_id, camera_name = excluded.camera_name,
+                        network_id = excluded.network_id, network_name = excluded.network_name,
+                        created_at = excluded.created_at, media_type = excluded.media_type,
+                        source = excluded.source, media_url = excluded.media_url,
+                        thumbnail_url = excluded.thumbnail_url, watched = MAX(watched, excluded.watched),
+                        deleted = excluded.deleted,
+                        indexed_at = excluded.indexed_at",
+                )?;
+                for item in items.iter().map(IndexedMedia::from) {
+                    stmt.execute(params![
+                        item.id, item.camera_id, item.camera_name, item.network_id, item.network_name,
+                        item.created_at, item.media_type, item.source, item.media_url, item.thumbnail_url,
+                        item.watched, item.deleted, now,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Camera and creation time of every clip (deleted ones left out) created from `from`
+    /// up to `to`, for reports. `None` when the index is disabled.
+    pub fn clip_times(&self, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Option<Vec<ClipTime>>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare(
+                "SELECT camera_id, camera_name, created_at FROM media
+                 WHERE deleted = 0 AND created_at >= ?1 AND created_at < ?2 ORDER BY created_at",
+            )?;
+            let bound = |t: DateTime<Utc>| t.format("%Y-%m-%dT%H:%M:%S").to_string();
+            let rows = stmt.query_map(params![bound(from), bound(to)], |row| {
+                Ok((row.get::<_, Option<i64>>(0)?, row.get::<_, Option<String>>(1)?, row.get::<_, Option<String>>(2)?))
+            })?;
+            let mut clips = Vec::new();
+            for row in rows {
+                let (camera_id, camera_name, created_at) = row?;
+                let Some(created_at) = created_at.as_deref().and_then(parse_blink_timestamp) else { continue };
+                clips.push(ClipTime { camera_id, camera_name, created_at });
+            }
+            Ok(clips)
+        })
+    }
+
+    /// Clips of `camera_id` (deleted ones left out) created from `from` up to `to`, counted
+    /// per UTC minute; minutes rather than hours so zones with half-hour offsets can be
+    /// bucketed. `None` when the index is disabled or its oldest clip is newer than `from`,
+    /// since it can't vouch for the start of the range then.
+    pub fn clips_per_minute(&self, camera_id: i64, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Option<Vec<ClipMinute>>> {
+        let bound = |t: DateTime<Utc>| t.format("%Y-%m-%dT%H:%M:%S").to_string();
+        let counts = self.with_conn(|conn| {
+            let oldest: Option<String> = conn.query_row("SELECT MIN(created_at) FROM media", [], |row| row.get(0))?;
+            if oldest.is_none_or(|oldest| oldest > bound(from)) {
+                return Ok(None);
+            }
+            let mut stmt = conn.prepare(
+                "SELECT substr(created_at, 1, 16) AS minute, COUNT(*) FROM media
+                 WHERE deleted = 0 AND camera_id = ?1 AND created_at >= ?2 AND created_at < ?3
+                 GROUP BY minute",
+            )?;
+            let rows = stmt.query_map(params![camera_id, bound(from), bound(to)], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+            })?;
+            let mut counts = Vec::new();
+            for row in rows {
+                let (minute, count) = row?;
+                let Ok(minute) = chrono::NaiveDateTime::parse_from_str(&minute, "%Y-%m-%dT%H:%M") else { continue };
+                counts.push(ClipMinute { minute: minute.and_utc(), clips: count as u64 });
+            }
+            Ok(Some(counts))
+        })?;
+        Ok(counts.flatten())
+    }
+
+    /// Parses a raw media page as returned by Blink and indexes it.
+    pub fn upsert_raw_page(&self, raw: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        let items: Vec<MediaItem> = serde_json::from_str::<serde_json::Value>(raw)
+            .ok()
+            .and_then(|json| json.get("media").cloned())
+            .and_then(|media| serde_json::from_value(media).ok())
+            .unwrap_or_default();
+        if let Err(e) = self.upsert(&items) {
+            eprintln!("Failed to index media page: {}", e);
+        }
+    }
+
+    /// Links an archived file to its clip. `url` may be the Blink path or a full URL.
+    pub fn set_local_path(&self, url: &str, local_path: &str) -> Result<()> {
+        self.with_conn(|conn| {
+            conn.execute(
+                "UPDATE media SET local_path = ?2
+                 WHERE media_url = ?1 OR (media_url IS NOT NULL AND ?1 LIKE '%' || media_url)",
+                params![url, local_path],
+            )?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    pub fn mark_deleted(&self, results: &[DeleteResult]) -> Result<()> {
+        let ids: Vec<i64> = results.iter()
+            .filter(|r| matches!(r.status, DeleteStatus::Deleted | DeleteStatus::NotFound))
+            .map(|r| r.id)
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.with_conn(|conn| {
+            let placeholders = vec!["?"; ids.len()].join(",");
+            conn.execute(&format!("UPDATE media SET deleted = 1 WHERE id IN ({})", placeholders), params_from_iter(&ids))?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    pub fn mark_watched(&self, ids: &[i64]) -> Result<()> {
+        if ids.is_empty() {
+            return Ok(());
+        }
+        self.with_conn(|conn| {
+            let placeholders = vec!["?"; ids.len()].join(",");
+            conn.execute(&format!("UPDATE media SET watched = 1 WHERE id IN ({})", placeholders), params_from_iter(ids))?;
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Bytes of archived clips per camera; `None` when the index is disabled. Files that
+    /// have been moved or removed since they were archived are not counted.
+    pub fn local_bytes_by_camera(&self) -> Result<Option<HashMap<i64, u64>>> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare("SELECT camera_id, local_path FROM media WHERE camera_id IS NOT NULL AND local_path IS NOT NULL")?;
+            let rows = stmt.query_map([], |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?)))?;
+            let mut totals = HashMap::new();
+            for row in rows {
+                let (camera_id, path) = row?;
+                if let Ok(meta) = std::fs::metadata(&path) {
+                    *totals.entry(camera_id).or_insert(0) += meta.len();
+                }
+            }
+            Ok(totals)
+        })
+    }
+
+    /// Fills in `local_bytes` for every camera in `report`. Leaves the report alone when
+    /// the index is disabled; failures are logged.
+    pub fn add_local_bytes(&self, report: &mut UsageReport) {
+        let totals = match self.local_bytes_by_camera() {
+            Ok(Some(totals)) => totals,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Failed to total archived clips: {}", e);
+                return;
+            }
+        };
+        for camera in report.networks.iter_mut().flat_map(|n| n.cameras.iter_mut()) {
+            camera.local_bytes = Some(totals.get(&camera.id).copied().unwrap_or(0));
+        }
+    }
+
+    /// Searches the index; `None` when it is disabled.
+    pub fn search(&self, filter: &MediaFilter) -> Result<Option<MediaSearchPage>> {
+        let from = MediaFilter::bound(&filter.from)?;
+        let to = MediaFilter::bound(&filter.to)?;
+        self.with_conn(|conn| {
+            let mut clauses = Vec::new();
+            let mut args: Vec<rusqlite::types::Value> = Vec::new();
+            if !filter.include_deleted {
+                clauses.push("deleted = 0".to_string());
+            }
+            if let Some(id) = filter.camera_id {
+                args.push(id.into());
+                clauses.push(format!("camera_id = ?{}", args.len()));
+            }
+            if let Some(id) = filter.network_id {
+                args.push(id.into());
+                clauses.push(format!("network_id = ?{}", args.len()));
+            }
+            if let Some(from) = &from {
+                args.push(from.clone().into());
+                clauses.push(format!("created_at >= ?{}", args.len()));
+            }
+            if let Some(to) = &to {
+                args.push(to.clone().into());
+                clauses.push(format!("created_at <= ?{}", args.len()));
+            }
+            if let Some(text) = filter.text.as_deref().filter(|t| !t.is_empty()) {
+                args.push(format!("%{}%", text.to_lowercase()).into());
+                clauses.push(format!("(lower(camera_name) LIKE ?{n} OR lower(network_name) LIKE ?{n})", n = args.len()));
+            }
+            let where_sql = if clauses.is_empty() { String::new() } else { format!("WHERE {}", clauses.join(" AND ")) };
+
+            let total: u64 = conn.query_row(
+                &format!("SELECT COUNT(*) FROM media {}", where_sql),
+                params_from_iter(&args),
+                |row| row.get(0),
+            )?;
+
+            let (page, page_size) = (filter.page(), filter.page_size());
+            args.push(i64::from(page_size).into());
+            args.push((i64::from(page - 1) * i64::from(page_size)).into());
+            let mut stmt = conn.prepare(&format!(
+                "SELECT * FROM media {} ORDER BY created_at DESC, id DESC LIMIT ?{} OFFSET ?{}",
+                where_sql,
+                args.len() - 1,
+                args.len(),
+            ))?;
+            let items = stmt.query_map(params_from_iter(&args), row_to_media)?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(MediaSearchPage { items, total, page, page_size, source: MediaSource::Index })
+        })
+    }
+
+    /// Pulls recent media from Blink into the index.
+    pub async fn sync(&self, client: &Mutex<BlinkClient>, since_days: i64, max_pages: i64) -> Result<usize> {
+        if !self.is_enabled() {
+            return Ok(0);
+        }
+        let items = {
+            let client = shared::snapshot(client).await;
+            if client.token.is_none() {
+                return Ok(0);
+            }
+            client.get_all_media(since_days, max_pages).await?
+        };
+        self.upsert(&items)?;
+        Ok(items.len())
+    }
+
+    /// Drops everything Blink can re-supply and re-reads the full history. Rows with an
+    /// archived file or watched state are kept so clips past retention stay browsable.
+    pub async fn rebuild(&self, client: &Mutex<BlinkClient>) -> Result<usize> {
+        let cleared = self.with_conn(|conn| {
+            conn.execute("DELETE FROM media WHERE local_path IS NULL AND watched = 0", [])?;
+            Ok(())
+        })?;
+        if cleared.is_none() {
+            return Err(anyhow!("Media index is disabled"));
+        }
+        self.sync(client, REBUILD_SINCE_DAYS, REBUILD_MAX_PAGES).await
+    }
+}
+
+/// Answers a search from one page of the live API when the index is disabled.
+pub async fn search_live(client: &Mutex<BlinkClient>, filter: &MediaFilter) -> Result<MediaSearchPage> {
+    let from = MediaFilter::bound(&filter.from)?;
+    let to = MediaFilter::bound(&filter.to)?;
+    let since_days = from.as_deref()
+        .and_then(parse_blink_timestamp)
+        .map(|from| (chrono::Utc::now() - from).num_days() + 1)
+        .unwrap_or(30)
+        .max(1);
+    let items = shared::snapshot(client).await.get_media_page(i64::from(filter.page()), since_days).await?;
+    let mut items: Vec<IndexedMedia> = items.iter()
+        .map(IndexedMedia::from)
+        .filter(|item| filter.matches(item, &from, &to))
+        .collect();
+    items.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+    Ok(MediaSearchPage {
+        total: items.len() as u64,
+        page: filter.page(),
+        page_size: items.len() as u32,
+        items,
+        source: MediaSource::Live,
+    })
+}
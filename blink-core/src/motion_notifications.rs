@@ -0,0 +1,176 @@
+//! Whether motion pushes fire at all, separately from arming: switching them off silences a
+//! flapping camera while it keeps recording. Where the switch lives differs by region and
+//! account. Some networks carry it in their homescreen entry under one of several names,
+//! and the rest only have the account-wide `motion` flag in the notification config. The
+//! network key is resolved from what the candidate scanner finds on the network, known
+//! names first, so a payload that renames it still resolves.
+
+use crate::blink::{BlinkClient, BlinkError};
+use crate::shared::{self, CandidateDeviceKind, LiveviewSettingCandidate, SettingWriteOutcome};
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Network keys seen holding the switch, preferred in this order over anything else the
+/// scanner turns up.
+pub const NETWORK_KEYS: &[&str] = &[
+    "motion_notifications",
+    "motion_notifications_enabled",
+    "notifications_enabled",
+    "motion_alerts_enabled",
+    "push_notifications",
+];
+
+/// Where the switch was found, and so what a write changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MotionNotificationScope {
+    /// A key on the network's homescreen entry; only this network is affected.
+    Network,
+    /// The account's notification config; every network is affected.
+    Account,
+}
+
+/// The switch as it stands for one network.
+#[derive(Debug, Clone, Serialize)]
+pub struct MotionNotifications {
+    pub network_id: i64,
+    pub scope: MotionNotificationScope,
+    /// The homescreen key, or `motion` for the account flag.
+    pub key: String,
+    /// `None` when the account flag has never been set.
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MotionNotificationWrite {
+    pub network_id: i64,
+    pub scope: MotionNotificationScope,
+    pub key: String,
+    pub outcome: SettingWriteOutcome,
+    pub before: Option<bool>,
+    /// What the switch held after the write; `None` if it disappeared.
+    pub after: Option<bool>,
+    pub error: Option<String>,
+}
+
+fn as_flag(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::Number(n) => n.as_i64().map(|n| n != 0),
+        _ => None,
+    }
+}
+
+fn key_of(candidate: &LiveviewSettingCandidate) -> &str {
+    candidate.path.rsplit('.').next().unwrap_or(&candidate.path)
+}
+
+/// The candidate on the network's own entry that best looks like the switch: a known key
+/// in `NETWORK_KEYS` order, else the first other toggle the scanner found there.
+pub fn resolve_network_key(homescreen: &Value, network_id: i64) -> Option<LiveviewSettingCandidate> {
+    let mut found: Vec<LiveviewSettingCandidate> = shared::motion_notification_candidates(std::slice::from_ref(homescreen))
+        .into_iter()
+        .filter(|c| c.device_kind == Some(CandidateDeviceKind::Network) && c.device_id == Some(network_id))
+        .filter(|c| as_flag(&c.value).is_some())
+        .collect();
+    found.sort_by_key(|c| NETWORK_KEYS.iter().position(|k| *k == key_of(c)).unwrap_or(NETWORK_KEYS.len()));
+    found.into_iter().next()
+}
+
+/// The switch and, for a network key, the raw value it holds.
+async fn read(client: &BlinkClient, network_id: i64) -> Result<(MotionNotifications, Option<Value>)> {
+    let homescreen = client.get_homescreen_json().await?;
+    let known = homescreen.get("networks").and_then(Value::as_array).into_iter().flatten()
+        .any(|n| n.get("id").and_then(Value::as_i64) == Some(network_id));
+    if !known {
+        return Err(BlinkError::NotFound(format!("Network {} not found", network_id)).into());
+    }
+    if let Some(candidate) = resolve_network_key(&homescreen, network_id) {
+        let state = MotionNotifications {
+            network_id,
+            scope: MotionNotificationScope::Network,
+            key: key_of(&candidate).to_string(),
+            enabled: as_flag(&candidate.value),
+        };
+        return Ok((state, Some(candidate.value)));
+    }
+    let config = client.get_notification_config().await?;
+    let state = MotionNotifications { network_id, scope: MotionNotificationScope::Account, key: "motion".to_string(), enabled: config.motion };
+    Ok((state, None))
+}
+
+/// Reads the switch for `network_id`, from its homescreen entry or else the account config.
+pub async fn get_motion_notifications(client: &BlinkClient, network_id: i64) -> Result<MotionNotifications> {
+    Ok(read(client, network_id).await?.0)
+}
+
+/// Turns motion pushes on or off for `network_id` and reads the switch back to report
+/// whether the change stuck. A network key keeps the 0/1 or bool form Blink used for it.
+pub async fn set_motion_notifications(client: &BlinkClient, network_id: i64, enabled: bool) -> Result<MotionNotificationWrite> {
+    let (current, raw) = read(client, network_id).await?;
+    let sent = match current.scope {
+        MotionNotificationScope::Network => {
+            let payload = match raw {
+                Some(Value::Number(_)) => Value::from(u8::from(enabled)),
+                _ => Value::Bool(enabled),
+            };
+            client.set_network_field(network_id, &current.key, payload).await
+        }
+        MotionNotificationScope::Account => {
+            let mut config = client.get_notification_config().await?;
+            config.motion = Some(enabled);
+            client.update_notification_config(&config).await
+        }
+    };
+
+    let after = match get_motion_notifications(client, network_id).await {
+        Ok(now) if now.scope == current.scope && now.key == current.key => now.enabled,
+        Ok(_) => None,
+        Err(e) if sent.is_ok() => return Err(e),
+        Err(_) => None,
+    };
+    let (outcome, error) = match sent {
+        Err(e) => (SettingWriteOutcome::Rejected, Some(e.to_string())),
+        Ok(()) if after == Some(enabled) => (SettingWriteOutcome::Applied, None),
+        Ok(()) => (SettingWriteOutcome::Unchanged, None),
+    };
+    Ok(MotionNotificationWrite {
+        network_id,
+        scope: current.scope,
+        key: current.key,
+        outcome,
+        before: current.enabled,
+        after,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(fixture: &str, network_id: i64) -> Option<(String, Option<bool>)> {
+        let homescreen: Value = serde_json::from_str(fixture).unwrap();
+        resolve_network_key(&homescreen, network_id).map(|c| (key_of(&c).to_string(), as_flag(&c.value)))
+    }
+
+    #[test]
+    fn boolean_network_flags_resolve_to_the_known_key_first() {
+        let fixture = include_str!("../tests/fixtures/homescreen_motion_notifications_flags.json");
+        // `notifications_enabled` is also on the network, but `motion_notifications` ranks first.
+        assert_eq!(resolved(fixture, 1001), Some(("motion_notifications".to_string(), Some(false))));
+        assert_eq!(resolved(fixture, 1002), Some(("motion_notifications_enabled".to_string(), Some(true))));
+        // A camera's own toggle doesn't count for its network, which falls back to the account flag.
+        assert_eq!(resolved(fixture, 1003), None);
+        assert_eq!(resolved(fixture, 9999), None);
+    }
+
+    #[test]
+    fn numeric_network_flags_resolve_including_unknown_names() {
+        let fixture = include_str!("../tests/fixtures/homescreen_motion_notifications_numeric.json");
+        // No known key here, so the scanner's own find is used, read as 0/1.
+        assert_eq!(resolved(fixture, 2001), Some(("push_motion_alerts".to_string(), Some(false))));
+        assert_eq!(resolved(fixture, 2002), Some(("push_notifications".to_string(), Some(true))));
+    }
+}
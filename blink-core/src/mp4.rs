@@ -0,0 +1,1226 @@
+//! MPEG-TS to MP4 remuxing for live view recordings, without re-encoding. `Remuxer` takes
+//! the camera's TS packets as they arrive and writes a fragmented MP4 (H.264 video and, if
+//! the camera sends it, AAC audio) one fragment per keyframe interval, so an interrupted
+//! recording is still playable. `Remuxer::faststart` then rewrites it as a regular MP4 with
+//! the `moov` in front of the media, which browsers and phones can start playing before
+//! the whole file has loaded. `duration_secs` reads either layout back.
+
+use crate::mpegts;
+use anyhow::{anyhow, bail, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// PES timestamps and the video track run on the 90 kHz MPEG clock.
+const VIDEO_TIMESCALE: u32 = 90_000;
+const MOVIE_TIMESCALE: u32 = 1_000;
+/// A fragment is cut at the first keyframe at least this far into it.
+const FRAGMENT_TICKS: u64 = VIDEO_TIMESCALE as u64;
+/// Samples per AAC frame.
+const AAC_FRAME_SAMPLES: u32 = 1024;
+/// Used for the last video sample, which has no successor to measure against, when there
+/// was no earlier one either.
+const DEFAULT_FRAME_TICKS: u32 = 3_000;
+/// How much audio is held back while waiting for the first keyframe.
+const EARLY_AUDIO_TICKS: u64 = 2 * VIDEO_TIMESCALE as u64;
+const AAC_SAMPLE_RATES: [u32; 13] = [96_000, 88_200, 64_000, 48_000, 44_100, 32_000, 24_000, 22_050, 16_000, 12_000, 11_025, 8_000, 7_350];
+const VIDEO_TRACK: u32 = 1;
+const AUDIO_TRACK: u32 = 2;
+
+/// One PES packet, reassembled from the TS packets of its PID.
+struct Pes {
+    stream_id: u8,
+    pts: Option<u64>,
+    dts: Option<u64>,
+    data: Vec<u8>,
+}
+
+/// Reassembles PES packets from TS packets. PSI (PAT, PMT) and anything else that doesn't
+/// start with a PES header is ignored; video stream ids are taken as H.264 and audio as
+/// AAC, as in `mpegts::elementary_streams`.
+#[derive(Default)]
+struct Demuxer {
+    /// Bytes of a packet split across two chunks.
+    partial: Vec<u8>,
+    pes: HashMap<u16, Vec<u8>>,
+}
+
+impl Demuxer {
+    fn push(&mut self, data: &[u8], out: &mut Vec<Pes>) {
+        let mut buf = std::mem::take(&mut self.partial);
+        buf.extend_from_slice(data);
+        let mut pos = 0;
+        while pos + mpegts::PACKET_LEN <= buf.len() {
+            if buf[pos] != 0x47 {
+                pos += 1;
+                continue;
+            }
+            self.packet(&buf[pos..pos + mpegts::PACKET_LEN], out);
+            pos += mpegts::PACKET_LEN;
+        }
+        buf.drain(..pos);
+        self.partial = buf;
+    }
+
+    fn packet(&mut self, packet: &[u8], out: &mut Vec<Pes>) {
+        let pid = (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2]);
+        let start = packet[1] & 0x40 != 0;
+        let payload_start = match packet[3] & 0x30 {
+            0x10 => 4,
+            0x30 => 5 + usize::from(packet[4]),
+            _ => return,
+        };
+        let Some(payload) = packet.get(payload_start..) else { return };
+        if start {
+            if let Some(pes) = self.pes.remove(&pid).and_then(|buf| parse_pes(&buf)) {
+                out.push(pes);
+            }
+            if payload.starts_with(&[0x00, 0x00, 0x01]) {
+                self.pes.insert(pid, payload.to_vec());
+            }
+        } else if let Some(buf) = self.pes.get_mut(&pid) {
+            buf.extend_from_slice(payload);
+        }
+    }
+
+    /// Hands over the PES packets still being assembled, at the end of the stream.
+    fn flush(&mut self, out: &mut Vec<Pes>) {
+        let mut pids: Vec<u16> = self.pes.keys().copied().collect();
+        pids.sort_unstable();
+        for pid in pids {
+            if let Some(pes) = self.pes.remove(&pid).and_then(|buf| parse_pes(&buf)) {
+                out.push(pes);
+            }
+        }
+    }
+}
+
+fn parse_pes(buf: &[u8]) -> Option<Pes> {
+    if buf.len() < 9 {
+        return None;
+    }
+    let stream_id = buf[3];
+    let flags = buf[7] >> 6;
+    let body = 9 + usize::from(buf[8]);
+    let pts = (flags & 0x2 != 0).then(|| buf.get(9..14).map(timestamp)).flatten();
+    let dts = (flags == 0x3).then(|| buf.get(14..19).map(timestamp)).flatten();
+    let declared = usize::from(u16::from_be_bytes([buf[4], buf[5]]));
+    let end = if declared == 0 { buf.len() } else { (6 + declared).min(buf.len()) };
+    Some(Pes { stream_id, pts, dts, data: buf.get(body..end)?.to_vec() })
+}
+
+fn timestamp(b: &[u8]) -> u64 {
+    (u64::from(b[0] & 0x0E) << 29) | (u64::from(b[1]) << 22) | (u64::from(b[2] & 0xFE) << 14) | (u64::from(b[3]) << 7) | (u64::from(b[4]) >> 1)
+}
+
+/// Undoes the 33-bit wrap of PES timestamps.
+#[derive(Default)]
+struct Unwrap {
+    last: Option<u64>,
+    offset: u64,
+}
+
+impl Unwrap {
+    fn apply(&mut self, ts: u64) -> u64 {
+        const WRAP: u64 = 1 << 33;
+        if let Some(last) = self.last {
+            if ts + WRAP / 2 < last {
+                self.offset += WRAP;
+            }
+        }
+        self.last = Some(ts);
+        ts + self.offset
+    }
+}
+
+/// Splits an Annex B byte stream into NAL units.
+fn nal_units(data: &[u8]) -> Vec<&[u8]> {
+    let mut units = Vec::new();
+    let mut start = None;
+    let mut i = 0;
+    while i + 3 <= data.len() {
+        if data[i..i + 3] == [0, 0, 1] {
+            if let Some(s) = start {
+                let mut end = i;
+                while end > s && data[end - 1] == 0 {
+                    end -= 1;
+                }
+                units.push(&data[s..end]);
+            }
+            i += 3;
+            start = Some(i);
+        } else {
+            i += 1;
+        }
+    }
+    if let Some(s) = start {
+        units.push(&data[s..]);
+    }
+    units.retain(|nal| !nal.is_empty());
+    units
+}
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl Bits<'_> {
+    fn bit(&mut self) -> Result<u32> {
+        let byte = *self.data.get(self.pos / 8).ok_or_else(|| anyhow!("SPS ends early"))?;
+        let bit = (byte >> (7 - self.pos % 8)) & 1;
+        self.pos += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn ue(&mut self) -> Result<u32> {
+        let mut zeros = 0;
+        while self.bit()? == 0 {
+            zeros += 1;
+            if zeros > 31 {
+                bail!("Malformed Exp-Golomb code in SPS");
+            }
+        }
+        let mut value = 0u32;
+        for _ in 0..zeros {
+            value = (value << 1) | self.bit()?;
+        }
+        Ok((1u32 << zeros) - 1 + value)
+    }
+
+    fn se(&mut self) -> Result<i32> {
+        let v = self.ue()?;
+        Ok(if v % 2 == 1 { v.div_ceil(2) as i32 } else { -((v / 2) as i32) })
+    }
+}
+
+/// Width and height of the picture an H.264 SPS (NAL header included) describes.
+fn sps_dimensions(sps: &[u8]) -> Result<(u32, u32)> {
+    let mut rbsp = Vec::with_capacity(sps.len());
+    for (i, &b) in sps.iter().enumerate().skip(1) {
+        if b == 3 && i >= 3 && sps[i - 1] == 0 && sps[i - 2] == 0 {
+            continue;
+        }
+        rbsp.push(b);
+    }
+    // Checked after unescaping: emulation prevention bytes can make a long enough NAL short.
+    if rbsp.len() < 4 {
+        bail!("SPS too short");
+    }
+    let profile = rbsp[0];
+    let mut r = Bits { data: &rbsp[3..], pos: 0 };
+    r.ue()?;
+    let mut chroma_format = 1;
+    if matches!(profile, 100 | 110 | 122 | 244 | 44 | 83 | 86 | 118 | 128 | 138 | 139 | 134 | 135) {
+        chroma_format = r.ue()?;
+        if chroma_format == 3 {
+            r.bit()?;
+        }
+        r.ue()?;
+        r.ue()?;
+        r.bit()?;
+        if r.bit()? == 1 {
+            for i in 0..if chroma_format == 3 { 12 } else { 8 } {
+                if r.bit()? == 1 {
+                    let size = if i < 6 { 16 } else { 64 };
+                    let (mut last, mut next) = (8i32, 8i32);
+                    for _ in 0..size {
+                        if next != 0 {
+                            next = (last + r.se()? + 256) % 256;
+                        }
+                        if next != 0 {
+                            last = next;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    r.ue()?;
+    match r.ue()? {
+        0 => {
+            r.ue()?;
+        }
+        1 => {
+            r.bit()?;
+            r.se()?;
+            r.se()?;
+            for _ in 0..r.ue()? {
+                r.se()?;
+            }
+        }
+        _ => {}
+    }
+    r.ue()?;
+    r.bit()?;
+    let width_mbs = r.ue()? + 1;
+    let height_units = r.ue()? + 1;
+    let frame_mbs_only = r.bit()?;
+    if frame_mbs_only == 0 {
+        r.bit()?;
+    }
+    r.bit()?;
+    let (mut crop_x, mut crop_y) = (0, 0);
+    if r.bit()? == 1 {
+        let (left, right, top, bottom) = (r.ue()?, r.ue()?, r.ue()?, r.ue()?);
+        let (unit_x, unit_y) = match chroma_format {
+            0 => (1, 2 - frame_mbs_only),
+            1 => (2, 2 * (2 - frame_mbs_only)),
+            2 => (2, 2 - frame_mbs_only),
+            _ => (1, 2 - frame_mbs_only),
+        };
+        crop_x = (left + right) * unit_x;
+        crop_y = (top + bottom) * unit_y;
+    }
+    let width = (width_mbs * 16).checked_sub(crop_x);
+    let height = ((2 - frame_mbs_only) * height_units * 16).checked_sub(crop_y);
+    match (width, height) {
+        (Some(w), Some(h)) if w > 0 && h > 0 => Ok((w, h)),
+        _ => bail!("SPS crops the picture away"),
+    }
+}
+
+/// An AAC stream's AudioSpecificConfig inputs, from its ADTS headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AacConfig {
+    object_type: u8,
+    rate_index: u8,
+    channels: u8,
+}
+
+impl AacConfig {
+    fn sample_rate(&self) -> u32 {
+        AAC_SAMPLE_RATES[usize::from(self.rate_index)]
+    }
+
+    fn specific_config(&self) -> [u8; 2] {
+        let v = (u16::from(self.object_type) << 11) | (u16::from(self.rate_index) << 7) | (u16::from(self.channels) << 3);
+        v.to_be_bytes()
+    }
+}
+
+/// The raw AAC frames in an ADTS byte stream.
+fn adts_frames(data: &[u8]) -> Vec<(AacConfig, &[u8])> {
+    let mut frames = Vec::new();
+    let mut pos = 0;
+    while pos + 7 <= data.len() {
+        let h = &data[pos..];
+        if h[0] != 0xFF || h[1] & 0xF6 != 0xF0 {
+            pos += 1;
+            continue;
+        }
+        let header_len = if h[1] & 0x01 == 1 { 7 } else { 9 };
+        let len = (usize::from(h[3] & 0x03) << 11) | (usize::from(h[4]) << 3) | (usize::from(h[5]) >> 5);
+        let rate_index = (h[2] >> 2) & 0x0F;
+        if len <= header_len || pos + len > data.len() || usize::from(rate_index) >= AAC_SAMPLE_RATES.len() {
+            break;
+        }
+        let config = AacConfig {
+            object_type: (h[2] >> 6) + 1,
+            rate_index,
+            channels: ((h[2] & 0x01) << 2) | (h[3] >> 6),
+        };
+        frames.push((config, &h[header_len..len]));
+        pos += len;
+    }
+    frames
+}
+
+
+struct Sample {
+    /// Decode time in the track's timescale, from the start of the recording.
+    dts: u64,
+    cts_offset: u32,
+    sync: bool,
+    data: Vec<u8>,
+}
+
+/// A sample already in the fragmented file, kept for `Finished::faststart`.
+#[derive(Clone, Copy)]
+struct Written {
+    offset: u64,
+    size: u32,
+    duration: u32,
+    cts_offset: u32,
+    sync: bool,
+}
+
+enum Codec {
+    Avc { sps: Vec<u8>, pps: Vec<u8>, width: u32, height: u32 },
+    Aac(AacConfig),
+}
+
+struct Track {
+    id: u32,
+    timescale: u32,
+    codec: Codec,
+    pending: Vec<Sample>,
+    written: Vec<Written>,
+}
+
+impl Track {
+    fn new(id: u32, timescale: u32, codec: Codec) -> Self {
+        Self { id, timescale, codec, pending: Vec::new(), written: Vec::new() }
+    }
+
+    fn duration(&self) -> u64 {
+        self.written.iter().map(|s| u64::from(s.duration)).sum()
+    }
+}
+
+/// Writes the fragmented MP4 for one recording. Nothing is written before the first
+/// keyframe that comes with its SPS and PPS; audio from before that point is dropped too,
+/// so both tracks start together. Audio that first shows up after the first fragment has
+/// no track to go in and is dropped as well.
+pub struct Remuxer<W: Write> {
+    out: W,
+    /// Bytes written to `out` so far.
+    position: u64,
+    demux: Demuxer,
+    clock: Unwrap,
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    /// The first keyframe's DTS (90 kHz, unwrapped): time zero of the recording.
+    origin: Option<u64>,
+    /// Audio PES (PTS, data) waiting for `origin`. A video PES is only complete once the
+    /// next one starts, so audio for the keyframe's first moments can arrive before it.
+    early_audio: Vec<(u64, Vec<u8>)>,
+    video: Option<Track>,
+    audio: Option<Track>,
+    /// Set once the init segment is out.
+    initialized: bool,
+    sequence: u32,
+    /// Where the fragment being collected starts, in video ticks.
+    fragment_start: u64,
+}
+
+/// A completed fragmented MP4 and the sample tables to rewrite it with.
+pub struct Finished {
+    tracks: Vec<Track>,
+}
+
+impl<W: Write> Remuxer<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            position: 0,
+            demux: Demuxer::default(),
+            clock: Unwrap::default(),
+            sps: None,
+            pps: None,
+            origin: None,
+            early_audio: Vec::new(),
+            video: None,
+            audio: None,
+            initialized: false,
+            sequence: 0,
+            fragment_start: 0,
+        }
+    }
+
+    /// Feeds a chunk of TS packets, writing a fragment whenever one is complete.
+    pub fn push(&mut self, data: &[u8]) -> Result<()> {
+        let mut pes = Vec::new();
+        self.demux.push(data, &mut pes);
+        pes.into_iter().try_for_each(|pes| self.pes(pes))
+    }
+
+    /// Writes what is left as the last fragment and closes the writer. Fails if the stream never produced a
+    /// keyframe to start from, in which case nothing usable was written.
+    pub fn finish(mut self) -> Result<Finished> {
+        let mut pes = Vec::new();
+        self.demux.flush(&mut pes);
+        pes.into_iter().try_for_each(|pes| self.pes(pes))?;
+        if self.video.is_none() {
+            bail!("The stream had no H.264 keyframe to start the recording from");
+        }
+        self.flush_fragment(None)?;
+        self.out.flush()?;
+        let tracks = [self.video, self.audio].into_iter().flatten().filter(|t| !t.written.is_empty()).collect();
+        Ok(Finished { tracks })
+    }
+
+    fn pes(&mut self, pes: Pes) -> Result<()> {
+        let Some(raw) = pes.pts else { return Ok(()) };
+        match pes.stream_id {
+            0xE0..=0xEF => {
+                let pts = self.clock.apply(raw);
+                let behind = pes.dts.map_or(0, |dts| raw.wrapping_sub(dts) & ((1 << 33) - 1));
+                self.video_frame(pts.saturating_sub(behind), pts, &pes.data)
+            }
+            0xC0..=0xDF => {
+                // Audio shares the program clock, so unwrap it against the video's epoch.
+                let offset = self.clock.offset;
+                let pts = match self.clock.last {
+                    Some(last) if raw + (1 << 32) < last => raw + offset + (1 << 33),
+                    Some(last) if raw > last + (1 << 32) && offset > 0 => raw + offset - (1 << 33),
+                    _ => raw + offset,
+                };
+                if self.origin.is_some() {
+                    self.audio_frames(pts, &pes.data);
+                } else {
+                    self.early_audio.retain(|(early, _)| early + EARLY_AUDIO_TICKS >= pts);
+                    self.early_audio.push((pts, pes.data));
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn video_frame(&mut self, dts: u64, pts: u64, data: &[u8]) -> Result<()> {
+        let mut sync = false;
+        let mut sample = Vec::with_capacity(data.len() + 16);
+        for nal in nal_units(data) {
+            match nal[0] & 0x1F {
+                7 => self.sps = Some(nal.to_vec()),
+                8 => self.pps = Some(nal.to_vec()),
+                // Access unit delimiters mean nothing inside an MP4 sample.
+                9 => {}
+                kind => {
+                    sync |= kind == 5;
+                    sample.extend_from_slice(&(nal.len() as u32).to_be_bytes());
+                    sample.extend_from_slice(nal);
+                }
+            }
+        }
+        if sample.is_empty() {
+            return Ok(());
+        }
+        if self.video.is_none() {
+            let (Some(sps), Some(pps), true) = (&self.sps, &self.pps, sync) else { return Ok(()) };
+            let (width, height) = sps_dimensions(sps)?;
+            self.origin = Some(dts);
+            self.video = Some(Track::new(VIDEO_TRACK, VIDEO_TIMESCALE, Codec::Avc { sps: sps.clone(), pps: pps.clone(), width, height }));
+            for (pts, data) in std::mem::take(&mut self.early_audio) {
+                self.audio_frames(pts, &data);
+            }
+        }
+        let origin = self.origin.unwrap_or(dts);
+        let Some(relative) = dts.checked_sub(origin) else { return Ok(()) };
+        if sync && relative >= self.fragment_start + FRAGMENT_TICKS {
+            self.flush_fragment(Some(relative))?;
+        }
+        if let Some(video) = self.video.as_mut() {
+            let cts_offset = pts.saturating_sub(dts).min(u64::from(u32::MAX)) as u32;
+            video.pending.push(Sample { dts: relative, cts_offset, sync, data: sample });
+        }
+        Ok(())
+    }
+
+    fn audio_frames(&mut self, pts: u64, data: &[u8]) {
+        let Some(start) = self.origin.and_then(|origin| pts.checked_sub(origin)) else { return };
+        if self.initialized && self.audio.is_none() {
+            return;
+        }
+        for (i, (config, frame)) in adts_frames(data).into_iter().enumerate() {
+            let track = self.audio.get_or_insert_with(|| Track::new(AUDIO_TRACK, config.sample_rate(), Codec::Aac(config)));
+            if !matches!(track.codec, Codec::Aac(c) if c == config) {
+                continue;
+            }
+            let dts = start * u64::from(track.timescale) / u64::from(VIDEO_TIMESCALE) + i as u64 * u64::from(AAC_FRAME_SAMPLES);
+            track.pending.push(Sample { dts, cts_offset: 0, sync: true, data: frame.to_vec() });
+        }
+    }
+
+    /// Writes the pending samples before `until` (video ticks; all of them when `None`) as
+    /// one `moof` and `mdat`, after the init segment if this is the first fragment.
+    fn flush_fragment(&mut self, until: Option<u64>) -> Result<()> {
+        let Some(video) = self.video.as_mut() else { return Ok(()) };
+        let mut previous = video.written.last().map_or(DEFAULT_FRAME_TICKS, |s| s.duration);
+        let pending = std::mem::take(&mut video.pending);
+        let next_dts: Vec<Option<u64>> = pending.iter().skip(1).map(|s| Some(s.dts)).chain([until]).collect();
+        let mut video_run = Vec::with_capacity(pending.len());
+        for (sample, next) in pending.into_iter().zip(next_dts) {
+            let duration = next.map_or(0, |next| next.saturating_sub(sample.dts).min(u64::from(u32::MAX)) as u32);
+            let duration = if duration == 0 { previous } else { duration };
+            previous = duration;
+            video_run.push((sample, duration));
+        }
+
+        let mut audio_run = Vec::new();
+        if let Some(audio) = self.audio.as_mut() {
+            let split = match until {
+                Some(until) => {
+                    let cutoff = until * u64::from(audio.timescale) / u64::from(VIDEO_TIMESCALE);
+                    audio.pending.iter().position(|s| s.dts >= cutoff).unwrap_or(audio.pending.len())
+                }
+                None => audio.pending.len(),
+            };
+            let rest = audio.pending.split_off(split);
+            audio_run = std::mem::replace(&mut audio.pending, rest).into_iter().map(|s| (s, AAC_FRAME_SAMPLES)).collect();
+        }
+
+        if !self.initialized {
+            let tracks: Vec<&Track> = [self.video.as_ref(), self.audio.as_ref()].into_iter().flatten().collect();
+            let init = [ftyp(b"iso5", &[b"iso5", b"iso6", b"avc1", b"mp41"]), fragmented_moov(&tracks)].concat();
+            self.write(&init)?;
+            self.initialized = true;
+        }
+        if let Some(until) = until {
+            self.fragment_start = until;
+        }
+        let runs: Vec<(u32, Vec<(Sample, u32)>)> = [(VIDEO_TRACK, video_run), (AUDIO_TRACK, audio_run)]
+            .into_iter()
+            .filter(|(_, run)| !run.is_empty())
+            .collect();
+        if runs.is_empty() {
+            return Ok(());
+        }
+        self.write_fragment(runs)
+    }
+
+    fn write_fragment(&mut self, runs: Vec<(u32, Vec<(Sample, u32)>)>) -> Result<()> {
+        self.sequence += 1;
+        let moof = |data_offsets: &[u32]| -> Vec<u8> {
+            let trafs: Vec<u8> = runs.iter().zip(data_offsets).flat_map(|((id, run), offset)| traf(*id, run, *offset)).collect();
+            mp4_box(b"moof", &[&full_box(b"mfhd", 0, 0, &self.sequence.to_be_bytes()), &trafs])
+        };
+        // Offsets don't change the box's length, so measure it first and then fill them in.
+        let moof_len = moof(&vec![0; runs.len()]).len() as u64;
+        let mut data_offsets = Vec::with_capacity(runs.len());
+        let mut offset = moof_len + 8;
+        for (_, run) in &runs {
+            data_offsets.push(offset as u32);
+            offset += run.iter().map(|(s, _)| s.data.len() as u64).sum::<u64>();
+        }
+        let moof = moof(&data_offsets);
+        let start = self.position;
+        self.write(&moof)?;
+        self.write(&((offset - moof_len) as u32).to_be_bytes())?;
+        self.write(b"mdat")?;
+        for (id, run) in runs {
+            let mut written = Vec::with_capacity(run.len());
+            for (sample, duration) in run {
+                written.push(Written {
+                    offset: self.position,
+                    size: sample.data.len() as u32,
+                    duration,
+                    cts_offset: sample.cts_offset,
+                    sync: sample.sync,
+                });
+                self.write(&sample.data)?;
+            }
+            if let Some(track) = [self.video.as_mut(), self.audio.as_mut()].into_iter().flatten().find(|t| t.id == id) {
+                track.written.extend(written);
+            }
+        }
+        debug_assert_eq!(self.position, start + offset);
+        Ok(())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.out.write_all(data)?;
+        self.position += data.len() as u64;
+        Ok(())
+    }
+}
+
+impl Finished {
+    /// Length of the video track.
+    pub fn duration_secs(&self) -> f64 {
+        self.tracks
+            .iter()
+            .find(|t| t.id == VIDEO_TRACK)
+            .map_or(0.0, |t| t.duration() as f64 / f64::from(t.timescale))
+    }
+
+    /// Rewrites the fragmented file at `fragmented` (the one this remuxer wrote) into a
+    /// regular MP4 at `dest` with the `moov` ahead of the media, copying the samples in
+    /// the order they were recorded. Returns the bytes written.
+    pub fn faststart(&self, fragmented: &Path, dest: &Path) -> Result<u64> {
+        let mut order: Vec<(usize, usize)> = self
+            .tracks
+            .iter()
+            .enumerate()
+            .flat_map(|(t, track)| (0..track.written.len()).map(move |s| (t, s)))
+            .collect();
+        order.sort_by_key(|&(t, s)| self.tracks[t].written[s].offset);
+        let media_len: u64 = order.iter().map(|&(t, s)| u64::from(self.tracks[t].written[s].size)).sum();
+        let large = media_len + 8 > u64::from(u32::MAX);
+        let mdat_header = if large { 16 } else { 8 };
+
+        let head = ftyp(b"isom", &[b"isom", b"iso2", b"avc1", b"mp41"]);
+        let placeholder: Vec<Vec<u64>> = self.tracks.iter().map(|t| vec![0; t.written.len()]).collect();
+        let moov_len = final_moov(&self.tracks, &placeholder).len() as u64;
+        let mut offsets = placeholder;
+        let mut at = head.len() as u64 + moov_len + mdat_header;
+        for &(t, s) in &order {
+            offsets[t][s] = at;
+            at += u64::from(self.tracks[t].written[s].size);
+        }
+
+        let mut source = fs::File::open(fragmented)?;
+        let mut out = BufWriter::new(fs::File::create(dest)?);
+        out.write_all(&head)?;
+        out.write_all(&final_moov(&self.tracks, &offsets))?;
+        if large {
+            out.write_all(&1u32.to_be_bytes())?;
+            out.write_all(b"mdat")?;
+            out.write_all(&(media_len + 16).to_be_bytes())?;
+        } else {
+            out.write_all(&((media_len + 8) as u32).to_be_bytes())?;
+            out.write_all(b"mdat")?;
+        }
+        let mut read_at = None;
+        for &(t, s) in &order {
+            let sample = self.tracks[t].written[s];
+            if read_at != Some(sample.offset) {
+                source.seek(SeekFrom::Start(sample.offset))?;
+            }
+            let copied = std::io::copy(&mut (&mut source).take(u64::from(sample.size)), &mut out)?;
+            if copied != u64::from(sample.size) {
+                bail!("{} ends in the middle of a sample", fragmented.display());
+            }
+            read_at = Some(sample.offset + copied);
+        }
+        let file = out.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        Ok(at)
+    }
+}
+
+fn mp4_box(kind: &[u8; 4], parts: &[&[u8]]) -> Vec<u8> {
+    let len = 8 + parts.iter().map(|p| p.len()).sum::<usize>();
+    let mut out = Vec::with_capacity(len);
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    for part in parts {
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+fn full_box(kind: &[u8; 4], version: u8, flags: u32, body: &[u8]) -> Vec<u8> {
+    let header = ((u32::from(version) << 24) | flags).to_be_bytes();
+    mp4_box(kind, &[&header, body])
+}
+
+/// Concatenates big-endian u32s.
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_be_bytes()).collect()
+}
+
+const MATRIX: [u32; 9] = [0x0001_0000, 0, 0, 0, 0x0001_0000, 0, 0, 0, 0x4000_0000];
+
+fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+    let brands: Vec<u8> = compatible.iter().flat_map(|b| b.iter().copied()).collect();
+    mp4_box(b"ftyp", &[major, &[0, 0, 0, 0], &brands])
+}
+
+fn mvhd(duration: u32, next_track: u32) -> Vec<u8> {
+    let mut body = words(&[0, 0, MOVIE_TIMESCALE, duration, 0x0001_0000]);
+    body.extend_from_slice(&[0x01, 0x00, 0, 0]);
+    body.extend(words(&[0, 0]));
+    body.extend(words(&MATRIX));
+    body.extend(words(&[0; 6]));
+    body.extend(words(&[next_track]));
+    full_box(b"mvhd", 0, 0, &body)
+}
+
+/// Clamps a duration into a version 0 box's 32-bit field.
+fn clamp32(value: u64) -> u32 {
+    value.min(u64::from(u32::MAX)) as u32
+}
+
+/// A track's `trak`. `tables` are the sample table boxes after `stsd`; the track's
+/// duration is left at 0 for the fragmented layout, where the fragments carry it.
+fn trak(track: &Track, duration: u64, tables: &[u8]) -> Vec<u8> {
+    let (width, height, volume) = match &track.codec {
+        Codec::Avc { width, height, .. } => (*width, *height, 0u16),
+        Codec::Aac(_) => (0, 0, 0x0100),
+    };
+    let movie_duration = duration * u64::from(MOVIE_TIMESCALE) / u64::from(track.timescale);
+    let mut tkhd = words(&[0, 0, track.id, 0, clamp32(movie_duration), 0, 0]);
+    tkhd.extend_from_slice(&[0, 0, 0, 0]);
+    tkhd.extend_from_slice(&volume.to_be_bytes());
+    tkhd.extend_from_slice(&[0, 0]);
+    tkhd.extend(words(&MATRIX));
+    tkhd.extend(words(&[width << 16, height << 16]));
+
+    let mut mdhd = words(&[0, 0, track.timescale, clamp32(duration)]);
+    // Language "und", packed as three 5-bit letters.
+    mdhd.extend_from_slice(&[0x55, 0xC4, 0, 0]);
+
+    let (handler, name, media_header): (&[u8; 4], &[u8], Vec<u8>) = match track.codec {
+        Codec::Avc { .. } => (b"vide", b"VideoHandler\0", full_box(b"vmhd", 0, 1, &[0; 8])),
+        Codec::Aac(_) => (b"soun", b"SoundHandler\0", full_box(b"smhd", 0, 0, &[0; 4])),
+    };
+    let mut hdlr = words(&[0]);
+    hdlr.extend_from_slice(handler);
+    hdlr.extend(words(&[0, 0, 0]));
+    hdlr.extend_from_slice(name);
+
+    let dref = full_box(b"dref", 0, 0, &[words(&[1]), full_box(b"url ", 0, 1, &[])].concat());
+    let stsd = full_box(b"stsd", 0, 0, &[words(&[1]), sample_entry(track)].concat());
+    let stbl = mp4_box(b"stbl", &[&stsd, tables]);
+    let minf = mp4_box(b"minf", &[&media_header, &mp4_box(b"dinf", &[&dref]), &stbl]);
+    let mdia = mp4_box(b"mdia", &[&full_box(b"mdhd", 0, 0, &mdhd), &full_box(b"hdlr", 0, 0, &hdlr), &minf]);
+    mp4_box(b"trak", &[&full_box(b"tkhd", 0, 3, &tkhd), &mdia])
+}
+
+fn sample_entry(track: &Track) -> Vec<u8> {
+    match &track.codec {
+        Codec::Avc { sps, pps, width, height } => {
+            let mut avcc = vec![1, sps[1], sps[2], sps[3], 0xFF, 0xE1];
+            avcc.extend_from_slice(&(sps.len() as u16).to_be_bytes());
+            avcc.extend_from_slice(sps);
+            avcc.push(1);
+            avcc.extend_from_slice(&(pps.len() as u16).to_be_bytes());
+            avcc.extend_from_slice(pps);
+            let mut body = vec![0; 6];
+            body.extend_from_slice(&1u16.to_be_bytes());
+            body.extend_from_slice(&[0; 16]);
+            body.extend_from_slice(&(*width as u16).to_be_bytes());
+            body.extend_from_slice(&(*height as u16).to_be_bytes());
+            body.extend(words(&[0x0048_0000, 0x0048_0000, 0]));
+            body.extend_from_slice(&1u16.to_be_bytes());
+            body.extend_from_slice(&[0; 32]);
+            body.extend_from_slice(&[0x00, 0x18, 0xFF, 0xFF]);
+            mp4_box(b"avc1", &[&body, &mp4_box(b"avcC", &[&avcc])])
+        }
+        Codec::Aac(config) => {
+            let descriptor = |tag: u8, body: &[u8]| [&[tag, body.len() as u8][..], body].concat();
+            let decoder_config = [&[0x40, 0x15, 0, 0, 0][..], &words(&[0, 0]), &descriptor(5, &config.specific_config())].concat();
+            let es = [&[0, track.id as u8, 0][..], &descriptor(4, &decoder_config), &descriptor(6, &[0x02])].concat();
+            let mut body = vec![0; 6];
+            body.extend_from_slice(&1u16.to_be_bytes());
+            body.extend_from_slice(&[0; 8]);
+            body.extend_from_slice(&u16::from(config.channels.max(1)).to_be_bytes());
+            body.extend_from_slice(&[0x00, 0x10, 0, 0, 0, 0]);
+            body.extend(words(&[config.sample_rate() << 16]));
+            mp4_box(b"mp4a", &[&body, &full_box(b"esds", 0, 0, &descriptor(3, &es))])
+        }
+    }
+}
+
+/// The init segment's `moov`: empty sample tables and an `mvex` saying fragments follow.
+fn fragmented_moov(tracks: &[&Track]) -> Vec<u8> {
+    let empty = [
+        full_box(b"stts", 0, 0, &words(&[0])),
+        full_box(b"stsc", 0, 0, &words(&[0])),
+        full_box(b"stsz", 0, 0, &words(&[0, 0])),
+        full_box(b"stco", 0, 0, &words(&[0])),
+    ]
+    .concat();
+    let traks: Vec<u8> = tracks.iter().flat_map(|t| trak(t, 0, &empty)).collect();
+    let trexs: Vec<u8> = tracks.iter().flat_map(|t| full_box(b"trex", 0, 0, &words(&[t.id, 1, 0, 0, 0]))).collect();
+    mp4_box(b"moov", &[&mvhd(0, tracks.len() as u32 + 1), &traks, &mp4_box(b"mvex", &[&trexs])])
+}
+
+/// The faststart `moov`, with every sample as its own chunk at `offsets[track][sample]`.
+fn final_moov(tracks: &[Track], offsets: &[Vec<u64>]) -> Vec<u8> {
+    let mut traks = Vec::new();
+    let mut movie_duration = 0;
+    for (track, offsets) in tracks.iter().zip(offsets) {
+        let samples = &track.written;
+        let mut stts: Vec<(u32, u32)> = Vec::new();
+        let mut ctts: Vec<(u32, u32)> = Vec::new();
+        for sample in samples {
+            match stts.last_mut() {
+                Some((count, duration)) if *duration == sample.duration => *count += 1,
+                _ => stts.push((1, sample.duration)),
+            }
+            match ctts.last_mut() {
+                Some((count, offset)) if *offset == sample.cts_offset => *count += 1,
+                _ => ctts.push((1, sample.cts_offset)),
+            }
+        }
+        let pairs = |entries: &[(u32, u32)]| -> Vec<u8> {
+            let mut body = words(&[entries.len() as u32]);
+            body.extend(entries.iter().flat_map(|&(a, b)| words(&[a, b])));
+            body
+        };
+        let mut tables = full_box(b"stts", 0, 0, &pairs(&stts));
+        if ctts.iter().any(|&(_, offset)| offset != 0) {
+            tables.extend(full_box(b"ctts", 0, 0, &pairs(&ctts)));
+        }
+        if samples.iter().any(|s| !s.sync) {
+            let sync: Vec<u32> = samples.iter().enumerate().filter(|(_, s)| s.sync).map(|(i, _)| i as u32 + 1).collect();
+            tables.extend(full_box(b"stss", 0, 0, &[words(&[sync.len() as u32]), words(&sync)].concat()));
+        }
+        tables.extend(full_box(b"stsc", 0, 0, &words(&[1, 1, 1, 1])));
+        let sizes: Vec<u32> = samples.iter().map(|s| s.size).collect();
+        tables.extend(full_box(b"stsz", 0, 0, &[words(&[0, sizes.len() as u32]), words(&sizes)].concat()));
+        let mut co64 = words(&[offsets.len() as u32]);
+        co64.extend(offsets.iter().flat_map(|o| o.to_be_bytes()));
+        tables.extend(full_box(b"co64", 0, 0, &co64));
+
+        let duration = track.duration();
+        movie_duration = movie_duration.max(duration * u64::from(MOVIE_TIMESCALE) / u64::from(track.timescale));
+        traks.extend(trak(track, duration, &tables));
+    }
+    mp4_box(b"moov", &[&mvhd(clamp32(movie_duration), tracks.len() as u32 + 1), &traks])
+}
+
+fn traf(track_id: u32, run: &[(Sample, u32)], data_offset: u32) -> Vec<u8> {
+    // tfhd: default-base-is-moof, so data offsets count from the start of the moof.
+    let tfhd = full_box(b"tfhd", 0, 0x02_0000, &words(&[track_id]));
+    let tfdt = full_box(b"tfdt", 1, 0, &run[0].0.dts.to_be_bytes());
+    let mut trun = words(&[run.len() as u32, data_offset]);
+    for (sample, duration) in run {
+        let flags = if sample.sync { 0x0200_0000 } else { 0x0101_0000 };
+        trun.extend(words(&[*duration, sample.data.len() as u32, flags, sample.cts_offset]));
+    }
+    // trun: data offset, and per sample duration, size, flags and composition offset.
+    mp4_box(b"traf", &[&tfhd, &tfdt, &full_box(b"trun", 0, 0x0F01, &trun)])
+}
+
+/// Child boxes of `data` as (type, body) pairs, stopping at the first malformed one.
+fn children(data: &[u8]) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let len = u32::from_be_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+        if len < 8 || len > rest.len() {
+            return None;
+        }
+        let kind: [u8; 4] = rest[4..8].try_into().ok()?;
+        let body = &rest[8..len];
+        rest = &rest[len..];
+        Some((kind, body))
+    })
+}
+
+fn child<'a>(data: &'a [u8], kind: &[u8; 4]) -> Option<&'a [u8]> {
+    children(data).find(|(k, _)| k == kind).map(|(_, body)| body)
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be64(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Timescale and duration from an `mvhd` or `mdhd` body.
+fn header_duration(body: &[u8]) -> Option<(u32, u64)> {
+    match body.first()? {
+        0 => Some((be32(body, 12)?, u64::from(be32(body, 16)?))),
+        _ => Some((be32(body, 20)?, be64(body, 24)?)),
+    }
+}
+
+/// Length in seconds of an MP4 file, from the `mvhd` of a regular file or, for a
+/// fragmented one, by adding up the first track's sample durations across its fragments.
+pub fn duration_secs(path: &Path) -> Result<f64> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut pos = 0u64;
+    let mut track: Option<(u32, u32)> = None;
+    let mut fragment_ticks = 0u64;
+    while pos + 8 <= len {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 16];
+        file.read_exact(&mut header[..8])?;
+        let mut size = u64::from(u32::from_be_bytes(header[..4].try_into()?));
+        let kind: [u8; 4] = header[4..8].try_into()?;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut header[8..])?;
+            size = u64::from_be_bytes(header[8..].try_into()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - pos;
+        }
+        if size < header_len {
+            bail!("{} has a malformed {} box", path.display(), String::from_utf8_lossy(&kind));
+        }
+        if &kind == b"moov" || &kind == b"moof" {
+            let mut body = vec![0u8; (size - header_len) as usize];
+            file.read_exact(&mut body)?;
+            if &kind == b"moov" {
+                let (timescale, duration) = child(&body, b"mvhd").and_then(header_duration).ok_or_else(|| anyhow!("{} has no mvhd", path.display()))?;
+                if duration > 0 && timescale > 0 {
+                    return Ok(duration as f64 / f64::from(timescale));
+                }
+                track = child(&body, b"trak").and_then(|trak| {
+                    let tkhd = child(trak, b"tkhd")?;
+                    let id = be32(tkhd, if tkhd[0] == 0 { 12 } else { 20 })?;
+                    let mdhd = child(child(trak, b"mdia")?, b"mdhd")?;
+                    Some((id, header_duration(mdhd)?.0))
+                });
+            } else if let Some((id, _)) = track {
+                fragment_ticks += children(&body)
+                    .filter(|(k, _)| k == b"traf")
+                    .filter_map(|(_, traf)| traf_ticks(traf, id))
+                    .sum::<u64>();
+            }
+        }
+        pos += size;
+    }
+    match track {
+        Some((_, timescale)) if timescale > 0 => Ok(fragment_ticks as f64 / f64::from(timescale)),
+        _ => bail!("{} has no movie header", path.display()),
+    }
+}
+
+/// The summed sample durations of a `traf` for track `id`.
+fn traf_ticks(traf: &[u8], id: u32) -> Option<u64> {
+    let tfhd = child(traf, b"tfhd")?;
+    let tf_flags = be32(tfhd, 0)? & 0x00FF_FFFF;
+    if be32(tfhd, 4)? != id {
+        return None;
+    }
+    let mut at = 8;
+    if tf_flags & 0x01 != 0 {
+        at += 8;
+    }
+    if tf_flags & 0x02 != 0 {
+        at += 4;
+    }
+    let default_duration = if tf_flags & 0x08 != 0 { be32(tfhd, at)? } else { 0 };
+    let mut total = 0u64;
+    for (kind, trun) in children(traf) {
+        if &kind != b"trun" {
+            continue;
+        }
+        let flags = be32(trun, 0)? & 0x00FF_FFFF;
+        let count = be32(trun, 4)?;
+        let mut at = 8 + if flags & 0x01 != 0 { 4 } else { 0 } + if flags & 0x04 != 0 { 4 } else { 0 };
+        let per_sample = [0x100, 0x200, 0x400, 0x800].iter().filter(|f| flags & **f != 0).count() * 4;
+        for _ in 0..count {
+            total += u64::from(if flags & 0x100 != 0 { be32(trun, at)? } else { default_duration });
+            at += per_sample;
+        }
+    }
+    Some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_live;
+
+    const AUDIO_PID: u16 = 0x101;
+
+    /// Remuxes `ts` fed in uneven chunks, so packets straddle `push` calls, into `dir`.
+    fn remux(dir: &Path, ts: &[u8]) -> (std::path::PathBuf, Finished) {
+        let path = dir.join("fragmented.mp4");
+        let mut remuxer = Remuxer::new(BufWriter::new(fs::File::create(&path).unwrap()));
+        for chunk in ts.chunks(1000) {
+            remuxer.push(chunk).unwrap();
+        }
+        (path, remuxer.finish().unwrap())
+    }
+
+    fn top_level(data: &[u8]) -> Vec<String> {
+        children(data).map(|(kind, _)| String::from_utf8_lossy(&kind).into_owned()).collect()
+    }
+
+    fn find<'a>(data: &'a [u8], path: &[&[u8; 4]]) -> &'a [u8] {
+        path.iter().fold(data, |data, kind| child(data, kind).unwrap_or_else(|| panic!("no {}", String::from_utf8_lossy(*kind))))
+    }
+
+    fn traks(moov: &[u8]) -> Vec<&[u8]> {
+        children(moov).filter(|(k, _)| k == b"trak").map(|(_, body)| body).collect()
+    }
+
+    /// The u32 entries of a `stsz`, `stss` or similar table after its version and `skip` words.
+    fn table(body: &[u8], skip: usize) -> Vec<u32> {
+        let count = be32(body, 4 + skip * 4).unwrap() as usize;
+        (0..count).map(|i| be32(body, 8 + skip * 4 + i * 4).unwrap()).collect()
+    }
+
+    /// The (count, value) entries of a `stts`.
+    fn stts(body: &[u8]) -> Vec<(u32, u32)> {
+        let count = be32(body, 4).unwrap() as usize;
+        (0..count).map(|i| (be32(body, 8 + i * 8).unwrap(), be32(body, 12 + i * 8).unwrap())).collect()
+    }
+
+    fn co64(body: &[u8]) -> Vec<u64> {
+        let count = be32(body, 4).unwrap() as usize;
+        (0..count).map(|i| be64(body, 8 + i * 8).unwrap()).collect()
+    }
+
+    /// An ADTS frame of AAC LC, 48 kHz stereo, with `len` payload bytes.
+    fn adts(len: usize) -> Vec<u8> {
+        let total = len + 7;
+        let mut frame = vec![0xFF, 0xF1, 0x4C, 0x80 | (total >> 11) as u8, (total >> 3) as u8, ((total & 7) << 5) as u8 | 0x1F, 0xFC];
+        frame.resize(total, 0x21);
+        frame
+    }
+
+    /// One PES in TS packets on `pid`, the last one stuffed through its adaptation field.
+    fn pes_packets(pid: u16, stream_id: u8, pts: u64, es: &[u8]) -> Vec<u8> {
+        let mut pes = vec![0, 0, 1, stream_id, 0, 0, 0x80, 0x80, 5];
+        pes.extend_from_slice(&[
+            0x21 | ((pts >> 29) & 0x0E) as u8,
+            (pts >> 22) as u8,
+            0x01 | ((pts >> 14) & 0xFE) as u8,
+            (pts >> 7) as u8,
+            0x01 | ((pts << 1) & 0xFE) as u8,
+        ]);
+        pes.extend_from_slice(es);
+        let mut out = Vec::new();
+        for (i, part) in pes.chunks(184).enumerate() {
+            let mut packet = vec![0x47, if i == 0 { 0x40 } else { 0 } | (pid >> 8) as u8, pid as u8];
+            if part.len() < 184 {
+                let stuffing = 183 - part.len();
+                packet.extend_from_slice(&[0x30, stuffing as u8]);
+                if stuffing > 0 {
+                    packet.push(0);
+                    packet.resize(packet.len() + stuffing - 1, 0xFF);
+                }
+            } else {
+                packet.push(0x10);
+            }
+            packet.extend_from_slice(part);
+            out.extend(packet);
+        }
+        out
+    }
+
+    /// Packs `bits` (as '0'/'1' characters, spaces ignored) into an SPS NAL unit after
+    /// `profile`, constraint flags 0 and `level`, with the RBSP stop bit.
+    fn sps(profile: u8, level: u8, bits: &str) -> Vec<u8> {
+        let mut bits: Vec<u8> = bits.bytes().filter(|b| *b != b' ').map(|b| b - b'0').collect();
+        bits.push(1);
+        bits.resize(bits.len().div_ceil(8) * 8, 0);
+        let mut nal = vec![0x67, profile, 0, level];
+        nal.extend(bits.chunks(8).map(|byte| byte.iter().fold(0, |acc, b| (acc << 1) | b)));
+        nal
+    }
+
+    /// Exp-Golomb code of `v` as a bit string.
+    fn ue(v: u32) -> String {
+        let code = format!("{:b}", v + 1);
+        format!("{}{}", "0".repeat(code.len() - 1), code)
+    }
+
+    #[test]
+    fn sps_dimensions_honour_cropping_and_high_profiles() {
+        // Baseline 1920x1088 with 8 rows cropped off the bottom, as cameras send 1080p.
+        let baseline = sps(66, 40, &[ue(0), ue(0), ue(2), ue(1), "0".into(), ue(119), ue(67), "1 1 1".into(), ue(0), ue(0), ue(0), ue(4), "0".into()].concat());
+        assert_eq!(sps_dimensions(&baseline).unwrap(), (1920, 1080));
+        // High profile adds chroma format, bit depths and scaling matrix flags.
+        let high = sps(100, 31, &[ue(0), ue(1), ue(0), ue(0), "0 0".into(), ue(0), ue(0), ue(4), ue(1), "0".into(), ue(79), ue(44), "1 1 0 0".into()].concat());
+        assert_eq!(sps_dimensions(&high).unwrap(), (1280, 720));
+        assert!(sps_dimensions(&baseline[..5]).is_err());
+        assert!(sps_dimensions(&[0x67, 0x00, 0x00, 0x03]).is_err());
+        assert!(sps_dimensions(&[0x67, 0x42, 0x00, 0x00, 0x03]).is_err());
+    }
+
+    #[test]
+    fn the_test_pattern_remuxes_into_a_fragment_per_second() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, finished) = remux(dir.path(), &fake_live::test_pattern(0..35));
+        let data = fs::read(&path).unwrap();
+        assert_eq!(top_level(&data), ["ftyp", "moov", "moof", "mdat", "moof", "mdat", "moof", "mdat", "moof", "mdat"]);
+        let moov = child(&data, b"moov").unwrap();
+        assert!(child(moov, b"mvex").is_some());
+        let tkhd = find(moov, &[b"trak", b"tkhd"]);
+        assert_eq!((be32(tkhd, 76).unwrap() >> 16, be32(tkhd, 80).unwrap() >> 16), (320, 240));
+        assert_eq!(finished.duration_secs(), 3.5);
+        assert_eq!(duration_secs(&path).unwrap(), 3.5);
+    }
+
+    #[test]
+    fn faststart_puts_the_moov_first_and_keeps_every_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let (fragmented, finished) = remux(dir.path(), &fake_live::test_pattern(0..35));
+        let dest = dir.path().join("final.mp4");
+        let bytes = finished.faststart(&fragmented, &dest).unwrap();
+        let data = fs::read(&dest).unwrap();
+        assert_eq!(data.len() as u64, bytes);
+        assert_eq!(top_level(&data), ["ftyp", "moov", "mdat"]);
+        assert_eq!(duration_secs(&dest).unwrap(), 3.5);
+
+        let moov = child(&data, b"moov").unwrap();
+        let stbl = find(moov, &[b"trak", b"mdia", b"minf", b"stbl"]);
+        let sizes = table(child(stbl, b"stsz").unwrap(), 1);
+        assert_eq!(sizes.len(), 35);
+        assert_eq!(table(child(stbl, b"stss").unwrap(), 0), [1, 11, 21, 31]);
+        assert_eq!(stts(child(stbl, b"stts").unwrap()), [(35, 9000)]);
+        let offsets = co64(child(stbl, b"co64").unwrap());
+        // Samples are length-prefixed NAL units, without the parameter sets or delimiters.
+        for (i, (&offset, &size)) in offsets.iter().zip(&sizes).enumerate() {
+            let sample = &data[offset as usize..(offset + u64::from(size)) as usize];
+            let nal_len = be32(sample, 0).unwrap() as usize;
+            assert_eq!(nal_len + 4, sample.len());
+            assert_eq!(sample[4] & 0x1F, if i % 10 == 0 { 5 } else { 1 });
+        }
+        let mdat_start = data.len() - sizes.iter().map(|s| *s as usize).sum::<usize>();
+        assert_eq!(offsets[0] as usize, mdat_start);
+    }
+
+    #[test]
+    fn audio_gets_its_own_track_starting_with_the_video() {
+        // Video starts at PTS 90000; audio frames every 1920 ticks from 0, interleaved.
+        let audio_pts: Vec<u64> = (0..=270_000).step_by(1920).collect();
+        let mut ts = Vec::new();
+        let mut next_audio = audio_pts.iter().peekable();
+        for frame in 0..20 {
+            while let Some(pts) = next_audio.next_if(|pts| **pts < frame * 9000 + 90_000) {
+                ts.extend(pes_packets(AUDIO_PID, 0xC0, *pts, &adts(200)));
+            }
+            ts.extend(fake_live::test_pattern(frame..frame + 1));
+        }
+        for pts in next_audio {
+            ts.extend(pes_packets(AUDIO_PID, 0xC0, *pts, &adts(200)));
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let (fragmented, finished) = remux(dir.path(), &ts);
+        let dest = dir.path().join("final.mp4");
+        finished.faststart(&fragmented, &dest).unwrap();
+
+        let data = fs::read(&dest).unwrap();
+        let moov = child(&data, b"moov").unwrap();
+        let traks = traks(moov);
+        assert_eq!(traks.len(), 2);
+        let audio = traks[1];
+        assert_eq!(&find(audio, &[b"mdia", b"hdlr"])[8..12], b"soun");
+        assert_eq!(header_duration(find(audio, &[b"mdia", b"mdhd"])).unwrap().0, 48_000);
+        let stbl = find(audio, &[b"mdia", b"minf", b"stbl"]);
+        let esds = find(child(stbl, b"stsd").unwrap().get(8..).unwrap(), &[b"mp4a"]);
+        // AudioSpecificConfig for AAC LC, 48 kHz, two channels.
+        assert!(esds.windows(4).any(|w| w == [0x05, 0x02, 0x11, 0x90]));
+        let sizes = table(child(stbl, b"stsz").unwrap(), 1);
+        let kept = audio_pts.iter().filter(|pts| **pts >= 90_000).count();
+        assert_eq!(sizes.len(), kept);
+        assert!(sizes.iter().all(|s| *s == 200));
+    }
+
+    #[test]
+    fn nothing_is_written_without_a_keyframe() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("fragmented.mp4");
+        let mut remuxer = Remuxer::new(BufWriter::new(fs::File::create(&path).unwrap()));
+        remuxer.push(&fake_live::test_pattern(1..10)).unwrap();
+        let err = remuxer.finish().err().unwrap();
+        assert!(err.to_string().contains("no H.264 keyframe"), "{}", err);
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn frames_before_the_first_keyframe_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (path, finished) = remux(dir.path(), &fake_live::test_pattern(5..25));
+        assert_eq!(finished.duration_secs(), 1.5);
+        assert_eq!(duration_secs(&path).unwrap(), 1.5);
+    }
+
+    #[test]
+    fn timestamps_wrapping_past_33_bits_stay_in_order() {
+        // PTS crosses 2^33 halfway through.
+        let dir = tempfile::tempdir().unwrap();
+        let (fragmented, finished) = remux(dir.path(), &fake_live::test_pattern(954_420..954_450));
+        assert_eq!(finished.duration_secs(), 3.0);
+        let dest = dir.path().join("final.mp4");
+        finished.faststart(&fragmented, &dest).unwrap();
+        let data = fs::read(&dest).unwrap();
+        let stbl = find(&data, &[b"moov", b"trak", b"mdia", b"minf", b"stbl"]);
+        assert_eq!(stts(child(stbl, b"stts").unwrap()), [(30, 9000)]);
+    }
+
+    #[test]
+    fn an_unreadable_file_has_no_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("junk.mp4");
+        fs::write(&path, b"\0\0\0\x04junk").unwrap();
+        assert!(duration_secs(&path).is_err());
+        fs::write(&path, b"").unwrap();
+        assert!(duration_secs(&path).is_err());
+    }
+}
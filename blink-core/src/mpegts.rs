@@ -0,0 +1,233 @@
+//! Synthetic MPEG-TS packets used to prime players before the camera's own stream arrives:
+//! null packets for mpegts.js, and a PAT/PMT pair for probing players such as ffmpeg and VLC.
+
+use bytes::Bytes;
+
+pub const PACKET_LEN: usize = 188;
+const SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0x0000;
+const NULL_PID: u16 = 0x1FFF;
+/// PMT PID announced in the synthetic PAT; the same one ffmpeg's muxer uses.
+pub const PMT_PID: u16 = 0x1000;
+pub const STREAM_TYPE_H264: u8 = 0x1B;
+pub const STREAM_TYPE_AAC: u8 = 0x0F;
+
+/// CRC-32/MPEG-2, as carried at the end of every PSI section.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= u32::from(byte) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 { (crc << 1) ^ 0x04C1_1DB7 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Standard MPEG-TS null packet: 188 bytes starting with 0x47 0x1F 0xFF 0x10.
+pub fn null_packet() -> Bytes {
+    let mut packet = vec![SYNC_BYTE, 0x1F, 0xFF, 0x10];
+    packet.resize(PACKET_LEN, 0xFF);
+    Bytes::from(packet)
+}
+
+/// Wraps one PSI section (table id through the CRC) into a single packet on `pid`.
+fn psi_packet(pid: u16, section: &[u8]) -> Bytes {
+    let mut packet = Vec::with_capacity(PACKET_LEN);
+    packet.push(SYNC_BYTE);
+    packet.push(0x40 | (pid >> 8) as u8); // payload_unit_start_indicator
+    packet.push(pid as u8);
+    packet.push(0x10); // payload only, continuity counter 0
+    packet.push(0x00); // pointer field
+    packet.extend_from_slice(section);
+    packet.resize(PACKET_LEN, 0xFF);
+    Bytes::from(packet)
+}
+
+/// Finishes a section: fills in `section_length` and appends the CRC.
+fn seal_section(mut section: Vec<u8>) -> Vec<u8> {
+    let length = section.len() - 3 + 4;
+    section[1] = 0xB0 | ((length >> 8) as u8 & 0x0F);
+    section[2] = length as u8;
+    let crc = crc32(&section);
+    section.extend_from_slice(&crc.to_be_bytes());
+    section
+}
+
+/// A PAT with one program (number 1) whose PMT is on `pmt_pid`.
+pub fn pat_packet(pmt_pid: u16) -> Bytes {
+    let section = vec![
+        0x00, 0, 0, // table id, section length (filled in)
+        0x00, 0x01, // transport stream id
+        0xC1, 0x00, 0x00, // version 0, current, section 0 of 0
+        0x00, 0x01, // program number
+        0xE0 | (pmt_pid >> 8) as u8, pmt_pid as u8,
+    ];
+    psi_packet(PAT_PID, &seal_section(section))
+}
+
+/// A PMT for program 1 listing `streams` as (stream type, PID), with the PCR on `pcr_pid`.
+pub fn pmt_packet(pmt_pid: u16, pcr_pid: u16, streams: &[(u8, u16)]) -> Bytes {
+    let mut section = vec![
+        0x02, 0, 0, // table id, section length (filled in)
+        0x00, 0x01, // program number
+        0xC1, 0x00, 0x00, // version 0, current, section 0 of 0
+        0xE0 | (pcr_pid >> 8) as u8, pcr_pid as u8,
+        0xF0, 0x00, // no program descriptors
+    ];
+    for &(stream_type, pid) in streams {
+        section.extend_from_slice(&[stream_type, 0xE0 | (pid >> 8) as u8, pid as u8, 0xF0, 0x00]);
+    }
+    psi_packet(pmt_pid, &seal_section(section))
+}
+
+fn packets(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    data.chunks_exact(PACKET_LEN).filter(|p| p[0] == SYNC_BYTE)
+}
+
+fn pid(packet: &[u8]) -> u16 {
+    (u16::from(packet[1] & 0x1F) << 8) | u16::from(packet[2])
+}
+
+/// Whether `data` already carries a PAT, in which case players can probe it as is.
+pub fn has_pat(data: &[u8]) -> bool {
+    packets(data).any(|p| pid(p) == PAT_PID)
+}
+
+/// Whether a packet in `data` sets the adaptation field's random access indicator, which
+/// encoders use to mark where a keyframe starts.
+pub fn has_random_access(data: &[u8]) -> bool {
+    packets(data).any(|p| p[3] & 0x20 != 0 && p[4] > 0 && p.get(5).is_some_and(|flags| flags & 0x40 != 0))
+}
+
+/// The PCR base (90 kHz, 33 bits) of the first packet in `data` that carries one.
+pub fn first_pcr(data: &[u8]) -> Option<u64> {
+    packets(data).find_map(pcr)
+}
+
+/// The PCR base of the last packet in `data` that carries one.
+pub fn last_pcr(data: &[u8]) -> Option<u64> {
+    packets(data).filter_map(pcr).last()
+}
+
+fn pcr(p: &[u8]) -> Option<u64> {
+    if p[3] & 0x20 == 0 || p[4] < 7 || p[5] & 0x10 == 0 {
+        return None;
+    }
+    let b = &p[6..11];
+    Some((u64::from(b[0]) << 25) | (u64::from(b[1]) << 17) | (u64::from(b[2]) << 9) | (u64::from(b[3]) << 1) | (u64::from(b[4]) >> 7))
+}
+
+/// Elementary streams found in `data`, from the PES headers that start on a packet:
+/// (stream type, PID) with video (stream id 0xE0-0xEF) taken as H.264 and audio
+/// (0xC0-0xDF) as AAC, which is what Blink cameras send.
+pub fn elementary_streams(data: &[u8]) -> Vec<(u8, u16)> {
+    let mut streams: Vec<(u8, u16)> = Vec::new();
+    for packet in packets(data) {
+        let pid = pid(packet);
+        if packet[1] & 0x40 == 0 || pid == PAT_PID || pid == NULL_PID || streams.iter().any(|s| s.1 == pid) {
+            continue;
+        }
+        let payload_start = match packet[3] & 0x30 {
+            0x10 => 4,
+            0x30 => 5 + usize::from(packet[4]),
+            _ => continue,
+        };
+        let Some(pes) = packet.get(payload_start..payload_start + 4) else { continue };
+        if pes[..3] != [0x00, 0x00, 0x01] {
+            continue;
+        }
+        match pes[3] {
+            0xE0..=0xEF => streams.push((STREAM_TYPE_H264, pid)),
+            0xC0..=0xDF => streams.push((STREAM_TYPE_AAC, pid)),
+            _ => {}
+        }
+    }
+    streams
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The section a PSI packet carries, from the table id through the CRC.
+    fn section(packet: &[u8]) -> &[u8] {
+        assert_eq!(packet[4], 0, "pointer field");
+        let start = 5;
+        let length = (usize::from(packet[start + 1] & 0x0F) << 8) | usize::from(packet[start + 2]);
+        &packet[start..start + 3 + length]
+    }
+
+    #[test]
+    fn crc_matches_the_mpeg2_check_value() {
+        assert_eq!(crc32(b"123456789"), 0x0376_E6E7);
+        assert_eq!(crc32(&[]), 0xFFFF_FFFF);
+    }
+
+    #[test]
+    fn pat_crc_is_valid() {
+        let packet = pat_packet(PMT_PID);
+        assert_eq!(packet.len(), PACKET_LEN);
+        assert_eq!(&packet[..4], &[SYNC_BYTE, 0x40, 0x00, 0x10]);
+        let section = section(&packet);
+        // Running the CRC over a section including its own CRC leaves no remainder.
+        assert_eq!(crc32(section), 0);
+        // Byte for byte what ffmpeg's muxer writes for the same program.
+        assert_eq!(section, &[0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00, 0x2A, 0xB1, 0x04, 0xB2]);
+        assert!(packet[5 + section.len()..].iter().all(|&b| b == 0xFF));
+    }
+
+    #[test]
+    fn pmt_crc_is_valid_for_any_stream_list() {
+        for streams in [vec![], vec![(STREAM_TYPE_H264, 0x100)], vec![(STREAM_TYPE_H264, 0x100), (STREAM_TYPE_AAC, 0x101)]] {
+            let packet = pmt_packet(PMT_PID, 0x100, &streams);
+            assert_eq!(packet.len(), PACKET_LEN);
+            assert_eq!(pid(&packet), PMT_PID);
+            let section = section(&packet);
+            assert_eq!(section[0], 0x02);
+            assert_eq!(section.len(), 16 + 5 * streams.len());
+            assert_eq!(crc32(section), 0, "{:?}", streams);
+        }
+    }
+
+    #[test]
+    fn a_corrupted_section_fails_the_crc() {
+        let packet = pat_packet(PMT_PID);
+        let mut section = section(&packet).to_vec();
+        section[4] ^= 0x01;
+        assert_ne!(crc32(&section), 0);
+    }
+
+    #[test]
+    fn null_packets_are_standard() {
+        let packet = null_packet();
+        assert_eq!(packet.len(), PACKET_LEN);
+        assert_eq!(pid(&packet), NULL_PID);
+        assert!(!has_pat(&packet));
+    }
+
+    #[test]
+    fn streams_and_pat_are_found_in_camera_packets() {
+        let mut video = vec![SYNC_BYTE, 0x41, 0x00, 0x10, 0x00, 0x00, 0x01, 0xE0];
+        video.resize(PACKET_LEN, 0xFF);
+        let mut audio = vec![SYNC_BYTE, 0x41, 0x01, 0x10, 0x00, 0x00, 0x01, 0xC0];
+        audio.resize(PACKET_LEN, 0xFF);
+        let data = [null_packet().to_vec(), video, audio].concat();
+        assert_eq!(elementary_streams(&data), vec![(STREAM_TYPE_H264, 0x100), (STREAM_TYPE_AAC, 0x101)]);
+        assert!(!has_pat(&data));
+        assert!(has_pat(&[data, pat_packet(PMT_PID).to_vec()].concat()));
+    }
+
+    #[test]
+    fn pcr_and_random_access_come_from_the_adaptation_field() {
+        // Adaptation field of 7 bytes: random access and PCR flags, PCR base 90000.
+        let base: u64 = 90_000;
+        let mut packet = vec![SYNC_BYTE, 0x41, 0x00, 0x30, 0x07, 0x50,
+            (base >> 25) as u8, (base >> 17) as u8, (base >> 9) as u8, (base >> 1) as u8, ((base & 1) << 7) as u8 | 0x7E, 0x00];
+        packet.resize(PACKET_LEN, 0xFF);
+        assert_eq!(first_pcr(&packet), Some(base));
+        assert!(has_random_access(&packet));
+        assert_eq!(first_pcr(&null_packet()), None);
+        assert!(!has_random_access(&null_packet()));
+    }
+}
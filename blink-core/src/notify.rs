@@ -0,0 +1,515 @@
+//! Push notifications through ntfy or Gotify, for people who don't run a webhook receiver.
+//! Each target in `Settings::notifiers` picks the alert types it wants and the priority
+//! for each, so motion can arrive quietly while an offline camera is loud. The `Alerter`
+//! turns the media feed and homescreen into alerts, at most one per camera (or sync module)
+//! and type every `ALERT_COOLDOWN_SECS`. Tokens come from the environment, never from settings.
+
+use crate::battery::BatteryStore;
+use crate::blink::{BlinkClient, Camera, SyncModule, UsbStorageState};
+use anyhow::{anyhow, Result};
+use futures_util::future::BoxFuture;
+use futures_util::FutureExt;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const NTFY_TOKEN_ENV: &str = "BLINK_NTFY_TOKEN";
+pub const GOTIFY_TOKEN_ENV: &str = "BLINK_GOTIFY_TOKEN";
+pub const DEFAULT_NTFY_SERVER: &str = "https://ntfy.sh";
+/// Shortest gap between two alerts of one type for one camera.
+pub const ALERT_COOLDOWN_SECS: u64 = 5 * 60;
+/// How often the server looks for something to alert on.
+pub const POLL_INTERVAL_SECS: u64 = 60;
+const SEND_TIMEOUT_SECS: u64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Motion,
+    Offline,
+    LowBattery,
+    /// A sync module's USB drive filled up or failed, so local clips stop being saved.
+    UsbStorage,
+}
+
+impl AlertKind {
+    fn tag(self) -> &'static str {
+        match self {
+            AlertKind::Motion => "motion",
+            AlertKind::Offline => "offline",
+            AlertKind::LowBattery => "low_battery",
+            AlertKind::UsbStorage => "usb_storage",
+        }
+    }
+}
+
+/// ntfy's five levels; Gotify gets them spread over its 0-10 scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    Min,
+    Low,
+    Default,
+    High,
+    Urgent,
+}
+
+impl Priority {
+    fn ntfy(self) -> u8 {
+        match self {
+            Priority::Min => 1,
+            Priority::Low => 2,
+            Priority::Default => 3,
+            Priority::High => 4,
+            Priority::Urgent => 5,
+        }
+    }
+
+    fn gotify(self) -> u8 {
+        match self {
+            Priority::Min => 1,
+            Priority::Low => 3,
+            Priority::Default => 5,
+            Priority::High => 8,
+            Priority::Urgent => 10,
+        }
+    }
+}
+
+/// Which service to push to. The token is read from `NTFY_TOKEN_ENV` (optional) or
+/// `GOTIFY_TOKEN_ENV` (the application token, required).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotifierConfig {
+    Ntfy {
+        #[serde(default = "default_ntfy_server")]
+        server: String,
+        topic: String,
+    },
+    Gotify {
+        /// e.g. `https://gotify.lan`.
+        server: String,
+    },
+}
+
+fn default_ntfy_server() -> String {
+    DEFAULT_NTFY_SERVER.to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotifierTarget {
+    #[serde(flatten)]
+    pub service: NotifierConfig,
+    /// Priority per alert type; types left out are not sent to this target.
+    #[serde(default)]
+    pub alerts: BTreeMap<AlertKind, Priority>,
+}
+
+/// One alert, ready to send.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub kind: AlertKind,
+    pub camera_id: i64,
+    pub camera_name: String,
+    pub title: String,
+    pub message: String,
+    /// Image bytes, attached where the service takes attachments.
+    pub thumbnail: Option<Vec<u8>>,
+    /// For low battery alerts, the battery history's projection of days left.
+    pub days_remaining: Option<f64>,
+}
+
+/// Where a notification went.
+#[derive(Debug, Clone, Serialize)]
+pub struct NotifyResult {
+    /// `ntfy` or `gotify` and its server.
+    pub target: String,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// A configured push service.
+pub trait Notifier: Send + Sync {
+    fn send<'a>(&'a self, notification: &'a Notification, priority: Priority) -> BoxFuture<'a, Result<()>>;
+}
+
+fn env_secret(name: &str) -> Option<SecretString> {
+    std::env::var(name).ok().filter(|v| !v.is_empty()).map(SecretString::from)
+}
+
+fn http_url(value: &str) -> Result<url::Url> {
+    let url = url::Url::parse(value).map_err(|_| anyhow!("Invalid notification server URL: {}", value))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(anyhow!("Notification servers must use http or https, not {}", url.scheme()));
+    }
+    Ok(url)
+}
+
+/// `server` with `path` appended, keeping any path the server already has.
+fn join(server: &str, path: &str) -> Result<url::Url> {
+    let mut base = http_url(server)?;
+    if !base.path().ends_with('/') {
+        base.set_path(&format!("{}/", base.path()));
+    }
+    Ok(base.join(path)?)
+}
+
+fn http_client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(SEND_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_default()
+}
+
+async fn check(res: reqwest::Response, service: &str) -> Result<()> {
+    let status = res.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = res.text().await.unwrap_or_default();
+    Err(anyhow!("{} answered {}: {}", service, status, body.chars().take(200).collect::<String>()))
+}
+
+impl NotifierConfig {
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            NotifierConfig::Ntfy { server, topic } => {
+                http_url(server)?;
+                if topic.is_empty() || topic.contains('/') {
+                    return Err(anyhow!("Invalid ntfy topic: {:?}", topic));
+                }
+                Ok(())
+            }
+            NotifierConfig::Gotify { server } => http_url(server).map(|_| ()),
+        }
+    }
+
+    /// Builds the notifier, reading its token from the environment.
+    pub fn open(&self) -> Result<Box<dyn Notifier>> {
+        self.validate()?;
+        Ok(match self {
+            NotifierConfig::Ntfy { server, topic } => Box::new(Ntfy {
+                http: http_client(),
+                url: join(server, topic)?,
+                token: env_secret(NTFY_TOKEN_ENV),
+            }),
+            NotifierConfig::Gotify { server } => {
+                let token = env_secret(GOTIFY_TOKEN_ENV)
+                    .ok_or_else(|| anyhow!("Set {} to send Gotify notifications", GOTIFY_TOKEN_ENV))?;
+                Box::new(Gotify { http: http_client(), url: join(server, "message")?, token })
+            }
+        })
+    }
+}
+
+impl NotifierTarget {
+    pub fn validate(&self) -> Result<()> {
+        self.service.validate()
+    }
+}
+
+struct Ntfy {
+    http: reqwest::Client,
+    url: url::Url,
+    token: Option<SecretString>,
+}
+
+impl Notifier for Ntfy {
+    /// With a thumbnail, the image is the request body and the text goes in `Message`,
+    /// which ntfy shows as a notification with an attachment.
+    fn send<'a>(&'a self, notification: &'a Notification, priority: Priority) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let mut req = match &notification.thumbnail {
+                Some(image) => self.http.put(self.url.clone())
+                    .header("Filename", "thumbnail.jpg")
+                    .header("Message", notification.message.replace('\n', " "))
+                    .body(image.clone()),
+                None => self.http.post(self.url.clone()).body(notification.message.clone()),
+            };
+            req = req.header("Title", &notification.title)
+                .header("Priority", priority.ntfy().to_string())
+                .header("Tags", notification.kind.tag());
+            if let Some(token) = &self.token {
+                req = req.bearer_auth(token.expose_secret());
+            }
+            check(req.send().await?, "ntfy").await
+        }.boxed()
+    }
+}
+
+struct Gotify {
+    http: reqwest::Client,
+    url: url::Url,
+    token: SecretString,
+}
+
+impl Notifier for Gotify {
+    /// Gotify messages can't carry attachments, so the thumbnail is left out.
+    fn send<'a>(&'a self, notification: &'a Notification, priority: Priority) -> BoxFuture<'a, Result<()>> {
+        async move {
+            let res = self.http.post(self.url.clone())
+                .header("X-Gotify-Key", self.token.expose_secret())
+                .json(&serde_json::json!({
+                    "title": notification.title,
+                    "message": notification.message,
+                    "priority": priority.gotify(),
+                    "extras": { "blink::battery": { "days_remaining": notification.days_remaining } },
+                }))
+                .send()
+                .await?;
+            check(res, "Gotify").await
+        }.boxed()
+    }
+}
+
+async fn send_to(target: &NotifierTarget, notification: &Notification, priority: Priority) -> NotifyResult {
+    let result = match target.service.open() {
+        Ok(notifier) => notifier.send(notification, priority).await,
+        Err(e) => Err(e),
+    };
+    let label = target.service.label();
+    match result {
+        Ok(()) => NotifyResult { target: label, ok: true, error: None },
+        Err(e) => {
+            eprintln!("Notification to {} failed: {}", label, e);
+            NotifyResult { target: label, ok: false, error: Some(e.to_string()) }
+        }
+    }
+}
+
+/// Sends `notification` to every target that wants its kind.
+pub async fn dispatch(targets: &[NotifierTarget], notification: &Notification) -> Vec<NotifyResult> {
+    let mut results = Vec::new();
+    for target in targets {
+        if let Some(priority) = target.alerts.get(&notification.kind) {
+            results.push(send_to(target, notification, *priority).await);
+        }
+    }
+    results
+}
+
+/// Sends a sample `kind` alert to every target, whether or not it wants that kind, at the
+/// priority it would use (or `Default`).
+pub async fn send_test(targets: &[NotifierTarget], kind: AlertKind) -> Vec<NotifyResult> {
+    let notification = test_notification(kind);
+    let mut results = Vec::new();
+    for target in targets {
+        let priority = target.alerts.get(&kind).copied().unwrap_or(Priority::Default);
+        results.push(send_to(target, &notification, priority).await);
+    }
+    results
+}
+
+impl NotifierConfig {
+    fn label(&self) -> String {
+        match self {
+            NotifierConfig::Ntfy { server, topic } => format!("ntfy {}/{}", server.trim_end_matches('/'), topic),
+            NotifierConfig::Gotify { server } => format!("gotify {}", server),
+        }
+    }
+}
+
+fn test_notification(kind: AlertKind) -> Notification {
+    Notification {
+        kind,
+        camera_id: 0,
+        camera_name: "Test camera".to_string(),
+        title: "Blink Monitor test".to_string(),
+        message: format!("Test {} alert from Blink Monitor", kind.tag().replace('_', " ")),
+        thumbnail: None,
+        days_remaining: None,
+    }
+}
+
+fn low_battery_message(camera_name: &str, days_remaining: Option<f64>) -> String {
+    match days_remaining {
+        Some(days) if days < 1.0 => format!("Replace the batteries in {} soon; they may run out within a day", camera_name),
+        Some(days) => format!("Replace the batteries in {} soon; about {:.0} days left at the recent rate", camera_name, days),
+        None => format!("Replace the batteries in {} soon", camera_name),
+    }
+}
+
+/// Turns new clips and camera state changes into notifications. The first look at the
+/// feed and the cameras only sets the baseline, so a restart doesn't replay old alerts.
+#[derive(Default)]
+pub struct Alerter {
+    state: Mutex<AlerterState>,
+}
+
+#[derive(Default)]
+struct AlerterState {
+    last_media_id: Option<i64>,
+    /// Cameras seen offline and with a low battery on the last look; `None` before the first.
+    offline: Option<HashSet<i64>>,
+    low_battery: Option<HashSet<i64>>,
+    /// Sync modules whose drive was full or failing on the last look.
+    usb_attention: Option<HashSet<i64>>,
+    last_sent: HashMap<(i64, AlertKind), Instant>,
+}
+
+fn is_offline(camera: &Camera) -> bool {
+    camera.status.eq_ignore_ascii_case("offline")
+}
+
+fn is_low_battery(camera: &Camera) -> bool {
+    camera.battery.as_deref().is_some_and(|b| b.eq_ignore_ascii_case("low"))
+}
+
+impl Alerter {
+    /// Whether an alert for this camera and type may go out now; marks it sent if so.
+    fn allow(&self, camera_id: i64, kind: AlertKind) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(ALERT_COOLDOWN_SECS);
+        if state.last_sent.get(&(camera_id, kind)).is_some_and(|sent| now.duration_since(*sent) < cooldown) {
+            return false;
+        }
+        state.last_sent.insert((camera_id, kind), now);
+        true
+    }
+
+    /// Cameras that went offline or low since the last call, from the homescreen.
+    fn camera_changes<'c>(&self, cameras: &'c [Camera]) -> Vec<(AlertKind, &'c Camera)> {
+        let mut state = self.state.lock().unwrap();
+        let offline: HashSet<i64> = cameras.iter().filter(|c| is_offline(c)).map(|c| c.id).collect();
+        let low: HashSet<i64> = cameras.iter().filter(|c| is_low_battery(c)).map(|c| c.id).collect();
+        let mut changes = Vec::new();
+        if let Some(before) = &state.offline {
+            changes.extend(cameras.iter().filter(|c| offline.contains(&c.id) && !before.contains(&c.id)).map(|c| (AlertKind::Offline, c)));
+        }
+        if let Some(before) = &state.low_battery {
+            changes.extend(cameras.iter().filter(|c| low.contains(&c.id) && !before.contains(&c.id)).map(|c| (AlertKind::LowBattery, c)));
+        }
+        state.offline = Some(offline);
+        state.low_battery = Some(low);
+        changes
+    }
+
+    /// Sync modules whose USB drive turned full or failed since the last call.
+    fn usb_changes<'s>(&self, sync_modules: &'s [SyncModule]) -> Vec<&'s SyncModule> {
+        let mut state = self.state.lock().unwrap();
+        let attention: HashSet<i64> = sync_modules.iter()
+            .filter(|s| s.local_storage_enabled && s.local_storage_status.needs_attention())
+            .map(|s| s.id)
+            .collect();
+        let changes = match &state.usb_attention {
+            Some(before) => sync_modules.iter().filter(|s| attention.contains(&s.id) && !before.contains(&s.id)).collect(),
+            None => Vec::new(),
+        };
+        state.usb_attention = Some(attention);
+        changes
+    }
+
+    /// Looks for new clips and camera changes and sends whatever the targets want. Low
+    /// battery alerts carry the projection from `battery`. Does nothing when no target
+    /// wants anything.
+    pub async fn poll(&self, client: &BlinkClient, targets: &[NotifierTarget], battery: &BatteryStore) -> Result<()> {
+        let wants = |kind: AlertKind| targets.iter().any(|t| t.alerts.contains_key(&kind));
+        let mut notifications = Vec::new();
+
+        if wants(AlertKind::Motion) {
+            let items = client.get_media_page(1, 1).await?;
+            let cursor = {
+                let mut state = self.state.lock().unwrap();
+                let cursor = state.last_media_id;
+                if let Some(newest) = items.iter().map(|item| item.id).max() {
+                    state.last_media_id = Some(cursor.map_or(newest, |c| c.max(newest)));
+                }
+                cursor
+            };
+            if let Some(cursor) = cursor {
+                let mut fresh: Vec<_> = items.iter().filter(|item| item.id > cursor && !item.deleted).collect();
+                fresh.sort_by_key(|item| item.id);
+                for item in fresh {
+                    let camera_id = item.device_id.unwrap_or(0);
+                    if !self.allow(camera_id, AlertKind::Motion) {
+                        continue;
+                    }
+                    let camera_name = item.device_name.clone().unwrap_or_else(|| "Camera".to_string());
+                    notifications.push((item.thumbnail.clone(), Notification {
+                        kind: AlertKind::Motion,
+                        camera_id,
+                        title: format!("Motion on {}", camera_name),
+                        message: format!("{} detected motion", camera_name),
+                        camera_name,
+                        thumbnail: None,
+                        days_remaining: None,
+                    }));
+                }
+            }
+        }
+
+        if wants(AlertKind::Offline) || wants(AlertKind::LowBattery) || wants(AlertKind::UsbStorage) {
+            let homescreen = client.get_homescreen().await?;
+            if wants(AlertKind::UsbStorage) {
+                for module in self.usb_changes(&homescreen.sync_modules) {
+                    if !self.allow(module.id, AlertKind::UsbStorage) {
+                        continue;
+                    }
+                    let name = if module.name.is_empty() { "Sync module".to_string() } else { module.name.clone() };
+                    let problem = match module.local_storage_status {
+                        UsbStorageState::Full => "is full",
+                        _ => "reported an error",
+                    };
+                    notifications.push((None, Notification {
+                        kind: AlertKind::UsbStorage,
+                        camera_id: module.id,
+                        title: format!("USB storage on {} {}", name, problem),
+                        message: format!("The USB drive in {} {}; cameras on it stop saving local clips until it is fixed", name, problem),
+                        camera_name: name,
+                        thumbnail: None,
+                        days_remaining: None,
+                    }));
+                }
+            }
+            let cameras: Vec<Camera> = homescreen.cameras.into_iter()
+                .chain(homescreen.owls)
+                .chain(homescreen.doorbells)
+                .collect();
+            for (kind, camera) in self.camera_changes(&cameras) {
+                if !wants(kind) || !self.allow(camera.id, kind) {
+                    continue;
+                }
+                let days_remaining = (kind == AlertKind::LowBattery).then(|| battery.days_remaining(camera.id)).flatten();
+                let (title, message) = match kind {
+                    AlertKind::Offline => (format!("{} is offline", camera.name), format!("{} stopped responding to Blink", camera.name)),
+                    _ => (format!("{} battery is low", camera.name), low_battery_message(&camera.name, days_remaining)),
+                };
+                notifications.push((Some(camera.thumbnail.clone()), Notification {
+                    kind,
+                    camera_id: camera.id,
+                    camera_name: camera.name.clone(),
+                    title,
+                    message,
+                    thumbnail: None,
+                    days_remaining,
+                }));
+            }
+        }
+
+        for (thumbnail, mut notification) in notifications {
+            if let Some(path) = thumbnail.filter(|p| !p.is_empty()) {
+                match client.get_thumbnail(&path).await {
+                    Ok(image) => notification.thumbnail = Some(image.bytes),
+                    Err(e) => eprintln!("Thumbnail for {} alert failed: {}", notification.camera_name, e),
+                }
+            }
+            dispatch(targets, &notification).await;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn low_battery_alerts_mention_the_projection_when_there_is_one() {
+        assert_eq!(low_battery_message("Porch", None), "Replace the batteries in Porch soon");
+        assert_eq!(low_battery_message("Porch", Some(11.6)), "Replace the batteries in Porch soon; about 12 days left at the recent rate");
+        assert_eq!(low_battery_message("Porch", Some(0.4)), "Replace the batteries in Porch soon; they may run out within a day");
+    }
+}
@@ -0,0 +1,211 @@
+//! Temporary disarms: a network is disarmed with a deadline, and the desktop app and the
+//! server each drive `RearmQueue::tick` to arm it again when the deadline passes. Deadlines
+//! are kept on disk as wall-clock times, so one that passed while the process was stopped,
+//! or that a clock change jumped over, fires on the first tick rather than being dropped.
+
+use crate::audit;
+use crate::blink::{self, ArmVerification, BlinkClient, Network};
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+pub const REARM_FILE: &str = "rearm.json";
+pub const TICK_SECS: u64 = 5;
+pub const MAX_DISARM_MINUTES: u32 = 24 * 60;
+const FIRST_RETRY_SECS: i64 = 60;
+const MAX_RETRY_SECS: i64 = 15 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingRearm {
+    pub network_id: i64,
+    pub rearm_at: DateTime<Utc>,
+    pub disarmed_at: DateTime<Utc>,
+    /// Failed re-arm attempts so far; drives the retry backoff.
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub retry_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl PendingRearm {
+    /// Due once the deadline has passed, or straight away if the clock now reads earlier
+    /// than the disarm itself, since the deadline can't be trusted after such a jump.
+    fn is_due(&self, now: DateTime<Utc>) -> bool {
+        (self.rearm_at <= now || now < self.disarmed_at) && self.retry_at.is_none_or(|at| at <= now)
+    }
+}
+
+/// Result of `RearmQueue::disarm_for`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TemporaryDisarm {
+    pub verification: ArmVerification,
+    pub rearm: PendingRearm,
+}
+
+/// One automatic re-arm attempt.
+#[derive(Debug, Clone, Serialize)]
+pub struct RearmRun {
+    pub network_id: i64,
+    pub rearm_at: DateTime<Utc>,
+    pub executed_at: DateTime<Utc>,
+    /// Whether the homescreen showed the network armed afterwards.
+    pub confirmed: bool,
+    /// Set when the attempt failed; it is retried with backoff until it succeeds or is
+    /// cancelled.
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for RearmRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            None if self.confirmed => write!(f, "Network {} re-armed after a temporary disarm", self.network_id),
+            None => write!(f, "Network {} re-armed after a temporary disarm (not yet confirmed)", self.network_id),
+            Some(e) => write!(f, "Re-arming network {} failed, will retry: {}", self.network_id, e),
+        }
+    }
+}
+
+/// Pending re-arms, at most one per network, kept in `REARM_FILE`.
+pub struct RearmQueue {
+    path: PathBuf,
+    entries: std::sync::Mutex<Vec<PendingRearm>>,
+}
+
+/// Rejects durations outside 1..=`MAX_DISARM_MINUTES`.
+pub fn validate_minutes(minutes: u32) -> Result<()> {
+    if minutes == 0 || minutes > MAX_DISARM_MINUTES {
+        return Err(anyhow!("minutes must be between 1 and {}", MAX_DISARM_MINUTES));
+    }
+    Ok(())
+}
+
+impl RearmQueue {
+    /// Reads the re-arms left by a previous run; a missing or unreadable file starts empty.
+    pub fn load(path: PathBuf) -> Self {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { path, entries: std::sync::Mutex::new(entries) }
+    }
+
+    fn save(&self, entries: &[PendingRearm]) {
+        let result = serde_json::to_vec_pretty(entries)
+            .map_err(std::io::Error::from)
+            .and_then(|json| fs::write(&self.path, json));
+        if let Err(e) = result {
+            eprintln!("Failed to save pending re-arms: {}", e);
+        }
+    }
+
+    /// Pending re-arms, soonest first.
+    pub fn pending(&self) -> Vec<PendingRearm> {
+        let mut entries = self.entries.lock().unwrap().clone();
+        entries.sort_by_key(|e| e.rearm_at);
+        entries
+    }
+
+    /// Fills in `rearm_at` on the networks that have a re-arm pending.
+    pub fn annotate(&self, networks: &mut [Network]) {
+        let entries = self.entries.lock().unwrap();
+        for network in networks {
+            network.rearm_at = entries.iter().find(|e| e.network_id == network.id).map(|e| e.rearm_at);
+        }
+    }
+
+    /// Disarms the network and re-arms it `minutes` from now. Calling it again while a
+    /// re-arm is pending replaces the deadline.
+    pub async fn disarm_for(&self, client: &BlinkClient, network_id: i64, minutes: u32) -> Result<TemporaryDisarm> {
+        validate_minutes(minutes)?;
+        let timeout = std::time::Duration::from_secs(blink::ARM_VERIFY_TIMEOUT_SECS);
+        let verification = client.set_arm_verified(network_id, false, timeout).await?;
+        let now = Utc::now();
+        let rearm = PendingRearm {
+            network_id,
+            rearm_at: now + Duration::minutes(i64::from(minutes)),
+            disarmed_at: now,
+            attempts: 0,
+            retry_at: None,
+            last_error: None,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|e| e.network_id != network_id);
+        entries.push(rearm.clone());
+        self.save(&entries);
+        Ok(TemporaryDisarm { verification, rearm })
+    }
+
+    /// Pushes a pending re-arm back by `minutes`.
+    pub fn extend(&self, network_id: i64, minutes: u32) -> Result<PendingRearm> {
+        validate_minutes(minutes)?;
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.iter_mut()
+            .find(|e| e.network_id == network_id)
+            .ok_or_else(|| anyhow!("No re-arm pending for network {}", network_id))?;
+        entry.rearm_at = entry.rearm_at.max(Utc::now()) + Duration::minutes(i64::from(minutes));
+        let entry = entry.clone();
+        self.save(&entries);
+        Ok(entry)
+    }
+
+    /// Drops the pending re-arm, leaving the network as it is. Also called whenever the
+    /// network is armed or disarmed by hand, which supersedes the temporary disarm.
+    pub fn cancel(&self, network_id: i64) -> Option<PendingRearm> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|e| e.network_id == network_id)?;
+        let removed = entries.remove(index);
+        self.save(&entries);
+        Some(removed)
+    }
+
+    /// Arms every network whose deadline has passed. Failed attempts stay queued and are
+    /// retried with backoff.
+    pub async fn tick(&self, blink_client: &Mutex<BlinkClient>) -> Vec<RearmRun> {
+        let now = Utc::now();
+        let due: Vec<PendingRearm> = self.entries.lock().unwrap().iter()
+            .filter(|e| e.is_due(now))
+            .cloned()
+            .collect();
+        if due.is_empty() {
+            return Vec::new();
+        }
+
+        let client = shared::snapshot(blink_client).await;
+        let timeout = std::time::Duration::from_secs(blink::ARM_VERIFY_TIMEOUT_SECS);
+        let mut runs = Vec::new();
+        for entry in due {
+            let result = audit::with_source("rearm".to_string(), client.set_arm_verified(entry.network_id, true, timeout)).await;
+            let executed_at = Utc::now();
+            let mut entries = self.entries.lock().unwrap();
+            // Leave the entry alone if it was cancelled or extended while the arm ran.
+            let current = entries.iter().position(|e| e.network_id == entry.network_id && e.rearm_at == entry.rearm_at);
+            let run = match result {
+                Ok(verification) => {
+                    if let Some(index) = current {
+                        entries.remove(index);
+                    }
+                    RearmRun { network_id: entry.network_id, rearm_at: entry.rearm_at, executed_at, confirmed: verification.confirmed, error: None }
+                }
+                Err(e) => {
+                    if let Some(index) = current {
+                        let pending = &mut entries[index];
+                        pending.attempts += 1;
+                        let backoff = (FIRST_RETRY_SECS << (pending.attempts - 1).min(10)).min(MAX_RETRY_SECS);
+                        pending.retry_at = Some(executed_at + Duration::seconds(backoff));
+                        pending.last_error = Some(e.to_string());
+                    }
+                    RearmRun { network_id: entry.network_id, rearm_at: entry.rearm_at, executed_at, confirmed: false, error: Some(e.to_string()) }
+                }
+            };
+            self.save(&entries);
+            runs.push(run);
+        }
+        runs
+    }
+}
@@ -0,0 +1,447 @@
+//! Tee-to-disk recording of live sessions. A session started with `record` while
+//! `liveview_recording_dir` is set gets a `Recorder`, which is fed every MPEG-TS chunk
+//! from IMMI before the consumer's backpressure can drop any, the same as the replay
+//! buffer. The raw stream always goes to disk; with the `mp4` format it is also remuxed
+//! on the fly into a fragmented MP4 (see `mp4::Remuxer`), which is rewritten with a
+//! faststart `moov` when the session ends. The raw stream is only deleted once the MP4 is
+//! complete: if either step fails it becomes the recording as `<stem>.ts`, and the sidecar
+//! says why. Files appear under their final names only once complete, and each recording
+//! gets a `<stem>.json` sidecar with its camera, start time and duration.
+
+use crate::{liveview, log_control, mp4, mpegts};
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+
+/// How far into either end of a `.ts` file `ts_duration_secs` looks for a PCR.
+const PCR_SCAN_BYTES: u64 = 1024 * 1024;
+
+/// What a recording is written as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingFormat {
+    /// The camera's MPEG-TS, as received.
+    Ts,
+    /// Remuxed into a faststart MP4; no re-encoding.
+    #[default]
+    Mp4,
+}
+
+/// The sidecar written next to each recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingSidecar {
+    pub session_id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub duration_secs: f64,
+    /// The format the recording ended up in, `ts` when the MP4 couldn't be finished.
+    pub format: RecordingFormat,
+    pub path: String,
+    pub bytes: u64,
+    /// Why the recording isn't in the requested format, or is incomplete.
+    pub error: Option<String>,
+}
+
+/// A recording found in the recordings folder.
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingInfo {
+    pub path: String,
+    pub format: RecordingFormat,
+    pub bytes: u64,
+    /// Read from the container itself; `None` when it can't be parsed.
+    pub duration_secs: Option<f64>,
+    /// From the sidecar, or the file's modification time without one.
+    pub started_at: DateTime<Utc>,
+    pub session_id: Option<String>,
+    pub network_id: Option<i64>,
+    pub camera_id: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// The session a recording belongs to.
+#[derive(Debug, Clone)]
+pub struct RecordingMeta {
+    pub session_id: String,
+    pub network_id: i64,
+    pub camera_id: i64,
+    pub started_at: DateTime<Utc>,
+}
+
+/// Picks the path a new recording of `camera_id` will end up at in `dir`, creating the
+/// folder if needed. Names never collide with an earlier recording's files.
+pub fn target(dir: &Path, camera_id: i64, started_at: DateTime<Utc>, format: RecordingFormat) -> Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    let base = format!("live-{}-{}", camera_id, started_at.format("%Y%m%d-%H%M%S"));
+    let ext = extension(format);
+    for n in 1.. {
+        let stem = if n == 1 { base.clone() } else { format!("{}-{}", base, n) };
+        let taken = ["mp4", "ts", "json"].iter().any(|e| dir.join(format!("{}.{}", stem, e)).exists());
+        if !taken {
+            return Ok(dir.join(format!("{}.{}", stem, ext)));
+        }
+    }
+    unreachable!()
+}
+
+fn extension(format: RecordingFormat) -> &'static str {
+    match format {
+        RecordingFormat::Ts => "ts",
+        RecordingFormat::Mp4 => "mp4",
+    }
+}
+
+/// Appends `suffix` to the whole file name, so `a.mp4` becomes `a.mp4.part`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Feeds a recording that is written on its own thread, so a slow disk never holds up
+/// the relay. Dropping it ends the recording; the thread then finishes the file and
+/// writes the sidecar.
+pub struct Recorder {
+    tx: mpsc::Sender<Bytes>,
+    thread: JoinHandle<RecordingSidecar>,
+}
+
+impl Recorder {
+    /// Opens the files for a recording that ends up at `path` (from `target`).
+    pub fn start(path: &Path, format: RecordingFormat, meta: RecordingMeta) -> Result<Recorder> {
+        let raw_path = with_suffix(&path.with_extension("ts"), ".part");
+        let raw = BufWriter::new(fs::File::create(&raw_path)?);
+        let remux = match format {
+            RecordingFormat::Ts => None,
+            RecordingFormat::Mp4 => Some(mp4::Remuxer::new(BufWriter::new(fs::File::create(with_suffix(path, ".frag.part"))?))),
+        };
+        let (tx, rx) = mpsc::channel();
+        let job = Job { path: path.to_path_buf(), raw_path, meta };
+        let thread = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || job.run(rx, raw, remux))?;
+        Ok(Recorder { tx, thread })
+    }
+
+    pub fn push(&self, chunk: Bytes) {
+        let _ = self.tx.send(chunk);
+    }
+
+    /// Ends the recording and waits for the file to be finished.
+    pub fn finish(self) -> Result<RecordingSidecar> {
+        let Recorder { tx, thread } = self;
+        drop(tx);
+        thread.join().map_err(|_| anyhow!("The recorder thread panicked"))
+    }
+}
+
+struct Job {
+    path: PathBuf,
+    raw_path: PathBuf,
+    meta: RecordingMeta,
+}
+
+impl Job {
+    fn run(self, rx: mpsc::Receiver<Bytes>, raw: BufWriter<fs::File>, mut remux: Option<mp4::Remuxer<BufWriter<fs::File>>>) -> RecordingSidecar {
+        let mut raw = Some(raw);
+        let mut raw_error = None;
+        let mut mp4_error = None;
+        let mut first = true;
+        for chunk in rx {
+            if let Some(writer) = raw.as_mut() {
+                let mut written = Ok(());
+                // Like a saved replay, the file starts with a PAT/PMT pair so players can probe it.
+                if first && !mpegts::has_pat(&chunk[..chunk.len().min(mpegts::PACKET_LEN)]) {
+                    for table in liveview::program_tables(&chunk) {
+                        written = written.and_then(|_| writer.write_all(&table));
+                    }
+                }
+                if let Err(e) = written.and_then(|_| writer.write_all(&chunk)) {
+                    log_control::warn("liveview", || format!("Writing {} failed: {}", self.raw_path.display(), e));
+                    raw_error = Some(e.to_string());
+                    raw = None;
+                }
+            }
+            if let Some(remuxer) = remux.as_mut() {
+                if let Err(e) = remuxer.push(&chunk) {
+                    log_control::warn("liveview", || format!("Remuxing {} failed: {}", self.path.display(), e));
+                    mp4_error = Some(e.to_string());
+                    remux = None;
+                    let _ = fs::remove_file(with_suffix(&self.path, ".frag.part"));
+                }
+            }
+            first = false;
+        }
+        if let Some(writer) = raw {
+            if let Err(e) = writer.into_inner().map_err(|e| e.into_error()).and_then(|file| file.sync_all()) {
+                raw_error = Some(e.to_string());
+            }
+        }
+
+        let mut error = mp4_error;
+        let mut format = RecordingFormat::Ts;
+        if let Some(remuxer) = remux {
+            match self.finish_mp4(remuxer) {
+                Ok(()) => format = RecordingFormat::Mp4,
+                Err(e) => {
+                    log_control::warn("liveview", || format!("Finishing {} failed, keeping the raw stream: {:#}", self.path.display(), e));
+                    error = Some(format!("{:#}", e));
+                }
+            }
+        }
+        let path = match format {
+            RecordingFormat::Mp4 => {
+                let _ = fs::remove_file(&self.raw_path);
+                self.path.clone()
+            }
+            RecordingFormat::Ts => {
+                let path = self.path.with_extension("ts");
+                if let Err(e) = fs::rename(&self.raw_path, &path) {
+                    raw_error.get_or_insert(e.to_string());
+                }
+                path
+            }
+        };
+        if let Some(e) = raw_error {
+            error = Some(match error {
+                Some(earlier) => format!("{}; writing the raw stream failed: {}", earlier, e),
+                None => format!("Writing the raw stream failed: {}", e),
+            });
+        }
+        let duration_secs = match format {
+            RecordingFormat::Mp4 => mp4::duration_secs(&path),
+            RecordingFormat::Ts => ts_duration_secs(&path),
+        };
+        let sidecar = RecordingSidecar {
+            session_id: self.meta.session_id.clone(),
+            network_id: self.meta.network_id,
+            camera_id: self.meta.camera_id,
+            started_at: self.meta.started_at,
+            ended_at: Utc::now(),
+            duration_secs: duration_secs.unwrap_or(0.0),
+            format,
+            path: path.to_string_lossy().into_owned(),
+            bytes: fs::metadata(&path).map(|m| m.len()).unwrap_or(0),
+            error,
+        };
+        if let Err(e) = write_sidecar(&self.path.with_extension("json"), &sidecar) {
+            log_control::warn("liveview", || format!("Writing the sidecar for {} failed: {}", path.display(), e));
+        }
+        log_control::info("liveview", || format!("Recorded {:.1}s of camera {} to {}", sidecar.duration_secs, sidecar.camera_id, sidecar.path));
+        sidecar
+    }
+
+    /// Closes the fragmented MP4 and rewrites it with a faststart `moov`; the partial
+    /// files are gone either way.
+    fn finish_mp4(&self, remuxer: mp4::Remuxer<BufWriter<fs::File>>) -> Result<()> {
+        let fragmented = with_suffix(&self.path, ".frag.part");
+        let partial = with_suffix(&self.path, ".part");
+        let result = remuxer
+            .finish()
+            .and_then(|finished| finished.faststart(&fragmented, &partial))
+            .and_then(|_| Ok(fs::rename(&partial, &self.path)?));
+        let _ = fs::remove_file(&fragmented);
+        if result.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        result
+    }
+}
+
+fn write_sidecar(path: &Path, sidecar: &RecordingSidecar) -> Result<()> {
+    let partial = with_suffix(path, ".part");
+    fs::write(&partial, serde_json::to_vec_pretty(sidecar)?)?;
+    fs::rename(&partial, path)?;
+    Ok(())
+}
+
+/// Length in seconds of an MPEG-TS file, from the first PCR near its start to the last
+/// one near its end.
+pub fn ts_duration_secs(path: &Path) -> Result<f64> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let mut head = Vec::new();
+    (&mut file).take(PCR_SCAN_BYTES).read_to_end(&mut head)?;
+    let first = mpegts::first_pcr(&head).ok_or_else(|| anyhow!("{} has no PCR", path.display()))?;
+    let packet = mpegts::PACKET_LEN as u64;
+    let tail_start = len.saturating_sub(PCR_SCAN_BYTES) / packet * packet;
+    file.seek(SeekFrom::Start(tail_start))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let last = mpegts::last_pcr(&tail).unwrap_or(first);
+    Ok((last.wrapping_sub(first) & ((1 << 33) - 1)) as f64 / 90_000.0)
+}
+
+/// The recordings in `dir`, newest first, with durations read from the files themselves.
+/// Recordings still being written (`.part` files) are skipped.
+pub fn list(dir: &Path) -> Result<Vec<RecordingInfo>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut media = Vec::new();
+    let mut sidecars: HashMap<PathBuf, RecordingSidecar> = HashMap::new();
+    for entry in entries {
+        let path = entry?.path();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("mp4") => media.push((path, RecordingFormat::Mp4)),
+            Some("ts") => media.push((path, RecordingFormat::Ts)),
+            Some("json") => {
+                if let Some(sidecar) = fs::read(&path).ok().and_then(|b| serde_json::from_slice::<RecordingSidecar>(&b).ok()) {
+                    sidecars.insert(path.with_extension(""), sidecar);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut recordings = Vec::new();
+    for (path, format) in media {
+        let sidecar = sidecars.get(&path.with_extension(""));
+        let metadata = fs::metadata(&path)?;
+        let duration_secs = match format {
+            RecordingFormat::Mp4 => mp4::duration_secs(&path),
+            RecordingFormat::Ts => ts_duration_secs(&path),
+        };
+        recordings.push(RecordingInfo {
+            path: path.to_string_lossy().into_owned(),
+            format,
+            bytes: metadata.len(),
+            duration_secs: duration_secs.ok(),
+            started_at: sidecar
+                .map(|s| s.started_at)
+                .or_else(|| metadata.modified().ok().map(DateTime::<Utc>::from))
+                .unwrap_or_else(Utc::now),
+            session_id: sidecar.map(|s| s.session_id.clone()),
+            network_id: sidecar.map(|s| s.network_id),
+            camera_id: sidecar.map(|s| s.camera_id),
+            error: sidecar.and_then(|s| s.error.clone()),
+        });
+    }
+    recordings.sort_by(|a, b| b.started_at.cmp(&a.started_at).then_with(|| a.path.cmp(&b.path)));
+    Ok(recordings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_live;
+    use chrono::TimeZone;
+
+    fn meta() -> RecordingMeta {
+        RecordingMeta {
+            session_id: "session-1".to_string(),
+            network_id: 7,
+            camera_id: 42,
+            started_at: Utc.with_ymd_and_hms(2026, 3, 1, 12, 30, 0).unwrap(),
+        }
+    }
+
+    /// Records `ts` in IMMI-sized chunks and returns the sidecar and what is left in `dir`.
+    fn record(dir: &Path, format: RecordingFormat, ts: &[u8]) -> (RecordingSidecar, Vec<String>) {
+        let path = target(dir, 42, meta().started_at, format).unwrap();
+        let recorder = Recorder::start(&path, format, meta()).unwrap();
+        for chunk in ts.chunks(7 * mpegts::PACKET_LEN) {
+            recorder.push(Bytes::copy_from_slice(chunk));
+        }
+        let sidecar = recorder.finish().unwrap();
+        let mut files: Vec<String> = fs::read_dir(dir).unwrap().map(|e| e.unwrap().file_name().to_string_lossy().into_owned()).collect();
+        files.sort();
+        (sidecar, files)
+    }
+
+    #[test]
+    fn an_mp4_recording_replaces_the_raw_stream() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sidecar, files) = record(dir.path(), RecordingFormat::Mp4, &fake_live::test_pattern(0..20));
+        assert_eq!(files, ["live-42-20260301-123000.json", "live-42-20260301-123000.mp4"]);
+        assert_eq!(sidecar.format, RecordingFormat::Mp4);
+        assert_eq!(sidecar.duration_secs, 2.0);
+        assert_eq!(sidecar.error, None);
+        assert_eq!((sidecar.session_id.as_str(), sidecar.network_id, sidecar.camera_id), ("session-1", 7, 42));
+        assert!(sidecar.path.ends_with(".mp4"));
+        assert_eq!(sidecar.bytes, fs::metadata(&sidecar.path).unwrap().len());
+        let written: RecordingSidecar = serde_json::from_slice(&fs::read(dir.path().join(&files[0])).unwrap()).unwrap();
+        assert_eq!(written, sidecar);
+    }
+
+    #[test]
+    fn a_stream_that_cannot_become_an_mp4_is_kept_as_ts() {
+        // No keyframe, so the remuxer has nothing to start from; the raw data survives.
+        let dir = tempfile::tempdir().unwrap();
+        let (sidecar, files) = record(dir.path(), RecordingFormat::Mp4, &fake_live::test_pattern(1..10));
+        assert_eq!(files, ["live-42-20260301-123000.json", "live-42-20260301-123000.ts"]);
+        assert_eq!(sidecar.format, RecordingFormat::Ts);
+        assert!(sidecar.path.ends_with(".ts"));
+        assert!(sidecar.error.as_deref().unwrap().contains("no H.264 keyframe"), "{:?}", sidecar.error);
+        // Nine frames 0.1s apart.
+        assert!((sidecar.duration_secs - 0.8).abs() < 1e-9, "{}", sidecar.duration_secs);
+    }
+
+    #[test]
+    fn a_ts_recording_starts_with_program_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sidecar, files) = record(dir.path(), RecordingFormat::Ts, &fake_live::test_pattern(5..25));
+        assert_eq!(files, ["live-42-20260301-123000.json", "live-42-20260301-123000.ts"]);
+        assert_eq!(sidecar.error, None);
+        let data = fs::read(&sidecar.path).unwrap();
+        assert!(mpegts::has_pat(&data[..mpegts::PACKET_LEN]));
+        assert!((sidecar.duration_secs - 1.9).abs() < 1e-9, "{}", sidecar.duration_secs);
+    }
+
+    #[test]
+    fn new_recordings_never_reuse_a_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let at = meta().started_at;
+        let first = target(dir.path(), 42, at, RecordingFormat::Mp4).unwrap();
+        assert_eq!(first.file_name().unwrap(), "live-42-20260301-123000.mp4");
+        // A fallback .ts or a lone sidecar takes the name just as well.
+        fs::write(dir.path().join("live-42-20260301-123000.ts"), b"").unwrap();
+        let second = target(dir.path(), 42, at, RecordingFormat::Mp4).unwrap();
+        assert_eq!(second.file_name().unwrap(), "live-42-20260301-123000-2.mp4");
+        fs::write(dir.path().join("live-42-20260301-123000-2.json"), b"{}").unwrap();
+        let third = target(dir.path(), 42, at, RecordingFormat::Ts).unwrap();
+        assert_eq!(third.file_name().unwrap(), "live-42-20260301-123000-3.ts");
+    }
+
+    #[test]
+    fn listing_reads_durations_from_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mp4, _) = record(dir.path(), RecordingFormat::Mp4, &fake_live::test_pattern(0..30));
+        let (ts, _) = record(dir.path(), RecordingFormat::Ts, &fake_live::test_pattern(0..11));
+        // A sidecar's figure is not what the listing reports.
+        let mut wrong = mp4.clone();
+        wrong.duration_secs = 99.0;
+        fs::write(dir.path().join("live-42-20260301-123000.json"), serde_json::to_vec(&wrong).unwrap()).unwrap();
+        // Partial files and unrelated ones are skipped; a file without a sidecar is listed.
+        fs::write(dir.path().join("live-9-20260101-000000.mp4.part"), b"partial").unwrap();
+        fs::write(dir.path().join("notes.txt"), b"").unwrap();
+        fs::copy(&ts.path, dir.path().join("copied.ts")).unwrap();
+
+        let list = list(dir.path()).unwrap();
+        assert_eq!(list.len(), 3);
+        let by_path = |p: &str| list.iter().find(|r| r.path == p).unwrap();
+        let listed = by_path(&mp4.path);
+        assert_eq!((listed.format, listed.duration_secs, listed.camera_id), (RecordingFormat::Mp4, Some(3.0), Some(42)));
+        let listed = by_path(&ts.path);
+        assert_eq!((listed.format, listed.duration_secs), (RecordingFormat::Ts, Some(1.0)));
+        let copied = by_path(&dir.path().join("copied.ts").to_string_lossy());
+        assert_eq!((copied.duration_secs, copied.camera_id, copied.session_id.as_deref()), (Some(1.0), None, None));
+        assert!(list.iter().all(|r| !r.path.ends_with(".part")));
+    }
+
+    #[test]
+    fn a_missing_folder_lists_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(list(&dir.path().join("absent")).unwrap().is_empty());
+    }
+}
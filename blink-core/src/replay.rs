@@ -0,0 +1,166 @@
+//! Instant replay: the last few seconds of a live session kept in memory so they can be
+//! saved after the fact, long before Blink's own clip is uploaded. The buffer holds the
+//! MPEG-TS chunks as they arrive from IMMI, before the consumer's backpressure can drop
+//! any, and always keeps the keyframe the window starts from so a saved replay decodes
+//! from its first frame.
+
+use crate::{liveview, mpegts};
+use anyhow::Result;
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Longest replay window the setting accepts.
+pub const MAX_REPLAY_SECS: u64 = 120;
+/// Per-session memory cap; older chunks go first once a high-bitrate stream reaches it.
+pub const MAX_REPLAY_BYTES: usize = 32 * 1024 * 1024;
+
+struct Chunk {
+    at: Instant,
+    /// Starts with a PAT or a keyframe, so playback can begin here.
+    key: bool,
+    data: Bytes,
+}
+
+#[derive(Default)]
+struct Inner {
+    window: Duration,
+    chunks: VecDeque<Chunk>,
+    bytes: usize,
+}
+
+/// The rolling buffer of one session. Empty and inert until `set_window` enables it.
+#[derive(Default)]
+pub struct ReplayBuffer {
+    inner: Mutex<Inner>,
+}
+
+/// How much the buffer holds, as published in `LiveSessionInfo`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReplayUsage {
+    /// The configured window; 0 when replay is off.
+    pub window_secs: u64,
+    /// Seconds of stream held, from the oldest chunk to the newest.
+    pub buffered_secs: f64,
+    pub buffered_bytes: usize,
+}
+
+/// A replay written to disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplaySaved {
+    pub session_id: String,
+    pub path: String,
+    pub bytes: u64,
+    /// Seconds from the keyframe the file starts on to its last chunk.
+    pub duration_secs: f64,
+}
+
+impl ReplayBuffer {
+    /// Sets how many seconds to keep; 0 turns the buffer off and frees it.
+    pub fn set_window(&self, secs: u64) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.window = Duration::from_secs(secs.min(MAX_REPLAY_SECS));
+        if inner.window.is_zero() {
+            inner.chunks.clear();
+            inner.bytes = 0;
+        }
+    }
+
+    /// Appends a chunk and evicts what fell out of the window, keeping the last keyframe
+    /// at or before the window's start so the whole window stays playable.
+    pub fn push(&self, data: Bytes) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.window.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let key = mpegts::has_pat(&data) || mpegts::has_random_access(&data);
+        inner.bytes += data.len();
+        inner.chunks.push_back(Chunk { at: now, key, data });
+
+        let cutoff = now.checked_sub(inner.window).unwrap_or(now);
+        let keep_from = inner.chunks.iter()
+            .take_while(|c| c.at <= cutoff)
+            .enumerate()
+            .filter(|(_, c)| c.key)
+            .map(|(i, _)| i)
+            .last()
+            .unwrap_or_else(|| inner.chunks.iter().take_while(|c| c.at <= cutoff).count());
+        for _ in 0..keep_from {
+            inner.evict();
+        }
+        while inner.bytes > MAX_REPLAY_BYTES {
+            inner.evict();
+        }
+    }
+
+    pub fn usage(&self) -> ReplayUsage {
+        let inner = self.inner.lock().unwrap();
+        ReplayUsage {
+            window_secs: inner.window.as_secs(),
+            buffered_secs: inner.span(0),
+            buffered_bytes: inner.bytes,
+        }
+    }
+
+    /// Writes the buffer to `dest`, starting at the earliest keyframe or PAT and with a
+    /// PAT/PMT pair in front when that chunk lacks one. The file appears under its final
+    /// name only once complete. `Ok(None)` when nothing playable has been buffered yet.
+    pub fn save(&self, session_id: &str, dest: &Path) -> Result<Option<ReplaySaved>> {
+        let (chunks, duration_secs) = {
+            let inner = self.inner.lock().unwrap();
+            let Some(start) = inner.chunks.iter().position(|c| c.key) else { return Ok(None) };
+            let chunks: Vec<Bytes> = inner.chunks.iter().skip(start).map(|c| c.data.clone()).collect();
+            (chunks, inner.span(start))
+        };
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut partial = dest.as_os_str().to_owned();
+        partial.push(".part");
+        let partial = PathBuf::from(partial);
+        let mut file = fs::File::create(&partial)?;
+        let mut bytes = 0u64;
+        if !mpegts::has_pat(&chunks[0]) {
+            for table in liveview::program_tables(&chunks[0]) {
+                file.write_all(&table)?;
+                bytes += table.len() as u64;
+            }
+        }
+        for chunk in &chunks {
+            file.write_all(chunk)?;
+            bytes += chunk.len() as u64;
+        }
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&partial, dest)?;
+
+        Ok(Some(ReplaySaved {
+            session_id: session_id.to_string(),
+            path: dest.to_string_lossy().into_owned(),
+            bytes,
+            duration_secs,
+        }))
+    }
+}
+
+impl Inner {
+    fn evict(&mut self) {
+        if let Some(chunk) = self.chunks.pop_front() {
+            self.bytes -= chunk.data.len();
+        }
+    }
+
+    fn span(&self, from: usize) -> f64 {
+        match (self.chunks.get(from), self.chunks.back()) {
+            (Some(first), Some(last)) => last.at.duration_since(first.at).as_secs_f64(),
+            _ => 0.0,
+        }
+    }
+}
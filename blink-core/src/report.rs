@@ -0,0 +1,467 @@
+//! Summary reports over a date range: clips per camera per day and the busiest hours from
+//! the media index, battery movement from the battery history, and arm changes from the
+//! event log. Rendered as JSON or as a self-contained HTML page that can be mailed as is.
+//! Days and hours are UTC.
+
+use crate::battery::{BatteryChange, BatteryStore};
+use crate::events::EventLog;
+use crate::media_index::MediaIndex;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveTime, Timelike, Utc, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+pub const DEFAULT_RANGE_DAYS: i64 = 7;
+pub const MAX_RANGE_DAYS: i64 = 366;
+/// Hours listed under "busiest hours".
+const BUSIEST_HOURS: usize = 5;
+/// Event kinds that change a network's arm state.
+const ARM_EVENT_KINDS: &[&str] = &["arm", "rearm", "schedule_run"];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    Daily,
+    Weekly,
+}
+
+/// When the server generates a report on its own and sends it to the webhooks as a
+/// `summary_report` event. Each report covers the day or week before it runs.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReportSchedule {
+    pub period: ReportPeriod,
+    /// UTC, `HH:MM`.
+    pub time: String,
+    /// Day weekly reports run on.
+    #[serde(default = "default_day")]
+    pub day: Weekday,
+}
+
+fn default_day() -> Weekday {
+    Weekday::Mon
+}
+
+impl ReportSchedule {
+    fn parsed_time(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.time, "%H:%M")
+            .map_err(|_| anyhow!("Invalid report time '{}', expected HH:MM", self.time))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.parsed_time()?;
+        Ok(())
+    }
+
+    /// The most recent run at or before `now`.
+    pub fn last_run(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let time = self.parsed_time().ok()?;
+        (0..8).map(|back| (now - Duration::days(back)).date_naive())
+            .filter(|date| self.period == ReportPeriod::Daily || date.weekday() == self.day)
+            .map(|date| date.and_time(time).and_utc())
+            .find(|run| *run <= now)
+    }
+
+    /// The range a run at `run` reports on.
+    pub fn range(&self, run: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        let days = match self.period {
+            ReportPeriod::Daily => 1,
+            ReportPeriod::Weekly => 7,
+        };
+        (run - Duration::days(days), run)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CameraDayClips {
+    pub camera_id: Option<i64>,
+    pub camera_name: Option<String>,
+    /// `YYYY-MM-DD`
+    pub day: String,
+    pub clips: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HourClips {
+    /// 0 to 23
+    pub hour: u32,
+    pub clips: u64,
+}
+
+/// One arm state change, from an `arm`, `rearm` or `schedule_run` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct ArmChange {
+    pub ts: DateTime<Utc>,
+    pub network_id: Option<i64>,
+    /// `None` when the event doesn't say which way it went.
+    pub armed: Option<bool>,
+    /// The event kind it came from.
+    pub source: String,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SummaryReport {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub generated_at: DateTime<Utc>,
+    /// Clip counts need the media index; they are empty when it is off.
+    pub media_index_enabled: bool,
+    pub total_clips: u64,
+    pub clips_per_camera_day: Vec<CameraDayClips>,
+    /// The hours with the most clips, busiest first.
+    pub busiest_hours: Vec<HourClips>,
+    pub battery: Vec<BatteryChange>,
+    pub arm_history: Vec<ArmChange>,
+}
+
+/// Rejects ranges that are backwards or longer than `MAX_RANGE_DAYS`.
+pub fn check_range(from: DateTime<Utc>, to: DateTime<Utc>) -> Result<()> {
+    if from >= to {
+        return Err(anyhow!("from must be before to"));
+    }
+    if to - from > Duration::days(MAX_RANGE_DAYS) {
+        return Err(anyhow!("A report covers at most {} days", MAX_RANGE_DAYS));
+    }
+    Ok(())
+}
+
+fn arm_change(event: &crate::events::Event) -> ArmChange {
+    let data = &event.data;
+    let armed = match event.kind.as_str() {
+        "arm" => data["verification"]["requested"].as_bool().or(data["requested"].as_bool()),
+        "rearm" => Some(true),
+        "schedule_run" => data["action"].as_str().map(|action| action == "arm"),
+        _ => None,
+    };
+    ArmChange {
+        ts: event.ts,
+        network_id: data["network_id"].as_i64(),
+        armed,
+        source: event.kind.clone(),
+        error: data["error"].as_str().map(str::to_string),
+    }
+}
+
+/// Builds the report for `from` up to (not including) `to`.
+pub fn generate(
+    index: &MediaIndex,
+    events: &EventLog,
+    battery: &BatteryStore,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<SummaryReport> {
+    check_range(from, to)?;
+    let clips = index.clip_times(from, to)?;
+    let media_index_enabled = clips.is_some();
+    let clips = clips.unwrap_or_default();
+
+    let mut per_day: BTreeMap<(String, Option<i64>), CameraDayClips> = BTreeMap::new();
+    let mut per_hour = [0u64; 24];
+    for clip in &clips {
+        let day = clip.created_at.format("%Y-%m-%d").to_string();
+        per_day.entry((day.clone(), clip.camera_id))
+            .or_insert_with(|| CameraDayClips { camera_id: clip.camera_id, camera_name: clip.camera_name.clone(), day, clips: 0 })
+            .clips += 1;
+        per_hour[clip.created_at.hour() as usize] += 1;
+    }
+    let mut busiest_hours: Vec<HourClips> = per_hour.iter()
+        .enumerate()
+        .filter(|(_, clips)| **clips > 0)
+        .map(|(hour, clips)| HourClips { hour: hour as u32, clips: *clips })
+        .collect();
+    busiest_hours.sort_by(|a, b| b.clips.cmp(&a.clips).then(a.hour.cmp(&b.hour)));
+    busiest_hours.truncate(BUSIEST_HOURS);
+
+    Ok(SummaryReport {
+        from,
+        to,
+        generated_at: Utc::now(),
+        media_index_enabled,
+        total_clips: clips.len() as u64,
+        clips_per_camera_day: per_day.into_values().collect(),
+        busiest_hours,
+        battery: battery.changes(from, to)?,
+        arm_history: events.between(from, to, ARM_EVENT_KINDS)?.iter().map(arm_change).collect(),
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn camera_label(id: Option<i64>, name: Option<&str>) -> String {
+    match (name, id) {
+        (Some(name), _) => escape(name),
+        (None, Some(id)) => format!("Camera {}", id),
+        (None, None) => "Unknown camera".to_string(),
+    }
+}
+
+/// The report as one HTML page with inline styles, for mail clients that strip the rest.
+pub fn render_html(report: &SummaryReport) -> String {
+    let mut html = String::new();
+    let range = format!("{} to {}", report.from.format("%Y-%m-%d %H:%M"), report.to.format("%Y-%m-%d %H:%M UTC"));
+    let _ = write!(
+        html,
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Blink Monitor summary</title></head>\
+         <body style=\"font-family:sans-serif;color:#222;max-width:720px;margin:auto\">\
+         <h1 style=\"font-size:20px\">Blink Monitor summary</h1><p>{}</p>",
+        range
+    );
+    let table = "style=\"border-collapse:collapse;width:100%\"";
+    let cell = "style=\"border-bottom:1px solid #ddd;padding:4px 8px;text-align:left\"";
+
+    let _ = write!(html, "<h2 style=\"font-size:16px\">Clips ({})</h2>", report.total_clips);
+    if !report.media_index_enabled {
+        html.push_str("<p>The media index is off, so clips aren't counted.</p>");
+    } else if report.clips_per_camera_day.is_empty() {
+        html.push_str("<p>No clips.</p>");
+    } else {
+        let _ = write!(html, "<table {}><tr><th {c}>Day</th><th {c}>Camera</th><th {c}>Clips</th></tr>", table, c = cell);
+        for row in &report.clips_per_camera_day {
+            let _ = write!(
+                html,
+                "<tr><td {c}>{}</td><td {c}>{}</td><td {c}>{}</td></tr>",
+                row.day,
+                camera_label(row.camera_id, row.camera_name.as_deref()),
+                row.clips,
+                c = cell
+            );
+        }
+        html.push_str("</table>");
+    }
+
+    if !report.busiest_hours.is_empty() {
+        html.push_str("<h2 style=\"font-size:16px\">Busiest hours (UTC)</h2><ul>");
+        for hour in &report.busiest_hours {
+            let _ = write!(html, "<li>{:02}:00 to {:02}:00: {} clips</li>", hour.hour, (hour.hour + 1) % 24, hour.clips);
+        }
+        html.push_str("</ul>");
+    }
+
+    html.push_str("<h2 style=\"font-size:16px\">Battery</h2>");
+    if report.battery.is_empty() {
+        html.push_str("<p>No battery samples.</p>");
+    } else {
+        let _ = write!(html, "<table {}><tr><th {c}>Camera</th><th {c}>From</th><th {c}>To</th><th {c}></th></tr>", table, c = cell);
+        for change in &report.battery {
+            let _ = write!(
+                html,
+                "<tr><td {c}>Camera {}</td><td {c}>{:.0}%</td><td {c}>{:.0}%</td><td {c}>{}</td></tr>",
+                change.camera_id,
+                change.first_level,
+                change.last_level,
+                if change.low { "Low" } else { "" },
+                c = cell
+            );
+        }
+        html.push_str("</table>");
+    }
+
+    html.push_str("<h2 style=\"font-size:16px\">Arm and disarm</h2>");
+    if report.arm_history.is_empty() {
+        html.push_str("<p>No changes.</p>");
+    } else {
+        html.push_str("<ul>");
+        for change in &report.arm_history {
+            let network = change.network_id.map(|id| format!("Network {}", id)).unwrap_or_else(|| "A network".to_string());
+            let what = match change.armed {
+                Some(true) => "armed",
+                Some(false) => "disarmed",
+                None => "changed",
+            };
+            let _ = write!(html, "<li>{} {} {} ({})", change.ts.format("%Y-%m-%d %H:%M"), network, what, escape(&change.source));
+            if let Some(error) = &change.error {
+                let _ = write!(html, ": failed, {}", escape(error));
+            }
+            html.push_str("</li>");
+        }
+        html.push_str("</ul>");
+    }
+
+    let _ = write!(html, "<p style=\"color:#888;font-size:12px\">Generated {}</p></body></html>", report.generated_at.to_rfc3339());
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blink::HomescreenResponse;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    struct Seeded {
+        _dir: tempfile::TempDir,
+        index: MediaIndex,
+        events: EventLog,
+        battery: BatteryStore,
+        /// Midnight UTC three days ago; the clips are placed relative to it.
+        day: DateTime<Utc>,
+    }
+
+    fn homescreen(level: i64, battery: &str) -> HomescreenResponse {
+        serde_json::from_value(json!({
+            "account": {},
+            "networks": [{ "id": 1, "name": "Home", "armed": true }],
+            "cameras": [
+                { "id": 5, "name": "Porch", "status": "done", "type": "catalina", "network_id": 1, "thumbnail": "/t/5", "battery": battery, "signals": { "battery": level } },
+                { "id": 6, "name": "Garage", "status": "done", "type": "owl", "network_id": 1, "thumbnail": "/t/6" },
+            ],
+        })).unwrap()
+    }
+
+    /// An index with eight clips over two days (one of them deleted), three arm changes
+    /// and a falling battery.
+    fn seeded() -> Seeded {
+        let dir = tempfile::tempdir().unwrap();
+        let index = MediaIndex::new(dir.path().join("media.sqlite"), true);
+        let events = EventLog::new(dir.path().join("events.sqlite"));
+        let battery = BatteryStore::new(dir.path().join("battery.sqlite"));
+        let day = (Utc::now() - Duration::days(3)).date_naive().and_hms_opt(0, 0, 0).unwrap().and_utc();
+
+        let clip = |id: i64, camera: i64, name: &str, offset_mins: i64, deleted: bool| json!({
+            "id": id,
+            "device_id": camera,
+            "device_name": name,
+            "network_id": 1,
+            "created_at": (day + Duration::minutes(offset_mins)).to_rfc3339(),
+            "deleted": deleted,
+        });
+        let media = vec![
+            clip(1, 5, "Porch", 8 * 60 + 5, false),
+            clip(2, 5, "Porch", 8 * 60 + 40, false),
+            clip(3, 5, "Porch", 18 * 60, false),
+            clip(4, 6, "Garage <side>", 8 * 60 + 59, false),
+            clip(5, 6, "Garage <side>", 18 * 60 + 30, true),
+            clip(6, 5, "Porch", 24 * 60 + 8 * 60 + 15, false),
+            clip(7, 6, "Garage <side>", 24 * 60 + 23 * 60 + 59, false),
+            // Before the range.
+            clip(8, 5, "Porch", -5 * 24 * 60, false),
+        ];
+        index.upsert_raw_page(&json!({ "media": media }).to_string());
+
+        events.record("arm", json!({ "network_id": 1, "verification": { "requested": false } }));
+        events.record("schedule_run", json!({ "network_id": 1, "action": "arm", "error": "Camera <offline> & busy" }));
+        events.record("rearm", json!({ "network_id": 1 }));
+        events.record("firmware_changed", json!({ "camera_id": 5 }));
+
+        battery.record(&homescreen(3, "ok"), &Default::default()).unwrap();
+        battery.record(&homescreen(2, "low"), &Default::default()).unwrap();
+        Seeded { _dir: dir, index, events, battery, day }
+    }
+
+    fn report(seeded: &Seeded) -> SummaryReport {
+        let from = seeded.day - Duration::days(1);
+        generate(&seeded.index, &seeded.events, &seeded.battery, from, Utc::now() + Duration::minutes(1)).unwrap()
+    }
+
+    #[test]
+    fn clips_are_counted_per_camera_and_day() {
+        let seeded = seeded();
+        let report = report(&seeded);
+        assert!(report.media_index_enabled);
+        assert_eq!(report.total_clips, 6);
+        let first = seeded.day.format("%Y-%m-%d").to_string();
+        let second = (seeded.day + Duration::days(1)).format("%Y-%m-%d").to_string();
+        let rows: Vec<_> = report.clips_per_camera_day.iter()
+            .map(|r| (r.day.clone(), r.camera_id, r.camera_name.clone().unwrap_or_default(), r.clips))
+            .collect();
+        assert_eq!(rows, vec![
+            (first.clone(), Some(5), "Porch".to_string(), 3),
+            (first, Some(6), "Garage <side>".to_string(), 1),
+            (second.clone(), Some(5), "Porch".to_string(), 1),
+            (second, Some(6), "Garage <side>".to_string(), 1),
+        ]);
+    }
+
+    #[test]
+    fn busiest_hours_come_first_and_ties_go_by_hour() {
+        let report = report(&seeded());
+        let hours: Vec<_> = report.busiest_hours.iter().map(|h| (h.hour, h.clips)).collect();
+        assert_eq!(hours, vec![(8, 4), (18, 1), (23, 1)]);
+    }
+
+    #[test]
+    fn arm_history_and_battery_come_from_their_stores() {
+        let report = report(&seeded());
+        let arms: Vec<_> = report.arm_history.iter().map(|a| (a.source.as_str(), a.network_id, a.armed)).collect();
+        assert_eq!(arms, vec![("arm", Some(1), Some(false)), ("schedule_run", Some(1), Some(true)), ("rearm", Some(1), Some(true))]);
+        assert_eq!(report.arm_history[1].error.as_deref(), Some("Camera <offline> & busy"));
+
+        assert_eq!(report.battery.len(), 1);
+        let change = &report.battery[0];
+        assert_eq!((change.camera_id, change.first_level, change.low), (5, 100.0, true));
+        assert!((change.last_level - 200.0 / 3.0).abs() < 1e-9, "{}", change.last_level);
+    }
+
+    #[test]
+    fn a_range_without_activity_is_empty() {
+        let seeded = seeded();
+        let from = seeded.day - Duration::days(30);
+        let report = generate(&seeded.index, &seeded.events, &seeded.battery, from, from + Duration::days(7)).unwrap();
+        assert_eq!(report.total_clips, 0);
+        assert!(report.clips_per_camera_day.is_empty() && report.busiest_hours.is_empty());
+        assert!(report.battery.is_empty() && report.arm_history.is_empty());
+        let html = render_html(&report);
+        assert!(html.contains("<p>No clips.</p>") && html.contains("<p>No battery samples.</p>") && html.contains("<p>No changes.</p>"));
+        assert!(!html.contains("Busiest hours"));
+    }
+
+    #[test]
+    fn a_disabled_index_is_reported_rather_than_counted_as_zero() {
+        let seeded = seeded();
+        seeded.index.set_enabled(false);
+        let report = report(&seeded);
+        assert!(!report.media_index_enabled);
+        assert!(render_html(&report).contains("The media index is off, so clips aren't counted."));
+    }
+
+    #[test]
+    fn the_html_page_shows_every_section_escaped() {
+        let html = render_html(&report(&seeded()));
+        assert!(html.starts_with("<!DOCTYPE html>") && html.ends_with("</body></html>"));
+        assert!(html.contains("<h2 style=\"font-size:16px\">Clips (6)</h2>"));
+        assert!(html.contains(">Garage &lt;side&gt;</td>") && !html.contains("Garage <side>"));
+        assert!(html.contains("<li>08:00 to 09:00: 4 clips</li>") && html.contains("<li>23:00 to 00:00: 1 clips</li>"));
+        assert!(html.contains(">Camera 5</td><td style=\"border-bottom:1px solid #ddd;padding:4px 8px;text-align:left\">100%</td>"));
+        assert!(html.contains(">Low</td>"));
+        assert!(html.contains("Network 1 disarmed (arm)"));
+        assert!(html.contains("Network 1 armed (schedule_run): failed, Camera &lt;offline&gt; &amp; busy"));
+        // Self-contained: no stylesheets, scripts or remote images.
+        assert!(!html.contains("<link") && !html.contains("<script") && !html.contains("<img"));
+    }
+
+    #[test]
+    fn ranges_are_checked() {
+        let from = Utc.with_ymd_and_hms(2026, 10, 1, 0, 0, 0).unwrap();
+        assert_eq!(check_range(from, from).unwrap_err().to_string(), "from must be before to");
+        assert!(check_range(from + Duration::days(1), from).is_err());
+        assert!(check_range(from, from + Duration::days(MAX_RANGE_DAYS)).is_ok());
+        assert_eq!(
+            check_range(from, from + Duration::days(MAX_RANGE_DAYS) + Duration::seconds(1)).unwrap_err().to_string(),
+            format!("A report covers at most {} days", MAX_RANGE_DAYS)
+        );
+    }
+
+    #[test]
+    fn schedules_cover_the_period_before_each_run() {
+        // A Thursday.
+        let now = Utc.with_ymd_and_hms(2026, 10, 15, 9, 30, 0).unwrap();
+        let daily = ReportSchedule { period: ReportPeriod::Daily, time: "08:00".to_string(), day: Weekday::Mon };
+        let run = daily.last_run(now).unwrap();
+        assert_eq!(run, Utc.with_ymd_and_hms(2026, 10, 15, 8, 0, 0).unwrap());
+        assert_eq!(daily.range(run), (run - Duration::days(1), run));
+        let later = ReportSchedule { time: "10:00".to_string(), ..daily.clone() };
+        assert_eq!(later.last_run(now), Some(Utc.with_ymd_and_hms(2026, 10, 14, 10, 0, 0).unwrap()));
+
+        let weekly = ReportSchedule { period: ReportPeriod::Weekly, time: "07:00".to_string(), day: Weekday::Mon };
+        let run = weekly.last_run(now).unwrap();
+        assert_eq!(run, Utc.with_ymd_and_hms(2026, 10, 12, 7, 0, 0).unwrap());
+        assert_eq!(weekly.range(run).0, Utc.with_ymd_and_hms(2026, 10, 5, 7, 0, 0).unwrap());
+
+        let broken = ReportSchedule { time: "25:00".to_string(), ..daily };
+        assert_eq!(broken.validate().unwrap_err().to_string(), "Invalid report time '25:00', expected HH:MM");
+        assert!(broken.last_run(now).is_none());
+    }
+}
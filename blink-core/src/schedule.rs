@@ -0,0 +1,401 @@
+//! Local arm/disarm schedules. Entries live in settings; the desktop app and the server
+//! each drive a `Scheduler` from a background task.
+
+use crate::audit;
+use crate::blink::BlinkClient;
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+
+/// A trigger missed while the machine slept still fires if we wake up within this window.
+pub const GRACE_SECS: i64 = 15 * 60;
+pub const TICK_SECS: u64 = 30;
+/// Network time zones rarely change; re-read them from the homescreen this often.
+const ZONE_REFRESH_SECS: i64 = 60 * 60;
+const UNKNOWN_ZONE_RETRY_SECS: i64 = 5 * 60;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduleAction {
+    Arm,
+    Disarm,
+}
+
+impl ScheduleAction {
+    pub fn armed(self) -> bool {
+        self == ScheduleAction::Arm
+    }
+}
+
+/// "Arm network 123 at 22:30 on weekdays". An empty `days` list means every day.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScheduleEntry {
+    #[serde(default)]
+    pub id: String,
+    pub network_id: i64,
+    pub action: ScheduleAction,
+    #[serde(default)]
+    pub days: Vec<Weekday>,
+    /// Local time in the network's time zone, `HH:MM`.
+    pub time: String,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl ScheduleEntry {
+    fn parsed_time(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.time, "%H:%M")
+            .map_err(|_| anyhow!("Invalid schedule time '{}', expected HH:MM", self.time))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        self.parsed_time()?;
+        Ok(())
+    }
+
+    fn runs_on(&self, date: NaiveDate) -> bool {
+        self.days.is_empty() || self.days.contains(&date.weekday())
+    }
+
+    /// The instant this entry fires on a given local date, if it runs that day.
+    fn trigger_on(&self, zone: &Zone, date: NaiveDate) -> Option<DateTime<Utc>> {
+        if !self.runs_on(date) {
+            return None;
+        }
+        zone.to_utc(date.and_time(self.parsed_time().ok()?))
+    }
+
+    /// Most recent trigger at or before `now`.
+    fn last_trigger(&self, zone: &Zone, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = zone.local(now).date();
+        (0..=7)
+            .filter_map(|d| self.trigger_on(zone, today - Duration::days(d)))
+            .find(|at| *at <= now)
+    }
+
+    /// First trigger strictly after `now`.
+    fn next_trigger(&self, zone: &Zone, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let today = zone.local(now).date();
+        (0..=7)
+            .filter_map(|d| self.trigger_on(zone, today + Duration::days(d)))
+            .find(|at| *at > now)
+    }
+}
+
+/// A network's time zone, falling back to the machine's when Blink didn't report one.
+#[derive(Debug, Clone)]
+enum Zone {
+    Named(Tz),
+    Local,
+}
+
+impl Zone {
+    fn from_name(name: Option<&str>) -> Self {
+        name.and_then(|n| n.parse::<Tz>().ok()).map(Zone::Named).unwrap_or(Zone::Local)
+    }
+
+    fn local(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Named(tz) => at.with_timezone(tz).naive_local(),
+            Zone::Local => at.with_timezone(&chrono::Local).naive_local(),
+        }
+    }
+
+    /// Wall-clock times skipped by a DST jump have no instant; ambiguous ones take the first.
+    fn to_utc(&self, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            Zone::Named(tz) => tz.from_local_datetime(&local).earliest().map(|t| t.with_timezone(&Utc)),
+            Zone::Local => chrono::Local.from_local_datetime(&local).earliest().map(|t| t.with_timezone(&Utc)),
+        }
+    }
+}
+
+/// The next change a network's schedule will make.
+#[derive(Debug, Clone, Serialize)]
+pub struct NextScheduledChange {
+    pub network_id: i64,
+    pub entry_id: String,
+    pub action: ScheduleAction,
+    pub at: DateTime<Utc>,
+}
+
+/// One executed (or failed) scheduled action.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleRun {
+    pub entry_id: String,
+    pub network_id: i64,
+    pub action: ScheduleAction,
+    pub scheduled_for: DateTime<Utc>,
+    pub executed_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+/// Returns `entries` with `entry` added (empty id) or replacing the entry with the same id.
+pub fn upsert(entries: &[ScheduleEntry], mut entry: ScheduleEntry) -> Result<(Vec<ScheduleEntry>, ScheduleEntry)> {
+    entry.validate()?;
+    let mut entries = entries.to_vec();
+    if entry.id.is_empty() {
+        entry.id = uuid::Uuid::new_v4().to_string();
+        entries.push(entry.clone());
+    } else {
+        let existing = entries.iter_mut()
+            .find(|e| e.id == entry.id)
+            .ok_or_else(|| anyhow!("Schedule entry not found: {}", entry.id))?;
+        *existing = entry.clone();
+    }
+    Ok((entries, entry))
+}
+
+pub fn remove(entries: &[ScheduleEntry], id: &str) -> Result<Vec<ScheduleEntry>> {
+    if !entries.iter().any(|e| e.id == id) {
+        return Err(anyhow!("Schedule entry not found: {}", id));
+    }
+    Ok(entries.iter().filter(|e| e.id != id).cloned().collect())
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SchedulerState {
+    /// Entry id -> unix time of the last trigger acted on, so restarts don't repeat it.
+    #[serde(default)]
+    last_fired: HashMap<String, i64>,
+}
+
+/// Time zone names per network as last read from the homescreen.
+#[derive(Default)]
+struct ZoneCache {
+    by_network: HashMap<i64, Option<String>>,
+    fetched_at: Option<DateTime<Utc>>,
+}
+
+pub struct Scheduler {
+    state_path: PathBuf,
+    state: std::sync::Mutex<SchedulerState>,
+    zones: std::sync::Mutex<ZoneCache>,
+}
+
+impl Scheduler {
+    pub fn load(state_path: PathBuf) -> Self {
+        let state = fs::read_to_string(&state_path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            state_path,
+            state: std::sync::Mutex::new(state),
+            zones: std::sync::Mutex::new(ZoneCache::default()),
+        }
+    }
+
+    fn save_state(&self, state: &SchedulerState) {
+        if let Ok(json) = serde_json::to_string(state) {
+            if let Err(e) = fs::write(&self.state_path, json) {
+                eprintln!("Failed to save scheduler state: {}", e);
+            }
+        }
+    }
+
+    fn zone(&self, network_id: i64) -> Zone {
+        let zones = self.zones.lock().unwrap();
+        Zone::from_name(zones.by_network.get(&network_id).and_then(|z| z.as_deref()))
+    }
+
+    /// Re-reads network time zones from the homescreen when they are stale or unknown.
+    async fn refresh_zones(&self, client: &Mutex<BlinkClient>, entries: &[ScheduleEntry], now: DateTime<Utc>) {
+        let stale = {
+            let zones = self.zones.lock().unwrap();
+            let age = zones.fetched_at.map(|at| (now - at).num_seconds());
+            let unknown = entries.iter().any(|e| !zones.by_network.contains_key(&e.network_id));
+            // A newly scheduled network prompts an early re-read, but not on every tick.
+            age.is_none_or(|age| age > ZONE_REFRESH_SECS || (unknown && age > UNKNOWN_ZONE_RETRY_SECS))
+        };
+        if !stale {
+            return;
+        }
+        let Ok(json) = shared::snapshot(client).await.get_homescreen_json().await else { return };
+        let mut zones = self.zones.lock().unwrap();
+        zones.by_network.clear();
+        for network in json.get("networks").and_then(|n| n.as_array()).into_iter().flatten() {
+            if let Some(id) = network.get("id").and_then(|v| v.as_i64()) {
+                let tz = network.get("time_zone").and_then(|v| v.as_str()).map(str::to_string);
+                zones.by_network.insert(id, tz);
+            }
+        }
+        zones.fetched_at = Some(now);
+    }
+
+    /// Next change per network across the enabled entries.
+    pub fn upcoming(&self, entries: &[ScheduleEntry], now: DateTime<Utc>) -> Vec<NextScheduledChange> {
+        let mut next: HashMap<i64, NextScheduledChange> = HashMap::new();
+        for entry in entries.iter().filter(|e| e.enabled) {
+            let Some(at) = entry.next_trigger(&self.zone(entry.network_id), now) else { continue };
+            if next.get(&entry.network_id).is_some_and(|n| n.at <= at) {
+                continue;
+            }
+            next.insert(entry.network_id, NextScheduledChange {
+                network_id: entry.network_id,
+                entry_id: entry.id.clone(),
+                action: entry.action,
+                at,
+            });
+        }
+        let mut next: Vec<_> = next.into_values().collect();
+        next.sort_by_key(|n| n.at);
+        next
+    }
+
+    /// Fires every entry whose latest trigger is due, not yet handled and within the grace
+    /// window. When several entries for one network are due (e.g. after sleeping through
+    /// both an arm and a disarm) only the latest is applied.
+    pub async fn tick(&self, client: &Mutex<BlinkClient>, entries: &[ScheduleEntry]) -> Vec<ScheduleRun> {
+        let now = Utc::now();
+        if entries.iter().any(|e| e.enabled) {
+            self.refresh_zones(client, entries, now).await;
+        }
+
+        let mut due: HashMap<i64, Vec<(&ScheduleEntry, DateTime<Utc>)>> = HashMap::new();
+        {
+            let state = self.state.lock().unwrap();
+            for entry in entries.iter().filter(|e| e.enabled) {
+                let Some(at) = entry.last_trigger(&self.zone(entry.network_id), now) else { continue };
+                let handled = state.last_fired.get(&entry.id).is_some_and(|t| *t >= at.timestamp());
+                if !handled && (now - at).num_seconds() <= GRACE_SECS {
+                    due.entry(entry.network_id).or_default().push((entry, at));
+                }
+            }
+        }
+
+        let mut runs = Vec::new();
+        for (network_id, mut candidates) in due {
+            candidates.sort_by_key(|(_, at)| *at);
+            let Some(&(entry, at)) = candidates.last() else { continue };
+            let result = {
+                let client = shared::snapshot(client).await;
+                audit::with_source("scheduler".to_string(), client.set_arm(network_id, entry.action.armed())).await
+            };
+            let mut state = self.state.lock().unwrap();
+            for (skipped, skipped_at) in &candidates {
+                state.last_fired.insert(skipped.id.clone(), skipped_at.timestamp());
+            }
+            self.save_state(&state);
+            runs.push(ScheduleRun {
+                entry_id: entry.id.clone(),
+                network_id,
+                action: entry.action,
+                scheduled_for: at,
+                executed_at: Utc::now(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let before = state.last_fired.len();
+        state.last_fired.retain(|id, _| entries.iter().any(|e| &e.id == id));
+        if state.last_fired.len() != before {
+            self.save_state(&state);
+        }
+        runs
+    }
+}
+
+impl std::fmt::Display for ScheduleRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let action = if self.action.armed() { "arm" } else { "disarm" };
+        match &self.error {
+            None => write!(f, "Scheduled {} of network {} ({}) done", action, self.network_id, self.entry_id),
+            Some(e) => write!(f, "Scheduled {} of network {} ({}) failed: {}", action, self.network_id, self.entry_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, network_id: i64, action: ScheduleAction, days: &[Weekday], time: &str) -> ScheduleEntry {
+        ScheduleEntry { id: id.to_string(), network_id, action, days: days.to_vec(), time: time.to_string(), enabled: true }
+    }
+
+    fn utc(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn zone(name: &str) -> Zone {
+        Zone::Named(name.parse().unwrap())
+    }
+
+    #[test]
+    fn times_must_be_hh_mm() {
+        assert!(entry("", 1, ScheduleAction::Arm, &[], "22:30").validate().is_ok());
+        for bad in ["24:00", "7pm", "22:30:00", ""] {
+            assert!(entry("", 1, ScheduleAction::Arm, &[], bad).validate().is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn upsert_adds_replaces_and_remove_drops() {
+        let (entries, added) = upsert(&[], entry("", 1, ScheduleAction::Arm, &[], "22:00")).unwrap();
+        assert!(!added.id.is_empty());
+        assert_eq!(entries, vec![added.clone()]);
+
+        let changed = ScheduleEntry { time: "23:00".to_string(), ..added.clone() };
+        let (entries, _) = upsert(&entries, changed.clone()).unwrap();
+        assert_eq!(entries, vec![changed]);
+
+        assert!(upsert(&entries, entry("missing", 1, ScheduleAction::Arm, &[], "01:00")).is_err());
+        assert!(upsert(&entries, entry(&added.id, 1, ScheduleAction::Arm, &[], "nope")).is_err());
+        assert!(remove(&entries, &added.id).unwrap().is_empty());
+        assert!(remove(&entries, "missing").is_err());
+    }
+
+    #[test]
+    fn triggers_follow_the_network_zone_and_weekdays() {
+        let weekdays = [Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri];
+        let arm = entry("a", 1, ScheduleAction::Arm, &weekdays, "22:30");
+        let ny = zone("America/New_York");
+        // Friday 2026-01-09 20:00 in New York; the next run is that evening, the one after
+        // skips the weekend.
+        let now = utc("2026-01-10T01:00:00Z");
+        assert_eq!(arm.next_trigger(&ny, now), Some(utc("2026-01-10T03:30:00Z")));
+        assert_eq!(arm.last_trigger(&ny, now), Some(utc("2026-01-09T03:30:00Z")));
+        assert_eq!(arm.next_trigger(&ny, utc("2026-01-10T03:30:00Z")), Some(utc("2026-01-13T03:30:00Z")));
+    }
+
+    #[test]
+    fn a_time_skipped_by_dst_does_not_fire_that_day() {
+        let arm = entry("a", 1, ScheduleAction::Arm, &[], "02:30");
+        let ny = zone("America/New_York");
+        // 02:30 doesn't exist on 2026-03-08 in New York.
+        assert_eq!(arm.next_trigger(&ny, utc("2026-03-08T05:00:00Z")), Some(utc("2026-03-09T06:30:00Z")));
+    }
+
+    #[test]
+    fn upcoming_picks_the_earliest_enabled_entry_per_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let scheduler = Scheduler::load(dir.path().join("schedule_state.json"));
+        let mut disabled = entry("d", 1, ScheduleAction::Disarm, &[], "06:00");
+        disabled.enabled = false;
+        let entries = [
+            entry("late", 1, ScheduleAction::Arm, &[], "23:00"),
+            entry("early", 1, ScheduleAction::Disarm, &[], "07:00"),
+            disabled,
+            entry("other", 2, ScheduleAction::Arm, &[], "12:00"),
+        ];
+        let now = chrono::Local.from_local_datetime(&NaiveDate::from_ymd_opt(2026, 6, 1).unwrap().and_hms_opt(5, 0, 0).unwrap())
+            .earliest()
+            .unwrap()
+            .with_timezone(&Utc);
+        let upcoming = scheduler.upcoming(&entries, now);
+        let ids: Vec<_> = upcoming.iter().map(|n| (n.network_id, n.entry_id.as_str())).collect();
+        assert_eq!(ids, vec![(1, "early"), (2, "other")]);
+    }
+}
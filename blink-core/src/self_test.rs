@@ -0,0 +1,450 @@
+//! Staged end-to-end check for "why is my camera grid empty": each stage depends on the
+//! one before, so the first failure says where the pipeline breaks (local storage, auth,
+//! Blink's API, or parsing) and everything after it is skipped rather than failing too.
+
+use crate::blink::{AuthState, BlinkClient};
+use crate::clock_skew::{self, ClockSkewReport};
+use crate::log_control;
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+
+const PROBE_FILE: &str = ".self-test";
+const MAX_ERROR_CHARS: usize = 300;
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    /// The data directory can be written and read back.
+    Storage,
+    /// A session is loaded.
+    Token,
+    /// The token is refreshed if it is due.
+    Refresh,
+    /// Blink's tier endpoint answers with this token.
+    Tier,
+    Homescreen,
+    /// One camera thumbnail downloads.
+    Thumbnail,
+    MediaPage,
+}
+
+const STAGES: [Stage; 7] = [
+    Stage::Storage,
+    Stage::Token,
+    Stage::Refresh,
+    Stage::Tier,
+    Stage::Homescreen,
+    Stage::Thumbnail,
+    Stage::MediaPage,
+];
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StageStatus {
+    Passed,
+    Failed,
+    /// Not run because an earlier stage failed, or nothing to check (no cameras).
+    Skipped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StageResult {
+    pub stage: Stage,
+    pub status: StageStatus,
+    pub duration_ms: u64,
+    /// What was found, e.g. the tier or how many cameras the homescreen listed.
+    pub detail: Option<String>,
+    /// Set on the failing stage, with tokens and signed query strings removed.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub started_at: DateTime<Utc>,
+    pub failed_stage: Option<Stage>,
+    pub stages: Vec<StageResult>,
+    /// Offset of the local clock from Blink's, as seen in its responses during the run.
+    pub clock_skew: ClockSkewReport,
+}
+
+/// Drops credentials from an error message so the report can be pasted into an issue.
+pub fn sanitize_error(message: &str) -> String {
+    redact(message).chars().take(MAX_ERROR_CHARS).collect()
+}
+
+/// `message` without bearer tokens or query strings, which carry signatures and ids.
+pub fn redact(message: &str) -> String {
+    static BEARER: OnceLock<Regex> = OnceLock::new();
+    static QUERY: OnceLock<Regex> = OnceLock::new();
+    let bearer = BEARER.get_or_init(|| Regex::new(r"(?i)bearer\s+\S+").unwrap());
+    let query = QUERY.get_or_init(|| Regex::new(r"(https?://[^\s?#]+)\?[^\s]*").unwrap());
+    let message = bearer.replace_all(message, "Bearer [redacted]");
+    query.replace_all(&message, "$1?[redacted]").into_owned()
+}
+
+/// Writes, reads back and removes a small file in `dir`.
+fn check_storage(dir: &Path) -> Result<String> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(PROBE_FILE);
+    let written = Utc::now().to_rfc3339();
+    std::fs::write(&path, &written)?;
+    let read = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    if read? != written {
+        return Err(anyhow!("{} read back different contents", path.display()));
+    }
+    Ok(dir.display().to_string())
+}
+
+/// Collects stage results, turning everything after the first failure into skips.
+struct Run {
+    stages: Vec<StageResult>,
+    failed: Option<Stage>,
+}
+
+impl Run {
+    fn should_run(&self) -> bool {
+        self.failed.is_none()
+    }
+
+    fn record<T>(&mut self, stage: Stage, started: Instant, result: Result<(T, Option<String>)>) -> Option<T> {
+        let duration_ms = started.elapsed().as_millis() as u64;
+        match result {
+            Ok((value, detail)) => {
+                self.stages.push(StageResult { stage, status: StageStatus::Passed, duration_ms, detail, error: None });
+                Some(value)
+            }
+            Err(e) => {
+                self.failed = Some(stage);
+                let error = Some(sanitize_error(&format!("{:#}", e)));
+                self.stages.push(StageResult { stage, status: StageStatus::Failed, duration_ms, detail: None, error });
+                None
+            }
+        }
+    }
+
+    fn skip(&mut self, stage: Stage, detail: Option<String>) {
+        self.stages.push(StageResult { stage, status: StageStatus::Skipped, duration_ms: 0, detail, error: None });
+    }
+
+    fn finish(mut self, started_at: DateTime<Utc>) -> SelfTestReport {
+        for stage in STAGES.into_iter().skip(self.stages.len()) {
+            self.skip(stage, None);
+        }
+        SelfTestReport {
+            passed: self.failed.is_none(),
+            started_at,
+            failed_stage: self.failed,
+            stages: self.stages,
+            clock_skew: clock_skew::report(),
+        }
+    }
+}
+
+/// Runs every stage in order against the shared client; `data_dir` is where this front
+/// end keeps its files.
+pub async fn run(blink_client: &Mutex<BlinkClient>, data_dir: &Path) -> SelfTestReport {
+    let started_at = Utc::now();
+    let mut run = Run { stages: Vec::new(), failed: None };
+
+    let started = Instant::now();
+    run.record(Stage::Storage, started, check_storage(data_dir).map(|dir| ((), Some(dir))));
+    if !run.should_run() {
+        return run.finish(started_at);
+    }
+
+    let client = {
+        let mut client = blink_client.lock().await;
+        let started = Instant::now();
+        let token = match client.auth_state() {
+            AuthState::LoggedIn => Ok(((), client.account_id.map(|id| format!("account {}", id)))),
+            AuthState::ReauthRequired => Err(anyhow!("Blink revoked the saved session; sign in again")),
+            AuthState::LoggedOut => Err(anyhow!("Not logged in")),
+        };
+        run.record(Stage::Token, started, token);
+        if !run.should_run() {
+            return run.finish(started_at);
+        }
+
+        let started = Instant::now();
+        let refresh = shared::refresh_and_persist(&mut client).await.map(|refreshed| {
+            let detail = if refreshed { "refreshed" } else { "not due" };
+            ((), Some(detail.to_string()))
+        });
+        run.record(Stage::Refresh, started, refresh);
+        client.clone()
+    };
+    if !run.should_run() {
+        return run.finish(started_at);
+    }
+
+    let started = Instant::now();
+    let tier = client.probe_tier().await.map(|tier| ((), Some(tier).filter(|t| !t.is_empty())));
+    run.record(Stage::Tier, started, tier);
+    if !run.should_run() {
+        return run.finish(started_at);
+    }
+
+    let started = Instant::now();
+    let homescreen = client.get_homescreen().await.map(|home| {
+        let detail = format!("{} cameras, {} networks", home.cameras.len(), home.networks.len());
+        (home, Some(detail))
+    });
+    let Some(homescreen) = run.record(Stage::Homescreen, started, homescreen) else {
+        return run.finish(started_at);
+    };
+
+    match homescreen.cameras.iter().find(|c| !c.thumbnail.is_empty()) {
+        Some(camera) => {
+            let started = Instant::now();
+            let thumbnail = client.get_thumbnail(&camera.thumbnail).await.map(|thumb| {
+                ((), Some(format!("{}: {} bytes of {}", camera.name, thumb.bytes.len(), thumb.content_type)))
+            });
+            run.record(Stage::Thumbnail, started, thumbnail);
+        }
+        None => run.skip(Stage::Thumbnail, Some("No camera has a thumbnail".to_string())),
+    }
+    if !run.should_run() {
+        return run.finish(started_at);
+    }
+
+    let started = Instant::now();
+    let media = client.get_media_page(1, 1).await.map(|items| ((), Some(format!("{} clips in the last day", items.len()))));
+    run.record(Stage::MediaPage, started, media);
+    run.finish(started_at)
+}
+
+/// Appends `report` as JSON to the end of a debug capture.
+fn append_report(path: &Path, report: &SelfTestReport) -> Result<()> {
+    let mut file = std::fs::OpenOptions::new().append(true).open(path)?;
+    let outcome = match report.failed_stage {
+        Some(stage) => format!("failed at {}", serde_json::to_value(stage)?.as_str().unwrap_or_default()),
+        None => "passed".to_string(),
+    };
+    writeln!(file, "# self-test {}", outcome)?;
+    serde_json::to_writer_pretty(&mut file, report)?;
+    writeln!(file)?;
+    Ok(())
+}
+
+/// A debug capture that doubles as a diagnostics bundle: the self-test runs as the capture
+/// starts, so the file holds each stage's logs, and its report is appended once the
+/// capture ends. Returns the file's path straight away; the rest happens in the background
+/// and the handle resolves once the report is written.
+pub fn capture_with_report(blink_client: Arc<Mutex<BlinkClient>>, dir: &Path, targets: &[String], duration_secs: u64) -> Result<(PathBuf, JoinHandle<()>)> {
+    let capture = log_control::start_capture(dir, targets, duration_secs)?;
+    let path = capture.path().to_path_buf();
+    let dir = dir.to_path_buf();
+    let done = tokio::spawn(async move {
+        let report = run(&blink_client, &dir).await;
+        let path = capture.finish().await;
+        if let Err(e) = append_report(&path, &report) {
+            eprintln!("Failed to add the self-test report to {}: {}", path.display(), e);
+        }
+    });
+    Ok((path, done))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blink::{BlinkAuthState, BlinkUrls};
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    const HOMESCREEN: &str = r#"{
+        "account": {},
+        "networks": [{ "id": 1, "name": "Home", "armed": true }],
+        "cameras": [{ "id": 5, "name": "Porch", "thumbnail": "/media/porch.jpg", "status": "done", "type": "catalina", "network_id": 1 }]
+    }"#;
+
+    fn client(server: &MockServer, expiry: i64) -> Mutex<BlinkClient> {
+        let state = serde_json::json!({
+            "token": "secret-token",
+            "refresh_token": "refresh",
+            "account_id": 42,
+            "base_url": server.uri(),
+            "device_id": "device",
+            "token_expiry": expiry,
+        });
+        let urls = BlinkUrls { oauth: server.uri(), rest: server.uri(), tier_template: server.uri() };
+        Mutex::new(BlinkClient::from_state_with_urls(BlinkAuthState::from_json(&state.to_string(), "test").unwrap(), urls))
+    }
+
+    fn logged_in(server: &MockServer) -> Mutex<BlinkClient> {
+        client(server, Utc::now().timestamp() + 3600)
+    }
+
+    /// Every Blink call the self-test makes, answering successfully unless its path is `failing`.
+    async fn mock_blink(failing: Option<&str>) -> MockServer {
+        let server = MockServer::start().await;
+        let ok: [(&str, &str, ResponseTemplate); 4] = [
+            ("POST", "/oauth/token", ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "a", "refresh_token": "r", "expires_in": 3600 }))),
+            ("GET", "/api/v1/users/tier_info", ResponseTemplate::new(200).set_body_json(serde_json::json!({ "tier": "u011" }))),
+            ("GET", "/api/v3/accounts/42/homescreen", ResponseTemplate::new(200).set_body_string(HOMESCREEN)),
+            ("GET", "/media/porch.jpg", ResponseTemplate::new(200).set_body_raw(vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg")),
+        ];
+        for (method, path, response) in ok {
+            let response = if failing == Some(path) {
+                ResponseTemplate::new(500).set_body_string("upstream broke for https://x.immedia-semi.com/a?sig=abc, Bearer secret-token")
+            } else {
+                response
+            };
+            Mock::given(matchers::method(method)).and(matchers::path(path)).respond_with(response).mount(&server).await;
+        }
+        let media = if failing == Some("media") {
+            ResponseTemplate::new(500)
+        } else {
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [{ "id": 1 }] }))
+        };
+        Mock::given(matchers::path("/api/v1/accounts/42/media/changed")).respond_with(media).mount(&server).await;
+        server
+    }
+
+    fn statuses(report: &SelfTestReport) -> Vec<(Stage, StageStatus)> {
+        report.stages.iter().map(|s| (s.stage, s.status)).collect()
+    }
+
+    /// `failed` failed, everything before it passed and everything after was skipped.
+    fn assert_failed_at(report: &SelfTestReport, failed: Stage) {
+        assert!(!report.passed);
+        assert_eq!(report.failed_stage, Some(failed));
+        let index = STAGES.iter().position(|&s| s == failed).unwrap();
+        let expected: Vec<_> = STAGES.iter().enumerate().map(|(i, &stage)| {
+            let status = match i.cmp(&index) {
+                std::cmp::Ordering::Less => StageStatus::Passed,
+                std::cmp::Ordering::Equal => StageStatus::Failed,
+                std::cmp::Ordering::Greater => StageStatus::Skipped,
+            };
+            (stage, status)
+        }).collect();
+        assert_eq!(statuses(report), expected);
+        assert!(report.stages[index].error.is_some());
+        assert!(report.stages.iter().filter(|s| s.status != StageStatus::Failed).all(|s| s.error.is_none()));
+    }
+
+    #[tokio::test]
+    async fn every_stage_passes_against_a_healthy_blink() {
+        let server = mock_blink(None).await;
+        let dir = tempfile::tempdir().unwrap();
+        let report = run(&logged_in(&server), dir.path()).await;
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(statuses(&report), STAGES.iter().map(|&s| (s, StageStatus::Passed)).collect::<Vec<_>>());
+        assert_eq!(report.stages[3].detail.as_deref(), Some("u011"));
+        assert_eq!(report.stages[4].detail.as_deref(), Some("1 cameras, 1 networks"));
+        assert!(!dir.path().join(PROBE_FILE).exists());
+    }
+
+    #[tokio::test]
+    async fn unwritable_storage_fails_first() {
+        let server = mock_blink(None).await;
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("not-a-dir");
+        std::fs::write(&file, b"").unwrap();
+        let report = run(&logged_in(&server), &file).await;
+        assert_failed_at(&report, Stage::Storage);
+        assert!(server.received_requests().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn no_session_fails_the_token_stage() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = run(&Mutex::new(BlinkClient::new()), dir.path()).await;
+        assert_failed_at(&report, Stage::Token);
+        assert_eq!(report.stages[1].error.as_deref(), Some("Not logged in"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_refresh_stops_before_blink_is_called() {
+        let server = mock_blink(Some("/oauth/token")).await;
+        let dir = tempfile::tempdir().unwrap();
+        let report = run(&client(&server, Utc::now().timestamp() - 10), dir.path()).await;
+        assert_failed_at(&report, Stage::Refresh);
+        let paths: Vec<_> = server.received_requests().await.unwrap().iter().map(|r| r.url.path().to_string()).collect();
+        assert_eq!(paths, vec!["/oauth/token"]);
+    }
+
+    #[tokio::test]
+    async fn each_blink_call_failing_is_reported_at_its_stage() {
+        for (path, stage) in [
+            ("/api/v1/users/tier_info", Stage::Tier),
+            ("/api/v3/accounts/42/homescreen", Stage::Homescreen),
+            ("/media/porch.jpg", Stage::Thumbnail),
+            ("media", Stage::MediaPage),
+        ] {
+            let server = mock_blink(Some(path)).await;
+            let dir = tempfile::tempdir().unwrap();
+            let report = run(&logged_in(&server), dir.path()).await;
+            assert_failed_at(&report, stage);
+        }
+    }
+
+    #[tokio::test]
+    async fn reported_errors_carry_no_tokens_or_signatures() {
+        let server = mock_blink(Some("/api/v1/users/tier_info")).await;
+        let dir = tempfile::tempdir().unwrap();
+        let report = run(&logged_in(&server), dir.path()).await;
+        let error = report.stages[3].error.as_deref().unwrap();
+        assert!(!error.contains("secret-token") && !error.contains("sig=abc"), "{}", error);
+        assert!(error.contains("Bearer [redacted]") && error.contains("https://x.immedia-semi.com/a?[redacted]"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn an_account_without_thumbnails_skips_that_stage_only() {
+        let server = MockServer::start().await;
+        Mock::given(matchers::path("/api/v1/users/tier_info"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "tier": "u011" })))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/api/v3/accounts/42/homescreen"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "account": {}, "networks": [] })))
+            .mount(&server)
+            .await;
+        Mock::given(matchers::path("/api/v1/accounts/42/media/changed"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [] })))
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let report = run(&logged_in(&server), dir.path()).await;
+        assert!(report.passed);
+        assert_eq!(report.stages[5].status, StageStatus::Skipped);
+        assert_eq!(report.stages[6].status, StageStatus::Passed);
+    }
+
+    #[tokio::test]
+    async fn the_debug_capture_ends_with_the_report() {
+        let server = mock_blink(Some("/api/v3/accounts/42/homescreen")).await;
+        let dir = tempfile::tempdir().unwrap();
+        let (path, done) = capture_with_report(Arc::new(logged_in(&server)), dir.path(), &["blink".to_string()], 1).unwrap();
+        assert!(!std::fs::read_to_string(&path).unwrap().contains("# self-test"));
+        done.await.unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        let (logs, report) = contents.split_once("# self-test failed at homescreen\n").unwrap();
+        // The stages' requests were logged into the same file, before the report.
+        assert!(logs.contains("/api/v3/accounts/42/homescreen"), "{}", logs);
+        let report: serde_json::Value = serde_json::from_str(report).unwrap();
+        assert_eq!(report["failed_stage"], "homescreen");
+        assert_eq!(report["stages"].as_array().unwrap().len(), STAGES.len());
+    }
+
+    #[tokio::test]
+    async fn bad_capture_arguments_run_nothing() {
+        let server = mock_blink(None).await;
+        let dir = tempfile::tempdir().unwrap();
+        assert!(capture_with_report(Arc::new(logged_in(&server)), dir.path(), &[], 1).is_err());
+        assert!(capture_with_report(Arc::new(logged_in(&server)), dir.path(), &["nope".to_string()], 1).is_err());
+        assert!(capture_with_report(Arc::new(logged_in(&server)), dir.path(), &["blink".to_string()], 0).is_err());
+        assert!(server.received_requests().await.unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::blink::{Camera, LiveviewQuality};
+use crate::clip_store::ClipStoreConfig;
+use crate::export;
+use crate::notify::NotifierTarget;
+use crate::recording::RecordingFormat;
+use crate::replay;
+use crate::report::ReportSchedule;
+use crate::schedule::ScheduleEntry;
+use crate::thumbnail_refresh::ThumbnailRefreshSettings;
+use crate::webhooks::WebhookTarget;
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+pub const SETTINGS_FILE: &str = "settings.json";
+pub const SETTINGS_VERSION: u32 = 1;
+
+pub const MAX_DOWNLOAD_CONCURRENCY: usize = 10;
+// Blink throttles accounts that poll the media feed aggressively.
+pub const MIN_MOTION_INTERVAL_SECS: u64 = 15;
+
+/// User-tunable knobs shared by the desktop app and the standalone server.
+/// Keys the server has no use for (tray, motion alerts) are simply ignored there.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub download_concurrency: usize,
+    /// Name for downloaded clips, without extension; see `export::FILENAME_PLACEHOLDERS`.
+    pub download_filename_template: String,
+    /// Write a `.json` file with the clip's metadata next to each download.
+    pub download_sidecar: bool,
+    pub motion_alerts_enabled: bool,
+    pub motion_interval_secs: u64,
+    /// How often a liveview request is retried while Blink reports the camera busy.
+    pub liveview_busy_retries: u32,
+    pub liveview_retry_delay_secs: u64,
+    /// A liveview is torn down when the IMMI server sends nothing for this long.
+    pub liveview_read_timeout_secs: u64,
+    /// Delete the clip Blink records for each liveview once it ends, unless the session
+    /// asked to keep it.
+    pub liveview_cleanup_enabled: bool,
+    /// Unmarked clips created this many seconds before a liveview started still count as
+    /// its own; clips Blink marks as liveview recordings don't need it.
+    pub liveview_cleanup_lookback_secs: u64,
+    /// Seconds of each live session kept in memory for instant replay; 0 turns it off.
+    pub liveview_replay_secs: u64,
+    /// When set, live sessions started with `record` are also written to this folder,
+    /// see `recording`.
+    pub liveview_recording_dir: Option<String>,
+    /// `mp4` to remux recordings into a faststart MP4, `ts` to keep the camera's stream.
+    pub liveview_recording_format: RecordingFormat,
+    pub close_to_tray: bool,
+    /// Arm/disarm entries run by the local scheduler.
+    pub schedules: Vec<ScheduleEntry>,
+    /// Keep a local SQLite index of media for search and offline browsing.
+    pub media_index_enabled: bool,
+    /// Local overrides per camera id; Blink never sees these.
+    pub camera_prefs: BTreeMap<i64, CameraPrefs>,
+    /// Allow writing settings found by the liveview-candidate scanner.
+    pub advanced_settings_enabled: bool,
+    /// When set, deferred deletions download each clip here before removing it from Blink.
+    pub archive_dir: Option<String>,
+    /// Archive into S3 or WebDAV (or a local folder) instead; takes precedence over
+    /// `archive_dir`. Credentials come from the environment, see `clip_store`.
+    pub archive_store: Option<ClipStoreConfig>,
+    /// Most events the server's event log keeps.
+    pub event_retention_rows: u64,
+    /// Server events older than this many days are pruned.
+    pub event_retention_days: u32,
+    /// Allow the media diagnostics probe (`diagnose_media`).
+    pub diagnostics_enabled: bool,
+    /// Where the server posts each event it records.
+    pub webhooks: Vec<WebhookTarget>,
+    /// Clips per delete request; larger selections go through the delete queue.
+    pub delete_batch_size: usize,
+    /// Pause between queued delete batches, to stay under Blink's abuse detection.
+    pub delete_batch_delay_secs: u64,
+    /// When the server sends a summary report to the webhooks; never when unset.
+    pub summary_report: Option<ReportSchedule>,
+    /// ntfy and Gotify targets the server pushes motion, offline and low battery alerts to.
+    pub notifiers: Vec<NotifierTarget>,
+    /// Scheduled thumbnail refreshes for cameras with an interval in `camera_prefs`.
+    pub thumbnail_refresh: ThumbnailRefreshSettings,
+    /// Folders the desktop app may download into besides Downloads. Only added when the
+    /// user picks a folder in one of the app's dialogs; updates may remove entries.
+    pub download_dirs: Vec<String>,
+}
+
+/// Display preferences for one camera. An entry with every field at its default is dropped.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraPrefs {
+    /// Shown instead of the Blink name when set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nickname: Option<String>,
+    /// Cameras with an order come first, ascending; the rest keep Blink's order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort_order: Option<i32>,
+    pub hidden: bool,
+    /// Default liveview quality (`saver` or `best`) when a request doesn't pick one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liveview_quality: Option<String>,
+    /// Ask the camera for a new thumbnail this often; see `thumbnail_refresh`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thumbnail_refresh_minutes: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            download_concurrency: 3,
+            download_filename_template: export::DEFAULT_FILENAME_TEMPLATE.to_string(),
+            download_sidecar: false,
+            motion_alerts_enabled: false,
+            motion_interval_secs: 60,
+            liveview_busy_retries: 5,
+            liveview_retry_delay_secs: 4,
+            liveview_read_timeout_secs: 20,
+            liveview_cleanup_enabled: true,
+            liveview_cleanup_lookback_secs: 0,
+            liveview_replay_secs: 15,
+            liveview_recording_dir: None,
+            liveview_recording_format: RecordingFormat::Mp4,
+            close_to_tray: false,
+            schedules: Vec::new(),
+            media_index_enabled: false,
+            camera_prefs: BTreeMap::new(),
+            advanced_settings_enabled: false,
+            archive_dir: None,
+            archive_store: None,
+            event_retention_rows: 10_000,
+            event_retention_days: 30,
+            diagnostics_enabled: false,
+            webhooks: Vec::new(),
+            delete_batch_size: 25,
+            delete_batch_delay_secs: 3,
+            summary_report: None,
+            notifiers: Vec::new(),
+            thumbnail_refresh: ThumbnailRefreshSettings::default(),
+            download_dirs: Vec::new(),
+        }
+    }
+}
+
+/// Brings an older document up to `SETTINGS_VERSION` and returns the version it started at.
+/// Each bump gets its own step; missing keys fall back to defaults and unknown ones are dropped.
+fn migrate(raw: &mut Value) -> u32 {
+    let from = raw.get("version").and_then(Value::as_u64).unwrap_or(0) as u32;
+    // 0 -> 1: files written before the version field existed already use the v1 key names.
+    if from < SETTINGS_VERSION {
+        if let Some(obj) = raw.as_object_mut() {
+            obj.insert("version".to_string(), SETTINGS_VERSION.into());
+        }
+    }
+    from
+}
+
+impl Settings {
+    /// Clamps values into ranges the rest of the app can rely on.
+    fn normalized(mut self) -> Self {
+        self.version = SETTINGS_VERSION;
+        self.download_concurrency = self.download_concurrency.clamp(1, MAX_DOWNLOAD_CONCURRENCY);
+        if export::validate_filename_template(&self.download_filename_template).is_err() {
+            self.download_filename_template = export::DEFAULT_FILENAME_TEMPLATE.to_string();
+        }
+        self.motion_interval_secs = self.motion_interval_secs.max(MIN_MOTION_INTERVAL_SECS);
+        self.liveview_busy_retries = self.liveview_busy_retries.clamp(1, 20);
+        self.liveview_retry_delay_secs = self.liveview_retry_delay_secs.clamp(1, 60);
+        self.liveview_read_timeout_secs = self.liveview_read_timeout_secs.clamp(5, 300);
+        self.liveview_cleanup_lookback_secs = self.liveview_cleanup_lookback_secs.min(120);
+        self.liveview_replay_secs = self.liveview_replay_secs.min(replay::MAX_REPLAY_SECS);
+        self.liveview_recording_dir = self.liveview_recording_dir.take().map(|d| d.trim().to_string()).filter(|d| !d.is_empty());
+        self.event_retention_rows = self.event_retention_rows.clamp(100, 1_000_000);
+        self.event_retention_days = self.event_retention_days.clamp(1, 3650);
+        self.delete_batch_size = self.delete_batch_size.clamp(1, 100);
+        self.delete_batch_delay_secs = self.delete_batch_delay_secs.min(120);
+        for entry in self.schedules.iter_mut().filter(|e| e.id.is_empty()) {
+            entry.id = uuid::Uuid::new_v4().to_string();
+        }
+        for prefs in self.camera_prefs.values_mut() {
+            prefs.nickname = prefs.nickname.take().map(|n| n.trim().to_string()).filter(|n| !n.is_empty());
+            prefs.liveview_quality = prefs.liveview_quality.take().filter(|q| q.parse::<LiveviewQuality>().is_ok());
+            prefs.thumbnail_refresh_minutes = prefs.thumbnail_refresh_minutes.filter(|m| *m > 0);
+        }
+        self.camera_prefs.retain(|_, prefs| *prefs != CameraPrefs::default());
+        self
+    }
+
+    /// Builds settings from a document that may be older than `SETTINGS_VERSION`, as
+    /// `load` does; documents from a newer version are rejected.
+    pub fn from_value(mut raw: Value) -> Result<Settings> {
+        let version = raw.get("version").and_then(Value::as_u64).unwrap_or(0);
+        if version > SETTINGS_VERSION as u64 {
+            return Err(anyhow!("Settings version {} is newer than this build supports ({})", version, SETTINGS_VERSION));
+        }
+        migrate(&mut raw);
+        Ok(serde_json::from_value::<Settings>(raw)?.normalized())
+    }
+
+    /// Where deferred deletions archive clips, if anywhere.
+    pub fn archive_store(&self) -> Option<ClipStoreConfig> {
+        self.archive_store.clone().or_else(|| {
+            let dir = self.archive_dir.as_deref().filter(|d| !d.is_empty())?;
+            Some(ClipStoreConfig::Local { dir: dir.to_string() })
+        })
+    }
+
+    /// The camera's preferred liveview quality, if one is set.
+    pub fn liveview_quality(&self, camera_id: i64) -> Option<LiveviewQuality> {
+        self.camera_prefs.get(&camera_id)?.liveview_quality.as_deref()?.parse().ok()
+    }
+
+    /// Fills each camera's display fields from `camera_prefs` and sorts by `sort_order`.
+    /// Hidden cameras are kept (flagged) so they can still be unhidden.
+    pub fn apply_camera_prefs(&self, cameras: &mut [Camera]) {
+        for camera in cameras.iter_mut() {
+            let prefs = self.camera_prefs.get(&camera.id).cloned().unwrap_or_default();
+            camera.display_name = prefs.nickname.unwrap_or_else(|| camera.name.clone());
+            camera.sort_order = prefs.sort_order;
+            camera.hidden = prefs.hidden;
+            camera.liveview_quality = prefs.liveview_quality;
+        }
+        cameras.sort_by_key(|camera| camera.sort_order.unwrap_or(i32::MAX));
+    }
+
+    /// Reads settings from `path`, migrating and rewriting older files. A missing or
+    /// unreadable file yields the defaults rather than an error.
+    pub fn load(path: &Path) -> Settings {
+        let Ok(json) = fs::read_to_string(path) else {
+            return Settings::default();
+        };
+        let mut raw: Value = match serde_json::from_str(&json) {
+            Ok(raw) => raw,
+            Err(e) => {
+                eprintln!("Ignoring unreadable settings file {}: {}", path.display(), e);
+                return Settings::default();
+            }
+        };
+        let from = migrate(&mut raw);
+        let settings = match serde_json::from_value::<Settings>(raw) {
+            Ok(settings) => settings.normalized(),
+            Err(e) => {
+                eprintln!("Ignoring invalid settings file {}: {}", path.display(), e);
+                return Settings::default();
+            }
+        };
+        if from < SETTINGS_VERSION {
+            if let Err(e) = settings.save(path) {
+                eprintln!("Failed to write migrated settings: {}", e);
+            }
+        }
+        settings
+    }
+
+    /// Writes to a temp file next to `path` and renames it over the original, so a crash
+    /// mid-write leaves the previous settings intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        drop(file);
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Returns a copy with the keys present in `patch` replaced. Unknown keys are rejected
+    /// so typos don't silently do nothing.
+    pub fn with_patch(&self, patch: Value) -> Result<Settings> {
+        let Value::Object(patch) = patch else {
+            return Err(anyhow!("Settings update must be a JSON object"));
+        };
+        let mut merged = serde_json::to_value(self)?;
+        let target = merged.as_object_mut().ok_or_else(|| anyhow!("Settings did not serialize to an object"))?;
+        for (key, value) in patch {
+            if key == "version" {
+                continue;
+            }
+            if !target.contains_key(&key) {
+                return Err(anyhow!("Unknown setting: {}", key));
+            }
+            target.insert(key, value);
+        }
+        let settings = serde_json::from_value::<Settings>(merged)?;
+        export::validate_filename_template(&settings.download_filename_template)?;
+        for quality in settings.camera_prefs.values().filter_map(|p| p.liveview_quality.as_deref()) {
+            if !quality.is_empty() {
+                quality.parse::<LiveviewQuality>()?;
+            }
+        }
+        let settings = settings.normalized();
+        for entry in &settings.schedules {
+            entry.validate()?;
+        }
+        for target in &settings.webhooks {
+            target.validate()?;
+        }
+        for target in &settings.notifiers {
+            target.validate()?;
+        }
+        settings.thumbnail_refresh.validate()?;
+        if let Some(schedule) = &settings.summary_report {
+            schedule.validate()?;
+        }
+        if let Some(store) = &settings.archive_store {
+            store.validate()?;
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn patches_replace_known_keys_and_reject_unknown_ones() {
+        let settings = Settings::default()
+            .with_patch(json!({ "download_concurrency": 5, "close_to_tray": true, "version": 99 }))
+            .unwrap();
+        assert_eq!(settings.download_concurrency, 5);
+        assert!(settings.close_to_tray);
+        assert_eq!(settings.version, SETTINGS_VERSION);
+
+        let err = Settings::default().with_patch(json!({ "download_concurency": 5 })).unwrap_err();
+        assert!(err.to_string().contains("Unknown setting: download_concurency"), "{}", err);
+        assert!(Settings::default().with_patch(json!([1, 2])).is_err());
+        assert!(Settings::default().with_patch(json!({ "download_filename_template": "{bogus}" })).is_err());
+    }
+
+    #[test]
+    fn out_of_range_values_are_clamped() {
+        let settings = Settings::default()
+            .with_patch(json!({
+                "download_concurrency": 0,
+                "motion_interval_secs": 1,
+                "liveview_busy_retries": 500,
+                "delete_batch_size": 0,
+            }))
+            .unwrap();
+        assert_eq!(settings.download_concurrency, 1);
+        assert_eq!(settings.motion_interval_secs, MIN_MOTION_INTERVAL_SECS);
+        assert_eq!(settings.liveview_busy_retries, 20);
+        assert_eq!(settings.delete_batch_size, 1);
+    }
+
+    #[test]
+    fn unversioned_documents_migrate_and_newer_ones_are_refused() {
+        let settings = Settings::from_value(json!({ "close_to_tray": true })).unwrap();
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert!(settings.close_to_tray);
+        assert!(Settings::from_value(json!({ "version": SETTINGS_VERSION + 1 })).is_err());
+    }
+
+    #[test]
+    fn save_and_load_round_trip_and_bad_files_fall_back_to_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join(SETTINGS_FILE);
+        let settings = Settings::default().with_patch(json!({ "archive_dir": "/srv/archive" })).unwrap();
+        settings.save(&path).unwrap();
+        assert_eq!(Settings::load(&path), settings);
+        assert!(!path.with_extension("json.tmp").exists());
+
+        std::fs::write(&path, "{ not json").unwrap();
+        assert_eq!(Settings::load(&path), Settings::default());
+        assert_eq!(Settings::load(&dir.path().join("missing.json")), Settings::default());
+    }
+}
@@ -0,0 +1,639 @@
+//! Logic shared by the Tauri commands and the standalone server's handlers.
+
+use crate::blink::{AuthState, BlinkAuthState, BlinkClient, BlinkError, TierHeal};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+use url::Url;
+
+type TierHealHook = Box<dyn Fn(&TierHeal) + Send + Sync>;
+
+static PERSIST_AUTH: OnceLock<fn(&BlinkAuthState) -> Result<()>> = OnceLock::new();
+static MEDIA_HOSTS: OnceLock<Vec<String>> = OnceLock::new();
+static ON_TIER_HEALED: OnceLock<TierHealHook> = OnceLock::new();
+
+/// Domains the media proxies and downloaders may fetch from, each including its
+/// subdomains. `BLINK_MEDIA_HOSTS` (comma-separated) replaces the list, e.g. to allow only
+/// one S3 bucket.
+pub const DEFAULT_MEDIA_HOSTS: &[&str] = &[
+    "immedia-semi.com",
+    "blinkforhome.com",
+    "blink.com",
+    "amazonaws.com",
+    "cloudfront.net",
+];
+
+fn media_hosts() -> &'static [String] {
+    MEDIA_HOSTS.get_or_init(|| {
+        let configured: Vec<String> = std::env::var("BLINK_MEDIA_HOSTS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|h| h.trim().trim_start_matches("*.").trim_matches('.').to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect();
+        if configured.is_empty() {
+            DEFAULT_MEDIA_HOSTS.iter().map(|h| h.to_string()).collect()
+        } else {
+            configured
+        }
+    })
+}
+
+/// Whether `host` is `domain` or a subdomain of it. Matches whole labels, so neither
+/// `evilamazonaws.com` nor `amazonaws.com.evil.com` passes for `amazonaws.com`.
+fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.strip_suffix(domain).is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// SSRF guard for media fetches: `url` must be HTTPS, carry no credentials, and name an
+/// allow-listed host (IP literals never match). Returns the parsed URL to request.
+pub fn validate_media_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|_| "Invalid URL format".to_string())?;
+    if parsed.scheme() != "https" {
+        return Err(format!("Media URLs must use HTTPS, not {}", parsed.scheme()));
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err("Media URLs must not contain credentials".to_string());
+    }
+    let host = match parsed.host() {
+        Some(url::Host::Domain(host)) => host.trim_end_matches('.').to_ascii_lowercase(),
+        Some(host) => return Err(format!("Invalid URL host: {}", host)),
+        None => return Err("Invalid URL".to_string()),
+    };
+    if !media_hosts().iter().any(|domain| host_matches(&host, domain)) {
+        return Err(format!("Invalid URL host: {}", host));
+    }
+    Ok(parsed)
+}
+
+/// Sets where `refresh_and_persist` saves refreshed sessions; each front end keeps auth
+/// in its own place. Until this is called, refreshed tokens only live in memory.
+pub fn init_auth_persistence(save: fn(&BlinkAuthState) -> Result<()>) {
+    let _ = PERSIST_AUTH.set(save);
+}
+
+/// Refreshes the access token if it is close to expiry and persists the new state.
+/// Returns whether a refresh happened. A revoked refresh token is persisted too, so the
+/// `ReauthRequired` state survives restarts.
+pub async fn refresh_and_persist(client: &mut BlinkClient) -> Result<bool> {
+    let was_revoked = client.auth_state() == AuthState::ReauthRequired;
+    let result = client.refresh_token_if_needed().await;
+    let newly_revoked = !was_revoked && client.auth_state() == AuthState::ReauthRequired;
+    if let (true, Some(save)) = (newly_revoked || matches!(result, Ok(true)), PERSIST_AUTH.get()) {
+        let _ = save(&client.get_state());
+    }
+    result
+}
+
+/// Called with each tier change applied to the session, e.g. to log an event. Only the
+/// first registration counts.
+pub fn on_tier_healed(notify: impl Fn(&TierHeal) + Send + Sync + 'static) {
+    let _ = ON_TIER_HEALED.set(Box::new(notify));
+}
+
+/// Moves the session to the host a clone found after the account changed tiers, and
+/// persists it. Returns whether anything changed.
+pub fn apply_tier_heal(client: &mut BlinkClient) -> bool {
+    let Some(heal) = client.take_tier_heal() else { return false };
+    if let Some(save) = PERSIST_AUTH.get() {
+        let _ = save(&client.get_state());
+    }
+    if let Some(notify) = ON_TIER_HEALED.get() {
+        notify(&heal);
+    }
+    true
+}
+
+/// Refreshes the token under the lock and returns a copy of the client to make requests
+/// with, so slow requests don't hold up everything else waiting on the mutex. Only for
+/// calls that leave the client's auth state alone; login and logout keep the lock.
+pub async fn snapshot(client: &tokio::sync::Mutex<BlinkClient>) -> BlinkClient {
+    let mut client = client.lock().await;
+    let _ = refresh_and_persist(&mut client).await;
+    apply_tier_heal(&mut client);
+    client.clone()
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RevokeOutcome {
+    pub id: i64,
+    /// The client was the one this app is signed in as.
+    pub current: bool,
+    pub revoked: bool,
+    /// The local session was dropped because its client no longer exists.
+    pub signed_out: bool,
+    pub warning: Option<String>,
+}
+
+/// Revokes a trusted client. Revoking the one this app uses ends its session, so that
+/// needs `force`; without it nothing is sent and the outcome carries a warning to show.
+/// After a forced revoke the client is logged out here and the caller clears stored auth.
+pub async fn revoke_trusted_client(blink_client: &tokio::sync::Mutex<BlinkClient>, id: i64, force: bool) -> Result<RevokeOutcome> {
+    let client = snapshot(blink_client).await;
+    let current = client.list_trusted_clients().await?
+        .into_iter()
+        .find(|c| c.id == id)
+        .ok_or_else(|| BlinkError::NotFound(format!("No trusted client {}", id)))?
+        .current;
+    if current && !force {
+        return Ok(RevokeOutcome {
+            id,
+            current,
+            revoked: false,
+            signed_out: false,
+            warning: Some("This is the client Blink Monitor is signed in as; revoking it signs you out here too".to_string()),
+        });
+    }
+    client.revoke_trusted_client(id).await?;
+    if current {
+        blink_client.lock().await.logout();
+    }
+    Ok(RevokeOutcome { id, current, revoked: true, signed_out: current, warning: None })
+}
+
+/// What kind of object a liveview-save candidate was found on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CandidateDeviceKind {
+    Network,
+    SyncModule,
+    Camera,
+    Owl,
+    Doorbell,
+}
+
+impl CandidateDeviceKind {
+    /// The kind implied by the homescreen list an object sits in.
+    pub(crate) fn from_list(key: &str) -> Option<Self> {
+        match key {
+            "networks" => Some(Self::Network),
+            "sync_modules" => Some(Self::SyncModule),
+            "cameras" => Some(Self::Camera),
+            "owls" => Some(Self::Owl),
+            "doorbells" => Some(Self::Doorbell),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, serde::Serialize)]
+pub struct LiveviewSettingCandidate {
+    pub path: String,
+    pub value: Value,
+    pub network_id: Option<i64>,
+    pub network_name: Option<String>,
+    /// The closest enclosing network, sync module or camera, when one was recognized.
+    pub device_kind: Option<CandidateDeviceKind>,
+    pub device_id: Option<i64>,
+}
+
+fn is_liveview_candidate_key(key: &str, value: &Value) -> bool {
+    let k = key.to_lowercase();
+    let has_live = k.contains("live") || k.contains("liveview") || k.contains("live_view") || k.contains("lv");
+    let has_save = k.contains("save") || k.contains("record") || k.contains("clip");
+    if !(has_live && has_save) {
+        return false;
+    }
+    matches!(value, Value::Bool(_) | Value::Number(_) | Value::String(_))
+}
+
+/// Where the scanner currently is: the network and device that own what it finds.
+#[derive(Clone, Default)]
+struct CandidateScope {
+    network_id: Option<i64>,
+    network_name: Option<String>,
+    device_kind: Option<CandidateDeviceKind>,
+    device_id: Option<i64>,
+}
+
+impl CandidateScope {
+    /// Narrows the scope if `map` is a network, sync module or camera. `list` is the key of
+    /// the array holding `map`, if any; objects outside the usual lists are recognized by
+    /// shape instead (networks carry `armed`, devices carry `network_id`).
+    fn enter(&self, map: &serde_json::Map<String, Value>, list: Option<&str>, network_names: &HashMap<i64, String>) -> Self {
+        let id = map.get("id").and_then(|v| v.as_i64());
+        let name = map.get("name").and_then(|v| v.as_str());
+        let (Some(id), Some(name)) = (id, name) else {
+            return self.clone();
+        };
+        let owner_network = map.get("network_id").and_then(|v| v.as_i64());
+        let kind = match list.and_then(CandidateDeviceKind::from_list) {
+            Some(kind) => kind,
+            None if map.get("armed").and_then(|v| v.as_bool()).is_some() => CandidateDeviceKind::Network,
+            None if owner_network.is_some() => CandidateDeviceKind::Camera,
+            None => return self.clone(),
+        };
+        if kind == CandidateDeviceKind::Network {
+            return Self {
+                network_id: Some(id),
+                network_name: Some(name.to_string()),
+                device_kind: Some(kind),
+                device_id: Some(id),
+            };
+        }
+        let network_id = owner_network.or(self.network_id);
+        let network_name = match owner_network {
+            Some(owner) if Some(owner) != self.network_id => network_names.get(&owner).cloned(),
+            _ => self.network_name.clone(),
+        };
+        Self {
+            network_id,
+            network_name,
+            device_kind: Some(kind),
+            device_id: Some(id),
+        }
+    }
+}
+
+/// Keys that look like a switch for motion push notifications, as opposed to arming.
+fn is_motion_notification_key(key: &str, value: &Value) -> bool {
+    let k = key.to_lowercase();
+    let about_motion = k.contains("notif") || (k.contains("motion") && (k.contains("alert") || k.contains("push")));
+    about_motion && matches!(value, Value::Bool(_) | Value::Number(_))
+}
+
+/// Walks raw homescreen documents looking for settings that look like
+/// "save liveview recordings" toggles. A path found in several documents (e.g. successive
+/// fetches) is reported once, from the first document that has it.
+pub fn liveview_candidates(pages: &[Value]) -> Vec<LiveviewSettingCandidate> {
+    scan_candidates(pages, is_liveview_candidate_key)
+}
+
+/// Like `liveview_candidates`, for toggles that look like they switch motion push
+/// notifications on or off.
+pub fn motion_notification_candidates(pages: &[Value]) -> Vec<LiveviewSettingCandidate> {
+    scan_candidates(pages, is_motion_notification_key)
+}
+
+fn scan_candidates(pages: &[Value], is_candidate: fn(&str, &Value) -> bool) -> Vec<LiveviewSettingCandidate> {
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    for page in pages {
+        let network_names: HashMap<i64, String> = page.get("networks")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|n| Some((n.get("id")?.as_i64()?, n.get("name")?.as_str()?.to_string())))
+            .collect();
+        let mut found = Vec::new();
+        scan_object_candidates(page, "", None, is_candidate, &mut found, &CandidateScope::default(), &network_names);
+        candidates.extend(found.into_iter().filter(|c| seen.insert(c.path.clone())));
+    }
+    candidates
+}
+
+fn scan_object_candidates(
+    value: &Value,
+    path: &str,
+    list: Option<&str>,
+    is_candidate: fn(&str, &Value) -> bool,
+    out: &mut Vec<LiveviewSettingCandidate>,
+    scope: &CandidateScope,
+    network_names: &HashMap<i64, String>,
+) {
+    match value {
+        Value::Object(map) => {
+            let scope = scope.enter(map, list, network_names);
+            for (k, v) in map {
+                let next_path = if path.is_empty() {
+                    k.to_string()
+                } else {
+                    format!("{}.{}", path, k)
+                };
+                if is_candidate(k, v) {
+                    out.push(LiveviewSettingCandidate {
+                        path: next_path.clone(),
+                        value: v.clone(),
+                        network_id: scope.network_id,
+                        network_name: scope.network_name.clone(),
+                        device_kind: scope.device_kind,
+                        device_id: scope.device_id,
+                    });
+                }
+                scan_object_candidates(v, &next_path, Some(k), is_candidate, out, &scope, network_names);
+            }
+        }
+        Value::Array(items) => {
+            for (idx, item) in items.iter().enumerate() {
+                let next_path = format!("{}[{}]", path, idx);
+                scan_object_candidates(item, &next_path, list, is_candidate, out, scope, network_names);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Discovered keys `set_discovered_setting` may write. Anything else found by the scanner
+/// is read-only; these are known to be plain toggles Blink accepts in a one-key patch.
+pub const WRITABLE_DISCOVERED_KEYS: &[&str] = &["lv_save", "liveview_save", "lv_save_enabled"];
+
+/// How a discovered-setting write turned out, judged by re-reading the homescreen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SettingWriteOutcome {
+    /// The path now holds the requested value.
+    Applied,
+    /// Blink refused the update; `error` says why.
+    Rejected,
+    /// Blink accepted the update but the path still holds its old value.
+    Unchanged,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DiscoveredSettingResult {
+    pub path: String,
+    pub outcome: SettingWriteOutcome,
+    pub before: Value,
+    /// What the path held after the write; `None` if it disappeared from the homescreen.
+    pub after: Option<Value>,
+    pub error: Option<String>,
+}
+
+/// Reads a scanner path such as `networks[0].lv_save` out of a document.
+pub fn value_at_path<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    let mut current = doc;
+    for segment in path.split('.') {
+        let (key, indices) = match segment.find('[') {
+            Some(open) => (&segment[..open], &segment[open..]),
+            None => (segment, ""),
+        };
+        if !key.is_empty() {
+            current = current.get(key)?;
+        }
+        for index in indices.split('[').filter(|s| !s.is_empty()) {
+            current = current.get(index.strip_suffix(']')?.parse::<usize>().ok()?)?;
+        }
+    }
+    Some(current)
+}
+
+/// The innermost object on `path` whose `id` is `device_id`.
+fn owner_object<'a>(doc: &'a Value, path: &str, device_id: i64) -> Option<&'a Value> {
+    let mut prefixes: Vec<usize> = path.match_indices(['.', '[']).map(|(i, _)| i).collect();
+    prefixes.reverse();
+    prefixes.into_iter()
+        .filter_map(|end| value_at_path(doc, &path[..end]))
+        .find(|v| v.get("id").and_then(|id| id.as_i64()) == Some(device_id))
+}
+
+/// Changes one setting found by `liveview_candidates`. The path is mapped back to the
+/// network or camera that owns it, a patch with just that key goes to the owner's update
+/// endpoint, and the homescreen is re-read to report whether the change stuck.
+/// Only keys in `WRITABLE_DISCOVERED_KEYS` are accepted, and the new value must have the
+/// same JSON type as the current one.
+pub async fn set_discovered_setting(client: &BlinkClient, path: &str, value: Value) -> Result<DiscoveredSettingResult> {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    if !WRITABLE_DISCOVERED_KEYS.contains(&key) {
+        return Err(anyhow!("{} is not a writable setting", key));
+    }
+
+    let homescreen = client.get_homescreen_json().await?;
+    let candidate = liveview_candidates(std::slice::from_ref(&*homescreen))
+        .into_iter()
+        .find(|c| c.path == path)
+        .ok_or_else(|| anyhow!("No discovered setting at {}", path))?;
+    let same_type = matches!(
+        (&candidate.value, &value),
+        (Value::Bool(_), Value::Bool(_)) | (Value::Number(_), Value::Number(_)) | (Value::String(_), Value::String(_))
+    );
+    if !same_type {
+        return Err(anyhow!("{} holds {}, which can't be replaced with {}", path, candidate.value, value));
+    }
+
+    let (Some(kind), Some(device_id)) = (candidate.device_kind, candidate.device_id) else {
+        return Err(anyhow!("Can't tell which device owns {}", path));
+    };
+    let patch = serde_json::json!({ key: value.clone() });
+    let sent = match kind {
+        CandidateDeviceKind::Network => client.set_network_field(device_id, key, value.clone()).await,
+        CandidateDeviceKind::Camera | CandidateDeviceKind::Owl | CandidateDeviceKind::Doorbell => {
+            let network_id = candidate.network_id.ok_or_else(|| anyhow!("Can't tell which network owns {}", path))?;
+            let product_type = owner_object(&homescreen, path, device_id)
+                .and_then(|owner| owner.get("type"))
+                .and_then(|t| t.as_str())
+                .map(str::to_string)
+                .unwrap_or_else(|| match kind {
+                    CandidateDeviceKind::Owl => "owl".to_string(),
+                    CandidateDeviceKind::Doorbell => "doorbell".to_string(),
+                    _ => "camera".to_string(),
+                });
+            client.update_camera_config(network_id, device_id, &product_type, patch).await
+        }
+        CandidateDeviceKind::SyncModule => Err(anyhow!("Sync module settings can't be changed here")),
+    };
+
+    let after = match client.get_homescreen_json().await {
+        Ok(doc) => value_at_path(&doc, path).cloned(),
+        Err(e) if sent.is_ok() => return Err(e),
+        Err(_) => None,
+    };
+    let (outcome, error) = match sent {
+        Err(e) => (SettingWriteOutcome::Rejected, Some(e.to_string())),
+        Ok(()) if after.as_ref() == Some(&value) => (SettingWriteOutcome::Applied, None),
+        Ok(()) => (SettingWriteOutcome::Unchanged, None),
+    };
+    Ok(DiscoveredSettingResult {
+        path: path.to_string(),
+        outcome,
+        before: candidate.value,
+        after,
+        error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::sync::{Mutex, Semaphore};
+
+    type Scope<'a> = (Option<i64>, Option<&'a str>, Option<CandidateDeviceKind>, Option<i64>);
+
+    fn scopes(candidates: &[LiveviewSettingCandidate]) -> HashMap<&str, Scope<'_>> {
+        candidates.iter()
+            .map(|c| (c.path.as_str(), (c.network_id, c.network_name.as_deref(), c.device_kind, c.device_id)))
+            .collect()
+    }
+
+    #[test]
+    fn candidates_are_scoped_to_the_device_they_sit_on() {
+        use CandidateDeviceKind::*;
+        let page: Value = serde_json::from_str(include_str!("../tests/fixtures/homescreen_nested_devices.json")).unwrap();
+        let candidates = liveview_candidates(std::slice::from_ref(&page));
+        let expected: HashMap<&str, Scope> = HashMap::from([
+            ("account.lv_save_default", (None, None, None, None)),
+            ("networks[0].lv_save", (Some(1001), Some("Home"), Some(Network), Some(1001))),
+            ("networks[0].cameras[0].liveview_save", (Some(1001), Some("Home"), Some(Camera), Some(2001))),
+            // A setting nested in a plain object still belongs to the sync module around it.
+            ("networks[0].sync_modules[0].local_storage.lv_save_enabled", (Some(1001), Some("Home"), Some(SyncModule), Some(5001))),
+            ("networks[1].cameras[0].liveview_save", (Some(1002), Some("Cabin"), Some(Camera), Some(2002))),
+            // Top-level devices take their network, name included, from `network_id`.
+            ("sync_modules[0].lv_save", (Some(1002), Some("Cabin"), Some(SyncModule), Some(5002))),
+            ("cameras[0].lv_record_clips", (Some(1001), Some("Home"), Some(Camera), Some(2003))),
+            ("owls[0].lv_save", (Some(1002), Some("Cabin"), Some(Owl), Some(3001))),
+        ]);
+        assert_eq!(scopes(&candidates), expected);
+        assert_eq!(candidates.len(), expected.len());
+    }
+
+    #[test]
+    fn a_path_seen_on_several_pages_is_reported_once_from_the_first() {
+        let first: Value = serde_json::from_str(include_str!("../tests/fixtures/homescreen_nested_devices.json")).unwrap();
+        let mut second = first.clone();
+        second["networks"][0]["lv_save"] = Value::Bool(false);
+        second["networks"][1]["lv_save"] = Value::Bool(true);
+
+        let candidates = liveview_candidates(&[first.clone(), second]);
+        let paths: Vec<&str> = candidates.iter().map(|c| c.path.as_str()).collect();
+        let unique: HashSet<&str> = paths.iter().copied().collect();
+        assert_eq!(paths.len(), unique.len(), "{:?}", paths);
+        assert_eq!(candidates.len(), liveview_candidates(std::slice::from_ref(&first)).len() + 1);
+
+        let home = candidates.iter().find(|c| c.path == "networks[0].lv_save").unwrap();
+        assert_eq!(home.value, Value::Bool(true));
+        let cabin = candidates.iter().find(|c| c.path == "networks[1].lv_save").unwrap();
+        assert_eq!((cabin.network_id, cabin.device_kind), (Some(1002), Some(CandidateDeviceKind::Network)));
+    }
+    use wiremock::{matchers, Mock, MockServer, ResponseTemplate};
+
+    fn logged_in(base_url: &str) -> BlinkClient {
+        let json = serde_json::json!({
+            "token": "token",
+            "refresh_token": "refresh",
+            "account_id": 42,
+            "base_url": base_url,
+            "device_id": "device",
+            "token_expiry": chrono::Utc::now().timestamp() + 3600,
+        });
+        BlinkClient::from_state(BlinkAuthState::from_json(&json.to_string(), "test").unwrap())
+    }
+
+    /// The gallery case: many slow thumbnail fetches behind a small permit pool, as the
+    /// app's commands do, must not hold up an arm request issued in the middle of them.
+    #[tokio::test]
+    async fn arm_is_not_starved_by_slow_thumbnail_fetches() {
+        let server = MockServer::start().await;
+        Mock::given(matchers::path_regex("^/media/thumb-\\d+\\.jpg$"))
+            .respond_with(ResponseTemplate::new(200)
+                .set_body_raw(vec![0xFF, 0xD8, 0xFF, 0xE0], "image/jpeg")
+                .set_delay(Duration::from_millis(300)))
+            .expect(50)
+            .mount(&server)
+            .await;
+        Mock::given(matchers::method("POST"))
+            .and(matchers::path("/api/v1/accounts/42/networks/7/state/arm"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = Arc::new(Mutex::new(logged_in(&server.uri())));
+        let permits = Arc::new(Semaphore::new(4));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let thumbnails: Vec<_> = (0..50)
+            .map(|i| {
+                let (client, permits, in_flight, peak) = (client.clone(), permits.clone(), in_flight.clone(), peak.clone());
+                tokio::spawn(async move {
+                    let _permit = permits.acquire().await.unwrap();
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    let result = snapshot(&client).await.get_thumbnail(&format!("/media/thumb-{}.jpg", i)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    result.map(|_| Instant::now())
+                })
+            })
+            .collect();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let started = Instant::now();
+        snapshot(&client).await.set_arm(7, true).await.unwrap();
+        let armed_at = Instant::now();
+        assert!(armed_at - started < Duration::from_secs(1), "arm took {:?}", armed_at - started);
+
+        let mut last_thumbnail = started;
+        for handle in thumbnails {
+            last_thumbnail = last_thumbnail.max(handle.await.unwrap().unwrap());
+        }
+        assert!(last_thumbnail > armed_at, "the arm should finish while thumbnails are still loading");
+        assert!(peak.load(Ordering::SeqCst) <= 4);
+    }
+
+    #[test]
+    fn host_matching_respects_label_boundaries() {
+        assert!(host_matches("amazonaws.com", "amazonaws.com"));
+        assert!(host_matches("foo.amazonaws.com", "amazonaws.com"));
+        assert!(host_matches("a.b.s3.amazonaws.com", "amazonaws.com"));
+        assert!(!host_matches("evilamazonaws.com", "amazonaws.com"));
+        assert!(!host_matches("foo.amazonaws.com.evil.com", "amazonaws.com"));
+        assert!(!host_matches("amazonaws.co", "amazonaws.com"));
+        assert!(!host_matches("com", "amazonaws.com"));
+    }
+
+    #[test]
+    fn media_urls_on_allowed_hosts_pass() {
+        for url in [
+            "https://rest-u011.immedia-semi.com/api/v2/media/clip.mp4",
+            "https://media.s3.us-east-1.amazonaws.com/clip.mp4?X-Amz-Signature=abc",
+            "https://d1234.cloudfront.net/thumb.jpg",
+            "https://REST-U011.IMMEDIA-SEMI.COM/clip.mp4",
+            "https://rest-u011.immedia-semi.com./clip.mp4",
+            "https://rest-u011.immedia-semi.com:443/clip.mp4",
+        ] {
+            assert!(validate_media_url(url).is_ok(), "{}", url);
+        }
+        let parsed = validate_media_url("https://d1234.cloudfront.net/thumb.jpg?x=1").unwrap();
+        assert_eq!((parsed.path(), parsed.query()), ("/thumb.jpg", Some("x=1")));
+    }
+
+    #[test]
+    fn tricky_media_hosts_are_rejected() {
+        for url in [
+            "https://foo.amazonaws.com.evil.com/clip.mp4",
+            "https://evilamazonaws.com/clip.mp4",
+            "https://immedia-semi.com-evil.com/clip.mp4",
+            "https://evil.com/rest-u011.immedia-semi.com/clip.mp4",
+            "https://evil.com?host=immedia-semi.com",
+            "https://evil.com#.immedia-semi.com",
+        ] {
+            let err = validate_media_url(url).unwrap_err();
+            assert!(err.starts_with("Invalid URL host"), "{}: {}", url, err);
+        }
+    }
+
+    #[test]
+    fn ip_literals_never_match() {
+        for url in [
+            "https://127.0.0.1/clip.mp4",
+            "https://169.254.169.254/latest/meta-data",
+            "https://[::1]/clip.mp4",
+            "https://[::ffff:127.0.0.1]/clip.mp4",
+            // Integer and hex forms are normalized to an IPv4 address by the URL parser.
+            "https://2130706433/clip.mp4",
+            "https://0x7f.1/clip.mp4",
+        ] {
+            let err = validate_media_url(url).unwrap_err();
+            assert!(err.starts_with("Invalid URL host"), "{}: {}", url, err);
+        }
+    }
+
+    #[test]
+    fn credentials_and_other_schemes_are_rejected() {
+        for url in [
+            "https://user@rest-u011.immedia-semi.com/clip.mp4",
+            "https://user:pw@rest-u011.immedia-semi.com/clip.mp4",
+            "https://:pw@rest-u011.immedia-semi.com/clip.mp4",
+            // Userinfo that looks like the host is still userinfo; the real host is evil.com.
+            "https://rest-u011.immedia-semi.com@evil.com/clip.mp4",
+        ] {
+            assert_eq!(validate_media_url(url).unwrap_err(), "Media URLs must not contain credentials", "{}", url);
+        }
+        for url in ["http://rest-u011.immedia-semi.com/clip.mp4", "file:///etc/passwd", "ftp://immedia-semi.com/x"] {
+            assert!(validate_media_url(url).unwrap_err().starts_with("Media URLs must use HTTPS"), "{}", url);
+        }
+        assert_eq!(validate_media_url("not a url").unwrap_err(), "Invalid URL format");
+    }
+}
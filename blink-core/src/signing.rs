@@ -0,0 +1,283 @@
+//! HMAC-signed proxy URLs, so an `<img>` or `<video>` tag can load a protected route
+//! without sending headers.
+
+use crate::auth_store;
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use rand::{thread_rng, RngCore};
+use sha2::Sha256;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const DEFAULT_TTL_SECS: i64 = 3600;
+pub const MAX_TTL_SECS: i64 = 24 * 3600;
+
+/// Routes that may be authorized by a signature instead of the API key. Each matches
+/// itself and the paths below it, never a longer name such as `/api/thumbnail-base64`.
+pub const SIGNABLE_PREFIXES: [&str; 4] = ["/api/clip", "/api/proxy", "/api/thumbnail", "/api/live/"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    Missing,
+    Malformed,
+    Expired,
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Missing => write!(f, "Missing signature"),
+            SignatureError::Malformed => write!(f, "Malformed signed URL"),
+            SignatureError::Expired => write!(f, "Signed URL expired"),
+            SignatureError::Invalid => write!(f, "Invalid signature"),
+        }
+    }
+}
+
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+impl UrlSigner {
+    pub fn new(key: Vec<u8>) -> Self {
+        Self { key }
+    }
+
+    /// A signer with a fresh random key; its URLs stop verifying once the process exits.
+    pub fn random() -> Self {
+        let mut key = vec![0u8; 32];
+        thread_rng().fill_bytes(&mut key);
+        Self::new(key)
+    }
+
+    /// Loads the HMAC key from `path`, generating and persisting a new one on first run.
+    /// Whoever can read the key can mint signed URLs, so it gets the same owner-only
+    /// permissions as the auth file, including keys written by older versions.
+    pub fn load_or_create(path: &Path) -> Result<Self> {
+        if let Ok(encoded) = fs::read_to_string(path) {
+            let key = general_purpose::STANDARD.decode(encoded.trim())?;
+            if key.len() < 32 {
+                return Err(anyhow!("Signing key at {} is too short", path.display()));
+            }
+            if let Err(e) = auth_store::restrict_permissions(path) {
+                eprintln!("Failed to restrict permissions on {}: {}", path.display(), e);
+            }
+            return Ok(Self::new(key));
+        }
+
+        let signer = Self::random();
+        auth_store::write_atomic(path, general_purpose::STANDARD.encode(&signer.key).as_bytes())?;
+        Ok(signer)
+    }
+
+    pub fn is_signable(path: &str) -> bool {
+        SIGNABLE_PREFIXES.iter().any(|prefix| {
+            path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+        })
+    }
+
+    /// Appends `exp` and `sig` to a proxy URL such as `/api/clip?url=...`.
+    pub fn sign(&self, target: &str, expires_at: i64) -> Result<String> {
+        let (path, query) = match target.split_once('?') {
+            Some((p, q)) => (p, q),
+            None => (target, ""),
+        };
+        let mut pairs = parse_pairs(query).map_err(|e| anyhow!(e.to_string()))?;
+        if pairs.iter().any(|(k, _)| k == "exp" || k == "sig") {
+            return Err(anyhow!("URL is already signed"));
+        }
+        pairs.push(("exp".to_string(), expires_at.to_string()));
+
+        let sig = self.compute(path, &pairs);
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        for (k, v) in &pairs {
+            serializer.append_pair(k, v);
+        }
+        serializer.append_pair("sig", &sig);
+        Ok(format!("{}?{}", path, serializer.finish()))
+    }
+
+    /// Checks `exp` and `sig` on an incoming request path/query pair.
+    pub fn verify(&self, path: &str, query: Option<&str>, now: i64) -> Result<(), SignatureError> {
+        let pairs = parse_pairs(query.unwrap_or(""))?;
+        let sig = pairs.iter()
+            .find(|(k, _)| k == "sig")
+            .map(|(_, v)| v.clone())
+            .ok_or(SignatureError::Missing)?;
+        let exp = pairs.iter()
+            .find(|(k, _)| k == "exp")
+            .and_then(|(_, v)| v.parse::<i64>().ok())
+            .ok_or(SignatureError::Malformed)?;
+
+        let signed: Vec<(String, String)> = pairs.into_iter().filter(|(k, _)| k != "sig").collect();
+        let provided = general_purpose::URL_SAFE_NO_PAD.decode(sig.as_bytes()).map_err(|_| SignatureError::Invalid)?;
+
+        let mac = self.mac(path, &signed);
+        mac.verify_slice(&provided).map_err(|_| SignatureError::Invalid)?;
+
+        if exp < now {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+
+    fn compute(&self, path: &str, pairs: &[(String, String)]) -> String {
+        let mac = self.mac(path, pairs);
+        general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+
+    fn mac(&self, path: &str, pairs: &[(String, String)]) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(canonicalize(path, pairs).as_bytes());
+        mac
+    }
+}
+
+fn parse_pairs(query: &str) -> Result<Vec<(String, String)>, SignatureError> {
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+    for (k, v) in url::form_urlencoded::parse(query.as_bytes()) {
+        // Duplicate keys are ambiguous (first vs last wins depends on the extractor), so refuse them.
+        if !seen.insert(k.to_string()) {
+            return Err(SignatureError::Malformed);
+        }
+        pairs.push((k.into_owned(), v.into_owned()));
+    }
+    Ok(pairs)
+}
+
+// The signature covers the path and every query parameter sorted by key, so
+// reordering parameters cannot produce a different request under the same signature.
+fn canonicalize(path: &str, pairs: &[(String, String)]) -> String {
+    let mut sorted: Vec<&(String, String)> = pairs.iter().collect();
+    sorted.sort();
+    let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+    for (k, v) in sorted {
+        serializer.append_pair(k, v);
+    }
+    format!("{}?{}", path, serializer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn signer() -> UrlSigner {
+        UrlSigner::new(vec![7u8; 32])
+    }
+
+    fn split(url: &str) -> (&str, &str) {
+        url.split_once('?').unwrap()
+    }
+
+    #[test]
+    fn signed_url_verifies_until_it_expires() {
+        let url = signer().sign("/api/clip?url=%2Fapi%2Fv2%2Fclip.mp4", NOW + 60).unwrap();
+        let (path, query) = split(&url);
+        assert_eq!(signer().verify(path, Some(query), NOW), Ok(()));
+        assert_eq!(signer().verify(path, Some(query), NOW + 60), Ok(()));
+        assert_eq!(signer().verify(path, Some(query), NOW + 61), Err(SignatureError::Expired));
+    }
+
+    #[test]
+    fn reordered_parameters_still_verify() {
+        let url = signer().sign("/api/proxy?url=a&kind=clip", NOW + 60).unwrap();
+        let (path, query) = split(&url);
+        let mut pairs: Vec<&str> = query.split('&').collect();
+        pairs.reverse();
+        assert_eq!(signer().verify(path, Some(&pairs.join("&")), NOW), Ok(()));
+    }
+
+    #[test]
+    fn tampering_breaks_the_signature() {
+        let url = signer().sign("/api/proxy?url=a", NOW + 60).unwrap();
+        let (path, query) = split(&url);
+
+        let other_target = query.replace("url=a", "url=b");
+        assert_eq!(signer().verify(path, Some(&other_target), NOW), Err(SignatureError::Invalid));
+
+        let extended = query.replace(&format!("exp={}", NOW + 60), &format!("exp={}", NOW + 6000));
+        assert_eq!(signer().verify(path, Some(&extended), NOW), Err(SignatureError::Invalid));
+
+        let extra = format!("{}&range=0-", query);
+        assert_eq!(signer().verify(path, Some(&extra), NOW), Err(SignatureError::Invalid));
+
+        assert_eq!(signer().verify("/api/clip", Some(query), NOW), Err(SignatureError::Invalid));
+        assert_eq!(UrlSigner::new(vec![8u8; 32]).verify(path, Some(query), NOW), Err(SignatureError::Invalid));
+    }
+
+    #[test]
+    fn duplicate_parameters_are_refused() {
+        let url = signer().sign("/api/proxy?url=a", NOW + 60).unwrap();
+        let (path, query) = split(&url);
+        let doubled = format!("{}&url=b", query);
+        assert_eq!(signer().verify(path, Some(&doubled), NOW), Err(SignatureError::Malformed));
+        assert!(signer().sign("/api/proxy?url=a&url=b", NOW).is_err());
+    }
+
+    #[test]
+    fn missing_or_malformed_signatures_are_reported() {
+        assert_eq!(signer().verify("/api/clip", Some("url=a"), NOW), Err(SignatureError::Missing));
+        assert_eq!(signer().verify("/api/clip", None, NOW), Err(SignatureError::Missing));
+        assert_eq!(signer().verify("/api/clip", Some("url=a&sig=abc"), NOW), Err(SignatureError::Malformed));
+        assert_eq!(signer().verify("/api/clip", Some("url=a&exp=soon&sig=abc"), NOW), Err(SignatureError::Malformed));
+        assert_eq!(signer().verify("/api/clip", Some("url=a&exp=1&sig=%%%"), NOW), Err(SignatureError::Invalid));
+    }
+
+    #[test]
+    fn only_media_and_live_routes_are_signable() {
+        for path in ["/api/clip", "/api/proxy", "/api/thumbnail", "/api/live/7/42/catalina"] {
+            assert!(UrlSigner::is_signable(path), "{} should be signable", path);
+        }
+        for path in ["/api/thumbnail-base64", "/api/thumbnails/prefetch", "/api/clips", "/api/proxyx", "/api/live", "/api/liveview-settings", "/api/settings"] {
+            assert!(!UrlSigner::is_signable(path), "{} should not be signable", path);
+        }
+    }
+
+    #[test]
+    fn already_signed_urls_are_not_signed_again() {
+        let url = signer().sign("/api/clip?url=a", NOW).unwrap();
+        assert!(signer().sign(&url, NOW).is_err());
+    }
+
+    #[test]
+    fn key_is_persisted_owner_only_and_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join("signing.key");
+        let first = UrlSigner::load_or_create(&path).unwrap();
+        let second = UrlSigner::load_or_create(&path).unwrap();
+        assert_eq!(first.key, second.key);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn loading_tightens_a_world_readable_key() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing.key");
+        fs::write(&path, general_purpose::STANDARD.encode([1u8; 32])).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        UrlSigner::load_or_create(&path).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    }
+
+    #[test]
+    fn short_keys_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("signing.key");
+        fs::write(&path, general_purpose::STANDARD.encode([1u8; 8])).unwrap();
+        assert!(UrlSigner::load_or_create(&path).is_err());
+    }
+}
@@ -0,0 +1,532 @@
+//! Takeout: everything the account has in Blink's cloud, copied out before a subscription
+//! ends. A job writes the device, network and notification metadata, walks every page of
+//! the media feed, then downloads each clip with its thumbnail and metadata into a local
+//! folder or the configured archive store, and finishes with `manifest.json` listing what
+//! was saved and what could not be fetched. Jobs are checkpointed to disk after every step,
+//! so a restart carries on where it stopped. Requests to Blink are spaced
+//! `REQUEST_GAP_MS` apart, and a rate-limited request waits as long as Blink asks before
+//! it is tried again.
+
+use crate::blink::{self, BlinkClient, BlinkError, MediaItem};
+use crate::clip_store::{self, ClipStore, ClipStoreConfig};
+use crate::export;
+use crate::settings::Settings;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, Notify};
+
+pub const TAKEOUT_FILE: &str = "takeout.json";
+/// Shortest gap between two requests to Blink; a takeout is never in a hurry.
+const REQUEST_GAP_MS: u64 = 1000;
+/// Wait after a rate limit that didn't say how long.
+const RATE_LIMIT_BACKOFF_SECS: u64 = 60;
+const MAX_RATE_LIMIT_BACKOFF_SECS: u64 = 15 * 60;
+/// Rate limits in a row on one request before it is recorded as failed.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+/// Stops the feed walk on an account that somehow never returns an empty page.
+const MAX_PAGES: i64 = 10_000;
+/// Finished jobs kept for the status view.
+const KEEP_FINISHED: usize = 5;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TakeoutState {
+    Running,
+    Done,
+    Cancelled,
+    /// The destination could not be written at all; see `last_error`.
+    Failed,
+}
+
+/// What a running job is doing; jobs go through these in order.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TakeoutPhase {
+    Metadata,
+    Listing,
+    Media,
+    Manifest,
+}
+
+/// Something the job could not fetch or write.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TakeoutFailure {
+    /// The clip it belongs to; `None` for account metadata.
+    pub media_id: Option<i64>,
+    /// `clip`, `thumbnail`, `clip_metadata`, or the metadata file's key.
+    pub what: String,
+    pub error: String,
+}
+
+/// One clip as saved, for the manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TakeoutFile {
+    pub media_id: i64,
+    pub clip: Option<String>,
+    pub thumbnail: Option<String>,
+    pub bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct TakeoutJob {
+    id: String,
+    created_at: DateTime<Utc>,
+    state: TakeoutState,
+    phase: TakeoutPhase,
+    store: ClipStoreConfig,
+    /// Prepended to every key, so a takeout into the archive store gets its own folder.
+    prefix: String,
+    next_page: i64,
+    /// The feed as listed, kept until the job finishes.
+    items: Vec<MediaItem>,
+    total: usize,
+    done: usize,
+    bytes: u64,
+    files: Vec<TakeoutFile>,
+    failures: Vec<TakeoutFailure>,
+    manifest: Option<String>,
+    last_error: Option<String>,
+    finished_at: Option<DateTime<Utc>>,
+}
+
+/// Where a job stands.
+#[derive(Debug, Clone, Serialize)]
+pub struct TakeoutProgress {
+    pub job_id: String,
+    pub state: TakeoutState,
+    pub phase: TakeoutPhase,
+    /// Clips found in the feed; grows while the job is still listing.
+    pub total: usize,
+    /// Clips attempted so far, failed ones included.
+    pub done: usize,
+    pub bytes: u64,
+    pub failed: usize,
+    /// The takeout's folder: a path or a URL, depending on the store.
+    pub location: String,
+    /// Where `manifest.json` was written, once it has been.
+    pub manifest: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// The last file a job writes.
+#[derive(Debug, Serialize)]
+struct TakeoutManifest<'a> {
+    job_id: &'a str,
+    created_at: DateTime<Utc>,
+    finished_at: DateTime<Utc>,
+    total: usize,
+    saved: usize,
+    bytes: u64,
+    files: &'a [TakeoutFile],
+    failures: &'a [TakeoutFailure],
+}
+
+impl TakeoutJob {
+    fn key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    fn progress(&self) -> TakeoutProgress {
+        let location = self.store.open()
+            .map(|store| store.location(self.prefix.trim_end_matches('/')))
+            .unwrap_or_default();
+        TakeoutProgress {
+            job_id: self.id.clone(),
+            state: self.state,
+            phase: self.phase,
+            total: self.total,
+            done: self.done,
+            bytes: self.bytes,
+            failed: self.failures.len(),
+            location,
+            manifest: self.manifest.clone(),
+            created_at: self.created_at,
+            finished_at: self.finished_at,
+            last_error: self.last_error.clone(),
+        }
+    }
+
+    fn finish(&mut self, state: TakeoutState) {
+        self.state = state;
+        self.finished_at = Some(Utc::now());
+        // The manifest has the full lists; the checkpoint only needs the totals.
+        self.items = Vec::new();
+        self.files = Vec::new();
+    }
+
+    fn fail(&mut self, media_id: Option<i64>, what: &str, error: impl std::fmt::Display) {
+        self.failures.push(TakeoutFailure { media_id, what: what.to_string(), error: error.to_string() });
+    }
+}
+
+/// Keeps requests to Blink at least `REQUEST_GAP_MS` apart.
+#[derive(Default)]
+struct Pacer {
+    last: Option<Instant>,
+}
+
+impl Pacer {
+    async fn wait(&mut self) {
+        if let Some(last) = self.last {
+            let gap = Duration::from_millis(REQUEST_GAP_MS);
+            let elapsed = last.elapsed();
+            if elapsed < gap {
+                tokio::time::sleep(gap - elapsed).await;
+            }
+        }
+        self.last = Some(Instant::now());
+    }
+
+    /// Runs `call` when the gap allows, waiting out and retrying rate limits.
+    async fn call<T, F, Fut>(&mut self, mut call: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut retries = 0;
+        loop {
+            self.wait().await;
+            let result = call().await;
+            let retry_after = match &result {
+                Err(e) if retries < MAX_RATE_LIMIT_RETRIES => match e.downcast_ref::<BlinkError>() {
+                    Some(BlinkError::RateLimited { retry_after_secs, .. }) => *retry_after_secs,
+                    _ => return result,
+                },
+                _ => return result,
+            };
+            let wait = retry_after.unwrap_or(RATE_LIMIT_BACKOFF_SECS).clamp(1, MAX_RATE_LIMIT_BACKOFF_SECS);
+            crate::log_control::warn("blink", || format!("Takeout rate-limited by Blink; waiting {}s", wait));
+            tokio::time::sleep(Duration::from_secs(wait)).await;
+            retries += 1;
+        }
+    }
+}
+
+/// Streams one clip into `store`, turning an error status into a `BlinkError` so a rate
+/// limit can be told apart from a missing clip.
+async fn download_clip(blink_client: &Arc<Mutex<BlinkClient>>, media_path: &str, store: &dyn ClipStore, key: &str) -> Result<u64> {
+    let res = export::fetch_clip(blink_client, media_path).await.map_err(|e| anyhow!(e))?;
+    let status = res.status();
+    if !status.is_success() {
+        let retry_after = blink::retry_after_header(res.headers());
+        let body = res.text().await.unwrap_or_default();
+        return Err(BlinkError::from_response(status, &body, "Clip download failed", retry_after).into());
+    }
+    let len = res.content_length();
+    let stream = res.bytes_stream().map(|chunk| chunk.map_err(std::io::Error::other)).boxed();
+    store.put(key, stream, len).await
+}
+
+fn json_stream(value: &impl Serialize) -> Result<clip_store::ClipStream> {
+    Ok(clip_store::bytes_stream(serde_json::to_vec_pretty(value)?))
+}
+
+fn thumbnail_extension(content_type: &str) -> &'static str {
+    match content_type {
+        "image/jpeg" => "jpg",
+        "image/png" => "png",
+        "image/webp" => "webp",
+        "image/gif" => "gif",
+        _ => "bin",
+    }
+}
+
+/// Runs takeout jobs one at a time, oldest first.
+pub struct Takeouts {
+    path: PathBuf,
+    /// Local takeouts go in a folder per job under here.
+    root: PathBuf,
+    jobs: std::sync::Mutex<Vec<TakeoutJob>>,
+    wake: Notify,
+}
+
+impl Takeouts {
+    /// Reads the jobs left by a previous run; running ones pick up from their checkpoint.
+    pub fn load(path: PathBuf, root: PathBuf) -> Self {
+        let jobs = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self { path, root, jobs: std::sync::Mutex::new(jobs), wake: Notify::new() }
+    }
+
+    fn save(&self, jobs: &[TakeoutJob]) {
+        let result = serde_json::to_vec(jobs)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&self.path, json));
+        if let Err(e) = result {
+            eprintln!("Failed to save takeout checkpoint: {}", e);
+        }
+    }
+
+    /// Starts a takeout into `store` (under `takeout/<job id>/`), or into a new folder
+    /// under the takeout root when `None`. Only one runs at a time, so this fails while
+    /// another is running.
+    pub fn start(&self, store: Option<ClipStoreConfig>) -> Result<TakeoutProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(running) = jobs.iter().find(|j| j.state == TakeoutState::Running) {
+            return Err(anyhow!("Takeout {} is still running", running.id));
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let (store, prefix) = match store {
+            Some(store) => (store, format!("takeout/{}/", id)),
+            None => (ClipStoreConfig::Local { dir: self.root.join(&id).to_string_lossy().into_owned() }, String::new()),
+        };
+        store.open()?;
+        let job = TakeoutJob {
+            id,
+            created_at: Utc::now(),
+            state: TakeoutState::Running,
+            phase: TakeoutPhase::Metadata,
+            store,
+            prefix,
+            next_page: 1,
+            items: Vec::new(),
+            total: 0,
+            done: 0,
+            bytes: 0,
+            files: Vec::new(),
+            failures: Vec::new(),
+            manifest: None,
+            last_error: None,
+            finished_at: None,
+        };
+        let progress = job.progress();
+        jobs.push(job);
+        self.save(&jobs);
+        drop(jobs);
+        self.wake.notify_one();
+        Ok(progress)
+    }
+
+    /// The id of the job currently running, if any.
+    pub fn running_id(&self) -> Option<String> {
+        self.jobs.lock().unwrap().iter().find(|j| j.state == TakeoutState::Running).map(|j| j.id.clone())
+    }
+
+    pub fn get(&self, job_id: &str) -> Option<TakeoutProgress> {
+        self.jobs.lock().unwrap().iter().find(|j| j.id == job_id).map(TakeoutJob::progress)
+    }
+
+    /// Stops a running job after the step in flight; what it saved stays where it is.
+    pub fn cancel(&self, job_id: &str) -> Option<TakeoutProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|j| j.id == job_id && j.state == TakeoutState::Running)?;
+        job.finish(TakeoutState::Cancelled);
+        let progress = job.progress();
+        self.save(&jobs);
+        Some(progress)
+    }
+
+    fn running(&self) -> Option<TakeoutJob> {
+        self.jobs.lock().unwrap().iter().find(|j| j.state == TakeoutState::Running).cloned()
+    }
+
+    /// Stores the job after a step, unless it was cancelled meanwhile, and prunes old
+    /// finished jobs.
+    fn checkpoint(&self, job: TakeoutJob) -> Option<TakeoutProgress> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let slot = jobs.iter_mut().find(|j| j.id == job.id)?;
+        if slot.state != TakeoutState::Running {
+            return None;
+        }
+        *slot = job;
+        let progress = slot.progress();
+        let finished: Vec<String> = jobs.iter().filter(|j| j.state != TakeoutState::Running).map(|j| j.id.clone()).collect();
+        let stale = &finished[..finished.len().saturating_sub(KEEP_FINISHED)];
+        jobs.retain(|j| !stale.contains(&j.id));
+        self.save(&jobs);
+        Some(progress)
+    }
+
+    /// Works through takeouts for as long as the app runs, calling `on_progress` after
+    /// every step.
+    pub async fn run(
+        &self,
+        blink_client: &Arc<Mutex<BlinkClient>>,
+        settings: impl Fn() -> Settings,
+        on_progress: impl Fn(&TakeoutProgress),
+    ) {
+        let mut pacer = Pacer::default();
+        loop {
+            let Some(mut job) = self.running() else {
+                self.wake.notified().await;
+                continue;
+            };
+            match job.store.open() {
+                Ok(store) => step(&mut job, blink_client, store.as_ref(), &settings(), &mut pacer).await,
+                Err(e) => {
+                    job.last_error = Some(format!("Takeout destination unavailable: {}", e));
+                    job.finish(TakeoutState::Failed);
+                }
+            }
+            if let Some(progress) = self.checkpoint(job) {
+                on_progress(&progress);
+            }
+        }
+    }
+}
+
+/// Does one unit of work: all metadata, one feed page, one clip, or the manifest.
+async fn step(job: &mut TakeoutJob, blink_client: &Arc<Mutex<BlinkClient>>, store: &dyn ClipStore, settings: &Settings, pacer: &mut Pacer) {
+    match job.phase {
+        TakeoutPhase::Metadata => {
+            write_metadata(job, blink_client, store, settings, pacer).await;
+            job.phase = TakeoutPhase::Listing;
+        }
+        TakeoutPhase::Listing => {
+            let page = job.next_page;
+            let client = crate::shared::snapshot(blink_client).await;
+            match pacer.call(|| client.get_media_page(page, blink::max_since_days())).await {
+                Ok(items) if !items.is_empty() && page < MAX_PAGES => {
+                    let known: HashSet<i64> = job.items.iter().map(|i| i.id).collect();
+                    job.items.extend(items.into_iter().filter(|i| !i.deleted && !known.contains(&i.id)));
+                    job.total = job.items.len();
+                    job.next_page += 1;
+                }
+                Ok(_) => job.phase = TakeoutPhase::Media,
+                Err(e) => {
+                    // Better a takeout of what was listed than none at all.
+                    job.fail(None, &format!("media page {}", page), format!("{:#}; clips on later pages were not listed", e));
+                    job.phase = TakeoutPhase::Media;
+                }
+            }
+        }
+        TakeoutPhase::Media => match job.items.get(job.done).cloned() {
+            Some(item) => {
+                save_item(job, &item, blink_client, store, pacer).await;
+                job.done += 1;
+            }
+            None => job.phase = TakeoutPhase::Manifest,
+        },
+        TakeoutPhase::Manifest => {
+            let finished_at = Utc::now();
+            let manifest = TakeoutManifest {
+                job_id: &job.id,
+                created_at: job.created_at,
+                finished_at,
+                total: job.total,
+                saved: job.files.iter().filter(|f| f.clip.is_some()).count(),
+                bytes: job.bytes,
+                files: &job.files,
+                failures: &job.failures,
+            };
+            let key = job.key("manifest.json");
+            let written = match json_stream(&manifest) {
+                Ok(stream) => store.put(&key, stream, None).await,
+                Err(e) => Err(e),
+            };
+            match written {
+                Ok(_) => {
+                    job.manifest = Some(store.location(&key));
+                    job.finish(TakeoutState::Done);
+                }
+                Err(e) => {
+                    job.last_error = Some(format!("Failed to write the manifest: {:#}", e));
+                    job.finish(TakeoutState::Failed);
+                }
+            }
+        }
+    }
+}
+
+/// Writes the homescreen, notification config, each network's settings, each camera's
+/// config and the app's own settings under `metadata/`. Each one that fails is recorded
+/// and the rest still written.
+async fn write_metadata(job: &mut TakeoutJob, blink_client: &Arc<Mutex<BlinkClient>>, store: &dyn ClipStore, settings: &Settings, pacer: &mut Pacer) {
+    let client = crate::shared::snapshot(blink_client).await;
+    let mut files: Vec<(String, Result<serde_json::Value>)> = Vec::new();
+
+    let homescreen = pacer.call(|| client.get_homescreen()).await;
+    files.push(("metadata/homescreen.json".to_string(), pacer.call(|| async { Ok((*client.get_homescreen_json().await?).clone()) }).await));
+    files.push(("metadata/notifications.json".to_string(), pacer.call(|| client.get_notification_config()).await.and_then(|c| Ok(serde_json::to_value(c)?))));
+    files.push(("metadata/settings.json".to_string(), serde_json::to_value(settings).map_err(Into::into)));
+    match homescreen {
+        Ok(homescreen) => {
+            for network in &homescreen.networks {
+                let result = pacer.call(|| client.get_network_settings(network.id)).await.and_then(|s| Ok(serde_json::to_value(s)?));
+                files.push((format!("metadata/networks/{}.json", network.id), result));
+            }
+            let cameras = homescreen.cameras.iter().chain(&homescreen.owls).chain(&homescreen.doorbells);
+            for camera in cameras {
+                let Some(network_id) = camera.network_id else { continue };
+                let result = pacer.call(|| client.get_camera_config(network_id, camera.id, &camera.product_type)).await;
+                files.push((format!("metadata/cameras/{}.json", camera.id), result));
+            }
+        }
+        Err(e) => job.fail(None, "metadata/networks", format!("{:#}; network and camera settings were not saved", e)),
+    }
+
+    for (key, value) in files {
+        let written = match value.and_then(|v| json_stream(&v)) {
+            Ok(stream) => store.put(&job.key(&key), stream, None).await,
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(bytes) => job.bytes += bytes,
+            Err(e) => job.fail(None, &key, format!("{:#}", e)),
+        }
+    }
+}
+
+/// Saves one clip, its thumbnail and its metadata under `clips/<camera>/` and
+/// `thumbnails/<camera>/`. Keys include the clip id, so a step repeated after a restart
+/// overwrites what it wrote before.
+async fn save_item(job: &mut TakeoutJob, item: &MediaItem, blink_client: &Arc<Mutex<BlinkClient>>, store: &dyn ClipStore, pacer: &mut Pacer) {
+    let camera = export::file_stem(item, "{camera}");
+    let stem = format!("{}_{}", export::file_stem(item, "{date}_{time}"), item.id);
+    let mut file = TakeoutFile { media_id: item.id, clip: None, thumbnail: None, bytes: 0 };
+
+    match item.media.as_deref().filter(|p| !p.is_empty()) {
+        Some(media_path) => {
+            let key = job.key(&format!("clips/{}/{}.mp4", camera, stem));
+            match pacer.call(|| download_clip(blink_client, media_path, store, &key)).await {
+                Ok(bytes) => {
+                    file.bytes += bytes;
+                    file.clip = Some(key);
+                }
+                Err(e) => job.fail(Some(item.id), "clip", format!("{:#}", e)),
+            }
+        }
+        None => job.fail(Some(item.id), "clip", "Item has no media URL"),
+    }
+
+    if let Some(path) = item.thumbnail.as_deref().filter(|p| !p.is_empty()) {
+        let client = crate::shared::snapshot(blink_client).await;
+        match pacer.call(|| client.get_thumbnail(path)).await {
+            Ok(thumbnail) => {
+                let key = job.key(&format!("thumbnails/{}/{}.{}", camera, stem, thumbnail_extension(&thumbnail.content_type)));
+                match store.put(&key, clip_store::bytes_stream(thumbnail.bytes), None).await {
+                    Ok(bytes) => {
+                        file.bytes += bytes;
+                        file.thumbnail = Some(key);
+                    }
+                    Err(e) => job.fail(Some(item.id), "thumbnail", format!("{:#}", e)),
+                }
+            }
+            Err(e) => job.fail(Some(item.id), "thumbnail", format!("{:#}", e)),
+        }
+    }
+
+    let key = job.key(&format!("clips/{}/{}.json", camera, stem));
+    let written = match json_stream(item) {
+        Ok(stream) => store.put(&key, stream, None).await,
+        Err(e) => Err(e),
+    };
+    match written {
+        Ok(bytes) => file.bytes += bytes,
+        Err(e) => job.fail(Some(item.id), "clip_metadata", format!("{:#}", e)),
+    }
+
+    job.bytes += file.bytes;
+    job.files.push(file);
+}
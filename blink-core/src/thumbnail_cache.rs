@@ -0,0 +1,246 @@
+//! Stale-while-revalidate for thumbnails. When Blink throttles us or the network drops, a
+//! thumbnail served minutes ago beats a blank grid tile: a failed fetch falls back to the
+//! last copy of the same image, marked stale, and a background fetch tries again later.
+//! The hosted server keeps its copies in `ThumbnailMemory`; the desktop app has a disk
+//! cache of its own and shares only the status and revalidation bookkeeping from here.
+//! Both warm their copies for a whole grid at once through a prefetch of the visible paths.
+
+use crate::blink::{BlinkError, Thumbnail};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Response header with the `CacheStatus` of a thumbnail.
+pub const CACHE_HEADER: &str = "x-blink-cache";
+/// Response header set to `true` on a stale thumbnail.
+pub const STALE_HEADER: &str = "x-blink-stale";
+/// Memory the hosted server spends on thumbnail copies; least recently used go first.
+pub const MAX_MEMORY_BYTES: usize = 32 * 1024 * 1024;
+/// Wait before revalidating when Blink gave no `Retry-After`.
+const REVALIDATE_DELAY_SECS: u64 = 30;
+const MAX_REVALIDATE_DELAY_SECS: u64 = 15 * 60;
+/// Most paths one prefetch takes; a grid only asks for the tiles on screen.
+pub const MAX_PREFETCH_PATHS: usize = 64;
+/// Thumbnails the hosted server fetches at once while prefetching. The desktop app goes
+/// through its fetch permits instead.
+pub const PREFETCH_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheStatus {
+    /// Served from a cached copy that was still fresh.
+    Hit,
+    /// Fetched from Blink just now.
+    Fresh,
+    /// Blink could not be reached; this is the last copy we had.
+    Stale,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Fresh => "fresh",
+            CacheStatus::Stale => "stale",
+        }
+    }
+}
+
+/// How one path of a prefetch went.
+#[derive(Debug, Clone, Serialize)]
+pub struct PrefetchResult {
+    pub path: String,
+    /// `None` when the fetch failed and there was no copy to fall back on.
+    pub cache: Option<CacheStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The paths of a prefetch request without blanks and repeats, in the order asked for.
+pub fn prefetch_paths(paths: Vec<String>) -> Result<Vec<String>> {
+    let mut seen = HashSet::new();
+    let paths: Vec<String> = paths.into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty() && seen.insert(p.clone()))
+        .collect();
+    if paths.len() > MAX_PREFETCH_PATHS {
+        return Err(anyhow!("At most {} thumbnails can be prefetched at once", MAX_PREFETCH_PATHS));
+    }
+    Ok(paths)
+}
+
+/// Blink appends `?ts=` to a camera's thumbnail path; the part before it identifies the
+/// image, so an older `ts` can stand in for a newer one.
+pub fn base_path(path: &str) -> &str {
+    path.split('?').next().unwrap_or(path)
+}
+
+/// How long to wait before fetching a thumbnail again after `error`: Blink's `Retry-After`
+/// when it rate limited us, capped, and `REVALIDATE_DELAY_SECS` otherwise.
+pub fn revalidate_delay(error: &anyhow::Error) -> Duration {
+    let secs = match error.downcast_ref::<BlinkError>() {
+        Some(BlinkError::RateLimited { retry_after_secs: Some(secs), .. }) => (*secs).min(MAX_REVALIDATE_DELAY_SECS),
+        _ => REVALIDATE_DELAY_SECS,
+    };
+    Duration::from_secs(secs)
+}
+
+/// Paths with a revalidation pending, so a grid full of failing tiles schedules one fetch
+/// per image rather than one per render.
+#[derive(Default)]
+pub struct Revalidations {
+    pending: Mutex<HashSet<String>>,
+}
+
+impl Revalidations {
+    /// Claims the revalidation of `path`; `false` when one is already pending.
+    pub fn begin(&self, path: &str) -> bool {
+        self.pending.lock().unwrap().insert(path.to_string())
+    }
+
+    pub fn end(&self, path: &str) {
+        self.pending.lock().unwrap().remove(path);
+    }
+}
+
+struct Entry {
+    thumbnail: Thumbnail,
+    stored: u64,
+    used: u64,
+}
+
+#[derive(Default)]
+struct MemoryInner {
+    entries: HashMap<String, Entry>,
+    bytes: usize,
+    clock: u64,
+}
+
+/// The hosted server's copies of the thumbnails it served, kept only to fall back on.
+pub struct ThumbnailMemory {
+    inner: Mutex<MemoryInner>,
+    max_bytes: usize,
+    pub revalidations: Revalidations,
+}
+
+impl ThumbnailMemory {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { inner: Mutex::default(), max_bytes, revalidations: Revalidations::default() }
+    }
+
+    pub fn store(&self, path: &str, thumbnail: &Thumbnail) {
+        if thumbnail.bytes.len() > self.max_bytes {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let entry = Entry { thumbnail: thumbnail.clone(), stored: clock, used: clock };
+        inner.bytes += entry.thumbnail.bytes.len();
+        if let Some(old) = inner.entries.insert(path.to_string(), entry) {
+            inner.bytes -= old.thumbnail.bytes.len();
+        }
+        while inner.bytes > self.max_bytes {
+            let Some(oldest) = inner.entries.iter().min_by_key(|(_, e)| e.used).map(|(key, _)| key.clone()) else { break };
+            if let Some(old) = inner.entries.remove(&oldest) {
+                inner.bytes -= old.thumbnail.bytes.len();
+            }
+        }
+    }
+
+    /// The copy of `path`, or failing that the newest copy of the same image under another
+    /// `ts`.
+    pub fn stale(&self, path: &str) -> Option<Thumbnail> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let base = base_path(path);
+        let key = if inner.entries.contains_key(path) {
+            path.to_string()
+        } else {
+            inner.entries.iter()
+                .filter(|(key, _)| base_path(key) == base)
+                .max_by_key(|(_, e)| e.stored)
+                .map(|(key, _)| key.clone())?
+        };
+        let entry = inner.entries.get_mut(&key)?;
+        entry.used = clock;
+        Some(entry.thumbnail.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn thumbnail(bytes: &[u8]) -> Thumbnail {
+        Thumbnail { bytes: bytes.to_vec(), content_type: "image/jpeg".to_string() }
+    }
+
+    #[test]
+    fn a_failed_fetch_falls_back_to_the_newest_copy_of_the_same_image() {
+        let memory = ThumbnailMemory::new(MAX_MEMORY_BYTES);
+        assert!(memory.stale("/media/cam/thumb?ts=1").is_none());
+
+        memory.store("/media/cam/thumb?ts=1", &thumbnail(b"first"));
+        memory.store("/media/cam/thumb?ts=2", &thumbnail(b"second"));
+        memory.store("/media/other/thumb?ts=3", &thumbnail(b"other"));
+
+        assert_eq!(memory.stale("/media/cam/thumb?ts=1").unwrap().bytes, b"first");
+        assert_eq!(memory.stale("/media/cam/thumb?ts=9").unwrap().bytes, b"second");
+        assert_eq!(memory.stale("/media/cam/thumb").unwrap().bytes, b"second");
+        assert!(memory.stale("/media/unknown/thumb?ts=1").is_none());
+    }
+
+    #[test]
+    fn memory_drops_the_least_recently_used_copy_first() {
+        let memory = ThumbnailMemory::new(10);
+        memory.store("a", &thumbnail(b"aaaa"));
+        memory.store("b", &thumbnail(b"bbbb"));
+        // Serving `a` makes `b` the one to go.
+        memory.stale("a");
+        memory.store("c", &thumbnail(b"cccc"));
+        assert!(memory.stale("a").is_some());
+        assert!(memory.stale("b").is_none());
+        assert!(memory.stale("c").is_some());
+
+        // Replacing a copy doesn't count its old bytes twice.
+        memory.store("c", &thumbnail(b"cc"));
+        memory.store("d", &thumbnail(b"dddd"));
+        assert!(memory.stale("a").is_some() && memory.stale("c").is_some() && memory.stale("d").is_some());
+
+        // A thumbnail bigger than the whole budget isn't kept and evicts nothing.
+        memory.store("huge", &thumbnail(&[0; 11]));
+        assert!(memory.stale("huge").is_none());
+        assert!(memory.stale("a").is_some());
+    }
+
+    #[test]
+    fn revalidation_waits_for_retry_after_within_a_cap() {
+        let limited = |secs| anyhow::Error::from(BlinkError::RateLimited { message: "slow down".into(), retry_after_secs: secs });
+        assert_eq!(revalidate_delay(&limited(Some(5))), Duration::from_secs(5));
+        assert_eq!(revalidate_delay(&limited(Some(86_400))), Duration::from_secs(MAX_REVALIDATE_DELAY_SECS));
+        assert_eq!(revalidate_delay(&limited(None)), Duration::from_secs(REVALIDATE_DELAY_SECS));
+        assert_eq!(revalidate_delay(&anyhow!("connection refused")), Duration::from_secs(REVALIDATE_DELAY_SECS));
+    }
+
+    #[test]
+    fn one_revalidation_is_pending_per_path() {
+        let revalidations = Revalidations::default();
+        assert!(revalidations.begin("/a"));
+        assert!(!revalidations.begin("/a"));
+        assert!(revalidations.begin("/b"));
+        revalidations.end("/a");
+        assert!(revalidations.begin("/a"));
+    }
+
+    #[test]
+    fn prefetch_paths_are_trimmed_deduplicated_and_capped() {
+        let paths = prefetch_paths(vec![" /a ".into(), "".into(), "/b".into(), "/a".into()]).unwrap();
+        assert_eq!(paths, ["/a", "/b"]);
+        let too_many = (0..=MAX_PREFETCH_PATHS).map(|i| format!("/{}", i)).collect();
+        assert!(prefetch_paths(too_many).is_err());
+    }
+}
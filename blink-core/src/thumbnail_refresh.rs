@@ -0,0 +1,237 @@
+//! Scheduled thumbnail refreshes. Blink only takes a new thumbnail on motion or when asked,
+//! so a quiet camera's grid tile can be days old. Cameras with an interval in their local
+//! preferences are asked for a snapshot on that cadence, never more often than the global
+//! minimum, and optionally only inside a time window. Battery cameras running low, snoozed
+//! cameras and cameras on disarmed networks can be left alone. Each request is written to
+//! the audit log; failures back off per camera.
+
+use crate::audit;
+use crate::blink::{BlinkClient, Camera};
+use crate::settings::Settings;
+use crate::shared;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use tokio::sync::Mutex;
+
+pub const TICK_SECS: u64 = 60;
+/// No camera is refreshed more often than this, whatever its preferences say.
+pub const MIN_INTERVAL_MINUTES: u32 = 15;
+const FIRST_RETRY_SECS: i64 = 5 * 60;
+const MAX_RETRY_SECS: i64 = 6 * 60 * 60;
+
+/// Local time span the scheduler may run in, `HH:MM`. A window whose end is before its
+/// start runs over midnight, e.g. `22:00`-`06:00`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RefreshWindow {
+    pub start: String,
+    pub end: String,
+}
+
+fn parse_time(time: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").map_err(|_| anyhow!("Invalid refresh window time '{}', expected HH:MM", time))
+}
+
+impl RefreshWindow {
+    pub fn validate(&self) -> Result<()> {
+        parse_time(&self.start)?;
+        parse_time(&self.end)?;
+        Ok(())
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        let (Ok(start), Ok(end)) = (parse_time(&self.start), parse_time(&self.end)) else {
+            return false;
+        };
+        if start <= end {
+            start <= time && time < end
+        } else {
+            time >= start || time < end
+        }
+    }
+}
+
+/// Global knobs; which cameras take part and how often is set per camera in
+/// `CameraPrefs::thumbnail_refresh_minutes`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailRefreshSettings {
+    pub enabled: bool,
+    /// Floor for every camera's interval; raised to `MIN_INTERVAL_MINUTES` if lower.
+    pub min_interval_minutes: u32,
+    /// Only refresh inside this window; any time when unset.
+    pub window: Option<RefreshWindow>,
+    /// Battery cameras whose battery signal is below this, or that Blink reports as low,
+    /// are skipped.
+    pub min_battery_signal: i64,
+    pub skip_snoozed: bool,
+    pub skip_disarmed: bool,
+}
+
+impl Default for ThumbnailRefreshSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_interval_minutes: 60,
+            window: None,
+            min_battery_signal: 2,
+            skip_snoozed: true,
+            skip_disarmed: false,
+        }
+    }
+}
+
+impl ThumbnailRefreshSettings {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(window) = &self.window {
+            window.validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// One scheduled refresh request.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThumbnailRefreshRun {
+    pub camera_id: i64,
+    pub network_id: i64,
+    pub camera_name: String,
+    /// The camera's thumbnail path; Blink keeps it across refreshes, so caches keyed on it
+    /// must drop their copy.
+    pub thumbnail: String,
+    pub requested_at: DateTime<Utc>,
+    /// Set when the request failed; the camera is tried again after a backoff.
+    pub error: Option<String>,
+}
+
+impl std::fmt::Display for ThumbnailRefreshRun {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.error {
+            None => write!(f, "Requested a new thumbnail from {}", self.camera_name),
+            Some(e) => write!(f, "Thumbnail refresh for {} failed, will retry: {}", self.camera_name, e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct CameraState {
+    last_run: Option<DateTime<Utc>>,
+    failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+}
+
+impl CameraState {
+    fn is_due(&self, interval: Duration, now: DateTime<Utc>) -> bool {
+        match self.retry_at {
+            Some(at) => at <= now,
+            None => self.last_run.is_none_or(|last| last + interval <= now),
+        }
+    }
+}
+
+/// Why a due camera was left alone, if it was.
+fn skip_reason(camera: &Camera, armed: &HashMap<i64, bool>, config: &ThumbnailRefreshSettings) -> Option<&'static str> {
+    let signal = camera.signals.as_ref().and_then(|s| s.battery);
+    let low = camera.battery.as_deref().is_some_and(|b| b.eq_ignore_ascii_case("low"));
+    if low || signal.is_some_and(|level| level < config.min_battery_signal) {
+        return Some("battery low");
+    }
+    if config.skip_snoozed && camera.snoozed {
+        return Some("snoozed");
+    }
+    let disarmed = camera.network_id.and_then(|id| armed.get(&id)).is_some_and(|armed| !armed);
+    if config.skip_disarmed && disarmed {
+        return Some("network disarmed");
+    }
+    None
+}
+
+/// Remembers when each camera was last refreshed; nothing is kept across restarts, so
+/// every scheduled camera is refreshed on the first tick after start.
+#[derive(Default)]
+pub struct ThumbnailRefresher {
+    cameras: std::sync::Mutex<HashMap<i64, CameraState>>,
+}
+
+impl ThumbnailRefresher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every due camera for a new thumbnail. Skipped cameras count as refreshed, so
+    /// they are looked at again one interval later rather than on every tick.
+    pub async fn tick(&self, blink_client: &Mutex<BlinkClient>, settings: &Settings) -> Vec<ThumbnailRefreshRun> {
+        let config = &settings.thumbnail_refresh;
+        if !config.enabled {
+            return Vec::new();
+        }
+        if config.window.as_ref().is_some_and(|w| !w.contains(chrono::Local::now().time())) {
+            return Vec::new();
+        }
+        let floor = config.min_interval_minutes.max(MIN_INTERVAL_MINUTES);
+        let now = Utc::now();
+        let due: HashMap<i64, Duration> = {
+            let states = self.cameras.lock().unwrap();
+            settings.camera_prefs.iter()
+                .filter_map(|(id, prefs)| prefs.thumbnail_refresh_minutes.map(|m| (*id, Duration::minutes(i64::from(m.max(floor))))))
+                .filter(|(id, interval)| states.get(id).cloned().unwrap_or_default().is_due(*interval, now))
+                .collect()
+        };
+        if due.is_empty() {
+            return Vec::new();
+        }
+
+        let client = shared::snapshot(blink_client).await;
+        let homescreen = match client.get_homescreen().await {
+            Ok(homescreen) => homescreen,
+            Err(e) => {
+                eprintln!("Thumbnail refresh could not load the homescreen: {}", e);
+                return Vec::new();
+            }
+        };
+        let armed: HashMap<i64, bool> = homescreen.networks.iter().map(|n| (n.id, n.armed)).collect();
+        let mut seen = HashSet::new();
+        let mut runs = Vec::new();
+        for camera in homescreen.cameras.iter().chain(&homescreen.owls).chain(&homescreen.doorbells) {
+            if !due.contains_key(&camera.id) || !seen.insert(camera.id) {
+                continue;
+            }
+            let Some(network_id) = camera.network_id else { continue };
+            if let Some(reason) = skip_reason(camera, &armed, config) {
+                eprintln!("Skipping thumbnail refresh for {}: {}", camera.name, reason);
+                self.cameras.lock().unwrap().insert(camera.id, CameraState { last_run: Some(now), ..CameraState::default() });
+                continue;
+            }
+            let target = format!("camera {} (network {})", camera.id, network_id);
+            let request = audit::track("refresh_thumbnail", target, client.request_thumbnail(network_id, camera.id, &camera.product_type));
+            let result = audit::with_source("thumbnail_refresh".to_string(), request).await;
+            let requested_at = Utc::now();
+            let mut states = self.cameras.lock().unwrap();
+            let state = states.entry(camera.id).or_default();
+            state.last_run = Some(requested_at);
+            let error = match result {
+                Ok(()) => {
+                    state.failures = 0;
+                    state.retry_at = None;
+                    None
+                }
+                Err(e) => {
+                    state.failures += 1;
+                    let backoff = (FIRST_RETRY_SECS << (state.failures - 1).min(10)).min(MAX_RETRY_SECS);
+                    state.retry_at = Some(requested_at + Duration::seconds(backoff));
+                    Some(e.to_string())
+                }
+            };
+            runs.push(ThumbnailRefreshRun {
+                camera_id: camera.id,
+                network_id,
+                camera_name: camera.name.clone(),
+                thumbnail: camera.thumbnail.clone(),
+                requested_at,
+                error,
+            });
+        }
+        runs
+    }
+}
@@ -0,0 +1,191 @@
+//! Groups the flat media feed into motion events: a camera that re-triggers while someone
+//! walks past produces several clips, which read better as one entry.
+
+use crate::blink::{parse_blink_timestamp, MediaItem};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+
+pub const DEFAULT_GAP_SECS: i64 = 120;
+pub const MAX_GAP_SECS: i64 = 24 * 60 * 60;
+pub const DEFAULT_SINCE_DAYS: i64 = 7;
+/// Media pages fetched per timeline request.
+pub const MAX_PAGES: i64 = 20;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineEvent {
+    pub camera_id: Option<i64>,
+    pub camera_name: Option<String>,
+    pub network_id: Option<i64>,
+    /// RFC 3339, UTC; creation time of the first clip.
+    pub start: String,
+    /// RFC 3339, UTC; creation time of the last clip.
+    pub end: String,
+    pub clip_count: usize,
+    /// Member clips, oldest first.
+    pub clips: Vec<MediaItem>,
+}
+
+/// Groups clips into events, newest event first. A clip joins its camera's open event when
+/// it was created less than `gap_secs` after that event's last clip; clips from other
+/// cameras in between don't split it. Deleted clips and clips without a parseable
+/// timestamp are skipped.
+pub fn group_events(items: Vec<MediaItem>, gap_secs: i64) -> Vec<TimelineEvent> {
+    let gap = chrono::Duration::seconds(gap_secs.clamp(0, MAX_GAP_SECS));
+    let mut clips: Vec<(DateTime<Utc>, MediaItem)> = items.into_iter()
+        .filter(|item| !item.deleted)
+        .filter_map(|item| {
+            let created = item.created_at.as_deref().and_then(parse_blink_timestamp)?;
+            Some((created, item))
+        })
+        .collect();
+    clips.sort_by_key(|(created, item)| (*created, item.id));
+
+    let mut groups: Vec<(DateTime<Utc>, DateTime<Utc>, Vec<MediaItem>)> = Vec::new();
+    let mut open: HashMap<Option<i64>, usize> = HashMap::new();
+    for (created, item) in clips {
+        let joined = open.get(&item.device_id)
+            .copied()
+            .filter(|&index| created - groups[index].1 < gap);
+        match joined {
+            Some(index) => {
+                groups[index].1 = created;
+                groups[index].2.push(item);
+            }
+            None => {
+                open.insert(item.device_id, groups.len());
+                groups.push((created, created, vec![item]));
+            }
+        }
+    }
+
+    groups.into_iter()
+        .rev()
+        .map(|(start, end, clips)| {
+            let first = &clips[0];
+            TimelineEvent {
+                camera_id: first.device_id,
+                camera_name: first.device_name.clone(),
+                network_id: first.network_id,
+                start: start.to_rfc3339(),
+                end: end.to_rfc3339(),
+                clip_count: clips.len(),
+                clips,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clip(id: i64, camera: i64, created_at: &str) -> MediaItem {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "created_at": created_at,
+            "device_id": camera,
+            "device_name": format!("Camera {}", camera),
+            "network_id": 1,
+        })).unwrap()
+    }
+
+    fn ids(event: &TimelineEvent) -> Vec<i64> {
+        event.clips.iter().map(|c| c.id).collect()
+    }
+
+    #[test]
+    fn clips_either_side_of_midnight_are_one_event() {
+        let events = group_events(vec![
+            clip(1, 10, "2026-10-14T23:59:30+00:00"),
+            clip(2, 10, "2026-10-15T00:00:45+00:00"),
+        ], DEFAULT_GAP_SECS);
+        assert_eq!(events.len(), 1);
+        assert_eq!(ids(&events[0]), vec![1, 2]);
+        assert_eq!(events[0].start, "2026-10-14T23:59:30+00:00");
+        assert_eq!(events[0].end, "2026-10-15T00:00:45+00:00");
+    }
+
+    #[test]
+    fn local_midnight_in_another_offset_is_compared_in_utc() {
+        // 23:59 in New York and 00:01 the next day in London are hours apart, not minutes.
+        let events = group_events(vec![
+            clip(1, 10, "2026-10-14T23:59:00-04:00"),
+            clip(2, 10, "2026-10-15T00:01:00+01:00"),
+        ], DEFAULT_GAP_SECS);
+        assert_eq!(events.len(), 2);
+        // And the same instant written in two offsets joins up.
+        let events = group_events(vec![
+            clip(1, 10, "2026-10-14T23:59:00-04:00"),
+            clip(2, 10, "2026-10-15T04:00:00+00:00"),
+        ], DEFAULT_GAP_SECS);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].start, "2026-10-15T03:59:00+00:00");
+    }
+
+    #[test]
+    fn interleaved_cameras_keep_their_own_events() {
+        let events = group_events(vec![
+            clip(1, 10, "2026-10-15T12:00:00+00:00"),
+            clip(2, 20, "2026-10-15T12:00:30+00:00"),
+            clip(3, 10, "2026-10-15T12:01:00+00:00"),
+            clip(4, 20, "2026-10-15T12:01:30+00:00"),
+            clip(5, 10, "2026-10-15T12:02:00+00:00"),
+        ], DEFAULT_GAP_SECS);
+        assert_eq!(events.len(), 2);
+        // Newest event first: camera 20's started later.
+        assert_eq!((events[0].camera_id, ids(&events[0])), (Some(20), vec![2, 4]));
+        assert_eq!((events[1].camera_id, ids(&events[1])), (Some(10), vec![1, 3, 5]));
+        assert_eq!(events[1].clip_count, 3);
+        assert_eq!(events[1].camera_name.as_deref(), Some("Camera 10"));
+    }
+
+    #[test]
+    fn a_gap_of_exactly_the_threshold_starts_a_new_event() {
+        let just_under = group_events(vec![
+            clip(1, 10, "2026-10-15T12:00:00+00:00"),
+            clip(2, 10, "2026-10-15T12:01:59+00:00"),
+        ], 120);
+        assert_eq!(just_under.len(), 1);
+
+        let exactly = group_events(vec![
+            clip(1, 10, "2026-10-15T12:00:00+00:00"),
+            clip(2, 10, "2026-10-15T12:02:00+00:00"),
+        ], 120);
+        assert_eq!(exactly.len(), 2);
+        assert_eq!(ids(&exactly[0]), vec![2]);
+    }
+
+    #[test]
+    fn the_gap_is_measured_from_the_last_clip_not_the_first() {
+        // Each clip is 100s after the previous one, so a 120s gap chains all of them even
+        // though the event spans five minutes.
+        let events = group_events((0..4).map(|i| {
+            clip(i, 10, &format!("2026-10-15T12:0{}:{:02}+00:00", (i * 100) / 60, (i * 100) % 60))
+        }).collect(), 120);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].clip_count, 4);
+    }
+
+    #[test]
+    fn deleted_and_undated_clips_are_left_out() {
+        let mut deleted = clip(2, 10, "2026-10-15T12:00:30+00:00");
+        deleted.deleted = true;
+        let mut undated = clip(3, 10, "2026-10-15T12:00:40+00:00");
+        undated.created_at = Some("yesterday-ish".to_string());
+        let events = group_events(vec![clip(1, 10, "2026-10-15T12:00:00+00:00"), deleted, undated], DEFAULT_GAP_SECS);
+        assert_eq!(events.len(), 1);
+        assert_eq!(ids(&events[0]), vec![1]);
+    }
+
+    #[test]
+    fn out_of_range_gaps_are_clamped() {
+        let clips = || vec![
+            clip(1, 10, "2026-10-14T00:00:00+00:00"),
+            clip(2, 10, "2026-10-15T12:00:00+00:00"),
+        ];
+        // A negative gap never joins anything; a huge one is capped at a day.
+        assert_eq!(group_events(clips(), -5).len(), 2);
+        assert_eq!(group_events(clips(), i64::MAX).len(), 2);
+    }
+}
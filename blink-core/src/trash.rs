@@ -0,0 +1,264 @@
+//! Deferred media deletion. Deleted clips are hidden right away but only removed from Blink
+//! once their undo window has passed; until then `undo` brings them back. Pending entries
+//! are kept on disk so a restart neither loses nor resurrects them.
+
+use crate::blink::{BlinkClient, DeleteResult, DeleteStatus, MediaItem};
+use crate::clip_store::{self, ClipStore};
+use crate::export;
+use crate::media_index::MediaIndex;
+use crate::settings::Settings;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, Notify};
+
+pub const TRASH_FILE: &str = "pending_deletions.json";
+pub const MAX_DEFER_SECS: u64 = 10 * 60;
+/// A failed Blink delete is tried again after this long.
+const RETRY_SECS: i64 = 60;
+/// Upper bound on one sleep, so a changed clock is noticed eventually.
+const MAX_SLEEP_SECS: u64 = 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDeletion {
+    pub id: i64,
+    /// The item as the client sent it; handed to `delete_media_verified` when due.
+    pub item: Value,
+    /// RFC 3339; when the real delete runs.
+    pub due_at: String,
+}
+
+impl PendingDeletion {
+    fn due(&self) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(&self.due_at)
+            .map(|t| t.with_timezone(&Utc))
+            .unwrap_or_else(|_| Utc::now())
+    }
+}
+
+pub struct Trash {
+    path: PathBuf,
+    pending: std::sync::Mutex<Vec<PendingDeletion>>,
+    wake: Notify,
+}
+
+impl Trash {
+    /// Reads pending deletions left by a previous run; a missing or unreadable file
+    /// starts empty.
+    pub fn load(path: PathBuf) -> Self {
+        let pending = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            pending: std::sync::Mutex::new(pending),
+            wake: Notify::new(),
+        }
+    }
+
+    fn save(&self, pending: &[PendingDeletion]) {
+        let result = serde_json::to_vec_pretty(pending)
+            .map_err(std::io::Error::from)
+            .and_then(|json| std::fs::write(&self.path, json));
+        if let Err(e) = result {
+            eprintln!("Failed to save pending deletions: {}", e);
+        }
+    }
+
+    /// Hides `items` and schedules their deletion `secs` from now (capped at
+    /// `MAX_DEFER_SECS`). Items without an id are skipped; ids already pending keep their
+    /// original deadline.
+    pub fn defer(&self, items: &[Value], secs: u64) -> Vec<DeleteResult> {
+        let due_at = (Utc::now() + chrono::Duration::seconds(secs.min(MAX_DEFER_SECS) as i64)).to_rfc3339();
+        let mut pending = self.pending.lock().unwrap();
+        let mut results = Vec::new();
+        for item in items {
+            let Some(id) = item.get("id").and_then(|v| v.as_i64()) else { continue };
+            if !pending.iter().any(|p| p.id == id) {
+                pending.push(PendingDeletion { id, item: item.clone(), due_at: due_at.clone() });
+            }
+            results.push(DeleteResult { id, status: DeleteStatus::Pending });
+        }
+        self.save(&pending);
+        drop(pending);
+        self.wake.notify_one();
+        results
+    }
+
+    /// Cancels pending deletions and returns the ids that were restored.
+    pub fn undo(&self, ids: &[i64]) -> Vec<i64> {
+        let mut pending = self.pending.lock().unwrap();
+        let restored: Vec<i64> = pending.iter().filter(|p| ids.contains(&p.id)).map(|p| p.id).collect();
+        if !restored.is_empty() {
+            pending.retain(|p| !ids.contains(&p.id));
+            self.save(&pending);
+        }
+        restored
+    }
+
+    /// Pending deletions, soonest first.
+    pub fn pending(&self) -> Vec<PendingDeletion> {
+        let mut pending = self.pending.lock().unwrap().clone();
+        pending.sort_by_key(|p| p.due());
+        pending
+    }
+
+    pub fn hidden_ids(&self) -> HashSet<i64> {
+        self.pending.lock().unwrap().iter().map(|p| p.id).collect()
+    }
+
+    pub fn retain_visible(&self, items: &mut Vec<MediaItem>) {
+        let hidden = self.hidden_ids();
+        if !hidden.is_empty() {
+            items.retain(|item| !hidden.contains(&item.id));
+        }
+    }
+
+    /// Drops pending items from a raw Blink media page. Pages that don't parse are
+    /// returned untouched.
+    pub fn filter_raw_page(&self, raw: String) -> String {
+        let hidden = self.hidden_ids();
+        if hidden.is_empty() {
+            return raw;
+        }
+        let Ok(mut json) = serde_json::from_str::<Value>(&raw) else {
+            return raw;
+        };
+        let Some(media) = json.get_mut("media").and_then(|m| m.as_array_mut()) else {
+            return raw;
+        };
+        media.retain(|item| item.get("id").and_then(|v| v.as_i64()).is_none_or(|id| !hidden.contains(&id)));
+        serde_json::to_string(&json).unwrap_or(raw)
+    }
+
+    fn take_due(&self, now: DateTime<Utc>) -> Vec<PendingDeletion> {
+        let mut pending = self.pending.lock().unwrap();
+        let (due, rest): (Vec<_>, Vec<_>) = pending.drain(..).partition(|p| p.due() <= now);
+        *pending = rest;
+        if !due.is_empty() {
+            self.save(&pending);
+        }
+        due
+    }
+
+    fn requeue(&self, mut entries: Vec<PendingDeletion>) {
+        let due_at = (Utc::now() + chrono::Duration::seconds(RETRY_SECS)).to_rfc3339();
+        let mut pending = self.pending.lock().unwrap();
+        for entry in entries.iter_mut() {
+            entry.due_at = due_at.clone();
+        }
+        pending.extend(entries);
+        self.save(&pending);
+    }
+
+    /// Performs deletions as they come due, for as long as the app runs. With an archive
+    /// configured (`archive_store` or `archive_dir`), each clip is copied there first.
+    pub async fn run(&self, blink_client: &Arc<Mutex<BlinkClient>>, media_index: &MediaIndex, settings: impl Fn() -> Settings) {
+        loop {
+            let next = self.pending.lock().unwrap().iter().map(PendingDeletion::due).min();
+            let wait = match next {
+                Some(due) => (due - Utc::now()).to_std().unwrap_or_default().min(Duration::from_secs(MAX_SLEEP_SECS)),
+                None => Duration::from_secs(MAX_SLEEP_SECS),
+            };
+            tokio::select! {
+                _ = tokio::time::sleep(wait) => {}
+                _ = self.wake.notified() => continue,
+            }
+
+            let due = self.take_due(Utc::now());
+            if due.is_empty() {
+                continue;
+            }
+            let settings = settings();
+            if let Some(config) = settings.archive_store() {
+                match config.open() {
+                    Ok(store) => archive(blink_client, media_index, &due, store.as_ref(), &settings).await,
+                    Err(e) => eprintln!("Archive unavailable, deleting without a copy: {}", e),
+                }
+            }
+
+            let items: Vec<Value> = due.iter().map(|p| p.item.clone()).collect();
+            let client = crate::shared::snapshot(blink_client).await;
+            match client.delete_media_verified(&items).await {
+                Ok(results) => {
+                    if let Err(e) = media_index.mark_deleted(&results) {
+                        eprintln!("Failed to mark deleted media in index: {}", e);
+                    }
+                    let kept = results.iter().filter(|r| r.status == DeleteStatus::StillPresent).count();
+                    if kept > 0 {
+                        eprintln!("{} clip(s) were still present after a deferred delete", kept);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Deferred delete failed ({}); retrying in {}s", e, RETRY_SECS);
+                    self.requeue(due);
+                }
+            }
+        }
+    }
+}
+
+/// Picks the archive key for `item`: the download filename, or with the clip id added
+/// when that name is taken (by an earlier clip or another one in this batch). Returns
+/// `None` when the id-suffixed name already exists, i.e. an earlier attempt archived this
+/// very clip, so a retried batch doesn't copy it twice.
+async fn archive_key(store: &dyn ClipStore, item: &MediaItem, template: &str, reserved: &mut HashSet<String>) -> anyhow::Result<Option<String>> {
+    let stem = export::file_stem(item, template);
+    let plain = format!("{}.mp4", stem);
+    if !reserved.contains(&plain) && !store.exists(&plain).await? {
+        reserved.insert(plain.clone());
+        return Ok(Some(plain));
+    }
+    let with_id = format!("{}_{}.mp4", stem, item.id);
+    if store.exists(&with_id).await? {
+        return Ok(None);
+    }
+    reserved.insert(with_id.clone());
+    Ok(Some(with_id))
+}
+
+/// Writes the clip's recording time and sidecar: as file metadata on a local store, or
+/// as a `.json` object next to the clip elsewhere.
+async fn archive_metadata(store: &dyn ClipStore, key: &str, item: &MediaItem, sidecar: bool) -> anyhow::Result<()> {
+    if let Some(path) = store.local_path(key) {
+        return Ok(export::apply_clip_metadata(&path, item, sidecar)?);
+    }
+    if sidecar {
+        let json = serde_json::to_vec_pretty(item)?;
+        let sidecar_key = format!("{}.json", key.trim_end_matches(".mp4"));
+        store.put(&sidecar_key, clip_store::bytes_stream(json), None).await?;
+    }
+    Ok(())
+}
+
+/// Copies clips into the archive before they are deleted from Blink. Failures are logged
+/// and don't hold up the delete.
+async fn archive(blink_client: &Arc<Mutex<BlinkClient>>, media_index: &MediaIndex, entries: &[PendingDeletion], store: &dyn ClipStore, settings: &Settings) {
+    let mut reserved = HashSet::new();
+    for entry in entries {
+        let Ok(item) = serde_json::from_value::<MediaItem>(entry.item.clone()) else { continue };
+        let Some(media_path) = item.media.as_deref() else { continue };
+        let result = async {
+            let Some(key) = archive_key(store, &item, &settings.download_filename_template, &mut reserved).await? else {
+                return Ok(None);
+            };
+            export::download_to_store(blink_client, media_path, store, &key).await?;
+            archive_metadata(store, &key, &item, settings.download_sidecar).await?;
+            Ok::<_, anyhow::Error>(Some(store.location(&key)))
+        }.await;
+        match result {
+            Ok(Some(location)) => {
+                if let Err(e) = media_index.set_local_path(media_path, &location) {
+                    eprintln!("Failed to record {} in media index: {}", location, e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Failed to archive clip {} before deleting it: {}", item.id, e),
+        }
+    }
+}
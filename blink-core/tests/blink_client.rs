@@ -0,0 +1,648 @@
+//! `BlinkClient` against a wiremock stand-in for Blink: sign-in, token refresh, homescreen
+//! parsing, media paging, the delete payload fallback, notification settings, how
+//! Blink's error bodies are surfaced and the liveview route fallback.
+
+use blink_core::{device_routes, AuthState, BlinkAuthState, BlinkClient, BlinkError, BlinkUrls, DeviceFamily, LiveviewOptions};
+use chrono::Utc;
+use secrecy::{ExposeSecret, SecretString};
+use serde_json::json;
+use wiremock::matchers::{body_json, body_string_contains, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const SIGNIN_PAGE: &str = include_str!("fixtures/signin_oauth_args.html");
+const CSRF: &str = "csrf-plain-7f3a9c";
+const CALLBACK: &str = "immedia-blink://applinks.blink.com/signin/callback";
+
+/// One mock host answers for OAuth, `tier_info` and every tier.
+fn urls(server: &MockServer) -> BlinkUrls {
+    BlinkUrls { oauth: server.uri(), rest: server.uri(), tier_template: server.uri() }
+}
+
+fn logged_in(server: &MockServer, token: &str, refresh: &str, expiry: i64) -> BlinkClient {
+    let state = json!({
+        "token": token,
+        "refresh_token": refresh,
+        "account_id": 42,
+        "base_url": server.uri(),
+        "device_id": "device",
+        "token_expiry": expiry,
+    });
+    BlinkClient::from_state_with_urls(BlinkAuthState::from_json(&state.to_string(), "test").unwrap(), urls(server))
+}
+
+fn token_of(client: &BlinkClient) -> String {
+    client.get_state().token.unwrap().expose_secret().to_string()
+}
+
+fn tokens(access: &str, refresh: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "access_token": access, "refresh_token": refresh, "expires_in": 3600 }))
+}
+
+/// The OAuth endpoints up to the code redirect, plus the token exchange and `tier_info`.
+async fn mount_oauth(server: &MockServer, code: &str, access: &str) {
+    Mock::given(method("GET"))
+        .and(path("/oauth/v2/authorize"))
+        .respond_with(ResponseTemplate::new(302).insert_header("Location", format!("{}?code={}", CALLBACK, code)))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/oauth/v2/signin"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(SIGNIN_PAGE))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .and(body_string_contains("grant_type=authorization_code"))
+        .and(body_string_contains(format!("code={}", code)))
+        .respond_with(tokens(access, &format!("{}-refresh", access)))
+        .expect(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/users/tier_info"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "tier": "u011", "account_id": 42 })))
+        .mount(server)
+        .await;
+}
+
+async fn mount_homescreen(server: &MockServer, fixture: &str) {
+    let body: serde_json::Value = serde_json::from_str(fixture).unwrap();
+    Mock::given(method("GET"))
+        .and(path("/api/v3/accounts/42/homescreen"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(body))
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn login_happy_path() {
+    let server = MockServer::start().await;
+    mount_oauth(&server, "code-1", "access-1").await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/signin"))
+        .and(body_string_contains("username=user%40example.com"))
+        .and(body_string_contains(format!("csrf-token={}", CSRF)))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut client = BlinkClient::with_urls(urls(&server));
+    client.start_oauth_flow().await.unwrap();
+    let outcome = client.login_oauth("user@example.com", SecretString::from("hunter2")).await.unwrap();
+
+    assert_eq!(outcome, "SUCCESS");
+    assert_eq!(client.auth_state(), AuthState::LoggedIn);
+    let state = client.get_state();
+    assert_eq!(state.token.unwrap().expose_secret(), "access-1");
+    assert_eq!(state.refresh_token.unwrap().expose_secret(), "access-1-refresh");
+    assert_eq!(state.account_id, Some(42));
+    assert_eq!(state.base_url, server.uri());
+    assert!(state.token_expiry.unwrap() > Utc::now().timestamp());
+}
+
+#[tokio::test]
+async fn two_factor_path() {
+    let server = MockServer::start().await;
+    mount_oauth(&server, "code-2fa", "access-2fa").await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/signin"))
+        .respond_with(ResponseTemplate::new(412))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/2fa/verify"))
+        .and(body_string_contains("2fa_code=123456"))
+        .and(body_string_contains(format!("csrf-token={}", CSRF)))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/2fa/verify"))
+        .respond_with(ResponseTemplate::new(400).set_body_string("invalid code"))
+        .mount(&server)
+        .await;
+
+    let mut client = BlinkClient::with_urls(urls(&server));
+    client.start_oauth_flow().await.unwrap();
+    let outcome = client.login_oauth("user@example.com", SecretString::from("hunter2")).await.unwrap();
+    assert_eq!(outcome, "2FA_REQUIRED");
+    assert_eq!(client.auth_state(), AuthState::LoggedOut);
+
+    assert!(client.verify_pin_oauth(SecretString::from("000000")).await.is_err());
+    assert_eq!(client.auth_state(), AuthState::LoggedOut);
+
+    assert_eq!(client.verify_pin_oauth(SecretString::from("123456")).await.unwrap(), "SUCCESS");
+    assert_eq!(token_of(&client), "access-2fa");
+}
+
+#[tokio::test]
+async fn refresh_uses_the_rotated_refresh_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .and(body_string_contains("grant_type=refresh_token"))
+        .and(body_string_contains("refresh_token=refresh-1"))
+        .respond_with(tokens("access-2", "refresh-2"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .and(body_string_contains("refresh_token=refresh-2"))
+        .respond_with(tokens("access-3", "refresh-3"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut client = logged_in(&server, "access-1", "refresh-1", Utc::now().timestamp() - 10);
+    assert!(client.refresh_token_if_needed().await.unwrap());
+    assert_eq!(token_of(&client), "access-2");
+    // A token well inside its lifetime is left alone.
+    assert!(!client.refresh_token_if_needed().await.unwrap());
+
+    // The next session, restored once this token has expired too, sends the rotated one.
+    let mut state = client.get_state();
+    assert_eq!(state.refresh_token.as_ref().unwrap().expose_secret(), "refresh-2");
+    state.token_expiry = Some(Utc::now().timestamp() - 10);
+    let mut restored = BlinkClient::from_state_with_urls(state, urls(&server));
+    assert!(restored.refresh_token_if_needed().await.unwrap());
+    assert_eq!(token_of(&restored), "access-3");
+}
+
+#[tokio::test]
+async fn an_invalid_grant_requires_signing_in_again() {
+    let server = MockServer::start().await;
+    mount_oauth(&server, "code-after-revoke", "access-new").await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .and(body_string_contains("grant_type=refresh_token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({ "error": "invalid_grant", "error_description": "refresh token revoked" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/signin"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let mut client = logged_in(&server, "access-old", "refresh-revoked", Utc::now().timestamp() - 10);
+    let err = client.refresh_token_if_needed().await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::ReauthRequired)), "{:?}", err);
+    assert_eq!(client.auth_state(), AuthState::ReauthRequired);
+
+    // Every later call fails the same way without another trip to the token endpoint.
+    let err = client.refresh_token_if_needed().await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::ReauthRequired)), "{:?}", err);
+    let err = client.get_raw_homescreen().await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::ReauthRequired)), "{:?}", err);
+
+    // The state is saved with the session, so a restart still reports it.
+    let restored = BlinkClient::from_state_with_urls(client.get_state(), urls(&server));
+    assert_eq!(restored.auth_state(), AuthState::ReauthRequired);
+
+    client.start_oauth_flow().await.unwrap();
+    let outcome = client.login_oauth("user@example.com", SecretString::from("pw")).await.unwrap();
+    assert_eq!(outcome, "SUCCESS");
+    assert_eq!(client.auth_state(), AuthState::LoggedIn);
+    assert_eq!(token_of(&client), "access-new");
+}
+
+#[tokio::test]
+async fn other_token_errors_are_not_a_revoked_session() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/token"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({ "error": "invalid_request" })))
+        .mount(&server)
+        .await;
+
+    let mut client = logged_in(&server, "access-old", "refresh-1", Utc::now().timestamp() - 10);
+    let err = client.refresh_token_if_needed().await.unwrap_err();
+    assert!(!matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::ReauthRequired)), "{:?}", err);
+    assert_eq!(client.auth_state(), AuthState::LoggedIn);
+}
+
+#[tokio::test]
+async fn a_401_expires_the_session_until_signing_in_again() {
+    let server = MockServer::start().await;
+    mount_oauth(&server, "code-again", "access-new").await;
+    Mock::given(method("POST"))
+        .and(path("/oauth/v2/signin"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v3/accounts/42/homescreen"))
+        .and(header("Authorization", "Bearer access-old"))
+        .respond_with(ResponseTemplate::new(401).set_body_json(json!({ "message": "Unauthorized Access", "code": 101 })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/v3/accounts/42/homescreen"))
+        .and(header("Authorization", "Bearer access-new"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("fixtures/homescreen_camera.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let mut client = logged_in(&server, "access-old", "refresh-old", Utc::now().timestamp() + 3600);
+    let err = client.get_homescreen().await.unwrap_err();
+    assert!(matches!(err.downcast_ref::<BlinkError>(), Some(BlinkError::AuthExpired)), "{:?}", err);
+
+    client.start_oauth_flow().await.unwrap();
+    assert_eq!(client.login_oauth("user@example.com", SecretString::from("hunter2")).await.unwrap(), "SUCCESS");
+    let homescreen = client.get_homescreen().await.unwrap();
+    assert_eq!(homescreen.networks[0].name, "Home");
+}
+
+#[tokio::test]
+async fn camera_account_homescreen() {
+    let server = MockServer::start().await;
+    mount_homescreen(&server, include_str!("fixtures/homescreen_camera.json")).await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let homescreen = client.get_homescreen().await.unwrap();
+    assert_eq!(homescreen.networks.len(), 1);
+    assert!(homescreen.networks[0].armed);
+    assert_eq!(homescreen.sync_modules[0].id, 5001);
+    let cameras: Vec<_> = homescreen.cameras.iter()
+        .map(|c| (c.id, c.name.as_str(), c.product_type.as_str(), c.device_family))
+        .collect();
+    assert_eq!(cameras, vec![
+        (2001, "Driveway", "catalina", DeviceFamily::Camera),
+        (2002, "Back Yard", "sedona", DeviceFamily::Camera),
+    ]);
+    assert_eq!(homescreen.cameras[0].signals.as_ref().unwrap().wifi, Some(4));
+    assert_eq!(homescreen.cameras[1].battery.as_deref(), Some("low"));
+    assert!(homescreen.cameras[1].snoozed);
+    assert!(!homescreen.cameras[0].snoozed);
+}
+
+#[tokio::test]
+async fn owl_account_homescreen() {
+    let server = MockServer::start().await;
+    mount_homescreen(&server, include_str!("fixtures/homescreen_owl.json")).await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let homescreen = client.get_homescreen().await.unwrap();
+    let cameras: Vec<_> = homescreen.cameras.iter()
+        .map(|c| (c.id, c.product_type.as_str(), c.device_family))
+        .collect();
+    assert_eq!(cameras, vec![(3001, "owl", DeviceFamily::Owl), (3002, "superior", DeviceFamily::Owl)]);
+    assert!(!homescreen.networks[0].armed);
+    assert!(homescreen.cameras.iter().all(|c| c.network_id == Some(1002)));
+}
+
+#[tokio::test]
+async fn doorbell_account_homescreen() {
+    let server = MockServer::start().await;
+    mount_homescreen(&server, include_str!("fixtures/homescreen_doorbell.json")).await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let homescreen = client.get_homescreen().await.unwrap();
+    assert_eq!(homescreen.cameras.len(), 1);
+    let doorbell = &homescreen.cameras[0];
+    assert_eq!((doorbell.id, doorbell.name.as_str()), (4001, "Front Door"));
+    assert_eq!(doorbell.product_type, "lotus");
+    assert_eq!(doorbell.device_family, DeviceFamily::Doorbell);
+    assert_eq!(doorbell.serial.as_deref(), Some("G8T1-9999-0000"));
+}
+
+/// A per-network device listing answering `body`.
+async fn mount_listing(server: &MockServer, listing: &str, status: u16, body: serde_json::Value) {
+    Mock::given(method("GET"))
+        .and(path(listing))
+        .respond_with(ResponseTemplate::new(status).set_body_json(body))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn empty_homescreen_falls_back_to_every_listing_of_every_network() {
+    let server = MockServer::start().await;
+    mount_homescreen(&server, include_str!("fixtures/homescreen_empty_two_networks.json")).await;
+    // Home has a camera, a Mini (listed as a bare array) and a doorbell (wrapped in an object).
+    mount_listing(&server, "/network/1001/cameras", 200, json!({ "dev": [
+        { "id": 2001, "name": "Driveway", "type": "catalina", "status": "done" }
+    ] })).await;
+    mount_listing(&server, "/api/v1/accounts/42/networks/1001/owls", 200, json!([
+        { "id": 3001, "name": "Hallway Mini", "type": "owl", "enabled": true }
+    ])).await;
+    mount_listing(&server, "/api/v1/accounts/42/networks/1001/doorbells", 200, json!({ "doorbells": [
+        { "id": 4001, "name": "Front Door", "enabled": false }
+    ] })).await;
+    // The cabin has only a camera: no Minis, and no doorbell listing at all.
+    mount_listing(&server, "/network/1003/cameras", 200, json!({ "dev": [
+        { "id": 2003, "name": "Porch", "type": "sedona", "status": "offline" }
+    ] })).await;
+    mount_listing(&server, "/api/v1/accounts/42/networks/1003/owls", 200, json!({ "owls": [] })).await;
+    mount_listing(&server, "/api/v1/accounts/42/networks/1003/doorbells", 404, json!({ "message": "Not found" })).await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let homescreen = client.get_homescreen().await.unwrap();
+    let mut devices: Vec<_> = homescreen.cameras.iter()
+        .map(|c| (c.id, c.network_id, c.product_type.as_str(), c.device_family, c.status.as_str()))
+        .collect();
+    devices.sort_by_key(|d| d.0);
+    assert_eq!(devices, vec![
+        (2001, Some(1001), "catalina", DeviceFamily::Camera, "done"),
+        (2003, Some(1003), "sedona", DeviceFamily::Camera, "offline"),
+        (3001, Some(1001), "owl", DeviceFamily::Owl, "online"),
+        (4001, Some(1001), "doorbell", DeviceFamily::Doorbell, "disabled"),
+    ]);
+}
+
+#[tokio::test]
+async fn media_pages_are_walked_until_an_empty_page() {
+    let server = MockServer::start().await;
+    let page = |n: &str, body: &str, hits: u64| {
+        Mock::given(method("GET"))
+            .and(path("/api/v1/accounts/42/media/changed"))
+            .and(query_param("page", n))
+            .respond_with(ResponseTemplate::new(200).set_body_string(body.to_string()))
+            .expect(hits)
+    };
+    page("1", include_str!("fixtures/media_page_1.json"), 1).mount(&server).await;
+    page("2", include_str!("fixtures/media_page_2.json"), 1).mount(&server).await;
+    page("3", r#"{"media":[]}"#, 1).mount(&server).await;
+    page("4", include_str!("fixtures/media_page_1.json"), 0).mount(&server).await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let ids: Vec<_> = client.get_all_media(7, 10).await.unwrap().iter().map(|m| m.id).collect();
+    assert_eq!(ids, vec![7001, 7003], "deleted clips are dropped");
+}
+
+#[tokio::test]
+async fn media_paging_stops_at_max_pages() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/42/media/changed"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("fixtures/media_page_2.json")))
+        .expect(2)
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    assert_eq!(client.get_all_media(7, 2).await.unwrap().len(), 2);
+}
+
+async fn delete_bodies(server: &MockServer) -> Vec<serde_json::Value> {
+    server.received_requests().await.unwrap().iter()
+        .filter(|r| r.url.path() == "/api/v1/accounts/42/media/delete")
+        .map(|r| serde_json::from_slice(&r.body).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn delete_falls_back_through_the_three_payloads() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .and(body_string_contains("media_id"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "message": "Success" })))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .respond_with(ResponseTemplate::new(400).set_body_json(json!({ "message": "Invalid media list" })))
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    client.delete_media(vec![7001, 7003]).await.unwrap();
+    assert_eq!(delete_bodies(&server).await, vec![
+        json!({ "media_list": [7001, 7003] }),
+        json!({ "media_list": [{ "id": 7001 }, { "id": 7003 }] }),
+        json!({ "media_list": [{ "media_id": 7001 }, { "media_id": 7003 }] }),
+    ]);
+}
+
+#[tokio::test]
+async fn delete_stops_at_the_first_accepted_payload() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "message": "Success" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    client.delete_media(vec![7001]).await.unwrap();
+    assert_eq!(delete_bodies(&server).await, vec![json!({ "media_list": [7001] })]);
+}
+
+#[tokio::test]
+async fn delete_reports_the_last_failure_when_every_payload_is_refused() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .respond_with(ResponseTemplate::new(422).set_body_json(json!({ "message": "Unprocessable" })))
+        .expect(3)
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let err = client.delete_media(vec![7001]).await.unwrap_err();
+    assert!(err.to_string().contains("422"), "{}", err);
+}
+
+#[tokio::test]
+async fn notification_config_round_trips_every_field() {
+    let server = MockServer::start().await;
+    let captured: serde_json::Value = serde_json::from_str(include_str!("fixtures/notification_config.json")).unwrap();
+    let mut expected = captured.clone();
+    expected["notifications"]["motion"] = json!(false);
+    Mock::given(method("GET"))
+        .and(path("/api/v1/accounts/42/notifications/configuration"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(captured.clone()))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/notifications/configuration"))
+        .and(body_json(expected))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "message": "Successfully updated notifications" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+    let mut config = client.get_notification_config().await.unwrap();
+    assert_eq!(config.motion, Some(true));
+    assert_eq!(config.temperature, Some(false));
+    // Flags without a typed field, nested objects included, are carried along as-is.
+    assert_eq!(config.other["cv_motion_types"], captured["notifications"]["cv_motion_types"]);
+    assert_eq!(config.other["accessory_low_battery"], json!(true));
+
+    config.motion = Some(false);
+    client.update_notification_config(&config).await.unwrap();
+}
+
+fn error_body(status: u16, fixture: &str) -> ResponseTemplate {
+    ResponseTemplate::new(status).set_body_raw(fixture.as_bytes().to_vec(), "application/json")
+}
+
+fn blink_error(error: anyhow::Error) -> BlinkError {
+    error.downcast::<BlinkError>().unwrap()
+}
+
+#[tokio::test]
+async fn arm_failures_carry_blinks_message_and_code() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/networks/1001/state/arm"))
+        .respond_with(error_body(400, include_str!("fixtures/error_arm_offline.json")))
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/networks/1002/state/arm"))
+        .respond_with(error_body(400, include_str!("fixtures/error_arm_busy.json")))
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    match blink_error(client.set_arm(1001, true).await.unwrap_err()) {
+        BlinkError::Http { status, code, message } => {
+            assert_eq!((status, code), (400, Some(71)));
+            assert_eq!(message, "Arm request failed: 400 Bad Request: Sync module is offline. Please check its connection and try again. (code 71)");
+        }
+        other => panic!("expected an HTTP error, got {:?}", other),
+    }
+    // Blink's busy code wins over the 400.
+    match blink_error(client.set_arm(1002, true).await.unwrap_err()) {
+        BlinkError::CameraBusy { message, .. } => assert_eq!(message, "Arm request failed: 400 Bad Request: System is busy, please wait (code 307)"),
+        other => panic!("expected busy, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn delete_failures_carry_blinks_message_and_code() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .respond_with(error_body(400, include_str!("fixtures/error_delete_not_found.json")))
+        .expect(3)
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    match blink_error(client.delete_media(vec![7001]).await.unwrap_err()) {
+        BlinkError::Http { status, code, message } => {
+            assert_eq!((status, code), (400, Some(1402)));
+            assert_eq!(message, "Delete failed: 400 Bad Request: Media not found (code 1402)");
+        }
+        other => panic!("expected an HTTP error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn an_unauthorized_code_expires_the_session_whatever_the_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v1/accounts/42/media/delete"))
+        .respond_with(error_body(400, include_str!("fixtures/error_unauthorized.json")))
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+
+    let error = blink_error(client.delete_media(vec![7001]).await.unwrap_err());
+    assert!(matches!(error, BlinkError::AuthExpired), "{:?}", error);
+    assert_eq!(error.to_string(), "AUTH_EXPIRED");
+}
+
+#[tokio::test]
+async fn liveview_failures_carry_blinks_message_and_code() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/api/v5/accounts/42/networks/1001/cameras/2001/liveview"))
+        .respond_with(error_body(400, include_str!("fixtures/error_liveview_offline.json")))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/api/v5/accounts/42/networks/1001/cameras/2002/liveview"))
+        .respond_with(error_body(400, include_str!("fixtures/error_liveview_busy.json")).insert_header("retry-after", "15"))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+    let options = LiveviewOptions::default();
+
+    match blink_error(client.request_liveview(1001, 2001, "catalina", &options).await.unwrap_err()) {
+        BlinkError::Http { status, code, message } => {
+            assert_eq!((status, code), (400, Some(2033)));
+            assert_eq!(message, "Liveview API returned: 400 Bad Request: Camera is offline (code 2033)");
+        }
+        other => panic!("expected an HTTP error, got {:?}", other),
+    }
+    // A 400 that says the camera is busy is retryable, after Blink's Retry-After.
+    match blink_error(client.request_liveview(1001, 2002, "catalina", &options).await.unwrap_err()) {
+        BlinkError::CameraBusy { message, retry_after } => {
+            assert_eq!(message, "Liveview API returned: 400 Bad Request: Camera is busy streaming to another device (code 2)");
+            assert_eq!(retry_after, Some(15));
+        }
+        other => panic!("expected busy, got {:?}", other),
+    }
+}
+
+const OWL_LIVEVIEW: &str = "/api/v1/accounts/42/networks/1001/owls/3001/liveview";
+const CAMERA_LIVEVIEW: &str = "/api/v5/accounts/42/networks/1001/cameras/3001/liveview";
+
+/// `first` answers 404 and `then` starts the stream.
+async fn mount_liveview_routes(server: &MockServer, first: &str, then: &str) {
+    Mock::given(method("POST"))
+        .and(path(first))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({ "message": "Not found" })))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(then))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "server": "immis://10.0.0.1:443/stream", "command_id": 77 })))
+        .mount(server)
+        .await;
+}
+
+async fn liveview_paths(server: &MockServer) -> Vec<String> {
+    server.received_requests().await.unwrap().iter().map(|r| r.url.path().to_string()).collect()
+}
+
+#[tokio::test]
+async fn liveview_falls_back_to_the_camera_route_and_remembers_it_until_it_404s() {
+    let dir = std::env::temp_dir().join(format!("blink-device-routes-{}", std::process::id()));
+    device_routes::init(dir.join(device_routes::DEVICE_ROUTES_FILE));
+    let server = MockServer::start().await;
+    let client = logged_in(&server, "token", "refresh", Utc::now().timestamp() + 3600);
+    let options = LiveviewOptions::default();
+
+    // A device reporting an owl type that only answers on the camera route.
+    mount_liveview_routes(&server, OWL_LIVEVIEW, CAMERA_LIVEVIEW).await;
+    let live = client.request_liveview(1001, 3001, "hawk", &options).await.unwrap();
+    assert_eq!(live.command_id, 77);
+    assert_eq!(liveview_paths(&server).await, [OWL_LIVEVIEW, CAMERA_LIVEVIEW]);
+    let learned = device_routes::lookup(3001, "hawk").unwrap();
+    assert_eq!(learned.family, DeviceFamily::Camera);
+    let attempts: Vec<_> = learned.attempts.iter().map(|a| (a.family, a.status)).collect();
+    assert_eq!(attempts, [(DeviceFamily::Owl, Some(404)), (DeviceFamily::Camera, None)]);
+    assert!(std::fs::read_to_string(dir.join(device_routes::DEVICE_ROUTES_FILE)).unwrap().contains("\"camera_id\": 3001"));
+
+    // The remembered route goes first, so the owl route isn't asked again.
+    client.request_liveview(1001, 3001, "hawk", &options).await.unwrap();
+    assert_eq!(liveview_paths(&server).await[2..], [CAMERA_LIVEVIEW]);
+
+    // After the device moves back, the remembered route 404s and is forgotten.
+    server.reset().await;
+    mount_liveview_routes(&server, CAMERA_LIVEVIEW, OWL_LIVEVIEW).await;
+    client.request_liveview(1001, 3001, "hawk", &options).await.unwrap();
+    assert_eq!(liveview_paths(&server).await, [CAMERA_LIVEVIEW, OWL_LIVEVIEW]);
+    assert!(device_routes::lookup(3001, "hawk").is_none());
+
+    client.request_liveview(1001, 3001, "hawk", &options).await.unwrap();
+    assert_eq!(liveview_paths(&server).await[2..], [OWL_LIVEVIEW]);
+    let _ = std::fs::remove_dir_all(&dir);
+}
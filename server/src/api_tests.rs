@@ -0,0 +1,624 @@
+//! The whole router against a wiremock stand-in for Blink: `ServerState` is built the way
+//! `main` builds it, with its files in a temp dir and its `BlinkClient` pointed at the mock.
+
+use super::*;
+use axum::body::Body;
+use blink_core::{BlinkAuthState, BlinkUrls};
+use http::Request;
+use std::sync::OnceLock;
+use tower::ServiceExt;
+use wiremock::matchers::{body_json, body_string_contains, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// `storage` reads the data dir from the environment, so every test shares one temp dir.
+fn data_dir() -> &'static std::path::Path {
+  static DIR: OnceLock<tempfile::TempDir> = OnceLock::new();
+  DIR.get_or_init(|| {
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("BLINK_DATA_DIR", dir.path());
+    dir
+  }).path()
+}
+
+fn urls(server: &MockServer) -> BlinkUrls {
+  BlinkUrls { oauth: server.uri(), rest: server.uri(), tier_template: server.uri() }
+}
+
+fn logged_in(server: &MockServer) -> BlinkClient {
+  let state = serde_json::json!({
+    "token": "token",
+    "refresh_token": "refresh",
+    "account_id": 42,
+    "base_url": server.uri(),
+    "device_id": "device",
+    "token_expiry": chrono::Utc::now().timestamp() + 3600
+  });
+  BlinkClient::from_state_with_urls(BlinkAuthState::from_json(&state.to_string(), "test").unwrap(), urls(server))
+}
+
+fn state_with(client: BlinkClient, api_key: Option<&str>) -> Arc<ServerState> {
+  let dir = tempfile::tempdir_in(data_dir()).unwrap().keep();
+  Arc::new(ServerState {
+    blink_client: Arc::new(Mutex::new(client)),
+    signer: UrlSigner::random(),
+    api_key: api_key.map(str::to_string),
+    live_sessions: Arc::new(live_sessions::SessionRegistry::default()),
+    readiness: ready::ReadinessCache::default(),
+    media_index: MediaIndex::new(dir.join(media_index::INDEX_FILE), false),
+    settings: std::sync::RwLock::new(Settings::default()),
+    scheduler: Scheduler::load(dir.join("schedule_state.json")),
+    trash: Trash::load(dir.join(trash::TRASH_FILE)),
+    delete_queue: DeleteQueue::load(dir.join(delete_queue::DELETE_QUEUE_FILE)),
+    firmware: FirmwareTracker::load(dir.join(firmware::FIRMWARE_FILE)),
+    cleanup: Arc::new(CleanupQueue::load(dir.join(cleanup::CLEANUP_FILE))),
+    events: EventLog::new(dir.join(events::EVENTS_FILE)),
+    rearm: RearmQueue::load(dir.join(rearm::REARM_FILE)),
+    battery: BatteryStore::new(dir.join(battery::BATTERY_FILE)),
+    takeout: Takeouts::load(dir.join(takeout::TAKEOUT_FILE), dir.join("takeout")),
+    request_budgets: timeouts::RouteBudgets::from_env(),
+    thumbnails: ThumbnailMemory::new(thumbnail_cache::MAX_MEMORY_BYTES),
+    fetch_permits: Semaphore::new(media::MAX_CONCURRENT_FETCHES)
+  })
+}
+
+async fn send(state: &Arc<ServerState>, request: Request<Body>) -> (StatusCode, HeaderMap, String) {
+  let response = router(state.clone()).oneshot(request).await.unwrap();
+  let status = response.status();
+  let headers = response.headers().clone();
+  let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+  (status, headers, String::from_utf8_lossy(&body).into_owned())
+}
+
+fn get_req(uri: &str) -> Request<Body> {
+  Request::get(uri).body(Body::empty()).unwrap()
+}
+
+fn post_json(uri: &str, body: serde_json::Value) -> Request<Body> {
+  Request::post(uri).header("content-type", "application/json").body(Body::from(body.to_string())).unwrap()
+}
+
+#[tokio::test]
+async fn login_then_verify_pin_signs_the_server_in() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/oauth/v2/authorize"))
+    .respond_with(ResponseTemplate::new(302).insert_header("Location", "immedia-blink://applinks.blink.com/signin/callback?code=c0de"))
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/oauth/v2/signin"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(r#"<script id="oauth-args">{"csrf-token":"csrf-1"}</script>"#))
+    .mount(&server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/oauth/v2/signin"))
+    .and(body_string_contains("csrf-token=csrf-1"))
+    .respond_with(ResponseTemplate::new(412))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/oauth/v2/2fa/verify"))
+    .and(body_string_contains("2fa_code=654321"))
+    .respond_with(ResponseTemplate::new(201))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/oauth/token"))
+    .and(body_string_contains("code=c0de"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "access_token": "a", "refresh_token": "r", "expires_in": 3600 })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/api/v1/users/tier_info"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "tier": "u011", "account_id": 42 })))
+    .mount(&server)
+    .await;
+  let state = state_with(BlinkClient::with_urls(urls(&server)), None);
+
+  let (status, _, body) = send(&state, post_json("/api/login", serde_json::json!({ "email": "user@example.com", "password": "pw" }))).await;
+  assert_eq!((status, body.as_str()), (StatusCode::OK, "2FA_REQUIRED"));
+  assert_eq!(state.blink_client.lock().await.auth_state(), AuthState::LoggedOut);
+
+  let (status, _, body) = send(&state, post_json("/api/verify-pin", serde_json::json!({ "pin": "654321" }))).await;
+  assert_eq!((status, body.as_str()), (StatusCode::OK, "SUCCESS"));
+  assert_eq!(state.blink_client.lock().await.auth_state(), AuthState::LoggedIn);
+  let saved = storage::load_auth().unwrap();
+  assert_eq!(saved.account_id, Some(42));
+}
+
+#[tokio::test]
+async fn login_failure_is_a_401() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/oauth/v2/signin"))
+    .respond_with(ResponseTemplate::new(200).set_body_string(r#"<script id="oauth-args">{"csrf-token":"csrf-1"}</script>"#))
+    .mount(&server)
+    .await;
+  Mock::given(method("POST"))
+    .and(path("/oauth/v2/signin"))
+    .respond_with(ResponseTemplate::new(403))
+    .mount(&server)
+    .await;
+  let state = state_with(BlinkClient::with_urls(urls(&server)), None);
+
+  let (status, _, body) = send(&state, post_json("/api/login", serde_json::json!({ "email": "user@example.com", "password": "bad" }))).await;
+  assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+}
+
+#[tokio::test]
+async fn a_revoked_refresh_token_is_a_401_with_a_reason() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/oauth/token"))
+    .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({ "error": "invalid_grant" })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/api/v3/accounts/42/homescreen"))
+    .respond_with(ResponseTemplate::new(200))
+    .expect(0)
+    .mount(&server)
+    .await;
+  let mut client = logged_in(&server);
+  let mut expired = client.get_state();
+  expired.token_expiry = Some(chrono::Utc::now().timestamp() - 10);
+  client = BlinkClient::from_state_with_urls(expired, urls(&server));
+  let state = state_with(client, None);
+
+  for _ in 0..2 {
+    let (status, _, body) = send(&state, get_req("/api/homescreen")).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED, "{}", body);
+    let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(body["reason"], "reauth_required");
+  }
+  assert_eq!(state.blink_client.lock().await.auth_state(), AuthState::ReauthRequired);
+}
+
+#[tokio::test]
+async fn media_defaults_to_page_one_of_the_last_30_days() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/api/v1/accounts/42/media/changed"))
+    .and(query_param("page", "1"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [{ "id": 7001 }] })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+
+  let (status, _, body) = send(&state, get_req("/api/media")).await;
+  assert_eq!(status, StatusCode::OK, "{}", body);
+  assert!(body.contains("7001"));
+
+  let requests = server.received_requests().await.unwrap();
+  let query: BTreeMap<_, _> = requests[0].url.query_pairs().into_owned().collect();
+  assert!(!query.contains_key("page_size"));
+  // The offset's `+` goes out unescaped, so it reads back as a space.
+  let since = chrono::DateTime::parse_from_rfc3339(&query["since"].replace(' ', "+")).unwrap();
+  let days = (chrono::Utc::now() - since.with_timezone(&chrono::Utc)).num_minutes() as f64 / (24.0 * 60.0);
+  assert!((days - 30.0).abs() < 0.01, "since is {} days back", days);
+}
+
+#[tokio::test]
+async fn media_arguments_out_of_range_never_reach_blink() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+  let state = state_with(logged_in(&server), None);
+
+  for (uri, message) in [
+    ("/api/media?page=0", "page must be 1 or more, not 0"),
+    ("/api/media?page=-2", "page must be 1 or more, not -2"),
+    ("/api/media?sinceDays=0", "sinceDays must be between 1 and 365, not 0"),
+    ("/api/media?sinceDays=36500", "sinceDays must be between 1 and 365, not 36500"),
+    ("/api/media?pageSize=0", "pageSize must be between 1 and 100, not 0"),
+    ("/api/media?pageSize=101", "pageSize must be between 1 and 100, not 101"),
+  ] {
+    let (status, _, body) = send(&state, get_req(uri)).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}: {}", uri, body);
+    assert!(body.contains(message), "{}: {}", uri, body);
+  }
+}
+
+#[tokio::test]
+async fn delete_media_reports_what_blink_shows_afterwards() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/api/v1/accounts/42/media/delete"))
+    .and(body_json(serde_json::json!({ "media_list": [7001, 7002] })))
+    .respond_with(ResponseTemplate::new(200))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/api/v1/accounts/42/media/changed"))
+    .and(query_param("page", "1"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [{ "id": 7001, "deleted": true }, { "id": 9999 }] })))
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/api/v1/accounts/42/media/changed"))
+    .and(query_param("page", "2"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [] })))
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+
+  let items = serde_json::json!({ "items": [
+    { "id": 7001, "created_at": "2026-10-14T21:04:11+00:00" },
+    { "id": 7002, "created_at": "2026-10-14T21:00:00+00:00" }
+  ] });
+  let (status, _, body) = send(&state, post_json("/api/delete-media", items)).await;
+  assert_eq!(status, StatusCode::OK, "{}", body);
+  let results: serde_json::Value = serde_json::from_str(&body).unwrap();
+  assert_eq!(results, serde_json::json!([{ "id": 7001, "status": "deleted" }, { "id": 7002, "status": "not_found" }]));
+
+  // Verification looks back from a minute before the oldest clip being deleted.
+  let requests = server.received_requests().await.unwrap();
+  let page_one = requests.iter().find(|r| r.url.path().ends_with("/media/changed")).unwrap();
+  assert!(page_one.url.query().unwrap().contains("since=2026-10-14T20:59:00+00:00"), "{}", page_one.url);
+}
+
+#[tokio::test]
+async fn delete_media_fails_when_blink_refused_and_the_clip_is_still_there() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("POST"))
+    .and(path("/api/v1/accounts/42/media/delete"))
+    .respond_with(ResponseTemplate::new(400).set_body_json(serde_json::json!({ "message": "Invalid media list" })))
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/api/v1/accounts/42/media/changed"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "media": [{ "id": 7001, "deleted": false }] })))
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+
+  let (status, _, body) = send(&state, post_json("/api/delete-media", serde_json::json!({ "items": [{ "id": 7001 }] }))).await;
+  assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+  assert!(body.contains("Invalid media list"), "{}", body);
+}
+
+#[tokio::test]
+async fn thumbnail_proxy_refuses_hosts_off_the_allow_list() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET")).respond_with(ResponseTemplate::new(200)).expect(0).mount(&server).await;
+  let state = state_with(logged_in(&server), None);
+
+  for url in ["https://evil.com/x", "https://immedia-semi.com.evil.com/x", "http://rest-u011.immedia-semi.com/x", "https://user:pw@rest-u011.immedia-semi.com/x"] {
+    let encoded: String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    let (status, _, body) = send(&state, get_req(&format!("/api/thumbnail?url={}", encoded))).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{}: {}", url, body);
+  }
+}
+
+#[tokio::test]
+async fn camera_config_round_trips_through_blink() {
+  data_dir();
+  let server = MockServer::start().await;
+  let config = serde_json::json!({ "name": "Driveway", "motion_sensitivity": 7, "video_quality": "best" });
+  Mock::given(method("POST"))
+    .and(path("/network/1001/camera/2001/update"))
+    .and(body_json(config.clone()))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": 1 })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/network/1001/camera/2001/config"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "camera": [config.clone()] })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+
+  let update = serde_json::json!({ "networkId": 1001, "cameraId": 2001, "productType": "catalina", "config": config });
+  let (status, _, body) = send(&state, post_json("/api/camera-config", update)).await;
+  assert_eq!(status, StatusCode::NO_CONTENT, "{}", body);
+
+  let (status, _, body) = send(&state, get_req("/api/camera-config?networkId=1001&cameraId=2001&productType=catalina")).await;
+  assert_eq!(status, StatusCode::OK, "{}", body);
+  let read: serde_json::Value = serde_json::from_str(&body).unwrap();
+  assert_eq!(read["camera"][0], config);
+
+  // A typo is refused here instead of being silently ignored by Blink.
+  let typo = serde_json::json!({ "networkId": 1001, "cameraId": 2001, "productType": "catalina", "config": { "motion_sensitivy": 7 } });
+  let (status, _, body) = send(&state, post_json("/api/camera-config", typo)).await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+  assert!(body.contains("invalid_config"));
+}
+
+#[tokio::test]
+async fn camera_config_checks_run_before_blink_unless_unknown_keys_are_allowed() {
+  data_dir();
+  let server = MockServer::start().await;
+  let unknown = serde_json::json!({ "new_blink_flag": true, "motion_sensitivity": 3 });
+  Mock::given(method("POST"))
+    .and(path("/network/1001/camera/2001/update"))
+    .and(body_json(unknown.clone()))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "id": 1 })))
+    .expect(1)
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+  let update = |config: serde_json::Value, allow_unknown: bool| serde_json::json!({
+    "networkId": 1001, "cameraId": 2001, "productType": "catalina", "config": config, "allowUnknown": allow_unknown,
+  });
+
+  let (status, _, body) = send(&state, post_json("/api/camera-config", update(unknown.clone(), false))).await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+  assert!(body.contains("new_blink_flag"), "{}", body);
+
+  // Known keys stay checked even with the escape hatch.
+  let out_of_range = serde_json::json!({ "new_blink_flag": true, "motion_sensitivity": 12 });
+  let (status, _, body) = send(&state, post_json("/api/camera-config", update(out_of_range, true))).await;
+  assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY, "{}", body);
+  assert!(body.contains("must be between 1 and 9, got 12"), "{}", body);
+
+  let (status, _, body) = send(&state, post_json("/api/camera-config", update(unknown, true))).await;
+  assert_eq!(status, StatusCode::NO_CONTENT, "{}", body);
+}
+
+#[test]
+fn proxied_responses_keep_upstream_headers() {
+  let mut upstream = HeaderMap::new();
+  upstream.insert(http::header::CONTENT_TYPE, "video/mp4".parse().unwrap());
+  upstream.insert(http::header::CONTENT_LENGTH, "1024".parse().unwrap());
+  upstream.insert(http::header::CONTENT_RANGE, "bytes 0-1023/4096".parse().unwrap());
+  upstream.insert(http::header::ACCEPT_RANGES, "bytes".parse().unwrap());
+  upstream.insert(http::header::ETAG, "\"abc\"".parse().unwrap());
+  upstream.insert(http::header::ACCESS_CONTROL_ALLOW_ORIGIN, "https://blinkforhome.com".parse().unwrap());
+
+  let streamed: Vec<_> = media::passthrough_headers(&upstream, false).map(|(name, _)| name.as_str()).collect();
+  assert_eq!(streamed.len(), 5);
+  assert!(!streamed.contains(&"access-control-allow-origin"));
+  assert!(streamed.contains(&"content-range") && streamed.contains(&"content-type"));
+
+  let buffered: Vec<_> = media::passthrough_headers(&upstream, true).map(|(name, _)| name.as_str()).collect();
+  assert!(!buffered.contains(&"content-type"));
+  assert!(buffered.contains(&"etag"));
+}
+
+#[tokio::test]
+async fn only_the_exact_api_key_or_a_media_signature_gets_in() {
+  data_dir();
+  let server = MockServer::start().await;
+  let state = state_with(logged_in(&server), Some("secret"));
+  let with_key = |key: &str| Request::get("/api/camera-prefs").header("x-api-key", key).body(Body::empty()).unwrap();
+
+  assert_eq!(send(&state, with_key("secret")).await.0, StatusCode::OK);
+  let bearer = Request::get("/api/camera-prefs").header("authorization", "Bearer secret").body(Body::empty()).unwrap();
+  assert_eq!(send(&state, bearer).await.0, StatusCode::OK);
+  for wrong in ["", "secre", "secret ", "secrets", "SECRET"] {
+    assert_eq!(send(&state, with_key(wrong)).await.0, StatusCode::UNAUTHORIZED, "{:?}", wrong);
+  }
+
+  // A signature only stands in for the key on the media routes themselves.
+  let expires = chrono::Utc::now().timestamp() + 60;
+  let signed = state.signer.sign("/api/thumbnail-base64?path=%2Fthumb.jpg", expires).unwrap();
+  assert_eq!(send(&state, get_req(&signed)).await.0, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn cors_preflight_is_answered_without_an_api_key() {
+  data_dir();
+  let server = MockServer::start().await;
+  let state = state_with(logged_in(&server), Some("secret"));
+
+  let preflight = Request::builder()
+    .method(Method::OPTIONS)
+    .uri("/api/camera-prefs/5")
+    .header("origin", "http://localhost:5173")
+    .header("access-control-request-method", "PUT")
+    .header("access-control-request-headers", "x-api-key,content-type")
+    .body(Body::empty())
+    .unwrap();
+  let (status, headers, _) = send(&state, preflight).await;
+  assert_eq!(status, StatusCode::OK);
+  assert_eq!(headers["access-control-allow-origin"], "*");
+  let methods = headers["access-control-allow-methods"].to_str().unwrap();
+  for method in ["GET", "POST", "PUT", "PATCH", "DELETE"] {
+    assert!(methods.contains(method), "{}", methods);
+  }
+  assert_eq!(headers["access-control-allow-headers"], "*");
+
+  // The real request still needs the key, and error headers are readable cross-origin.
+  let (status, headers, _) = send(&state, Request::get("/api/cameras").header("origin", "http://localhost:5173").body(Body::empty()).unwrap()).await;
+  assert_eq!(status, StatusCode::UNAUTHORIZED);
+  assert_eq!(headers["access-control-allow-origin"], "*");
+  let exposed = headers["access-control-expose-headers"].to_str().unwrap().to_ascii_lowercase();
+  assert!(exposed.contains("x-blink-error-code") && exposed.contains("retry-after"), "{}", exposed);
+}
+
+#[tokio::test]
+async fn a_recorded_fake_session_shows_up_in_the_recordings_list() {
+  data_dir();
+  let server = MockServer::start().await;
+  let state = state_with(logged_in(&server), None);
+  let (status, _, body) = send(&state, get_req("/api/recordings")).await;
+  assert_eq!((status, body.as_str()), (StatusCode::OK, "[]"));
+
+  let dir = tempfile::tempdir_in(data_dir()).unwrap().keep();
+  state.settings.write().unwrap().liveview_recording_dir = Some(dir.to_string_lossy().into_owned());
+  let response = router(state.clone())
+    .oneshot(get_req("/api/live/7/42/catalina?fake=true&record=true"))
+    .await
+    .unwrap();
+  assert_eq!(response.status(), StatusCode::OK);
+  let sessions = state.live_sessions.list();
+  let recording_path = sessions[0].recording_path.clone().unwrap();
+  assert!(recording_path.ends_with(".mp4"), "{}", recording_path);
+
+  // Read a little over a second of the test pattern, then hang up.
+  let mut body = response.into_body().into_data_stream();
+  let deadline = tokio::time::Instant::now() + std::time::Duration::from_millis(1300);
+  while tokio::time::timeout_at(deadline, body.next()).await.is_ok() {}
+  drop(body);
+
+  let mut listed = Vec::new();
+  for _ in 0..50 {
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let (_, _, body) = send(&state, get_req("/api/recordings")).await;
+    listed = serde_json::from_str::<Vec<serde_json::Value>>(&body).unwrap();
+    if !listed.is_empty() {
+      break;
+    }
+  }
+  assert_eq!(listed.len(), 1, "{:?}", listed);
+  let recording = &listed[0];
+  assert_eq!(recording["path"], recording_path.as_str());
+  assert_eq!(recording["format"], "mp4");
+  assert_eq!((recording["camera_id"].as_i64(), recording["network_id"].as_i64()), (Some(42), Some(7)));
+  assert_eq!(recording["session_id"], sessions[0].id.as_str());
+  let duration = recording["duration_secs"].as_f64().unwrap();
+  assert!(duration >= 1.0, "{}", duration);
+}
+
+#[tokio::test]
+async fn a_handler_past_its_budget_gets_a_504() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/api/v3/accounts/42/homescreen"))
+    .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})).set_delay(std::time::Duration::from_secs(5)))
+    .mount(&server)
+    .await;
+  let mut state = state_with(logged_in(&server), None);
+  Arc::get_mut(&mut state).unwrap().request_budgets = timeouts::RouteBudgets::new(120, "/api/homescreen=1", 5000);
+
+  let started = std::time::Instant::now();
+  let (status, _, body) = send(&state, get_req("/api/homescreen")).await;
+  let elapsed = started.elapsed();
+  assert_eq!(status, StatusCode::GATEWAY_TIMEOUT, "{}", body);
+  assert!(elapsed >= std::time::Duration::from_secs(1) && elapsed < std::time::Duration::from_secs(4), "{:?}", elapsed);
+  let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+  assert_eq!(body["reason"], "timeout");
+  assert_eq!(body["message"], "The request did not finish within its 1s budget");
+  // The abandoned handler released the client for the next request.
+  assert!(tokio::time::timeout(std::time::Duration::from_secs(1), state.blink_client.lock()).await.is_ok());
+}
+
+#[tokio::test]
+async fn a_thumbnail_blink_fails_to_serve_falls_back_to_the_last_copy() {
+  data_dir();
+  let server = MockServer::start().await;
+  Mock::given(method("GET"))
+    .and(path("/media/cam/thumb.jpg"))
+    .respond_with(ResponseTemplate::new(200).set_body_raw(b"old".to_vec(), "image/jpeg"))
+    .up_to_n_times(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/media/cam/thumb.jpg"))
+    .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "1"))
+    .up_to_n_times(1)
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/media/cam/thumb.jpg"))
+    .respond_with(ResponseTemplate::new(200).set_body_raw(b"new".to_vec(), "image/jpeg"))
+    .mount(&server)
+    .await;
+  Mock::given(method("GET"))
+    .and(path("/media/never/thumb.jpg"))
+    .respond_with(ResponseTemplate::new(503))
+    .mount(&server)
+    .await;
+  let state = state_with(logged_in(&server), None);
+  let data_uri = |bytes: &[u8]| format!("data:image/jpeg;base64,{}", base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes));
+
+  let (status, headers, body) = send(&state, get_req("/api/thumbnail-base64?path=/media/cam/thumb.jpg")).await;
+  assert_eq!(status, StatusCode::OK, "{}", body);
+  assert_eq!(body, data_uri(b"old"));
+  assert_eq!(headers[thumbnail_cache::CACHE_HEADER], "fresh");
+  assert!(headers.get(thumbnail_cache::STALE_HEADER).is_none());
+
+  // Blink rate limits the next fetch: the last copy goes out, marked stale.
+  let (status, headers, body) = send(&state, get_req("/api/thumbnail-base64?path=/media/cam/thumb.jpg")).await;
+  assert_eq!(status, StatusCode::OK, "{}", body);
+  assert_eq!(body, data_uri(b"old"));
+  assert_eq!(headers[thumbnail_cache::CACHE_HEADER], "stale");
+  assert_eq!(headers[thumbnail_cache::STALE_HEADER], "true");
+
+  // The background fetch honours Retry-After and replaces the copy.
+  let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+  while state.thumbnails.stale("/media/cam/thumb.jpg").unwrap().bytes != b"new" {
+    assert!(std::time::Instant::now() < deadline, "the stale copy was never revalidated");
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+  }
+
+  // Without a copy to fall back on the failure is reported.
+  let (status, _, body) = send(&state, get_req("/api/thumbnail-base64?path=/media/never/thumb.jpg")).await;
+  assert_eq!(status, StatusCode::BAD_GATEWAY, "{}", body);
+}
+
+#[tokio::test]
+async fn a_thumbnail_body_cut_short_falls_back_to_the_stale_copy() {
+  data_dir();
+  // Promises 64 bytes, sends 3 and hangs up, so reading the body fails after the headers.
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  tokio::spawn(async move {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    while let Ok((mut socket, _)) = listener.accept().await {
+      let mut request = [0u8; 1024];
+      let _ = socket.read(&mut request).await;
+      let _ = socket.write_all(b"HTTP/1.1 200 OK\r\ncontent-type: image/jpeg\r\ncontent-length: 64\r\n\r\nnew").await;
+    }
+  });
+  let server = MockServer::start().await;
+  let state = state_with(logged_in(&server), None);
+  let cut_short = || async { reqwest::get(format!("http://{}/thumb.jpg", addr)).await.unwrap() };
+
+  let failed = media::read_thumbnail(&state, "/media/cam/thumb.jpg", cut_short().await).await.unwrap_err();
+  assert_eq!(failed.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+  state.thumbnails.store("/media/cam/thumb.jpg", &blink::Thumbnail { bytes: b"old".to_vec(), content_type: "image/jpeg".into() });
+  let stale = media::read_thumbnail(&state, "/media/cam/thumb.jpg", cut_short().await).await.unwrap_err();
+  assert_eq!(stale.status(), StatusCode::OK);
+  assert_eq!(stale.headers()[thumbnail_cache::STALE_HEADER], "true");
+  let body = axum::body::to_bytes(stale.into_body(), usize::MAX).await.unwrap();
+  assert_eq!(&body[..], b"old");
+}
+
+#[tokio::test]
+async fn the_event_socket_replays_after_the_given_seq_then_sends_new_events() {
+  use tokio_tungstenite::tungstenite::{client::IntoClientRequest, Message as WsMessage};
+
+  let server = MockServer::start().await;
+  let state = state_with(logged_in(&server), Some("secret"));
+  for n in 1..=3 {
+    state.events.record("arm", serde_json::json!({ "n": n }));
+  }
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let addr = listener.local_addr().unwrap();
+  let app = router(state.clone());
+  tokio::spawn(async move { axum::serve(listener, app).await });
+
+  let url = format!("ws://{}/api/events?after_seq=1", addr);
+  assert!(tokio_tungstenite::connect_async(url.as_str()).await.is_err());
+  let mut request = url.into_client_request().unwrap();
+  request.headers_mut().insert("x-api-key", "secret".parse().unwrap());
+  let (mut socket, _) = tokio_tungstenite::connect_async(request).await.unwrap();
+
+  async fn next<S: futures_util::Stream<Item = tokio_tungstenite::tungstenite::Result<WsMessage>> + Unpin>(socket: &mut S) -> Event {
+    let Some(Ok(WsMessage::Text(text))) = socket.next().await else { panic!("expected an event frame") };
+    serde_json::from_str(&text).unwrap()
+  }
+  assert_eq!(next(&mut socket).await.seq, 2);
+  assert_eq!(next(&mut socket).await.seq, 3);
+  state.events.record("rearm", serde_json::json!({ "n": 4 }));
+  let live = next(&mut socket).await;
+  assert_eq!((live.seq, live.kind.as_str()), (4, "rearm"));
+}
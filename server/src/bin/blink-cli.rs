@@ -0,0 +1,451 @@
+//! `blink-cli`: headless control for scripts and cron jobs. It reads and writes the same
+//! auth state as the server (`BLINK_DATA_DIR`, `BLINK_AUTH_PATH`), so a session created by
+//! either one is picked up by the other.
+
+use anyhow::{anyhow, bail, Context, Result};
+use blink_core::{audit, export, shared};
+use blink_core::settings::Settings;
+use blink_core::blink::{parse_blink_timestamp, BlinkClient, BlinkError, Camera, DeviceFamily, MediaItem, Network};
+use blink_monitor_server::{bootstrap, storage};
+use clap::{Args, Parser, Subcommand};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const EXIT_FAILURE: u8 = 1;
+/// Not logged in, or the stored session was rejected or revoked: run `blink-cli login`.
+const EXIT_AUTH: u8 = 3;
+/// Blink could not be reached, or failed in a way worth retrying later.
+const EXIT_NETWORK: u8 = 4;
+
+#[derive(Parser)]
+#[command(
+  name = "blink-cli",
+  version,
+  about = "Control Blink cameras from the command line",
+  after_help = "Exit codes: 0 success, 1 failure, 2 bad usage, 3 not logged in or session expired, 4 Blink unreachable or failing"
+)]
+struct Cli {
+  /// Print JSON instead of tables
+  #[arg(long, global = true)]
+  json: bool,
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Sign in, prompting for the password and, if Blink asks for one, the 2FA PIN
+  Login {
+    /// Account email; prompted for when omitted
+    email: Option<String>,
+    /// Read the password from this file instead of prompting
+    #[arg(long)]
+    password_file: Option<PathBuf>,
+  },
+  /// Forget the stored session
+  Logout,
+  /// Show the session and each network's arm state
+  Status,
+  /// List cameras
+  #[command(subcommand)]
+  Cameras(CamerasCommand),
+  /// Arm a network, by name or id
+  Arm { network: String },
+  /// Disarm a network, by name or id
+  Disarm { network: String },
+  /// List or download recorded clips
+  #[command(subcommand)]
+  Media(MediaCommand),
+  /// Save a camera's latest thumbnail
+  Thumbnail {
+    /// Camera name or id
+    camera: String,
+    #[arg(long)]
+    out: PathBuf,
+  },
+  /// Read or change a camera's Blink settings
+  #[command(subcommand)]
+  Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+enum CamerasCommand {
+  List,
+}
+
+#[derive(Subcommand)]
+enum MediaCommand {
+  List(MediaArgs),
+  /// Download clips into a directory; files already there are skipped. Names follow the
+  /// `download_filename_template` setting
+  Download {
+    #[command(flatten)]
+    filter: MediaArgs,
+    #[arg(long)]
+    out: PathBuf,
+  },
+}
+
+#[derive(Args)]
+struct MediaArgs {
+  /// How far back to look, e.g. `30m`, `24h`, `7d` or `2w`
+  #[arg(long, default_value = "7d", value_parser = parse_since)]
+  since: chrono::Duration,
+  /// Only clips from this camera (by name)
+  #[arg(long)]
+  camera: Option<String>,
+  /// Upper bound on media pages fetched from Blink
+  #[arg(long, default_value_t = 10)]
+  max_pages: i64,
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+  /// Print the camera's settings as JSON
+  Get { camera: String },
+  /// Change settings, given as a JSON object of the fields to update
+  Set { camera: String, config: String },
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  let cli = Cli::parse();
+  audit::init(storage::data_dir().join(audit::AUDIT_FILE), "cli");
+  shared::init_auth_persistence(storage::save_auth);
+  match run(cli).await {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("Error: {:#}", e);
+      ExitCode::from(exit_code(&e))
+    }
+  }
+}
+
+fn exit_code(e: &anyhow::Error) -> u8 {
+  match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::NotLoggedIn | BlinkError::AuthExpired | BlinkError::ReauthRequired) => EXIT_AUTH,
+    Some(BlinkError::RateLimited { .. } | BlinkError::CameraBusy { .. }) => EXIT_NETWORK,
+    Some(BlinkError::Http { status, .. }) if *status >= 500 => EXIT_NETWORK,
+    Some(_) => EXIT_FAILURE,
+    None if e.chain().any(|cause| cause.is::<reqwest::Error>()) => EXIT_NETWORK,
+    None => EXIT_FAILURE,
+  }
+}
+
+async fn run(cli: Cli) -> Result<()> {
+  let json = cli.json;
+  match cli.command {
+    Command::Login { email, password_file } => {
+      let email = bootstrap::login(email, password_file.as_deref()).await?;
+      println!("Logged in as {}", email);
+      Ok(())
+    }
+    Command::Logout => {
+      storage::clear_auth()?;
+      audit::record("logout", "account", None);
+      println!("Logged out");
+      Ok(())
+    }
+    Command::Status => status(json).await,
+    Command::Cameras(CamerasCommand::List) => list_cameras(json).await,
+    Command::Arm { network } => set_arm(&network, true).await,
+    Command::Disarm { network } => set_arm(&network, false).await,
+    Command::Media(MediaCommand::List(filter)) => list_media(&filter, json).await,
+    Command::Media(MediaCommand::Download { filter, out }) => download_media(&filter, &out, json).await,
+    Command::Thumbnail { camera, out } => save_thumbnail(&camera, &out).await,
+    Command::Config(ConfigCommand::Get { camera }) => get_config(&camera).await,
+    Command::Config(ConfigCommand::Set { camera, config }) => set_config(&camera, &config).await,
+  }
+}
+
+/// The stored session, refreshed (and re-saved) if the token is about to expire.
+async fn session() -> Result<BlinkClient> {
+  let saved = storage::load_auth()
+    .map_err(|e| anyhow!(BlinkError::NotLoggedIn).context(format!("No stored session ({}); run `blink-cli login`", e)))?;
+  let mut client = BlinkClient::from_state(saved);
+  client.auth()?;
+  shared::refresh_and_persist(&mut client).await?;
+  Ok(client)
+}
+
+#[derive(Serialize)]
+struct StatusReport {
+  account_id: Option<i64>,
+  base_url: String,
+  token_expires_at: Option<chrono::DateTime<chrono::Utc>>,
+  networks: Vec<Network>,
+}
+
+async fn status(json: bool) -> Result<()> {
+  let client = session().await?;
+  let homescreen = client.get_homescreen().await?;
+  let state = client.get_state();
+  let report = StatusReport {
+    account_id: state.account_id,
+    base_url: state.base_url,
+    token_expires_at: state.token_expiry.and_then(|ts| chrono::DateTime::from_timestamp(ts, 0)),
+    networks: homescreen.networks,
+  };
+  if json {
+    return print_json(&report);
+  }
+
+  println!("Account {} ({})", report.account_id.unwrap_or_default(), report.base_url);
+  if let Some(expiry) = report.token_expires_at {
+    println!("Token valid until {}", expiry.to_rfc3339());
+  }
+  println!();
+  print_table(&["ID", "NETWORK", "ARMED"], report.networks.iter()
+    .map(|n| vec![n.id.to_string(), n.name.clone(), yes_no(n.armed).to_string()])
+    .collect());
+  Ok(())
+}
+
+/// Every device on the account, whichever homescreen list it came from.
+async fn all_cameras(client: &BlinkClient) -> Result<(Vec<Network>, Vec<Camera>)> {
+  let homescreen = client.get_homescreen().await?;
+  let cameras = homescreen.cameras.into_iter()
+    .chain(homescreen.owls)
+    .chain(homescreen.doorbells)
+    .collect();
+  Ok((homescreen.networks, cameras))
+}
+
+async fn list_cameras(json: bool) -> Result<()> {
+  let client = session().await?;
+  let (networks, cameras) = all_cameras(&client).await?;
+  if json {
+    return print_json(&cameras);
+  }
+
+  print_table(&["ID", "NAME", "NETWORK", "TYPE", "STATUS", "BATTERY"], cameras.iter()
+    .map(|c| {
+      let network = networks.iter()
+        .find(|n| Some(n.id) == c.network_id)
+        .map(|n| n.name.clone())
+        .unwrap_or_default();
+      vec![
+        c.id.to_string(),
+        c.name.clone(),
+        network,
+        c.product_type.clone(),
+        c.status.clone(),
+        c.battery.clone().unwrap_or_default(),
+      ]
+    })
+    .collect());
+  Ok(())
+}
+
+/// Matches by id first, then by case-insensitive name; ambiguous names are an error.
+fn find_by_name<'a, T>(items: &'a [T], query: &str, kind: &str, id: impl Fn(&T) -> i64, name: impl Fn(&T) -> &str) -> Result<&'a T> {
+  if let Ok(wanted) = query.parse::<i64>() {
+    if let Some(item) = items.iter().find(|item| id(item) == wanted) {
+      return Ok(item);
+    }
+  }
+  let matches: Vec<&T> = items.iter().filter(|item| name(item).eq_ignore_ascii_case(query)).collect();
+  match matches.as_slice() {
+    [item] => Ok(item),
+    [] => bail!("No {} named '{}'", kind, query),
+    _ => bail!("More than one {} is named '{}'; use its id", kind, query),
+  }
+}
+
+async fn find_camera(client: &BlinkClient, query: &str) -> Result<Camera> {
+  let (_, cameras) = all_cameras(client).await?;
+  find_by_name(&cameras, query, "camera", |c| c.id, |c| &c.name).cloned()
+}
+
+async fn set_arm(query: &str, arm: bool) -> Result<()> {
+  let client = session().await?;
+  let networks = client.get_homescreen().await?.networks;
+  let network = find_by_name(&networks, query, "network", |n| n.id, |n| &n.name)?;
+  client.set_arm(network.id, arm).await?;
+  println!("{} {}", if arm { "Armed" } else { "Disarmed" }, network.name);
+  Ok(())
+}
+
+/// Parses `30m`, `24h`, `7d` or `2w`; a bare number is days.
+fn parse_since(value: &str) -> Result<chrono::Duration, String> {
+  let value = value.trim();
+  let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+    Some(i) => value.split_at(i),
+    None => (value, "d"),
+  };
+  let n: i64 = number.parse().map_err(|_| format!("invalid duration '{}'", value))?;
+  match unit {
+    "m" => Ok(chrono::Duration::minutes(n)),
+    "h" => Ok(chrono::Duration::hours(n)),
+    "d" => Ok(chrono::Duration::days(n)),
+    "w" => Ok(chrono::Duration::weeks(n)),
+    _ => Err(format!("invalid duration '{}': use m, h, d or w", value)),
+  }
+}
+
+/// Clips matching `filter`, newest first.
+async fn find_media(client: &BlinkClient, filter: &MediaArgs) -> Result<Vec<MediaItem>> {
+  // Blink filters by whole days; trim to the exact window afterwards.
+  let since_days = (filter.since.num_hours() + 23) / 24;
+  let cutoff = chrono::Utc::now() - filter.since;
+  let mut items: Vec<MediaItem> = client.get_all_media(since_days.max(1), filter.max_pages).await?
+    .into_iter()
+    .filter(|item| !item.deleted)
+    .filter(|item| item.created_at.as_deref().and_then(parse_blink_timestamp).is_none_or(|ts| ts >= cutoff))
+    .filter(|item| filter.camera.as_ref().is_none_or(|camera| {
+      item.device_name.as_deref().is_some_and(|name| name.eq_ignore_ascii_case(camera))
+    }))
+    .collect();
+  items.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+  Ok(items)
+}
+
+async fn list_media(filter: &MediaArgs, json: bool) -> Result<()> {
+  let client = session().await?;
+  let items = find_media(&client, filter).await?;
+  if json {
+    return print_json(&items);
+  }
+
+  print_table(&["ID", "CREATED", "CAMERA", "NETWORK", "SOURCE"], items.iter()
+    .map(|item| vec![
+      item.id.to_string(),
+      item.created_at.clone().unwrap_or_default(),
+      item.device_name.clone().unwrap_or_default(),
+      item.network_name.clone().unwrap_or_default(),
+      item.source.clone().unwrap_or_default(),
+    ])
+    .collect());
+  Ok(())
+}
+
+#[derive(Serialize)]
+struct DownloadResult {
+  id: i64,
+  file: PathBuf,
+  /// `downloaded`, `skipped` (already on disk) or `failed`.
+  status: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+}
+
+async fn download_media(filter: &MediaArgs, out: &Path, json: bool) -> Result<()> {
+  let client = session().await?;
+  let items = find_media(&client, filter).await?;
+  std::fs::create_dir_all(out).with_context(|| format!("Failed to create {}", out.display()))?;
+  let settings = Settings::load(&storage::settings_path());
+
+  let client = Arc::new(Mutex::new(client));
+  let mut used = HashSet::new();
+  let mut results = Vec::new();
+  for item in &items {
+    let name = export::entry_name(item, &settings.download_filename_template, &mut used);
+    let file = out.join(name);
+    let outcome = match item.media.as_deref() {
+      _ if file.exists() => Ok(false),
+      // Downloads go through a `.part` file, so an interrupted run never leaves a
+      // truncated clip that later runs would skip.
+      Some(media_path) => export::download_to_file(&client, media_path, &file).await.map(|()| true),
+      None => Err(anyhow!("Blink did not return a media path")),
+    };
+    let outcome = match outcome {
+      Ok(true) => export::apply_clip_metadata(&file, item, settings.download_sidecar)
+        .map(|()| true)
+        .with_context(|| format!("Failed to write metadata for {}", file.display())),
+      other => other,
+    };
+    let result = match outcome {
+      Ok(true) => DownloadResult { id: item.id, file, status: "downloaded", error: None },
+      Ok(false) => DownloadResult { id: item.id, file, status: "skipped", error: None },
+      Err(e) => DownloadResult { id: item.id, file, status: "failed", error: Some(e.to_string()) },
+    };
+    if !json {
+      match &result.error {
+        Some(error) => eprintln!("{} {}: {}", result.status, result.file.display(), error),
+        None => eprintln!("{} {}", result.status, result.file.display()),
+      }
+    }
+    results.push(result);
+  }
+
+  if json {
+    print_json(&results)?;
+  }
+  let failed = results.iter().filter(|r| r.status == "failed").count();
+  if failed > 0 {
+    bail!("{} of {} clips failed to download", failed, results.len());
+  }
+  Ok(())
+}
+
+async fn save_thumbnail(query: &str, out: &Path) -> Result<()> {
+  let client = session().await?;
+  let camera = find_camera(&client, query).await?;
+  let thumbnail = client.get_thumbnail(&camera.thumbnail).await?;
+  std::fs::write(out, &thumbnail.bytes).with_context(|| format!("Failed to write {}", out.display()))?;
+  println!("Saved {} ({}) to {}", camera.name, thumbnail.content_type, out.display());
+  Ok(())
+}
+
+fn camera_network(camera: &Camera) -> Result<i64> {
+  camera.network_id.ok_or_else(|| anyhow!("Blink did not report a network for {}", camera.name))
+}
+
+async fn get_config(query: &str) -> Result<()> {
+  let client = session().await?;
+  let camera = find_camera(&client, query).await?;
+  let config = client.get_camera_config(camera_network(&camera)?, camera.id, &camera.product_type).await?;
+  print_json(&config)
+}
+
+async fn set_config(query: &str, config: &str) -> Result<()> {
+  let config: serde_json::Value = serde_json::from_str(config).context("config must be a JSON object")?;
+  if !config.is_object() {
+    bail!("config must be a JSON object");
+  }
+  let client = session().await?;
+  let camera = find_camera(&client, query).await?;
+  // Standard cameras take their settings wrapped in `camera`; Minis and doorbells don't.
+  let payload = match camera.device_family {
+    DeviceFamily::Camera => serde_json::json!({ "camera": config }),
+    DeviceFamily::Owl | DeviceFamily::Doorbell => config,
+  };
+  client.update_camera_config(camera_network(&camera)?, camera.id, &camera.product_type, payload).await?;
+  println!("Updated {}", camera.name);
+  Ok(())
+}
+
+fn yes_no(value: bool) -> &'static str {
+  if value { "yes" } else { "no" }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+  println!("{}", serde_json::to_string_pretty(value)?);
+  Ok(())
+}
+
+/// Left-aligned columns sized to their widest cell.
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+  let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+  for row in &rows {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.chars().count());
+    }
+  }
+  let format_row = |cells: Vec<&str>| {
+    cells.iter().zip(&widths)
+      .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+      .collect::<Vec<_>>()
+      .join("  ")
+      .trim_end()
+      .to_string()
+  };
+  println!("{}", format_row(headers.to_vec()));
+  for row in &rows {
+    println!("{}", format_row(row.iter().map(String::as_str).collect()));
+  }
+}
@@ -0,0 +1,95 @@
+//! Terminal sign-in shared by `blink-monitor-server login` and `blink-cli login`, so a
+//! session can be stored before the API is reachable and without a password in shell
+//! history. Also the `export`/`import` subcommands that move state between machines.
+
+use anyhow::{bail, Context, Result};
+use blink_core::backup::{self, ExportSummary, ImportSummary};
+use blink_core::blink::BlinkClient;
+use blink_core::media_index::{self, MediaIndex};
+use blink_core::settings::Settings;
+use secrecy::{ExposeSecret, SecretString};
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+use crate::storage;
+
+/// Reads one line from stdin after printing `label` to stderr.
+pub fn prompt(label: &str) -> Result<String> {
+    eprint!("{}", label);
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+/// The secret in `file` (trailing newline dropped), or prompted for with `label` without echo.
+fn secret(file: Option<&Path>, label: &str) -> Result<SecretString> {
+    let Some(path) = file else {
+        return Ok(SecretString::from(rpassword::prompt_password(label)?));
+    };
+    let contents = std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let password = contents.trim_end_matches(['\r', '\n']);
+    if password.is_empty() {
+        bail!("{} is empty", path.display());
+    }
+    Ok(SecretString::from(password.to_string()))
+}
+
+/// Runs the OAuth sign-in, prompting for whatever wasn't given and for the 2FA PIN if
+/// Blink asks for one, then saves the session to the configured auth store. Returns the
+/// email that signed in.
+pub async fn login(email: Option<String>, password_file: Option<&Path>) -> Result<String> {
+    let email = match email {
+        Some(email) => email,
+        None => prompt("Email: ")?,
+    };
+    let password = secret(password_file, "Password: ")?;
+
+    let mut client = BlinkClient::new();
+    client.start_oauth_flow().await.context("OAuth init failed")?;
+    let mut status = client.login_oauth(&email, password).await?;
+    if status == "AMAZON_LOGIN_REQUIRED" {
+        let password = secret(password_file, "Amazon password: ")?;
+        status = match client.login_amazon(&email, password).await {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("{}", e);
+                let callback = prompt("Address the browser ended on: ")?;
+                client.complete_amazon_login(&callback).await?
+            }
+        };
+    }
+    if status == "2FA_REQUIRED" {
+        let pin = SecretString::from(prompt("2FA PIN: ")?);
+        client.verify_pin_oauth(pin).await?;
+    }
+    storage::save_auth(&client.get_state())?;
+    Ok(email)
+}
+
+/// Writes an encrypted archive of the stored session and settings (and the media index,
+/// when `with_media_index` is set) for `import` on another machine. The passphrase is
+/// prompted for twice unless it comes from `passphrase_file`.
+pub fn export_state(dest: &Path, with_media_index: bool, passphrase_file: Option<&Path>) -> Result<ExportSummary> {
+    let passphrase = secret(passphrase_file, "Archive passphrase: ")?;
+    if passphrase_file.is_none() && rpassword::prompt_password("Repeat passphrase: ")? != passphrase.expose_secret() {
+        bail!("The passphrases don't match");
+    }
+    let auth = match storage::load_auth() {
+        Ok(auth) => Some(auth),
+        Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => None,
+        Err(e) => return Err(e.context("Failed to read the stored session")),
+    };
+    let settings = Settings::load(&storage::settings_path());
+    let index = with_media_index.then(|| MediaIndex::new(storage::data_dir().join(media_index::INDEX_FILE), false));
+    backup::export(dest, passphrase.expose_secret(), auth, &settings, index.as_ref())
+}
+
+/// Restores an archive written by `export_state` (or the desktop app's export). Run it
+/// while the server is stopped; it picks everything up on the next start.
+pub fn import_state(src: &Path, passphrase_file: Option<&Path>) -> Result<ImportSummary> {
+    let passphrase = secret(passphrase_file, "Archive passphrase: ")?;
+    let archive = backup::read(src, passphrase.expose_secret())?;
+    let index = MediaIndex::new(storage::data_dir().join(media_index::INDEX_FILE), false);
+    archive.restore(&storage::settings_path(), &index, storage::save_auth)
+}
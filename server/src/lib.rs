@@ -0,0 +1,4 @@
+//! Pieces of the hosted server that `blink-cli` shares with it.
+
+pub mod bootstrap;
+pub mod storage;
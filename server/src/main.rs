@@ -1,279 +1,2316 @@
 use axum::{
-  extract::{Query, State},
+  extract::{ws::{Message, WebSocket, WebSocketUpgrade}, Path, Query, Request, State},
   http::StatusCode,
-  routing::{get, post},
+  middleware::{self, Next},
+  response::{Html, IntoResponse, Response},
+  routing::{delete, get, post, put},
   Json, Router
 };
-use http::Method;
-use serde::Deserialize;
+use http::{HeaderMap, Method};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, Semaphore};
 use tower_http::cors::{Any, CorsLayer};
-
-#[path = "../../src-tauri/src/blink.rs"]
-mod blink;
-#[path = "../../src-tauri/src/immi.rs"]
-mod immi;
+use futures_util::StreamExt;
 
 mod media;
-mod storage;
+mod openapi;
+mod ready;
+mod timeouts;
+#[cfg(test)]
+mod api_tests;
+
+use blink_core::{audit, battery, blink, camera_config, cleanup, clip_store, clock_skew, delete_queue, device_routes, diagnostics, events, export, firmware, heatmap, live_sessions, liveview, liveview_settings, log_control, media_index, motion_notifications, notify, rearm, recording, replay, report, schedule, self_test, settings, shared, signing, takeout, thumbnail_cache, timeline, trash, webhooks};
+use blink_monitor_server::{bootstrap, storage};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
-use blink::BlinkClient;
+use audit::{AuditEntry, AuditFilter};
+use battery::{BatteryHistory, BatteryStore};
+use blink::{ArmVerification, AuthState, BlinkClient, BlinkError, Camera, DeleteResult, MediaItem, Network, NetworkArmResult, NetworkSettings, NetworkSettingsPatch, NotificationConfig, LocalStorageStatus, TrustedClient, UsageReport, UsbEjectResult};
 use media::ServerState;
+use media_index::{MediaFilter, MediaIndex, MediaSearchPage};
+use secrecy::SecretString;
+use schedule::{NextScheduledChange, ScheduleEntry, Scheduler};
+use self_test::SelfTestReport;
+use shared::RevokeOutcome;
+use settings::{CameraPrefs, Settings};
+use cleanup::{CleanupEntry, CleanupQueue};
+use clip_store::StoredClip;
+use delete_queue::{DeleteProgress, DeleteQueue, JobState};
+use takeout::{TakeoutProgress, TakeoutState, Takeouts};
+use thumbnail_cache::{CacheStatus, PrefetchResult, ThumbnailMemory};
+use liveview_settings::{LiveviewSetting, LiveviewSettingWrite};
+use motion_notifications::{MotionNotificationWrite, MotionNotifications};
+use diagnostics::MediaDiagnosis;
+use events::{Event, EventLog};
+use firmware::{FirmwareRecord, FirmwareTracker};
+use heatmap::ActivityHeatmap;
+use rearm::{PendingRearm, RearmQueue, TemporaryDisarm};
+use timeline::TimelineEvent;
+use trash::{PendingDeletion, Trash};
+use webhooks::{BuiltinFormat, WebhookPayload};
+use shared::LiveviewSettingCandidate;
+use signing::UrlSigner;
+
+/// Handler error: the message goes out as plain text, and Blink's own error code, when
+/// the failure came from Blink with one, in `X-Blink-Error-Code`, and Blink's requested
+/// wait after a rate limit in `Retry-After`. Errors with a `reason`
+/// are sent as `{reason, message}` JSON instead, so clients can branch on them.
+struct ApiError {
+  status: StatusCode,
+  message: String,
+  blink_code: Option<i64>,
+  retry_after_secs: Option<u64>,
+  reason: Option<&'static str>
+}
+
+impl From<(StatusCode, String)> for ApiError {
+  fn from((status, message): (StatusCode, String)) -> Self {
+    Self { status, message, blink_code: None, retry_after_secs: None, reason: None }
+  }
+}
+
+impl IntoResponse for ApiError {
+  fn into_response(self) -> Response {
+    let mut response = match self.reason {
+      Some(reason) => (self.status, Json(serde_json::json!({ "reason": reason, "message": self.message }))).into_response(),
+      None => (self.status, self.message).into_response()
+    };
+    if let Some(code) = self.blink_code {
+      response.headers_mut().insert("X-Blink-Error-Code", code.into());
+    }
+    if let Some(secs) = self.retry_after_secs {
+      response.headers_mut().insert(http::header::RETRY_AFTER, secs.into());
+    }
+    response
+  }
+}
 
-type ApiResult<T> = Result<T, (StatusCode, String)>;
+type ApiResult<T> = Result<T, ApiError>;
 
-#[derive(Deserialize)]
+/// Maps a failed Blink call onto a response status: auth and not-found problems keep their
+/// meaning for API clients, everything else is reported as a bad gateway.
+fn upstream_error(e: anyhow::Error) -> ApiError {
+  let blink_code = match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::Http { code, .. }) => *code,
+    _ => None
+  };
+  let retry_after_secs = match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::RateLimited { retry_after_secs, .. }) => *retry_after_secs,
+    _ => None
+  };
+  let reason = match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::ReauthRequired) => Some("reauth_required"),
+    _ => None
+  };
+  let status = match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::NotLoggedIn | BlinkError::AuthExpired | BlinkError::ReauthRequired) => StatusCode::UNAUTHORIZED,
+    Some(BlinkError::NotFound(_)) => StatusCode::NOT_FOUND,
+    Some(BlinkError::InvalidPath(_)) => StatusCode::BAD_REQUEST,
+    Some(BlinkError::OutOfRange(_)) => StatusCode::UNPROCESSABLE_ENTITY,
+    Some(BlinkError::RateLimited { .. }) => StatusCode::TOO_MANY_REQUESTS,
+    Some(BlinkError::CameraBusy { .. }) => StatusCode::SERVICE_UNAVAILABLE,
+    Some(BlinkError::Http { status, .. }) => StatusCode::from_u16(*status)
+      .ok()
+      .filter(|s| s.is_client_error())
+      .unwrap_or(StatusCode::BAD_GATEWAY),
+    Some(BlinkError::LoginPageChanged(_) | BlinkError::NoAuthCode(_) | BlinkError::AuthCodeRejected { .. } | BlinkError::ResponseTooLarge { .. }) | None => {
+      StatusCode::BAD_GATEWAY
+    }
+  };
+  ApiError { status, message: e.to_string(), blink_code, retry_after_secs, reason }
+}
+
+#[derive(Deserialize, ToSchema)]
 struct LoginRequest {
   email: String,
-  password: String
+  #[schema(value_type = String)]
+  password: SecretString
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+struct AmazonCallbackRequest {
+  /// The address the browser ended on after signing in with Amazon
+  url: String
+}
+
+#[derive(Deserialize, ToSchema)]
 struct PinRequest {
-  pin: String
+  #[schema(value_type = String)]
+  pin: SecretString
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct SetArmRequest {
   networkId: i64,
+  arm: bool,
+  /// How long to wait for Blink to confirm the change, in seconds (default 30, max 120)
+  #[serde(rename = "timeoutSecs")]
+  timeout_secs: Option<u64>
+}
+
+#[derive(Deserialize, ToSchema)]
+struct SetArmAllRequest {
   arm: bool
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+struct DisarmTemporaryRequest {
+  #[serde(rename = "networkId")]
+  network_id: i64,
+  /// How long to stay disarmed (1 to 1440)
+  minutes: u32
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct MediaQuery {
+  /// Page number, from 1
   page: Option<i64>,
-  sinceDays: Option<i64>
+  /// Days of history to cover, from 1 to `BLINK_MAX_SINCE_DAYS` (default 365); defaults to 30
+  sinceDays: Option<i64>,
+  /// Clips per page, from 1 to 100; Blink's default when omitted
+  #[serde(rename = "pageSize")]
+  page_size: Option<u32>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct DeleteMediaRequest {
-  items: Vec<serde_json::Value>
+  #[schema(value_type = Vec<Object>)]
+  items: Vec<serde_json::Value>,
+  /// Hide the clips for this many seconds (at most 600) before deleting them, so the delete can be undone
+  #[serde(default, rename = "deferSecs")]
+  defer_secs: Option<u64>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct DeleteMediaQuery {
+  /// Delete in the background in batches of `delete_batch_size`; every id comes back `pending` and progress is on `/api/delete-media/queue`
+  #[serde(default)]
+  queued: bool
+}
+
+#[derive(Deserialize, ToSchema)]
+struct UndoDeleteRequest {
+  ids: Vec<i64>
+}
+
+#[derive(Deserialize, ToSchema)]
+struct MarkWatchedRequest {
+  ids: Vec<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct TimelineQuery {
+  #[serde(rename = "sinceDays")]
+  since_days: Option<i64>,
+  gap_secs: Option<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct NetworkSettingsQuery {
+  #[serde(rename = "networkId")]
+  network_id: i64
+}
+
+#[derive(Deserialize, ToSchema)]
+struct NetworkSettingsUpdate {
+  #[serde(rename = "networkId")]
+  network_id: i64,
+  #[serde(flatten)]
+  #[schema(inline, value_type = crate::openapi::NetworkSettingsPatchSchema)]
+  patch: NetworkSettingsPatch
+}
+
+#[derive(Deserialize, ToSchema)]
+struct MotionNotificationsUpdate {
+  #[serde(rename = "networkId")]
+  network_id: i64,
+  enabled: bool
+}
+
+#[derive(Deserialize, Default, ToSchema)]
+struct TakeoutRequest {
+  /// Write into the configured archive store (under `takeout/<id>/`) instead of a folder in the data directory
+  #[serde(default, rename = "useArchiveStore")]
+  use_archive_store: bool
+}
+
+#[derive(Deserialize, IntoParams, ToSchema)]
+#[into_params(parameter_in = Query)]
+struct SyncModuleStorageQuery {
+  #[serde(rename = "networkId")]
+  network_id: i64,
+  #[serde(rename = "syncModuleId")]
+  sync_module_id: i64
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LiveviewSettingUpdate {
+  #[serde(rename = "networkId")]
+  network_id: i64,
+  /// A name from `/api/liveview-settings`, e.g. `lv_save`
+  flag: String,
+  /// Which device, for per-device flags on a network with several
+  #[serde(default, rename = "deviceId")]
+  device_id: Option<i64>,
+  value: serde_json::Value
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct CameraConfigQuery {
   networkId: i64,
   cameraId: i64,
   productType: String
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 struct CameraConfigUpdate {
   networkId: i64,
   cameraId: i64,
   productType: String,
-  config: serde_json::Value
+  #[schema(value_type = Object)]
+  config: serde_json::Value,
+  /// Send keys the server doesn't know for this device type; known keys are still checked
+  #[serde(rename = "allowUnknown")]
+  allow_unknown: Option<bool>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+struct CameraThumbnailRequest {
+  network_id: i64,
+  camera_id: i64,
+  product_type: String
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 struct ThumbnailQuery {
   path: String
 }
 
+#[derive(Deserialize, ToSchema)]
+struct PrefetchThumbnailsRequest {
+  /// Thumbnail paths as in `/api/thumbnail-base64`, at most 64; repeats are fetched once
+  paths: Vec<String>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SignQuery {
+  url: String,
+  ttl: Option<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MediaAllQuery {
+  #[serde(rename = "sinceDays")]
+  since_days: Option<i64>,
+  #[serde(rename = "maxPages")]
+  max_pages: Option<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct MediaIndexQuery {
+  /// RFC 3339 lower bound on `created_at`.
+  from: Option<String>,
+  /// RFC 3339 upper bound on `created_at`.
+  to: Option<String>,
+  #[serde(rename = "cameraId")]
+  camera_id: Option<i64>,
+  #[serde(rename = "networkId")]
+  network_id: Option<i64>,
+  /// Case-insensitive match on camera or network name.
+  text: Option<String>,
+  #[serde(rename = "includeDeleted")]
+  include_deleted: Option<bool>,
+  page: Option<u32>,
+  #[serde(rename = "pageSize")]
+  page_size: Option<u32>
+}
+
+impl From<MediaIndexQuery> for MediaFilter {
+  fn from(query: MediaIndexQuery) -> Self {
+    MediaFilter {
+      from: query.from,
+      to: query.to,
+      camera_id: query.camera_id,
+      network_id: query.network_id,
+      text: query.text,
+      include_deleted: query.include_deleted.unwrap_or(false),
+      page: query.page,
+      page_size: query.page_size
+    }
+  }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BatteryHistoryQuery {
+  camera_id: i64,
+  /// How many days back to return (default 7, max 365); points older than 7 days are hourly averages
+  days: Option<u32>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct HeatmapQuery {
+  #[serde(rename = "cameraId")]
+  camera_id: i64,
+  /// RFC 3339; defaults to 28 days before `to`
+  from: Option<chrono::DateTime<chrono::Utc>>,
+  /// RFC 3339; defaults to now. The range may cover at most 366 days
+  to: Option<chrono::DateTime<chrono::Utc>>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SummaryReportQuery {
+  /// RFC 3339; defaults to 7 days before `to`
+  from: Option<chrono::DateTime<chrono::Utc>>,
+  /// RFC 3339; defaults to now. The range may cover at most 366 days
+  to: Option<chrono::DateTime<chrono::Utc>>,
+  /// `json` (default) or `html`
+  format: Option<String>
+}
+
+#[derive(Deserialize, ToSchema)]
+struct WebhookPreviewRequest {
+  /// `slack`, `discord` or `ntfy`; takes precedence over `template`
+  #[schema(value_type = Option<String>)]
+  format: Option<BuiltinFormat>,
+  /// JSON payload with `{{path}}` placeholders, e.g. `{"msg": "{{summary}}", "seq": {{seq}}}`
+  template: Option<String>,
+  /// Event to render; a sample `arm` event when omitted
+  #[schema(value_type = Option<crate::openapi::EventSchema>)]
+  event: Option<Event>
+}
+
+#[derive(Deserialize, ToSchema)]
+struct NotifyTestRequest {
+  /// Target to try before saving it; every configured notifier when omitted
+  #[schema(value_type = Option<crate::openapi::NotifierTargetSchema>)]
+  target: Option<notify::NotifierTarget>,
+  /// `motion` (default), `offline`, `low_battery` or `usb_storage`
+  #[schema(value_type = Option<String>)]
+  kind: Option<notify::AlertKind>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RevokeClientQuery {
+  /// Revoke even if it is the client this server is signed in as, which signs the server out
+  #[serde(default)]
+  force: bool
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct EventStreamQuery {
+  /// Start after this sequence number; 0 or omitted starts from the oldest kept
+  after_seq: Option<i64>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ReplayQuery {
+  /// Return events after this sequence number; 0 or omitted starts from the oldest kept
+  after_seq: Option<i64>,
+  /// Most events to return (default 500, max 5000)
+  limit: Option<u32>
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AuditQuery {
+  /// Maximum entries to return, newest first (default 100, at most 5000).
+  limit: Option<usize>,
+  /// Exact action, e.g. `arm`, `delete_media`, `liveview_start`.
+  action: Option<String>,
+  /// Substring of the target, e.g. `network 123`.
+  target: Option<String>,
+  /// Exact source: `server`, `scheduler`, `signed-url` or `api-key:<id>`.
+  source: Option<String>,
+  since: Option<chrono::DateTime<chrono::Utc>>,
+  until: Option<chrono::DateTime<chrono::Utc>>,
+  #[serde(rename = "failedOnly")]
+  failed_only: Option<bool>
+}
+
+#[derive(Deserialize, ToSchema)]
+struct LogLevelRequest {
+  /// One of `blink`, `http`, `immi` or `liveview`
+  target: String,
+  #[schema(value_type = openapi::LogLevelSchema)]
+  level: log_control::Level,
+  /// How long before the target goes back to `info` (1 to 3600)
+  #[serde(rename = "durationSecs")]
+  duration_secs: u64
+}
+
+#[derive(Deserialize, ToSchema)]
+struct DebugCaptureRequest {
+  /// Log targets to capture at debug level, e.g. `["immi", "liveview"]`
+  targets: Vec<String>,
+  /// How long to capture (1 to 3600)
+  #[serde(rename = "durationSecs")]
+  duration_secs: u64
+}
+
+#[derive(Serialize, ToSchema)]
+struct DebugCaptureResult {
+  /// The capture file in the data directory
+  path: String
+}
+
+#[derive(Serialize, ToSchema)]
+struct CameraResponse {
+  #[serde(flatten)]
+  #[schema(value_type = openapi::CameraSchema)]
+  camera: Camera,
+  thumbnail_url: Option<String>
+}
+
+#[derive(Serialize, ToSchema)]
+struct MediaItemResponse {
+  #[serde(flatten)]
+  #[schema(value_type = openapi::MediaItemSchema)]
+  item: MediaItem,
+  media_url: Option<String>,
+  thumbnail_url: Option<String>
+}
+
+#[derive(Parser)]
+#[command(name = "blink-monitor-server", version, about = "Blink Monitor API server")]
+struct Cli {
+  #[command(subcommand)]
+  command: Option<Command>
+}
+
+#[derive(Subcommand)]
+enum Command {
+  /// Serve the API (the default)
+  Serve,
+  /// Sign in on this terminal, save the session and exit; run it before exposing the server
+  Login {
+    /// Account email; prompted for when omitted
+    #[arg(long)]
+    email: Option<String>,
+    /// Read the password from this file (e.g. a Docker secret) instead of prompting
+    #[arg(long)]
+    password_file: Option<PathBuf>
+  },
+  /// Write the session, settings and optionally the media index to an encrypted archive
+  Export {
+    /// Archive to write
+    path: PathBuf,
+    /// Include the media index database
+    #[arg(long)]
+    media_index: bool,
+    /// Read the archive passphrase from this file instead of prompting
+    #[arg(long)]
+    passphrase_file: Option<PathBuf>
+  },
+  /// Restore an archive written by `export` or the desktop app; run with the server stopped
+  Import {
+    /// Archive to read
+    path: PathBuf,
+    /// Read the archive passphrase from this file instead of prompting
+    #[arg(long)]
+    passphrase_file: Option<PathBuf>
+  }
+}
+
+const LOGIN_HINT: &str = "No Blink session is stored, so every Blink call will fail with 401 until one is. \
+Sign in with `blink-monitor-server login` (Docker: `docker compose run --rm api blink-monitor-server login`) \
+and restart the server or POST /api/reload-auth, or use POST /api/login and /api/verify-pin.";
+
 #[tokio::main]
 async fn main() {
+  let cli = Cli::parse();
+  audit::init(storage::data_dir().join(audit::AUDIT_FILE), "server");
+  device_routes::init(storage::data_dir().join(device_routes::DEVICE_ROUTES_FILE));
+  clock_skew::init(storage::data_dir().join(clock_skew::CLOCK_SKEW_FILE));
+  shared::init_auth_persistence(storage::save_auth);
+  let result = match cli.command {
+    None | Some(Command::Serve) => None,
+    Some(Command::Login { email, password_file }) => {
+      Some(bootstrap::login(email, password_file.as_deref()).await.map(|email| format!("Logged in as {}", email)))
+    }
+    Some(Command::Export { path, media_index, passphrase_file }) => {
+      Some(bootstrap::export_state(&path, media_index, passphrase_file.as_deref()).map(|summary| {
+        format!("Wrote {} ({} bytes; session: {}, media index: {})", summary.path, summary.bytes, summary.auth, summary.media_index)
+      }))
+    }
+    Some(Command::Import { path, passphrase_file }) => {
+      Some(bootstrap::import_state(&path, passphrase_file.as_deref()).map(|summary| {
+        format!("Restored state exported at {} (session: {}, media index: {})", summary.exported_at, summary.auth, summary.media_index)
+      }))
+    }
+  };
+  if let Some(result) = result {
+    match result {
+      Ok(message) => println!("{}", message),
+      Err(e) => {
+        eprintln!("Error: {:#}", e);
+        std::process::exit(1);
+      }
+    }
+    return;
+  }
+
   let blink_client = Arc::new(Mutex::new(BlinkClient::new()));
-  if let Ok(saved) = storage::load_auth() {
-    let mut client = blink_client.lock().await;
-    *client = BlinkClient::from_state(saved);
+  match storage::load_auth() {
+    Ok(saved) => {
+      let mut client = blink_client.lock().await;
+      *client = BlinkClient::from_state(saved);
+    }
+    Err(e) if e.downcast_ref::<std::io::Error>().is_some_and(|e| e.kind() == std::io::ErrorKind::NotFound) => {}
+    Err(e) => eprintln!("Failed to load saved auth: {}", e)
   }
+  if blink_client.lock().await.token.is_none() {
+    eprintln!("{}", LOGIN_HINT);
+  }
+
+  let signer = match UrlSigner::load_or_create(&storage::signing_key_path()) {
+    Ok(signer) => signer,
+    Err(e) => {
+      eprintln!("Failed to persist URL signing key, signed URLs will not survive restarts: {}", e);
+      UrlSigner::random()
+    }
+  };
+  let api_key = std::env::var("BLINK_API_KEY").ok().filter(|k| !k.is_empty());
+
+  let settings = Settings::load(&storage::settings_path());
+  let state = Arc::new(ServerState {
+    blink_client,
+    signer,
+    api_key,
+    live_sessions: Arc::new(live_sessions::SessionRegistry::default()),
+    readiness: ready::ReadinessCache::default(),
+    media_index: MediaIndex::new(storage::data_dir().join(media_index::INDEX_FILE), settings.media_index_enabled),
+    settings: std::sync::RwLock::new(settings),
+    scheduler: Scheduler::load(storage::data_dir().join("schedule_state.json")),
+    trash: Trash::load(storage::data_dir().join(trash::TRASH_FILE)),
+    delete_queue: DeleteQueue::load(storage::data_dir().join(delete_queue::DELETE_QUEUE_FILE)),
+    firmware: FirmwareTracker::load(storage::data_dir().join(firmware::FIRMWARE_FILE)),
+    cleanup: Arc::new(CleanupQueue::load(storage::data_dir().join(cleanup::CLEANUP_FILE))),
+    events: EventLog::new(storage::data_dir().join(events::EVENTS_FILE)),
+    rearm: RearmQueue::load(storage::data_dir().join(rearm::REARM_FILE)),
+    battery: BatteryStore::new(storage::data_dir().join(battery::BATTERY_FILE)),
+    takeout: Takeouts::load(storage::data_dir().join(takeout::TAKEOUT_FILE), storage::data_dir().join("takeout")),
+    request_budgets: timeouts::RouteBudgets::from_env(),
+    thumbnails: ThumbnailMemory::new(thumbnail_cache::MAX_MEMORY_BYTES),
+    fetch_permits: Semaphore::new(media::MAX_CONCURRENT_FETCHES)
+  });
+
+  let heal_state = state.clone();
+  shared::on_tier_healed(move |heal| heal_state.events.record("tier_healed", heal));
+  let expiring_state = state.clone();
+  liveview::on_expiring(move |expiring| expiring_state.events.record("live_expiring", expiring));
+
+  tokio::spawn(drive_schedules(state.clone()));
+  tokio::spawn(drive_media_index(state.clone()));
+  tokio::spawn(drive_trash(state.clone()));
+  tokio::spawn(drive_delete_queue(state.clone()));
+  tokio::spawn(drive_takeout(state.clone()));
+  tokio::spawn(drive_firmware(state.clone()));
+  tokio::spawn(drive_battery(state.clone()));
+  tokio::spawn(drive_cleanup(state.clone()));
+  tokio::spawn(drive_event_pruning(state.clone()));
+  tokio::spawn(drive_rearm(state.clone()));
+  tokio::spawn(drive_webhooks(state.clone()));
+  tokio::spawn(drive_summary_report(state.clone()));
+  tokio::spawn(drive_alerts(state.clone()));
 
-  let state = Arc::new(ServerState { blink_client });
+  let port = std::env::var("PORT").unwrap_or_else(|_| "3020".to_string());
+  let addr = format!("0.0.0.0:{}", port);
+  let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+  println!("Blink Monitor API listening on {}", addr);
+  axum::serve(listener, router(state)).await.unwrap();
+}
 
+/// Every route, under its time budget and behind the API key check and CORS. Takes the state ready-made (whatever
+/// `BlinkClient` it holds) so the whole API can be served without touching storage.
+fn router(state: Arc<ServerState>) -> Router {
   let cors = CorsLayer::new()
     .allow_origin(Any)
-    .allow_methods([Method::GET, Method::POST])
-    .allow_headers(Any);
+    .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
+    .allow_headers(Any)
+    .expose_headers([
+      http::HeaderName::from_static("x-blink-error-code"),
+      http::header::RETRY_AFTER,
+      http::HeaderName::from_static(thumbnail_cache::CACHE_HEADER),
+      http::HeaderName::from_static(thumbnail_cache::STALE_HEADER)
+    ]);
 
-  let app = Router::new()
+  Router::new()
     .merge(media::router())
     .route("/api/health", get(health))
+    .route("/api/ready", get(ready::ready))
+    .route("/api/self-test", get(run_self_test))
+    .route("/api/log-level", post(set_log_level))
+    .route("/api/debug-capture", post(start_debug_capture))
     .route("/api/check-auth", get(check_auth))
+    .route("/api/reload-auth", post(reload_auth))
     .route("/api/login", post(login))
+    .route("/api/login/amazon", get(get_amazon_signin_url).post(login_amazon))
+    .route("/api/login/amazon/callback", post(complete_amazon_login))
     .route("/api/verify-pin", post(verify_pin))
     .route("/api/logout", post(logout))
+    .route("/api/trusted-clients", get(list_trusted_clients))
+    .route("/api/trusted-clients/:id", delete(revoke_trusted_client))
     .route("/api/homescreen", get(get_raw_homescreen))
+    .route("/api/liveview-candidates", get(get_liveview_setting_candidates))
     .route("/api/media", get(get_raw_media_page))
     .route("/api/set-arm", post(set_network_arm))
+    .route("/api/set-arm-all", post(set_all_networks_arm))
+    .route("/api/networks", get(get_networks))
+    .route("/api/disarm-temporary", get(get_pending_rearms).post(disarm_temporary))
+    .route("/api/disarm-temporary/extend", post(extend_temporary_disarm))
+    .route("/api/disarm-temporary/:network_id", delete(cancel_temporary_disarm))
+    .route("/api/firmware", get(get_firmware_report))
+    .route("/api/usage", get(get_usage))
+    .route("/api/battery-history", get(get_battery_history))
+    .route("/api/events", get(stream_events))
+    .route("/api/events/replay", get(replay_events))
+    .route("/api/webhooks/preview", post(preview_webhook))
+    .route("/api/reports/summary", get(get_summary_report))
+    .route("/api/heatmap", get(get_activity_heatmap))
+    .route("/api/notify/test", post(test_notify))
     .route("/api/delete-media", post(delete_media_items))
+    .route("/api/delete-media/undo", post(undo_delete))
+    .route("/api/delete-media/pending", get(get_pending_deletions))
+    .route("/api/delete-media/queue", get(get_delete_queue))
+    .route("/api/delete-media/queue/:job_id", delete(cancel_delete_job))
+    .route("/api/archive/list", get(list_archive))
+    .route("/api/takeout", post(start_takeout))
+    .route("/api/takeout/:id", get(get_takeout).delete(cancel_takeout))
+    .route("/api/cleanup-queue", get(get_cleanup_queue))
     .route("/api/camera-config", get(get_camera_config).post(update_camera_config))
+    .route("/api/notifications", get(get_notification_config).post(update_notification_config))
+    .route("/api/network-settings", get(get_network_settings).post(update_network_settings))
+    .route("/api/liveview-settings", get(get_liveview_settings).post(set_liveview_setting))
+    .route("/api/motion-notifications", get(get_motion_notifications).post(set_motion_notifications))
+    .route("/api/sync-module-storage", get(get_sync_module_storage))
+    .route("/api/sync-module-storage/eject", post(eject_usb))
+    .route("/api/camera-thumbnail", post(refresh_camera_thumbnail))
     .route("/api/thumbnail-base64", get(get_thumbnail_base64))
+    .route("/api/thumbnails/prefetch", post(prefetch_thumbnails))
+    .route("/api/settings", get(get_settings).patch(update_settings))
+    .route("/api/audit", get(get_audit_log))
+    .route("/api/camera-prefs", get(get_camera_prefs))
+    .route("/api/camera-prefs/:id", put(set_camera_prefs))
+    .route("/api/schedules", get(get_schedules).post(save_schedule_entry))
+    .route("/api/schedules/:id", delete(delete_schedule_entry))
+    .route("/api/sign", get(sign_url))
+    .route("/api/cameras", get(get_cameras))
+    .route("/api/media/all", get(get_all_media))
+    .route("/api/timeline", get(get_timeline))
+    .route("/api/media/index", get(search_media_index))
+    .route("/api/media/index/rebuild", post(rebuild_media_index))
+    .route("/api/media/watched", post(mark_media_watched))
+    .route("/api/diagnose-media", post(diagnose_media))
+    .route("/api/openapi.json", get(openapi::openapi_json))
+    .route("/api/docs", get(openapi::swagger_ui))
+    .route_layer(middleware::from_fn_with_state(state.clone(), timeouts::enforce))
+    .layer(middleware::from_fn_with_state(state.clone(), require_api_key))
     .with_state(state)
-    .layer(cors);
-
-  let port = std::env::var("PORT").unwrap_or_else(|_| "3020".to_string());
-  let addr = format!("0.0.0.0:{}", port);
-  let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-  println!("Blink Monitor API listening on {}", addr);
-  axum::serve(listener, app).await.unwrap();
+    .layer(cors)
 }
 
+#[utoipa::path(
+  get, path = "/api/health", tag = "meta",
+  responses((status = 200, description = "Process is up", body = String, content_type = "text/plain"))
+)]
 async fn health() -> &'static str {
   "ok"
 }
 
-async fn check_auth(State(state): State<Arc<ServerState>>) -> ApiResult<Json<bool>> {
+#[utoipa::path(
+  get, path = "/api/self-test", tag = "meta",
+  responses(
+    (status = 200, description = "Each stage (storage, token, refresh, tier, homescreen, thumbnail, media page) with its timing. Stages after the first failure are skipped; `failed_stage` and that stage's `error` say where things broke", body = crate::openapi::SelfTestReportSchema)
+  ),
+  security(("api_key" = []))
+)]
+async fn run_self_test(State(state): State<Arc<ServerState>>) -> Json<SelfTestReport> {
+  Json(self_test::run(&state.blink_client, &storage::data_dir()).await)
+}
+
+#[utoipa::path(
+  post, path = "/api/log-level", tag = "meta",
+  request_body = LogLevelRequest,
+  responses(
+    (status = 200, description = "The new level and when it reverts to info", body = crate::openapi::LevelOverrideSchema),
+    (status = 400, description = "Unknown target or duration out of range", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_log_level(Json(payload): Json<LogLevelRequest>) -> ApiResult<Json<log_control::LevelOverride>> {
+  log_control::set_level(&payload.target, payload.level, payload.duration_secs)
+    .map(Json)
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into())
+}
+
+#[utoipa::path(
+  post, path = "/api/debug-capture", tag = "meta",
+  request_body = DebugCaptureRequest,
+  responses(
+    (status = 200, description = "Answers as the capture starts, with the file it goes to: the logs of a self-test run as the capture starts, an `# ended` line when it ends, then the self-test report. Tokens and query strings are redacted", body = DebugCaptureResult),
+    (status = 400, description = "No or unknown targets, or duration out of range", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn start_debug_capture(State(state): State<Arc<ServerState>>, Json(payload): Json<DebugCaptureRequest>) -> ApiResult<Json<DebugCaptureResult>> {
+  let (path, _) = self_test::capture_with_report(state.blink_client.clone(), &storage::data_dir(), &payload.targets, payload.duration_secs)
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  Ok(Json(DebugCaptureResult { path: path.display().to_string() }))
+}
+
+fn provided_api_key(headers: &HeaderMap) -> Option<&str> {
+  if let Some(key) = headers.get("x-api-key").and_then(|v| v.to_str().ok()) {
+    return Some(key);
+  }
+  headers.get(http::header::AUTHORIZATION)
+    .and_then(|v| v.to_str().ok())
+    .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Whether `provided` is the API key, in time that doesn't depend on where they differ:
+/// both are MACed under the expected key and the tags compared with `verify_slice`.
+fn api_key_matches(provided: &str, expected: &str) -> bool {
+  use hmac::{Hmac, Mac};
+  let tag = |key: &str| {
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(expected.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(key.as_bytes());
+    mac
+  };
+  tag(provided).verify_slice(&tag(expected).finalize().into_bytes()).is_ok()
+}
+
+/// Audit source for requests made with `key`: a short hash, so the log never holds the key.
+fn api_key_id(key: &str) -> String {
+  use sha2::{Digest, Sha256};
+  let digest = Sha256::digest(key.as_bytes());
+  format!("api-key:{}", digest[..4].iter().map(|b| format!("{:02x}", b)).collect::<String>())
+}
+
+async fn require_api_key(State(state): State<Arc<ServerState>>, req: Request, next: Next) -> Response {
+  let Some(expected) = state.api_key.as_deref() else {
+    return next.run(req).await;
+  };
+  let path = req.uri().path();
+  if matches!(path, "/api/health" | "/api/ready" | "/api/openapi.json" | "/api/docs") {
+    return next.run(req).await;
+  }
+  if provided_api_key(req.headers()).is_some_and(|key| api_key_matches(key, expected)) {
+    return audit::with_source(api_key_id(expected), next.run(req)).await;
+  }
+
+  // Media and live routes are loaded by <video>/<img> tags that cannot send headers,
+  // so a valid signature from /api/sign stands in for the API key there.
+  if UrlSigner::is_signable(path) {
+    return match state.signer.verify(path, req.uri().query(), chrono::Utc::now().timestamp()) {
+      Ok(()) => audit::with_source("signed-url".to_string(), next.run(req)).await,
+      Err(signing::SignatureError::Missing) => (StatusCode::UNAUTHORIZED, "Missing API key or signature").into_response(),
+      Err(e) => (StatusCode::FORBIDDEN, e.to_string()).into_response(),
+    };
+  }
+
+  (StatusCode::UNAUTHORIZED, "Missing or invalid API key").into_response()
+}
+
+#[utoipa::path(
+  get, path = "/api/sign", tag = "media",
+  params(SignQuery),
+  responses(
+    (status = 200, description = "Proxy URL with `exp` and `sig` appended", body = String, content_type = "text/plain"),
+    (status = 400, description = "URL is not a signable proxy or live route", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn sign_url(State(state): State<Arc<ServerState>>, Query(query): Query<SignQuery>) -> ApiResult<String> {
+  let path = query.url.split('?').next().unwrap_or_default();
+  if !UrlSigner::is_signable(path) {
+    return Err((StatusCode::BAD_REQUEST, "Only proxy and live URLs can be signed".to_string()).into());
+  }
+  let ttl = query.ttl.unwrap_or(signing::DEFAULT_TTL_SECS).clamp(1, signing::MAX_TTL_SECS);
+  state.signer.sign(&query.url, chrono::Utc::now().timestamp() + ttl)
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()).into())
+}
+
+#[utoipa::path(
+  get, path = "/api/check-auth", tag = "auth",
+  responses((status = 200, description = "Session state; `reauth_required` means Blink revoked the saved session", body = openapi::AuthStateSchema)),
+  security(("api_key" = []))
+)]
+async fn check_auth(State(state): State<Arc<ServerState>>) -> ApiResult<Json<AuthState>> {
   let mut client = state.blink_client.lock().await;
   if let Ok(saved) = storage::load_auth() {
-    *client = BlinkClient::from_state(saved);
+    client.hydrate_from(saved);
   }
+  Ok(Json(client.auth_state()))
+}
+
+#[utoipa::path(
+  post, path = "/api/reload-auth", tag = "auth",
+  responses(
+    (status = 200, description = "Whether the reloaded state has a token", body = bool),
+    (status = 500, description = "Stored auth could not be read", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn reload_auth(State(state): State<Arc<ServerState>>) -> ApiResult<Json<bool>> {
+  let saved = storage::load_auth().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to load auth: {}", e)))?;
+  let mut client = state.blink_client.lock().await;
+  *client = BlinkClient::from_state(saved);
   Ok(Json(client.token.is_some()))
 }
 
+#[utoipa::path(
+  post, path = "/api/login", tag = "auth",
+  request_body = LoginRequest,
+  responses(
+    (status = 200, description = "`SUCCESS`, `2FA_REQUIRED`, or `AMAZON_LOGIN_REQUIRED` for accounts that sign in with Amazon (continue with `/api/login/amazon`)", body = String, content_type = "text/plain"),
+    (status = 401, description = "Login rejected", body = ErrorBody, content_type = "text/plain"),
+    (status = 429, description = "Too many attempts; nothing is sent to Blink again until the wait has passed", body = ErrorBody, content_type = "text/plain",
+      headers(("Retry-After" = u64, description = "Seconds to wait before trying again, when Blink said"))),
+    (status = 500, description = "OAuth flow could not be started", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
 async fn login(State(state): State<Arc<ServerState>>, Json(payload): Json<LoginRequest>) -> ApiResult<String> {
   let mut client = state.blink_client.lock().await;
-  client.start_oauth_flow().await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("OAuth Init Failed: {}", e)))?;
-  match client.login_oauth(&payload.email, &payload.password).await {
+  client.start_oauth_flow().await.map_err(|e| match e.downcast_ref::<BlinkError>() {
+    Some(BlinkError::RateLimited { .. }) => upstream_error(e),
+    _ => (StatusCode::INTERNAL_SERVER_ERROR, format!("OAuth Init Failed: {}", e)).into()
+  })?;
+  match client.login_oauth(&payload.email, payload.password).await {
     Ok(status) => {
       if status == "SUCCESS" {
         let _ = storage::save_auth(&client.get_state());
       }
       Ok(status)
     }
-    Err(e) => Err((StatusCode::UNAUTHORIZED, format!("Login Failed: {}", e)))
+    Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::RateLimited { .. })) => Err(upstream_error(e)),
+    Err(e) => Err((StatusCode::UNAUTHORIZED, format!("Login Failed: {}", e)).into())
   }
 }
 
+#[utoipa::path(
+  get, path = "/api/login/amazon", tag = "auth",
+  responses(
+    (status = 200, description = "The Login with Amazon page to sign in on, or null when no Amazon sign-in is in progress", body = Option<String>)
+  ),
+  security(("api_key" = []))
+)]
+async fn get_amazon_signin_url(State(state): State<Arc<ServerState>>) -> Json<Option<String>> {
+  Json(state.blink_client.lock().await.amazon_signin_url())
+}
+
+#[utoipa::path(
+  post, path = "/api/login/amazon", tag = "auth",
+  request_body = LoginRequest,
+  responses(
+    (status = 200, description = "`SUCCESS` or `2FA_REQUIRED`", body = String, content_type = "text/plain"),
+    (status = 401, description = "Amazon rejected the sign-in or asked for more (OTP, captcha); sign in on the page from `GET /api/login/amazon` in a browser and post where it ends to `/api/login/amazon/callback`", body = ErrorBody, content_type = "text/plain"),
+    (status = 429, description = "Too many attempts", body = ErrorBody, content_type = "text/plain",
+      headers(("Retry-After" = u64, description = "Seconds to wait before trying again, when Blink said")))
+  ),
+  security(("api_key" = []))
+)]
+async fn login_amazon(State(state): State<Arc<ServerState>>, Json(payload): Json<LoginRequest>) -> ApiResult<String> {
+  let mut client = state.blink_client.lock().await;
+  let result = client.login_amazon(&payload.email, payload.password).await;
+  finish_amazon_login(&client, result)
+}
+
+#[utoipa::path(
+  post, path = "/api/login/amazon/callback", tag = "auth",
+  request_body = AmazonCallbackRequest,
+  responses(
+    (status = 200, description = "`SUCCESS` or `2FA_REQUIRED`", body = String, content_type = "text/plain"),
+    (status = 401, description = "Not a Blink callback, or Blink rejected it", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn complete_amazon_login(State(state): State<Arc<ServerState>>, Json(payload): Json<AmazonCallbackRequest>) -> ApiResult<String> {
+  let mut client = state.blink_client.lock().await;
+  let result = client.complete_amazon_login(&payload.url).await;
+  finish_amazon_login(&client, result)
+}
+
+fn finish_amazon_login(client: &BlinkClient, result: anyhow::Result<String>) -> ApiResult<String> {
+  match result {
+    Ok(status) => {
+      if status == "SUCCESS" {
+        let _ = storage::save_auth(&client.get_state());
+      }
+      Ok(status)
+    }
+    Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::RateLimited { .. })) => Err(upstream_error(e)),
+    Err(e) => Err((StatusCode::UNAUTHORIZED, format!("Amazon Login Failed: {}", e)).into())
+  }
+}
+
+#[utoipa::path(
+  post, path = "/api/verify-pin", tag = "auth",
+  request_body = PinRequest,
+  responses(
+    (status = 200, description = "PIN accepted", body = String, content_type = "text/plain"),
+    (status = 401, description = "PIN rejected", body = ErrorBody, content_type = "text/plain"),
+    (status = 429, description = "Too many attempts", body = ErrorBody, content_type = "text/plain",
+      headers(("Retry-After" = u64, description = "Seconds to wait before trying again, when Blink said")))
+  ),
+  security(("api_key" = []))
+)]
 async fn verify_pin(State(state): State<Arc<ServerState>>, Json(payload): Json<PinRequest>) -> ApiResult<String> {
   let mut client = state.blink_client.lock().await;
-  match client.verify_pin_oauth(&payload.pin).await {
+  match client.verify_pin_oauth(payload.pin).await {
     Ok(_) => {
       let _ = storage::save_auth(&client.get_state());
       Ok("SUCCESS".to_string())
     }
-    Err(e) => Err((StatusCode::UNAUTHORIZED, format!("Verification Failed: {}", e)))
+    Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::RateLimited { .. })) => Err(upstream_error(e)),
+    Err(e) => Err((StatusCode::UNAUTHORIZED, format!("Verification Failed: {}", e)).into())
   }
 }
 
+#[utoipa::path(
+  post, path = "/api/logout", tag = "auth",
+  responses(
+    (status = 200, description = "Stored auth cleared", body = String, content_type = "text/plain"),
+    (status = 500, description = "Stored auth could not be removed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
 async fn logout(State(state): State<Arc<ServerState>>) -> ApiResult<String> {
   let mut client = state.blink_client.lock().await;
-  client.token = None;
-  client.refresh_token = None;
-  client.account_id = None;
-  client.token_expiry = None;
+  client.logout();
   storage::clear_auth().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
   Ok("Logged out successfully".to_string())
 }
 
+#[utoipa::path(
+  get, path = "/api/trusted-clients", tag = "auth",
+  responses(
+    (status = 200, description = "Devices the Blink account trusts; `current` marks the one this server is signed in as", body = Vec<crate::openapi::TrustedClientSchema>),
+    (status = 404, description = "The account's tier serves no client list", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn list_trusted_clients(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<TrustedClient>>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  Ok(Json(client.list_trusted_clients().await.map_err(upstream_error)?))
+}
+
+#[utoipa::path(
+  delete, path = "/api/trusted-clients/{id}", tag = "auth",
+  params(("id" = i64, Path, description = "Trusted client id"), RevokeClientQuery),
+  responses(
+    (status = 200, description = "The outcome. Without `force`, the current client is left alone and `warning` says why; with it, the server is signed out after revoking", body = crate::openapi::RevokeOutcomeSchema),
+    (status = 404, description = "No such client", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn revoke_trusted_client(
+  State(state): State<Arc<ServerState>>,
+  Path(id): Path<i64>,
+  Query(query): Query<RevokeClientQuery>
+) -> ApiResult<Json<RevokeOutcome>> {
+  let outcome = shared::revoke_trusted_client(&state.blink_client, id, query.force).await.map_err(upstream_error)?;
+  if outcome.signed_out {
+    storage::clear_auth().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  }
+  Ok(Json(outcome))
+}
+
+#[utoipa::path(
+  get, path = "/api/homescreen", tag = "devices",
+  responses(
+    (status = 200, description = "Raw Blink homescreen JSON", body = String, content_type = "application/json"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
 async fn get_raw_homescreen(State(state): State<Arc<ServerState>>) -> ApiResult<String> {
   let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
-  }
-  client.get_raw_homescreen().await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.get_raw_homescreen().await.map_err(upstream_error)
 }
 
-async fn get_raw_media_page(State(state): State<Arc<ServerState>>, Query(query): Query<MediaQuery>) -> ApiResult<String> {
+#[utoipa::path(
+  get, path = "/api/liveview-candidates", tag = "devices",
+  responses(
+    (status = 200, description = "Homescreen settings that look like liveview-save toggles, with the network and device each belongs to", body = Vec<LiveviewSettingCandidate>),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_liveview_setting_candidates(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<LiveviewSettingCandidate>>> {
   let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
-  }
+  let _ = shared::refresh_and_persist(&mut client).await;
+  let json = client.get_homescreen_json().await.map_err(upstream_error)?;
+  Ok(Json(shared::liveview_candidates(std::slice::from_ref(&*json))))
+}
+
+#[utoipa::path(
+  get, path = "/api/media", tag = "media",
+  params(MediaQuery),
+  responses(
+    (status = 200, description = "Raw Blink media page JSON", body = String, content_type = "application/json"),
+    (status = 422, description = "page, sinceDays or pageSize out of range", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_raw_media_page(State(state): State<Arc<ServerState>>, Query(query): Query<MediaQuery>) -> ApiResult<String> {
   let page = query.page.unwrap_or(1);
   let since_days = query.sinceDays.unwrap_or(30);
-  client.get_raw_media_page(page, since_days).await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))
+  blink::check_media_page(page, since_days, query.page_size).map_err(|e| upstream_error(e.into()))?;
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  let raw = client.get_raw_media_page(page, since_days, query.page_size).await.map_err(upstream_error)?;
+  state.media_index.upsert_raw_page(&raw);
+  Ok(state.trash.filter_raw_page(raw))
 }
 
-async fn set_network_arm(State(state): State<Arc<ServerState>>, Json(payload): Json<SetArmRequest>) -> ApiResult<String> {
-  let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
+#[utoipa::path(
+  post, path = "/api/set-arm", tag = "devices",
+  request_body = SetArmRequest,
+  responses(
+    (status = 200, description = "Blink accepted the change. `confirmed` is false, with a `warning`, when the homescreen didn't show the new state within the timeout", body = crate::openapi::ArmVerificationSchema),
+    (status = 502, description = "Blink rejected the request or the arm command failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_network_arm(State(state): State<Arc<ServerState>>, Json(payload): Json<SetArmRequest>) -> ApiResult<Json<ArmVerification>> {
+  let timeout = payload.timeout_secs.unwrap_or(blink::ARM_VERIFY_TIMEOUT_SECS).clamp(1, blink::MAX_ARM_VERIFY_TIMEOUT_SECS);
+  let client = shared::snapshot(&state.blink_client).await;
+  let verification = client.set_arm_verified(payload.networkId, payload.arm, std::time::Duration::from_secs(timeout)).await
+    .map_err(upstream_error)?;
+  state.rearm.cancel(payload.networkId);
+  state.events.record("arm", serde_json::json!({ "network_id": payload.networkId, "verification": &verification }));
+  Ok(Json(verification))
+}
+
+#[utoipa::path(
+  post, path = "/api/set-arm-all", tag = "devices",
+  request_body = SetArmAllRequest,
+  responses(
+    (status = 200, description = "One result per network; networks are armed concurrently, so some may succeed while others fail", body = Vec<crate::openapi::NetworkArmResultSchema>),
+    (status = 502, description = "The network list could not be read", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_all_networks_arm(State(state): State<Arc<ServerState>>, Json(payload): Json<SetArmAllRequest>) -> ApiResult<Json<Vec<NetworkArmResult>>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  let results = client.set_arm_all(payload.arm).await.map_err(upstream_error)?;
+  for result in results.iter().filter(|r| r.ok) {
+    state.rearm.cancel(result.network_id);
+    state.events.record("arm", serde_json::json!({ "network_id": result.network_id, "requested": payload.arm }));
+  }
+  Ok(Json(results))
+}
+
+#[utoipa::path(
+  get, path = "/api/networks", tag = "devices",
+  responses(
+    (status = 200, description = "Networks with their arm state; `rearm_at` is set while a temporary disarm is pending", body = Vec<crate::openapi::NetworkSchema>),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_networks(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<Network>>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  let mut networks = client.get_homescreen().await.map_err(upstream_error)?.networks;
+  state.rearm.annotate(&mut networks);
+  Ok(Json(networks))
+}
+
+#[utoipa::path(
+  post, path = "/api/disarm-temporary", tag = "devices",
+  request_body = DisarmTemporaryRequest,
+  responses(
+    (status = 200, description = "Network disarmed; it is armed again at `rearm.rearm_at`, even if the server restarts in between. Repeating the call replaces the deadline", body = crate::openapi::TemporaryDisarmSchema),
+    (status = 400, description = "`minutes` out of range", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink rejected the disarm", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn disarm_temporary(State(state): State<Arc<ServerState>>, Json(payload): Json<DisarmTemporaryRequest>) -> ApiResult<Json<TemporaryDisarm>> {
+  rearm::validate_minutes(payload.minutes).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  let client = shared::snapshot(&state.blink_client).await;
+  let disarm = state.rearm.disarm_for(&client, payload.network_id, payload.minutes).await.map_err(upstream_error)?;
+  state.events.record("arm", serde_json::json!({ "network_id": payload.network_id, "verification": &disarm.verification, "rearm_at": disarm.rearm.rearm_at }));
+  Ok(Json(disarm))
+}
+
+#[utoipa::path(
+  get, path = "/api/disarm-temporary", tag = "devices",
+  responses((status = 200, description = "Pending re-arms, soonest first", body = Vec<crate::openapi::PendingRearmSchema>)),
+  security(("api_key" = []))
+)]
+async fn get_pending_rearms(State(state): State<Arc<ServerState>>) -> Json<Vec<PendingRearm>> {
+  Json(state.rearm.pending())
+}
+
+#[utoipa::path(
+  post, path = "/api/disarm-temporary/extend", tag = "devices",
+  request_body(content = DisarmTemporaryRequest, description = "`minutes` is added to the current deadline"),
+  responses(
+    (status = 200, description = "The re-arm with its new deadline", body = crate::openapi::PendingRearmSchema),
+    (status = 400, description = "`minutes` out of range", body = ErrorBody, content_type = "text/plain"),
+    (status = 404, description = "No re-arm pending for the network", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn extend_temporary_disarm(State(state): State<Arc<ServerState>>, Json(payload): Json<DisarmTemporaryRequest>) -> ApiResult<Json<PendingRearm>> {
+  rearm::validate_minutes(payload.minutes).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  let pending = state.rearm.extend(payload.network_id, payload.minutes).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+  Ok(Json(pending))
+}
+
+#[utoipa::path(
+  delete, path = "/api/disarm-temporary/{network_id}", tag = "devices",
+  params(("network_id" = i64, Path, description = "Blink network id")),
+  responses(
+    (status = 200, description = "The cancelled re-arm; the network stays disarmed", body = crate::openapi::PendingRearmSchema),
+    (status = 404, description = "No re-arm pending for the network", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn cancel_temporary_disarm(State(state): State<Arc<ServerState>>, Path(network_id): Path<i64>) -> ApiResult<Json<PendingRearm>> {
+  let pending = state.rearm.cancel(network_id)
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No re-arm pending for network {}", network_id)))?;
+  Ok(Json(pending))
+}
+
+/// Re-arms temporarily disarmed networks once their deadline passes, including deadlines
+/// that passed while the server was down.
+async fn drive_rearm(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(rearm::TICK_SECS));
+  loop {
+    interval.tick().await;
+    for run in state.rearm.tick(&state.blink_client).await {
+      eprintln!("{}", run);
+      state.events.record("rearm", &run);
+    }
   }
-  client.set_arm(payload.networkId, payload.arm).await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
-  Ok("Success".to_string())
 }
 
-async fn delete_media_items(State(state): State<Arc<ServerState>>, Json(payload): Json<DeleteMediaRequest>) -> ApiResult<Json<Vec<i64>>> {
+#[utoipa::path(
+  post, path = "/api/delete-media", tag = "media",
+  params(DeleteMediaQuery),
+  request_body = DeleteMediaRequest,
+  responses(
+    (status = 200, description = "Per-id outcome, verified against Blink's media/changed feed; `pending` for every id when `deferSecs` or `queued` is set", body = Vec<DeleteResult>),
+    (status = 502, description = "Blink rejected the delete or the request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn delete_media_items(
+  State(state): State<Arc<ServerState>>,
+  Query(query): Query<DeleteMediaQuery>,
+  Json(payload): Json<DeleteMediaRequest>
+) -> ApiResult<Json<Vec<DeleteResult>>> {
   if payload.items.is_empty() {
     return Ok(Json(Vec::new()));
   }
+  if let Some(secs) = payload.defer_secs.filter(|&secs| secs > 0) {
+    return Ok(Json(state.trash.defer(&payload.items, secs)));
+  }
+  if query.queued {
+    let (_, results) = state.delete_queue.enqueue(&payload.items);
+    return Ok(Json(results));
+  }
 
   let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
+  let _ = shared::refresh_and_persist(&mut client).await;
+
+  let results = client.delete_media_verified(&payload.items).await.map_err(upstream_error)?;
+  if let Err(e) = state.media_index.mark_deleted(&results) {
+    eprintln!("Failed to mark deleted media in index: {}", e);
   }
+  Ok(Json(results))
+}
 
-  let media_ids: Vec<i64> = payload.items.iter()
-    .filter_map(|item| item.get("id").and_then(|v| v.as_i64()))
-    .collect();
+#[utoipa::path(
+  post, path = "/api/delete-media/undo", tag = "media",
+  request_body = UndoDeleteRequest,
+  responses(
+    (status = 200, description = "Ids whose pending deletion was cancelled; ids that were not pending are left out", body = Vec<i64>)
+  ),
+  security(("api_key" = []))
+)]
+async fn undo_delete(State(state): State<Arc<ServerState>>, Json(payload): Json<UndoDeleteRequest>) -> Json<Vec<i64>> {
+  Json(state.trash.undo(&payload.ids))
+}
 
-  let delete_result = client.delete_media(media_ids.clone()).await;
-
-  if delete_result.is_err() {
-    let mut entries: Vec<serde_json::Value> = Vec::new();
-    for item in &payload.items {
-      if let Some(obj) = item.as_object() {
-        let mut map = obj.clone();
-        if let Some(id_val) = map.get("id").cloned() {
-          if !map.contains_key("media_id") {
-            map.insert("media_id".to_string(), id_val.clone());
-          }
-          if let Some(id) = id_val.as_i64() {
-            map.insert("media_id".to_string(), serde_json::json!(id.to_string()));
-          }
-        }
-        entries.push(serde_json::Value::Object(map));
-      } else {
-        entries.push(item.clone());
-      }
-    }
-    let _ = client.delete_media_with_payloads(media_ids.clone(), entries).await;
-  }
-
-  tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-  let raw = client.get_raw_media().await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
-  let parsed: serde_json::Value = serde_json::from_str(&raw).map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-  let mut remaining = Vec::new();
-  if let Some(items) = parsed.get("media").and_then(|v| v.as_array()) {
-    let remaining_ids: std::collections::HashSet<i64> = items.iter()
-      .filter_map(|item| item.get("id").and_then(|v| v.as_i64()))
-      .collect();
-    for id in media_ids {
-      if remaining_ids.contains(&id) {
-        remaining.push(id);
-      }
-    }
+#[utoipa::path(
+  post, path = "/api/diagnose-media", tag = "media",
+  request_body(content = crate::openapi::MediaItemSchema, description = "Media item as returned by `/api/media`; needs `id` and `media` and/or `thumbnail`"),
+  responses(
+    (status = 200, description = "What the clip URL (first byte only) and the thumbnail returned, with latency and whether the host passed the allow-list", body = crate::openapi::MediaDiagnosisSchema),
+    (status = 403, description = "`diagnostics_enabled` is off", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn diagnose_media(State(state): State<Arc<ServerState>>, Json(item): Json<MediaItem>) -> ApiResult<Json<MediaDiagnosis>> {
+  if !state.settings.read().unwrap().diagnostics_enabled {
+    return Err((StatusCode::FORBIDDEN, "Turn on diagnostics_enabled to probe media".to_string()).into());
   }
+  let client = shared::snapshot(&state.blink_client).await;
+  Ok(Json(diagnostics::diagnose_media(&client, &item).await))
+}
+
+#[utoipa::path(
+  get, path = "/api/archive/list", tag = "media",
+  responses(
+    (status = 200, description = "Everything in the configured archive (`archive_store`, or the `archive_dir` folder), whichever backend it is", body = Vec<crate::openapi::StoredClipSchema>),
+    (status = 404, description = "No archive is configured", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "The archive could not be opened or listed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn list_archive(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<StoredClip>>> {
+  let config = state.settings.read().unwrap().archive_store()
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "No archive is configured".to_string()))?;
+  let store = config.open().map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+  let mut clips = store.list().await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+  clips.sort_by(|a, b| a.key.cmp(&b.key));
+  Ok(Json(clips))
+}
+
+/// Deletes the clips Blink recorded for finished liveviews.
+async fn drive_cleanup(state: Arc<ServerState>) {
+  state.cleanup.run(&state.blink_client, || state.settings.read().unwrap().clone()).await
+}
+
+#[utoipa::path(
+  get, path = "/api/cleanup-queue", tag = "live",
+  responses(
+    (status = 200, description = "Finished liveviews whose recorded clip is still being deleted, next check first. Entries leave the queue once the feed shows no clip from the session or after a day", body = Vec<crate::openapi::CleanupEntrySchema>)
+  ),
+  security(("api_key" = []))
+)]
+async fn get_cleanup_queue(State(state): State<Arc<ServerState>>) -> Json<Vec<CleanupEntry>> {
+  Json(state.cleanup.entries())
+}
+
+#[utoipa::path(
+  get, path = "/api/delete-media/pending", tag = "media",
+  responses(
+    (status = 200, description = "Deferred deletions that can still be undone, soonest first", body = Vec<crate::openapi::PendingDeletionSchema>)
+  ),
+  security(("api_key" = []))
+)]
+async fn get_pending_deletions(State(state): State<Arc<ServerState>>) -> Json<Vec<PendingDeletion>> {
+  Json(state.trash.pending())
+}
+
+#[utoipa::path(
+  get, path = "/api/delete-media/queue", tag = "media",
+  responses(
+    (status = 200, description = "Queued delete jobs and the last few finished ones, oldest first; `failed` lists the ids Blink still has", body = Vec<crate::openapi::DeleteProgressSchema>)
+  ),
+  security(("api_key" = []))
+)]
+async fn get_delete_queue(State(state): State<Arc<ServerState>>) -> Json<Vec<DeleteProgress>> {
+  Json(state.delete_queue.status())
+}
 
-  if remaining.is_empty() {
-    return Ok(Json(remaining));
+#[utoipa::path(
+  delete, path = "/api/delete-media/queue/{job_id}", tag = "media",
+  params(("job_id" = String, Path, description = "Delete job id")),
+  responses(
+    (status = 200, description = "The cancelled job; clips not sent yet stay in Blink", body = crate::openapi::DeleteProgressSchema),
+    (status = 404, description = "No running job with that id", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn cancel_delete_job(State(state): State<Arc<ServerState>>, Path(job_id): Path<String>) -> ApiResult<Json<DeleteProgress>> {
+  let progress = state.delete_queue.cancel(&job_id)
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No running delete job {}", job_id)))?;
+  state.events.record("delete_finished", &progress);
+  Ok(Json(progress))
+}
+
+#[utoipa::path(
+  post, path = "/api/takeout", tag = "media",
+  request_body(content = TakeoutRequest, description = "Optional; without a body the takeout goes to a folder in the data directory"),
+  responses(
+    (status = 202, description = "Takeout started; poll `/api/takeout/{id}` for progress", body = crate::openapi::TakeoutProgressSchema),
+    (status = 400, description = "No archive store is configured, or it can't be opened", body = ErrorBody, content_type = "text/plain"),
+    (status = 409, description = "Another takeout is still running", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn start_takeout(State(state): State<Arc<ServerState>>, payload: Option<Json<TakeoutRequest>>) -> ApiResult<(StatusCode, Json<TakeoutProgress>)> {
+  let payload = payload.map(|Json(p)| p).unwrap_or_default();
+  let store = if payload.use_archive_store {
+    let config = state.settings.read().unwrap().archive_store()
+      .ok_or_else(|| (StatusCode::BAD_REQUEST, "No archive store is configured".to_string()))?;
+    Some(config)
+  } else {
+    None
+  };
+  if let Some(running) = state.takeout.running_id() {
+    return Err((StatusCode::CONFLICT, format!("Takeout {} is still running", running)).into());
   }
+  let progress = state.takeout.start(store).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  Ok((StatusCode::ACCEPTED, Json(progress)))
+}
+
+#[utoipa::path(
+  get, path = "/api/takeout/{id}", tag = "media",
+  params(("id" = String, Path, description = "Takeout job id")),
+  responses(
+    (status = 200, description = "Items done and total, bytes written and failures so far; `manifest` is set once the job is done", body = crate::openapi::TakeoutProgressSchema),
+    (status = 404, description = "Unknown takeout", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_takeout(State(state): State<Arc<ServerState>>, Path(id): Path<String>) -> ApiResult<Json<TakeoutProgress>> {
+  let progress = state.takeout.get(&id)
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No takeout {}", id)))?;
+  Ok(Json(progress))
+}
+
+#[utoipa::path(
+  delete, path = "/api/takeout/{id}", tag = "media",
+  params(("id" = String, Path, description = "Takeout job id")),
+  responses(
+    (status = 200, description = "The cancelled takeout; what it already saved is kept", body = crate::openapi::TakeoutProgressSchema),
+    (status = 404, description = "No running takeout with that id", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn cancel_takeout(State(state): State<Arc<ServerState>>, Path(id): Path<String>) -> ApiResult<Json<TakeoutProgress>> {
+  let progress = state.takeout.cancel(&id)
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No running takeout {}", id)))?;
+  state.events.record("takeout_finished", &progress);
+  Ok(Json(progress))
+}
 
-  if let Err(e) = delete_result {
-    return Err((StatusCode::BAD_REQUEST, e.to_string()));
+#[utoipa::path(
+  post, path = "/api/media/watched", tag = "media",
+  request_body = MarkWatchedRequest,
+  responses(
+    (status = 204, description = "Clips marked as viewed; ids that were already watched are accepted"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn mark_media_watched(State(state): State<Arc<ServerState>>, Json(payload): Json<MarkWatchedRequest>) -> ApiResult<StatusCode> {
+  if payload.ids.is_empty() {
+    return Ok(StatusCode::NO_CONTENT);
   }
 
-  Ok(Json(remaining))
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.mark_media_watched(&payload.ids).await.map_err(upstream_error)?;
+  if let Err(e) = state.media_index.mark_watched(&payload.ids) {
+    eprintln!("Failed to mark watched media in index: {}", e);
+  }
+  Ok(StatusCode::NO_CONTENT)
 }
 
+#[utoipa::path(
+  get, path = "/api/camera-config", tag = "devices",
+  params(CameraConfigQuery),
+  responses(
+    (status = 200, description = "Camera configuration as returned by Blink", body = Object),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
 async fn get_camera_config(State(state): State<Arc<ServerState>>, Query(query): Query<CameraConfigQuery>) -> ApiResult<Json<serde_json::Value>> {
   let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
-  }
+  let _ = shared::refresh_and_persist(&mut client).await;
   let res = client.get_camera_config(query.networkId, query.cameraId, &query.productType)
     .await
-    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    .map_err(upstream_error)?;
   Ok(Json(res))
 }
 
-async fn update_camera_config(State(state): State<Arc<ServerState>>, Json(payload): Json<CameraConfigUpdate>) -> ApiResult<StatusCode> {
-  let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
+#[utoipa::path(
+  post, path = "/api/camera-config", tag = "devices",
+  request_body = CameraConfigUpdate,
+  responses(
+    (status = 204, description = "Configuration sent"),
+    (status = 422, description = "The update has unknown keys or values Blink would ignore; nothing was sent. `reason` is `invalid_config`", body = crate::openapi::ConfigRejectedSchema),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn update_camera_config(State(state): State<Arc<ServerState>>, Json(payload): Json<CameraConfigUpdate>) -> ApiResult<Response> {
+  if let Err(rejected) = camera_config::validate(&payload.productType, &payload.config, payload.allow_unknown.unwrap_or(false)) {
+    let body = serde_json::json!({ "reason": "invalid_config", "message": rejected.to_string(), "problems": rejected.problems });
+    return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(body)).into_response());
   }
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
   client.update_camera_config(payload.networkId, payload.cameraId, &payload.productType, payload.config)
     .await
-    .map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
+    .map_err(upstream_error)?;
+  Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+#[utoipa::path(
+  get, path = "/api/network-settings", tag = "devices",
+  params(NetworkSettingsQuery),
+  responses(
+    (status = 200, description = "Time zone, DST, liveview saving and other network-wide settings", body = crate::openapi::NetworkSettingsSchema),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_network_settings(State(state): State<Arc<ServerState>>, Query(query): Query<NetworkSettingsQuery>) -> ApiResult<Json<NetworkSettings>> {
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.get_network_settings(query.network_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  post, path = "/api/network-settings", tag = "devices",
+  request_body = NetworkSettingsUpdate,
+  responses(
+    (status = 200, description = "Network settings after the update", body = crate::openapi::NetworkSettingsSchema),
+    (status = 400, description = "Unknown time zone or empty name", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn update_network_settings(State(state): State<Arc<ServerState>>, Json(payload): Json<NetworkSettingsUpdate>) -> ApiResult<Json<NetworkSettings>> {
+  payload.patch.validate().map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.update_network_settings(payload.network_id, &payload.patch).await.map_err(upstream_error)?;
+  client.get_network_settings(payload.network_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  get, path = "/api/liveview-settings", tag = "devices",
+  params(NetworkSettingsQuery),
+  responses(
+    (status = 200, description = "Known liveview flags (`lv_save`, `extended_liveview`, `doorbell_auto_save`) the homescreen reports for the network and its devices; which appear depends on the account", body = Vec<crate::openapi::LiveviewSettingSchema>),
+    (status = 404, description = "No such network", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_liveview_settings(State(state): State<Arc<ServerState>>, Query(query): Query<NetworkSettingsQuery>) -> ApiResult<Json<Vec<LiveviewSetting>>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  liveview_settings::get_liveview_settings(&client, query.network_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  post, path = "/api/liveview-settings", tag = "devices",
+  request_body = LiveviewSettingUpdate,
+  responses(
+    (status = 200, description = "Whether the change stuck, judged by re-reading the homescreen", body = crate::openapi::LiveviewSettingWriteSchema),
+    (status = 400, description = "The value doesn't fit the flag", body = ErrorBody, content_type = "text/plain"),
+    (status = 404, description = "Unknown flag, or not reported for this network", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_liveview_setting(State(state): State<Arc<ServerState>>, Json(payload): Json<LiveviewSettingUpdate>) -> ApiResult<Json<LiveviewSettingWrite>> {
+  let flag = liveview_settings::flag(&payload.flag).map_err(|e| upstream_error(e.into()))?;
+  flag.check(&payload.value).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  let client = shared::snapshot(&state.blink_client).await;
+  liveview_settings::set_liveview_setting(&client, payload.network_id, &payload.flag, payload.device_id, payload.value)
+    .await
+    .map(Json)
+    .map_err(upstream_error)
+}
+
+#[utoipa::path(
+  get, path = "/api/motion-notifications", tag = "devices",
+  params(NetworkSettingsQuery),
+  responses(
+    (status = 200, description = "Whether motion pushes fire for the network, and whether that is a network key or the account-wide flag", body = crate::openapi::MotionNotificationsSchema),
+    (status = 404, description = "No such network", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_motion_notifications(State(state): State<Arc<ServerState>>, Query(query): Query<NetworkSettingsQuery>) -> ApiResult<Json<MotionNotifications>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  motion_notifications::get_motion_notifications(&client, query.network_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  post, path = "/api/motion-notifications", tag = "devices",
+  request_body = MotionNotificationsUpdate,
+  responses(
+    (status = 200, description = "Whether the change stuck, judged by reading the switch back. With `scope: account` every network was changed", body = crate::openapi::MotionNotificationWriteSchema),
+    (status = 404, description = "No such network", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_motion_notifications(State(state): State<Arc<ServerState>>, Json(payload): Json<MotionNotificationsUpdate>) -> ApiResult<Json<MotionNotificationWrite>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  motion_notifications::set_motion_notifications(&client, payload.network_id, payload.enabled)
+    .await
+    .map(Json)
+    .map_err(upstream_error)
+}
+
+#[utoipa::path(
+  get, path = "/api/sync-module-storage", tag = "devices",
+  params(SyncModuleStorageQuery),
+  responses(
+    (status = 200, description = "Whether the sync module's USB drive is present, formatting or full, and when it was last backed up to", body = crate::openapi::LocalStorageStatusSchema),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_sync_module_storage(State(state): State<Arc<ServerState>>, Query(query): Query<SyncModuleStorageQuery>) -> ApiResult<Json<LocalStorageStatus>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  client.get_local_storage_status(query.network_id, query.sync_module_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  post, path = "/api/sync-module-storage/eject", tag = "devices",
+  request_body = SyncModuleStorageQuery,
+  responses(
+    (status = 200, description = "Eject sent and polled; the drive is safe to remove once `finished` is true", body = crate::openapi::UsbEjectResultSchema),
+    (status = 502, description = "Blink rejected the eject or its command failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn eject_usb(State(state): State<Arc<ServerState>>, Json(payload): Json<SyncModuleStorageQuery>) -> ApiResult<Json<UsbEjectResult>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  client.eject_usb(payload.network_id, payload.sync_module_id).await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  get, path = "/api/notifications", tag = "devices",
+  responses(
+    (status = 200, description = "Which push notifications the account sends", body = crate::openapi::NotificationConfigSchema),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_notification_config(State(state): State<Arc<ServerState>>) -> ApiResult<Json<NotificationConfig>> {
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.get_notification_config().await.map(Json).map_err(upstream_error)
+}
+
+#[utoipa::path(
+  post, path = "/api/notifications", tag = "devices",
+  request_body(content = crate::openapi::NotificationConfigSchema, description = "The full configuration, as returned by GET; it replaces the current one"),
+  responses(
+    (status = 204, description = "Configuration sent"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn update_notification_config(State(state): State<Arc<ServerState>>, Json(config): Json<NotificationConfig>) -> ApiResult<StatusCode> {
+  let mut client = state.blink_client.lock().await;
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.update_notification_config(&config).await.map_err(upstream_error)?;
   Ok(StatusCode::NO_CONTENT)
 }
 
-async fn get_thumbnail_base64(State(state): State<Arc<ServerState>>, Query(query): Query<ThumbnailQuery>) -> ApiResult<String> {
+#[utoipa::path(
+  get, path = "/api/settings", tag = "meta",
+  responses((status = 200, description = "Effective settings; desktop-only keys are stored but unused here", body = crate::openapi::SettingsSchema)),
+  security(("api_key" = []))
+)]
+async fn get_settings(State(state): State<Arc<ServerState>>) -> Json<Settings> {
+  Json(state.settings.read().unwrap().clone())
+}
+
+#[utoipa::path(
+  patch, path = "/api/settings", tag = "meta",
+  request_body(content = Object, description = "Any subset of the settings keys"),
+  responses(
+    (status = 200, description = "Settings after the update", body = crate::openapi::SettingsSchema),
+    (status = 400, description = "Unknown key or wrong value type", body = ErrorBody, content_type = "text/plain"),
+    (status = 500, description = "Settings file could not be written", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn update_settings(State(state): State<Arc<ServerState>>, Json(patch): Json<serde_json::Value>) -> ApiResult<Json<Settings>> {
+  apply_settings(&state, patch).map(Json)
+}
+
+#[utoipa::path(
+  get, path = "/api/camera-prefs", tag = "devices",
+  responses((status = 200, description = "Local preferences keyed by camera id", body = BTreeMap<String, crate::openapi::CameraPrefsSchema>)),
+  security(("api_key" = []))
+)]
+async fn get_camera_prefs(State(state): State<Arc<ServerState>>) -> Json<BTreeMap<i64, CameraPrefs>> {
+  Json(state.settings.read().unwrap().camera_prefs.clone())
+}
+
+#[utoipa::path(
+  put, path = "/api/camera-prefs/{id}", tag = "devices",
+  params(("id" = i64, Path, description = "Camera id")),
+  request_body(content = crate::openapi::CameraPrefsSchema, description = "Replaces the camera's preferences; all defaults removes them"),
+  responses(
+    (status = 200, description = "All camera preferences after the change", body = BTreeMap<String, crate::openapi::CameraPrefsSchema>),
+    (status = 500, description = "Settings file could not be written", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn set_camera_prefs(State(state): State<Arc<ServerState>>, Path(id): Path<i64>, Json(prefs): Json<CameraPrefs>) -> ApiResult<Json<BTreeMap<i64, CameraPrefs>>> {
+  let mut all = state.settings.read().unwrap().camera_prefs.clone();
+  all.insert(id, prefs);
+  apply_settings(&state, serde_json::json!({ "camera_prefs": all })).map(|settings| Json(settings.camera_prefs))
+}
+
+#[utoipa::path(
+  get, path = "/api/audit", tag = "meta",
+  params(AuditQuery),
+  responses(
+    (status = 200, description = "State-changing operations, newest first", body = Vec<crate::openapi::AuditEntrySchema>),
+    (status = 500, description = "Audit log could not be read", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_audit_log(Query(query): Query<AuditQuery>) -> ApiResult<Json<Vec<AuditEntry>>> {
+  let filter = AuditFilter {
+    action: query.action,
+    target: query.target,
+    source: query.source,
+    since: query.since,
+    until: query.until,
+    failed_only: query.failed_only.unwrap_or(false)
+  };
+  audit::read(query.limit, &filter)
+    .map(Json)
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into())
+}
+
+fn apply_settings(state: &ServerState, patch: serde_json::Value) -> ApiResult<Settings> {
+  let mut settings = state.settings.write().unwrap();
+  let updated = settings.with_patch(patch).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  updated.save(&storage::settings_path())
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save settings: {}", e)))?;
+  state.media_index.set_enabled(updated.media_index_enabled);
+  *settings = updated.clone();
+  Ok(updated)
+}
+
+/// Runs due schedule entries every `TICK_SECS` and logs what was done.
+async fn drive_schedules(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(schedule::TICK_SECS));
+  loop {
+    interval.tick().await;
+    let entries = state.settings.read().unwrap().schedules.clone();
+    for run in state.scheduler.tick(&state.blink_client, &entries).await {
+      println!("{}", run);
+      state.events.record("schedule_run", &run);
+    }
+  }
+}
+
+/// Applies the event log's retention settings every `PRUNE_INTERVAL_SECS`.
+async fn drive_event_pruning(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(events::PRUNE_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    let (max_rows, max_age_days) = {
+      let settings = state.settings.read().unwrap();
+      (settings.event_retention_rows, settings.event_retention_days)
+    };
+    if let Err(e) = state.events.prune(max_rows, max_age_days) {
+      eprintln!("Event log pruning failed: {}", e);
+    }
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/events/replay", tag = "meta",
+  params(ReplayQuery),
+  responses(
+    (status = 200, description = "Logged events (`arm`, `rearm`, `schedule_run`, `firmware_changed`, `delete_finished`, `summary_report`, `tier_healed`) oldest first. Page by passing the last `seq` back as `after_seq`; a gap before the first `seq` means older events were pruned", body = Vec<crate::openapi::EventSchema>),
+    (status = 500, description = "The event log could not be read", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn replay_events(State(state): State<Arc<ServerState>>, Query(query): Query<ReplayQuery>) -> ApiResult<Json<Vec<Event>>> {
+  let events = state.events.replay(query.after_seq.unwrap_or(0), query.limit.unwrap_or(events::DEFAULT_REPLAY_LIMIT))
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to read event log: {}", e)))?;
+  Ok(Json(events))
+}
+
+#[utoipa::path(
+  get, path = "/api/events", tag = "meta",
+  params(EventStreamQuery),
+  responses(
+    (status = 101, description = "WebSocket of events as JSON text frames, shaped like the `/api/events/replay` items: the logged events after `after_seq` first, then each new one as it is recorded. Every `seq` is sent once and in order, so a client that reconnects with the last `seq` it saw misses nothing that was kept")
+  ),
+  security(("api_key" = []))
+)]
+async fn stream_events(ws: WebSocketUpgrade, State(state): State<Arc<ServerState>>, Query(query): Query<EventStreamQuery>) -> Response {
+  ws.on_upgrade(move |socket| forward_events(state, socket, query.after_seq.unwrap_or(0)))
+}
+
+/// Sends the event feed down the socket until the client goes away.
+async fn forward_events(state: Arc<ServerState>, mut socket: WebSocket, after_seq: i64) {
+  let mut feed = state.events.feed(after_seq);
+  loop {
+    let event = tokio::select! {
+      event = feed.recv(&state.events) => event,
+      // Clients have nothing to say; anything but a close is ignored.
+      incoming = socket.recv() => match incoming {
+        Some(Ok(Message::Close(_)) | Err(_)) | None => return,
+        Some(Ok(_)) => continue,
+      },
+    };
+    let event = match event {
+      Ok(Some(event)) => event,
+      Ok(None) => return,
+      Err(e) => {
+        eprintln!("Event stream could not read the event log: {}", e);
+        return;
+      }
+    };
+    let text = serde_json::to_string(&event).unwrap_or_default();
+    if socket.send(Message::Text(text)).await.is_err() {
+      return;
+    }
+  }
+}
+
+/// Posts each recorded event to the webhooks in settings. Delivery is best effort: a
+/// slow or failing receiver is logged and the event is not retried.
+async fn drive_webhooks(state: Arc<ServerState>) {
+  let http = reqwest::Client::new();
+  let mut events = state.events.subscribe();
+  loop {
+    let event = match events.recv().await {
+      Ok(event) => event,
+      Err(tokio::sync::broadcast::error::RecvError::Lagged(missed)) => {
+        eprintln!("Webhooks fell behind and skipped {} events", missed);
+        continue;
+      }
+      Err(tokio::sync::broadcast::error::RecvError::Closed) => return
+    };
+    let targets = state.settings.read().unwrap().webhooks.clone();
+    webhooks::deliver(&http, &targets, &event).await;
+  }
+}
+
+/// Records a `summary_report` event, which the webhooks deliver, each time the schedule
+/// in settings comes round. A run missed while the server was down is not made up.
+async fn drive_summary_report(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+  let mut last_sent: Option<chrono::DateTime<chrono::Utc>> = None;
+  loop {
+    interval.tick().await;
+    let Some(schedule) = state.settings.read().unwrap().summary_report.clone() else { continue };
+    let now = chrono::Utc::now();
+    let Some(run) = schedule.last_run(now) else { continue };
+    let due = now - run < chrono::Duration::minutes(5);
+    if !due || last_sent.is_some_and(|sent| sent >= run) {
+      continue;
+    }
+    last_sent = Some(run);
+    let (from, to) = schedule.range(run);
+    match report::generate(&state.media_index, &state.events, &state.battery, from, to) {
+      Ok(summary) => state.events.record("summary_report", &summary),
+      Err(e) => eprintln!("Summary report failed: {}", e)
+    }
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/reports/summary", tag = "meta",
+  params(SummaryReportQuery),
+  responses(
+    (status = 200, description = "Clips per camera per day and busiest hours (UTC) from the media index, battery changes, and arm history for the range. With `format=html`, the same as a self-contained page", body = crate::openapi::SummaryReportSchema),
+    (status = 400, description = "Backwards or too long a range, or an unknown format", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_summary_report(State(state): State<Arc<ServerState>>, Query(query): Query<SummaryReportQuery>) -> ApiResult<Response> {
+  let to = query.to.unwrap_or_else(chrono::Utc::now);
+  let from = query.from.unwrap_or(to - chrono::Duration::days(report::DEFAULT_RANGE_DAYS));
+  let bad_request = |e: anyhow::Error| (StatusCode::BAD_REQUEST, e.to_string());
+  let summary = report::generate(&state.media_index, &state.events, &state.battery, from, to).map_err(bad_request)?;
+  match query.format.as_deref().unwrap_or("json") {
+    "json" => Ok(Json(summary).into_response()),
+    "html" => Ok(Html(report::render_html(&summary)).into_response()),
+    other => Err((StatusCode::BAD_REQUEST, format!("Unknown format {}; expected json or html", other)).into())
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/heatmap", tag = "media",
+  params(HeatmapQuery),
+  responses(
+    (status = 200, description = "Clip counts by weekday and hour plus clips per day, in the local time of the camera's network. Counted from the media index when it covers the range, otherwise from the live feed (`source: live`)", body = crate::openapi::ActivityHeatmapSchema),
+    (status = 400, description = "Backwards or too long a range", body = ErrorBody, content_type = "text/plain"),
+    (status = 404, description = "Unknown camera", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_activity_heatmap(State(state): State<Arc<ServerState>>, Query(query): Query<HeatmapQuery>) -> ApiResult<Json<ActivityHeatmap>> {
+  let to = query.to.unwrap_or_else(chrono::Utc::now);
+  let from = query.from.unwrap_or(to - chrono::Duration::days(heatmap::DEFAULT_RANGE_DAYS));
+  heatmap::check_range(from, to).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  let heatmap = heatmap::activity(&state.blink_client, &state.media_index, query.camera_id, from, to).await.map_err(upstream_error)?;
+  Ok(Json(heatmap))
+}
+
+#[utoipa::path(
+  post, path = "/api/webhooks/preview", tag = "meta",
+  request_body = WebhookPreviewRequest,
+  responses(
+    (status = 200, description = "The body a webhook with this format or template would receive. Nothing is sent. `warning` is set when the template failed and the default payload (the event as JSON) was used instead", body = crate::openapi::WebhookPayloadSchema)
+  ),
+  security(("api_key" = []))
+)]
+async fn preview_webhook(Json(payload): Json<WebhookPreviewRequest>) -> Json<WebhookPayload> {
+  let target = webhooks::WebhookTarget { format: payload.format, template: payload.template, ..Default::default() };
+  let event = payload.event.unwrap_or_else(webhooks::sample_event);
+  Json(webhooks::payload(&target, &event))
+}
+
+/// Pushes motion, offline and low battery alerts to the configured notifiers every
+/// `POLL_INTERVAL_SECS`. Nothing is polled while no notifier wants an alert.
+async fn drive_alerts(state: Arc<ServerState>) {
+  let alerter = notify::Alerter::default();
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(notify::POLL_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    let targets = state.settings.read().unwrap().notifiers.clone();
+    if targets.iter().all(|t| t.alerts.is_empty()) {
+      continue;
+    }
+    let client = shared::snapshot(&state.blink_client).await;
+    if let Err(e) = alerter.poll(&client, &targets, &state.battery).await {
+      eprintln!("Alert poll failed: {}", e);
+    }
+  }
+}
+
+#[utoipa::path(
+  post, path = "/api/notify/test", tag = "meta",
+  request_body = NotifyTestRequest,
+  responses(
+    (status = 200, description = "One result per notifier tried. A sample alert is sent whether or not the notifier wants that kind. ntfy tokens come from `BLINK_NTFY_TOKEN`, Gotify tokens from `BLINK_GOTIFY_TOKEN`", body = Vec<crate::openapi::NotifyResultSchema>),
+    (status = 400, description = "The target is invalid, or none is given and none is configured", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn test_notify(State(state): State<Arc<ServerState>>, Json(payload): Json<NotifyTestRequest>) -> ApiResult<Json<Vec<notify::NotifyResult>>> {
+  let targets = match payload.target {
+    Some(target) => {
+      target.validate().map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+      vec![target]
+    }
+    None => state.settings.read().unwrap().notifiers.clone()
+  };
+  if targets.is_empty() {
+    return Err((StatusCode::BAD_REQUEST, "No notifiers configured".to_string()).into());
+  }
+  Ok(Json(notify::send_test(&targets, payload.kind.unwrap_or(notify::AlertKind::Motion)).await))
+}
+
+/// Refreshes recent media into the index every `SYNC_INTERVAL_SECS` while it is enabled.
+async fn drive_media_index(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(media_index::SYNC_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    if let Err(e) = state.media_index.sync(&state.blink_client, media_index::SYNC_SINCE_DAYS, media_index::SYNC_MAX_PAGES).await {
+      eprintln!("Media index sync failed: {}", e);
+    }
+  }
+}
+
+/// Checks device firmware every `POLL_INTERVAL_SECS` and logs any version changes.
+async fn drive_firmware(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(firmware::POLL_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    check_firmware(&state).await;
+  }
+}
+
+/// Samples battery levels from the homescreen, and voltages from camera configs, every
+/// `SAMPLE_INTERVAL_SECS`.
+async fn drive_battery(state: Arc<ServerState>) {
+  let mut interval = tokio::time::interval(std::time::Duration::from_secs(battery::SAMPLE_INTERVAL_SECS));
+  loop {
+    interval.tick().await;
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_homescreen().await {
+      Ok(homescreen) => {
+        let voltages = battery::sample_voltages(&client, &homescreen).await;
+        state.battery.observe(&homescreen, &voltages)
+      }
+      Err(e) => eprintln!("Battery sample failed: {}", e)
+    }
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/battery-history", tag = "devices",
+  params(BatteryHistoryQuery),
+  responses(
+    (status = 200, description = "Battery level points for charting, oldest first, with a projection of days remaining from the last week's trend", body = crate::openapi::BatteryHistorySchema),
+    (status = 500, description = "History could not be read", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_battery_history(State(state): State<Arc<ServerState>>, Query(query): Query<BatteryHistoryQuery>) -> ApiResult<Json<BatteryHistory>> {
+  let history = state.battery.history(query.camera_id, query.days.unwrap_or(battery::DEFAULT_RANGE_DAYS))
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+  Ok(Json(history))
+}
+
+async fn check_firmware(state: &ServerState) {
+  let client = shared::snapshot(&state.blink_client).await;
+  match state.firmware.poll(&client).await {
+    Ok(changes) => {
+      for change in changes {
+        println!("Firmware: {}", change);
+        state.events.record("firmware_changed", &change);
+      }
+    }
+    Err(e) => eprintln!("Firmware check failed: {}", e)
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/firmware", tag = "devices",
+  responses(
+    (status = 200, description = "Firmware per camera and sync module with the previous version and when the current one was first seen. Checks Blink first; falls back to the last known versions if that fails", body = Vec<crate::openapi::FirmwareRecordSchema>)
+  ),
+  security(("api_key" = []))
+)]
+async fn get_firmware_report(State(state): State<Arc<ServerState>>) -> Json<Vec<FirmwareRecord>> {
+  check_firmware(&state).await;
+  Json(state.firmware.report())
+}
+
+#[utoipa::path(
+  get, path = "/api/usage", tag = "devices",
+  responses(
+    (status = 200, description = "Recording time per camera as Blink counts it, plus archived bytes per camera when the media index is enabled. Empty when the account has no plan", body = crate::openapi::UsageReportSchema),
+    (status = 502, description = "Blink rejected the request or it failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_usage(State(state): State<Arc<ServerState>>) -> ApiResult<Json<UsageReport>> {
+  let client = shared::snapshot(&state.blink_client).await;
+  let mut report = client.get_usage().await.map_err(upstream_error)?;
+  state.media_index.add_local_bytes(&mut report);
+  Ok(Json(report))
+}
+
+/// Deletes deferred clips from Blink once their undo window has passed.
+async fn drive_trash(state: Arc<ServerState>) {
+  state.trash.run(&state.blink_client, &state.media_index, || state.settings.read().unwrap().clone()).await
+}
+
+/// Runs takeouts; a finished or failed one goes to the event log.
+async fn drive_takeout(state: Arc<ServerState>) {
+  let settings = || state.settings.read().unwrap().clone();
+  state.takeout.run(&state.blink_client, settings, |progress| {
+    if matches!(progress.state, TakeoutState::Done | TakeoutState::Failed) {
+      state.events.record("takeout_finished", progress);
+    }
+  }).await
+}
+
+/// Works through queued deletions; each completed job goes to the event log (cancelled
+/// ones are logged by `cancel_delete_job`).
+async fn drive_delete_queue(state: Arc<ServerState>) {
+  let settings = || state.settings.read().unwrap().clone();
+  state.delete_queue.run(&state.blink_client, &state.media_index, settings, |progress| {
+    if progress.state == JobState::Done {
+      state.events.record("delete_finished", progress);
+    }
+  }).await
+}
+
+#[utoipa::path(
+  get, path = "/api/media/index", tag = "media",
+  params(MediaIndexQuery),
+  responses(
+    (status = 200, description = "Matching media, newest first. `source` is `live` when the index is disabled and one page of the Blink feed was filtered instead", body = crate::openapi::MediaSearchPageSchema),
+    (status = 400, description = "Invalid timestamp", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn search_media_index(State(state): State<Arc<ServerState>>, Query(query): Query<MediaIndexQuery>) -> ApiResult<Json<MediaSearchPage>> {
+  let filter = MediaFilter::from(query);
+  let mut page = match state.media_index.search(&filter).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))? {
+    Some(page) => page,
+    None => media_index::search_live(&state.blink_client, &filter).await.map_err(upstream_error)?
+  };
+  let hidden = state.trash.hidden_ids();
+  let before = page.items.len();
+  page.items.retain(|item| !hidden.contains(&item.id));
+  page.total = page.total.saturating_sub((before - page.items.len()) as u64);
+  Ok(Json(page))
+}
+
+#[utoipa::path(
+  post, path = "/api/media/index/rebuild", tag = "media",
+  responses(
+    (status = 200, description = "Number of media items indexed", body = usize),
+    (status = 409, description = "The media index is disabled", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn rebuild_media_index(State(state): State<Arc<ServerState>>) -> ApiResult<Json<usize>> {
+  if !state.media_index.is_enabled() {
+    return Err((StatusCode::CONFLICT, "Media index is disabled".to_string()).into());
+  }
+  state.media_index.rebuild(&state.blink_client).await.map(Json).map_err(upstream_error)
+}
+
+#[derive(Serialize, ToSchema)]
+struct ScheduleOverview {
+  #[schema(value_type = Vec<crate::openapi::ScheduleEntrySchema>)]
+  entries: Vec<ScheduleEntry>,
+  #[schema(value_type = Vec<crate::openapi::NextScheduledChangeSchema>)]
+  upcoming: Vec<NextScheduledChange>
+}
+
+fn schedule_overview(state: &ServerState) -> ScheduleOverview {
+  let entries = state.settings.read().unwrap().schedules.clone();
+  let upcoming = state.scheduler.upcoming(&entries, chrono::Utc::now());
+  ScheduleOverview { entries, upcoming }
+}
+
+#[utoipa::path(
+  get, path = "/api/schedules", tag = "devices",
+  responses((status = 200, description = "Schedule entries and the next change per network", body = ScheduleOverview)),
+  security(("api_key" = []))
+)]
+async fn get_schedules(State(state): State<Arc<ServerState>>) -> Json<ScheduleOverview> {
+  Json(schedule_overview(&state))
+}
+
+#[utoipa::path(
+  post, path = "/api/schedules", tag = "devices",
+  request_body(content = crate::openapi::ScheduleEntrySchema, description = "Entry to add (empty id) or replace (existing id)"),
+  responses(
+    (status = 200, description = "Schedules after the change", body = ScheduleOverview),
+    (status = 400, description = "Invalid entry or unknown id", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn save_schedule_entry(State(state): State<Arc<ServerState>>, Json(entry): Json<ScheduleEntry>) -> ApiResult<Json<ScheduleOverview>> {
+  let current = state.settings.read().unwrap().schedules.clone();
+  let (entries, _) = schedule::upsert(&current, entry).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  apply_settings(&state, serde_json::json!({ "schedules": entries }))?;
+  Ok(Json(schedule_overview(&state)))
+}
+
+#[utoipa::path(
+  delete, path = "/api/schedules/{id}", tag = "devices",
+  params(("id" = String, Path, description = "Schedule entry id")),
+  responses(
+    (status = 200, description = "Schedules after the change", body = ScheduleOverview),
+    (status = 404, description = "No entry with that id", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn delete_schedule_entry(State(state): State<Arc<ServerState>>, Path(id): Path<String>) -> ApiResult<Json<ScheduleOverview>> {
+  let current = state.settings.read().unwrap().schedules.clone();
+  let entries = schedule::remove(&current, &id).map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+  apply_settings(&state, serde_json::json!({ "schedules": entries }))?;
+  Ok(Json(schedule_overview(&state)))
+}
+
+#[utoipa::path(
+  post, path = "/api/camera-thumbnail", tag = "devices",
+  request_body = CameraThumbnailRequest,
+  responses(
+    (status = 204, description = "Snapshot requested; the homescreen shows the new thumbnail once it lands"),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn refresh_camera_thumbnail(State(state): State<Arc<ServerState>>, Json(payload): Json<CameraThumbnailRequest>) -> ApiResult<StatusCode> {
   let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = storage::save_auth(&client.get_state());
+  let _ = shared::refresh_and_persist(&mut client).await;
+  client.request_thumbnail(payload.network_id, payload.camera_id, &payload.product_type)
+    .await
+    .map_err(upstream_error)?;
+  Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+  get, path = "/api/thumbnail-base64", tag = "media",
+  params(ThumbnailQuery),
+  responses(
+    (status = 200, description = "Thumbnail as a data URI. When Blink can't be reached the last copy served is returned instead and fetched again in the background", body = String, content_type = "text/plain",
+      headers(
+        ("X-Blink-Cache" = String, description = "`fresh`, or `stale` for a fallback copy"),
+        ("X-Blink-Stale" = bool, description = "`true` on a fallback copy")
+      )),
+    (status = 502, description = "Blink request failed and no copy was kept", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_thumbnail_base64(State(state): State<Arc<ServerState>>, Query(query): Query<ThumbnailQuery>) -> ApiResult<Response> {
+  let result = {
+    let mut client = state.blink_client.lock().await;
+    let _ = shared::refresh_and_persist(&mut client).await;
+    client.get_thumbnail(&query.path).await
+  };
+  let (thumbnail, status) = match result {
+    Ok(thumbnail) => {
+      state.thumbnails.store(&query.path, &thumbnail);
+      (thumbnail, CacheStatus::Fresh)
+    }
+    Err(e) => {
+      let Some(stale) = state.thumbnails.stale(&query.path) else { return Err(upstream_error(e)) };
+      media::spawn_revalidation(&state, &query.path, thumbnail_cache::revalidate_delay(&e));
+      (stale, CacheStatus::Stale)
+    }
+  };
+  let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, thumbnail.bytes);
+  let mut response = format!("data:{};base64,{}", thumbnail.content_type, encoded).into_response();
+  response.headers_mut().insert(thumbnail_cache::CACHE_HEADER, http::HeaderValue::from_static(status.as_str()));
+  if status == CacheStatus::Stale {
+    response.headers_mut().insert(thumbnail_cache::STALE_HEADER, http::HeaderValue::from_static("true"));
   }
-  let bytes = client.get_thumbnail(&query.path).await.map_err(|e| (StatusCode::BAD_GATEWAY, e.to_string()))?;
-  let encoded = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
-  Ok(format!("data:image/jpeg;base64,{}", encoded))
+  Ok(response)
+}
+
+#[utoipa::path(
+  post, path = "/api/thumbnails/prefetch", tag = "media",
+  request_body = PrefetchThumbnailsRequest,
+  responses(
+    (status = 200, description = "How each path went, in the order asked for. Thumbnails are fetched a few at a time and kept to fall back on; a path that failed with a copy already kept comes back `stale`, one without a copy has `error` set", body = Vec<crate::openapi::PrefetchResultSchema>),
+    (status = 400, description = "More than 64 paths", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn prefetch_thumbnails(State(state): State<Arc<ServerState>>, Json(payload): Json<PrefetchThumbnailsRequest>) -> ApiResult<Json<Vec<PrefetchResult>>> {
+  let paths = thumbnail_cache::prefetch_paths(payload.paths).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+  {
+    let mut client = state.blink_client.lock().await;
+    let _ = shared::refresh_and_persist(&mut client).await;
+  }
+  let client = shared::snapshot(&state.blink_client).await;
+  let (client, state) = (&client, &state);
+  let results = futures_util::stream::iter(paths)
+    .map(|path| async move {
+      match client.get_thumbnail(&path).await {
+        Ok(thumbnail) => {
+          state.thumbnails.store(&path, &thumbnail);
+          PrefetchResult { path, cache: Some(CacheStatus::Fresh), error: None }
+        }
+        Err(e) if state.thumbnails.stale(&path).is_some() => {
+          media::spawn_revalidation(state, &path, thumbnail_cache::revalidate_delay(&e));
+          PrefetchResult { path, cache: Some(CacheStatus::Stale), error: None }
+        }
+        Err(e) => PrefetchResult { path, cache: None, error: Some(e.to_string()) }
+      }
+    })
+    .buffered(thumbnail_cache::PREFETCH_CONCURRENCY)
+    .collect()
+    .await;
+  Ok(Json(results))
+}
+
+#[utoipa::path(
+  get, path = "/api/cameras", tag = "devices",
+  responses(
+    (status = 200, description = "Cameras with signed thumbnail URLs", body = Vec<CameraResponse>),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_cameras(State(state): State<Arc<ServerState>>) -> ApiResult<Json<Vec<CameraResponse>>> {
+  let mut cameras = {
+    let mut client = state.blink_client.lock().await;
+    let _ = shared::refresh_and_persist(&mut client).await;
+    client.get_homescreen().await.map_err(upstream_error)?.cameras
+  };
+  state.settings.read().unwrap().apply_camera_prefs(&mut cameras);
+
+  let response = cameras.into_iter()
+    .map(|camera| {
+      let thumbnail_url = if camera.thumbnail.is_empty() {
+        None
+      } else {
+        state.signed_proxy_url("thumbnail", &camera.thumbnail)
+      };
+      CameraResponse { camera, thumbnail_url }
+    })
+    .collect();
+  Ok(Json(response))
+}
+
+#[utoipa::path(
+  get, path = "/api/timeline", tag = "media",
+  params(TimelineQuery),
+  responses(
+    (status = 200, description = "Clips grouped into per-camera motion events, newest first", body = Vec<crate::openapi::TimelineEventSchema>),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_timeline(State(state): State<Arc<ServerState>>, Query(query): Query<TimelineQuery>) -> ApiResult<Json<Vec<TimelineEvent>>> {
+  let mut items = {
+    let mut client = state.blink_client.lock().await;
+    let _ = shared::refresh_and_persist(&mut client).await;
+    let since_days = query.since_days.unwrap_or(timeline::DEFAULT_SINCE_DAYS);
+    client.get_all_media(since_days, timeline::MAX_PAGES).await.map_err(upstream_error)?
+  };
+  state.trash.retain_visible(&mut items);
+  let gap_secs = query.gap_secs.unwrap_or(timeline::DEFAULT_GAP_SECS);
+  Ok(Json(timeline::group_events(items, gap_secs)))
+}
+
+#[utoipa::path(
+  get, path = "/api/media/all", tag = "media",
+  params(MediaAllQuery),
+  responses(
+    (status = 200, description = "All media items with signed clip and thumbnail URLs", body = Vec<MediaItemResponse>),
+    (status = 502, description = "Blink request failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+async fn get_all_media(State(state): State<Arc<ServerState>>, Query(query): Query<MediaAllQuery>) -> ApiResult<Json<Vec<MediaItemResponse>>> {
+  let mut items = {
+    let mut client = state.blink_client.lock().await;
+    let _ = shared::refresh_and_persist(&mut client).await;
+    let since_days = query.since_days.unwrap_or(30);
+    let max_pages = query.max_pages.unwrap_or(20).clamp(1, 100);
+    client.get_all_media(since_days, max_pages).await.map_err(upstream_error)?
+  };
+  state.trash.retain_visible(&mut items);
+
+  let response = items.into_iter()
+    .map(|item| {
+      let media_url = item.media.as_deref().and_then(|m| state.signed_proxy_url("clip", m));
+      let thumbnail_url = item.thumbnail.as_deref().and_then(|t| state.signed_proxy_url("thumbnail", t));
+      MediaItemResponse { item, media_url, thumbnail_url }
+    })
+    .collect();
+  Ok(Json(response))
 }
@@ -1,42 +1,111 @@
 use axum::{
   extract::{Path, Query, State},
   response::{IntoResponse, Response},
-  routing::get,
-  Router
+  routing::{delete, get, post},
+  Json, Router
 };
 use axum::body::Body;
 use http::{header, StatusCode};
 use std::sync::Arc;
-use tokio::sync::{Mutex, mpsc};
+use tokio::sync::{Mutex, Semaphore};
 use futures_util::StreamExt;
-use tokio_util::sync::CancellationToken;
-use chrono::{Utc, Duration};
+use secrecy::ExposeSecret;
+use chrono::Utc;
 use tokio_stream::wrappers::ReceiverStream;
-
-use crate::blink::BlinkClient;
-use crate::immi::{self, ImmiStream};
-
-#[derive(serde::Deserialize)]
+use tokio_util::io::ReaderStream;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::blink::{BlinkClient, LiveviewOptions, LiveviewQuality, MediaItem};
+use crate::export::{self, ExportCompression};
+use crate::live_sessions::{LiveSessionInfo, SessionRegistry};
+use crate::recording::{self, RecordingInfo};
+use crate::replay::ReplaySaved;
+use crate::liveview::{self, LiveviewError, LiveviewRequest, StreamMode};
+use crate::settings::Settings;
+use crate::signing::{UrlSigner, DEFAULT_TTL_SECS};
+use crate::thumbnail_cache::{self, CacheStatus};
+
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ProxyQuery {
+  /// Blink media URL or path; must resolve to an allow-listed Blink host
   pub url: String
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct LiveQuery {
   pub serial: Option<String>,
-  pub record: Option<bool>
+  /// Keep Blink's clip of the session; also writes it to `liveview_recording_dir` when that is set
+  pub record: Option<bool>,
+  /// `saver` or `best`; defaults to the camera's `liveview_quality` preference. Cameras that can't choose ignore it
+  #[param(value_type = Option<String>)]
+  pub quality: Option<LiveviewQuality>,
+  /// Ask for the audio track on or off; Blink's default when omitted
+  pub audio: Option<bool>,
+  /// `mse` (default, for the web player), `raw` (no synthetic packets) or `ffmpeg` (PAT/PMT before the first video, for ffmpeg and VLC)
+  #[param(value_type = Option<String>)]
+  pub mode: Option<StreamMode>,
+  /// Debug builds only: stream a generated test pattern instead of the camera, without asking Blink. Release builds need `BLINK_FAKE_LIVE=1`, which fakes every session
+  pub fake: Option<bool>
+}
+
+#[derive(serde::Deserialize, ToSchema)]
+pub struct ExportRequest {
+  /// Media items as returned by `/api/media`; each needs at least `id` and `media`
+  #[schema(value_type = Vec<crate::openapi::MediaItemSchema>)]
+  pub items: Vec<MediaItem>,
+  #[serde(default)]
+  #[schema(value_type = crate::openapi::ExportCompressionSchema)]
+  pub compression: ExportCompression
 }
 
+/// Thumbnail and clip fetches the media proxy runs against Blink at once.
+pub const MAX_CONCURRENT_FETCHES: usize = 6;
+
 pub struct ServerState {
-  pub blink_client: Arc<Mutex<BlinkClient>>
+  pub blink_client: Arc<Mutex<BlinkClient>>,
+  pub signer: UrlSigner,
+  pub api_key: Option<String>,
+  pub live_sessions: Arc<SessionRegistry>,
+  pub readiness: crate::ready::ReadinessCache,
+  pub settings: std::sync::RwLock<Settings>,
+  pub scheduler: crate::schedule::Scheduler,
+  pub media_index: crate::media_index::MediaIndex,
+  pub trash: blink_core::trash::Trash,
+  pub delete_queue: blink_core::delete_queue::DeleteQueue,
+  pub firmware: blink_core::firmware::FirmwareTracker,
+  pub cleanup: Arc<blink_core::cleanup::CleanupQueue>,
+  pub events: blink_core::events::EventLog,
+  pub rearm: blink_core::rearm::RearmQueue,
+  pub battery: blink_core::battery::BatteryStore,
+  pub takeout: blink_core::takeout::Takeouts,
+  pub thumbnails: blink_core::thumbnail_cache::ThumbnailMemory,
+  pub request_budgets: crate::timeouts::RouteBudgets,
+  /// Media proxy fetches allowed in flight at once; see `MAX_CONCURRENT_FETCHES`.
+  pub fetch_permits: Semaphore
+}
+
+impl ServerState {
+  /// Builds a signed `/api/{route}?url=...` link that a `<video>`/`<img>` tag can load without headers.
+  pub fn signed_proxy_url(&self, route: &str, target: &str) -> Option<String> {
+    let encoded: String = url::form_urlencoded::byte_serialize(target.as_bytes()).collect();
+    let unsigned = format!("/api/{}?url={}", route, encoded);
+    self.signer.sign(&unsigned, Utc::now().timestamp() + DEFAULT_TTL_SECS).ok()
+  }
 }
 
 pub fn router() -> Router<Arc<ServerState>> {
   Router::new()
     .route("/api/clip", get(proxy_clip))
-    .route("/api/proxy", get(proxy_clip))
+    .route("/api/proxy", get(proxy_media))
     .route("/api/thumbnail", get(proxy_thumbnail))
     .route("/api/live/:network_id/:camera_id/:product_type", get(proxy_live))
+    .route("/api/live-sessions", get(list_live_sessions))
+    .route("/api/live-sessions/:id", delete(stop_live_session))
+    .route("/api/live-sessions/:id/replay", post(save_replay))
+    .route("/api/recordings", get(list_recordings))
+    .route("/api/export", post(export_clips))
 }
 
 async fn proxy_request_internal(
@@ -44,60 +113,75 @@ async fn proxy_request_internal(
   url: String,
   force_cache: bool,
 ) -> impl IntoResponse {
-  let mut client = state.blink_client.lock().await;
-  if let Ok(true) = client.refresh_token_if_needed().await {
-    let _ = crate::storage::save_auth(&client.get_state());
-  }
-  
+  let _permit = match state.fetch_permits.acquire().await {
+    Ok(permit) => permit,
+    Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+  };
+  let client = crate::shared::snapshot(&state.blink_client).await;
   let token = match &client.token {
     Some(t) => t.clone(),
     None => return (StatusCode::UNAUTHORIZED, "Not logged in").into_response(),
   };
 
-  let req_url = if url.starts_with("http") {
-    url.clone()
-  } else {
-    format!("{}{}", client.base_url, url)
+  let resolved = client.resolve_url(&url).map_err(|e| e.to_string());
+  let req_url = match resolved.and_then(|url| crate::shared::validate_media_url(url.as_str())) {
+    Ok(url) => url,
+    Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
   };
 
-  if let Ok(parsed) = url::Url::parse(&req_url) {
-    if let Some(host) = parsed.host_str() {
-      if !host.ends_with(".immedia-semi.com")
-        && !host.ends_with(".blinkforhome.com")
-        && !host.ends_with(".blink.com")
-        && !host.ends_with(".amazonaws.com")
-        && !host.ends_with(".cloudfront.net")
-      {
-        return (StatusCode::BAD_REQUEST, format!("Invalid URL host: {}", host)).into_response();
-      }
-    } else {
-      return (StatusCode::BAD_REQUEST, "Invalid URL").into_response();
-    }
-  } else {
-    return (StatusCode::BAD_REQUEST, "Invalid URL format").into_response();
-  }
-
-  let res = match client.client.get(&req_url)
-    .header("Authorization", format!("Bearer {}", token))
+  let res = match client.media_client.get(req_url)
+    .header("Authorization", format!("Bearer {}", token.expose_secret()))
     .send()
     .await {
       Ok(r) => r,
-      Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+      Err(e) => {
+        let message = e.to_string();
+        if force_cache {
+          if let Some(stale) = stale_thumbnail(&state, &url, thumbnail_cache::revalidate_delay(&e.into())) {
+            return stale;
+          }
+        }
+        return (StatusCode::INTERNAL_SERVER_ERROR, message).into_response();
+      }
     };
 
   let status = res.status();
+  if force_cache && !status.is_success() {
+    let error = crate::blink::BlinkError::from_response(status, "", "Thumbnail request failed", crate::blink::retry_after_header(res.headers()));
+    if let Some(stale) = stale_thumbnail(&state, &url, thumbnail_cache::revalidate_delay(&error.into())) {
+      return stale;
+    }
+  }
   let mut response_builder = Response::builder().status(status);
 
-  for (name, value) in res.headers().iter() {
-    if name != header::ACCESS_CONTROL_ALLOW_ORIGIN {
-      response_builder = response_builder.header(name, value);
-    }
+  // Thumbnails are buffered, so a copy can be kept to fall back on and the real type can
+  // be sniffed when Blink sends no image Content-Type.
+  let upstream_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+  let buffer = force_cache && status.is_success();
+
+  for (name, value) in passthrough_headers(res.headers(), buffer) {
+    response_builder = response_builder.header(name, value);
   }
 
   if force_cache {
     response_builder = response_builder.header(header::CACHE_CONTROL, "public, max-age=3600");
   }
 
+  if buffer {
+    let bytes = match read_thumbnail(&state, &url, res).await {
+      Ok(bytes) => bytes,
+      Err(failed) => return failed,
+    };
+    let content_type = crate::blink::image_content_type(upstream_type.as_deref(), &bytes);
+    state.thumbnails.store(&url, &crate::blink::Thumbnail { bytes: bytes.to_vec(), content_type: content_type.clone() });
+    return response_builder
+      .header(header::CONTENT_TYPE, content_type)
+      .header(thumbnail_cache::CACHE_HEADER, CacheStatus::Fresh.as_str())
+      .body(Body::from(bytes))
+      .unwrap()
+      .into_response();
+  }
+
   let stream = res.bytes_stream().map(|result| {
     result.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
   });
@@ -106,256 +190,281 @@ async fn proxy_request_internal(
   response_builder.body(body).unwrap().into_response()
 }
 
-async fn proxy_clip(
+/// The upstream headers a proxied response keeps: all but `Access-Control-Allow-Origin`,
+/// which the CORS layer sets, and, when the body is `buffered`, `Content-Type`, which is
+/// worked out again from the bytes.
+pub fn passthrough_headers(upstream: &http::HeaderMap, buffered: bool) -> impl Iterator<Item = (&http::HeaderName, &http::HeaderValue)> {
+  upstream.iter().filter(move |(name, _)| {
+    *name != header::ACCESS_CONTROL_ALLOW_ORIGIN && !(buffered && *name == header::CONTENT_TYPE)
+  })
+}
+
+/// Reads the body of a thumbnail response. A read that fails partway counts as a failed
+/// fetch, so the stale copy goes out if there is one.
+pub(crate) async fn read_thumbnail(state: &Arc<ServerState>, path: &str, res: reqwest::Response) -> Result<axum::body::Bytes, Response> {
+  res.bytes().await.map_err(|e| {
+    let message = e.to_string();
+    stale_thumbnail(state, path, thumbnail_cache::revalidate_delay(&e.into()))
+      .unwrap_or_else(|| (StatusCode::INTERNAL_SERVER_ERROR, message).into_response())
+  })
+}
+
+/// Serves the last copy of a thumbnail whose fetch just failed, marked stale, and fetches
+/// it again after `delay`. `None` when there is no copy, so the failure goes out as is.
+fn stale_thumbnail(state: &Arc<ServerState>, path: &str, delay: std::time::Duration) -> Option<Response> {
+  let thumbnail = state.thumbnails.stale(path)?;
+  spawn_revalidation(state, path, delay);
+  Some(Response::builder()
+    .header(header::CONTENT_TYPE, thumbnail.content_type)
+    .header(header::CACHE_CONTROL, "no-cache")
+    .header(thumbnail_cache::CACHE_HEADER, CacheStatus::Stale.as_str())
+    .header(thumbnail_cache::STALE_HEADER, "true")
+    .body(Body::from(thumbnail.bytes))
+    .unwrap())
+}
+
+/// Fetches `path` again after `delay` so the next request gets a fresh copy; one pending
+/// fetch per path.
+pub fn spawn_revalidation(state: &Arc<ServerState>, path: &str, delay: std::time::Duration) {
+  if !state.thumbnails.revalidations.begin(path) {
+    return;
+  }
+  let state = state.clone();
+  let path = path.to_string();
+  tokio::spawn(async move {
+    tokio::time::sleep(delay).await;
+    match crate::shared::snapshot(&state.blink_client).await.get_thumbnail(&path).await {
+      Ok(thumbnail) => state.thumbnails.store(&path, &thumbnail),
+      Err(e) => eprintln!("Thumbnail revalidation failed: {}", e),
+    }
+    state.thumbnails.revalidations.end(&path);
+  });
+}
+
+#[utoipa::path(
+  get, path = "/api/clip", tag = "media",
+  params(ProxyQuery),
+  responses(
+    (status = 200, description = "Upstream body streamed through with its headers"),
+    (status = 400, description = "URL host is not allow-listed", body = ErrorBody, content_type = "text/plain"),
+    (status = 401, description = "Not logged in", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []), ("signed_url" = []))
+)]
+pub async fn proxy_clip(
   State(state): State<Arc<ServerState>>,
   Query(query): Query<ProxyQuery>,
 ) -> impl IntoResponse {
   proxy_request_internal(state, query.url, false).await
 }
 
-async fn proxy_thumbnail(
+#[utoipa::path(
+  get, path = "/api/proxy", tag = "media",
+  params(ProxyQuery),
+  responses(
+    (status = 200, description = "Upstream body streamed through with its headers"),
+    (status = 400, description = "URL host is not allow-listed", body = ErrorBody, content_type = "text/plain"),
+    (status = 401, description = "Not logged in", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []), ("signed_url" = []))
+)]
+pub async fn proxy_media(
   State(state): State<Arc<ServerState>>,
   Query(query): Query<ProxyQuery>,
 ) -> impl IntoResponse {
-  proxy_request_internal(state, query.url, true).await
+  proxy_request_internal(state, query.url, false).await
 }
 
-async fn proxy_live(
+#[utoipa::path(
+  post, path = "/api/export", tag = "media",
+  request_body = ExportRequest,
+  responses(
+    (status = 200, description = "ZIP of the clips plus `manifest.json`, streamed as it is built", content_type = "application/zip"),
+    (status = 400, description = "No items were given", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+pub async fn export_clips(
   State(state): State<Arc<ServerState>>,
-  Path((network_id, camera_id, product_type)): Path<(i64, i64, String)>,
-  Query(query): Query<LiveQuery>,
+  Json(payload): Json<ExportRequest>,
 ) -> impl IntoResponse {
-  let serial = query.serial.unwrap_or_default();
-  let record = query.record.unwrap_or(false);
-  let session_start_time = Utc::now();
-  
-  let mut lv_res = None;
-  let mut retries = 0;
-  let max_retries = 5;
-
-  while retries < max_retries {
-    let res = {
-      let mut client = state.blink_client.lock().await;
-      if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = crate::storage::save_auth(&client.get_state());
-      }
-      client.request_liveview(network_id, camera_id, &product_type, record).await
-    };
-
-    match res {
-      Ok(res) => {
-        lv_res = Some(res);
-        break;
-      }
-      Err(e) => {
-        let err_msg = e.to_string();
-        eprintln!("Liveview request failed: {}", err_msg);
-        if err_msg.contains("307") || err_msg.contains("busy") {
-          tokio::time::sleep(std::time::Duration::from_secs(4)).await;
-          retries += 1;
-          continue;
-        } else {
-          return (StatusCode::INTERNAL_SERVER_ERROR, format!("Blink API Error: {}", err_msg)).into_response();
-        }
-      }
-    }
+  if payload.items.is_empty() {
+    return (StatusCode::BAD_REQUEST, "No items to export").into_response();
   }
 
-  let lv_res = match lv_res {
-    Some(res) => res,
-    None => return (StatusCode::SERVICE_UNAVAILABLE, "Camera remains busy after retries").into_response(),
-  };
-
-  let immi = match ImmiStream::connect(&lv_res.server, &serial).await {
-    Ok(s) => s,
-    Err(e) => {
-      eprintln!("IMMI connection failed: {}", e);
-      return (StatusCode::INTERNAL_SERVER_ERROR, format!("IMMI Connection Failed: {}", e)).into_response();
-    }
-  };
-
-  let (mut immi_rx, mut immi_tx) = (immi.reader, immi.writer);
-  let cancel_token = CancellationToken::new();
-  let (tx, rx) = mpsc::channel::<Result<axum::body::Bytes, std::io::Error>>(100);
-
-  let token_keepalive = cancel_token.clone();
-  let blink_client_inner = state.blink_client.clone();
-  let polling_interval = lv_res.polling_interval as u64;
-  let cmd_id = lv_res.command_id;
-  
+  let (writer, reader) = tokio::io::duplex(64 * 1024);
+  let blink_client = state.blink_client.clone();
+  let template = state.settings.read().unwrap().download_filename_template.clone();
   tokio::spawn(async move {
-    let mut last_poll = std::time::Instant::now();
-    let mut keepalive_seq = 0u32;
-    let mut every10s = 0u32;
-
-    let _ = immi::send_latency_stats(&mut immi_tx).await;
-    keepalive_seq += 1;
-    let _ = immi::send_keepalive(&mut immi_tx, keepalive_seq).await;
-
-    loop {
-      tokio::select! {
-        _ = token_keepalive.cancelled() => {
-          break;
-        }
-        _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
-          if let Err(_) = immi::send_latency_stats(&mut immi_tx).await {
-            break;
-          }
-
-          every10s += 1;
-          if (every10s % 10) == 0 {
-            keepalive_seq += 1;
-            if let Err(_) = immi::send_keepalive(&mut immi_tx, keepalive_seq).await {
-              break;
-            }
-          }
-
-          if last_poll.elapsed().as_secs() >= polling_interval {
-            let res = {
-              let client = blink_client_inner.lock().await;
-              client.get_command_status(network_id, cmd_id).await
-            };
-            match res {
-              Ok(status) => {
-                let mut running = false;
-                if let Some(cmds) = status["commands"].as_array() {
-                  for c in cmds {
-                    if c["id"].as_i64() == Some(cmd_id) {
-                      let state = c["state_condition"].as_str().unwrap_or("");
-                      if state == "new" || state == "running" { running = true; }
-                    }
-                  }
-                }
-                if !running {
-                  break;
-                }
-              }
-              Err(_) => break,
-            }
-            last_poll = std::time::Instant::now();
-          }
-        }
+    // There is no event channel to the browser here, so per-file progress goes to the log.
+    let mut files_done = 0;
+    let result = export::write_clips_zip(&blink_client, &payload.items, &template, payload.compression, writer, |progress| {
+      if progress.files_done != files_done {
+        files_done = progress.files_done;
+        eprintln!("Export: {}/{} {}", progress.files_done, progress.file_count, progress.file_name);
       }
+    }).await;
+    match result {
+      Ok(summary) => eprintln!("Export finished: {} written, {} skipped", summary.written, summary.skipped),
+      Err(e) => eprintln!("Export failed: {}", e),
     }
-    token_keepalive.cancel();
   });
 
-  let token_reader = cancel_token.clone();
-  let tx_clone = tx.clone();
-  
-  tokio::spawn(async move {
-    let mut null_packet = vec![0x47, 0x1F, 0xFF, 0x10];
-    null_packet.extend(vec![0xFF; 184]);
-    
-    for _ in 0..3 {
-      if tx_clone.send(Ok(axum::body::Bytes::from(null_packet.clone()))).await.is_err() {
-        token_reader.cancel();
-        return;
-      }
-    }
-    
-    let mut mpegts_started = false;
-    let mut last_null_packet = std::time::Instant::now();
-    let stream_start_time = std::time::Instant::now();
-    
-    loop {
-      let packet_res = tokio::select! {
-        _ = token_reader.cancelled() => break,
-        res = tokio::time::timeout(std::time::Duration::from_secs(20), immi::read_packet(&mut immi_rx)) => res,
-      };
-
-      match packet_res {
-        Ok(Ok((msg_type, payload))) => {
-          if msg_type == 0x00 && !payload.is_empty() {
-            if payload[0] == 0x47 {
-              if !mpegts_started {
-                mpegts_started = true;
-                eprintln!("First MPEG-TS packet received after {}ms", stream_start_time.elapsed().as_millis());
-              }
-              if tx_clone.send(Ok(axum::body::Bytes::from(payload))).await.is_err() {
-                break;
-              }
-              last_null_packet = std::time::Instant::now();
-            }
-          } else if !mpegts_started && last_null_packet.elapsed().as_secs() >= 2 {
-            if tx_clone.send(Ok(axum::body::Bytes::from(null_packet.clone()))).await.is_err() {
-              break;
-            }
-            last_null_packet = std::time::Instant::now();
-          }
-        }
-        Ok(Err(e)) => {
-          eprintln!("IMMI read error: {}", e);
-          break;
-        }
-        Err(_) => {
-          eprintln!("IMMI read timeout");
-          break;
-        }
-      }
-      
-      if !mpegts_started && stream_start_time.elapsed().as_secs() > 35 {
-        eprintln!("Stream timed out waiting for data");
-        break;
-      }
-    }
-    token_reader.cancel();
-  });
+  let filename = format!("blink-export-{}.zip", Utc::now().format("%Y%m%d-%H%M%S"));
+  Response::builder()
+    .header(header::CONTENT_TYPE, "application/zip")
+    .header(header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename))
+    .body(Body::from_stream(ReaderStream::new(reader)))
+    .unwrap()
+    .into_response()
+}
 
-  let cleanup_token = cancel_token.clone();
-  let cleanup_client = state.blink_client.clone();
-  
-  tokio::spawn(async move {
-    cleanup_token.cancelled().await;
-    
-    if !record {
-      let delays = [5u64, 10, 15, 20, 25, 30];
-      let search_after = session_start_time - Duration::seconds(60);
-      for delay in delays {
-        tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
-        
-        let ids = {
-          let client = cleanup_client.lock().await;
-          client.get_latest_media_for_camera(camera_id, search_after).await
-        };
-
-        match ids {
-          Ok(ids) => {
-            if !ids.is_empty() {
-              {
-                let client = cleanup_client.lock().await;
-                let _ = client.delete_media(ids.clone()).await;
-              };
-
-              tokio::time::sleep(std::time::Duration::from_secs(4)).await;
-
-              let verification = {
-                let client = cleanup_client.lock().await;
-                client.get_latest_media_for_camera(camera_id, search_after).await
-              };
-
-              match verification {
-                Ok(remaining_ids) if remaining_ids.is_empty() => {
-                  break;
-                }
-                _ => {}
-              }
-            }
-          }
-          Err(_) => {}
-        }
-      }
-    }
-  });
+#[utoipa::path(
+  get, path = "/api/thumbnail", tag = "media",
+  params(ProxyQuery),
+  responses(
+    (status = 200, description = "Upstream body streamed through with its headers"),
+    (status = 400, description = "URL host is not allow-listed", body = ErrorBody, content_type = "text/plain"),
+    (status = 401, description = "Not logged in", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []), ("signed_url" = []))
+)]
+pub async fn proxy_thumbnail(
+  State(state): State<Arc<ServerState>>,
+  Query(query): Query<ProxyQuery>,
+) -> impl IntoResponse {
+  proxy_request_internal(state, query.url, true).await
+}
 
-  let body_stream = ReceiverStream::new(rx);
-  let body = Body::from_stream(body_stream);
+#[utoipa::path(
+  get, path = "/api/live/{network_id}/{camera_id}/{product_type}", tag = "live",
+  params(
+    ("network_id" = i64, Path, description = "Blink network id"),
+    ("camera_id" = i64, Path, description = "Camera id"),
+    ("product_type" = String, Path, description = "Camera `type` as Blink reports it, e.g. `catalina` or `hawk`"),
+    LiveQuery
+  ),
+  responses(
+    (status = 200, description = "MPEG-TS stream; the `X-Live-Session-Id` header identifies the session", content_type = "video/mp2t"),
+    (status = 500, description = "Blink or IMMI connection failed", body = ErrorBody, content_type = "text/plain"),
+    (status = 503, description = "Camera remained busy after retries", body = ErrorBody, content_type = "text/plain",
+      headers(("Retry-After" = u64, description = "Seconds to wait before trying again")))
+  ),
+  security(("api_key" = []), ("signed_url" = []))
+)]
+pub async fn proxy_live(
+  State(state): State<Arc<ServerState>>,
+  Path((network_id, camera_id, product_type)): Path<(i64, i64, String)>,
+  Query(query): Query<LiveQuery>,
+) -> impl IntoResponse {
+  let settings = state.settings.read().unwrap().clone();
+  let request = LiveviewRequest {
+    network_id,
+    camera_id,
+    product_type,
+    serial: query.serial.unwrap_or_default(),
+    record: query.record.unwrap_or(false),
+    options: LiveviewOptions {
+      quality: query.quality.or_else(|| settings.liveview_quality(camera_id)),
+      audio: query.audio,
+      intent: None
+    },
+    mode: query.mode.unwrap_or_default(),
+    fake: query.fake.unwrap_or(false)
+  };
+  let live = match liveview::start(&state.blink_client, &state.live_sessions, &state.cleanup, &settings, request).await {
+    Ok(live) => live,
+    Err(e @ LiveviewError::Busy { retry_after }) => {
+      return (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry_after.to_string())], e.to_string()).into_response();
+    }
+    Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+  };
 
+  let body_stream = ReceiverStream::new(live.chunks).map(Ok::<_, std::io::Error>);
   Response::builder()
     .header(header::CONTENT_TYPE, "video/mp2t")
     .header(header::CACHE_CONTROL, "no-cache")
     .header(header::CONNECTION, "keep-alive")
-    .body(body)
+    .header("X-Live-Session-Id", live.session.id.as_str())
+    .body(Body::from_stream(body_stream))
     .unwrap()
     .into_response()
 }
+
+#[utoipa::path(
+  get, path = "/api/live-sessions", tag = "live",
+  responses((status = 200, description = "Active liveview sessions", body = Vec<LiveSessionInfo>)),
+  security(("api_key" = []))
+)]
+pub async fn list_live_sessions(State(state): State<Arc<ServerState>>) -> Json<Vec<LiveSessionInfo>> {
+  Json(state.live_sessions.list())
+}
+
+#[utoipa::path(
+  delete, path = "/api/live-sessions/{id}", tag = "live",
+  params(("id" = String, Path, description = "Session id from the list or the `X-Live-Session-Id` header")),
+  responses(
+    (status = 204, description = "Session cancelled and Blink command stopped"),
+    (status = 404, description = "Unknown session", body = ErrorBody, content_type = "text/plain"),
+    (status = 502, description = "Session cancelled but Blink rejected the stop", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+pub async fn stop_live_session(
+  State(state): State<Arc<ServerState>>,
+  Path(id): Path<String>,
+) -> impl IntoResponse {
+  match state.live_sessions.stop(&id, &state.blink_client).await {
+    Ok(true) => StatusCode::NO_CONTENT.into_response(),
+    Ok(false) => (StatusCode::NOT_FOUND, "Unknown live session").into_response(),
+    Err(e) => (StatusCode::BAD_GATEWAY, format!("Session cancelled but stopping the Blink command failed: {}", e)).into_response(),
+  }
+}
+
+#[utoipa::path(
+  post, path = "/api/live-sessions/{id}/replay", tag = "live",
+  params(("id" = String, Path, description = "Session id from the list or the `X-Live-Session-Id` header")),
+  responses(
+    (status = 200, description = "The buffered seconds, from the earliest keyframe, saved as MPEG-TS under the data directory's `replays` folder", body = ReplaySaved),
+    (status = 404, description = "Unknown session", body = ErrorBody, content_type = "text/plain"),
+    (status = 409, description = "Replay is off or no keyframe has been buffered yet", body = ErrorBody, content_type = "text/plain"),
+    (status = 500, description = "Writing the file failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+pub async fn save_replay(
+  State(state): State<Arc<ServerState>>,
+  Path(id): Path<String>,
+) -> impl IntoResponse {
+  let Some(session) = state.live_sessions.get(&id) else {
+    return (StatusCode::NOT_FOUND, "Unknown live session").into_response();
+  };
+  let dest = blink_monitor_server::storage::data_dir()
+    .join("replays")
+    .join(format!("replay-{}-{}.ts", session.camera_id, Utc::now().format("%Y%m%d-%H%M%S")));
+  match tokio::task::spawn_blocking(move || session.stats.replay.save(&session.id, &dest)).await {
+    Ok(Ok(Some(saved))) => Json::<ReplaySaved>(saved).into_response(),
+    Ok(Ok(None)) => (StatusCode::CONFLICT, "Nothing playable buffered for this session yet").into_response(),
+    Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save replay: {}", e)).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to save replay: {}", e)).into_response(),
+  }
+}
+
+#[utoipa::path(
+  get, path = "/api/recordings", tag = "live",
+  responses(
+    (status = 200, description = "Recordings in `liveview_recording_dir`, newest first, with durations read from the files; empty when no folder is set", body = Vec<RecordingInfo>),
+    (status = 500, description = "Reading the folder failed", body = ErrorBody, content_type = "text/plain")
+  ),
+  security(("api_key" = []))
+)]
+pub async fn list_recordings(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+  let Some(dir) = state.settings.read().unwrap().liveview_recording_dir.clone() else {
+    return Json(Vec::<RecordingInfo>::new()).into_response();
+  };
+  match tokio::task::spawn_blocking(move || recording::list(std::path::Path::new(&dir))).await {
+    Ok(Ok(recordings)) => Json(recordings).into_response(),
+    Ok(Err(e)) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list recordings: {}", e)).into_response(),
+    Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to list recordings: {}", e)).into_response(),
+  }
+}
@@ -0,0 +1,1668 @@
+use axum::{
+  response::{Html, IntoResponse},
+  Json
+};
+use http::StatusCode;
+use utoipa::{OpenApi, ToSchema};
+
+// `blink.rs`, `live_sessions.rs`, `shared.rs`, `export.rs`, `settings.rs`, `schedule.rs`, `media_index.rs` and `audit.rs` are shared with the desktop app, which does not
+// depend on utoipa, so their wire shapes are described here with schema-only mirrors.
+
+#[derive(ToSchema)]
+#[schema(as = CameraSignals)]
+#[allow(dead_code)]
+pub struct CameraSignalsSchema {
+  wifi: Option<i64>,
+  battery: Option<i64>,
+  temp: Option<i64>
+}
+
+#[derive(ToSchema)]
+#[schema(as = Camera)]
+#[allow(dead_code)]
+pub struct CameraSchema {
+  id: i64,
+  name: String,
+  thumbnail: String,
+  status: String,
+  battery: Option<String>,
+  signals: Option<CameraSignalsSchema>,
+  network_id: Option<i64>,
+  /// Blink's model codename, e.g. `catalina`, `hawk` or `lotus`.
+  #[schema(rename = "type")]
+  product_type: String,
+  device_family: DeviceFamilySchema,
+  serial: Option<String>,
+  fw_version: Option<String>,
+  /// Blink's notification snooze is on
+  snoozed: bool,
+  /// Local nickname, or the Blink name when none is set.
+  display_name: String,
+  sort_order: Option<i32>,
+  hidden: bool,
+  liveview_quality: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = AuthState)]
+#[allow(dead_code)]
+pub enum AuthStateSchema {
+  #[schema(rename = "logged_out")]
+  LoggedOut,
+  #[schema(rename = "logged_in")]
+  LoggedIn,
+  #[schema(rename = "reauth_required")]
+  ReauthRequired
+}
+
+#[derive(ToSchema)]
+#[schema(as = DeviceFamily)]
+#[allow(dead_code)]
+pub enum DeviceFamilySchema {
+  #[schema(rename = "camera")]
+  Camera,
+  #[schema(rename = "owl")]
+  Owl,
+  #[schema(rename = "doorbell")]
+  Doorbell
+}
+
+#[derive(ToSchema)]
+#[schema(as = MediaItem)]
+#[allow(dead_code)]
+pub struct MediaItemSchema {
+  id: i64,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+  deleted: bool,
+  device_id: Option<i64>,
+  device_name: Option<String>,
+  network_id: Option<i64>,
+  network_name: Option<String>,
+  #[schema(rename = "type")]
+  media_type: Option<String>,
+  source: Option<String>,
+  thumbnail: Option<String>,
+  media: Option<String>,
+  time_zone: Option<String>,
+  watched: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = DeleteStatus)]
+#[allow(dead_code)]
+pub enum DeleteStatusSchema {
+  #[schema(rename = "deleted")]
+  Deleted,
+  #[schema(rename = "still_present")]
+  StillPresent,
+  #[schema(rename = "not_found")]
+  NotFound,
+  #[schema(rename = "pending")]
+  Pending
+}
+
+#[derive(ToSchema)]
+#[schema(as = DeleteResult)]
+#[allow(dead_code)]
+pub struct DeleteResultSchema {
+  id: i64,
+  status: DeleteStatusSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveviewSettingCandidate)]
+#[allow(dead_code)]
+pub struct LiveviewSettingCandidateSchema {
+  path: String,
+  #[schema(value_type = Object)]
+  value: serde_json::Value,
+  network_id: Option<i64>,
+  network_name: Option<String>,
+  device_kind: Option<CandidateDeviceKindSchema>,
+  device_id: Option<i64>
+}
+
+#[derive(ToSchema)]
+#[schema(as = CandidateDeviceKind)]
+#[allow(dead_code)]
+pub enum CandidateDeviceKindSchema {
+  #[schema(rename = "network")]
+  Network,
+  #[schema(rename = "sync_module")]
+  SyncModule,
+  #[schema(rename = "camera")]
+  Camera,
+  #[schema(rename = "owl")]
+  Owl,
+  #[schema(rename = "doorbell")]
+  Doorbell
+}
+
+#[derive(ToSchema)]
+#[schema(as = ExportCompression)]
+#[allow(dead_code)]
+pub enum ExportCompressionSchema {
+  #[schema(rename = "store")]
+  Store,
+  #[schema(rename = "deflate")]
+  Deflate
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveSessionInfo)]
+#[allow(dead_code)]
+pub struct LiveSessionInfoSchema {
+  id: String,
+  network_id: i64,
+  camera_id: i64,
+  started_at: chrono::DateTime<chrono::Utc>,
+  subscriber_count: usize,
+  bytes_sent: u64,
+  /// Chunks skipped because the consumer read too slowly
+  dropped_chunks: u64,
+  dropped_bytes: u64,
+  recording_path: Option<String>,
+  /// Liveview parameters sent to Blink and the ones it confirmed
+  params: LiveviewParamsSchema,
+  latency: LiveLatencySchema,
+  replay: ReplayUsageSchema,
+  /// Times the liveview command was extended past the duration Blink first gave it
+  extends: u64,
+  /// Set once Blink refused another extension: the stream stops at this time. A `live_expiring` event is recorded then too
+  expires_at: Option<chrono::DateTime<chrono::Utc>>
+}
+
+#[derive(ToSchema)]
+#[schema(as = ReplayUsage)]
+#[allow(dead_code)]
+pub struct ReplayUsageSchema {
+  /// Configured replay window; 0 when replay is off
+  window_secs: u64,
+  /// Seconds of stream held, oldest chunk to newest
+  buffered_secs: f64,
+  /// Memory held by the buffer (capped at 32 MiB per session)
+  buffered_bytes: usize
+}
+
+#[derive(ToSchema)]
+#[schema(as = ReplaySaved)]
+#[allow(dead_code)]
+pub struct ReplaySavedSchema {
+  session_id: String,
+  /// Where the MPEG-TS file was written
+  path: String,
+  bytes: u64,
+  /// Seconds from the keyframe the file starts on to its end
+  duration_secs: f64
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveLatency)]
+#[allow(dead_code)]
+pub struct LiveLatencySchema {
+  /// Time to open and authenticate the IMMI connection; null for the test pattern
+  connect_ms: Option<u64>,
+  /// From the IMMI connection to the first MPEG-TS packet
+  first_packet_ms: Option<u64>,
+  /// Round trip of keepalives the IMMI server echoed
+  keepalive_rtt: Option<LatencySummarySchema>,
+  /// Estimated end-to-end delay: the first-packet wait plus how far the wall clock has run ahead of the stream's PCR
+  stream_delay: Option<LatencySummarySchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = LatencySummary)]
+#[allow(dead_code)]
+pub struct LatencySummarySchema {
+  last_ms: f64,
+  min_ms: f64,
+  avg_ms: f64,
+  max_ms: f64,
+  samples: u64
+}
+
+#[derive(ToSchema)]
+#[schema(as = RecordingFormat)]
+#[allow(dead_code)]
+pub enum RecordingFormatSchema {
+  #[schema(rename = "ts")]
+  Ts,
+  #[schema(rename = "mp4")]
+  Mp4
+}
+
+#[derive(ToSchema)]
+#[schema(as = RecordingInfo)]
+#[allow(dead_code)]
+pub struct RecordingInfoSchema {
+  path: String,
+  /// `ts` when the session was recorded as MPEG-TS or the MP4 could not be finished
+  format: RecordingFormatSchema,
+  bytes: u64,
+  /// Read from the file itself; null when it can't be parsed
+  duration_secs: Option<f64>,
+  /// From the sidecar JSON, or the file's modification time without one
+  started_at: chrono::DateTime<chrono::Utc>,
+  session_id: Option<String>,
+  network_id: Option<i64>,
+  camera_id: Option<i64>,
+  /// Why the recording isn't in the requested format, or is incomplete
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveviewOptions)]
+#[allow(dead_code)]
+pub struct LiveviewOptionsSchema {
+  /// `saver` or `best`
+  quality: Option<String>,
+  audio: Option<bool>,
+  intent: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveviewParams)]
+#[allow(dead_code)]
+pub struct LiveviewParamsSchema {
+  requested: LiveviewOptionsSchema,
+  /// Parameters Blink echoed back; anything missing was not acknowledged
+  honored: LiveviewOptionsSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = Settings)]
+#[allow(dead_code)]
+pub struct SettingsSchema {
+  version: u32,
+  download_concurrency: usize,
+  /// Placeholders: {camera}, {network}, {date}, {time}, {id}.
+  download_filename_template: String,
+  download_sidecar: bool,
+  motion_alerts_enabled: bool,
+  motion_interval_secs: u64,
+  liveview_busy_retries: u32,
+  liveview_retry_delay_secs: u64,
+  liveview_read_timeout_secs: u64,
+  /// Delete the clip Blink records for each liveview unless the request sets `record`
+  liveview_cleanup_enabled: bool,
+  /// Seconds before a liveview in which unmarked clips still count as its own (at most 120)
+  liveview_cleanup_lookback_secs: u64,
+  /// Seconds of each live session buffered for instant replay (0 disables, at most 120)
+  liveview_replay_secs: u64,
+  /// Live sessions requested with `record` are also written here
+  liveview_recording_dir: Option<String>,
+  /// `mp4` (default; remuxed, faststart) or `ts` (the camera's stream as received)
+  liveview_recording_format: RecordingFormatSchema,
+  close_to_tray: bool,
+  schedules: Vec<ScheduleEntrySchema>,
+  media_index_enabled: bool,
+  /// Keyed by camera id.
+  camera_prefs: std::collections::BTreeMap<String, CameraPrefsSchema>,
+  /// Desktop only: allow writing discovered settings
+  advanced_settings_enabled: bool,
+  /// Deferred deletions archive clips here first
+  archive_dir: Option<String>,
+  /// Archive backend; takes precedence over `archive_dir`
+  archive_store: Option<ClipStoreConfigSchema>,
+  /// Most events the event log keeps
+  event_retention_rows: u64,
+  /// Events older than this many days are pruned
+  event_retention_days: u32,
+  /// Allow `/api/diagnose-media`
+  diagnostics_enabled: bool,
+  /// Receivers for every logged event
+  webhooks: Vec<WebhookTargetSchema>,
+  /// Clips per delete request in a queued delete (1-100)
+  delete_batch_size: usize,
+  /// Seconds between queued delete batches (at most 120)
+  delete_batch_delay_secs: u64,
+  /// When to send a summary report to the webhooks; never when null
+  summary_report: Option<ReportScheduleSchema>,
+  /// ntfy and Gotify targets for motion, offline and low battery alerts
+  notifiers: Vec<NotifierTargetSchema>,
+  /// Desktop only: scheduled thumbnail refreshes for cameras with an interval in `camera_prefs`
+  thumbnail_refresh: ThumbnailRefreshSettingsSchema,
+  /// Desktop only: folders granted for downloads besides Downloads
+  download_dirs: Vec<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = ReportSchedule)]
+#[allow(dead_code)]
+pub struct ReportScheduleSchema {
+  /// `daily` or `weekly`; the report covers the day or week before each run
+  period: String,
+  /// `HH:MM`, UTC
+  #[schema(example = "07:00")]
+  time: String,
+  /// `Mon`..`Sun`, for weekly reports (default `Mon`)
+  day: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = SummaryReport)]
+#[allow(dead_code)]
+pub struct SummaryReportSchema {
+  /// RFC 3339
+  from: String,
+  /// RFC 3339
+  to: String,
+  /// RFC 3339
+  generated_at: String,
+  /// Clip counts are empty when the media index is off
+  media_index_enabled: bool,
+  total_clips: u64,
+  clips_per_camera_day: Vec<CameraDayClipsSchema>,
+  /// Busiest first
+  busiest_hours: Vec<HourClipsSchema>,
+  battery: Vec<BatteryChangeSchema>,
+  arm_history: Vec<ArmChangeSchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = CameraDayClips)]
+#[allow(dead_code)]
+pub struct CameraDayClipsSchema {
+  camera_id: Option<i64>,
+  camera_name: Option<String>,
+  /// `YYYY-MM-DD`, UTC
+  day: String,
+  clips: u64
+}
+
+#[derive(ToSchema)]
+#[schema(as = ActivityHeatmap)]
+#[allow(dead_code)]
+pub struct ActivityHeatmapSchema {
+  camera_id: i64,
+  camera_name: Option<String>,
+  /// IANA zone the counts are in: the camera's network zone, or `UTC` when it has none
+  time_zone: String,
+  /// RFC 3339
+  from: String,
+  /// RFC 3339
+  to: String,
+  /// 7 rows of 24 clip counts: `counts[weekday][hour]`, Monday first, local time
+  counts: Vec<Vec<u64>>,
+  total: u64,
+  /// Clips per local day, oldest first, days without clips included
+  per_day: Vec<DayClipsSchema>,
+  /// `index`, or `live` when the media index is off or doesn't reach back to `from`
+  source: MediaSourceSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = PrefetchResult)]
+#[allow(dead_code)]
+pub struct PrefetchResultSchema {
+  path: String,
+  /// `fresh`, `stale` when Blink failed but an older copy is kept, or null with `error` set
+  cache: Option<String>,
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = DayClips)]
+#[allow(dead_code)]
+pub struct DayClipsSchema {
+  /// `YYYY-MM-DD`, local
+  day: String,
+  clips: u64
+}
+
+#[derive(ToSchema)]
+#[schema(as = HourClips)]
+#[allow(dead_code)]
+pub struct HourClipsSchema {
+  /// 0-23, UTC
+  hour: u32,
+  clips: u64
+}
+
+#[derive(ToSchema)]
+#[schema(as = BatteryChange)]
+#[allow(dead_code)]
+pub struct BatteryChangeSchema {
+  camera_id: i64,
+  first_level: f64,
+  last_level: f64,
+  /// Reported low at some point in the range
+  low: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = ArmChange)]
+#[allow(dead_code)]
+pub struct ArmChangeSchema {
+  /// RFC 3339
+  ts: String,
+  network_id: Option<i64>,
+  /// Null when the event doesn't say
+  armed: Option<bool>,
+  /// `arm`, `rearm` or `schedule_run`
+  source: String,
+  error: Option<String>
+}
+
+/// `kind` is `local` (with `dir`), `s3` (with `endpoint`, `bucket`, optional `region`
+/// and `prefix`; keys from `BLINK_S3_ACCESS_KEY_ID`/`BLINK_S3_SECRET_ACCESS_KEY`) or
+/// `webdav` (with `url` and optional `username`; password from `BLINK_WEBDAV_PASSWORD`).
+#[derive(ToSchema)]
+#[schema(as = ClipStoreConfig)]
+#[allow(dead_code)]
+pub struct ClipStoreConfigSchema {
+  kind: String,
+  dir: Option<String>,
+  endpoint: Option<String>,
+  bucket: Option<String>,
+  region: Option<String>,
+  prefix: Option<String>,
+  url: Option<String>,
+  username: Option<String>
+}
+
+/// `kind` is `ntfy` (with `topic` and optional `server`, default `https://ntfy.sh`; token
+/// from `BLINK_NTFY_TOKEN` when set) or `gotify` (with `server`; application token from
+/// `BLINK_GOTIFY_TOKEN`).
+#[derive(ToSchema)]
+#[schema(as = NotifierTarget)]
+#[allow(dead_code)]
+pub struct NotifierTargetSchema {
+  kind: String,
+  server: Option<String>,
+  topic: Option<String>,
+  /// Priority (`min`, `low`, `default`, `high`, `urgent`) keyed by alert type (`motion`,
+  /// `offline`, `low_battery`, `usb_storage`); types left out aren't sent
+  alerts: std::collections::BTreeMap<String, String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = NotifyResult)]
+#[allow(dead_code)]
+pub struct NotifyResultSchema {
+  /// The service and where it sends
+  target: String,
+  ok: bool,
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = StoredClip)]
+#[allow(dead_code)]
+pub struct StoredClipSchema {
+  /// Path relative to the archive root
+  key: String,
+  size: u64,
+  /// RFC 3339
+  modified: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = WebhookTarget)]
+#[allow(dead_code)]
+pub struct WebhookTargetSchema {
+  /// http or https
+  url: String,
+  /// Event kinds to send; empty sends all
+  kinds: Vec<String>,
+  /// `slack`, `discord` or `ntfy`; takes precedence over `template`
+  format: Option<String>,
+  /// JSON payload with `{{path}}` placeholders such as `{{kind}}`, `{{summary}}` or `{{data.network_id}}`
+  template: Option<String>,
+  /// Sent with every request
+  headers: std::collections::BTreeMap<String, String>,
+  /// `POST` (default) or `PUT`
+  method: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = SelfTestStage)]
+#[allow(dead_code)]
+pub enum SelfTestStageSchema {
+  #[schema(rename = "storage")]
+  Storage,
+  #[schema(rename = "token")]
+  Token,
+  #[schema(rename = "refresh")]
+  Refresh,
+  #[schema(rename = "tier")]
+  Tier,
+  #[schema(rename = "homescreen")]
+  Homescreen,
+  #[schema(rename = "thumbnail")]
+  Thumbnail,
+  #[schema(rename = "media_page")]
+  MediaPage
+}
+
+#[derive(ToSchema)]
+#[schema(as = StageStatus)]
+#[allow(dead_code)]
+pub enum StageStatusSchema {
+  #[schema(rename = "passed")]
+  Passed,
+  #[schema(rename = "failed")]
+  Failed,
+  #[schema(rename = "skipped")]
+  Skipped
+}
+
+#[derive(ToSchema)]
+#[schema(as = StageResult)]
+#[allow(dead_code)]
+pub struct StageResultSchema {
+  stage: SelfTestStageSchema,
+  status: StageStatusSchema,
+  duration_ms: u64,
+  detail: Option<String>,
+  /// Tokens and signed query strings are removed
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = SelfTestReport)]
+#[allow(dead_code)]
+pub struct SelfTestReportSchema {
+  passed: bool,
+  /// RFC 3339
+  started_at: String,
+  failed_stage: Option<SelfTestStageSchema>,
+  stages: Vec<StageResultSchema>,
+  clock_skew: ClockSkewReportSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = ClockSkewReport)]
+#[allow(dead_code)]
+pub struct ClockSkewReportSchema {
+  /// Seconds Blink's clock is ahead of the local one (negative when behind)
+  skew_secs: i64,
+  samples: u64,
+  /// RFC 3339
+  updated_at: Option<String>,
+  refresh_margin_secs: i64,
+  warning: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = WebhookPayload)]
+#[allow(dead_code)]
+pub struct WebhookPayloadSchema {
+  content_type: String,
+  body: String,
+  /// Why the template was not used
+  warning: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = SettingWriteOutcome)]
+#[allow(dead_code)]
+pub enum SettingWriteOutcomeSchema {
+  #[schema(rename = "applied")]
+  Applied,
+  /// Blink refused the update; `error` says why
+  #[schema(rename = "rejected")]
+  Rejected,
+  /// Blink accepted the update but the homescreen still shows the old value
+  #[schema(rename = "unchanged")]
+  Unchanged
+}
+
+/// One registered liveview flag as it stands on a network or device.
+#[derive(ToSchema)]
+#[schema(as = LiveviewSetting)]
+#[allow(dead_code)]
+pub struct LiveviewSettingSchema {
+  /// `lv_save`, `extended_liveview` or `doorbell_auto_save`
+  flag: String,
+  device_kind: Option<CandidateDeviceKindSchema>,
+  /// The network id for network flags, the device id otherwise
+  device_id: i64,
+  device_name: Option<String>,
+  /// The homescreen key it was found under
+  key: String,
+  /// A bool for toggles, seconds for `extended_liveview`
+  value: serde_json::Value
+}
+
+#[derive(ToSchema)]
+#[schema(as = LogLevel)]
+#[allow(dead_code)]
+pub enum LogLevelSchema {
+  #[schema(rename = "error")]
+  Error,
+  #[schema(rename = "warn")]
+  Warn,
+  #[schema(rename = "info")]
+  Info,
+  #[schema(rename = "debug")]
+  Debug,
+  #[schema(rename = "trace")]
+  Trace
+}
+
+#[derive(ToSchema)]
+#[schema(as = LevelOverride)]
+#[allow(dead_code)]
+pub struct LevelOverrideSchema {
+  target: String,
+  level: LogLevelSchema,
+  /// RFC 3339
+  expires_at: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = LiveviewSettingWrite)]
+#[allow(dead_code)]
+pub struct LiveviewSettingWriteSchema {
+  flag: String,
+  device_id: i64,
+  outcome: SettingWriteOutcomeSchema,
+  before: serde_json::Value,
+  /// What the flag held after the write; absent if it disappeared from the homescreen
+  after: Option<serde_json::Value>,
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = MotionNotificationScope)]
+#[allow(dead_code)]
+pub enum MotionNotificationScopeSchema {
+  /// A key on the network's homescreen entry; only this network is affected
+  #[schema(rename = "network")]
+  Network,
+  /// The account's notification config; every network is affected
+  #[schema(rename = "account")]
+  Account
+}
+
+#[derive(ToSchema)]
+#[schema(as = MotionNotifications)]
+#[allow(dead_code)]
+pub struct MotionNotificationsSchema {
+  network_id: i64,
+  scope: MotionNotificationScopeSchema,
+  /// The homescreen key, or `motion` for the account flag
+  key: String,
+  /// Absent when the account flag has never been set
+  enabled: Option<bool>
+}
+
+#[derive(ToSchema)]
+#[schema(as = MotionNotificationWrite)]
+#[allow(dead_code)]
+pub struct MotionNotificationWriteSchema {
+  network_id: i64,
+  scope: MotionNotificationScopeSchema,
+  key: String,
+  outcome: SettingWriteOutcomeSchema,
+  before: Option<bool>,
+  /// What the switch held after the write; absent if it disappeared
+  after: Option<bool>,
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = UsbStorageState)]
+#[allow(dead_code)]
+pub enum UsbStorageStateSchema {
+  #[schema(rename = "active")]
+  Active,
+  #[schema(rename = "not_inserted")]
+  NotInserted,
+  #[schema(rename = "formatting")]
+  Formatting,
+  #[schema(rename = "full")]
+  Full,
+  #[schema(rename = "error")]
+  Error,
+  #[schema(rename = "ejected")]
+  Ejected,
+  #[schema(rename = "unknown")]
+  Unknown
+}
+
+#[derive(ToSchema)]
+#[schema(as = LocalStorageStatus)]
+#[allow(dead_code)]
+pub struct LocalStorageStatusSchema {
+  network_id: i64,
+  sync_module_id: i64,
+  state: UsbStorageStateSchema,
+  present: bool,
+  formatting: bool,
+  full: bool,
+  used_bytes: Option<u64>,
+  capacity_bytes: Option<u64>,
+  /// When Blink last backed clips up to the drive, as Blink reported it
+  last_backup: Option<String>,
+  /// The response as Blink sent it
+  #[schema(value_type = Object)]
+  raw: serde_json::Value
+}
+
+#[derive(ToSchema)]
+#[schema(as = UsbEjectResult)]
+#[allow(dead_code)]
+pub struct UsbEjectResultSchema {
+  network_id: i64,
+  sync_module_id: i64,
+  command_id: Option<i64>,
+  /// False when Blink's unmount command was still running after 60s
+  finished: bool,
+  /// The drive read back afterwards; absent if that read failed
+  status: Option<LocalStorageStatusSchema>
+}
+
+/// Keys Blink returns beyond these are passed through unchanged.
+#[derive(ToSchema)]
+#[schema(as = NetworkSettings)]
+#[allow(dead_code)]
+pub struct NetworkSettingsSchema {
+  id: i64,
+  name: Option<String>,
+  /// IANA zone name, e.g. `Europe/Berlin`
+  time_zone: Option<String>,
+  dst: Option<bool>,
+  locale: Option<String>,
+  /// Save every liveview as a clip
+  lv_save: Option<bool>,
+  video_quality: Option<String>
+}
+
+/// Omitted fields are left unchanged.
+#[derive(ToSchema)]
+#[schema(as = NetworkSettingsPatch)]
+#[allow(dead_code)]
+pub struct NetworkSettingsPatchSchema {
+  name: Option<String>,
+  /// Must be an IANA zone name
+  time_zone: Option<String>,
+  dst: Option<bool>,
+  locale: Option<String>,
+  lv_save: Option<bool>,
+  video_quality: Option<String>
+}
+
+/// Unlisted flags Blink returns are passed through unchanged.
+#[derive(ToSchema)]
+#[schema(as = NotificationConfig)]
+#[allow(dead_code)]
+pub struct NotificationConfigSchema {
+  motion: Option<bool>,
+  doorbell: Option<bool>,
+  low_battery: Option<bool>,
+  battery_dead: Option<bool>,
+  camera_offline: Option<bool>,
+  sync_module_offline: Option<bool>,
+  temperature: Option<bool>,
+  scheduling: Option<bool>,
+  camera_usage: Option<bool>,
+  wifi: Option<bool>,
+  local_storage: Option<bool>
+}
+
+#[derive(ToSchema)]
+#[schema(as = CameraPrefs)]
+#[allow(dead_code)]
+pub struct CameraPrefsSchema {
+  nickname: Option<String>,
+  /// Cameras with an order come first, ascending.
+  sort_order: Option<i32>,
+  hidden: bool,
+  liveview_quality: Option<String>,
+  /// Desktop only: ask the camera for a new thumbnail this often
+  thumbnail_refresh_minutes: Option<u32>
+}
+
+#[derive(ToSchema)]
+#[schema(as = ThumbnailRefreshSettings)]
+#[allow(dead_code)]
+pub struct ThumbnailRefreshSettingsSchema {
+  enabled: bool,
+  /// Floor for every camera's interval (at least 15)
+  min_interval_minutes: u32,
+  /// Only refresh inside this local time window
+  window: Option<RefreshWindowSchema>,
+  /// Battery cameras below this battery signal, or reported low, are skipped
+  min_battery_signal: i64,
+  skip_snoozed: bool,
+  skip_disarmed: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = RefreshWindow)]
+#[allow(dead_code)]
+pub struct RefreshWindowSchema {
+  /// `HH:MM`, local time
+  #[schema(example = "22:00")]
+  start: String,
+  /// `HH:MM`, local time; before `start` to run over midnight
+  #[schema(example = "06:00")]
+  end: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = ScheduleAction)]
+#[allow(dead_code)]
+pub enum ScheduleActionSchema {
+  #[schema(rename = "arm")]
+  Arm,
+  #[schema(rename = "disarm")]
+  Disarm
+}
+
+#[derive(ToSchema)]
+#[schema(as = ScheduleEntry)]
+#[allow(dead_code)]
+pub struct ScheduleEntrySchema {
+  /// Empty when creating an entry.
+  id: String,
+  network_id: i64,
+  action: ScheduleActionSchema,
+  /// `Mon`..`Sun`; empty means every day.
+  days: Vec<String>,
+  /// `HH:MM` in the network's time zone.
+  #[schema(example = "22:30")]
+  time: String,
+  enabled: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = NextScheduledChange)]
+#[allow(dead_code)]
+pub struct NextScheduledChangeSchema {
+  network_id: i64,
+  entry_id: String,
+  action: ScheduleActionSchema,
+  at: chrono::DateTime<chrono::Utc>
+}
+
+#[derive(ToSchema)]
+#[schema(as = IndexedMedia)]
+#[allow(dead_code)]
+pub struct IndexedMediaSchema {
+  id: i64,
+  camera_id: Option<i64>,
+  camera_name: Option<String>,
+  network_id: Option<i64>,
+  network_name: Option<String>,
+  created_at: Option<chrono::DateTime<chrono::Utc>>,
+  media_type: Option<String>,
+  source: Option<String>,
+  /// Blink path of the clip; sign it with `/api/sign` to play it.
+  media_url: Option<String>,
+  thumbnail_url: Option<String>,
+  /// Where the clip was archived on disk, if it was downloaded.
+  local_path: Option<String>,
+  watched: bool,
+  deleted: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = MediaSource)]
+#[allow(dead_code)]
+pub enum MediaSourceSchema {
+  #[schema(rename = "index")]
+  Index,
+  #[schema(rename = "live")]
+  Live
+}
+
+#[derive(ToSchema)]
+#[schema(as = MediaSearchPage)]
+#[allow(dead_code)]
+pub struct MediaSearchPageSchema {
+  items: Vec<IndexedMediaSchema>,
+  total: u64,
+  page: u32,
+  page_size: u32,
+  source: MediaSourceSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = TimelineEvent)]
+#[allow(dead_code)]
+pub struct TimelineEventSchema {
+  camera_id: Option<i64>,
+  camera_name: Option<String>,
+  network_id: Option<i64>,
+  start: String,
+  end: String,
+  clip_count: usize,
+  clips: Vec<MediaItemSchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = ArmVerification)]
+#[allow(dead_code)]
+pub struct ArmVerificationSchema {
+  /// The arm state asked for
+  requested: bool,
+  /// The homescreen shows the requested state
+  confirmed: bool,
+  /// The network's arm state as last read back; null if it couldn't be read
+  state: Option<bool>,
+  /// Why the change isn't confirmed; omitted once it is
+  warning: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = Network)]
+#[allow(dead_code)]
+pub struct NetworkSchema {
+  id: i64,
+  name: String,
+  armed: bool,
+  /// RFC 3339; when a temporary disarm will arm the network again
+  rearm_at: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = PendingRearm)]
+#[allow(dead_code)]
+pub struct PendingRearmSchema {
+  network_id: i64,
+  /// RFC 3339
+  rearm_at: String,
+  /// RFC 3339
+  disarmed_at: String,
+  /// Failed re-arm attempts; later attempts back off up to 15 minutes
+  attempts: u32,
+  /// RFC 3339; when a failed re-arm is tried again
+  retry_at: Option<String>,
+  last_error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = TemporaryDisarm)]
+#[allow(dead_code)]
+pub struct TemporaryDisarmSchema {
+  verification: ArmVerificationSchema,
+  rearm: PendingRearmSchema
+}
+
+#[derive(ToSchema)]
+#[schema(as = NetworkArmResult)]
+#[allow(dead_code)]
+pub struct NetworkArmResultSchema {
+  network_id: i64,
+  ok: bool,
+  /// Why this network failed; omitted on success
+  error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = BatteryPoint)]
+#[allow(dead_code)]
+pub struct BatteryPointSchema {
+  /// RFC 3339
+  ts: String,
+  /// Blink's battery bars (0-3) as a percentage; an average when `hourly` is set
+  level: f64,
+  /// Battery voltage from the camera's config, as Blink reports it
+  voltage: Option<f64>,
+  /// Blink reported the battery as low
+  low: bool,
+  /// Averages an hour of samples older than 7 days
+  hourly: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = BatteryHistory)]
+#[allow(dead_code)]
+pub struct BatteryHistorySchema {
+  camera_id: i64,
+  points: Vec<BatteryPointSchema>,
+  /// Days until the level reaches zero at the last week's rate; null when it isn't falling or there is too little data
+  days_remaining: Option<f64>
+}
+
+#[derive(ToSchema)]
+#[schema(as = Event)]
+#[allow(dead_code)]
+pub struct EventSchema {
+  /// Increases with every event and is never reused
+  seq: i64,
+  /// RFC 3339
+  ts: String,
+  /// `arm`, `rearm`, `schedule_run` or `firmware_changed`
+  kind: String,
+  #[schema(value_type = Object)]
+  data: serde_json::Value
+}
+
+#[derive(ToSchema)]
+#[schema(as = ProbeResult)]
+#[allow(dead_code)]
+pub struct ProbeResultSchema {
+  url: String,
+  /// The host is on the media allow-list; other URLs are not requested
+  host_allowed: bool,
+  /// A Blink access token was sent
+  auth_attached: bool,
+  status: Option<u16>,
+  content_type: Option<String>,
+  content_length: Option<u64>,
+  /// Full size from `Content-Range`, when the range request was honoured
+  total_bytes: Option<u64>,
+  /// After redirects
+  final_url: Option<String>,
+  /// Until the response headers arrived
+  latency_ms: Option<u64>,
+  /// The first thing that looks wrong: an HTTP error, an empty body or an unexpected content type
+  problem: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = MediaDiagnosis)]
+#[allow(dead_code)]
+pub struct MediaDiagnosisSchema {
+  media_id: i64,
+  /// RFC 3339
+  checked_at: String,
+  /// Null when the item has no clip URL
+  media: Option<ProbeResultSchema>,
+  /// Null when the item has no thumbnail URL
+  thumbnail: Option<ProbeResultSchema>,
+  /// Set when the clip's camera only answers on another device family's routes
+  device_route: Option<RouteRecordSchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = RouteAttempt)]
+#[allow(dead_code)]
+pub struct RouteAttemptSchema {
+  family: DeviceFamilySchema,
+  /// Status of the failed attempt; null for the family that answered
+  status: Option<u16>
+}
+
+#[derive(ToSchema)]
+#[schema(as = RouteRecord)]
+#[allow(dead_code)]
+pub struct RouteRecordSchema {
+  camera_id: i64,
+  product_type: String,
+  /// The family whose routes answered
+  family: DeviceFamilySchema,
+  attempts: Vec<RouteAttemptSchema>,
+  /// RFC 3339
+  learned_at: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = CleanupEntry)]
+#[allow(dead_code)]
+pub struct CleanupEntrySchema {
+  /// The liveview session the clip came from
+  session_id: String,
+  network_id: i64,
+  camera_id: i64,
+  /// RFC 3339
+  started_at: String,
+  /// RFC 3339
+  ended_at: String,
+  /// Checks that failed or still found clips; later checks back off
+  attempts: u32,
+  /// Clips deleted for this session so far
+  deleted: usize,
+  /// RFC 3339
+  next_check_at: String,
+  last_error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = TrustedClient)]
+#[allow(dead_code)]
+pub struct TrustedClientSchema {
+  id: i64,
+  name: String,
+  created_at: Option<String>,
+  /// The client this server is signed in as
+  current: bool
+}
+
+#[derive(ToSchema)]
+#[schema(as = RevokeOutcome)]
+#[allow(dead_code)]
+pub struct RevokeOutcomeSchema {
+  id: i64,
+  current: bool,
+  revoked: bool,
+  /// The server's session was dropped along with its client
+  signed_out: bool,
+  warning: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = UsageReport)]
+#[allow(dead_code)]
+pub struct UsageReportSchema {
+  /// Days the usage figures cover
+  range_days: Option<i64>,
+  networks: Vec<NetworkUsageSchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = NetworkUsage)]
+#[allow(dead_code)]
+pub struct NetworkUsageSchema {
+  network_id: i64,
+  name: String,
+  cameras: Vec<CameraUsageSchema>
+}
+
+#[derive(ToSchema)]
+#[schema(as = CameraUsage)]
+#[allow(dead_code)]
+pub struct CameraUsageSchema {
+  id: i64,
+  name: String,
+  /// Seconds counted against the plan
+  usage: Option<i64>,
+  clip_seconds: Option<i64>,
+  lv_seconds: Option<i64>,
+  /// Bytes of this camera's archived clips; omitted when the media index is disabled
+  local_bytes: Option<u64>
+}
+
+#[derive(ToSchema)]
+#[schema(as = FirmwareDeviceKind)]
+#[allow(dead_code)]
+pub enum FirmwareDeviceKindSchema {
+  #[schema(rename = "camera")]
+  Camera,
+  #[schema(rename = "sync_module")]
+  SyncModule
+}
+
+#[derive(ToSchema)]
+#[schema(as = FirmwareRecord)]
+#[allow(dead_code)]
+pub struct FirmwareRecordSchema {
+  kind: FirmwareDeviceKindSchema,
+  device_id: i64,
+  name: String,
+  network_id: Option<i64>,
+  version: String,
+  /// The version before the last observed change
+  previous_version: Option<String>,
+  /// RFC 3339; when the current version was first seen
+  observed_at: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = JobState)]
+#[allow(dead_code)]
+pub enum JobStateSchema {
+  #[schema(rename = "running")]
+  Running,
+  #[schema(rename = "done")]
+  Done,
+  #[schema(rename = "cancelled")]
+  Cancelled
+}
+
+#[derive(ToSchema)]
+#[schema(as = DeleteProgress)]
+#[allow(dead_code)]
+pub struct DeleteProgressSchema {
+  job_id: String,
+  state: JobStateSchema,
+  total: usize,
+  /// Items attempted so far, failed ones included
+  done: usize,
+  /// Ids Blink still listed after their batch, or whose batch failed
+  failed: Vec<i64>,
+  /// RFC 3339
+  created_at: String,
+  /// RFC 3339
+  finished_at: Option<String>,
+  last_error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = TakeoutState)]
+#[allow(dead_code)]
+pub enum TakeoutStateSchema {
+  #[schema(rename = "running")]
+  Running,
+  #[schema(rename = "done")]
+  Done,
+  #[schema(rename = "cancelled")]
+  Cancelled,
+  /// The destination couldn't be written at all; see `last_error`
+  #[schema(rename = "failed")]
+  Failed
+}
+
+#[derive(ToSchema)]
+#[schema(as = TakeoutPhase)]
+#[allow(dead_code)]
+pub enum TakeoutPhaseSchema {
+  #[schema(rename = "metadata")]
+  Metadata,
+  #[schema(rename = "listing")]
+  Listing,
+  #[schema(rename = "media")]
+  Media,
+  #[schema(rename = "manifest")]
+  Manifest
+}
+
+#[derive(ToSchema)]
+#[schema(as = TakeoutProgress)]
+#[allow(dead_code)]
+pub struct TakeoutProgressSchema {
+  job_id: String,
+  state: TakeoutStateSchema,
+  phase: TakeoutPhaseSchema,
+  /// Clips found in the feed; grows while the job is still listing
+  total: usize,
+  /// Clips attempted so far, failed ones included
+  done: usize,
+  bytes: u64,
+  /// Clips, thumbnails and metadata files that couldn't be fetched; listed in the manifest
+  failed: usize,
+  /// The takeout's folder: a path or a URL, depending on the store
+  location: String,
+  /// Where `manifest.json` was written, once it has been
+  manifest: Option<String>,
+  /// RFC 3339
+  created_at: String,
+  /// RFC 3339
+  finished_at: Option<String>,
+  last_error: Option<String>
+}
+
+#[derive(ToSchema)]
+#[schema(as = PendingDeletion)]
+#[allow(dead_code)]
+pub struct PendingDeletionSchema {
+  id: i64,
+  /// The item as sent to `/api/delete-media`
+  #[schema(value_type = Object)]
+  item: serde_json::Value,
+  /// RFC 3339; when the clip is deleted from Blink
+  due_at: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = AuditEntry)]
+#[allow(dead_code)]
+pub struct AuditEntrySchema {
+  ts: chrono::DateTime<chrono::Utc>,
+  #[schema(example = "disarm")]
+  action: String,
+  #[schema(example = "network 123")]
+  target: String,
+  ok: bool,
+  error: Option<String>,
+  /// `server`, `scheduler`, `signed-url` or `api-key:<id>`.
+  source: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = ConfigProblem)]
+#[allow(dead_code)]
+pub struct ConfigProblemSchema {
+  /// `camera.<key>` when the update is wrapped
+  key: String,
+  problem: String
+}
+
+#[derive(ToSchema)]
+#[schema(as = ConfigRejected)]
+#[allow(dead_code)]
+pub struct ConfigRejectedSchema {
+  /// `invalid_config`
+  reason: String,
+  message: String,
+  problems: Vec<ConfigProblemSchema>
+}
+
+/// Errors are returned as a plain-text message with an appropriate status code.
+#[derive(ToSchema)]
+#[schema(as = ErrorBody, value_type = String, example = "Blink API Error: 401 Unauthorized")]
+#[allow(dead_code)]
+pub struct ErrorBody(String);
+
+#[derive(OpenApi)]
+#[openapi(
+  info(title = "Blink Monitor API", description = "Hosted-mode REST API for Blink Monitor"),
+  paths(
+    crate::health,
+    crate::ready::ready,
+    crate::run_self_test,
+    crate::set_log_level,
+    crate::start_debug_capture,
+    crate::check_auth,
+    crate::reload_auth,
+    crate::login,
+    crate::get_amazon_signin_url,
+    crate::login_amazon,
+    crate::complete_amazon_login,
+    crate::verify_pin,
+    crate::logout,
+    crate::list_trusted_clients,
+    crate::revoke_trusted_client,
+    crate::get_raw_homescreen,
+    crate::get_liveview_setting_candidates,
+    crate::get_raw_media_page,
+    crate::set_network_arm,
+    crate::set_all_networks_arm,
+    crate::get_networks,
+    crate::disarm_temporary,
+    crate::get_pending_rearms,
+    crate::extend_temporary_disarm,
+    crate::cancel_temporary_disarm,
+    crate::get_firmware_report,
+    crate::get_usage,
+    crate::get_battery_history,
+    crate::stream_events,
+    crate::replay_events,
+    crate::preview_webhook,
+    crate::get_summary_report,
+    crate::get_activity_heatmap,
+    crate::test_notify,
+    crate::delete_media_items,
+    crate::undo_delete,
+    crate::get_pending_deletions,
+    crate::get_delete_queue,
+    crate::cancel_delete_job,
+    crate::start_takeout,
+    crate::get_takeout,
+    crate::cancel_takeout,
+    crate::list_archive,
+    crate::get_cleanup_queue,
+    crate::mark_media_watched,
+    crate::diagnose_media,
+    crate::get_camera_config,
+    crate::update_camera_config,
+    crate::get_network_settings,
+    crate::update_network_settings,
+    crate::get_liveview_settings,
+    crate::set_liveview_setting,
+    crate::get_motion_notifications,
+    crate::set_motion_notifications,
+    crate::get_sync_module_storage,
+    crate::eject_usb,
+    crate::get_notification_config,
+    crate::update_notification_config,
+    crate::refresh_camera_thumbnail,
+    crate::get_thumbnail_base64,
+    crate::prefetch_thumbnails,
+    crate::get_settings,
+    crate::update_settings,
+    crate::get_audit_log,
+    crate::get_camera_prefs,
+    crate::set_camera_prefs,
+    crate::get_schedules,
+    crate::save_schedule_entry,
+    crate::delete_schedule_entry,
+    crate::sign_url,
+    crate::get_cameras,
+    crate::get_all_media,
+    crate::get_timeline,
+    crate::search_media_index,
+    crate::rebuild_media_index,
+    openapi_json,
+    crate::media::proxy_clip,
+    crate::media::proxy_media,
+    crate::media::proxy_thumbnail,
+    crate::media::export_clips,
+    crate::media::proxy_live,
+    crate::media::list_live_sessions,
+    crate::media::save_replay,
+    crate::media::list_recordings,
+    crate::media::stop_live_session
+  ),
+  components(schemas(
+    crate::LoginRequest,
+    crate::PinRequest,
+    crate::AmazonCallbackRequest,
+    AuthStateSchema,
+    TrustedClientSchema,
+    RevokeOutcomeSchema,
+    crate::SetArmRequest,
+    crate::SetArmAllRequest,
+    crate::DisarmTemporaryRequest,
+    ArmVerificationSchema,
+    NetworkSchema,
+    PendingRearmSchema,
+    TemporaryDisarmSchema,
+    NetworkArmResultSchema,
+    FirmwareDeviceKindSchema,
+    FirmwareRecordSchema,
+    UsageReportSchema,
+    NetworkUsageSchema,
+    CameraUsageSchema,
+    BatteryPointSchema,
+    BatteryHistorySchema,
+    crate::DeleteMediaRequest,
+    crate::UndoDeleteRequest,
+    crate::MarkWatchedRequest,
+    crate::CameraConfigUpdate,
+    crate::CameraThumbnailRequest,
+    crate::CameraResponse,
+    crate::LogLevelRequest,
+    crate::DebugCaptureRequest,
+    crate::DebugCaptureResult,
+    crate::MediaItemResponse,
+    CameraSchema,
+    ThumbnailRefreshSettingsSchema,
+    RefreshWindowSchema,
+    DeviceFamilySchema,
+    CameraSignalsSchema,
+    MediaItemSchema,
+    DeleteStatusSchema,
+    DeleteResultSchema,
+    LiveSessionInfoSchema,
+    LiveLatencySchema,
+    LatencySummarySchema,
+    ReplayUsageSchema,
+    ReplaySavedSchema,
+    RecordingFormatSchema,
+    RecordingInfoSchema,
+    LiveviewOptionsSchema,
+    LiveviewParamsSchema,
+    ExportCompressionSchema,
+    crate::media::ExportRequest,
+    LiveviewSettingCandidateSchema,
+    CandidateDeviceKindSchema,
+    SettingsSchema,
+    CameraPrefsSchema,
+    NotificationConfigSchema,
+    NetworkSettingsSchema,
+    NetworkSettingsPatchSchema,
+    crate::NetworkSettingsUpdate,
+    SettingWriteOutcomeSchema,
+    LiveviewSettingSchema,
+    LiveviewSettingWriteSchema,
+    LogLevelSchema,
+    LevelOverrideSchema,
+    crate::LiveviewSettingUpdate,
+    MotionNotificationScopeSchema,
+    MotionNotificationsSchema,
+    MotionNotificationWriteSchema,
+    crate::MotionNotificationsUpdate,
+    UsbStorageStateSchema,
+    LocalStorageStatusSchema,
+    UsbEjectResultSchema,
+    crate::SyncModuleStorageQuery,
+    ScheduleActionSchema,
+    ScheduleEntrySchema,
+    NextScheduledChangeSchema,
+    crate::ScheduleOverview,
+    IndexedMediaSchema,
+    MediaSourceSchema,
+    MediaSearchPageSchema,
+    TimelineEventSchema,
+    PendingDeletionSchema,
+    JobStateSchema,
+    DeleteProgressSchema,
+    TakeoutStateSchema,
+    TakeoutPhaseSchema,
+    TakeoutProgressSchema,
+    crate::TakeoutRequest,
+    CleanupEntrySchema,
+    ProbeResultSchema,
+    MediaDiagnosisSchema,
+    RouteAttemptSchema,
+    RouteRecordSchema,
+    EventSchema,
+    ClipStoreConfigSchema,
+    StoredClipSchema,
+    WebhookTargetSchema,
+    ReportScheduleSchema,
+    SummaryReportSchema,
+    CameraDayClipsSchema,
+    HourClipsSchema,
+    ActivityHeatmapSchema,
+    DayClipsSchema,
+    PrefetchResultSchema,
+    crate::PrefetchThumbnailsRequest,
+    BatteryChangeSchema,
+    ArmChangeSchema,
+    WebhookPayloadSchema,
+    crate::WebhookPreviewRequest,
+    NotifierTargetSchema,
+    NotifyResultSchema,
+    crate::NotifyTestRequest,
+    AuditEntrySchema,
+    ErrorBody,
+    ConfigProblemSchema,
+    ConfigRejectedSchema,
+    SelfTestStageSchema,
+    StageStatusSchema,
+    StageResultSchema,
+    SelfTestReportSchema,
+    ClockSkewReportSchema,
+    crate::ready::ReadinessReport,
+    crate::ready::AuthStatus
+  )),
+  modifiers(&ApiKeyAddon),
+  tags(
+    (name = "auth", description = "Blink account login and session state"),
+    (name = "devices", description = "Networks, cameras, and configuration"),
+    (name = "media", description = "Clip listing, deletion, and authenticated proxying"),
+    (name = "live", description = "Liveview streaming and session control"),
+    (name = "meta", description = "Health and API description")
+  )
+)]
+pub struct ApiDoc;
+
+struct ApiKeyAddon;
+
+impl utoipa::Modify for ApiKeyAddon {
+  fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+    use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+    let components = openapi.components.get_or_insert_with(Default::default);
+    components.add_security_scheme("api_key", SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))));
+    components.add_security_scheme(
+      "signed_url",
+      SecurityScheme::ApiKey(ApiKey::Query(ApiKeyValue::with_description("sig", "Signature from /api/sign, together with its `exp` parameter")))
+    );
+  }
+}
+
+fn swagger_ui_enabled() -> bool {
+  std::env::var("BLINK_SWAGGER_UI")
+    .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+    .unwrap_or(false)
+}
+
+#[utoipa::path(
+  get,
+  path = "/api/openapi.json",
+  tag = "meta",
+  responses((status = 200, description = "This OpenAPI document", content_type = "application/json"))
+)]
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+  Json(ApiDoc::openapi())
+}
+
+pub async fn swagger_ui() -> impl IntoResponse {
+  if !swagger_ui_enabled() {
+    return (StatusCode::NOT_FOUND, "Swagger UI is disabled; set BLINK_SWAGGER_UI=1").into_response();
+  }
+  Html(SWAGGER_PAGE).into_response()
+}
+
+const SWAGGER_PAGE: &str = r##"<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8" />
+    <title>Blink Monitor API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+      window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>"##;
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::collections::BTreeSet;
+
+  /// Routes that serve pages rather than API responses.
+  const UNDOCUMENTED: [&str; 1] = ["/api/docs"];
+
+  /// Every `(method, path)` registered in the router sources, with `:param` segments
+  /// written the way OpenAPI spells them.
+  fn registered_routes() -> BTreeSet<(String, String)> {
+    let route = regex::Regex::new(r#"\.route\("([^"]+)",\s*([^\n]+)\)"#).unwrap();
+    let method = regex::Regex::new(r"\b(get|post|put|patch|delete)\(").unwrap();
+    let param = regex::Regex::new(r":(\w+)").unwrap();
+    let mut routes = BTreeSet::new();
+    for source in [include_str!("main.rs"), include_str!("media.rs")] {
+      for caps in route.captures_iter(source) {
+        if UNDOCUMENTED.contains(&&caps[1]) {
+          continue;
+        }
+        let path = param.replace_all(&caps[1], "{$1}").to_string();
+        for m in method.captures_iter(&caps[2]) {
+          routes.insert((m[1].to_string(), path.clone()));
+        }
+      }
+    }
+    routes
+  }
+
+  fn documented_routes(spec: &serde_json::Value) -> BTreeSet<(String, String)> {
+    let mut routes = BTreeSet::new();
+    for (path, item) in spec["paths"].as_object().expect("spec has paths") {
+      for method in item.as_object().unwrap().keys() {
+        routes.insert((method.clone(), path.clone()));
+      }
+    }
+    routes
+  }
+
+  #[test]
+  fn spec_serializes_and_parses_back() {
+    let json = ApiDoc::openapi().to_json().expect("spec serializes");
+    let spec: serde_json::Value = serde_json::from_str(&json).expect("spec is valid JSON");
+    assert!(spec["openapi"].as_str().unwrap().starts_with("3."));
+    assert!(spec["components"]["schemas"].as_object().is_some_and(|s| !s.is_empty()));
+  }
+
+  #[test]
+  fn every_route_is_documented() {
+    let registered = registered_routes();
+    assert!(registered.len() > 50, "route scan found only {} routes", registered.len());
+    let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+    let documented = documented_routes(&spec);
+    let missing: Vec<_> = registered.difference(&documented).collect();
+    assert!(missing.is_empty(), "routes without #[utoipa::path]: {:?}", missing);
+    let stale: Vec<_> = documented.difference(&registered).collect();
+    assert!(stale.is_empty(), "documented routes that don't exist: {:?}", stale);
+  }
+}
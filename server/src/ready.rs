@@ -0,0 +1,121 @@
+use axum::{
+  extract::State,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+  Json
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+use crate::blink::{AuthState, BlinkError};
+use crate::media::ServerState;
+use crate::shared;
+
+#[derive(Clone, Copy, Serialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthStatus {
+  Ok,
+  Expired,
+  /// Blink revoked the refresh token; only a new login helps.
+  Revoked,
+  Missing
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct ReadinessReport {
+  auth: AuthStatus,
+  tier: Option<String>,
+  last_successful_request: Option<DateTime<Utc>>,
+  blink_reachable: bool,
+  checked_at: DateTime<Utc>
+}
+
+/// Caches the result of the Blink probe so frequent health checks don't hit Blink.
+#[derive(Default)]
+pub struct ReadinessCache {
+  inner: Mutex<Option<(Instant, ReadinessReport)>>
+}
+
+fn cache_ttl() -> Duration {
+  let secs = std::env::var("BLINK_READY_CACHE_SECS")
+    .ok()
+    .and_then(|v| v.parse::<u64>().ok())
+    .unwrap_or(60);
+  Duration::from_secs(secs)
+}
+
+async fn probe(state: &ServerState, previous: Option<&ReadinessReport>) -> ReadinessReport {
+  let mut report = ReadinessReport {
+    auth: AuthStatus::Missing,
+    tier: previous.and_then(|p| p.tier.clone()),
+    last_successful_request: previous.and_then(|p| p.last_successful_request),
+    blink_reachable: false,
+    checked_at: Utc::now()
+  };
+
+  // The snapshot refreshes under the lock; the tier probe runs on the copy so a slow
+  // Blink doesn't hold up every other handler behind an unauthenticated route.
+  let client = shared::snapshot(&state.blink_client).await;
+  if client.auth_state() == AuthState::ReauthRequired {
+    report.auth = AuthStatus::Revoked;
+    return report;
+  }
+  if client.token.is_none() && client.refresh_token.is_none() {
+    return report;
+  }
+  let expired = client.token_expiry.map(|exp| exp <= Utc::now().timestamp()).unwrap_or(false);
+  if expired {
+    // Still expired after the snapshot's refresh, so the refresh failed.
+    report.auth = AuthStatus::Expired;
+    return report;
+  }
+
+  match client.probe_tier().await {
+    Ok(tier) => {
+      report.auth = AuthStatus::Ok;
+      report.blink_reachable = true;
+      report.last_successful_request = Some(report.checked_at);
+      if !tier.is_empty() {
+        report.tier = Some(tier);
+      }
+    }
+    Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::AuthExpired)) => {
+      report.auth = AuthStatus::Expired;
+      report.blink_reachable = true;
+    }
+    Err(e) => {
+      // Blink is unreachable, so judge auth by the token expiry we already know.
+      eprintln!("Readiness probe: Blink request failed: {}", e);
+      report.auth = AuthStatus::Ok;
+    }
+  }
+  report
+}
+
+#[utoipa::path(
+  get, path = "/api/ready", tag = "meta",
+  responses(
+    (status = 200, description = "Stored auth is valid", body = ReadinessReport),
+    (status = 503, description = "Auth is missing or expired; a re-login is needed", body = ReadinessReport)
+  )
+)]
+pub async fn ready(State(state): State<Arc<ServerState>>) -> Response {
+  let report = {
+    let mut cached = state.readiness.inner.lock().await;
+    match cached.as_ref() {
+      Some((at, report)) if at.elapsed() < cache_ttl() => report.clone(),
+      _ => {
+        let report = probe(&state, cached.as_ref().map(|(_, r)| r)).await;
+        *cached = Some((Instant::now(), report.clone()));
+        report
+      }
+    }
+  };
+
+  let status = if report.auth == AuthStatus::Ok { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+  (status, Json(report)).into_response()
+}
@@ -1,40 +1,50 @@
 use anyhow::Result;
-use std::fs;
 use std::path::PathBuf;
 
-use crate::blink::BlinkAuthState;
+use blink_core::auth_store::{self, AuthStore};
+use blink_core::blink::BlinkAuthState;
+
+pub fn data_dir() -> PathBuf {
+    let dir = std::env::var("BLINK_DATA_DIR").unwrap_or_else(|_| "data".to_string());
+    PathBuf::from(dir)
+}
 
 fn auth_path() -> PathBuf {
     if let Ok(path) = std::env::var("BLINK_AUTH_PATH") {
         return PathBuf::from(path);
     }
-    let dir = std::env::var("BLINK_DATA_DIR").unwrap_or_else(|_| "data".to_string());
-    let mut path = PathBuf::from(dir);
+    let mut path = data_dir();
     path.push("auth.json");
     path
 }
 
-pub fn save_auth(state: &BlinkAuthState) -> Result<()> {
-    let json = serde_json::to_string(state)?;
-    let path = auth_path();
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+/// `BLINK_SETTINGS_PATH` overrides the default `settings.json` in the data dir.
+pub fn settings_path() -> PathBuf {
+    if let Ok(path) = std::env::var("BLINK_SETTINGS_PATH") {
+        return PathBuf::from(path);
     }
-    fs::write(path, json)?;
-    Ok(())
+    data_dir().join(blink_core::settings::SETTINGS_FILE)
+}
+
+pub fn signing_key_path() -> PathBuf {
+    let mut path = data_dir();
+    path.push("url_signing.key");
+    path
+}
+
+/// File-backed unless `BLINK_USE_KEYCHAIN` is set: headless hosts usually have no keychain.
+fn auth_store() -> AuthStore {
+    AuthStore::new(auth_path(), auth_store::keychain_enabled(false))
+}
+
+pub fn save_auth(state: &BlinkAuthState) -> Result<()> {
+    auth_store().save(state)
 }
 
 pub fn load_auth() -> Result<BlinkAuthState> {
-    let path = auth_path();
-    let json = fs::read_to_string(&path)?;
-    let state: BlinkAuthState = serde_json::from_str(&json)?;
-    Ok(state)
+    auth_store().load()
 }
 
 pub fn clear_auth() -> Result<()> {
-    let path = auth_path();
-    if path.exists() {
-        fs::remove_file(path)?;
-    }
-    Ok(())
+    auth_store().clear()
 }
@@ -0,0 +1,173 @@
+//! Time limits for API requests, and a log line for slow ones. A handler waiting on a Blink
+//! call that never answers would otherwise hold the request open until the client gives
+//! up; past its route's budget the handler is dropped and the client gets a 504. Budgets
+//! are matched by route prefix, the longest one winning, and can be changed with
+//! `BLINK_ROUTE_TIMEOUTS`.
+
+use axum::{
+  extract::{MatchedPath, Request, State},
+  http::StatusCode,
+  middleware::Next,
+  response::{IntoResponse, Response},
+  Json
+};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::log_control;
+use crate::media::ServerState;
+
+/// Budget for routes without one of their own (`BLINK_REQUEST_TIMEOUT_SECS`).
+pub const DEFAULT_TIMEOUT_SECS: u64 = 120;
+/// Requests slower than this are logged under the `http` target (`BLINK_SLOW_REQUEST_MS`).
+pub const DEFAULT_SLOW_REQUEST_MS: u64 = 5000;
+
+/// Routes that page through Blink or wait for it to confirm and so need longer than the
+/// default.
+const DEFAULT_BUDGETS: &[(&str, u64)] = &[
+  ("/api/delete-media", 600),
+  ("/api/media/all", 600),
+  ("/api/media/index/rebuild", 1800)
+];
+
+/// Never timed out: streams, whose handlers may wait on Blink before the first byte.
+const EXEMPT_ROUTES: &[&str] = &["/api/live", "/api/clip", "/api/proxy", "/api/export"];
+
+pub struct RouteBudgets {
+  default: Option<Duration>,
+  /// Longest prefix first; `None` disables the limit for that prefix.
+  routes: Vec<(String, Option<Duration>)>,
+  slow: Duration
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+  std::env::var(name).ok().and_then(|v| v.trim().parse().ok())
+}
+
+fn budget(secs: u64) -> Option<Duration> {
+  (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Whether `path` is `prefix` or below it, so `/api/live` doesn't cover `/api/live-sessions`.
+fn under(path: &str, prefix: &str) -> bool {
+  path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/') || prefix.ends_with('/'))
+}
+
+impl RouteBudgets {
+  /// Reads `BLINK_ROUTE_TIMEOUTS` (`/api/prefix=seconds`, comma-separated, 0 for no limit)
+  /// over the defaults, plus `BLINK_REQUEST_TIMEOUT_SECS` and `BLINK_SLOW_REQUEST_MS`.
+  pub fn from_env() -> Self {
+    Self::new(
+      env_u64("BLINK_REQUEST_TIMEOUT_SECS").unwrap_or(DEFAULT_TIMEOUT_SECS),
+      &std::env::var("BLINK_ROUTE_TIMEOUTS").unwrap_or_default(),
+      env_u64("BLINK_SLOW_REQUEST_MS").unwrap_or(DEFAULT_SLOW_REQUEST_MS)
+    )
+  }
+
+  pub(crate) fn new(default_secs: u64, overrides: &str, slow_ms: u64) -> Self {
+    let mut routes: Vec<(String, u64)> = DEFAULT_BUDGETS.iter().map(|(prefix, secs)| (prefix.to_string(), *secs)).collect();
+    for entry in overrides.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+      let parsed = entry.split_once('=')
+        .and_then(|(prefix, secs)| Some((prefix.trim(), secs.trim().parse::<u64>().ok()?)))
+        .filter(|(prefix, _)| prefix.starts_with('/'));
+      match parsed {
+        Some((prefix, secs)) => {
+          routes.retain(|(p, _)| p != prefix);
+          routes.push((prefix.to_string(), secs));
+        }
+        None => eprintln!("Ignoring BLINK_ROUTE_TIMEOUTS entry {:?}; expected /api/prefix=seconds", entry)
+      }
+    }
+    routes.sort_by_key(|(prefix, _)| std::cmp::Reverse(prefix.len()));
+    Self {
+      default: budget(default_secs),
+      routes: routes.into_iter().map(|(prefix, secs)| (prefix, budget(secs))).collect(),
+      slow: Duration::from_millis(slow_ms)
+    }
+  }
+
+  /// The limit for `path`, `None` when it has none.
+  pub fn budget_for(&self, path: &str) -> Option<Duration> {
+    if EXEMPT_ROUTES.iter().any(|prefix| under(path, prefix)) {
+      return None;
+    }
+    self.routes.iter()
+      .find(|(prefix, _)| under(path, prefix))
+      .map_or(self.default, |(_, budget)| *budget)
+  }
+}
+
+fn timed_out(budget: Duration) -> Response {
+  let message = format!("The request did not finish within its {}s budget", budget.as_secs());
+  (StatusCode::GATEWAY_TIMEOUT, Json(serde_json::json!({ "reason": "timeout", "message": message }))).into_response()
+}
+
+/// Runs the request under its route's budget and logs it when it was slow. Routes are
+/// named by their pattern (`/api/takeout/:id`), so ids don't end up in the log.
+pub async fn enforce(State(state): State<Arc<ServerState>>, request: Request, next: Next) -> Response {
+  let route = request.extensions().get::<MatchedPath>()
+    .map(|path| path.as_str().to_string())
+    .unwrap_or_else(|| request.uri().path().to_string());
+  let method = request.method().clone();
+  let budget = state.request_budgets.budget_for(&route);
+  let started = Instant::now();
+  let response = match budget {
+    Some(budget) => tokio::time::timeout(budget, next.run(request)).await.unwrap_or_else(|_| timed_out(budget)),
+    None => next.run(request).await
+  };
+  let elapsed = started.elapsed();
+  if elapsed >= state.request_budgets.slow {
+    log_control::warn("http", || format!(
+      "Slow request: {} {} answered {} after {}ms (budget {})",
+      method,
+      route,
+      response.status().as_u16(),
+      elapsed.as_millis(),
+      budget.map_or("none".to_string(), |b| format!("{}s", b.as_secs()))
+    ));
+  }
+  response
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn secs(n: u64) -> Option<Duration> {
+    Some(Duration::from_secs(n))
+  }
+
+  #[test]
+  fn the_longest_matching_prefix_wins() {
+    let budgets = RouteBudgets::new(120, "/api/media=30", 5000);
+    assert_eq!(budgets.budget_for("/api/cameras"), secs(120));
+    assert_eq!(budgets.budget_for("/api/media"), secs(30));
+    assert_eq!(budgets.budget_for("/api/media/:id/watched"), secs(30));
+    // The built-in budget for the longer prefix still applies below it.
+    assert_eq!(budgets.budget_for("/api/media/all"), secs(600));
+    assert_eq!(budgets.budget_for("/api/media/index/rebuild"), secs(1800));
+    // A prefix only covers whole path segments.
+    assert_eq!(budgets.budget_for("/api/media-summary"), secs(120));
+  }
+
+  #[test]
+  fn streams_are_never_timed_out() {
+    let budgets = RouteBudgets::new(120, "/api/live=5", 5000);
+    assert_eq!(budgets.budget_for("/api/live/:network_id/:camera_id/:product_type"), None);
+    assert_eq!(budgets.budget_for("/api/clip"), None);
+    assert_eq!(budgets.budget_for("/api/debug-capture"), secs(120));
+    assert_eq!(budgets.budget_for("/api/live-sessions"), secs(120));
+  }
+
+  #[test]
+  fn overrides_replace_defaults_and_zero_means_no_limit() {
+    let budgets = RouteBudgets::new(0, " /api/media/all = 60 ,/api/login=0,, bogus, /api/x=abc, api/no-slash=3", 250);
+    assert_eq!(budgets.budget_for("/api/media/all"), secs(60));
+    assert_eq!(budgets.budget_for("/api/login"), None);
+    assert_eq!(budgets.budget_for("/api/cameras"), None);
+    assert_eq!(budgets.budget_for("/api/x"), None);
+    assert_eq!(budgets.budget_for("/api/delete-media"), secs(600));
+    assert_eq!(budgets.slow, Duration::from_millis(250));
+    assert!(!budgets.routes.iter().any(|(prefix, _)| prefix.contains("no-slash")));
+  }
+}
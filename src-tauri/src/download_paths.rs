@@ -0,0 +1,281 @@
+//! Where the webview is allowed to have clips written. A path from the frontend is only
+//! used when it lands inside the user's Downloads folder or a folder they picked in one
+//! of our own dialogs, which is remembered in `Settings::download_dirs`.
+
+use crate::error::{CommandError, CommandResult, ErrorKind};
+use blink_core::clip_store::ClipStoreConfig;
+use blink_core::settings::Settings;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// The Downloads folder plus every granted folder, canonicalized. Grants whose folder
+/// has since been removed simply drop out.
+pub fn allowed_roots(granted: &[String]) -> Vec<PathBuf> {
+    dirs::download_dir()
+        .into_iter()
+        .chain(granted.iter().map(PathBuf::from))
+        .filter_map(|dir| dir.canonicalize().ok())
+        .collect()
+}
+
+/// Whether `dir` already falls under one of `roots`, so granting it would add nothing.
+pub fn is_covered(dir: &Path, roots: &[PathBuf]) -> bool {
+    dir.canonicalize().is_ok_and(|dir| roots.iter().any(|root| dir.starts_with(root)))
+}
+
+fn not_allowed(message: impl Into<String>) -> CommandError {
+    CommandError::new(ErrorKind::PathNotAllowed, message)
+}
+
+/// `path` with every symlink in its existing part resolved. The missing tail is appended
+/// as is, which is safe because `..` was already refused and a missing entry can't be a
+/// link.
+fn resolve(path: &Path) -> CommandResult<PathBuf> {
+    if !path.is_absolute() {
+        return Err(not_allowed(format!("{} is not an absolute path", path.display())));
+    }
+    if path.components().any(|c| c == Component::ParentDir) {
+        return Err(not_allowed(format!("{} steps out of its folder with `..`", path.display())));
+    }
+    let mut existing = path;
+    let mut tail = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(resolved) => return Ok(tail.iter().rev().fold(resolved, |acc, part| acc.join(part))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (Some(parent), Some(name)) = (existing.parent(), existing.file_name()) else {
+                    return Err(e.into());
+                };
+                tail.push(name);
+                existing = parent;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+fn check_inside(path: &Path, roots: &[PathBuf]) -> CommandResult<()> {
+    if roots.iter().any(|root| path.starts_with(root)) {
+        return Ok(());
+    }
+    Err(not_allowed(format!(
+        "{} is outside the Downloads folder and the folders you've chosen; pick it in the save dialog first",
+        path.display()
+    )))
+}
+
+/// Checks a clip destination and creates its missing parent folders, returning the path
+/// to write to. An existing file is only replaced when `overwrite` is set, and never
+/// through a symlink.
+pub fn prepare_file(path: &str, roots: &[PathBuf], overwrite: bool) -> CommandResult<PathBuf> {
+    let path = Path::new(path);
+    // Only the folder is resolved; the file itself is checked below without following it.
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Err(CommandError::new(ErrorKind::InvalidInput, format!("{} is not a file path", path.display())));
+    };
+    let parent = resolve(parent)?;
+    check_inside(&parent, roots)?;
+    let target = parent.join(name);
+    match std::fs::symlink_metadata(&target) {
+        Ok(meta) if meta.file_type().is_symlink() => {
+            return Err(not_allowed(format!("{} is a symlink; downloads don't write through links", target.display())));
+        }
+        Ok(meta) if meta.is_dir() => {
+            return Err(CommandError::new(ErrorKind::AlreadyExists, format!("{} is a folder", target.display())));
+        }
+        Ok(_) if !overwrite => {
+            return Err(CommandError::new(ErrorKind::AlreadyExists, format!("{} already exists", target.display())));
+        }
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+    std::fs::create_dir_all(&parent)?;
+    Ok(target)
+}
+
+/// Opens a checked destination, failing with `AlreadyExists` rather than replacing a file
+/// that appeared since `prepare_file` unless `overwrite` is set.
+pub async fn create_file(path: &Path, overwrite: bool) -> io::Result<tokio::fs::File> {
+    tokio::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(!overwrite)
+        .open(path)
+        .await
+}
+
+/// Checks a folder the download queue will fill and creates it if it's missing.
+pub fn prepare_dir(dir: &str, roots: &[PathBuf]) -> CommandResult<PathBuf> {
+    let target = resolve(Path::new(dir))?;
+    check_inside(&target, roots)?;
+    std::fs::create_dir_all(&target)?;
+    Ok(target)
+}
+
+/// Holds imported `settings` to what this machine has granted: download folders not chosen
+/// here are dropped, and the folders the app writes to on its own must fall inside what is
+/// left. Those that don't are cleared. Returns every folder that was dropped.
+pub fn confine_imported(settings: &mut Settings, granted: &[String]) -> Vec<String> {
+    let (kept, mut dropped): (Vec<String>, Vec<String>) = settings.download_dirs.drain(..).partition(|dir| granted.contains(dir));
+    settings.download_dirs = kept;
+    let roots = allowed_roots(&settings.download_dirs);
+    let mut confine = |dir: &mut Option<String>| {
+        let Some(path) = dir.take().filter(|d| !d.trim().is_empty()) else { return };
+        match prepare_dir(path.trim(), &roots) {
+            Ok(resolved) => *dir = Some(resolved.to_string_lossy().into()),
+            Err(_) => dropped.push(path),
+        }
+    };
+    confine(&mut settings.archive_dir);
+    confine(&mut settings.liveview_recording_dir);
+    if let Some(ClipStoreConfig::Local { dir }) = &settings.archive_store {
+        let mut store = Some(dir.clone());
+        confine(&mut store);
+        settings.archive_store = store.map(|dir| ClipStoreConfig::Local { dir });
+    }
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A granted folder and one that isn't, both empty.
+    fn folders() -> (tempfile::TempDir, tempfile::TempDir, Vec<PathBuf>) {
+        let granted = tempfile::tempdir().unwrap();
+        let other = tempfile::tempdir().unwrap();
+        let roots = allowed_roots(&[granted.path().to_string_lossy().to_string()]);
+        (granted, other, roots)
+    }
+
+    fn kind(result: CommandResult<PathBuf>) -> ErrorKind {
+        result.unwrap_err().kind
+    }
+
+    fn at(dir: &Path, rest: &str) -> String {
+        dir.join(rest).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn a_file_in_a_granted_folder_gets_its_parents_created() {
+        let (granted, _, roots) = folders();
+        let target = prepare_file(&at(granted.path(), "2026/10/clip.mp4"), &roots, false).unwrap();
+        assert_eq!(target, granted.path().canonicalize().unwrap().join("2026/10/clip.mp4"));
+        assert!(target.parent().unwrap().is_dir());
+        assert!(!target.exists());
+    }
+
+    #[test]
+    fn traversal_and_relative_paths_are_refused() {
+        let (granted, _, roots) = folders();
+        // Refused even where `..` would land back inside the granted folder.
+        for path in [at(granted.path(), "../../.bashrc"), at(granted.path(), "sub/../clip.mp4"), "clip.mp4".to_string(), "../clip.mp4".to_string()] {
+            assert_eq!(kind(prepare_file(&path, &roots, true)), ErrorKind::PathNotAllowed, "{}", path);
+        }
+        assert_eq!(kind(prepare_dir(&at(granted.path(), "../elsewhere"), &roots)), ErrorKind::PathNotAllowed);
+        assert!(!granted.path().join("sub").exists());
+    }
+
+    #[test]
+    fn folders_outside_the_granted_ones_are_refused_without_being_created() {
+        let (_, other, roots) = folders();
+        assert_eq!(kind(prepare_file(&at(other.path(), "new/clip.mp4"), &roots, true)), ErrorKind::PathNotAllowed);
+        assert_eq!(kind(prepare_dir(&at(other.path(), "new"), &roots)), ErrorKind::PathNotAllowed);
+        assert!(!other.path().join("new").exists());
+        // With nothing granted there is nowhere to write.
+        assert_eq!(kind(prepare_dir(&at(other.path(), "new"), &[])), ErrorKind::PathNotAllowed);
+    }
+
+    #[test]
+    fn existing_files_are_only_replaced_with_overwrite() {
+        let (granted, _, roots) = folders();
+        std::fs::write(granted.path().join("clip.mp4"), b"kept").unwrap();
+        std::fs::create_dir(granted.path().join("folder.mp4")).unwrap();
+        let path = at(granted.path(), "clip.mp4");
+        assert_eq!(kind(prepare_file(&path, &roots, false)), ErrorKind::AlreadyExists);
+        assert!(prepare_file(&path, &roots, true).is_ok());
+        assert_eq!(kind(prepare_file(&at(granted.path(), "folder.mp4"), &roots, true)), ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read(granted.path().join("clip.mp4")).unwrap(), b"kept");
+    }
+
+    #[tokio::test]
+    async fn create_file_refuses_a_file_that_appeared_after_the_check() {
+        let (granted, _, roots) = folders();
+        let target = prepare_file(&at(granted.path(), "clip.mp4"), &roots, false).unwrap();
+        std::fs::write(&target, b"raced").unwrap();
+        let error = create_file(&target, false).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(CommandError::from(error).kind, ErrorKind::AlreadyExists);
+        assert!(create_file(&target, true).await.is_ok());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_lead_out_of_a_granted_folder() {
+        let (granted, other, roots) = folders();
+        // A linked folder counts where it points, not where the link sits.
+        std::os::unix::fs::symlink(other.path(), granted.path().join("escape")).unwrap();
+        assert_eq!(kind(prepare_file(&at(granted.path(), "escape/clip.mp4"), &roots, false)), ErrorKind::PathNotAllowed);
+        assert_eq!(kind(prepare_dir(&at(granted.path(), "escape/new"), &roots)), ErrorKind::PathNotAllowed);
+        assert!(!other.path().join("new").exists());
+
+        std::fs::create_dir(granted.path().join("real")).unwrap();
+        std::os::unix::fs::symlink(granted.path().join("real"), granted.path().join("inside")).unwrap();
+        let target = prepare_file(&at(granted.path(), "inside/clip.mp4"), &roots, false).unwrap();
+        assert_eq!(target, granted.path().canonicalize().unwrap().join("real/clip.mp4"));
+
+        // A file that is itself a link is never written through, even with overwrite.
+        std::fs::write(other.path().join(".bashrc"), b"kept").unwrap();
+        std::os::unix::fs::symlink(other.path().join(".bashrc"), granted.path().join("clip.mp4")).unwrap();
+        assert_eq!(kind(prepare_file(&at(granted.path(), "clip.mp4"), &roots, true)), ErrorKind::PathNotAllowed);
+        assert_eq!(std::fs::read(other.path().join(".bashrc")).unwrap(), b"kept");
+    }
+
+    #[test]
+    fn imported_settings_keep_only_what_was_granted_here() {
+        let (granted, other, _) = folders();
+        let here = vec![granted.path().to_string_lossy().to_string()];
+        let mut settings = Settings {
+            download_dirs: vec![here[0].clone(), at(other.path(), "")],
+            archive_dir: Some(at(granted.path(), "archive")),
+            liveview_recording_dir: Some(at(other.path(), "recordings")),
+            archive_store: Some(ClipStoreConfig::Local { dir: at(other.path(), "store") }),
+            ..Settings::default()
+        };
+        let dropped = confine_imported(&mut settings, &here);
+
+        assert_eq!(settings.download_dirs, here);
+        assert_eq!(settings.archive_dir, Some(at(&granted.path().canonicalize().unwrap(), "archive")));
+        assert!(granted.path().join("archive").is_dir());
+        assert_eq!(settings.liveview_recording_dir, None);
+        assert_eq!(settings.archive_store, None);
+        assert_eq!(dropped, [at(other.path(), ""), at(other.path(), "recordings"), at(other.path(), "store")]);
+        assert!(!other.path().join("recordings").exists() && !other.path().join("store").exists());
+    }
+
+    #[test]
+    fn an_imported_local_store_inside_a_grant_is_kept() {
+        let (granted, _, _) = folders();
+        let here = vec![granted.path().to_string_lossy().to_string()];
+        let mut settings = Settings {
+            archive_store: Some(ClipStoreConfig::Local { dir: at(granted.path(), "store") }),
+            ..Settings::default()
+        };
+        assert!(confine_imported(&mut settings, &here).is_empty());
+        assert_eq!(settings.archive_store, Some(ClipStoreConfig::Local { dir: at(&granted.path().canonicalize().unwrap(), "store") }));
+    }
+
+    #[test]
+    fn grants_cover_their_subfolders_and_removed_ones_drop_out() {
+        let (granted, other, roots) = folders();
+        std::fs::create_dir(granted.path().join("sub")).unwrap();
+        assert!(is_covered(&granted.path().join("sub"), &roots));
+        assert!(!is_covered(other.path(), &roots));
+
+        let gone = at(other.path(), "removed");
+        let roots = allowed_roots(&[gone]);
+        assert!(roots.iter().all(|root| !root.starts_with(other.path())));
+    }
+}
@@ -0,0 +1,402 @@
+use crate::blink::{BlinkClient, MediaItem};
+use crate::export;
+use crate::media_index::MediaIndex;
+use crate::settings::{Settings, MAX_DOWNLOAD_CONCURRENCY};
+use crate::shared;
+use futures_util::StreamExt;
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{watch, Mutex};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+const PROGRESS_EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+/// One clip to fetch into `dir`; the file name comes from `download_filename_template`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DownloadRequest {
+    pub item: MediaItem,
+    pub dir: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    fn is_terminal(self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadJob {
+    pub id: String,
+    pub url: String,
+    pub path: String,
+    pub item: MediaItem,
+    /// Whether a metadata sidecar is written once the clip is complete.
+    pub sidecar: bool,
+    pub status: JobStatus,
+    pub received: u64,
+    pub total: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Payload of the `download-queue` event: aggregate counters plus every job.
+#[derive(Clone, Serialize)]
+pub struct QueueSnapshot {
+    pub paused: bool,
+    pub concurrency: usize,
+    pub queued: usize,
+    pub running: usize,
+    pub completed: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+    pub received: u64,
+    pub total: u64,
+    pub jobs: Vec<DownloadJob>,
+}
+
+struct QueueState {
+    jobs: Vec<DownloadJob>,
+    pending: VecDeque<String>,
+    running: HashMap<String, CancellationToken>,
+    paused: bool,
+    concurrency: usize,
+    last_emit: Option<Instant>,
+}
+
+impl QueueState {
+    fn job_mut(&mut self, id: &str) -> Option<&mut DownloadJob> {
+        self.jobs.iter_mut().find(|j| j.id == id)
+    }
+
+    fn snapshot(&self) -> QueueSnapshot {
+        let count = |status: JobStatus| self.jobs.iter().filter(|j| j.status == status).count();
+        QueueSnapshot {
+            paused: self.paused,
+            concurrency: self.concurrency,
+            queued: count(JobStatus::Queued),
+            running: count(JobStatus::Running),
+            completed: count(JobStatus::Completed),
+            failed: count(JobStatus::Failed),
+            cancelled: count(JobStatus::Cancelled),
+            received: self.jobs.iter().map(|j| j.received).sum(),
+            total: self.jobs.iter().filter_map(|j| j.total).sum(),
+            jobs: self.jobs.clone(),
+        }
+    }
+}
+
+fn part_path(path: &str) -> String {
+    format!("{}.part", path)
+}
+
+/// Downloads clips in the background with a bounded number of concurrent transfers.
+/// Paused jobs keep their `.part` file and continue with a Range request on resume.
+pub struct DownloadManager {
+    blink_client: Arc<Mutex<BlinkClient>>,
+    media_index: Arc<MediaIndex>,
+    state: std::sync::Mutex<QueueState>,
+}
+
+impl DownloadManager {
+    pub fn new(blink_client: Arc<Mutex<BlinkClient>>, media_index: Arc<MediaIndex>, concurrency: usize) -> Self {
+        Self {
+            blink_client,
+            media_index,
+            state: std::sync::Mutex::new(QueueState {
+                jobs: Vec::new(),
+                pending: VecDeque::new(),
+                running: HashMap::new(),
+                paused: false,
+                concurrency,
+                last_emit: None,
+            }),
+        }
+    }
+
+    /// Queues the clips under canonical, collision-free names. Items without a media URL
+    /// are added as failed jobs so they still show up in the list.
+    pub fn enqueue(self: &Arc<Self>, app: &AppHandle, requests: Vec<DownloadRequest>, settings: &Settings) -> Vec<String> {
+        let ids = {
+            let mut state = self.state.lock().unwrap();
+            let mut reserved: HashSet<String> = state.jobs.iter()
+                .filter(|j| !j.status.is_terminal())
+                .map(|j| j.path.clone())
+                .collect();
+            requests.into_iter()
+                .map(|req| {
+                    let id = Uuid::new_v4().to_string();
+                    let path = export::unique_path(Path::new(&req.dir), &req.item, &settings.download_filename_template, &mut reserved);
+                    let (status, error) = match req.item.media {
+                        Some(_) => (JobStatus::Queued, None),
+                        None => (JobStatus::Failed, Some("Item has no media URL".to_string())),
+                    };
+                    state.jobs.push(DownloadJob {
+                        id: id.clone(),
+                        url: req.item.media.clone().unwrap_or_default(),
+                        path: path.to_string_lossy().to_string(),
+                        item: req.item,
+                        sidecar: settings.download_sidecar,
+                        status,
+                        received: 0,
+                        total: None,
+                        error,
+                    });
+                    if status == JobStatus::Queued {
+                        state.pending.push_back(id.clone());
+                    }
+                    id
+                })
+                .collect()
+        };
+        self.pump(app);
+        self.emit(app, true);
+        ids
+    }
+
+    pub fn jobs(&self) -> Vec<DownloadJob> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    /// Drops completed, failed and cancelled jobs from the list.
+    pub fn clear_finished(&self, app: &AppHandle) {
+        self.state.lock().unwrap().jobs.retain(|j| !j.status.is_terminal());
+        self.emit(app, true);
+    }
+
+    pub fn set_concurrency(self: &Arc<Self>, app: &AppHandle, limit: usize) {
+        self.state.lock().unwrap().concurrency = limit.clamp(1, MAX_DOWNLOAD_CONCURRENCY);
+        self.pump(app);
+        self.emit(app, true);
+    }
+
+    /// Applies `download_concurrency` changes from settings for as long as the app runs.
+    pub async fn follow_settings(self: Arc<Self>, app: AppHandle, mut settings: watch::Receiver<Settings>) {
+        while settings.changed().await.is_ok() {
+            let limit = settings.borrow_and_update().download_concurrency;
+            if limit != self.state.lock().unwrap().concurrency {
+                self.set_concurrency(&app, limit);
+            }
+        }
+    }
+
+    /// Stops starting new jobs and interrupts running ones; they go back to the front of the queue.
+    pub fn pause(&self, app: &AppHandle) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.paused = true;
+            let running: Vec<(String, CancellationToken)> = state.running.iter()
+                .map(|(id, token)| (id.clone(), token.clone()))
+                .collect();
+            for (id, token) in running {
+                if let Some(job) = state.job_mut(&id) {
+                    job.status = JobStatus::Queued;
+                }
+                token.cancel();
+            }
+        }
+        self.emit(app, true);
+    }
+
+    pub fn resume(self: &Arc<Self>, app: &AppHandle) {
+        self.state.lock().unwrap().paused = false;
+        self.pump(app);
+        self.emit(app, true);
+    }
+
+    /// Cancels a queued or running job and removes its partial file.
+    /// Returns false for unknown or already finished jobs.
+    pub fn cancel(&self, app: &AppHandle, id: &str) -> bool {
+        let orphaned_part = {
+            let mut state = self.state.lock().unwrap();
+            let path = match state.job_mut(id) {
+                Some(job) if !job.status.is_terminal() => {
+                    job.status = JobStatus::Cancelled;
+                    job.path.clone()
+                }
+                _ => return false,
+            };
+            state.pending.retain(|p| p != id);
+            match state.running.get(id) {
+                // The worker removes the part file once it has stopped writing.
+                Some(token) => {
+                    token.cancel();
+                    None
+                }
+                None => Some(part_path(&path)),
+            }
+        };
+        if let Some(part) = orphaned_part {
+            tauri::async_runtime::spawn(async move {
+                let _ = tokio::fs::remove_file(part).await;
+            });
+        }
+        self.emit(app, true);
+        true
+    }
+
+    /// Starts queued jobs until the concurrency limit is reached.
+    fn pump(self: &Arc<Self>, app: &AppHandle) {
+        let mut to_start = Vec::new();
+        {
+            let mut state = self.state.lock().unwrap();
+            while !state.paused && state.running.len() < state.concurrency {
+                let Some(id) = state.pending.pop_front() else { break };
+                let Some(job) = state.job_mut(&id) else { continue };
+                if job.status != JobStatus::Queued {
+                    continue;
+                }
+                job.status = JobStatus::Running;
+                job.error = None;
+                let (url, path) = (job.url.clone(), job.path.clone());
+                let token = CancellationToken::new();
+                state.running.insert(id.clone(), token.clone());
+                to_start.push((id, url, path, token));
+            }
+        }
+
+        for (id, url, path, token) in to_start {
+            let manager = self.clone();
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                let result = manager.download(&app, &id, &url, &path, &token).await;
+                manager.finish(&app, &id, &path, result).await;
+            });
+        }
+    }
+
+    async fn finish(self: &Arc<Self>, app: &AppHandle, id: &str, path: &str, result: Result<bool, String>) {
+        let mut archived = None;
+        let remove_part = {
+            let mut state = self.state.lock().unwrap();
+            state.running.remove(id);
+            let Some(job) = state.job_mut(id) else { return };
+            match (job.status, result) {
+                // A pause that lands after the last chunk still counts as a completed download.
+                (JobStatus::Running | JobStatus::Queued, Ok(true)) => {
+                    job.status = JobStatus::Completed;
+                    archived = Some((job.url.clone(), job.item.clone(), job.sidecar));
+                    false
+                }
+                (JobStatus::Running, Err(e)) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e);
+                    true
+                }
+                // Interrupted by pause: keep the part file and retry first on resume.
+                (JobStatus::Queued, _) => {
+                    state.pending.push_front(id.to_string());
+                    false
+                }
+                (JobStatus::Cancelled, _) => true,
+                _ => false,
+            }
+        };
+        if remove_part {
+            let _ = tokio::fs::remove_file(part_path(path)).await;
+        }
+        if let Some((url, item, sidecar)) = archived {
+            if let Err(e) = self.media_index.set_local_path(&url, path) {
+                eprintln!("Failed to record {} in media index: {}", path, e);
+            }
+            if let Err(e) = export::apply_clip_metadata(Path::new(path), &item, sidecar) {
+                eprintln!("Failed to write clip metadata for {}: {}", path, e);
+            }
+        }
+        self.pump(app);
+        self.emit(app, true);
+    }
+
+    async fn download(&self, app: &AppHandle, id: &str, url: &str, path: &str, cancel: &CancellationToken) -> Result<bool, String> {
+        let (token, full_url, http_client) = {
+            let mut client = self.blink_client.lock().await;
+            let _ = shared::refresh_and_persist(&mut client).await;
+            let token = client.token().map_err(|e| e.to_string())?.clone();
+            let full_url = client.resolve_url(url).map_err(|e| e.to_string())?;
+            (token, full_url, client.media_client.clone())
+        };
+        let full_url = shared::validate_media_url(full_url.as_str())?;
+
+        let part = part_path(path);
+        let existing = tokio::fs::metadata(&part).await.map(|m| m.len()).unwrap_or(0);
+        let mut req = http_client.get(full_url).header("Authorization", format!("Bearer {}", token.expose_secret()));
+        if existing > 0 {
+            req = req.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+        }
+
+        let res = tokio::select! {
+            _ = cancel.cancelled() => return Ok(false),
+            res = req.send() => res.map_err(|e| e.to_string())?,
+        };
+        if !res.status().is_success() {
+            let status = res.status();
+            let body = res.text().await.unwrap_or_default();
+            return Err(format!("Download failed: {} {}", status, body));
+        }
+
+        // Servers that ignore Range answer 200 with the full body, so start over.
+        let resumed = existing > 0 && res.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        let total = res.content_length().map(|len| if resumed { len + existing } else { len });
+        let mut received = if resumed { existing } else { 0 };
+        let mut file = if resumed {
+            tokio::fs::OpenOptions::new().append(true).open(&part).await
+        } else {
+            tokio::fs::File::create(&part).await
+        }.map_err(|e| e.to_string())?;
+        self.update_progress(app, id, received, total);
+
+        let mut stream = res.bytes_stream();
+        loop {
+            let chunk = tokio::select! {
+                _ = cancel.cancelled() => return Ok(false),
+                chunk = stream.next() => chunk,
+            };
+            let Some(chunk) = chunk else { break };
+            let chunk = chunk.map_err(|e| e.to_string())?;
+            file.write_all(&chunk).await.map_err(|e| e.to_string())?;
+            received += chunk.len() as u64;
+            self.update_progress(app, id, received, total);
+        }
+        file.flush().await.map_err(|e| e.to_string())?;
+        drop(file);
+
+        tokio::fs::rename(&part, path).await.map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    fn update_progress(&self, app: &AppHandle, id: &str, received: u64, total: Option<u64>) {
+        if let Some(job) = self.state.lock().unwrap().job_mut(id) {
+            job.received = received;
+            job.total = total;
+        }
+        self.emit(app, false);
+    }
+
+    /// Emits a `download-queue` snapshot; progress-only updates are throttled.
+    fn emit(&self, app: &AppHandle, force: bool) {
+        let snapshot = {
+            let mut state = self.state.lock().unwrap();
+            if !force && state.last_emit.is_some_and(|t| t.elapsed() < PROGRESS_EMIT_INTERVAL) {
+                return;
+            }
+            state.last_emit = Some(Instant::now());
+            state.snapshot()
+        };
+        let _ = app.emit("download-queue", snapshot);
+    }
+}
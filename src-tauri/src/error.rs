@@ -0,0 +1,272 @@
+use crate::blink::BlinkError;
+use crate::camera_config::{ConfigProblem, ConfigRejected};
+use crate::liveview::LiveviewError;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Emitter};
+
+static APP: OnceLock<AppHandle> = OnceLock::new();
+/// Set once `auth-expired` has been emitted so a burst of failing commands only redirects once.
+static AUTH_EXPIRED_SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    AuthExpired,
+    /// Blink revoked the refresh token; the user has to sign in again.
+    ReauthRequired,
+    RateLimited,
+    CameraBusy,
+    Network,
+    NotFound,
+    /// The arguments were out of range; retrying them won't help.
+    InvalidInput,
+    /// The OS refused to write there.
+    PermissionDenied,
+    /// A download would replace a file and `overwrite` wasn't set.
+    AlreadyExists,
+    /// The path is outside the folders downloads may be written to.
+    PathNotAllowed,
+    Internal,
+}
+
+/// Error payload returned by every Tauri command.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub retryable: bool,
+    /// Blink's own error code, when the failure came with one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i64>,
+    /// Seconds Blink asked us to wait after a rate limit, when it said.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    /// What was wrong with a rejected camera config update, key by key.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub problems: Vec<ConfigProblem>,
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+/// Lets command errors emit `auth-expired` without every command taking an `AppHandle`.
+pub fn init(app: &AppHandle) {
+    let _ = APP.set(app.clone());
+}
+
+/// Re-arms the `auth-expired` event after a successful login.
+pub fn auth_restored() {
+    AUTH_EXPIRED_SIGNALLED.store(false, Ordering::Relaxed);
+}
+
+impl CommandError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        let error = Self {
+            kind,
+            message: message.into(),
+            retryable: matches!(kind, ErrorKind::RateLimited | ErrorKind::CameraBusy | ErrorKind::Network),
+            code: None,
+            retry_after_secs: None,
+            problems: Vec::new(),
+        };
+        let signals_auth = matches!(kind, ErrorKind::AuthExpired | ErrorKind::ReauthRequired);
+        if signals_auth && !AUTH_EXPIRED_SIGNALLED.swap(true, Ordering::Relaxed) {
+            if let Some(app) = APP.get() {
+                let _ = app.emit("auth-expired", &error);
+            }
+        }
+        error
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl From<&BlinkError> for CommandError {
+    fn from(e: &BlinkError) -> Self {
+        let kind = match e {
+            BlinkError::NotLoggedIn | BlinkError::AuthExpired => ErrorKind::AuthExpired,
+            BlinkError::ReauthRequired => ErrorKind::ReauthRequired,
+            BlinkError::RateLimited { .. } => ErrorKind::RateLimited,
+            BlinkError::CameraBusy { .. } => ErrorKind::CameraBusy,
+            BlinkError::NotFound(_) => ErrorKind::NotFound,
+            BlinkError::OutOfRange(_) => ErrorKind::InvalidInput,
+            BlinkError::Http { status, .. } if *status >= 500 => ErrorKind::Network,
+            BlinkError::Http { .. }
+            | BlinkError::LoginPageChanged(_)
+            | BlinkError::InvalidPath(_)
+            | BlinkError::ResponseTooLarge { .. }
+            | BlinkError::NoAuthCode(_)
+            | BlinkError::AuthCodeRejected { .. } => ErrorKind::Internal,
+        };
+        let code = match e {
+            BlinkError::Http { code, .. } => *code,
+            _ => None,
+        };
+        let retry_after_secs = match e {
+            BlinkError::RateLimited { retry_after_secs, .. } => *retry_after_secs,
+            _ => None,
+        };
+        Self { code, retry_after_secs, ..Self::new(kind, e.to_string()) }
+    }
+}
+
+impl From<BlinkError> for CommandError {
+    fn from(e: BlinkError) -> Self {
+        Self::from(&e)
+    }
+}
+
+impl From<anyhow::Error> for CommandError {
+    fn from(e: anyhow::Error) -> Self {
+        if let Some(blink) = e.downcast_ref::<BlinkError>() {
+            return Self::from(blink);
+        }
+        if e.downcast_ref::<reqwest::Error>().is_some() {
+            return Self::new(ErrorKind::Network, e.to_string());
+        }
+        Self::internal(e.to_string())
+    }
+}
+
+impl From<ConfigRejected> for CommandError {
+    fn from(e: ConfigRejected) -> Self {
+        let message = e.to_string();
+        Self { problems: e.problems, ..Self::new(ErrorKind::InvalidInput, message) }
+    }
+}
+
+impl From<LiveviewError> for CommandError {
+    fn from(e: LiveviewError) -> Self {
+        match e {
+            LiveviewError::Busy { .. } => Self::new(ErrorKind::CameraBusy, e.to_string()),
+            LiveviewError::Immi(_) => Self::new(ErrorKind::Network, e.to_string()),
+            LiveviewError::Blink(inner) => Self::from(inner),
+        }
+    }
+}
+
+impl From<reqwest::Error> for CommandError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::new(ErrorKind::Network, e.to_string())
+    }
+}
+
+impl From<std::io::Error> for CommandError {
+    fn from(e: std::io::Error) -> Self {
+        let kind = match e.kind() {
+            std::io::ErrorKind::NotFound => ErrorKind::NotFound,
+            std::io::ErrorKind::PermissionDenied => ErrorKind::PermissionDenied,
+            std::io::ErrorKind::AlreadyExists => ErrorKind::AlreadyExists,
+            _ => ErrorKind::Internal,
+        };
+        Self::new(kind, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for CommandError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::internal(e.to_string())
+    }
+}
+
+/// Errors that are already plain messages (host validation, settings, ...).
+impl From<String> for CommandError {
+    fn from(message: String) -> Self {
+        Self::internal(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn wire(error: impl Into<CommandError>) -> serde_json::Value {
+        serde_json::to_value(error.into()).unwrap()
+    }
+
+    #[test]
+    fn plain_errors_carry_only_kind_message_and_retryable() {
+        assert_eq!(
+            wire(CommandError::new(ErrorKind::NotFound, "Unknown live session")),
+            json!({ "kind": "not_found", "message": "Unknown live session", "retryable": false })
+        );
+        assert_eq!(wire(CommandError::internal("boom"))["kind"], "internal");
+    }
+
+    #[test]
+    fn kinds_serialize_in_snake_case() {
+        let kinds = [
+            (ErrorKind::AuthExpired, "auth_expired"),
+            (ErrorKind::ReauthRequired, "reauth_required"),
+            (ErrorKind::RateLimited, "rate_limited"),
+            (ErrorKind::CameraBusy, "camera_busy"),
+            (ErrorKind::Network, "network"),
+            (ErrorKind::NotFound, "not_found"),
+            (ErrorKind::InvalidInput, "invalid_input"),
+            (ErrorKind::PermissionDenied, "permission_denied"),
+            (ErrorKind::AlreadyExists, "already_exists"),
+            (ErrorKind::PathNotAllowed, "path_not_allowed"),
+            (ErrorKind::Internal, "internal"),
+        ];
+        for (kind, name) in kinds {
+            assert_eq!(serde_json::to_value(kind).unwrap(), name);
+        }
+    }
+
+    #[test]
+    fn only_transient_kinds_are_retryable() {
+        for kind in [ErrorKind::RateLimited, ErrorKind::CameraBusy, ErrorKind::Network] {
+            assert!(CommandError::new(kind, "").retryable);
+        }
+        for kind in [ErrorKind::AuthExpired, ErrorKind::NotFound, ErrorKind::InvalidInput, ErrorKind::PathNotAllowed, ErrorKind::Internal] {
+            assert!(!CommandError::new(kind, "").retryable);
+        }
+    }
+
+    #[test]
+    fn blink_errors_keep_code_and_retry_after() {
+        let rate_limited = wire(BlinkError::RateLimited { message: "Slow down".into(), retry_after_secs: Some(30) });
+        assert_eq!(rate_limited["kind"], "rate_limited");
+        assert_eq!(rate_limited["retryable"], true);
+        assert_eq!(rate_limited["retry_after_secs"], 30);
+
+        let http = wire(BlinkError::Http { status: 400, code: Some(101), message: "Bad request".into() });
+        assert_eq!(http["kind"], "internal");
+        assert_eq!(http["code"], 101);
+        assert!(http.get("retry_after_secs").is_none());
+
+        assert_eq!(wire(BlinkError::Http { status: 503, code: None, message: String::new() })["kind"], "network");
+        assert_eq!(wire(BlinkError::ReauthRequired)["kind"], "reauth_required");
+        assert_eq!(wire(BlinkError::OutOfRange("page".into()))["kind"], "invalid_input");
+        assert_eq!(wire(anyhow::Error::from(BlinkError::NotLoggedIn))["kind"], "auth_expired");
+    }
+
+    #[test]
+    fn config_rejections_list_their_problems() {
+        let rejected = ConfigRejected {
+            problems: vec![ConfigProblem { key: "camera.motion_sensitivity".into(), problem: "must be 1-9".into() }],
+        };
+        let value = wire(rejected);
+        assert_eq!(value["kind"], "invalid_input");
+        assert_eq!(value["problems"], json!([{ "key": "camera.motion_sensitivity", "problem": "must be 1-9" }]));
+    }
+
+    #[test]
+    fn io_errors_map_to_their_kind() {
+        use std::io::{Error, ErrorKind as Io};
+        assert_eq!(CommandError::from(Error::from(Io::NotFound)).kind, ErrorKind::NotFound);
+        assert_eq!(CommandError::from(Error::from(Io::PermissionDenied)).kind, ErrorKind::PermissionDenied);
+        assert_eq!(CommandError::from(Error::from(Io::AlreadyExists)).kind, ErrorKind::AlreadyExists);
+        assert_eq!(CommandError::from(Error::other("disk on fire")).kind, ErrorKind::Internal);
+    }
+}
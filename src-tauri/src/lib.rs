@@ -1,625 +1,2095 @@
-mod blink;
+mod downloads;
+mod download_paths;
+mod error;
 mod storage;
 mod server;
-mod immi;
+mod motion;
+mod thumbnails;
+mod tray;
 
-use blink::{BlinkClient, Camera, Network};
+use blink_core::{audit, auth_store, backup, battery, blink, camera_config, cleanup, clip_store, clock_skew, delete_queue, device_routes, diagnostics, export, firmware, heatmap, live_sessions, liveview, liveview_settings, log_control, media_index, motion_notifications, rearm, recording, replay, schedule, self_test, settings, shared, signing, thumbnail_cache, thumbnail_refresh, timeline, trash};
+
+use audit::{AuditEntry, AuditFilter};
+use backup::{ExportSummary as StateExportSummary, ImportSummary};
+use battery::{BatteryHistory, BatteryStore};
+use blink::{ArmVerification, AuthState, BlinkClient, BlinkError, Camera, DeleteResult, LiveviewOptions, LiveviewQuality, MediaItem, Network, NetworkArmResult, NetworkSettings, NetworkSettingsPatch, NotificationConfig, LocalStorageStatus, TrustedClient, UsageReport, UsbEjectResult};
+use cleanup::{CleanupEntry, CleanupQueue};
+use delete_queue::{DeleteProgress, DeleteQueue};
+use diagnostics::MediaDiagnosis;
+use downloads::{DownloadJob, DownloadManager, DownloadRequest};
+use error::{CommandError, CommandResult, ErrorKind};
+use export::{ExportCompression, ExportSummary};
+use live_sessions::{LiveSessionInfo, SessionRegistry};
+use recording::RecordingInfo;
+use replay::ReplaySaved;
+use liveview::{LiveviewRequest, StreamMode};
+use media_index::{MediaFilter, MediaIndex, MediaSearchPage};
+use motion::{MotionAlertState, MotionPoller};
+use rearm::{PendingRearm, RearmQueue, TemporaryDisarm};
+use schedule::{NextScheduledChange, ScheduleEntry, Scheduler};
+use settings::{CameraPrefs, Settings};
+use signing::UrlSigner;
+use thumbnail_cache::{CacheStatus, PrefetchResult};
+use thumbnail_refresh::ThumbnailRefresher;
+use thumbnails::{CachedThumbnail, ThumbnailCache, ThumbnailCacheStats};
+use firmware::{FirmwareRecord, FirmwareTracker};
+use heatmap::ActivityHeatmap;
+use timeline::TimelineEvent;
+use trash::{PendingDeletion, Trash};
+use tray::TrayState;
+use secrecy::{ExposeSecret, SecretString};
+use shared::{DiscoveredSettingResult, LiveviewSettingCandidate, SettingWriteOutcome};
+use liveview_settings::{LiveviewSetting, LiveviewSettingWrite};
+use motion_notifications::{MotionNotificationWrite, MotionNotifications};
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use tauri::{Emitter, Manager, State, Window};
+use tokio::sync::{watch, Mutex, Semaphore};
+use tokio_util::sync::CancellationToken;
+use tauri::ipc::{Channel, InvokeResponseBody};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State, Window, WindowEvent};
+use tauri_plugin_dialog::DialogExt;
+use tauri_plugin_notification::NotificationExt;
 use tokio::io::AsyncWriteExt;
 use futures_util::StreamExt;
-use url::Url;
 use serde_json::Value;
-#[cfg(debug_assertions)]
-use http::header;
+
+/// Thumbnail and media-page requests allowed in flight at once.
+const MAX_CONCURRENT_FETCHES: usize = 6;
+/// Granularity of the expiry on signed embedded-server URLs.
+const SERVER_URL_TTL_SECS: i64 = 60 * 60;
+/// How long commands wait for the embedded server to come up before giving up.
+const SERVER_START_WAIT: std::time::Duration = std::time::Duration::from_secs(5);
+/// How often running live sessions are published as `live-stats` events.
+const LIVE_STATS_INTERVAL_SECS: u64 = 2;
 
 struct AppState {
     blink_client: Arc<Mutex<BlinkClient>>,
-    server_port: Mutex<Option<u16>>,
+    server: Arc<server::ServerHealth>,
+    /// Signs URLs for the embedded server; the key lives only as long as the app.
+    signer: Arc<UrlSigner>,
+    live_sessions: Arc<SessionRegistry>,
+    downloads: DownloadTokens,
+    download_manager: Arc<DownloadManager>,
+    motion_poller: Arc<MotionPoller>,
+    scheduler: Arc<Scheduler>,
+    media_index: Arc<MediaIndex>,
+    trash: Arc<Trash>,
+    delete_queue: Arc<DeleteQueue>,
+    firmware: Arc<FirmwareTracker>,
+    cleanup: Arc<CleanupQueue>,
+    rearm: Arc<RearmQueue>,
+    battery: Arc<BatteryStore>,
+    settings: watch::Sender<Settings>,
+    tray: TrayState,
+    thumbnails: Arc<ThumbnailCache>,
+    /// Caps concurrent thumbnail and media-page fetches so a gallery can't crowd out
+    /// everything else talking to Blink. The embedded server takes from the same pool.
+    fetch_permits: Arc<Semaphore>,
+}
+
+#[tauri::command]
+async fn get_server_port(state: State<'_, AppState>) -> CommandResult<u16> {
+    server_port(&state).await
+}
+
+/// The embedded server's port, waiting up to `SERVER_START_WAIT` for it to start; the
+/// webview can ask for it before setup has finished.
+async fn server_port(state: &AppState) -> CommandResult<u16> {
+    let deadline = tokio::time::Instant::now() + SERVER_START_WAIT;
+    loop {
+        if let Some(port) = state.server.port() {
+            return Ok(port);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(CommandError::internal("Server not started"));
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+}
+
+/// Uptime, port and restart count of the embedded server.
+#[tauri::command]
+fn get_server_status(state: State<'_, AppState>) -> server::ServerStatus {
+    state.server.status()
+}
+
+/// Drops the embedded server's listener so the watchdog binds a fresh one; the new port
+/// arrives in a `server-restarted` event.
+#[tauri::command]
+fn restart_server(state: State<'_, AppState>) {
+    state.server.restart();
+}
+
+#[tauri::command]
+async fn list_live_sessions(state: State<'_, AppState>) -> CommandResult<Vec<LiveSessionInfo>> {
+    Ok(state.live_sessions.list())
+}
+
+#[tauri::command]
+async fn stop_live_session(session_id: String, state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.live_sessions.stop(&session_id, &state.blink_client).await?)
+}
+
+/// Saves the session's instant replay buffer to `dest` as MPEG-TS, starting at the
+/// earliest buffered keyframe. `dest` must be in an allowed download folder.
+#[tauri::command]
+async fn save_replay(session_id: String, dest: String, overwrite: Option<bool>, state: State<'_, AppState>) -> CommandResult<ReplaySaved> {
+    let session = state.live_sessions.get(&session_id)
+        .ok_or_else(|| CommandError::new(ErrorKind::NotFound, "Unknown live session"))?;
+    let dest = download_target(&state, &dest, overwrite.unwrap_or(false))?;
+    session.stats.replay.save(&session.id, std::path::Path::new(&dest))?
+        .ok_or_else(|| CommandError::new(ErrorKind::InvalidInput, "Nothing playable buffered for this session yet"))
+}
+
+/// Recordings in `liveview_recording_dir`, newest first; empty when no folder is set.
+#[tauri::command]
+async fn list_recordings(state: State<'_, AppState>) -> CommandResult<Vec<RecordingInfo>> {
+    let Some(dir) = state.settings.borrow().liveview_recording_dir.clone() else {
+        return Ok(Vec::new());
+    };
+    Ok(recording::list(std::path::Path::new(&dir))?)
+}
+
+/// Starts a liveview without going through the embedded HTTP relay. MPEG-TS chunks are
+/// pushed to `on_chunk` as raw binary messages until the stream ends or `stop_liveview`
+/// is called. Returns the session id. `quality` falls back to the camera's preference.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn start_liveview(
+    network_id: i64,
+    camera_id: i64,
+    product_type: String,
+    serial: Option<String>,
+    record: Option<bool>,
+    quality: Option<LiveviewQuality>,
+    audio: Option<bool>,
+    on_chunk: Channel,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let settings = state.settings.borrow().clone();
+    let request = LiveviewRequest {
+        network_id,
+        camera_id,
+        product_type,
+        serial: serial.unwrap_or_default(),
+        record: record.unwrap_or(false),
+        options: LiveviewOptions {
+            quality: quality.or_else(|| settings.liveview_quality(camera_id)),
+            audio,
+            intent: None,
+        },
+        mode: StreamMode::Mse,
+        fake: false,
+    };
+    let mut live = liveview::start(&state.blink_client, &state.live_sessions, &state.cleanup, &settings, request).await?;
+    let session = live.session.clone();
+    tauri::async_runtime::spawn(async move {
+        while let Some(chunk) = live.chunks.recv().await {
+            if on_chunk.send(InvokeResponseBody::Raw(chunk.to_vec())).is_err() {
+                break;
+            }
+        }
+        // Ends the IMMI side too when the webview stopped listening.
+        live.session.cancel.cancel();
+    });
+    Ok(session.id.clone())
+}
+
+#[tauri::command]
+async fn stop_liveview(session_id: String, state: State<'_, AppState>) -> CommandResult<()> {
+    if state.live_sessions.stop(&session_id, &state.blink_client).await? {
+        Ok(())
+    } else {
+        Err(CommandError::new(ErrorKind::NotFound, "Unknown live session"))
+    }
+}
+
+#[tauri::command]
+async fn check_auth(state: State<'_, AppState>) -> CommandResult<AuthState> {
+    let mut client = state.blink_client.lock().await;
+    if let Ok(saved) = storage::load_auth() {
+        client.hydrate_from(saved);
+    }
+    Ok(client.auth_state())
+}
+
+/// Replaces the in-memory client with whatever is in storage, even if it is older.
+#[tauri::command]
+async fn reload_auth(state: State<'_, AppState>) -> CommandResult<bool> {
+    let saved = storage::load_auth().map_err(|e| CommandError::internal(format!("Failed to load auth: {}", e)))?;
+    let mut client = state.blink_client.lock().await;
+    *client = BlinkClient::from_state(saved);
+    if client.token.is_some() {
+        error::auth_restored();
+    }
+    Ok(client.token.is_some())
+}
+
+#[tauri::command]
+async fn login(
+    email: String,
+    password: SecretString,
+    state: State<'_, AppState>,
+) -> CommandResult<String> {
+    let mut client = state.blink_client.lock().await;
+    
+    if let Err(e) = client.start_oauth_flow().await {
+        if let Some(blink @ BlinkError::RateLimited { .. }) = e.downcast_ref::<BlinkError>() {
+            return Err(blink.into());
+        }
+        return Err(CommandError::internal(format!("OAuth Init Failed: {}", e)));
+    }
+
+    match client.login_oauth(&email, password).await {
+        Ok(status) => {
+            if status == "SUCCESS" {
+                let _ = storage::save_auth(&client.get_state());
+                error::auth_restored();
+            }
+            Ok(status)
+        },
+        Err(e) => match e.downcast_ref::<BlinkError>() {
+            Some(blink @ BlinkError::RateLimited { .. }) => Err(blink.into()),
+            _ => Err(CommandError::internal(format!("Login Failed: {}", e))),
+        },
+    }
+}
+
+/// Where an Amazon-linked account signs in, after `login` returned `AMAZON_LOGIN_REQUIRED`.
+#[tauri::command]
+async fn get_amazon_signin_url(state: State<'_, AppState>) -> CommandResult<Option<String>> {
+    Ok(state.blink_client.lock().await.amazon_signin_url())
+}
+
+/// Submits the Amazon credentials; `SUCCESS` or `2FA_REQUIRED` like `login`.
+#[tauri::command]
+async fn login_amazon(email: String, password: SecretString, state: State<'_, AppState>) -> CommandResult<String> {
+    let mut client = state.blink_client.lock().await;
+    let result = client.login_amazon(&email, password).await;
+    finish_amazon_login(&client, result)
+}
+
+/// Resumes sign-in from the URL the browser ended on after signing in with Amazon there.
+#[tauri::command]
+async fn complete_amazon_login(callback_url: String, state: State<'_, AppState>) -> CommandResult<String> {
+    let mut client = state.blink_client.lock().await;
+    let result = client.complete_amazon_login(&callback_url).await;
+    finish_amazon_login(&client, result)
+}
+
+fn finish_amazon_login(client: &BlinkClient, result: anyhow::Result<String>) -> CommandResult<String> {
+    match result {
+        Ok(status) => {
+            if status == "SUCCESS" {
+                let _ = storage::save_auth(&client.get_state());
+                error::auth_restored();
+            }
+            Ok(status)
+        }
+        Err(e) => match e.downcast_ref::<BlinkError>() {
+            Some(blink @ BlinkError::RateLimited { .. }) => Err(blink.into()),
+            _ => Err(CommandError::internal(format!("Amazon Login Failed: {}", e))),
+        },
+    }
+}
+
+#[tauri::command]
+async fn verify_pin(pin: SecretString, state: State<'_, AppState>) -> CommandResult<String> {
+    let mut client = state.blink_client.lock().await;
+    match client.verify_pin_oauth(pin).await {
+        Ok(_) => {
+            let _ = storage::save_auth(&client.get_state());
+            error::auth_restored();
+            Ok("SUCCESS".to_string())
+        },
+        Err(e) => match e.downcast_ref::<BlinkError>() {
+            Some(blink @ BlinkError::RateLimited { .. }) => Err(blink.into()),
+            _ => Err(CommandError::internal(format!("Verification Failed: {}", e))),
+        },
+    }
+}
+
+#[tauri::command]
+async fn get_cameras(state: State<'_, AppState>) -> CommandResult<Vec<Camera>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_homescreen().await {
+        Ok(mut res) => {
+            state.settings.borrow().apply_camera_prefs(&mut res.cameras);
+            Ok(res.cameras)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+async fn get_networks(state: State<'_, AppState>) -> CommandResult<Vec<Network>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_homescreen().await {
+        Ok(mut res) => {
+            state.rearm.annotate(&mut res.networks);
+            Ok(res.networks)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns the cached file for a thumbnail path, downloading it first on a miss. When the
+/// download fails, the last copy is returned marked stale and fetched again later; the
+/// error only comes through when there is no copy at all.
+async fn cached_thumbnail(path: &str, state: &AppState) -> CommandResult<CachedThumbnail> {
+    if let Some(cached) = state.thumbnails.lookup(path) {
+        return Ok(cached);
+    }
+    let result = {
+        let _permit = state.fetch_permits.acquire().await.map_err(|e| CommandError::internal(e.to_string()))?;
+        shared::snapshot(&state.blink_client).await.get_thumbnail(path).await
+    };
+    match result {
+        Ok(thumbnail) => Ok(state.thumbnails.store(path, &thumbnail.bytes, &thumbnail.content_type)?),
+        Err(e) => {
+            let Some(stale) = state.thumbnails.stale(path) else { return Err(e.into()) };
+            revalidate_thumbnail(state, path, thumbnail_cache::revalidate_delay(&e));
+            Ok(stale)
+        }
+    }
+}
+
+/// Fetches a thumbnail that was just served stale again after `delay`, once per path.
+fn revalidate_thumbnail(state: &AppState, path: &str, delay: std::time::Duration) {
+    if !state.thumbnails.revalidations.begin(path) {
+        return;
+    }
+    let blink_client = state.blink_client.clone();
+    let thumbnails = state.thumbnails.clone();
+    let path = path.to_string();
+    tauri::async_runtime::spawn(async move {
+        tokio::time::sleep(delay).await;
+        match shared::snapshot(&blink_client).await.get_thumbnail(&path).await {
+            Ok(thumbnail) => {
+                if let Err(e) = thumbnails.store(&path, &thumbnail.bytes, &thumbnail.content_type) {
+                    eprintln!("Failed to cache revalidated thumbnail: {}", e);
+                }
+            }
+            Err(e) => eprintln!("Thumbnail revalidation failed: {}", e),
+        }
+        thumbnails.revalidations.end(&path);
+    });
+}
+
+async fn thumbnail_data_url(cached: &CachedThumbnail) -> CommandResult<String> {
+    let bytes = tokio::fs::read(&cached.file).await?;
+    let content_type = blink::image_content_type(cached.content_type.as_deref(), &bytes);
+    Ok(format!("data:{};base64,{}", content_type, base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes)))
+}
+
+/// Deprecated in favour of `get_thumbnail_url`, which streams the image instead of
+/// shipping it through IPC as base64.
+#[tauri::command]
+async fn get_thumbnail_base64(path: String, state: State<'_, AppState>) -> CommandResult<String> {
+    let cached = cached_thumbnail(&path, &state).await?;
+    thumbnail_data_url(&cached).await
+}
+
+#[derive(serde::Serialize)]
+struct ThumbnailData {
+    data_url: String,
+    /// `stale` when Blink couldn't be reached and an older copy was served.
+    cache: CacheStatus,
+}
+
+/// `get_thumbnail_base64` plus whether the image came from the cache, Blink, or an
+/// older copy kept for when Blink can't be reached.
+#[tauri::command]
+async fn get_thumbnail(path: String, state: State<'_, AppState>) -> CommandResult<ThumbnailData> {
+    let cached = cached_thumbnail(&path, &state).await?;
+    Ok(ThumbnailData { data_url: thumbnail_data_url(&cached).await?, cache: cached.status })
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ThumbnailReady {
+    path: String,
+    /// Names the cached file, as `get_thumbnail_file` returns it.
+    cache_key: String,
+    cache: CacheStatus,
+}
+
+#[derive(Clone, serde::Serialize)]
+struct ThumbnailFailed {
+    path: String,
+    error: String,
+}
+
+/// Fills the thumbnail cache for every path a grid is about to show. Fetches run
+/// concurrently under the fetch permits; each one emits `thumbnail-ready` (or
+/// `thumbnail-failed`) as it finishes, so tiles appear as they arrive, and a failure
+/// doesn't stop the others.
+#[tauri::command]
+async fn prefetch_thumbnails(paths: Vec<String>, app: AppHandle, state: State<'_, AppState>) -> CommandResult<Vec<PrefetchResult>> {
+    let paths = thumbnail_cache::prefetch_paths(paths)
+        .map_err(|e| CommandError::new(ErrorKind::InvalidInput, e.to_string()))?;
+    let state = &*state;
+    let mut fetches: futures_util::stream::FuturesUnordered<_> = paths.into_iter()
+        .map(|path| async move {
+            let result = cached_thumbnail(&path, state).await;
+            (path, result)
+        })
+        .collect();
+    let mut results = Vec::new();
+    while let Some((path, result)) = fetches.next().await {
+        match result {
+            Ok(cached) => {
+                let _ = app.emit("thumbnail-ready", ThumbnailReady {
+                    path: path.clone(),
+                    cache_key: thumbnails::cache_key(&path),
+                    cache: cached.status,
+                });
+                results.push(PrefetchResult { path, cache: Some(cached.status), error: None });
+            }
+            Err(e) => {
+                let _ = app.emit("thumbnail-failed", ThumbnailFailed { path: path.clone(), error: e.to_string() });
+                results.push(PrefetchResult { path, cache: None, error: Some(e.to_string()) });
+            }
+        }
+    }
+    Ok(results)
+}
+
+/// A signed `http://127.0.0.1` URL for the embedded server's `route`, proxying `url`.
+/// They expire on hour boundaries, so repeated requests for the same URL get the same
+/// signature and the webview's cache keeps working.
+async fn signed_server_url(state: &AppState, route: &str, url: &str) -> CommandResult<String> {
+    let port = server_port(state).await?;
+    let encoded: String = url::form_urlencoded::byte_serialize(url.as_bytes()).collect();
+    let now = chrono::Utc::now().timestamp();
+    let expires_at = (now / SERVER_URL_TTL_SECS + 2) * SERVER_URL_TTL_SECS;
+    let signed = state.signer.sign(&format!("{}?url={}", route, encoded), expires_at)
+        .map_err(|e| CommandError::internal(e.to_string()))?;
+    Ok(format!("http://127.0.0.1:{}{}", port, signed))
+}
+
+/// A signed URL for a thumbnail, served by the embedded server's `/api/thumbnail` route
+/// so an `<img>` can load it directly.
+#[tauri::command]
+async fn get_thumbnail_url(path: String, state: State<'_, AppState>) -> CommandResult<String> {
+    signed_server_url(&state, "/api/thumbnail", &path).await
+}
+
+/// A signed URL for a clip, streamed by the embedded server's `/api/clip` route so a
+/// `<video>` can play it.
+#[tauri::command]
+async fn get_clip_url(url: String, state: State<'_, AppState>) -> CommandResult<String> {
+    signed_server_url(&state, "/api/clip", &url).await
+}
+
+/// Like `get_thumbnail_base64` but returns the cached file's path for `convertFileSrc`,
+/// so the image never has to pass through IPC as a string.
+#[tauri::command]
+async fn get_thumbnail_file(path: String, state: State<'_, AppState>) -> CommandResult<String> {
+    let cached = cached_thumbnail(&path, &state).await?;
+    Ok(cached.file.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+async fn get_thumbnail_cache_stats(state: State<'_, AppState>) -> CommandResult<ThumbnailCacheStats> {
+    Ok(state.thumbnails.stats())
+}
+
+#[tauri::command]
+async fn clear_thumbnail_cache(state: State<'_, AppState>) -> CommandResult<u64> {
+    Ok(state.thumbnails.clear()?)
+}
+
+/// Asks Blink for a fresh snapshot and drops the camera's cached thumbnail so the
+/// next camera-list render fetches the new one.
+#[tauri::command]
+async fn refresh_camera_thumbnail(
+    network_id: i64,
+    camera_id: i64,
+    product_type: String,
+    thumbnail: Option<String>,
+    state: State<'_, AppState>,
+) -> CommandResult<()> {
+    shared::snapshot(&state.blink_client).await.request_thumbnail(network_id, camera_id, &product_type).await?;
+    if let Some(path) = thumbnail {
+        state.thumbnails.invalidate(&path);
+    }
+    Ok(())
+}
+
+/// Shared by the `set_network_arm` command and the tray's Armed checkboxes. A manual
+/// change supersedes any pending re-arm.
+async fn arm_network(state: &AppState, network_id: i64, arm: bool, timeout_secs: Option<u64>) -> CommandResult<ArmVerification> {
+    let timeout = timeout_secs.unwrap_or(blink::ARM_VERIFY_TIMEOUT_SECS).clamp(1, blink::MAX_ARM_VERIFY_TIMEOUT_SECS);
+    let verification = shared::snapshot(&state.blink_client).await.set_arm_verified(network_id, arm, std::time::Duration::from_secs(timeout)).await?;
+    state.rearm.cancel(network_id);
+    Ok(verification)
+}
+
+/// Disarms a network and arms it again after `minutes`, even across a restart.
+#[tauri::command]
+async fn disarm_for(app: AppHandle, network_id: i64, minutes: u32, state: State<'_, AppState>) -> CommandResult<TemporaryDisarm> {
+    let client = shared::snapshot(&state.blink_client).await;
+    let disarm = state.rearm.disarm_for(&client, network_id, minutes).await?;
+    tray::set_network_armed(&app, network_id, disarm.verification.state.unwrap_or(false));
+    Ok(disarm)
+}
+
+#[tauri::command]
+async fn get_pending_rearms(state: State<'_, AppState>) -> CommandResult<Vec<PendingRearm>> {
+    Ok(state.rearm.pending())
+}
+
+/// Adds `minutes` to a pending re-arm's deadline.
+#[tauri::command]
+async fn extend_rearm(network_id: i64, minutes: u32, state: State<'_, AppState>) -> CommandResult<PendingRearm> {
+    state.rearm.extend(network_id, minutes).map_err(|e| CommandError::new(ErrorKind::NotFound, e.to_string()))
+}
+
+/// Drops a pending re-arm; the network stays disarmed.
+#[tauri::command]
+async fn cancel_rearm(network_id: i64, state: State<'_, AppState>) -> CommandResult<PendingRearm> {
+    state.rearm.cancel(network_id)
+        .ok_or_else(|| CommandError::new(ErrorKind::NotFound, format!("No re-arm pending for network {}", network_id)))
+}
+
+/// Re-arms temporarily disarmed networks when their deadline passes, with a notification
+/// and a `network-rearmed` event for each attempt.
+async fn drive_rearm(app: AppHandle, queue: Arc<RearmQueue>, blink_client: Arc<Mutex<BlinkClient>>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(rearm::TICK_SECS));
+    loop {
+        interval.tick().await;
+        for run in queue.tick(&blink_client).await {
+            eprintln!("{}", run);
+            if run.error.is_none() {
+                tray::set_network_armed(&app, run.network_id, true);
+            }
+            let _ = app.notification().builder().title("Blink Monitor").body(run.to_string()).show();
+            let _ = app.emit("network-rearmed", &run);
+        }
+    }
+}
+
+/// Runs due schedule entries every `TICK_SECS`, keeping the tray in step and announcing
+/// each action as a `schedule-executed` event.
+async fn drive_schedules(app: AppHandle, scheduler: Arc<Scheduler>, blink_client: Arc<Mutex<BlinkClient>>, settings: watch::Receiver<Settings>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(schedule::TICK_SECS));
+    loop {
+        interval.tick().await;
+        let entries = settings.borrow().schedules.clone();
+        for run in scheduler.tick(&blink_client, &entries).await {
+            eprintln!("{}", run);
+            if run.error.is_none() {
+                tray::set_network_armed(&app, run.network_id, run.action.armed());
+            }
+            let _ = app.emit("schedule-executed", &run);
+        }
+    }
+}
+
+/// Opens or closes the media index as the setting changes and refreshes recent media
+/// every `SYNC_INTERVAL_SECS` while it is enabled.
+async fn drive_media_index(index: Arc<MediaIndex>, blink_client: Arc<Mutex<BlinkClient>>, mut settings: watch::Receiver<Settings>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(media_index::SYNC_INTERVAL_SECS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            changed = settings.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                let enabled = settings.borrow_and_update().media_index_enabled;
+                if enabled == index.is_enabled() {
+                    continue;
+                }
+            }
+        }
+        index.set_enabled(settings.borrow().media_index_enabled);
+        if let Err(e) = index.sync(&blink_client, media_index::SYNC_SINCE_DAYS, media_index::SYNC_MAX_PAGES).await {
+            eprintln!("Media index sync failed: {}", e);
+        }
+    }
+}
+
+/// Checks device firmware every `POLL_INTERVAL_SECS`.
+async fn drive_firmware(app: AppHandle, tracker: Arc<FirmwareTracker>, blink_client: Arc<Mutex<BlinkClient>>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(firmware::POLL_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        check_firmware(&app, &tracker, &blink_client).await;
+    }
+}
+
+/// Polls firmware versions and announces each change as a `firmware-changed` event and a
+/// notification.
+async fn check_firmware(app: &AppHandle, tracker: &FirmwareTracker, blink_client: &Mutex<BlinkClient>) {
+    let client = shared::snapshot(blink_client).await;
+    let changes = match tracker.poll(&client).await {
+        Ok(changes) => changes,
+        Err(e) => {
+            eprintln!("Firmware check failed: {}", e);
+            return;
+        }
+    };
+    if changes.is_empty() {
+        return;
+    }
+    for change in &changes {
+        let _ = app.notification().builder().title("Blink Monitor").body(change.to_string()).show();
+    }
+    let _ = app.emit("firmware-changed", &changes);
+}
+
+/// Firmware per camera and sync module, after checking Blink for changes.
+#[tauri::command]
+async fn get_firmware_report(app: AppHandle, state: State<'_, AppState>) -> CommandResult<Vec<FirmwareRecord>> {
+    check_firmware(&app, &state.firmware, &state.blink_client).await;
+    Ok(state.firmware.report())
+}
+
+/// Samples battery levels from the homescreen, and voltages from camera configs, every
+/// `SAMPLE_INTERVAL_SECS`.
+async fn drive_battery(store: Arc<BatteryStore>, blink_client: Arc<Mutex<BlinkClient>>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(battery::SAMPLE_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        let client = shared::snapshot(&blink_client).await;
+        match client.get_homescreen().await {
+            Ok(homescreen) => {
+                let voltages = battery::sample_voltages(&client, &homescreen).await;
+                store.observe(&homescreen, &voltages)
+            }
+            Err(e) => eprintln!("Battery sample failed: {}", e),
+        }
+    }
+}
+
+/// Runs scheduled thumbnail refreshes every `TICK_SECS`. After each successful request the
+/// cached copy is dropped and a `thumbnail-updated` event carries the camera id, so the
+/// grid loads the new one.
+async fn drive_thumbnail_refresh(app: AppHandle, refresher: Arc<ThumbnailRefresher>, blink_client: Arc<Mutex<BlinkClient>>, settings: watch::Receiver<Settings>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(thumbnail_refresh::TICK_SECS));
+    loop {
+        interval.tick().await;
+        let current = settings.borrow().clone();
+        for run in refresher.tick(&blink_client, &current).await {
+            eprintln!("{}", run);
+            if run.error.is_none() {
+                app.state::<AppState>().thumbnails.invalidate(&run.thumbnail);
+                let _ = app.emit("thumbnail-updated", &run);
+            }
+        }
+    }
+}
+
+/// Publishes every running live session's stats, latency included, as a `live-stats`
+/// event every `LIVE_STATS_INTERVAL_SECS`.
+async fn drive_live_stats(app: AppHandle, live_sessions: Arc<SessionRegistry>) {
+    let mut interval = tokio::time::interval(std::time::Duration::from_secs(LIVE_STATS_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        for info in live_sessions.list() {
+            let _ = app.emit("live-stats", &info);
+        }
+    }
+}
+
+/// Checks storage, auth and each Blink call the dashboard depends on, stopping at the
+/// first failure.
+#[tauri::command]
+async fn run_self_test(state: State<'_, AppState>) -> CommandResult<self_test::SelfTestReport> {
+    Ok(self_test::run(&state.blink_client, &storage::get_config_dir()).await)
+}
+
+/// Raises (or lowers) one log target's level for `duration_secs`, after which it reverts.
+#[tauri::command]
+async fn set_log_level(target: String, level: log_control::Level, duration_secs: u64) -> CommandResult<log_control::LevelOverride> {
+    Ok(log_control::set_level(&target, level, duration_secs)?)
+}
+
+/// Captures `targets` at debug level into a file in the config directory for
+/// `duration_secs`, with a self-test run at the start and its report at the end. Returns
+/// its path as the capture starts.
+#[tauri::command]
+async fn start_debug_capture(targets: Vec<String>, duration_secs: u64, state: State<'_, AppState>) -> CommandResult<String> {
+    let (path, _) = self_test::capture_with_report(state.blink_client.clone(), &storage::get_config_dir(), &targets, duration_secs)?;
+    Ok(path.display().to_string())
+}
+
+/// Battery level points for one camera over the last `days` (default 7), with a
+/// projection of days remaining.
+#[tauri::command]
+async fn get_battery_history(camera_id: i64, days: Option<u32>, state: State<'_, AppState>) -> CommandResult<BatteryHistory> {
+    Ok(state.battery.history(camera_id, days.unwrap_or(battery::DEFAULT_RANGE_DAYS))?)
+}
+
+/// Clips of one camera by weekday and hour in its network's local time, plus clips per
+/// day; `from` defaults to 28 days before `to`, which defaults to now.
+#[tauri::command]
+async fn get_activity_heatmap(
+    camera_id: i64,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    state: State<'_, AppState>,
+) -> CommandResult<ActivityHeatmap> {
+    let to = to.unwrap_or_else(chrono::Utc::now);
+    let from = from.unwrap_or(to - chrono::Duration::days(heatmap::DEFAULT_RANGE_DAYS));
+    heatmap::check_range(from, to).map_err(|e| CommandError::new(ErrorKind::InvalidInput, e.to_string()))?;
+    Ok(heatmap::activity(&state.blink_client, &state.media_index, camera_id, from, to).await?)
+}
+
+/// Recording time per camera, with archived bytes per camera when the media index is on.
+#[tauri::command]
+async fn get_usage(state: State<'_, AppState>) -> CommandResult<UsageReport> {
+    let mut report = shared::snapshot(&state.blink_client).await.get_usage().await?;
+    state.media_index.add_local_bytes(&mut report);
+    Ok(report)
+}
+
+/// Deletes deferred clips from Blink once their undo window has passed.
+async fn drive_trash(trash: Arc<Trash>, blink_client: Arc<Mutex<BlinkClient>>, media_index: Arc<MediaIndex>, settings: watch::Receiver<Settings>) {
+    trash.run(&blink_client, &media_index, || settings.borrow().clone()).await
+}
+
+/// Works through queued deletions, emitting `delete-progress` after every batch.
+async fn drive_delete_queue(app: AppHandle, queue: Arc<DeleteQueue>, blink_client: Arc<Mutex<BlinkClient>>, media_index: Arc<MediaIndex>, settings: watch::Receiver<Settings>) {
+    queue.run(&blink_client, &media_index, || settings.borrow().clone(), |progress| {
+        let _ = app.emit("delete-progress", progress);
+    }).await
+}
+
+/// Deletes the clips Blink recorded for finished liveviews.
+async fn drive_cleanup(cleanup: Arc<CleanupQueue>, blink_client: Arc<Mutex<BlinkClient>>, settings: watch::Receiver<Settings>) {
+    cleanup.run(&blink_client, || settings.borrow().clone()).await
+}
+
+/// Searches the local media index, or one page of the live API when the index is off.
+#[tauri::command]
+async fn search_local_media(filter: Option<MediaFilter>, state: State<'_, AppState>) -> CommandResult<MediaSearchPage> {
+    let filter = filter.unwrap_or_default();
+    let mut page = match state.media_index.search(&filter)? {
+        Some(page) => page,
+        None => media_index::search_live(&state.blink_client, &filter).await?,
+    };
+    let hidden = state.trash.hidden_ids();
+    let before = page.items.len();
+    page.items.retain(|item| !hidden.contains(&item.id));
+    page.total = page.total.saturating_sub((before - page.items.len()) as u64);
+    Ok(page)
+}
+
+/// Recent clips grouped into per-camera motion events, newest first.
+#[tauri::command]
+async fn get_timeline(since_days: Option<i64>, gap_secs: Option<i64>, state: State<'_, AppState>) -> CommandResult<Vec<TimelineEvent>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    let mut items = client.get_all_media(since_days.unwrap_or(timeline::DEFAULT_SINCE_DAYS), timeline::MAX_PAGES).await?;
+    state.trash.retain_visible(&mut items);
+    Ok(timeline::group_events(items, gap_secs.unwrap_or(timeline::DEFAULT_GAP_SECS)))
+}
+
+/// Drops cached rows and re-reads the full media history. Returns the number of items indexed.
+#[tauri::command]
+async fn rebuild_media_index(state: State<'_, AppState>) -> CommandResult<usize> {
+    Ok(state.media_index.rebuild(&state.blink_client).await?)
+}
+
+#[derive(serde::Serialize)]
+struct ScheduleOverview {
+    entries: Vec<ScheduleEntry>,
+    upcoming: Vec<NextScheduledChange>,
+}
+
+fn schedule_overview(state: &AppState) -> ScheduleOverview {
+    let entries = state.settings.borrow().schedules.clone();
+    let upcoming = state.scheduler.upcoming(&entries, chrono::Utc::now());
+    ScheduleOverview { entries, upcoming }
+}
+
+#[tauri::command]
+async fn get_schedules(state: State<'_, AppState>) -> CommandResult<ScheduleOverview> {
+    Ok(schedule_overview(&state))
+}
+
+/// Adds `entry` when its id is empty, otherwise replaces the entry with that id.
+#[tauri::command]
+async fn save_schedule_entry(app: AppHandle, entry: ScheduleEntry, state: State<'_, AppState>) -> CommandResult<ScheduleOverview> {
+    let current = state.settings.borrow().schedules.clone();
+    let (entries, _) = schedule::upsert(&current, entry).map_err(|e| CommandError::internal(e.to_string()))?;
+    apply_settings(&app, &state, serde_json::json!({ "schedules": entries }))?;
+    Ok(schedule_overview(&state))
+}
+
+#[tauri::command]
+async fn delete_schedule_entry(app: AppHandle, id: String, state: State<'_, AppState>) -> CommandResult<ScheduleOverview> {
+    let current = state.settings.borrow().schedules.clone();
+    let entries = schedule::remove(&current, &id).map_err(|e| CommandError::new(ErrorKind::NotFound, e.to_string()))?;
+    apply_settings(&app, &state, serde_json::json!({ "schedules": entries }))?;
+    Ok(schedule_overview(&state))
+}
+
+/// Resolves once Blink has confirmed the change or `timeout_secs` (default 30) has passed;
+/// an unconfirmed change comes back with `confirmed: false` and a warning.
+#[tauri::command]
+async fn set_network_arm(app: AppHandle, network_id: i64, arm: bool, timeout_secs: Option<u64>, state: State<'_, AppState>) -> CommandResult<ArmVerification> {
+    let verification = arm_network(&state, network_id, arm, timeout_secs).await?;
+    tray::set_network_armed(&app, network_id, verification.state.unwrap_or(arm));
+    Ok(verification)
+}
+
+/// Arms or disarms every network concurrently, one result per network, then re-reads the
+/// networks so the tray shows what actually took effect.
+#[tauri::command]
+async fn set_all_networks_arm(app: AppHandle, arm: bool, state: State<'_, AppState>) -> CommandResult<Vec<NetworkArmResult>> {
+    let results = shared::snapshot(&state.blink_client).await.set_arm_all(arm).await?;
+    for result in results.iter().filter(|r| r.ok) {
+        state.rearm.cancel(result.network_id);
+    }
+    tray::refresh(&app).await;
+    Ok(results)
+}
+
+#[tauri::command]
+async fn get_close_to_tray(state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.settings.borrow().close_to_tray)
+}
+
+#[tauri::command]
+async fn set_close_to_tray(app: AppHandle, enabled: bool, state: State<'_, AppState>) -> CommandResult<()> {
+    apply_settings(&app, &state, serde_json::json!({ "close_to_tray": enabled }))?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn logout(state: State<'_, AppState>) -> CommandResult<String> {
+    let mut client = state.blink_client.lock().await;
+    client.logout();
+    
+    if let Err(e) = storage::clear_auth() {
+        return Err(CommandError::internal(format!("Failed to clear auth: {}", e)));
+    }
+    
+    Ok("Logged out successfully".to_string())
+}
+
+#[tauri::command]
+async fn list_trusted_clients(state: State<'_, AppState>) -> CommandResult<Vec<TrustedClient>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.list_trusted_clients().await?)
+}
+
+/// Revoking the client this app uses needs `force` and signs the app out afterwards.
+#[tauri::command]
+async fn revoke_trusted_client(id: i64, force: bool, state: State<'_, AppState>) -> CommandResult<shared::RevokeOutcome> {
+    let outcome = shared::revoke_trusted_client(&state.blink_client, id, force).await?;
+    if outcome.signed_out {
+        storage::clear_auth().map_err(|e| CommandError::internal(format!("Failed to clear auth: {}", e)))?;
+    }
+    Ok(outcome)
+}
+
+#[tauri::command]
+async fn get_raw_homescreen(state: State<'_, AppState>) -> CommandResult<String> {
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_raw_homescreen().await {
+        Ok(res) => Ok(res),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+async fn get_raw_media(state: State<'_, AppState>) -> CommandResult<String> {
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_raw_media().await {
+        Ok(res) => Ok(state.trash.filter_raw_page(res)),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[tauri::command]
+async fn get_raw_media_page(page: i64, since_days: i64, page_size: Option<u32>, state: State<'_, AppState>) -> CommandResult<String> {
+    let _permit = state.fetch_permits.acquire().await.map_err(|e| CommandError::internal(e.to_string()))?;
+    let client = shared::snapshot(&state.blink_client).await;
+    match client.get_raw_media_page(page, since_days, page_size).await {
+        Ok(res) => {
+            state.media_index.upsert_raw_page(&res);
+            Ok(state.trash.filter_raw_page(res))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Probes a clip's URL (first byte only) and its thumbnail and reports status, content
+/// type, size and latency, for "clips won't play" reports. Only available with
+/// `diagnostics_enabled`.
+#[tauri::command]
+async fn diagnose_media(item: MediaItem, state: State<'_, AppState>) -> CommandResult<MediaDiagnosis> {
+    if !state.settings.borrow().diagnostics_enabled {
+        return Err(CommandError::internal("Turn on diagnostics to probe media"));
+    }
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(diagnostics::diagnose_media(&client, &item).await)
+}
+
+/// With `defer_secs`, the clips are only hidden and deleted once that window has passed,
+/// unless `undo_delete` restores them first. More than `delete_batch_size` items are
+/// queued and deleted in the background; every id comes back `pending` and progress
+/// arrives as `delete-progress` events.
+#[tauri::command]
+async fn delete_media_items(items: Vec<serde_json::Value>, defer_secs: Option<u64>, state: State<'_, AppState>) -> CommandResult<Vec<DeleteResult>> {
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    if let Some(secs) = defer_secs.filter(|&secs| secs > 0) {
+        return Ok(state.trash.defer(&items, secs));
+    }
+    if items.len() > state.settings.borrow().delete_batch_size {
+        let (_, results) = state.delete_queue.enqueue(&items);
+        return Ok(results);
+    }
+    let client = shared::snapshot(&state.blink_client).await;
+    let results = client.delete_media_verified(&items).await?;
+    if let Err(e) = state.media_index.mark_deleted(&results) {
+        eprintln!("Failed to mark deleted media in index: {}", e);
+    }
+    Ok(results)
+}
+
+/// Cancels pending deletions; returns the ids that were restored.
+#[tauri::command]
+fn undo_delete(ids: Vec<i64>, state: State<'_, AppState>) -> Vec<i64> {
+    state.trash.undo(&ids)
+}
+
+#[tauri::command]
+fn get_pending_deletions(state: State<'_, AppState>) -> Vec<PendingDeletion> {
+    state.trash.pending()
+}
+
+/// Queued delete jobs and the last few finished ones, oldest first.
+#[tauri::command]
+fn get_delete_queue(state: State<'_, AppState>) -> Vec<DeleteProgress> {
+    state.delete_queue.status()
+}
+
+/// Stops a queued delete; clips not sent yet stay in Blink.
+#[tauri::command]
+fn cancel_delete_job(job_id: String, app: AppHandle, state: State<'_, AppState>) -> CommandResult<DeleteProgress> {
+    let progress = state.delete_queue.cancel(&job_id)
+        .ok_or_else(|| CommandError::new(ErrorKind::NotFound, format!("No running delete job {}", job_id)))?;
+    let _ = app.emit("delete-progress", &progress);
+    Ok(progress)
+}
+
+/// Everything in the configured archive, sorted by key.
+#[tauri::command]
+async fn list_archive(state: State<'_, AppState>) -> CommandResult<Vec<clip_store::StoredClip>> {
+    let config = state.settings.borrow().archive_store()
+        .ok_or_else(|| CommandError::new(ErrorKind::NotFound, "No archive is configured"))?;
+    let mut clips = config.open()?.list().await?;
+    clips.sort_by(|a, b| a.key.cmp(&b.key));
+    Ok(clips)
+}
+
+/// Finished liveviews whose recorded clip is still being deleted.
+#[tauri::command]
+fn get_cleanup_queue(state: State<'_, AppState>) -> Vec<CleanupEntry> {
+    state.cleanup.entries()
+}
+
+#[tauri::command]
+async fn mark_watched(ids: Vec<i64>, state: State<'_, AppState>) -> CommandResult<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let client = shared::snapshot(&state.blink_client).await;
+    client.mark_media_watched(&ids).await?;
+    if let Err(e) = state.media_index.mark_watched(&ids) {
+        eprintln!("Failed to mark watched media in index: {}", e);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn get_liveview_setting_candidates(state: State<'_, AppState>) -> CommandResult<Vec<LiveviewSettingCandidate>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    let json = client.get_homescreen_json().await?;
+    Ok(shared::liveview_candidates(std::slice::from_ref(&*json)))
+}
+
+/// Writes one setting found by `get_liveview_setting_candidates` and reports whether it
+/// stuck: `applied`, `rejected` (Blink refused; see `error`) or `unchanged`. Only
+/// available with `advanced_settings_enabled`.
+#[tauri::command]
+async fn set_discovered_setting(path: String, value: Value, state: State<'_, AppState>) -> CommandResult<DiscoveredSettingResult> {
+    if !state.settings.borrow().advanced_settings_enabled {
+        return Err(CommandError::internal("Turn on advanced settings to change discovered settings"));
+    }
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(shared::set_discovered_setting(&client, &path, value).await?)
+}
+
+/// The known liveview flags (see `liveview_settings::LIVEVIEW_FLAGS`) the homescreen
+/// reports for a network and its devices.
+#[tauri::command]
+async fn get_liveview_settings(network_id: i64, state: State<'_, AppState>) -> CommandResult<Vec<LiveviewSetting>> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(liveview_settings::get_liveview_settings(&client, network_id).await?)
 }
 
-#[derive(Clone, serde::Serialize)]
-struct LiveviewSettingCandidate {
-    path: String,
-    value: Value,
-    network_id: Option<i64>,
-    network_name: Option<String>,
+/// Writes one liveview flag and reports whether it stuck, like `set_discovered_setting`.
+/// `device_id` picks the device for per-device flags.
+#[tauri::command]
+async fn set_liveview_setting(network_id: i64, flag: String, device_id: Option<i64>, value: Value, state: State<'_, AppState>) -> CommandResult<LiveviewSettingWrite> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(liveview_settings::set_liveview_setting(&client, network_id, &flag, device_id, value).await?)
 }
 
-fn is_liveview_candidate_key(key: &str, value: &Value) -> bool {
-    let k = key.to_lowercase();
-    let has_live = k.contains("live") || k.contains("liveview") || k.contains("live_view") || k.contains("lv");
-    let has_save = k.contains("save") || k.contains("record") || k.contains("clip");
-    if !(has_live && has_save) {
-        return false;
+/// Shorthand for `set_liveview_setting` with `lv_save`; true when the change stuck.
+#[tauri::command]
+async fn set_network_liveview_save(network_id: i64, enabled: bool, state: State<'_, AppState>) -> CommandResult<bool> {
+    let client = shared::snapshot(&state.blink_client).await;
+    let write = liveview_settings::set_liveview_setting(&client, network_id, "lv_save", None, Value::Bool(enabled)).await?;
+    match write.error {
+        Some(e) => Err(CommandError::internal(e)),
+        None => Ok(write.outcome == SettingWriteOutcome::Applied),
     }
-    matches!(value, Value::Bool(_) | Value::Number(_) | Value::String(_))
 }
 
-fn scan_liveview_candidates(
-    value: &Value,
-    path: &str,
-    out: &mut Vec<LiveviewSettingCandidate>,
-    network_id: Option<i64>,
-    network_name: Option<String>,
-) {
-    match value {
-        Value::Object(map) => {
-            let mut next_network_id = network_id;
-            let mut next_network_name = network_name.clone();
-
-            if let (Some(id), Some(name), Some(_armed)) = (
-                map.get("id").and_then(|v| v.as_i64()),
-                map.get("name").and_then(|v| v.as_str()),
-                map.get("armed").and_then(|v| v.as_bool()),
-            ) {
-                next_network_id = Some(id);
-                next_network_name = Some(name.to_string());
-            }
+/// Whether motion pushes fire for a network; see `motion_notifications`.
+#[tauri::command]
+async fn get_motion_notifications(network_id: i64, state: State<'_, AppState>) -> CommandResult<MotionNotifications> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(motion_notifications::get_motion_notifications(&client, network_id).await?)
+}
 
-            for (k, v) in map {
-                let next_path = if path.is_empty() {
-                    k.to_string()
-                } else {
-                    format!("{}.{}", path, k)
-                };
-                if is_liveview_candidate_key(k, v) {
-                    out.push(LiveviewSettingCandidate {
-                        path: next_path.clone(),
-                        value: v.clone(),
-                        network_id: next_network_id,
-                        network_name: next_network_name.clone(),
-                    });
-                }
-                scan_liveview_candidates(v, &next_path, out, next_network_id, next_network_name.clone());
-            }
-        }
-        Value::Array(items) => {
-            for (idx, item) in items.iter().enumerate() {
-                let next_path = format!("{}[{}]", path, idx);
-                scan_liveview_candidates(item, &next_path, out, network_id, network_name.clone());
-            }
-        }
-        _ => {}
-    }
+/// Turns motion pushes on or off without touching arming, and reports whether it stuck.
+#[tauri::command]
+async fn set_motion_notifications(network_id: i64, enabled: bool, state: State<'_, AppState>) -> CommandResult<MotionNotificationWrite> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(motion_notifications::set_motion_notifications(&client, network_id, enabled).await?)
 }
 
+/// The sync module's USB drive: present, formatting, full, last backup.
 #[tauri::command]
-async fn get_server_port(state: State<'_, AppState>) -> Result<u16, String> {
-    let port = state.server_port.lock().await;
-    port.ok_or_else(|| "Server not started".to_string())
+async fn get_sync_module_storage(network_id: i64, sync_module_id: i64, state: State<'_, AppState>) -> CommandResult<LocalStorageStatus> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.get_local_storage_status(network_id, sync_module_id).await?)
 }
 
+/// Safely unmounts the sync module's USB drive and waits for Blink's command to finish.
 #[tauri::command]
-async fn check_auth(state: State<'_, AppState>) -> Result<bool, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(saved) = storage::load_auth() {
-        *client = BlinkClient::from_state(saved);
-        return Ok(client.token.is_some());
-    }
-    Ok(false)
+async fn eject_usb(network_id: i64, sync_module_id: i64, state: State<'_, AppState>) -> CommandResult<UsbEjectResult> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.eject_usb(network_id, sync_module_id).await?)
 }
 
 #[tauri::command]
-async fn login(
-    email: String,
-    password: String,
-    state: State<'_, AppState>,
-) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    
-    if let Err(e) = client.start_oauth_flow().await {
-        return Err(format!("OAuth Init Failed: {}", e));
+async fn download_clip(item: MediaItem, path: String, overwrite: Option<bool>, state: State<'_, AppState>) -> CommandResult<()> {
+    let overwrite = overwrite.unwrap_or(false);
+    let path = download_target(&state, &path, overwrite)?;
+    let url = clip_url(&item)?;
+    let (token, full_url, http_client) = {
+        let client = state.blink_client.lock().await;
+        let (token, _account_id) = client.auth()?;
+        (token.clone(), client.resolve_url(&url)?, client.media_client.clone())
+    };
+
+    let full_url = shared::validate_media_url(full_url.as_str())?;
+
+    let res = http_client.get(full_url.clone())
+        .header("Authorization", format!("Bearer {}", token.expose_secret()))
+        .send().await?;
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        return Err(BlinkError::from_response(status, &body, "Download failed", None).into());
     }
 
-    match client.login_oauth(&email, &password).await {
-        Ok(status) => {
-            if status == "SUCCESS" {
-                let _ = storage::save_auth(&client.get_state());
-            }
-            Ok(status)
-        },
-        Err(e) => Err(format!("Login Failed: {}", e)),
+    let bytes = res.bytes().await?;
+    let mut file = download_paths::create_file(std::path::Path::new(&path), overwrite).await?;
+    file.write_all(&bytes).await?;
+    file.flush().await?;
+    finish_clip_download(&state, &item, full_url.as_str(), &path);
+    Ok(())
+}
+
+/// Checks a destination sent by the webview against the allowed download folders.
+fn download_target(state: &AppState, path: &str, overwrite: bool) -> CommandResult<String> {
+    let roots = download_paths::allowed_roots(&state.settings.borrow().download_dirs);
+    Ok(download_paths::prepare_file(path, &roots, overwrite)?.to_string_lossy().to_string())
+}
+
+/// Remembers a folder the user picked in one of our dialogs so downloads may go there.
+fn grant_download_dir(app: &AppHandle, state: &AppState, dir: &std::path::Path) -> CommandResult<()> {
+    let mut granted = state.settings.borrow().download_dirs.clone();
+    if download_paths::is_covered(dir, &download_paths::allowed_roots(&granted)) {
+        return Ok(());
     }
+    granted.push(dir.to_string_lossy().to_string());
+    apply_settings(app, state, serde_json::json!({ "download_dirs": granted }))?;
+    Ok(())
 }
 
+/// Shows the save dialog from here rather than the webview, so the folder the user picks
+/// is granted for downloads. `None` when they cancel. The filter defaults to MP4 video.
 #[tauri::command]
-async fn verify_pin(pin: String, state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    match client.verify_pin_oauth(&pin).await {
-        Ok(_) => {
-            let _ = storage::save_auth(&client.get_state());
-            Ok("SUCCESS".to_string())
-        },
-        Err(e) => Err(format!("Verification Failed: {}", e)),
+async fn choose_download_path(
+    app: AppHandle,
+    default_name: String,
+    filter_name: Option<String>,
+    extensions: Option<Vec<String>>,
+    state: State<'_, AppState>,
+) -> CommandResult<Option<String>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let extensions = extensions.unwrap_or_else(|| vec!["mp4".to_string()]);
+    let extensions: Vec<&str> = extensions.iter().map(String::as_str).collect();
+    app.dialog().file()
+        .set_file_name(default_name)
+        .add_filter(filter_name.as_deref().unwrap_or("Video"), &extensions)
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+    let Some(path) = rx.await.ok().flatten() else { return Ok(None) };
+    let path = path.into_path().map_err(|e| CommandError::internal(e.to_string()))?;
+    if let Some(dir) = path.parent() {
+        grant_download_dir(&app, &state, dir)?;
     }
+    Ok(Some(path.to_string_lossy().to_string()))
 }
 
+/// Folder picker for the download queue; the chosen folder is granted like
+/// `choose_download_path` does.
 #[tauri::command]
-async fn get_cameras(state: State<'_, AppState>) -> Result<Vec<Camera>, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    match client.get_homescreen().await {
-        Ok(res) => Ok(res.cameras),
-        Err(e) => Err(e.to_string()),
+async fn choose_download_dir(app: AppHandle, state: State<'_, AppState>) -> CommandResult<Option<String>> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog().file().pick_folder(move |dir| {
+        let _ = tx.send(dir);
+    });
+    let Some(dir) = rx.await.ok().flatten() else { return Ok(None) };
+    let dir = dir.into_path().map_err(|e| CommandError::internal(e.to_string()))?;
+    grant_download_dir(&app, &state, &dir)?;
+    Ok(Some(dir.to_string_lossy().to_string()))
+}
+
+fn clip_url(item: &MediaItem) -> CommandResult<String> {
+    item.media.clone().ok_or_else(|| CommandError::internal("Item has no media URL"))
+}
+
+/// Records a finished download in the index and stamps it with the clip's metadata.
+fn finish_clip_download(state: &AppState, item: &MediaItem, full_url: &str, path: &str) {
+    let _ = state.media_index.set_local_path(full_url, path);
+    let sidecar = state.settings.borrow().download_sidecar;
+    if let Err(e) = export::apply_clip_metadata(std::path::Path::new(path), item, sidecar) {
+        eprintln!("Failed to write clip metadata for {}: {}", path, e);
     }
 }
 
+/// Suggested file name for a clip, from the `download_filename_template` setting.
 #[tauri::command]
-async fn get_networks(state: State<'_, AppState>) -> Result<Vec<Network>, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
+async fn clip_file_name(item: MediaItem, state: State<'_, AppState>) -> CommandResult<String> {
+    let template = state.settings.borrow().download_filename_template.clone();
+    Ok(format!("{}.mp4", export::file_stem(&item, &template)))
+}
+
+#[derive(Clone, Default, serde::Serialize)]
+struct DownloadProgress {
+    id: String,
+    received: u64,
+    total: Option<u64>,
+    done: bool,
+    cancelled: bool,
+    error: Option<String>,
+}
+
+type DownloadTokens = std::sync::Mutex<HashMap<String, CancellationToken>>;
+
+/// Streams `full_url` into `path`, stopping early if `cancel` fires.
+/// Returns `Ok(false)` when cancelled.
+#[allow(clippy::too_many_arguments)]
+async fn stream_download(
+    progress: &(dyn Fn(DownloadProgress) + Sync),
+    http_client: reqwest::Client,
+    token: &SecretString,
+    full_url: reqwest::Url,
+    path: &str,
+    overwrite: bool,
+    download_id: &str,
+    cancel: &CancellationToken,
+) -> CommandResult<bool> {
+    let res = tokio::select! {
+        _ = cancel.cancelled() => return Ok(false),
+        res = http_client.get(full_url).header("Authorization", format!("Bearer {}", token.expose_secret())).send() => {
+            res?
+        }
+    };
+
+    if !res.status().is_success() {
+        let status = res.status();
+        let body = res.text().await.unwrap_or_default();
+        return Err(BlinkError::from_response(status, &body, "Download failed", None).into());
     }
-    match client.get_homescreen().await {
-        Ok(res) => Ok(res.networks),
-        Err(e) => Err(e.to_string()),
+
+    let total = res.content_length();
+    let mut received: u64 = 0;
+    let mut file = download_paths::create_file(std::path::Path::new(path), overwrite).await?;
+    let mut stream = res.bytes_stream();
+
+    loop {
+        let chunk = tokio::select! {
+            _ = cancel.cancelled() => return Ok(false),
+            chunk = stream.next() => chunk,
+        };
+        let Some(chunk) = chunk else { break };
+        let chunk = chunk?;
+        file.write_all(&chunk).await?;
+        received += chunk.len() as u64;
+        progress(DownloadProgress {
+            id: download_id.to_string(),
+            received,
+            total,
+            ..Default::default()
+        });
     }
+    file.flush().await?;
+
+    Ok(true)
 }
 
+/// Returns false if the download was cancelled via `cancel_download`.
 #[tauri::command]
-async fn get_thumbnail_base64(path: String, state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    match client.get_thumbnail(&path).await {
-        Ok(bytes) => Ok(format!("data:image/jpeg;base64,{}", base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes))),
-        Err(e) => Err(e.to_string()),
+async fn download_clip_with_progress(
+    window: Window,
+    item: MediaItem,
+    path: String,
+    download_id: String,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> CommandResult<bool> {
+    let overwrite = overwrite.unwrap_or(false);
+    let path = download_target(&state, &path, overwrite)?;
+    let url = clip_url(&item)?;
+    let (token, full_url, http_client) = {
+        let client = state.blink_client.lock().await;
+        let (token, _account_id) = client.auth()?;
+        (token.clone(), client.resolve_url(&url)?, client.media_client.clone())
+    };
+
+    let full_url = shared::validate_media_url(full_url.as_str())?;
+
+    let progress = |event: DownloadProgress| {
+        let _ = window.emit("download-progress", event);
+    };
+    let result = run_download(&state.downloads, &progress, http_client, &token, full_url.clone(), &path, overwrite, download_id).await;
+    if let Ok(true) = result {
+        finish_clip_download(&state, &item, full_url.as_str(), &path);
     }
+    result
 }
 
-#[tauri::command]
-async fn set_network_arm(network_id: i64, arm: bool, state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
+/// Registers the download under `download_id` for `cancel_download`, streams it, and
+/// reports how it ended in a last progress event. A cancelled or failed download is
+/// removed unless the file was already there.
+#[allow(clippy::too_many_arguments)]
+async fn run_download(
+    downloads: &DownloadTokens,
+    progress: &(dyn Fn(DownloadProgress) + Sync),
+    http_client: reqwest::Client,
+    token: &SecretString,
+    full_url: reqwest::Url,
+    path: &str,
+    overwrite: bool,
+    download_id: String,
+) -> CommandResult<bool> {
+    let cancel = CancellationToken::new();
+    downloads.lock().unwrap().insert(download_id.clone(), cancel.clone());
+    let result = stream_download(progress, http_client, token, full_url, path, overwrite, &download_id, &cancel).await;
+    downloads.lock().unwrap().remove(&download_id);
+
+    let mut last = DownloadProgress { id: download_id, ..Default::default() };
+    match &result {
+        Ok(true) => last.done = true,
+        Ok(false) => last.cancelled = true,
+        Err(e) => last.error = Some(e.message.clone()),
     }
-    match client.set_arm(network_id, arm).await {
-        Ok(_) => Ok("Success".to_string()),
-        Err(e) => Err(e.to_string()),
+    let collided = matches!(&result, Err(e) if e.kind == ErrorKind::AlreadyExists);
+    if !last.done && !collided {
+        // Don't leave a truncated clip behind at the destination.
+        let _ = tokio::fs::remove_file(path).await;
     }
+    progress(last);
+    result
 }
 
-#[tauri::command]
-async fn logout(state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    client.token = None;
-    client.refresh_token = None;
-    client.account_id = None;
-    client.token_expiry = None;
-    
-    if let Err(e) = storage::clear_auth() {
-        return Err(format!("Failed to clear auth: {}", e));
+/// Fires the token of a running download; false when there is none.
+fn cancel_registered(downloads: &DownloadTokens, download_id: &str) -> bool {
+    match downloads.lock().unwrap().get(download_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
     }
-    
-    Ok("Logged out successfully".to_string())
 }
 
+/// Cancels an in-flight download. Returns false if it already finished or never existed.
 #[tauri::command]
-async fn get_raw_homescreen(state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    match client.get_raw_homescreen().await {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string()),
-    }
+async fn cancel_download(download_id: String, state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(cancel_registered(&state.downloads, &download_id))
 }
 
 #[tauri::command]
-async fn get_raw_media(state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    match client.get_raw_media().await {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string()),
+async fn enqueue_downloads(app: AppHandle, mut items: Vec<DownloadRequest>, state: State<'_, AppState>) -> CommandResult<Vec<String>> {
+    let settings = state.settings.borrow().clone();
+    let roots = download_paths::allowed_roots(&settings.download_dirs);
+    for req in &mut items {
+        req.dir = download_paths::prepare_dir(&req.dir, &roots)?.to_string_lossy().to_string();
     }
+    Ok(state.download_manager.enqueue(&app, items, &settings))
 }
 
 #[tauri::command]
-async fn get_raw_media_page(page: i64, since_days: i64, state: State<'_, AppState>) -> Result<String, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    match client.get_raw_media_page(page, since_days).await {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string()),
-    }
+async fn get_download_jobs(state: State<'_, AppState>) -> CommandResult<Vec<DownloadJob>> {
+    Ok(state.download_manager.jobs())
 }
 
-#[cfg(debug_assertions)]
-#[derive(serde::Serialize)]
-struct ProbeResult {
-    url: String,
-    status: u16,
-    content_type: Option<String>,
-    content_length: Option<u64>,
-    final_url: Option<String>,
+#[tauri::command]
+async fn clear_download_jobs(app: AppHandle, state: State<'_, AppState>) -> CommandResult<()> {
+    state.download_manager.clear_finished(&app);
+    Ok(())
 }
 
-#[cfg(debug_assertions)]
 #[tauri::command]
-async fn probe_media_url(url: String, kind: String, state: State<'_, AppState>) -> Result<ProbeResult, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    let token = client.token().map_err(|e| e.to_string())?;
-    let base_url = client.base_url.clone();
-    let full_url = if url.starts_with("http") { url } else { format!("{}{}", base_url, url) };
-
-    let parsed = Url::parse(&full_url).map_err(|e| e.to_string())?;
-    let host = parsed.host_str().ok_or_else(|| "Invalid URL host".to_string())?;
-    if !host.ends_with(".immedia-semi.com")
-        && !host.ends_with(".blinkforhome.com")
-        && !host.ends_with(".blink.com")
-        && !host.ends_with(".amazonaws.com")
-        && !host.ends_with(".cloudfront.net")
-    {
-        return Err(format!("Invalid URL host: {}", host));
-    }
-
-    let mut req = client.client.get(&full_url)
-        .header("Authorization", format!("Bearer {}", token));
-    if kind == "media" {
-        req = req.header(header::RANGE, "bytes=0-0");
-    } else {
-        req = req.header(header::ACCEPT, "image/*");
-    }
+async fn pause_downloads(app: AppHandle, state: State<'_, AppState>) -> CommandResult<()> {
+    state.download_manager.pause(&app);
+    Ok(())
+}
+
+#[tauri::command]
+async fn resume_downloads(app: AppHandle, state: State<'_, AppState>) -> CommandResult<()> {
+    state.download_manager.resume(&app);
+    Ok(())
+}
 
-    let res = req.send().await.map_err(|e| e.to_string())?;
-    let status = res.status().as_u16();
-    let content_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(|v| v.to_string());
-    let content_length = res.headers().get(header::CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok());
-    let final_url = res.url().as_str().to_string();
+#[tauri::command]
+async fn cancel_download_job(app: AppHandle, job_id: String, state: State<'_, AppState>) -> CommandResult<bool> {
+    Ok(state.download_manager.cancel(&app, &job_id))
+}
 
-    Ok(ProbeResult {
-        url: full_url,
-        status,
-        content_type,
-        content_length,
-        final_url: Some(final_url),
-    })
+#[tauri::command]
+async fn set_download_concurrency(app: AppHandle, limit: usize, state: State<'_, AppState>) -> CommandResult<()> {
+    apply_settings(&app, &state, serde_json::json!({ "download_concurrency": limit }))?;
+    Ok(())
 }
 
+/// Writes the given media items and a `manifest.json` into a ZIP at `dest_path`, which
+/// must be in an allowed download folder, emitting `export-progress` events as each clip
+/// streams in.
 #[tauri::command]
-async fn delete_media_items(items: Vec<serde_json::Value>, state: State<'_, AppState>) -> Result<Vec<i64>, String> {
+async fn export_clips_zip(
+    window: Window,
+    items: Vec<MediaItem>,
+    dest_path: String,
+    compression: Option<ExportCompression>,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> CommandResult<ExportSummary> {
     if items.is_empty() {
-        return Ok(Vec::new());
+        return Err(CommandError::internal("No items to export"));
     }
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    let media_ids: Vec<i64> = items.iter()
-        .filter_map(|item| item.get("id").and_then(|v| v.as_i64()))
-        .collect();
-
-    let delete_result = client.delete_media(media_ids.clone()).await;
-
-    // Try richer payloads if the basic delete fails
-    if delete_result.is_err() {
-        let mut entries: Vec<serde_json::Value> = Vec::new();
-        for item in &items {
-            if let Some(obj) = item.as_object() {
-                let mut map = obj.clone();
-                if let Some(id_val) = map.get("id").cloned() {
-                    if !map.contains_key("media_id") {
-                        map.insert("media_id".to_string(), id_val.clone());
-                    }
-                    if let Some(id) = id_val.as_i64() {
-                        map.insert("media_id".to_string(), serde_json::json!(id.to_string()));
-                    }
-                }
-                entries.push(serde_json::Value::Object(map));
-            } else {
-                entries.push(item.clone());
-            }
-        }
-        let _ = client.delete_media_with_payloads(media_ids.clone(), entries).await;
-    }
-
-    // Give Blink a moment to process
-    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-    let raw = client.get_raw_media().await.map_err(|e| e.to_string())?;
-    let parsed: serde_json::Value = serde_json::from_str(&raw).map_err(|e| e.to_string())?;
-    let mut remaining = Vec::new();
-    if let Some(items) = parsed.get("media").and_then(|v| v.as_array()) {
-        let remaining_ids: std::collections::HashSet<i64> = items.iter()
-            .filter_map(|item| item.get("id").and_then(|v| v.as_i64()))
-            .collect();
-        for id in media_ids {
-            if remaining_ids.contains(&id) {
-                remaining.push(id);
-            }
-        }
+    let overwrite = overwrite.unwrap_or(false);
+    let dest_path = download_target(&state, &dest_path, overwrite)?;
+    let template = state.settings.borrow().download_filename_template.clone();
+    let file = download_paths::create_file(std::path::Path::new(&dest_path), overwrite).await?;
+    let result = export::write_clips_zip(
+        &state.blink_client,
+        &items,
+        &template,
+        compression.unwrap_or_default(),
+        tokio::io::BufWriter::new(file),
+        |progress| {
+            let _ = window.emit("export-progress", progress.clone());
+        },
+    ).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&dest_path).await;
     }
+    Ok(result?)
+}
 
-    if remaining.is_empty() {
-        return Ok(remaining);
-    }
+#[tauri::command]
+async fn get_motion_alerts(state: State<'_, AppState>) -> CommandResult<MotionAlertState> {
+    Ok(state.motion_poller.config(&state.settings.borrow()))
+}
 
-    if let Err(e) = delete_result {
-        return Err(e.to_string());
+#[tauri::command]
+async fn set_motion_alerts(app: AppHandle, enabled: bool, interval_secs: Option<u64>, state: State<'_, AppState>) -> CommandResult<MotionAlertState> {
+    let mut patch = serde_json::json!({ "motion_alerts_enabled": enabled });
+    if let Some(secs) = interval_secs {
+        patch["motion_interval_secs"] = secs.into();
     }
+    let settings = apply_settings(&app, &state, patch)?;
+    Ok(state.motion_poller.config(&settings))
+}
 
-    Ok(remaining)
+/// Persists a partial settings update, then notifies background tasks through the watch
+/// channel and the frontend through `settings-changed`.
+fn apply_settings(app: &AppHandle, state: &AppState, patch: Value) -> CommandResult<Settings> {
+    let updated = state.settings.borrow().with_patch(patch)?;
+    updated.save(&storage::settings_path())
+        .map_err(|e| CommandError::internal(format!("Failed to save settings: {}", e)))?;
+    state.settings.send_replace(updated.clone());
+    let _ = app.emit("settings-changed", &updated);
+    Ok(updated)
 }
 
 #[tauri::command]
-async fn get_liveview_setting_candidates(state: State<'_, AppState>) -> Result<Vec<LiveviewSettingCandidate>, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    let raw = client.get_raw_homescreen().await.map_err(|e| e.to_string())?;
-    let json: Value = serde_json::from_str(&raw).map_err(|e| e.to_string())?;
-    let mut candidates = Vec::new();
-    scan_liveview_candidates(&json, "", &mut candidates, None, None);
-    Ok(candidates)
+async fn get_camera_prefs(state: State<'_, AppState>) -> CommandResult<BTreeMap<i64, CameraPrefs>> {
+    Ok(state.settings.borrow().camera_prefs.clone())
 }
 
+/// Replaces the preferences for one camera; passing the defaults removes the entry.
 #[tauri::command]
-async fn set_network_liveview_save(network_id: i64, enabled: bool, state: State<'_, AppState>) -> Result<bool, String> {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = storage::save_auth(&client.get_state());
-    }
-    client.set_network_liveview_save(network_id, enabled).await.map_err(|e| e.to_string())?;
-
-    let raw = client.get_raw_homescreen().await.map_err(|e| e.to_string())?;
-    let json: Value = serde_json::from_str(&raw).map_err(|e| e.to_string())?;
-    let networks = json.get("networks").and_then(|v| v.as_array()).ok_or_else(|| "No networks in response".to_string())?;
-    for net in networks {
-        let id = net.get("id").and_then(|v| v.as_i64());
-        if id == Some(network_id) {
-            let lv_save = net.get("lv_save");
-            let matches = match lv_save {
-                Some(Value::Bool(b)) => *b == enabled,
-                Some(Value::Number(n)) => n.as_i64().map(|v| v != 0).unwrap_or(false) == enabled,
-                _ => false,
-            };
-            return Ok(matches);
-        }
-    }
-    Err("Network not found in response".to_string())
+async fn set_camera_prefs(app: AppHandle, camera_id: i64, prefs: CameraPrefs, state: State<'_, AppState>) -> CommandResult<BTreeMap<i64, CameraPrefs>> {
+    let mut all = state.settings.borrow().camera_prefs.clone();
+    all.insert(camera_id, prefs);
+    Ok(apply_settings(&app, &state, serde_json::json!({ "camera_prefs": all }))?.camera_prefs)
 }
 
+/// Newest first; `limit` defaults to `audit::DEFAULT_LIMIT`.
 #[tauri::command]
-async fn download_clip(url: String, path: String, state: State<'_, AppState>) -> Result<(), String> {
-    let (token, base_url, http_client) = {
-        let client = state.blink_client.lock().await;
-        let (token, _account_id) = client.auth().map_err(|e: anyhow::Error| e.to_string())?;
-        (token.to_string(), client.base_url.clone(), client.client.clone())
-    };
+async fn get_audit_log(limit: Option<usize>, filter: Option<AuditFilter>) -> CommandResult<Vec<AuditEntry>> {
+    Ok(audit::read(limit, &filter.unwrap_or_default())?)
+}
 
-    let full_url = if url.starts_with("http") {
-        url
-    } else {
-        format!("{}{}", base_url, url)
-    };
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> CommandResult<Settings> {
+    Ok(state.settings.borrow().clone())
+}
 
-    let parsed = Url::parse(&full_url).map_err(|e| e.to_string())?;
-    let host = parsed.host_str().ok_or_else(|| "Invalid URL host".to_string())?;
-    if !host.ends_with(".immedia-semi.com")
-        && !host.ends_with(".blinkforhome.com")
-        && !host.ends_with(".blink.com")
-        && !host.ends_with(".amazonaws.com")
-        && !host.ends_with(".cloudfront.net")
-    {
-        return Err(format!("Invalid URL host: {}", host));
+#[tauri::command]
+async fn update_settings(app: AppHandle, mut patch: Value, state: State<'_, AppState>) -> CommandResult<Settings> {
+    // Download folders are granted through `choose_download_path`/`choose_download_dir`;
+    // the webview may only take them away.
+    if let Some(dirs) = patch.get("download_dirs") {
+        let requested: Vec<String> = serde_json::from_value(dirs.clone())
+            .map_err(|e| CommandError::new(ErrorKind::InvalidInput, format!("Invalid download_dirs: {}", e)))?;
+        let granted = state.settings.borrow().download_dirs.clone();
+        if let Some(dir) = requested.iter().find(|dir| !granted.contains(dir)) {
+            return Err(CommandError::new(ErrorKind::PathNotAllowed, format!("{} can only be added by choosing it in a download dialog", dir)));
+        }
     }
-
-    let res = http_client.get(&full_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .send().await.map_err(|e| e.to_string())?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let body = res.text().await.unwrap_or_default();
-        return Err(format!("Download failed: {} {}", status, body));
+    // Folders the app writes to on its own must be allowed download folders too.
+    let roots = download_paths::allowed_roots(&state.settings.borrow().download_dirs);
+    for key in ["archive_dir", "liveview_recording_dir"] {
+        let Some(dir) = patch.get(key).and_then(|v| v.as_str()).map(str::trim).filter(|d| !d.is_empty()) else { continue };
+        let dir = download_paths::prepare_dir(dir, &roots)?;
+        patch[key] = dir.to_string_lossy().into();
     }
-
-    let bytes = res.bytes().await.map_err(|e| e.to_string())?;
-    tokio::fs::write(&path, bytes).await.map_err(|e| e.to_string())?;
-    Ok(())
-}
-
-#[derive(Clone, serde::Serialize)]
-struct DownloadProgress {
-    id: String,
-    received: u64,
-    total: Option<u64>,
+    if patch["archive_store"]["kind"] == "local" {
+        if let Some(dir) = patch["archive_store"]["dir"].as_str() {
+            let dir = download_paths::prepare_dir(dir.trim(), &roots)?;
+            patch["archive_store"]["dir"] = dir.to_string_lossy().into();
+        }
+    }
+    apply_settings(&app, &state, patch)
 }
 
+/// Writes an encrypted archive of the session, settings and optionally the media index,
+/// for `import_app_state` on another machine. `dest` must be in an allowed download folder.
 #[tauri::command]
-async fn download_clip_with_progress(
-    window: Window,
-    url: String,
-    path: String,
-    download_id: String,
+async fn export_app_state(
+    dest: String,
+    passphrase: String,
+    include_media_index: bool,
+    overwrite: Option<bool>,
     state: State<'_, AppState>,
-) -> Result<(), String> {
-    let (token, base_url, http_client) = {
+) -> CommandResult<StateExportSummary> {
+    let dest = download_target(&state, &dest, overwrite.unwrap_or(false))?;
+    let auth = {
         let client = state.blink_client.lock().await;
-        let (token, _account_id) = client.auth().map_err(|e: anyhow::Error| e.to_string())?;
-        (token.to_string(), client.base_url.clone(), client.client.clone())
-    };
-
-    let full_url = if url.starts_with("http") {
-        url
-    } else {
-        format!("{}{}", base_url, url)
+        client.token.is_some().then(|| client.get_state())
     };
+    let settings = state.settings.borrow().clone();
+    let index = include_media_index.then_some(state.media_index.as_ref());
+    Ok(backup::export(std::path::Path::new(&dest), &passphrase, auth, &settings, index)?)
+}
 
-    let parsed = Url::parse(&full_url).map_err(|e| e.to_string())?;
-    let host = parsed.host_str().ok_or_else(|| "Invalid URL host".to_string())?;
-    if !host.ends_with(".immedia-semi.com")
-        && !host.ends_with(".blinkforhome.com")
-        && !host.ends_with(".blink.com")
-        && !host.ends_with(".amazonaws.com")
-        && !host.ends_with(".cloudfront.net")
-    {
-        return Err(format!("Invalid URL host: {}", host));
+/// Restores an archive and loads it into the running app: the session replaces the
+/// current one and the settings take effect at once. Folders in the archive only carry
+/// over where this machine has granted them (see `download_paths::confine_imported`).
+#[tauri::command]
+async fn import_app_state(app: AppHandle, src: String, passphrase: String, state: State<'_, AppState>) -> CommandResult<ImportSummary> {
+    let mut archive = backup::read(std::path::Path::new(&src), &passphrase)?;
+    let granted = state.settings.borrow().download_dirs.clone();
+    let dropped = download_paths::confine_imported(&mut archive.settings, &granted);
+    let mut summary = archive.restore(&storage::settings_path(), &state.media_index, storage::save_auth)?;
+    summary.dropped_folders = dropped;
+    if let Some(auth) = archive.auth {
+        let mut client = state.blink_client.lock().await;
+        *client = BlinkClient::from_state(auth);
+        if client.token.is_some() {
+            error::auth_restored();
+        }
     }
+    state.settings.send_replace(archive.settings.clone());
+    let _ = app.emit("settings-changed", &archive.settings);
+    Ok(summary)
+}
 
-    let res = http_client.get(&full_url)
-        .header("Authorization", format!("Bearer {}", token))
-        .send().await.map_err(|e| e.to_string())?;
-
-    if !res.status().is_success() {
-        let status = res.status();
-        let body = res.text().await.unwrap_or_default();
-        return Err(format!("Download failed: {} {}", status, body));
-    }
+#[tauri::command]
+async fn get_camera_config(network_id: i64, camera_id: i64, product_type: String, state: State<'_, AppState>) -> CommandResult<serde_json::Value> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.get_camera_config(network_id, camera_id, &product_type).await?)
+}
 
-    let total = res.content_length();
-    let mut received: u64 = 0;
-    let mut file = tokio::fs::File::create(&path).await.map_err(|e| e.to_string())?;
-    let mut stream = res.bytes_stream();
+/// Checked against `camera_config` first; unknown keys are refused unless `allow_unknown`.
+#[tauri::command]
+async fn update_camera_config(network_id: i64, camera_id: i64, product_type: String, config: serde_json::Value, allow_unknown: Option<bool>, state: State<'_, AppState>) -> CommandResult<()> {
+    camera_config::validate(&product_type, &config, allow_unknown.unwrap_or(false))?;
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.update_camera_config(network_id, camera_id, &product_type, config).await?)
+}
 
-    while let Some(chunk) = stream.next().await {
-        let chunk = chunk.map_err(|e| e.to_string())?;
-        file.write_all(&chunk).await.map_err(|e| e.to_string())?;
-        received += chunk.len() as u64;
-        let _ = window.emit("download-progress", DownloadProgress {
-            id: download_id.clone(),
-            received,
-            total,
-        });
-    }
+#[tauri::command]
+async fn get_network_settings(network_id: i64, state: State<'_, AppState>) -> CommandResult<NetworkSettings> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.get_network_settings(network_id).await?)
+}
 
-    Ok(())
+/// Applies the given fields and returns the network's settings afterwards.
+#[tauri::command]
+async fn update_network_settings(network_id: i64, patch: NetworkSettingsPatch, state: State<'_, AppState>) -> CommandResult<NetworkSettings> {
+    patch.validate()?;
+    let client = shared::snapshot(&state.blink_client).await;
+    client.update_network_settings(network_id, &patch).await?;
+    Ok(client.get_network_settings(network_id).await?)
 }
+
 #[tauri::command]
-async fn get_camera_config(network_id: i64, camera_id: i64, product_type: String, state: State<'_, AppState>) -> Result<serde_json::Value, String> {
-    let client = state.blink_client.lock().await;
-    client.get_camera_config(network_id, camera_id, &product_type).await.map_err(|e| e.to_string())
+async fn get_notification_config(state: State<'_, AppState>) -> CommandResult<NotificationConfig> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.get_notification_config().await?)
 }
 
+/// Sends the full configuration back, as the official app does.
 #[tauri::command]
-async fn update_camera_config(network_id: i64, camera_id: i64, product_type: String, config: serde_json::Value, state: State<'_, AppState>) -> Result<(), String> {
-    let client = state.blink_client.lock().await;
-    client.update_camera_config(network_id, camera_id, &product_type, config).await.map_err(|e| e.to_string())
+async fn update_notification_config(config: NotificationConfig, state: State<'_, AppState>) -> CommandResult<()> {
+    let client = shared::snapshot(&state.blink_client).await;
+    Ok(client.update_notification_config(&config).await?)
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 #[cfg(debug_assertions)]
 pub fn run() {
+    audit::init(storage::get_config_dir().join(audit::AUDIT_FILE), "tauri");
+    device_routes::init(storage::get_config_dir().join(device_routes::DEVICE_ROUTES_FILE));
+    clock_skew::init(storage::get_config_dir().join(clock_skew::CLOCK_SKEW_FILE));
+    shared::init_auth_persistence(storage::save_auth);
     let blink_client = Arc::new(Mutex::new(BlinkClient::new()));
+    let live_sessions = Arc::new(SessionRegistry::default());
+    let motion_poller = Arc::new(MotionPoller::load());
+    let scheduler = Arc::new(Scheduler::load(storage::get_config_dir().join("schedule_state.json")));
+    let (settings_tx, settings_rx) = watch::channel(storage::load_settings());
+    let media_index = Arc::new(MediaIndex::new(storage::get_config_dir().join(media_index::INDEX_FILE), settings_rx.borrow().media_index_enabled));
+    let trash = Arc::new(Trash::load(storage::get_config_dir().join(trash::TRASH_FILE)));
+    let delete_queue = Arc::new(DeleteQueue::load(storage::get_config_dir().join(delete_queue::DELETE_QUEUE_FILE)));
+    let firmware = Arc::new(FirmwareTracker::load(storage::get_config_dir().join(firmware::FIRMWARE_FILE)));
+    let cleanup = Arc::new(CleanupQueue::load(storage::get_config_dir().join(cleanup::CLEANUP_FILE)));
+    let rearm = Arc::new(RearmQueue::load(storage::get_config_dir().join(rearm::REARM_FILE)));
+    let battery = Arc::new(BatteryStore::new(storage::get_config_dir().join(battery::BATTERY_FILE)));
+    let thumbnail_refresher = Arc::new(ThumbnailRefresher::new());
+    let download_manager = Arc::new(DownloadManager::new(blink_client.clone(), media_index.clone(), settings_rx.borrow().download_concurrency));
+    let signer = Arc::new(UrlSigner::random());
+    let fetch_permits = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+    let thumbnails = Arc::new(ThumbnailCache::load());
+    let server_health = Arc::new(server::ServerHealth::default());
 
     tauri::Builder::default()
         .manage(AppState {
             blink_client: blink_client.clone(),
-            server_port: Mutex::new(None),
+            server: server_health.clone(),
+            signer: signer.clone(),
+            live_sessions: live_sessions.clone(),
+            downloads: std::sync::Mutex::new(HashMap::new()),
+            download_manager: download_manager.clone(),
+            motion_poller: motion_poller.clone(),
+            scheduler: scheduler.clone(),
+            media_index: media_index.clone(),
+            trash: trash.clone(),
+            delete_queue: delete_queue.clone(),
+            firmware: firmware.clone(),
+            cleanup: cleanup.clone(),
+            rearm: rearm.clone(),
+            battery: battery.clone(),
+            settings: settings_tx,
+            tray: TrayState::default(),
+            thumbnails: thumbnails.clone(),
+            fetch_permits: fetch_permits.clone(),
         })
         .setup(move |app| {
             let handle = app.handle().clone();
+            error::init(&handle);
             let blink_client_clone = blink_client.clone();
+            let live_sessions_clone = live_sessions.clone();
+            let settings_rx_clone = settings_rx.clone();
+
+            tauri::async_runtime::spawn(motion_poller.clone().run(handle.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(download_manager.clone().follow_settings(handle.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_schedules(handle.clone(), scheduler.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_media_index(media_index.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_trash(trash.clone(), blink_client.clone(), media_index.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_delete_queue(handle.clone(), delete_queue.clone(), blink_client.clone(), media_index.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_cleanup(cleanup.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_rearm(handle.clone(), rearm.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_firmware(handle.clone(), firmware.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_battery(battery.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_live_stats(handle.clone(), live_sessions.clone()));
+            let expiring_handle = handle.clone();
+            liveview::on_expiring(move |expiring| {
+                let _ = expiring_handle.emit("live-expiring", expiring);
+            });
+            tauri::async_runtime::spawn(drive_thumbnail_refresh(handle.clone(), thumbnail_refresher.clone(), blink_client.clone(), settings_rx.clone()));
+            tray::init(&handle)?;
             
             tauri::async_runtime::spawn(async move {
                 let server_state = Arc::new(server::ServerState {
                     blink_client: blink_client_clone,
+                    live_sessions: live_sessions_clone,
+                    settings: settings_rx_clone,
+                    signer,
+                    cleanup: cleanup.clone(),
+                    fetch_permits,
+                    thumbnails,
                 });
-                let port = server::start_server(server_state).await;
-                
-                let state = handle.state::<AppState>();
-                let mut server_port = state.server_port.lock().await;
-                *server_port = Some(port);
+                let server_handle = handle.clone();
+                server::supervise(server_state, server_health.clone(), move |port| {
+                    let _ = server_handle.emit("server-restarted", port);
+                }).await;
             });
 
             Ok(())
         })
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                if window.state::<AppState>().settings.borrow().close_to_tray {
+                    api.prevent_close();
+                    let _ = window.hide();
+                }
+            }
+        })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             check_auth,
+            reload_auth,
             login,
             verify_pin,
+            get_amazon_signin_url,
+            login_amazon,
+            complete_amazon_login,
             logout,
+            list_trusted_clients,
+            revoke_trusted_client,
             get_cameras,
             get_networks,
             get_thumbnail_base64,
+            get_thumbnail,
+            prefetch_thumbnails,
+            get_thumbnail_url,
+            get_clip_url,
+            get_thumbnail_file,
+            get_thumbnail_cache_stats,
+            clear_thumbnail_cache,
+            refresh_camera_thumbnail,
             set_network_arm,
+            disarm_for,
+            get_pending_rearms,
+            extend_rearm,
+            cancel_rearm,
+            set_all_networks_arm,
+            get_firmware_report,
+            get_usage,
+            get_battery_history,
+            get_activity_heatmap,
+            run_self_test,
+            set_log_level,
+            start_debug_capture,
             get_raw_homescreen,
             get_raw_media,
             get_raw_media_page,
-            probe_media_url,
+            diagnose_media,
             delete_media_items,
+            undo_delete,
+            get_pending_deletions,
+            get_delete_queue,
+            cancel_delete_job,
+            list_archive,
+            get_cleanup_queue,
+            mark_watched,
+            search_local_media,
+            get_timeline,
+            rebuild_media_index,
             get_liveview_setting_candidates,
+            set_discovered_setting,
             set_network_liveview_save,
+            get_motion_notifications,
+            set_motion_notifications,
+            get_sync_module_storage,
+            eject_usb,
+            get_liveview_settings,
+            set_liveview_setting,
             get_server_port,
+            get_server_status,
+            restart_server,
             download_clip,
             download_clip_with_progress,
+            choose_download_path,
+            choose_download_dir,
+            clip_file_name,
+            cancel_download,
+            enqueue_downloads,
+            get_download_jobs,
+            clear_download_jobs,
+            pause_downloads,
+            resume_downloads,
+            cancel_download_job,
+            set_download_concurrency,
+            export_clips_zip,
+            get_motion_alerts,
+            set_motion_alerts,
+            get_settings,
+            update_settings,
+            export_app_state,
+            import_app_state,
+            get_audit_log,
+            get_camera_prefs,
+            set_camera_prefs,
+            get_close_to_tray,
+            set_close_to_tray,
+            get_schedules,
+            save_schedule_entry,
+            delete_schedule_entry,
             get_camera_config,
-            update_camera_config
+            update_camera_config,
+            get_network_settings,
+            update_network_settings,
+            get_notification_config,
+            update_notification_config,
+            list_live_sessions,
+            stop_live_session,
+            save_replay,
+            list_recordings,
+            start_liveview,
+            stop_liveview
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                tray::teardown(app);
+            }
+        });
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 #[cfg(not(debug_assertions))]
 pub fn run() {
+    audit::init(storage::get_config_dir().join(audit::AUDIT_FILE), "tauri");
+    device_routes::init(storage::get_config_dir().join(device_routes::DEVICE_ROUTES_FILE));
+    clock_skew::init(storage::get_config_dir().join(clock_skew::CLOCK_SKEW_FILE));
+    shared::init_auth_persistence(storage::save_auth);
     let blink_client = Arc::new(Mutex::new(BlinkClient::new()));
+    let live_sessions = Arc::new(SessionRegistry::default());
+    let motion_poller = Arc::new(MotionPoller::load());
+    let scheduler = Arc::new(Scheduler::load(storage::get_config_dir().join("schedule_state.json")));
+    let (settings_tx, settings_rx) = watch::channel(storage::load_settings());
+    let media_index = Arc::new(MediaIndex::new(storage::get_config_dir().join(media_index::INDEX_FILE), settings_rx.borrow().media_index_enabled));
+    let trash = Arc::new(Trash::load(storage::get_config_dir().join(trash::TRASH_FILE)));
+    let delete_queue = Arc::new(DeleteQueue::load(storage::get_config_dir().join(delete_queue::DELETE_QUEUE_FILE)));
+    let firmware = Arc::new(FirmwareTracker::load(storage::get_config_dir().join(firmware::FIRMWARE_FILE)));
+    let cleanup = Arc::new(CleanupQueue::load(storage::get_config_dir().join(cleanup::CLEANUP_FILE)));
+    let rearm = Arc::new(RearmQueue::load(storage::get_config_dir().join(rearm::REARM_FILE)));
+    let battery = Arc::new(BatteryStore::new(storage::get_config_dir().join(battery::BATTERY_FILE)));
+    let thumbnail_refresher = Arc::new(ThumbnailRefresher::new());
+    let download_manager = Arc::new(DownloadManager::new(blink_client.clone(), media_index.clone(), settings_rx.borrow().download_concurrency));
+    let signer = Arc::new(UrlSigner::random());
+    let fetch_permits = Arc::new(Semaphore::new(MAX_CONCURRENT_FETCHES));
+    let thumbnails = Arc::new(ThumbnailCache::load());
+    let server_health = Arc::new(server::ServerHealth::default());
 
     tauri::Builder::default()
         .manage(AppState {
             blink_client: blink_client.clone(),
-            server_port: Mutex::new(None),
+            server: server_health.clone(),
+            signer: signer.clone(),
+            live_sessions: live_sessions.clone(),
+            downloads: std::sync::Mutex::new(HashMap::new()),
+            download_manager: download_manager.clone(),
+            motion_poller: motion_poller.clone(),
+            scheduler: scheduler.clone(),
+            media_index: media_index.clone(),
+            trash: trash.clone(),
+            delete_queue: delete_queue.clone(),
+            firmware: firmware.clone(),
+            cleanup: cleanup.clone(),
+            rearm: rearm.clone(),
+            battery: battery.clone(),
+            settings: settings_tx,
+            tray: TrayState::default(),
+            thumbnails: thumbnails.clone(),
+            fetch_permits: fetch_permits.clone(),
         })
         .setup(move |app| {
             let handle = app.handle().clone();
+            error::init(&handle);
             let blink_client_clone = blink_client.clone();
+            let live_sessions_clone = live_sessions.clone();
+            let settings_rx_clone = settings_rx.clone();
+
+            tauri::async_runtime::spawn(motion_poller.clone().run(handle.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(download_manager.clone().follow_settings(handle.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_schedules(handle.clone(), scheduler.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_media_index(media_index.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_trash(trash.clone(), blink_client.clone(), media_index.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_delete_queue(handle.clone(), delete_queue.clone(), blink_client.clone(), media_index.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_cleanup(cleanup.clone(), blink_client.clone(), settings_rx.clone()));
+            tauri::async_runtime::spawn(drive_rearm(handle.clone(), rearm.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_firmware(handle.clone(), firmware.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_battery(battery.clone(), blink_client.clone()));
+            tauri::async_runtime::spawn(drive_live_stats(handle.clone(), live_sessions.clone()));
+            let expiring_handle = handle.clone();
+            liveview::on_expiring(move |expiring| {
+                let _ = expiring_handle.emit("live-expiring", expiring);
+            });
+            tauri::async_runtime::spawn(drive_thumbnail_refresh(handle.clone(), thumbnail_refresher.clone(), blink_client.clone(), settings_rx.clone()));
+            tray::init(&handle)?;
             
             tauri::async_runtime::spawn(async move {
                 let server_state = Arc::new(server::ServerState {
                     blink_client: blink_client_clone,
+                    live_sessions: live_sessions_clone,
+                    settings: settings_rx_clone,
+                    signer,
+                    cleanup: cleanup.clone(),
+                    fetch_permits,
+                    thumbnails,
                 });
-                let port = server::start_server(server_state).await;
-                
-                let state = handle.state::<AppState>();
-                let mut server_port = state.server_port.lock().await;
-                *server_port = Some(port);
+                let server_handle = handle.clone();
+                server::supervise(server_state, server_health.clone(), move |port| {
+                    let _ = server_handle.emit("server-restarted", port);
+                }).await;
             });
 
             Ok(())
         })
+        .on_window_event(|window, event| {
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                if window.state::<AppState>().settings.borrow().close_to_tray {
+                    api.prevent_close();
+                    let _ = window.hide();
+                }
+            }
+        })
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_dialog::init())
         .invoke_handler(tauri::generate_handler![
             check_auth,
+            reload_auth,
             login,
             verify_pin,
+            get_amazon_signin_url,
+            login_amazon,
+            complete_amazon_login,
             logout,
+            list_trusted_clients,
+            revoke_trusted_client,
             get_cameras,
             get_networks,
             get_thumbnail_base64,
+            get_thumbnail,
+            prefetch_thumbnails,
+            get_thumbnail_url,
+            get_clip_url,
+            get_thumbnail_file,
+            get_thumbnail_cache_stats,
+            clear_thumbnail_cache,
+            refresh_camera_thumbnail,
             set_network_arm,
+            disarm_for,
+            get_pending_rearms,
+            extend_rearm,
+            cancel_rearm,
+            set_all_networks_arm,
+            get_firmware_report,
+            get_usage,
+            get_battery_history,
+            get_activity_heatmap,
+            run_self_test,
+            set_log_level,
+            start_debug_capture,
             get_raw_homescreen,
             get_raw_media,
             get_raw_media_page,
+            diagnose_media,
             delete_media_items,
+            undo_delete,
+            get_pending_deletions,
+            get_delete_queue,
+            cancel_delete_job,
+            list_archive,
+            get_cleanup_queue,
+            mark_watched,
+            search_local_media,
+            get_timeline,
+            rebuild_media_index,
             get_liveview_setting_candidates,
+            set_discovered_setting,
             set_network_liveview_save,
+            get_motion_notifications,
+            set_motion_notifications,
+            get_sync_module_storage,
+            eject_usb,
+            get_liveview_settings,
+            set_liveview_setting,
             get_server_port,
+            get_server_status,
+            restart_server,
             download_clip,
             download_clip_with_progress,
+            choose_download_path,
+            choose_download_dir,
+            clip_file_name,
+            cancel_download,
+            enqueue_downloads,
+            get_download_jobs,
+            clear_download_jobs,
+            pause_downloads,
+            resume_downloads,
+            cancel_download_job,
+            set_download_concurrency,
+            export_clips_zip,
+            get_motion_alerts,
+            set_motion_alerts,
+            get_settings,
+            update_settings,
+            export_app_state,
+            import_app_state,
+            get_audit_log,
+            get_camera_prefs,
+            set_camera_prefs,
+            get_close_to_tray,
+            set_close_to_tray,
+            get_schedules,
+            save_schedule_entry,
+            delete_schedule_entry,
             get_camera_config,
-            update_camera_config
+            update_camera_config,
+            get_network_settings,
+            update_network_settings,
+            get_notification_config,
+            update_notification_config,
+            list_live_sessions,
+            stop_live_session,
+            save_replay,
+            list_recordings,
+            start_liveview,
+            stop_liveview
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                tray::teardown(app);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    /// Answers one request with `status`, announcing `total` body bytes but sending only
+    /// `sent`, then keeps the connection open the way a stalled network would.
+    async fn serve(status: &'static str, total: usize, sent: usize) -> reqwest::Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 4096];
+            let _ = socket.read(&mut request).await;
+            let head = format!("HTTP/1.1 {}\r\ncontent-length: {}\r\ncontent-type: video/mp4\r\n\r\n", status, total);
+            socket.write_all(head.as_bytes()).await.unwrap();
+            socket.write_all(&vec![7u8; sent]).await.unwrap();
+            socket.flush().await.unwrap();
+            std::future::pending::<()>().await;
+        });
+        format!("http://{}/clip.mp4", addr).parse().unwrap()
+    }
+
+    struct Run {
+        result: CommandResult<bool>,
+        events: Vec<DownloadProgress>,
+        downloads: DownloadTokens,
+    }
+
+    /// Downloads `url` to `path` as `download_clip_with_progress` would, cancelling
+    /// through the registry once `cancel_after` bytes have arrived.
+    async fn download(url: reqwest::Url, path: &str, cancel_after: Option<u64>) -> Run {
+        let downloads = DownloadTokens::default();
+        let events = std::sync::Mutex::new(Vec::new());
+        let progress = |event: DownloadProgress| {
+            if cancel_after.is_some_and(|n| event.received >= n) && !event.done {
+                cancel_registered(&downloads, "d1");
+            }
+            events.lock().unwrap().push(event);
+        };
+        let token = SecretString::from("token");
+        let run = run_download(&downloads, &progress, reqwest::Client::new(), &token, url, path, false, "d1".to_string());
+        let result = tokio::time::timeout(Duration::from_secs(10), run).await.expect("download hung");
+        Run { result, events: events.into_inner().unwrap(), downloads }
+    }
+
+    #[tokio::test]
+    async fn cancel_mid_stream_stops_and_removes_the_partial_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mp4").to_string_lossy().to_string();
+        let run = download(serve("200 OK", 100_000, 1_000).await, &path, Some(1)).await;
+
+        assert!(matches!(run.result, Ok(false)));
+        assert!(!std::path::Path::new(&path).exists());
+        let last = run.events.last().unwrap();
+        assert!(last.cancelled && !last.done && last.error.is_none());
+        assert!(run.downloads.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancel_after_completion_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mp4").to_string_lossy().to_string();
+        let run = download(serve("200 OK", 1_000, 1_000).await, &path, None).await;
+
+        assert!(matches!(run.result, Ok(true)));
+        assert!(run.events.last().unwrap().done);
+        assert!(!cancel_registered(&run.downloads, "d1"));
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 1_000);
+    }
+
+    #[tokio::test]
+    async fn failed_download_ends_with_an_error_event() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("clip.mp4").to_string_lossy().to_string();
+        let run = download(serve("500 Internal Server Error", 0, 0).await, &path, None).await;
+
+        assert!(run.result.is_err());
+        let last = run.events.last().unwrap();
+        assert!(last.error.is_some() && !last.done && !last.cancelled);
+        assert!(!std::path::Path::new(&path).exists());
+    }
 }
@@ -0,0 +1,162 @@
+use crate::blink::{parse_blink_timestamp, BlinkClient, MediaItem};
+use crate::settings::Settings;
+use crate::{shared, storage};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{watch, Mutex};
+
+const CURSOR_FILE: &str = "motion_alerts.json";
+const MAX_BACKOFF_SECS: u64 = 30 * 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MotionAlertState {
+    pub enabled: bool,
+    pub interval_secs: u64,
+    pub last_seen_id: Option<i64>,
+}
+
+/// Highest media id already announced; persisted so restarts don't re-notify.
+#[derive(Default, Serialize, Deserialize)]
+struct MotionCursor {
+    #[serde(default)]
+    last_seen_id: Option<i64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct MotionClip {
+    pub id: i64,
+    pub camera: String,
+    pub network_id: Option<i64>,
+    pub created_at: Option<String>,
+    pub thumbnail: Option<String>,
+    /// Unwatched clips for this camera in the latest media page, this one included.
+    pub unwatched_count: usize,
+}
+
+/// Polls the media feed in the background and announces new clips as desktop
+/// notifications and `motion-clips` events. Enabled state and interval come from settings.
+pub struct MotionPoller {
+    last_seen_id: std::sync::Mutex<Option<i64>>,
+}
+
+impl MotionPoller {
+    pub fn load() -> Self {
+        let cursor: MotionCursor = storage::load_json(CURSOR_FILE).unwrap_or_default();
+        Self {
+            last_seen_id: std::sync::Mutex::new(cursor.last_seen_id),
+        }
+    }
+
+    pub fn config(&self, settings: &Settings) -> MotionAlertState {
+        MotionAlertState {
+            enabled: settings.motion_alerts_enabled,
+            interval_secs: settings.motion_interval_secs,
+            last_seen_id: *self.last_seen_id.lock().unwrap(),
+        }
+    }
+
+    fn set_cursor(&self, id: i64) {
+        *self.last_seen_id.lock().unwrap() = Some(id);
+        let _ = storage::save_json(CURSOR_FILE, &MotionCursor { last_seen_id: Some(id) });
+    }
+
+    pub async fn run(self: Arc<Self>, app: AppHandle, blink_client: Arc<Mutex<BlinkClient>>, mut settings: watch::Receiver<Settings>) {
+        let mut backoff_secs = 0;
+        loop {
+            let (enabled, interval_secs) = {
+                let current = settings.borrow_and_update();
+                (current.motion_alerts_enabled, current.motion_interval_secs)
+            };
+            if !enabled {
+                if settings.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            let wait = if backoff_secs > 0 { backoff_secs } else { interval_secs };
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(wait)) => {}
+                // Settings changed: start over with the new interval.
+                changed = settings.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                    backoff_secs = 0;
+                    continue;
+                }
+            }
+
+            match self.poll_once(&app, &blink_client).await {
+                Ok(()) => backoff_secs = 0,
+                Err(e) => {
+                    backoff_secs = (backoff_secs.max(interval_secs) * 2).min(MAX_BACKOFF_SECS);
+                    eprintln!("Motion poll failed ({}); retrying in {}s", e, backoff_secs);
+                }
+            }
+        }
+    }
+
+    async fn poll_once(&self, app: &AppHandle, blink_client: &Arc<Mutex<BlinkClient>>) -> Result<()> {
+        let items = {
+            let client = shared::snapshot(blink_client).await;
+            if client.token.is_none() {
+                return Err(anyhow!("Not logged in"));
+            }
+            client.get_media_page(1, 1).await?
+        };
+
+        let Some(newest) = items.iter().map(|item| item.id).max() else {
+            return Ok(());
+        };
+        let Some(cursor) = *self.last_seen_id.lock().unwrap() else {
+            // First run: remember where we are instead of announcing the backlog.
+            self.set_cursor(newest);
+            return Ok(());
+        };
+
+        let mut fresh: Vec<&MediaItem> = items.iter()
+            .filter(|item| item.id > cursor && !item.deleted)
+            .collect();
+        if fresh.is_empty() {
+            return Ok(());
+        }
+        fresh.sort_by_key(|item| item.id);
+
+        let mut unwatched: HashMap<Option<i64>, usize> = HashMap::new();
+        for item in items.iter().filter(|item| !item.watched && !item.deleted) {
+            *unwatched.entry(item.device_id).or_default() += 1;
+        }
+
+        let clips: Vec<MotionClip> = fresh.iter()
+            .map(|item| MotionClip {
+                id: item.id,
+                camera: item.device_name.clone().unwrap_or_else(|| "Camera".to_string()),
+                network_id: item.network_id,
+                created_at: item.created_at.clone(),
+                thumbnail: item.thumbnail.clone(),
+                unwatched_count: unwatched.get(&item.device_id).copied().unwrap_or(0),
+            })
+            .collect();
+
+        for clip in &clips {
+            let time = clip.created_at.as_deref()
+                .and_then(parse_blink_timestamp)
+                .map(|dt| dt.with_timezone(&chrono::Local).format("%H:%M").to_string());
+            let body = match time {
+                Some(time) => format!("{} detected motion, {}", clip.camera, time),
+                None => format!("{} detected motion", clip.camera),
+            };
+            let _ = app.notification().builder().title("Blink Monitor").body(body).show();
+        }
+        let _ = app.emit("motion-clips", clips);
+
+        self.set_cursor(newest.max(cursor));
+        Ok(())
+    }
+}
@@ -7,14 +7,18 @@ use axum::{
 use axum::body::Body;
 use http::{header, StatusCode};
 use std::sync::Arc;
-use tokio::sync::{Mutex, mpsc};
-use crate::blink::BlinkClient;
-use crate::immi::{self, ImmiStream};
+use tokio::sync::{Mutex, Semaphore, watch};
+use crate::cleanup::CleanupQueue;
+use crate::blink::{BlinkClient, LiveviewOptions, LiveviewQuality};
+use crate::live_sessions::SessionRegistry;
+use crate::liveview::{self, LiveviewError, LiveviewRequest, StreamMode};
+use crate::settings::Settings;
+use crate::signing::{SignatureError, UrlSigner};
+use crate::thumbnails::ThumbnailCache;
 use futures_util::StreamExt;
+use secrecy::ExposeSecret;
 use tower_http::cors::CorsLayer;
-use tokio_util::sync::CancellationToken;
 use http::Method;
-use chrono::{Utc, Duration};
 
 #[derive(serde::Deserialize)]
 pub struct ProxyQuery {
@@ -25,13 +29,150 @@ pub struct ProxyQuery {
 pub struct LiveQuery {
     pub serial: Option<String>,
     pub record: Option<bool>,
+    pub quality: Option<LiveviewQuality>,
+    pub audio: Option<bool>,
+    pub mode: Option<StreamMode>,
+    pub fake: Option<bool>,
 }
 
 pub struct ServerState {
     pub blink_client: Arc<Mutex<BlinkClient>>,
+    pub live_sessions: Arc<SessionRegistry>,
+    pub settings: watch::Receiver<Settings>,
+    pub signer: Arc<UrlSigner>,
+    pub cleanup: Arc<CleanupQueue>,
+    /// Shared with the thumbnail commands, so the webview's `<img>` loads count against
+    /// the same cap.
+    pub fetch_permits: Arc<Semaphore>,
+    /// Filled by `prefetch_thumbnails`; signed thumbnail requests are served from it first.
+    pub thumbnails: Arc<ThumbnailCache>,
 }
 
-pub async fn start_server(state: Arc<ServerState>) -> u16 {
+/// Pause before restarting the server after it stops or fails to bind.
+const RESTART_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ServerStatus {
+    pub running: bool,
+    pub port: Option<u16>,
+    /// Seconds since the current listener started.
+    pub uptime_secs: u64,
+    pub restarts: u32,
+    /// Why the server last stopped or failed to start.
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct HealthInner {
+    port: Option<u16>,
+    started: Option<std::time::Instant>,
+    restarts: u32,
+    last_error: Option<String>,
+    serving: Option<tokio::task::AbortHandle>,
+}
+
+/// What `supervise` knows about the embedded server, shared with the commands.
+#[derive(Default)]
+pub struct ServerHealth {
+    inner: std::sync::Mutex<HealthInner>,
+}
+
+impl ServerHealth {
+    /// The port, while the server is up.
+    pub fn port(&self) -> Option<u16> {
+        let inner = self.inner.lock().unwrap();
+        inner.started.and(inner.port)
+    }
+
+    pub fn status(&self) -> ServerStatus {
+        let inner = self.inner.lock().unwrap();
+        ServerStatus {
+            running: inner.started.is_some(),
+            port: inner.port,
+            uptime_secs: inner.started.map(|s| s.elapsed().as_secs()).unwrap_or(0),
+            restarts: inner.restarts,
+            last_error: inner.last_error.clone(),
+        }
+    }
+
+    /// Drops the current listener; `supervise` binds a new one after `RESTART_DELAY`.
+    pub fn restart(&self) {
+        if let Some(serving) = self.inner.lock().unwrap().serving.take() {
+            serving.abort();
+        }
+    }
+
+    fn started(&self, port: u16, serving: tokio::task::AbortHandle) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.port = Some(port);
+        inner.started = Some(std::time::Instant::now());
+        inner.serving = Some(serving);
+    }
+
+    fn stopped(&self, error: String) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.started = None;
+        inner.serving = None;
+        inner.restarts += 1;
+        inner.last_error = Some(error);
+    }
+}
+
+/// Runs the embedded server for the life of the app. Whenever it stops (the port can't be
+/// bound, the serve loop returns, or it panics) the cause is logged and it is started
+/// again, on the previous port if that is still free. `on_restart` gets the port after
+/// every start but the first, so the webview can rebuild its URLs.
+pub async fn supervise(state: Arc<ServerState>, health: Arc<ServerHealth>, on_restart: impl Fn(u16) + Send + 'static) {
+    let mut previous_port = None;
+    loop {
+        match bind(previous_port).await {
+            Ok((listener, port)) => {
+                let app = router(state.clone());
+                let serving = tokio::spawn(async move { axum::serve(listener, app).await });
+                health.started(port, serving.abort_handle());
+                if previous_port.is_some() {
+                    on_restart(port);
+                }
+                previous_port = Some(port);
+                let cause = match serving.await {
+                    Ok(Ok(())) => "stopped".to_string(),
+                    Err(e) if e.is_cancelled() => "was restarted".to_string(),
+                    Ok(Err(e)) => format!("failed: {}", e),
+                    Err(e) if e.is_panic() => {
+                        let panic = e.into_panic();
+                        let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+                        format!("panicked: {}", message)
+                    }
+                    Err(e) => e.to_string(),
+                };
+                eprintln!("Embedded server on port {} {}; restarting", port, cause);
+                health.stopped(cause);
+            }
+            Err(e) => {
+                eprintln!("Embedded server could not bind: {}; retrying", e);
+                health.stopped(format!("bind failed: {}", e));
+            }
+        }
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+/// Binds to `previous` when given and free, otherwise to any port.
+async fn bind(previous: Option<u16>) -> std::io::Result<(tokio::net::TcpListener, u16)> {
+    let listener = match previous {
+        Some(port) => match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
+            Ok(listener) => listener,
+            Err(_) => tokio::net::TcpListener::bind("127.0.0.1:0").await?,
+        },
+        None => tokio::net::TcpListener::bind("127.0.0.1:0").await?,
+    };
+    let port = listener.local_addr()?.port();
+    Ok((listener, port))
+}
+
+fn router(state: Arc<ServerState>) -> Router {
     // Only allow requests from Tauri WebView and Vite dev server
     let cors = CorsLayer::new()
         .allow_origin([
@@ -42,22 +183,12 @@ pub async fn start_server(state: Arc<ServerState>) -> u16 {
         .allow_methods([Method::GET])
         .allow_headers([http::header::CONTENT_TYPE]);
 
-    let app = Router::new()
-        .route("/clip", get(proxy_clip))
-        .route("/proxy", get(proxy_clip))
-        .route("/thumbnail", get(proxy_thumbnail))
+    Router::new()
+        .route("/api/clip", get(signed_clip))
+        .route("/api/thumbnail", get(signed_thumbnail))
         .route("/live/:network_id/:camera_id/:product_type", get(proxy_live))
         .layer(cors)
-        .with_state(state);
-
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-    let port = listener.local_addr().unwrap().port();
-    
-    tokio::spawn(async move {
-        axum::serve(listener, app).await.unwrap();
-    });
-
-    port
+        .with_state(state)
 }
 
 async fn proxy_request_internal(
@@ -65,42 +196,25 @@ async fn proxy_request_internal(
     url: String,
     force_cache: bool,
 ) -> impl IntoResponse {
-    let mut client = state.blink_client.lock().await;
-    if let Ok(true) = client.refresh_token_if_needed().await {
-        let _ = crate::storage::save_auth(&client.get_state());
-    }
-    
+    let _permit = match state.fetch_permits.acquire().await {
+        Ok(permit) => permit,
+        Err(e) => return (StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response(),
+    };
+    let client = crate::shared::snapshot(&state.blink_client).await;
     let token = match &client.token {
         Some(t) => t.clone(),
         None => return (StatusCode::UNAUTHORIZED, "Not logged in").into_response(),
     };
 
-    let req_url = if url.starts_with("http") {
-        url.clone()
-    } else {
-        format!("{}{}", client.base_url, url)
-    };
-
     // SSRF protection: only allow Blink domains
-    if let Ok(parsed) = url::Url::parse(&req_url) {
-        if let Some(host) = parsed.host_str() {
-            if !host.ends_with(".immedia-semi.com")
-                && !host.ends_with(".blinkforhome.com")
-                && !host.ends_with(".blink.com")
-                && !host.ends_with(".amazonaws.com")
-                && !host.ends_with(".cloudfront.net")
-            {
-                return (StatusCode::BAD_REQUEST, format!("Invalid URL host: {}", host)).into_response();
-            }
-        } else {
-            return (StatusCode::BAD_REQUEST, "Invalid URL").into_response();
-        }
-    } else {
-        return (StatusCode::BAD_REQUEST, "Invalid URL format").into_response();
-    }
+    let resolved = client.resolve_url(&url).map_err(|e| e.to_string());
+    let req_url = match resolved.and_then(|url| crate::shared::validate_media_url(url.as_str())) {
+        Ok(url) => url,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
 
-    let res = match client.client.get(&req_url)
-        .header("Authorization", format!("Bearer {}", token))
+    let res = match client.media_client.get(req_url)
+        .header("Authorization", format!("Bearer {}", token.expose_secret()))
         .send()
         .await {
             Ok(r) => r,
@@ -110,9 +224,13 @@ async fn proxy_request_internal(
     let status = res.status();
     let mut response_builder = Response::builder().status(status);
 
+    // Thumbnails without an image Content-Type are buffered so the real type can be sniffed.
+    let upstream_type = res.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+    let sniff = force_cache && status.is_success() && !upstream_type.as_deref().is_some_and(|t| t.starts_with("image/"));
+
     for (name, value) in res.headers().iter() {
         // Skip CORS headers from upstream as we set our own
-        if name != header::ACCESS_CONTROL_ALLOW_ORIGIN {
+        if name != header::ACCESS_CONTROL_ALLOW_ORIGIN && !(sniff && name == header::CONTENT_TYPE) {
             response_builder = response_builder.header(name, value);
         }
     }
@@ -121,6 +239,15 @@ async fn proxy_request_internal(
         response_builder = response_builder.header(header::CACHE_CONTROL, "public, max-age=3600");
     }
 
+    if sniff {
+        let bytes = match res.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        };
+        let content_type = crate::blink::image_content_type(upstream_type.as_deref(), &bytes);
+        return response_builder.header(header::CONTENT_TYPE, content_type).body(Body::from(bytes)).unwrap().into_response();
+    }
+
     let stream = res.bytes_stream().map(|result| {
         result.map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
     });
@@ -129,18 +256,50 @@ async fn proxy_request_internal(
     response_builder.body(body).unwrap().into_response()
 }
 
-async fn proxy_clip(
+/// Refuses requests to a signed route that don't carry a valid signature from
+/// `get_thumbnail_url` or `get_clip_url`.
+fn check_signature(state: &ServerState, uri: &http::Uri) -> Result<(), Response> {
+    match state.signer.verify(uri.path(), uri.query(), chrono::Utc::now().timestamp()) {
+        Ok(()) => Ok(()),
+        Err(SignatureError::Missing) => Err((StatusCode::UNAUTHORIZED, "Missing signature").into_response()),
+        Err(e) => Err((StatusCode::FORBIDDEN, e.to_string()).into_response()),
+    }
+}
+
+/// Clip route for URLs handed out by `get_clip_url`.
+async fn signed_clip(
     State(state): State<Arc<ServerState>>,
+    uri: http::Uri,
     Query(query): Query<ProxyQuery>,
-) -> impl IntoResponse {
-    proxy_request_internal(state, query.url, false).await
+) -> Response {
+    if let Err(refused) = check_signature(&state, &uri) {
+        return refused;
+    }
+    proxy_request_internal(state, query.url, false).await.into_response()
 }
 
-async fn proxy_thumbnail(
+/// Thumbnail route for URLs handed out by `get_thumbnail_url`. A copy the cache already
+/// holds is served from disk, marked `no-cache` so the webview asks again after a refresh
+/// replaces it; anything else is fetched from Blink.
+async fn signed_thumbnail(
     State(state): State<Arc<ServerState>>,
+    uri: http::Uri,
     Query(query): Query<ProxyQuery>,
-) -> impl IntoResponse {
-    proxy_request_internal(state, query.url, true).await
+) -> Response {
+    if let Err(refused) = check_signature(&state, &uri) {
+        return refused;
+    }
+    if let Some(cached) = state.thumbnails.lookup(&query.url) {
+        if let Ok(bytes) = tokio::fs::read(&cached.file).await {
+            let content_type = crate::blink::image_content_type(cached.content_type.as_deref(), &bytes);
+            return Response::builder()
+                .header(header::CONTENT_TYPE, content_type)
+                .header(header::CACHE_CONTROL, "no-cache")
+                .body(Body::from(bytes))
+                .unwrap();
+        }
+    }
+    proxy_request_internal(state, query.url, true).await.into_response()
 }
 
 async fn proxy_live(
@@ -148,254 +307,124 @@ async fn proxy_live(
     Path((network_id, camera_id, product_type)): Path<(i64, i64, String)>,
     Query(query): Query<LiveQuery>,
 ) -> impl IntoResponse {
-    let serial = query.serial.unwrap_or_default();
-    let record = query.record.unwrap_or(false);
-    let session_start_time = Utc::now();
-    
-    // 1. Liveview Request with Retry logic
-    let mut lv_res = None;
-    let mut retries = 0;
-    let max_retries = 5;
-
-    while retries < max_retries {
-        let res = {
-            let mut client = state.blink_client.lock().await;
-            if let Ok(true) = client.refresh_token_if_needed().await {
-                let _ = crate::storage::save_auth(&client.get_state());
-            }
-            client.request_liveview(network_id, camera_id, &product_type, record).await
-        };
-
-        match res {
-            Ok(res) => {
-                lv_res = Some(res);
-                break;
-            },
-            Err(e) => {
-                let err_msg = e.to_string();
-                eprintln!("Liveview request failed: {}", err_msg);
-                if err_msg.contains("307") || err_msg.contains("busy") {
-                    tokio::time::sleep(std::time::Duration::from_secs(4)).await;
-                    retries += 1;
-                    continue;
-                } else {
-                    return (StatusCode::INTERNAL_SERVER_ERROR, format!("Blink API Error: {}", err_msg)).into_response();
-                }
-            }
-        }
-    }
-
-    let lv_res = match lv_res {
-        Some(res) => res,
-        None => return (StatusCode::SERVICE_UNAVAILABLE, "Camera remains busy after retries").into_response(),
-    };
-
-    // 2. Connect to IMMI server
-    let immi = match ImmiStream::connect(&lv_res.server, &serial).await {
-        Ok(s) => {
-            s
+    let settings = state.settings.borrow().clone();
+    let request = LiveviewRequest {
+        network_id,
+        camera_id,
+        product_type,
+        serial: query.serial.unwrap_or_default(),
+        record: query.record.unwrap_or(false),
+        options: LiveviewOptions {
+            quality: query.quality.or_else(|| settings.liveview_quality(camera_id)),
+            audio: query.audio,
+            intent: None,
         },
-        Err(e) => {
-            eprintln!("IMMI connection failed: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, format!("IMMI Connection Failed: {}", e)).into_response();
+        mode: query.mode.unwrap_or_default(),
+        fake: query.fake.unwrap_or(false),
+    };
+    let live = match liveview::start(&state.blink_client, &state.live_sessions, &state.cleanup, &settings, request).await {
+        Ok(live) => live,
+        Err(e @ LiveviewError::Busy { retry_after }) => {
+            return (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, retry_after.to_string())], e.to_string()).into_response();
         }
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
     };
 
-    let (mut immi_rx, mut immi_tx) = (immi.reader, immi.writer);
-    let cancel_token = CancellationToken::new();
-    let (tx, rx) = mpsc::channel::<Result<axum::body::Bytes, std::io::Error>>(100);
-
-    // 3. Spawn Keepalive Task
-    let token_keepalive = cancel_token.clone();
-    let blink_client_inner = state.blink_client.clone();
-    let polling_interval = lv_res.polling_interval as u64;
-    let cmd_id = lv_res.command_id;
-    
-    tokio::spawn(async move {
-        let mut last_poll = std::time::Instant::now();
-        let mut keepalive_seq = 0u32;
-        let mut every10s = 0u32;
-
-        // Initial handshake packets
-        let _ = immi::send_latency_stats(&mut immi_tx).await;
-        keepalive_seq += 1;
-        let _ = immi::send_keepalive(&mut immi_tx, keepalive_seq).await;
-
-        loop {
-            tokio::select! {
-                _ = token_keepalive.cancelled() => {
-                    break;
-                }
-                _ = tokio::time::sleep(std::time::Duration::from_secs(1)) => {
-                    if let Err(_) = immi::send_latency_stats(&mut immi_tx).await {
-                        break;
-                    }
+    let body_stream = tokio_stream::wrappers::ReceiverStream::new(live.chunks).map(Ok::<_, std::io::Error>);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "video/mp2t")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .header(header::CONNECTION, "keep-alive")
+        .header("X-Live-Session-Id", live.session.id.as_str())
+        .body(Body::from_stream(body_stream))
+        .unwrap()
+        .into_response()
+}
 
-                    every10s += 1;
-                    if (every10s % 10) == 0 {
-                        keepalive_seq += 1;
-                        if let Err(_) = immi::send_keepalive(&mut immi_tx, keepalive_seq).await {
-                            break;
-                        }
-                    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn supervised() -> (Arc<ServerHealth>, tokio::sync::mpsc::UnboundedReceiver<u16>) {
+        let (_, settings) = watch::channel(Settings::default());
+        let dir = tempfile::tempdir().unwrap().keep();
+        let state = Arc::new(ServerState {
+            blink_client: Arc::new(Mutex::new(BlinkClient::new())),
+            live_sessions: Arc::new(SessionRegistry::default()),
+            settings,
+            signer: Arc::new(UrlSigner::new(vec![7; 32])),
+            cleanup: Arc::new(CleanupQueue::load(dir.join("cleanup.json"))),
+            fetch_permits: Arc::new(Semaphore::new(1)),
+            thumbnails: Arc::new(ThumbnailCache::in_dir(dir.join("thumbnails"))),
+        });
+        let health = Arc::new(ServerHealth::default());
+        let (restarted_tx, restarted) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(supervise(state, health.clone(), move |port| {
+            let _ = restarted_tx.send(port);
+        }));
+        (health, restarted)
+    }
 
-                    if last_poll.elapsed().as_secs() >= polling_interval {
-                        let res = {
-                            let client = blink_client_inner.lock().await;
-                            client.get_command_status(network_id, cmd_id).await
-                        };
-                        match res {
-                            Ok(status) => {
-                                 let mut running = false;
-                                 if let Some(cmds) = status["commands"].as_array() {
-                                     for c in cmds {
-                                         if c["id"].as_i64() == Some(cmd_id) {
-                                             let state = c["state_condition"].as_str().unwrap_or("");
-                                             if state == "new" || state == "running" { running = true; }
-                                         }
-                                     }
-                                 }
-                                 if !running { 
-                                     break; 
-                                 }
-                            },
-                            Err(_) => break,
-                        }
-                        last_poll = std::time::Instant::now();
-                    }
-                }
+    async fn port_once_running(health: &ServerHealth) -> u16 {
+        for _ in 0..100 {
+            if let Some(port) = health.port() {
+                return port;
             }
+            tokio::time::sleep(Duration::from_millis(20)).await;
         }
-        token_keepalive.cancel(); // Ensure both tasks stop
-    });
+        panic!("server never started");
+    }
 
-    // 4. Spawn Reader Task
-    let token_reader = cancel_token.clone();
-    let tx_clone = tx.clone();
-    
-    tokio::spawn(async move {
-        // Send initial TS null packets to keep HTTP connection alive during camera wake-up
-        // Standard MPEG-TS null packet: 188 bytes starting with 0x47 0x1F 0xFF 0x10
-        let mut null_packet = vec![0x47, 0x1F, 0xFF, 0x10];
-        null_packet.extend(vec![0xFF; 184]);
-        
-        for _ in 0..3 {
-            if tx_clone.send(Ok(axum::body::Bytes::from(null_packet.clone()))).await.is_err() {
-                token_reader.cancel();
-                return;
-            }
-        }
-        
-        let mut mpegts_started = false;
-        let mut last_null_packet = std::time::Instant::now();
-        let stream_start_time = std::time::Instant::now();
-        
-        loop {
-            let packet_res = tokio::select! {
-                _ = token_reader.cancelled() => break,
-                res = tokio::time::timeout(std::time::Duration::from_secs(20), immi::read_packet(&mut immi_rx)) => res,
-            };
-
-            match packet_res {
-                Ok(Ok((msg_type, payload))) => {
-                    if msg_type == 0x00 && !payload.is_empty() {
-                        if payload[0] == 0x47 {
-                            if !mpegts_started {
-                                mpegts_started = true;
-                                eprintln!("First MPEG-TS packet received after {}ms", stream_start_time.elapsed().as_millis());
-                            }
-                            if tx_clone.send(Ok(axum::body::Bytes::from(payload))).await.is_err() {
-                                break;
-                            }
-                            last_null_packet = std::time::Instant::now();
-                        }
-                    } else if !mpegts_started && last_null_packet.elapsed().as_secs() >= 2 {
-                        // Send periodic null packets while waiting for real data
-                        if tx_clone.send(Ok(axum::body::Bytes::from(null_packet.clone()))).await.is_err() {
-                            break;
-                        }
-                        last_null_packet = std::time::Instant::now();
-                    }
-                }
-                Ok(Err(e)) => {
-                    eprintln!("IMMI read error: {}", e);
-                    break;
-                }
-                Err(_) => {
-                    eprintln!("IMMI read timeout");
-                    break;
-                }
-            }
-            
-            // If we've been waiting more than 35 seconds without any data, bail
-            if !mpegts_started && stream_start_time.elapsed().as_secs() > 35 {
-                eprintln!("Stream timed out waiting for data");
-                break;
-            }
-        }
-        token_reader.cancel();
-    });
+    /// The status line of an unsigned thumbnail request, which the server refuses itself.
+    async fn status_line(port: u16) -> String {
+        let mut socket = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        socket.write_all(b"GET /api/thumbnail?url=x HTTP/1.1\r\nhost: localhost\r\nconnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        socket.read_to_string(&mut response).await.unwrap();
+        response.lines().next().unwrap_or_default().to_string()
+    }
 
-    // 5. Spawn Cleanup Task (Auto-delete clips if not recording)
-    let cleanup_token = cancel_token.clone();
-    let cleanup_client = state.blink_client.clone();
-    
-    tokio::spawn(async move {
-        cleanup_token.cancelled().await;
-        
-        if !record {
-            // Wait for Blink to finalize the clip (can take up to ~90s in some cases)
-            let delays = [5u64, 10, 15, 20, 25, 30];
-            let search_after = session_start_time - Duration::seconds(60);
-            for delay in delays {
-                tokio::time::sleep(std::time::Duration::from_secs(delay)).await;
-                
-                let ids = {
-                    let client = cleanup_client.lock().await;
-                    client.get_latest_media_for_camera(camera_id, search_after).await
-                };
+    #[tokio::test]
+    async fn a_killed_listener_comes_back_on_the_same_port() {
+        let (health, mut restarted) = supervised().await;
+        let port = port_once_running(&health).await;
+        assert_eq!(status_line(port).await, "HTTP/1.1 401 Unauthorized");
+        assert_eq!(health.status().restarts, 0);
+
+        health.restart();
+        let new_port = tokio::time::timeout(Duration::from_secs(10), restarted.recv()).await.unwrap().unwrap();
+
+        assert_eq!(new_port, port);
+        let status = health.status();
+        assert!(status.running);
+        assert_eq!(status.restarts, 1);
+        assert_eq!(status.last_error.as_deref(), Some("was restarted"));
+        assert_eq!(status_line(new_port).await, "HTTP/1.1 401 Unauthorized");
+    }
 
-                match ids {
-                    Ok(ids) => {
-                        if !ids.is_empty() {
-                            // Send delete request
-                            {
-                                let client = cleanup_client.lock().await;
-                                let _ = client.delete_media(ids.clone()).await;
-                            };
-
-                            // Wait for Blink to process the deletion
-                            tokio::time::sleep(std::time::Duration::from_secs(4)).await;
-
-                            // Verify deletion by re-fetching
-                            let verification = {
-                                let client = cleanup_client.lock().await;
-                                client.get_latest_media_for_camera(camera_id, search_after).await
-                            };
-
-                            match verification {
-                                Ok(remaining_ids) if remaining_ids.is_empty() => {
-                                    break;
-                                }
-                                _ => {}
-                            }
-                        }
-                    }
-                    Err(_) => {}
-                }
+    #[tokio::test]
+    async fn a_taken_port_moves_the_server_to_a_new_one() {
+        let (health, mut restarted) = supervised().await;
+        let port = port_once_running(&health).await;
+
+        health.restart();
+        // Grab the port while the supervisor waits to rebind.
+        let mut squatter = None;
+        for _ in 0..100 {
+            if let Ok(listener) = tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
+                squatter = Some(listener);
+                break;
             }
+            tokio::time::sleep(Duration::from_millis(10)).await;
         }
-    });
-
-    // 6. Return HTTP Response with stream
-    let body_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
-    let body = Body::from_stream(body_stream);
+        assert!(squatter.is_some(), "the old listener was never dropped");
+        assert!(health.port().is_none());
 
-    Response::builder()
-        .header(header::CONTENT_TYPE, "video/mp2t")
-        .header(header::CACHE_CONTROL, "no-cache")
-        .header(header::CONNECTION, "keep-alive")
-        .body(body)
-        .unwrap()
-        .into_response()
+        let new_port = tokio::time::timeout(Duration::from_secs(10), restarted.recv()).await.unwrap().unwrap();
+        assert_ne!(new_port, port);
+        assert_eq!(health.port(), Some(new_port));
+        assert_eq!(status_line(new_port).await, "HTTP/1.1 401 Unauthorized");
+    }
 }
@@ -1,40 +1,15 @@
+use crate::auth_store::{self, AuthStore};
 use crate::blink::BlinkAuthState;
-use anyhow::{anyhow, Result};
-use keyring::Entry;
+use crate::settings::{Settings, SETTINGS_FILE};
+use anyhow::Result;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 use std::fs;
 use std::path::PathBuf;
 
-const KEYRING_SERVICE: &str = "blink-monitor";
-const KEYRING_USERNAME: &str = "auth";
-
-fn keyring_entry() -> Result<Entry> {
-    Entry::new(KEYRING_SERVICE, KEYRING_USERNAME)
-        .map_err(|e| anyhow!("Failed to open keychain entry: {}", e))
-}
-
-fn allow_plaintext_auth() -> bool {
-    std::env::var("BLINK_ALLOW_PLAINTEXT_AUTH")
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false)
-}
-
-fn use_keychain() -> bool {
-    if std::env::var("BLINK_DISABLE_KEYCHAIN")
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false)
-    {
-        return false;
-    }
-    if std::env::var("BLINK_USE_KEYCHAIN")
-        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
-        .unwrap_or(false)
-    {
-        return true;
-    }
-    if cfg!(debug_assertions) {
-        return false;
-    }
-    true
+fn auth_store() -> AuthStore {
+    // Debug builds keep auth in a file so development doesn't litter the real keychain.
+    AuthStore::new(get_config_dir().join("auth.json"), auth_store::keychain_enabled(!cfg!(debug_assertions)))
 }
 
 pub fn get_config_dir() -> PathBuf {
@@ -46,67 +21,59 @@ pub fn get_config_dir() -> PathBuf {
     path
 }
 
-pub fn save_auth(state: &BlinkAuthState) -> Result<()> {
-    let json = serde_json::to_string(state)?;
-    if !use_keychain() {
-        let mut path = get_config_dir();
-        path.push("auth.json");
-        fs::write(path, json)?;
-        return Ok(());
+/// Reads a JSON document from the config directory.
+pub fn load_json<T: DeserializeOwned>(name: &str) -> Result<T> {
+    let path = get_config_dir().join(name);
+    let json = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&json)?)
+}
+
+pub fn save_json<T: Serialize>(name: &str, value: &T) -> Result<()> {
+    let path = get_config_dir().join(name);
+    fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
+
+pub fn settings_path() -> PathBuf {
+    get_config_dir().join(SETTINGS_FILE)
+}
+
+/// Loads `settings.json`, seeding it on first run from the per-feature files that predate it.
+pub fn load_settings() -> Settings {
+    let path = settings_path();
+    if path.exists() {
+        return Settings::load(&path);
     }
-    let entry = keyring_entry()?;
 
-    match entry.set_password(&json) {
-        Ok(()) => Ok(()),
-        Err(e) => {
-            if allow_plaintext_auth() {
-                let mut path = get_config_dir();
-                path.push("auth.json");
-                fs::write(path, json)?;
-                Ok(())
-            } else {
-                Err(anyhow!(
-                    "Keychain storage failed: {}. Set BLINK_ALLOW_PLAINTEXT_AUTH=1 to allow insecure fallback.",
-                    e
-                ))
-            }
+    let mut legacy = serde_json::Map::new();
+    if let Ok(motion) = load_json::<Value>("motion_alerts.json") {
+        if let Some(enabled) = motion.get("enabled") {
+            legacy.insert("motion_alerts_enabled".to_string(), enabled.clone());
+        }
+        if let Some(interval) = motion.get("interval_secs") {
+            legacy.insert("motion_interval_secs".to_string(), interval.clone());
         }
     }
-}
-
-pub fn load_auth() -> Result<BlinkAuthState> {
-    if use_keychain() {
-        if let Ok(entry) = keyring_entry() {
-            if let Ok(json) = entry.get_password() {
-                let state: BlinkAuthState = serde_json::from_str(&json)?;
-                return Ok(state);
-            }
+    if let Ok(tray) = load_json::<Value>("tray.json") {
+        if let Some(close_to_tray) = tray.get("close_to_tray") {
+            legacy.insert("close_to_tray".to_string(), close_to_tray.clone());
         }
     }
+    let settings = Settings::default().with_patch(Value::Object(legacy)).unwrap_or_default();
+    if let Err(e) = settings.save(&path) {
+        eprintln!("Failed to write settings: {}", e);
+    }
+    settings
+}
 
-    let mut path = get_config_dir();
-    path.push("auth.json");
-    let json = fs::read_to_string(&path)?;
-    let state: BlinkAuthState = serde_json::from_str(&json)?;
+pub fn save_auth(state: &BlinkAuthState) -> Result<()> {
+    auth_store().save(state)
+}
 
-    // Best-effort migration to keychain storage.
-    if use_keychain() {
-        let _ = save_auth(&state);
-    }
-    Ok(state)
+pub fn load_auth() -> Result<BlinkAuthState> {
+    auth_store().load()
 }
 
 pub fn clear_auth() -> Result<()> {
-    if use_keychain() {
-        if let Ok(entry) = keyring_entry() {
-            let _ = entry.delete_password();
-        }
-    }
-
-    let mut path = get_config_dir();
-    path.push("auth.json");
-    if path.exists() {
-        fs::remove_file(path)?;
-    }
-    Ok(())
+    auth_store().clear()
 }
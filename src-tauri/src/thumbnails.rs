@@ -0,0 +1,230 @@
+use crate::storage;
+use crate::thumbnail_cache::{self, CacheStatus, Revalidations};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const CACHE_DIR: &str = "thumbnails";
+const INDEX_FILE: &str = "index.json";
+/// Camera thumbnails keep their path between refreshes, so entries can't live forever.
+/// Older ones are kept to fall back on while Blink can't be reached.
+const TTL_SECS: i64 = 6 * 60 * 60;
+pub const MAX_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    path: String,
+    size: u64,
+    fetched_at: i64,
+    last_access: i64,
+    /// Absent for entries written before the type was recorded; callers sniff those.
+    #[serde(default)]
+    content_type: Option<String>,
+}
+
+pub struct CachedThumbnail {
+    pub file: PathBuf,
+    pub content_type: Option<String>,
+    pub status: CacheStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ThumbnailCacheStats {
+    pub entries: usize,
+    pub bytes: u64,
+    pub max_bytes: u64,
+    pub hits: u64,
+    pub misses: u64,
+    /// Expired copies served because Blink couldn't be reached.
+    pub stale: u64,
+    pub dir: String,
+}
+
+pub fn cache_key(path: &str) -> String {
+    Sha256::digest(path.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// On-disk thumbnail cache under the config dir, keyed by a hash of the Blink path and
+/// trimmed least-recently-used first once it grows past `MAX_CACHE_BYTES`.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    index: std::sync::Mutex<HashMap<String, CacheEntry>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    stale: AtomicU64,
+    pub revalidations: Revalidations,
+}
+
+impl ThumbnailCache {
+    pub fn load() -> Self {
+        Self::in_dir(storage::get_config_dir().join(CACHE_DIR))
+    }
+
+    pub fn in_dir(dir: PathBuf) -> Self {
+        let _ = fs::create_dir_all(&dir);
+        let mut index: HashMap<String, CacheEntry> = fs::read_to_string(dir.join(INDEX_FILE))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        index.retain(|key, _| dir.join(format!("{}.jpg", key)).exists());
+        Self {
+            dir,
+            index: std::sync::Mutex::new(index),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            stale: AtomicU64::new(0),
+            revalidations: Revalidations::default(),
+        }
+    }
+
+    fn file_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.jpg", key))
+    }
+
+    fn save_index(&self, index: &HashMap<String, CacheEntry>) {
+        if let Ok(json) = serde_json::to_string(index) {
+            let _ = fs::write(self.dir.join(INDEX_FILE), json);
+        }
+    }
+
+    /// Returns the cached file for `path` if it is still fresh. Expired entries stay until
+    /// they are replaced or evicted, for `stale` to fall back on.
+    pub fn lookup(&self, path: &str) -> Option<CachedThumbnail> {
+        let key = cache_key(path);
+        let now = chrono::Utc::now().timestamp();
+        let mut index = self.index.lock().unwrap();
+        let file = self.file_for(&key);
+        if !file.exists() {
+            index.remove(&key);
+        }
+        let fresh = match index.get_mut(&key) {
+            Some(entry) if now - entry.fetched_at < TTL_SECS => {
+                entry.last_access = now;
+                Some(entry.content_type.clone())
+            }
+            _ => None,
+        };
+        if let Some(content_type) = fresh {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Some(CachedThumbnail { file, content_type, status: CacheStatus::Hit });
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        None
+    }
+
+    /// Any copy of `path`, however old, or failing that the newest copy of the same
+    /// thumbnail under another `ts`; for when fetching it from Blink just failed.
+    pub fn stale(&self, path: &str) -> Option<CachedThumbnail> {
+        let base = thumbnail_cache::base_path(path);
+        let now = chrono::Utc::now().timestamp();
+        let mut index = self.index.lock().unwrap();
+        let exact = cache_key(path);
+        let key = if index.contains_key(&exact) {
+            exact
+        } else {
+            index.iter()
+                .filter(|(_, e)| thumbnail_cache::base_path(&e.path) == base)
+                .max_by_key(|(_, e)| e.fetched_at)
+                .map(|(key, _)| key.clone())?
+        };
+        let file = self.file_for(&key);
+        if !file.exists() {
+            index.remove(&key);
+            return None;
+        }
+        let entry = index.get_mut(&key)?;
+        entry.last_access = now;
+        self.stale.fetch_add(1, Ordering::Relaxed);
+        Some(CachedThumbnail { file, content_type: entry.content_type.clone(), status: CacheStatus::Stale })
+    }
+
+    pub fn store(&self, path: &str, bytes: &[u8], content_type: &str) -> Result<CachedThumbnail> {
+        let key = cache_key(path);
+        let file = self.file_for(&key);
+        fs::write(&file, bytes)?;
+
+        let now = chrono::Utc::now().timestamp();
+        let mut index = self.index.lock().unwrap();
+        index.insert(key.clone(), CacheEntry {
+            path: path.to_string(),
+            size: bytes.len() as u64,
+            fetched_at: now,
+            last_access: now,
+            content_type: Some(content_type.to_string()),
+        });
+        self.evict(&mut index, &key);
+        self.save_index(&index);
+        Ok(CachedThumbnail { file, content_type: Some(content_type.to_string()), status: CacheStatus::Fresh })
+    }
+
+    /// Removes least-recently-used entries until the cache fits, never touching `keep`.
+    fn evict(&self, index: &mut HashMap<String, CacheEntry>, keep: &str) {
+        let mut total: u64 = index.values().map(|e| e.size).sum();
+        if total <= MAX_CACHE_BYTES {
+            return;
+        }
+        let mut by_age: Vec<(String, i64, u64)> = index.iter()
+            .filter(|(key, _)| key.as_str() != keep)
+            .map(|(key, e)| (key.clone(), e.last_access, e.size))
+            .collect();
+        by_age.sort_by_key(|(_, last_access, _)| *last_access);
+        for (key, _, size) in by_age {
+            if total <= MAX_CACHE_BYTES {
+                break;
+            }
+            let _ = fs::remove_file(self.file_for(&key));
+            index.remove(&key);
+            total -= size;
+        }
+    }
+
+    /// Drops every entry for the same thumbnail as `path`, whatever its `ts` suffix.
+    pub fn invalidate(&self, path: &str) -> usize {
+        let base = thumbnail_cache::base_path(path);
+        let mut index = self.index.lock().unwrap();
+        let stale: Vec<String> = index.iter()
+            .filter(|(_, e)| thumbnail_cache::base_path(&e.path) == base)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &stale {
+            let _ = fs::remove_file(self.file_for(key));
+            index.remove(key);
+        }
+        if !stale.is_empty() {
+            self.save_index(&index);
+        }
+        stale.len()
+    }
+
+    /// Deletes all cached files and returns the number of bytes freed.
+    pub fn clear(&self) -> Result<u64> {
+        let mut index = self.index.lock().unwrap();
+        let freed = index.values().map(|e| e.size).sum();
+        for key in index.keys() {
+            let _ = fs::remove_file(self.file_for(key));
+        }
+        index.clear();
+        self.save_index(&index);
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.stale.store(0, Ordering::Relaxed);
+        Ok(freed)
+    }
+
+    pub fn stats(&self) -> ThumbnailCacheStats {
+        let index = self.index.lock().unwrap();
+        ThumbnailCacheStats {
+            entries: index.len(),
+            bytes: index.values().map(|e| e.size).sum(),
+            max_bytes: MAX_CACHE_BYTES,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            stale: self.stale.load(Ordering::Relaxed),
+            dir: self.dir.to_string_lossy().to_string(),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use crate::blink::{BlinkError, Network};
+use crate::{shared, AppState};
+use chrono::{DateTime, Local};
+use std::time::Duration;
+use tauri::menu::{CheckMenuItem, Menu, MenuEvent, MenuItem, PredefinedMenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager, Wry};
+
+const TRAY_ID: &str = "main";
+const REFRESH_INTERVAL: Duration = Duration::from_secs(120);
+const ARM_ITEM_PREFIX: &str = "arm:";
+
+#[derive(Clone, Default)]
+enum TrayStatus {
+    #[default]
+    Loading,
+    Updated(DateTime<Local>),
+    SignedOut,
+    Failed(String),
+}
+
+impl TrayStatus {
+    fn line(&self) -> String {
+        match self {
+            TrayStatus::Loading => "Loading…".to_string(),
+            TrayStatus::Updated(at) => format!("Updated {}", at.format("%H:%M")),
+            TrayStatus::SignedOut => "Sign in required".to_string(),
+            TrayStatus::Failed(e) => format!("Refresh failed: {}", e),
+        }
+    }
+}
+
+/// Last known tray menu contents.
+#[derive(Default)]
+pub struct TrayState {
+    networks: std::sync::Mutex<Vec<Network>>,
+    status: std::sync::Mutex<TrayStatus>,
+}
+
+impl TrayState {
+    fn set_status(&self, status: TrayStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Creates the tray icon and starts the periodic homescreen refresh.
+pub fn init(app: &AppHandle) -> tauri::Result<()> {
+    let menu = build_menu(app)?;
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip("Blink Monitor")
+        .menu(&menu)
+        .on_menu_event(handle_menu_event);
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+
+    let handle = app.clone();
+    tauri::async_runtime::spawn(async move {
+        loop {
+            refresh(&handle).await;
+            tokio::time::sleep(REFRESH_INTERVAL).await;
+        }
+    });
+    Ok(())
+}
+
+pub fn teardown(app: &AppHandle) {
+    let _ = app.remove_tray_by_id(TRAY_ID);
+}
+
+/// Records an arm change made elsewhere (e.g. the main window) so the checkbox matches.
+pub fn set_network_armed(app: &AppHandle, network_id: i64, armed: bool) {
+    let state = app.state::<AppState>();
+    if let Some(network) = state.tray.networks.lock().unwrap().iter_mut().find(|n| n.id == network_id) {
+        network.armed = armed;
+    }
+    rebuild_menu(app);
+}
+
+fn build_menu(app: &AppHandle) -> tauri::Result<Menu<Wry>> {
+    let state = app.state::<AppState>();
+    let status = state.tray.status.lock().unwrap().clone();
+    let networks = state.tray.networks.lock().unwrap().clone();
+    let signed_out = matches!(status, TrayStatus::SignedOut);
+
+    let menu = Menu::new(app)?;
+    menu.append(&MenuItem::with_id(app, "status", status.line(), false, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    if networks.is_empty() {
+        menu.append(&MenuItem::with_id(app, "no-networks", "No networks", false, None::<&str>)?)?;
+    }
+    for network in &networks {
+        let label = format!("{} — Armed", network.name);
+        let id = format!("{}{}", ARM_ITEM_PREFIX, network.id);
+        menu.append(&CheckMenuItem::with_id(app, id, label, !signed_out, network.armed, None::<&str>)?)?;
+    }
+    menu.append(&PredefinedMenuItem::separator(app)?)?;
+    menu.append(&MenuItem::with_id(app, "open", "Open Blink Monitor", true, None::<&str>)?)?;
+    menu.append(&PredefinedMenuItem::quit(app, Some("Quit"))?)?;
+    Ok(menu)
+}
+
+fn rebuild_menu(app: &AppHandle) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else { return };
+    match build_menu(app) {
+        Ok(menu) => {
+            let _ = tray.set_menu(Some(menu));
+        }
+        Err(e) => eprintln!("Failed to rebuild tray menu: {}", e),
+    }
+}
+
+/// Re-reads the networks from Blink and rebuilds the menu.
+pub async fn refresh(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let result = shared::snapshot(&state.blink_client).await.get_homescreen().await;
+
+    match result {
+        Ok(homescreen) => {
+            *state.tray.networks.lock().unwrap() = homescreen.networks;
+            state.tray.set_status(TrayStatus::Updated(Local::now()));
+        }
+        Err(e) if matches!(e.downcast_ref::<BlinkError>(), Some(BlinkError::AuthExpired | BlinkError::NotLoggedIn)) => {
+            state.tray.set_status(TrayStatus::SignedOut)
+        }
+        // Keep showing the last known networks; only the status line changes.
+        Err(e) => state.tray.set_status(TrayStatus::Failed(e.to_string())),
+    }
+    rebuild_menu(app);
+}
+
+fn handle_menu_event(app: &AppHandle, event: MenuEvent) {
+    let id = event.id().as_ref();
+    if id == "open" {
+        show_main_window(app);
+    } else if let Some(network_id) = id.strip_prefix(ARM_ITEM_PREFIX).and_then(|s| s.parse::<i64>().ok()) {
+        let app = app.clone();
+        tauri::async_runtime::spawn(async move { toggle_arm(&app, network_id).await });
+    }
+}
+
+async fn toggle_arm(app: &AppHandle, network_id: i64) {
+    let state = app.state::<AppState>();
+    let currently_armed = state.tray.networks.lock().unwrap()
+        .iter()
+        .find(|n| n.id == network_id)
+        .map(|n| n.armed);
+    let Some(currently_armed) = currently_armed else { return };
+
+    match crate::arm_network(&state, network_id, !currently_armed, None).await {
+        Ok(verification) => {
+            state.tray.set_status(match verification.warning {
+                Some(warning) => TrayStatus::Failed(warning),
+                None => TrayStatus::Updated(Local::now()),
+            });
+            set_network_armed(app, network_id, verification.state.unwrap_or(!currently_armed));
+        }
+        Err(e) => {
+            // The menu toggled the checkbox optimistically; rebuilding restores the real state.
+            state.tray.set_status(TrayStatus::Failed(e.message));
+            rebuild_menu(app);
+        }
+    }
+}
+
+pub fn show_main_window(app: &AppHandle) {
+    if let Some(window) = app.get_webview_window("main") {
+        let _ = window.unminimize();
+        let _ = window.show();
+        let _ = window.set_focus();
+    }
+}
@@ -17,17 +17,26 @@ interface Network {
   armed: boolean;
 }
 
+const REAUTH_MESSAGE = "Blink ended your session (for example after a password change). Please sign in again.";
+
 function App() {
   const [email, setEmail] = useState("");
   const [password, setPassword] = useState("");
   const [pin, setPin] = useState("");
-  const [step, setStep] = useState<"login" | "pin" | "dashboard">("login");
+  const [step, setStep] = useState<"login" | "pin" | "amazon" | "dashboard">("login");
+  // Accounts that sign in with Amazon: the Login with Amazon page and, when Amazon wants
+  // more than a password, the address the user's browser ended on.
+  const [amazonSigninUrl, setAmazonSigninUrl] = useState<string | null>(null);
+  const [amazonCallback, setAmazonCallback] = useState("");
   const [activeTab, setActiveTab] = useState("cameras");
   const [cameras, setCameras] = useState<Camera[]>([]);
   const [networks, setNetworks] = useState<Network[]>([]);
   const [media, setMedia] = useState<Media[]>([]);
   const [loading, setLoading] = useState(false);
   const [error, setError] = useState("");
+  // Blink rate-limited sign-in; the buttons stay disabled until this time (ms since epoch).
+  const [loginRetryAt, setLoginRetryAt] = useState<number | null>(null);
+  const [loginWaitSecs, setLoginWaitSecs] = useState(0);
   const [dashboardNotice, setDashboardNotice] = useState<string | null>(null);
   const [mediaBaseUrl, setMediaBaseUrl] = useState<string | null>(null);
   const [playingItems, setPlayingItems] = useState<Map<number, { id: number, type: 'camera' | 'media', url: string, camera?: Camera }>>(new Map());
@@ -54,6 +63,8 @@ function App() {
     }
   });
   const [thumbCache, setThumbCache] = useState<Map<string, string>>(() => {
+    // Desktop tiles load signed localhost URLs, which don't outlive the app.
+    if (apiClient.isDesktop) return new Map();
     const raw = localStorage.getItem("blink_thumb_cache_v1");
     if (!raw) return new Map();
     try {
@@ -86,6 +97,8 @@ function App() {
       console.warn("Thumb cache prune failed:", e);
     }
   }, []);
+  // Desktop thumbnails asked for and not yet in `thumbCache`, so refills don't repeat them.
+  const requestedThumbsRef = useRef<Set<string>>(new Set());
   const [mediaThumbCache, setMediaThumbCache] = useState<Map<string, string>>(() => {
     const raw = localStorage.getItem("blink_media_thumb_cache_v1");
     if (!raw) return new Map();
@@ -224,9 +237,9 @@ function App() {
       
       const nets = parsedHome.networks || [];
       const cams = [
-        ...(parsedHome.cameras || []).map((c: any) => ({ ...c, product_type: c.type || "camera", serial: c.serial })),
-        ...(parsedHome.owls || []).map((c: any) => ({ ...c, product_type: c.type || "owl", serial: c.serial })),
-        ...(parsedHome.doorbells || []).map((c: any) => ({ ...c, product_type: c.type || "doorbell", serial: c.serial }))
+        ...(parsedHome.cameras || []).map((c: any) => ({ ...c, product_type: c.type || "camera", device_family: "camera", serial: c.serial })),
+        ...(parsedHome.owls || []).map((c: any) => ({ ...c, product_type: c.type || "owl", device_family: "owl", serial: c.serial })),
+        ...(parsedHome.doorbells || []).map((c: any) => ({ ...c, product_type: c.type || "doorbell", device_family: "doorbell", serial: c.serial }))
       ];
       
       setNetworks(nets);
@@ -299,9 +312,10 @@ function App() {
       setLastRefresh(new Date());
     } catch (e: any) {
       const errStr = typeof e === 'string' ? e : String(e);
-      if (errStr.includes("AUTH_EXPIRED") || errStr.includes("401") || errStr.toLowerCase().includes("refresh token")) {
+      const revoked = e?.kind === "reauth_required" || e?.reason === "reauth_required";
+      if (revoked || e?.kind === "auth_expired" || errStr.includes("AUTH_EXPIRED") || errStr.includes("401") || errStr.toLowerCase().includes("refresh token")) {
         handleLogout();
-        setError("Session expired. Please sign in again.");
+        setError(revoked ? REAUTH_MESSAGE : "Session expired. Please sign in again.");
       } else {
         console.error("Fetch error:", e);
       }
@@ -342,8 +356,11 @@ function App() {
           if (!granted) apiClient.requestNotificationPermission();
         });
 
-        const authed = await apiClient.checkAuth();
-        if (authed) {
+        const authState = await apiClient.checkAuth();
+        if (authState === "reauth_required") {
+          setError(REAUTH_MESSAGE);
+        }
+        if (authState === "logged_in") {
           if (apiClient.isDesktop) {
             const port = await getServerPortWithRetry();
             setMediaBaseUrl(apiClient.buildMediaBaseUrl(port));
@@ -369,6 +386,49 @@ function App() {
     }
   }, []);
 
+  useEffect(() => {
+    let unlisten: (() => void) | null = null;
+    apiClient.onServerRestarted((port) => {
+      setMediaBaseUrl(apiClient.buildMediaBaseUrl(port));
+      // Signed thumbnail URLs name the old port; refill them.
+      requestedThumbsRef.current.clear();
+      setThumbCache(new Map());
+      setMediaThumbCache(new Map());
+    }).then((fn) => { unlisten = fn; });
+    return () => {
+      if (unlisten) unlisten();
+    };
+  }, []);
+
+  useEffect(() => {
+    let unlisten: (() => void) | null = null;
+    apiClient.onAuthExpired((error) => {
+      handleLogout();
+      setError(error.kind === "reauth_required" ? REAUTH_MESSAGE : "Session expired. Please sign in again.");
+    }).then((fn) => { unlisten = fn; });
+    return () => {
+      if (unlisten) unlisten();
+    };
+  }, [handleLogout]);
+
+  useEffect(() => {
+    let unlisten: (() => void) | null = null;
+    apiClient.onThumbnailUpdated((run) => {
+      // The path stays the same across refreshes, so drop the cached image and refill.
+      localStorage.removeItem(`blink_thumb_${run.thumbnail}`);
+      requestedThumbsRef.current.delete(run.thumbnail);
+      setThumbCache(prev => {
+        if (!prev.has(run.thumbnail)) return prev;
+        const next = new Map(prev);
+        next.delete(run.thumbnail);
+        return next;
+      });
+    }).then((fn) => { unlisten = fn; });
+    return () => {
+      if (unlisten) unlisten();
+    };
+  }, []);
+
   useEffect(() => {
     if (step !== "dashboard") return;
     const interval = setInterval(() => {
@@ -417,7 +477,11 @@ function App() {
 
   const toggleArm = useCallback(async (networkId: number, currentlyArmed: boolean) => {
     try {
-      await apiClient.setNetworkArm(networkId, !currentlyArmed);
+      const result = await apiClient.setNetworkArm(networkId, !currentlyArmed);
+      if (!result.confirmed && result.warning) {
+        setDashboardNotice(`Not confirmed: ${result.warning}`);
+        window.setTimeout(() => setDashboardNotice(null), 6000);
+      }
       fetchData();
     } catch (e: any) {
       console.error(e);
@@ -494,12 +558,16 @@ function App() {
     if (!mediaBaseUrl) return;
     const mediaUrl = item.media_url || (typeof item.media === "string" ? item.media : "");
     if (!mediaUrl) return;
-    const url = `${mediaBaseUrl}/proxy?url=${encodeURIComponent(mediaUrl)}`;
+    const url = await apiClient.getClipUrl(mediaUrl);
     setPlayingItems(new Map([[item.id, {
       id: item.id,
       type: 'media',
       url
     }]]));
+    if (item.watched === false) {
+      setMedia(prev => prev.map(m => m.id === item.id ? { ...m, watched: true } : m));
+      apiClient.markWatched([item.id]).catch((e) => console.warn("Mark watched failed:", e));
+    }
   }, [mediaBaseUrl]);
 
   useEffect(() => {
@@ -569,7 +637,8 @@ function App() {
     setDeletingSelected(true);
     try {
       const selectedItems = media.filter(item => selectedMediaIds.has(item.id));
-      const remaining = await apiClient.deleteMediaItems(selectedItems);
+      const results = await apiClient.deleteMediaItems(selectedItems);
+      const remaining = results.filter(r => r.status === "still_present").map(r => r.id);
       const selectedIds = Array.from(selectedMediaIds);
       const remainingSet = new Set(remaining);
       const deletedIds = selectedIds.filter(id => !remainingSet.has(id));
@@ -639,10 +708,47 @@ function App() {
   }, [media]);
 
 
+  useEffect(() => {
+    let active = true;
+    const unlisteners: Array<() => void> = [];
+    // Point each tile at a signed localhost URL once its image is in the disk cache, so it
+    // streams into the <img> instead of crossing IPC as base64.
+    apiClient.onThumbnailReady(async (ready) => {
+      if (!requestedThumbsRef.current.has(ready.path)) return;
+      const url = await apiClient.getThumbnailUrl(ready.path).catch(() => null);
+      if (!active || !url) return;
+      requestedThumbsRef.current.delete(ready.path);
+      setThumbCache(prev => {
+        if (prev.get(ready.path) === url) return prev;
+        const next = new Map(prev);
+        next.set(ready.path, url);
+        return next;
+      });
+    }).then((fn) => (active ? unlisteners.push(fn) : fn()));
+    // Let the next refill try a failed tile again.
+    apiClient.onThumbnailFailed((failed) => {
+      requestedThumbsRef.current.delete(failed.path);
+    }).then((fn) => (active ? unlisteners.push(fn) : fn()));
+    return () => {
+      active = false;
+      unlisteners.forEach(fn => fn());
+    };
+  }, []);
+
   useEffect(() => {
     let cancelled = false;
     async function fillThumbCache() {
       if (!mediaBaseUrl) return;
+      if (apiClient.isDesktop) {
+        // Fetch the missing tiles together; each one is filled from its `thumbnail-ready`
+        // event as it lands.
+        const requested = requestedThumbsRef.current;
+        const missing = Array.from(new Set(cameras.map(cam => cam.thumbnail).filter(path => path && !thumbCache.has(path) && !requested.has(path))));
+        if (missing.length === 0) return;
+        missing.forEach(path => requested.add(path));
+        apiClient.prefetchThumbnails(missing).catch(() => missing.forEach(path => requested.delete(path)));
+        return;
+      }
       for (const cam of cameras) {
         if (!cam.thumbnail) continue;
         if (thumbCache.has(cam.thumbnail)) continue;
@@ -693,7 +799,9 @@ function App() {
         if (!thumbPath) continue;
         if (mediaThumbCache.has(thumbPath)) continue;
         try {
-          const dataUrl = await apiClient.getThumbnailBase64(thumbPath);
+          const dataUrl = apiClient.isDesktop
+            ? await apiClient.getThumbnailUrl(thumbPath)
+            : await apiClient.getThumbnailBase64(thumbPath);
           if (cancelled) return;
           setMediaThumbCache(prev => {
             if (prev.has(thumbPath)) return prev;
@@ -717,25 +825,75 @@ function App() {
     };
   }, [media, mediaBaseUrl, mediaThumbCache]);
 
+  useEffect(() => {
+    if (loginRetryAt == null) return;
+    const tick = () => {
+      const remaining = Math.ceil((loginRetryAt - Date.now()) / 1000);
+      if (remaining <= 0) {
+        setLoginRetryAt(null);
+        setLoginWaitSecs(0);
+      } else {
+        setLoginWaitSecs(remaining);
+      }
+    };
+    tick();
+    const timer = window.setInterval(tick, 1000);
+    return () => window.clearInterval(timer);
+  }, [loginRetryAt]);
+
+  function handleAuthError(e: any) {
+    setError(typeof e === "string" ? e : e?.message || String(e));
+    if (typeof e?.retryAfterSecs === "number" && e.retryAfterSecs > 0) {
+      setLoginRetryAt(Date.now() + e.retryAfterSecs * 1000);
+    }
+  }
+
+  async function enterDashboard() {
+    if (apiClient.isDesktop) {
+      const port = await getServerPortWithRetry();
+      setMediaBaseUrl(apiClient.buildMediaBaseUrl(port));
+    } else {
+      setMediaBaseUrl(apiClient.buildMediaBaseUrl());
+    }
+    await fetchData();
+    setStep("dashboard");
+  }
+
+  /** Moves on from a sign-in step by its status: PIN entry, Amazon sign-in, or the dashboard. */
+  async function continueLogin(result: string) {
+    if (result === "2FA_REQUIRED") {
+      setStep("pin");
+    } else if (result === "AMAZON_LOGIN_REQUIRED") {
+      setAmazonSigninUrl(await apiClient.getAmazonSigninUrl());
+      setStep("amazon");
+    } else {
+      await enterDashboard();
+    }
+  }
+
   async function handleLogin() {
     setLoading(true);
     setError("");
     try {
-      const result = await apiClient.login(email, password);
-      if (result === "2FA_REQUIRED") {
-        setStep("pin");
-      } else {
-        if (apiClient.isDesktop) {
-          const port = await getServerPortWithRetry();
-          setMediaBaseUrl(apiClient.buildMediaBaseUrl(port));
-        } else {
-          setMediaBaseUrl(apiClient.buildMediaBaseUrl());
-        }
-        await fetchData();
-        setStep("dashboard");
-      }
+      await continueLogin(await apiClient.login(email, password));
+    } catch (e: any) {
+      handleAuthError(e);
+    } finally {
+      setLoading(false);
+    }
+  }
+
+  async function handleAmazonLogin() {
+    setLoading(true);
+    setError("");
+    try {
+      const callback = amazonCallback.trim();
+      const result = callback
+        ? await apiClient.completeAmazonLogin(callback)
+        : await apiClient.loginAmazon(email, password);
+      await continueLogin(result);
     } catch (e: any) {
-      setError(typeof e === "string" ? e : e?.message || String(e));
+      handleAuthError(e);
     } finally {
       setLoading(false);
     }
@@ -746,16 +904,9 @@ function App() {
     setError("");
     try {
       await apiClient.verifyPin(pin);
-      if (apiClient.isDesktop) {
-        const port = await getServerPortWithRetry();
-        setMediaBaseUrl(apiClient.buildMediaBaseUrl(port));
-      } else {
-        setMediaBaseUrl(apiClient.buildMediaBaseUrl());
-      }
-      await fetchData();
-      setStep("dashboard");
+      await enterDashboard();
     } catch (e: any) {
-      setError(typeof e === "string" ? e : e?.message || String(e));
+      handleAuthError(e);
     } finally {
       setLoading(false);
     }
@@ -802,9 +953,65 @@ function App() {
               type="button"
               className="w-full bg-[var(--app-accent)] hover:bg-[var(--app-accent-strong)] text-white font-semibold h-11 transition-all rounded-lg disabled:opacity-50" 
               onClick={handleLogin}
-              disabled={loading}
+              disabled={loading || loginWaitSecs > 0}
+            >
+              {loading ? "Authenticating..." : loginWaitSecs > 0 ? `Try again in ${loginWaitSecs}s` : "Sign In"}
+            </button>
+          </CardContent>
+        </Card>
+      </div>
+    );
+  }
+
+  if (step === "amazon") {
+    return (
+      <div className="app-shell flex h-screen w-screen items-center justify-center text-white p-6">
+        <Card className="w-full max-w-[380px] border-white/10 bg-[var(--app-surface-2)] text-white relative z-10 shadow-2xl">
+          <CardHeader className="text-center">
+            <CardTitle className="text-xl font-semibold">Sign in with Amazon</CardTitle>
+            <CardDescription className="text-slate-400">This Blink account is linked to Amazon. Enter your Amazon password.</CardDescription>
+          </CardHeader>
+          <CardContent className="space-y-4">
+            <Input
+              type="email"
+              placeholder="Amazon Email"
+              value={email}
+              onChange={(e) => setEmail(e.target.value)}
+              className="h-11"
+            />
+            <Input
+              type="password"
+              placeholder="Amazon Password"
+              value={password}
+              onChange={(e) => setPassword(e.target.value)}
+              className="h-11"
+            />
+            <div className="space-y-1.5">
+              <p className="text-xs text-slate-400 leading-relaxed">
+                If Amazon asks for a code or a captcha, sign in
+                {amazonSigninUrl ? <> on <a href={amazonSigninUrl} target="_blank" rel="noreferrer" className="text-[var(--app-accent)] underline">this page</a></> : " in a browser"}
+                {" "}and paste the address it ends on:
+              </p>
+              <Input
+                type="text"
+                placeholder="https://..."
+                value={amazonCallback}
+                onChange={(e) => setAmazonCallback(e.target.value)}
+                className="h-10 font-mono text-xs"
+              />
+            </div>
+            {error && (
+              <div className="p-3 rounded-lg bg-red-500/10 border border-red-500/20">
+                <p className="text-xs text-red-400 font-medium leading-relaxed">{error}</p>
+              </div>
+            )}
+            <button
+              type="button"
+              className="w-full bg-[var(--app-accent)] hover:bg-[var(--app-accent-strong)] text-white font-semibold h-11 transition-all rounded-lg disabled:opacity-50"
+              onClick={handleAmazonLogin}
+              disabled={loading || loginWaitSecs > 0}
             >
-              {loading ? "Authenticating..." : "Sign In"}
+              {loading ? "Authenticating..." : loginWaitSecs > 0 ? `Try again in ${loginWaitSecs}s` : "Continue"}
             </button>
           </CardContent>
         </Card>
@@ -838,9 +1045,9 @@ function App() {
               type="button"
               className="w-full bg-[var(--app-accent)] hover:bg-[var(--app-accent-strong)] text-white font-semibold h-11 transition-all rounded-lg disabled:opacity-50" 
               onClick={handleVerifyPin}
-              disabled={loading}
+              disabled={loading || loginWaitSecs > 0}
             >
-              {loading ? "Verifying..." : "Confirm PIN"}
+              {loading ? "Verifying..." : loginWaitSecs > 0 ? `Try again in ${loginWaitSecs}s` : "Confirm PIN"}
             </button>
           </CardContent>
         </Card>
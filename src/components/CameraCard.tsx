@@ -1,5 +1,6 @@
 import { useRef, useState, useEffect } from "react";
 import { Button } from "@/components/ui/button";
+import apiClient from "@/lib/apiClient";
 import { Card, CardDescription, CardHeader, CardTitle } from "@/components/ui/card";
 import { Badge } from "@/components/ui/badge";
 import { Battery, Wifi, Play, Square, Circle, Settings, Maximize2, Star, PictureInPicture, Volume2, VolumeX, Camera as CameraIcon } from "lucide-react";
@@ -21,6 +22,7 @@ export interface Camera {
   signals?: CameraSignals;
   network_id?: number;
   product_type: string;
+  device_family?: "camera" | "owl" | "doorbell";
   serial?: string;
 }
 
@@ -44,10 +46,13 @@ export function CameraCard({ camera, onLiveView, mediaBaseUrl, isPlaying, playUr
   const [streamStatus, setStreamStatus] = useState<string>("");
   const [isSettingsOpen, setIsSettingsOpen] = useState(false);
   const [isMuted, setIsMuted] = useState(true);
+  const [endsAt, setEndsAt] = useState<number | null>(null);
+  const [secondsLeft, setSecondsLeft] = useState<number | null>(null);
   const videoRef = useRef<HTMLVideoElement>(null);
   const wrapperRef = useRef<HTMLDivElement>(null);
 
-  const thumbUrl = mediaBaseUrl && camera.thumbnail 
+  // Desktop tiles get signed URLs from the app through `thumbnailDataUrl`.
+  const thumbUrl = !apiClient.isDesktop && mediaBaseUrl && camera.thumbnail
     ? `${mediaBaseUrl}/thumbnail?url=${encodeURIComponent(camera.thumbnail)}`
     : null;
   const effectiveThumbUrl = thumbnailDataUrl || thumbUrl;
@@ -81,6 +86,30 @@ export function CameraCard({ camera, onLiveView, mediaBaseUrl, isPlaying, playUr
     setIsMuted(nextMuted);
   };
 
+  // Blink refused to extend the liveview any further; count down to when it stops.
+  useEffect(() => {
+    setEndsAt(null);
+    if (!isPlaying) return;
+    let unlisten: (() => void) | null = null;
+    apiClient.onLiveExpiring((expiring) => {
+      if (expiring.camera_id === camera.id) setEndsAt(Date.parse(expiring.expires_at));
+    }).then((fn) => { unlisten = fn; });
+    return () => {
+      if (unlisten) unlisten();
+    };
+  }, [isPlaying, camera.id]);
+
+  useEffect(() => {
+    if (endsAt == null) {
+      setSecondsLeft(null);
+      return;
+    }
+    const tick = () => setSecondsLeft(Math.max(0, Math.ceil((endsAt - Date.now()) / 1000)));
+    tick();
+    const timer = window.setInterval(tick, 1000);
+    return () => window.clearInterval(timer);
+  }, [endsAt]);
+
   useEffect(() => {
     const video = videoRef.current;
     if (!video) return;
@@ -149,7 +178,10 @@ export function CameraCard({ camera, onLiveView, mediaBaseUrl, isPlaying, playUr
             <CardTitle className="text-base sm:text-lg font-semibold text-white leading-tight truncate">{camera.name}</CardTitle>
             <CardDescription className="text-[11px] sm:text-xs text-slate-500 font-medium truncate">
               {isPlaying ? (
-                <span className="text-[var(--app-accent)]">{streamStatus || "Connecting..."}</span>
+                <span className="text-[var(--app-accent)]">
+                  {streamStatus || "Connecting..."}
+                  {secondsLeft != null && ` · Blink ends this stream in ${secondsLeft}s`}
+                </span>
               ) : (
                 getCleanStatus(camera.status)
               )}
@@ -242,6 +274,7 @@ export function CameraCard({ camera, onLiveView, mediaBaseUrl, isPlaying, playUr
         networkId={camera.network_id || 0}
         cameraId={camera.id}
         productType={camera.product_type}
+        deviceFamily={camera.device_family}
         cameraName={camera.name}
       />
     </>
@@ -10,11 +10,13 @@ interface CameraSettingsProps {
   networkId: number;
   cameraId: number;
   productType: string;
+  deviceFamily?: "camera" | "owl" | "doorbell";
   cameraName: string;
 }
 
-export function CameraSettings({ isOpen, onClose, networkId, cameraId, productType, cameraName }: CameraSettingsProps) {
+export function CameraSettings({ isOpen, onClose, networkId, cameraId, productType, deviceFamily, cameraName }: CameraSettingsProps) {
   const [config, setConfig] = useState<any>(null);
+  const [original, setOriginal] = useState<any>(null);
   const [loading, setLoading] = useState(true);
   const [saving, setSaving] = useState(false);
   const [error, setError] = useState<string | null>(null);
@@ -25,6 +27,7 @@ export function CameraSettings({ isOpen, onClose, networkId, cameraId, productTy
     try {
       const res = await apiClient.getCameraConfig(networkId, cameraId, productType);
       setConfig(res.camera || res);
+      setOriginal(res.camera || res);
     } catch (e: any) {
       setError(e.toString());
     } finally {
@@ -42,9 +45,19 @@ export function CameraSettings({ isOpen, onClose, networkId, cameraId, productTy
     setSaving(true);
     setError(null);
     try {
-      const payload = productType === "owl" || productType === "mini" || productType === "tulip" || productType === "doorbell"
-        ? config
-        : { camera: config };
+      // Only what was edited: the rest of what Blink returned isn't settable and would be
+      // refused as unknown keys.
+      const changes = Object.fromEntries(
+        Object.entries(config).filter(([key, value]) => JSON.stringify(value) !== JSON.stringify(original?.[key]))
+      );
+      if (Object.keys(changes).length === 0) {
+        onClose();
+        return;
+      }
+      // Owls and doorbells take the config as-is; standard cameras expect it wrapped.
+      const payload = deviceFamily === "owl" || deviceFamily === "doorbell"
+        ? changes
+        : { camera: changes };
       await apiClient.updateCameraConfig(networkId, cameraId, productType, payload);
       onClose();
     } catch (e: any) {
@@ -13,6 +13,7 @@ export interface Media {
   media?: any;
   media_url?: string;
   created_at: string;
+  watched?: boolean;
   [key: string]: any;
 }
 
@@ -42,7 +43,8 @@ export function MediaCard({ item, onPlay, mediaBaseUrl, isPlaying, playUrl, onSt
   const [isFullscreen, setIsFullscreen] = useState(false);
   
   const thumbnailPath = item.thumbnail_url || (typeof item.thumbnail === "string" ? item.thumbnail : "");
-  const thumbUrl = mediaBaseUrl && thumbnailPath 
+  // Desktop tiles get signed URLs from the app through `thumbnailDataUrl`.
+  const thumbUrl = !apiClient.isDesktop && mediaBaseUrl && thumbnailPath
     ? `${mediaBaseUrl}/thumbnail?url=${encodeURIComponent(thumbnailPath)}`
     : null;
   const [thumbSrc, setThumbSrc] = useState<string | null>(thumbnailDataUrl || thumbUrl);
@@ -203,7 +205,10 @@ export function MediaCard({ item, onPlay, mediaBaseUrl, isPlaying, playUrl, onSt
   async function handleDownload(e: React.MouseEvent) {
     e.stopPropagation();
     try {
-      const fileName = `${item.device_name.replace(/[^a-z0-9]/gi, '_')}_${item.created_at.replace(/[:.]/g, '-')}.mp4`;
+      const created = new Date(item.created_at);
+      const pad = (n: number) => String(n).padStart(2, "0");
+      const stamp = `${created.getFullYear()}${pad(created.getMonth() + 1)}${pad(created.getDate())}_${pad(created.getHours())}${pad(created.getMinutes())}${pad(created.getSeconds())}`;
+      const fileName = `${item.device_name.replace(/[^a-z0-9_-]/gi, '-')}_${stamp}.mp4`;
       const mediaUrl = item.media_url || (typeof item.media === "string" ? item.media : "");
       if (!mediaUrl) {
         setDownloadError("Missing clip URL.");
@@ -217,6 +222,7 @@ export function MediaCard({ item, onPlay, mediaBaseUrl, isPlaying, playUrl, onSt
       setDownloadProgress(0);
       setDownloading(true);
       const completed = await apiClient.downloadClip({
+        item,
         url: mediaUrl,
         defaultFileName: fileName,
         downloadId,
@@ -336,7 +342,10 @@ export function MediaCard({ item, onPlay, mediaBaseUrl, isPlaying, playUrl, onSt
       </div>
       <CardHeader className="p-5 bg-[var(--app-surface)] flex flex-col gap-3 sm:flex-row sm:items-center sm:justify-between">
         <div className="space-y-1 min-w-0">
-          <CardTitle className="text-base sm:text-lg font-semibold text-white truncate leading-tight">{item.device_name}</CardTitle>
+          <CardTitle className="text-base sm:text-lg font-semibold text-white truncate leading-tight flex items-center gap-2">
+            {item.watched === false && <span className="w-2 h-2 rounded-full bg-sky-400 shrink-0" title="Not watched yet" />}
+            <span className="truncate">{item.device_name}</span>
+          </CardTitle>
           <CardDescription className="text-[11px] sm:text-xs text-slate-500 font-medium">
             {new Date(item.created_at).toLocaleString([], { dateStyle: 'medium', timeStyle: 'short' })}
           </CardDescription>
@@ -2,15 +2,691 @@ type DownloadProgressEvent = {
   id: string;
   received: number;
   total?: number;
+  done?: boolean;
+  cancelled?: boolean;
+  error?: string | null;
 };
 
 type DownloadOptions = {
+  item: any;
   url: string;
   defaultFileName: string;
   downloadId?: string;
   onProgress?: (pct: number) => void;
 };
 
+type DeleteResult = {
+  id: number;
+  status: "deleted" | "still_present" | "not_found" | "pending";
+};
+
+type NetworkArmResult = {
+  network_id: number;
+  ok: boolean;
+  error?: string;
+};
+
+type ArmVerification = {
+  requested: boolean;
+  confirmed: boolean;
+  state: boolean | null;
+  warning?: string;
+};
+
+type PendingRearm = {
+  network_id: number;
+  rearm_at: string;
+  disarmed_at: string;
+  attempts: number;
+  retry_at: string | null;
+  last_error: string | null;
+};
+
+type TemporaryDisarm = {
+  verification: ArmVerification;
+  rearm: PendingRearm;
+};
+
+type ProbeResult = {
+  url: string;
+  host_allowed: boolean;
+  auth_attached: boolean;
+  status: number | null;
+  content_type: string | null;
+  content_length: number | null;
+  total_bytes: number | null;
+  final_url: string | null;
+  latency_ms: number | null;
+  problem: string | null;
+};
+
+type MediaDiagnosis = {
+  media_id: number;
+  checked_at: string;
+  media: ProbeResult | null;
+  thumbnail: ProbeResult | null;
+  device_route: {
+    camera_id: number;
+    product_type: string;
+    family: "camera" | "owl" | "doorbell";
+    attempts: { family: "camera" | "owl" | "doorbell"; status: number | null }[];
+    learned_at: string;
+  } | null;
+};
+
+type BatteryPoint = {
+  ts: string;
+  /** Blink's battery bars as a percentage */
+  level: number;
+  voltage: number | null;
+  low: boolean;
+  hourly: boolean;
+};
+
+type BatteryHistory = {
+  camera_id: number;
+  points: BatteryPoint[];
+  days_remaining: number | null;
+};
+
+/** `counts[weekday][hour]`, Monday first, in the camera's network time zone. */
+type ActivityHeatmap = {
+  camera_id: number;
+  camera_name: string | null;
+  time_zone: string;
+  from: string;
+  to: string;
+  counts: number[][];
+  total: number;
+  per_day: { day: string; clips: number }[];
+  source: "index" | "live";
+};
+
+type ClipStoreConfig =
+  | { kind: "local"; dir: string }
+  | { kind: "s3"; endpoint: string; bucket: string; region?: string; prefix?: string }
+  | { kind: "webdav"; url: string; username?: string | null };
+
+type StoredClip = {
+  key: string;
+  size: number;
+  modified: string | null;
+};
+
+type StateExportSummary = {
+  path: string;
+  bytes: number;
+  auth: boolean;
+  media_index: boolean;
+};
+
+type StateImportSummary = {
+  exported_at: string;
+  auth: boolean;
+  media_index: boolean;
+  /** Folders from the archive this machine hasn't granted, left out of the settings */
+  dropped_folders: string[];
+};
+
+type DeleteProgress = {
+  job_id: string;
+  state: "running" | "done" | "cancelled";
+  total: number;
+  done: number;
+  failed: number[];
+  created_at: string;
+  finished_at: string | null;
+  last_error: string | null;
+};
+
+type TakeoutProgress = {
+  job_id: string;
+  state: "running" | "done" | "cancelled" | "failed";
+  phase: "metadata" | "listing" | "media" | "manifest";
+  total: number;
+  done: number;
+  bytes: number;
+  failed: number;
+  location: string;
+  manifest: string | null;
+  created_at: string;
+  finished_at: string | null;
+  last_error: string | null;
+};
+
+type ServerStatus = {
+  running: boolean;
+  port: number | null;
+  uptime_secs: number;
+  restarts: number;
+  last_error: string | null;
+};
+
+type TrustedClient = {
+  id: number;
+  name: string;
+  created_at: string | null;
+  current: boolean;
+};
+
+type RevokeOutcome = {
+  id: number;
+  current: boolean;
+  revoked: boolean;
+  signed_out: boolean;
+  warning: string | null;
+};
+
+type SelfTestStage = "storage" | "token" | "refresh" | "tier" | "homescreen" | "thumbnail" | "media_page";
+
+type LogTarget = "blink" | "http" | "immi" | "liveview";
+type LogLevel = "error" | "warn" | "info" | "debug" | "trace";
+
+type LevelOverride = {
+  target: LogTarget;
+  level: LogLevel;
+  expires_at: string;
+};
+
+type SelfTestReport = {
+  passed: boolean;
+  started_at: string;
+  failed_stage: SelfTestStage | null;
+  stages: {
+    stage: SelfTestStage;
+    status: "passed" | "failed" | "skipped";
+    duration_ms: number;
+    detail: string | null;
+    error: string | null;
+  }[];
+  clock_skew: {
+    skew_secs: number;
+    samples: number;
+    updated_at: string | null;
+    refresh_margin_secs: number;
+    warning: string | null;
+  };
+};
+
+type WebhookTarget = {
+  url: string;
+  kinds?: string[];
+  format?: "slack" | "discord" | "ntfy";
+  template?: string;
+  headers?: Record<string, string>;
+  method?: "POST" | "PUT";
+};
+
+type WebhookPayload = {
+  content_type: string;
+  body: string;
+  warning: string | null;
+};
+
+type AlertKind = "motion" | "offline" | "low_battery" | "usb_storage";
+
+type NotifierTarget = ({ kind: "ntfy"; server?: string; topic: string } | { kind: "gotify"; server: string }) & {
+  alerts: Partial<Record<AlertKind, "min" | "low" | "default" | "high" | "urgent">>;
+};
+
+type NotifyResult = {
+  target: string;
+  ok: boolean;
+  error: string | null;
+};
+
+/** One reason a camera config update was refused. */
+type ConfigProblem = {
+  key: string;
+  problem: string;
+};
+
+type FirmwareRecord = {
+  kind: "camera" | "sync_module";
+  device_id: number;
+  name: string;
+  network_id: number | null;
+  version: string;
+  previous_version: string | null;
+  observed_at: string;
+};
+
+type CameraUsage = {
+  id: number;
+  name: string;
+  usage: number | null;
+  clip_seconds: number | null;
+  lv_seconds: number | null;
+  local_bytes: number | null;
+};
+
+type UsageReport = {
+  range_days: number | null;
+  networks: { network_id: number; name: string; cameras: CameraUsage[] }[];
+};
+
+type CleanupEntry = {
+  session_id: string;
+  network_id: number;
+  camera_id: number;
+  started_at: string;
+  ended_at: string;
+  attempts: number;
+  deleted: number;
+  next_check_at: string;
+  last_error: string | null;
+};
+
+type PendingDeletion = {
+  id: number;
+  item: any;
+  due_at: string;
+};
+
+type Settings = {
+  version: number;
+  download_concurrency: number;
+  download_filename_template: string;
+  download_sidecar: boolean;
+  motion_alerts_enabled: boolean;
+  motion_interval_secs: number;
+  liveview_busy_retries: number;
+  liveview_retry_delay_secs: number;
+  liveview_read_timeout_secs: number;
+  liveview_cleanup_enabled: boolean;
+  liveview_cleanup_lookback_secs: number;
+  liveview_replay_secs: number;
+  liveview_recording_dir: string | null;
+  liveview_recording_format: "ts" | "mp4";
+  close_to_tray: boolean;
+  schedules: ScheduleEntry[];
+  media_index_enabled: boolean;
+  camera_prefs: Record<string, CameraPrefs>;
+  advanced_settings_enabled: boolean;
+  archive_dir: string | null;
+  archive_store: ClipStoreConfig | null;
+  event_retention_rows: number;
+  event_retention_days: number;
+  diagnostics_enabled: boolean;
+  webhooks: WebhookTarget[];
+  delete_batch_size: number;
+  delete_batch_delay_secs: number;
+  summary_report: { period: "daily" | "weekly"; time: string; day?: string } | null;
+  notifiers: NotifierTarget[];
+  thumbnail_refresh: {
+    enabled: boolean;
+    min_interval_minutes: number;
+    window: { start: string; end: string } | null;
+    min_battery_signal: number;
+    skip_snoozed: boolean;
+    skip_disarmed: boolean;
+  };
+  /** Desktop only: folders granted for downloads besides Downloads; can only be removed. */
+  download_dirs: string[];
+};
+
+type CameraPrefs = {
+  nickname?: string;
+  sort_order?: number;
+  hidden?: boolean;
+  liveview_quality?: string;
+  thumbnail_refresh_minutes?: number;
+};
+
+type LatencySummary = {
+  last_ms: number;
+  min_ms: number;
+  avg_ms: number;
+  max_ms: number;
+  samples: number;
+};
+
+type LiveSessionInfo = {
+  id: string;
+  network_id: number;
+  camera_id: number;
+  started_at: string;
+  subscriber_count: number;
+  bytes_sent: number;
+  dropped_chunks: number;
+  dropped_bytes: number;
+  recording_path: string | null;
+  params: any;
+  latency: {
+    connect_ms: number | null;
+    first_packet_ms: number | null;
+    keepalive_rtt: LatencySummary | null;
+    stream_delay: LatencySummary | null;
+  };
+  replay: {
+    window_secs: number;
+    buffered_secs: number;
+    buffered_bytes: number;
+  };
+  extends: number;
+  /** Set once Blink refused to extend the session again; the stream stops then. */
+  expires_at: string | null;
+};
+
+type LiveExpiring = {
+  session_id: string;
+  network_id: number;
+  camera_id: number;
+  expires_at: string;
+  seconds_left: number;
+  extends: number;
+};
+
+type ReplaySaved = {
+  session_id: string;
+  path: string;
+  bytes: number;
+  duration_secs: number;
+};
+
+type ThumbnailRefreshRun = {
+  camera_id: number;
+  network_id: number;
+  camera_name: string;
+  thumbnail: string;
+  requested_at: string;
+  error: string | null;
+};
+
+type RecordingInfo = {
+  path: string;
+  format: "ts" | "mp4";
+  bytes: number;
+  duration_secs: number | null;
+  started_at: string;
+  session_id: string | null;
+  network_id: number | null;
+  camera_id: number | null;
+  error: string | null;
+};
+
+type MediaFilter = {
+  from?: string;
+  to?: string;
+  camera_id?: number;
+  network_id?: number;
+  text?: string;
+  include_deleted?: boolean;
+  page?: number;
+  page_size?: number;
+};
+
+type IndexedMedia = {
+  id: number;
+  camera_id: number | null;
+  camera_name: string | null;
+  network_id: number | null;
+  network_name: string | null;
+  created_at: string | null;
+  media_type: string | null;
+  source: string | null;
+  media_url: string | null;
+  thumbnail_url: string | null;
+  local_path: string | null;
+  watched: boolean;
+  deleted: boolean;
+};
+
+type MediaSearchPage = {
+  items: IndexedMedia[];
+  total: number;
+  page: number;
+  page_size: number;
+  source: "index" | "live";
+};
+
+/** `applied`: the path now holds the value; `rejected`: Blink refused (see `error`); `unchanged`: accepted but not applied. */
+type DiscoveredSettingResult = {
+  path: string;
+  outcome: "applied" | "rejected" | "unchanged";
+  before: unknown;
+  after: unknown | null;
+  error: string | null;
+};
+
+type LiveviewFlag = "lv_save" | "extended_liveview" | "doorbell_auto_save";
+
+type LiveviewSetting = {
+  flag: LiveviewFlag;
+  device_kind: "network" | "sync_module" | "camera" | "owl" | "doorbell" | null;
+  /** The network id for network flags, the device id otherwise. */
+  device_id: number;
+  device_name: string | null;
+  key: string;
+  /** A boolean for toggles, seconds for `extended_liveview`. */
+  value: boolean | number;
+};
+
+type LiveviewSettingWrite = {
+  flag: LiveviewFlag;
+  device_id: number;
+  outcome: DiscoveredSettingResult["outcome"];
+  before: unknown;
+  after: unknown | null;
+  error: string | null;
+};
+
+type MotionNotifications = {
+  network_id: number;
+  /** `account` means the switch is the account-wide flag, so it covers every network. */
+  scope: "network" | "account";
+  key: string;
+  enabled: boolean | null;
+};
+
+type MotionNotificationWrite = {
+  network_id: number;
+  scope: MotionNotifications["scope"];
+  key: string;
+  outcome: DiscoveredSettingResult["outcome"];
+  before: boolean | null;
+  after: boolean | null;
+  error: string | null;
+};
+
+type UsbStorageState = "active" | "not_inserted" | "formatting" | "full" | "error" | "ejected" | "unknown";
+
+type LocalStorageStatus = {
+  network_id: number;
+  sync_module_id: number;
+  state: UsbStorageState;
+  present: boolean;
+  formatting: boolean;
+  full: boolean;
+  used_bytes: number | null;
+  capacity_bytes: number | null;
+  last_backup: string | null;
+  raw: any;
+};
+
+type UsbEjectResult = {
+  network_id: number;
+  sync_module_id: number;
+  command_id: number | null;
+  /** The drive is safe to remove only once this is true. */
+  finished: boolean;
+  status: LocalStorageStatus | null;
+};
+
+type NetworkSettingsPatch = {
+  name?: string;
+  time_zone?: string;
+  dst?: boolean;
+  locale?: string;
+  lv_save?: boolean;
+  video_quality?: string;
+};
+
+type NetworkSettings = {
+  id: number;
+  name?: string | null;
+  time_zone?: string | null;
+  dst?: boolean | null;
+  locale?: string | null;
+  lv_save?: boolean | null;
+  video_quality?: string | null;
+  [key: string]: unknown;
+};
+
+type NotificationConfig = {
+  motion?: boolean;
+  doorbell?: boolean;
+  low_battery?: boolean;
+  battery_dead?: boolean;
+  camera_offline?: boolean;
+  sync_module_offline?: boolean;
+  temperature?: boolean;
+  scheduling?: boolean;
+  camera_usage?: boolean;
+  wifi?: boolean;
+  local_storage?: boolean;
+  [key: string]: unknown;
+};
+
+type TimelineEvent = {
+  camera_id: number | null;
+  camera_name: string | null;
+  network_id: number | null;
+  start: string;
+  end: string;
+  clip_count: number;
+  clips: any[];
+};
+
+type ScheduleAction = "arm" | "disarm";
+
+type ScheduleEntry = {
+  id: string;
+  network_id: number;
+  action: ScheduleAction;
+  days: Array<"Mon" | "Tue" | "Wed" | "Thu" | "Fri" | "Sat" | "Sun">;
+  time: string;
+  enabled: boolean;
+};
+
+type NextScheduledChange = {
+  network_id: number;
+  entry_id: string;
+  action: ScheduleAction;
+  at: string;
+};
+
+type ScheduleOverview = {
+  entries: ScheduleEntry[];
+  upcoming: NextScheduledChange[];
+};
+
+type ScheduleRun = {
+  entry_id: string;
+  network_id: number;
+  action: ScheduleAction;
+  scheduled_for: string;
+  executed_at: string;
+  error: string | null;
+};
+
+type AuditEntry = {
+  ts: string;
+  action: string;
+  target: string;
+  ok: boolean;
+  error?: string;
+  source: string;
+};
+
+type AuditFilter = {
+  action?: string;
+  target?: string;
+  source?: string;
+  since?: string;
+  until?: string;
+  failed_only?: boolean;
+};
+
+type ThumbnailCacheStats = {
+  entries: number;
+  bytes: number;
+  max_bytes: number;
+  hits: number;
+  misses: number;
+  stale: number;
+  dir: string;
+};
+
+/** `stale`: Blink couldn't be reached, so an older copy was served and is being fetched again. */
+type ThumbnailCacheStatus = "hit" | "fresh" | "stale";
+
+type ThumbnailData = {
+  data_url: string;
+  cache: ThumbnailCacheStatus;
+};
+
+/** `cache` is null when the fetch failed and no older copy was kept; `error` says why. */
+type ThumbnailPrefetchResult = {
+  path: string;
+  cache: ThumbnailCacheStatus | null;
+  error?: string;
+};
+
+type ThumbnailReady = {
+  path: string;
+  cache_key: string;
+  cache: ThumbnailCacheStatus;
+};
+
+type ThumbnailFailed = {
+  path: string;
+  error: string;
+};
+
+type DownloadJob = {
+  id: string;
+  url: string;
+  path: string;
+  item: any;
+  sidecar: boolean;
+  status: "queued" | "running" | "completed" | "failed" | "cancelled";
+  received: number;
+  total?: number | null;
+  error?: string | null;
+};
+
+type DownloadQueueEvent = {
+  paused: boolean;
+  concurrency: number;
+  queued: number;
+  running: number;
+  completed: number;
+  failed: number;
+  cancelled: number;
+  received: number;
+  total: number;
+  jobs: DownloadJob[];
+};
+
+type MotionAlertState = {
+  enabled: boolean;
+  interval_secs: number;
+  last_seen_id?: number | null;
+};
+
+type MotionClip = {
+  id: number;
+  camera: string;
+  network_id?: number | null;
+  created_at?: string | null;
+  thumbnail?: string | null;
+  unwatched_count: number;
+};
+
+/** `reauth_required`: Blink revoked the saved session, so the user must sign in again. */
+type AuthState = "logged_out" | "logged_in" | "reauth_required";
+
 type NotificationPayload = {
   title: string;
   body?: string;
@@ -24,6 +700,33 @@ const isDesktop =
   (window as any).__TAURI__ &&
   target !== "web";
 
+/** Error for a failed API response, carrying Blink's own error code when the server passed one on. */
+async function responseError(res: Response) {
+  const body = await res.text();
+  let message = body || res.statusText;
+  let reason: string | undefined;
+  let problems: ConfigProblem[] | undefined;
+  // Errors clients are expected to branch on come as `{reason, message}` JSON.
+  if (res.headers.get("Content-Type")?.includes("application/json")) {
+    try {
+      const parsed = JSON.parse(body);
+      message = parsed.message ?? message;
+      reason = parsed.reason;
+      problems = parsed.problems;
+    } catch {
+      // Not the JSON it claimed to be; keep the raw text.
+    }
+  }
+  const error: Error & { code?: number; reason?: string; retryAfterSecs?: number; problems?: ConfigProblem[] } = new Error(message);
+  const code = res.headers.get("X-Blink-Error-Code");
+  if (code) error.code = Number(code);
+  const retryAfter = res.headers.get("Retry-After");
+  if (retryAfter && Number.isFinite(Number(retryAfter))) error.retryAfterSecs = Number(retryAfter);
+  if (reason) error.reason = reason;
+  if (problems) error.problems = problems;
+  return error;
+}
+
 async function apiFetchText(path: string, options: RequestInit = {}) {
   const headers: HeadersInit = {
     ...(options.body ? { "Content-Type": "application/json" } : {}),
@@ -31,8 +734,7 @@ async function apiFetchText(path: string, options: RequestInit = {}) {
   };
   const res = await fetch(`${apiBase}${path}`, { ...options, headers });
   if (!res.ok) {
-    const message = await res.text();
-    throw new Error(message || res.statusText);
+    throw await responseError(res);
   }
   return res.text();
 }
@@ -44,15 +746,52 @@ async function apiFetchJson<T>(path: string, options: RequestInit = {}) {
   };
   const res = await fetch(`${apiBase}${path}`, { ...options, headers });
   if (!res.ok) {
-    const message = await res.text();
-    throw new Error(message || res.statusText);
+    throw await responseError(res);
   }
   return res.json() as Promise<T>;
 }
 
+type CommandErrorKind = "auth_expired" | "reauth_required" | "rate_limited" | "camera_busy" | "network" | "not_found" | "invalid_input" | "permission_denied" | "already_exists" | "path_not_allowed" | "internal";
+
+type CommandErrorPayload = {
+  kind: CommandErrorKind;
+  message: string;
+  retryable: boolean;
+  code?: number;
+  retry_after_secs?: number;
+  problems?: ConfigProblem[];
+};
+
+export class CommandError extends Error {
+  kind: CommandErrorKind;
+  retryable: boolean;
+  code?: number;
+  retryAfterSecs?: number;
+  problems?: ConfigProblem[];
+
+  constructor(payload: CommandErrorPayload) {
+    super(payload.message);
+    this.name = "CommandError";
+    this.kind = payload.kind;
+    this.retryable = payload.retryable;
+    this.code = payload.code;
+    this.retryAfterSecs = payload.retry_after_secs;
+    this.problems = payload.problems;
+  }
+}
+
+function isCommandErrorPayload(value: unknown): value is CommandErrorPayload {
+  return typeof value === "object" && value !== null && "kind" in value && "message" in value;
+}
+
 async function tauriInvoke<T>(command: string, args?: Record<string, any>) {
   const { invoke } = await import("@tauri-apps/api/core");
-  return invoke<T>(command, args);
+  try {
+    return await invoke<T>(command, args);
+  } catch (e) {
+    // Commands reject with a structured payload; keep it inspectable but Error-shaped.
+    throw isCommandErrorPayload(e) ? new CommandError(e) : e;
+  }
 }
 
 async function tauriListen<T>(event: string, handler: (event: { payload: T }) => void) {
@@ -70,18 +809,101 @@ const apiClient = {
     }
     return apiBase;
   },
+  /** Desktop only: streams MPEG-TS chunks straight from the backend, no localhost relay. */
+  async startLiveview(
+    camera: { network_id: number; id: number; product_type: string; serial?: string },
+    record: boolean,
+    onChunk: (chunk: ArrayBuffer) => void,
+    options: { quality?: "saver" | "best"; audio?: boolean } = {},
+  ) {
+    if (!isDesktop) throw new Error("Native liveview is only available in the desktop app");
+    const { Channel } = await import("@tauri-apps/api/core");
+    const channel = new Channel<ArrayBuffer>();
+    channel.onmessage = onChunk;
+    return tauriInvoke<string>("start_liveview", {
+      networkId: camera.network_id,
+      cameraId: camera.id,
+      productType: camera.product_type,
+      serial: camera.serial ?? null,
+      record,
+      quality: options.quality ?? null,
+      audio: options.audio ?? null,
+      onChunk: channel,
+    });
+  },
+  async stopLiveview(sessionId: string) {
+    if (!isDesktop) return;
+    return tauriInvoke<void>("stop_liveview", { sessionId });
+  },
+  /** Saves the last seconds of a live session. `dest` is required on desktop and must be in a download folder; the server picks a file under its data directory. */
+  async saveReplay(sessionId: string, dest?: string, overwrite = false) {
+    if (isDesktop) {
+      if (!dest) throw new Error("Choose where to save the replay");
+      return tauriInvoke<ReplaySaved>("save_replay", { sessionId, dest, overwrite });
+    }
+    return apiFetchJson<ReplaySaved>(`/live-sessions/${encodeURIComponent(sessionId)}/replay`, { method: "POST" });
+  },
+  /** Live sessions recorded to `liveview_recording_dir`, newest first. */
+  async listRecordings() {
+    if (isDesktop) return tauriInvoke<RecordingInfo[]>("list_recordings");
+    return apiFetchJson<RecordingInfo[]>("/recordings");
+  },
   async getServerPort() {
     if (!isDesktop) return null;
     return tauriInvoke<number>("get_server_port");
   },
+  /** Desktop only, and `dest` must be in a download folder; the server uses `blink-monitor-server export`. */
+  async exportAppState(dest: string, passphrase: string, includeMediaIndex = false, overwrite = false) {
+    if (!isDesktop) throw new Error("Use `blink-monitor-server export` on the server");
+    return tauriInvoke<StateExportSummary>("export_app_state", { dest, passphrase, includeMediaIndex, overwrite });
+  },
+  /** Desktop only; the server uses `blink-monitor-server import`. */
+  async importAppState(src: string, passphrase: string) {
+    if (!isDesktop) throw new Error("Use `blink-monitor-server import` on the server");
+    return tauriInvoke<StateImportSummary>("import_app_state", { src, passphrase });
+  },
+  async getServerStatus() {
+    if (!isDesktop) return null;
+    return tauriInvoke<ServerStatus>("get_server_status");
+  },
+  /** Desktop only; `onServerRestarted` reports the new port. */
+  async restartServer() {
+    if (!isDesktop) return;
+    return tauriInvoke<void>("restart_server");
+  },
+  /** Desktop only: the embedded server came back up, possibly on a new port. */
+  async onServerRestarted(handler: (port: number) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<number>("server-restarted", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
   async checkAuth() {
-    if (isDesktop) return tauriInvoke<boolean>("check_auth");
-    return apiFetchJson<boolean>("/check-auth");
+    if (isDesktop) return tauriInvoke<AuthState>("check_auth");
+    return apiFetchJson<AuthState>("/check-auth");
   },
   async login(email: string, password: string) {
     if (isDesktop) return tauriInvoke<string>("login", { email, password });
     return apiFetchText("/login", { method: "POST", body: JSON.stringify({ email, password }) });
   },
+  async getAmazonSigninUrl() {
+    if (isDesktop) return tauriInvoke<string | null>("get_amazon_signin_url");
+    return apiFetchJson<string | null>("/login/amazon");
+  },
+  async loginAmazon(email: string, password: string) {
+    if (isDesktop) return tauriInvoke<string>("login_amazon", { email, password });
+    return apiFetchText("/login/amazon", { method: "POST", body: JSON.stringify({ email, password }) });
+  },
+  /** For when Amazon wants a one-time code or captcha: sign in on `getAmazonSigninUrl()` in a browser, then pass where it ended. */
+  async completeAmazonLogin(callbackUrl: string) {
+    if (isDesktop) return tauriInvoke<string>("complete_amazon_login", { callbackUrl });
+    return apiFetchText("/login/amazon/callback", { method: "POST", body: JSON.stringify({ url: callbackUrl }) });
+  },
   async verifyPin(pin: string) {
     if (isDesktop) return tauriInvoke<string>("verify_pin", { pin });
     return apiFetchText("/verify-pin", { method: "POST", body: JSON.stringify({ pin }) });
@@ -90,33 +912,272 @@ const apiClient = {
     if (isDesktop) return tauriInvoke<string>("logout");
     await apiFetchText("/logout", { method: "POST" });
   },
+  async listTrustedClients() {
+    if (isDesktop) return tauriInvoke<TrustedClient[]>("list_trusted_clients");
+    return apiFetchJson<TrustedClient[]>("/trusted-clients");
+  },
+  /** Without `force`, the current client is left alone and `warning` explains why. */
+  async revokeTrustedClient(id: number, force = false) {
+    if (isDesktop) return tauriInvoke<RevokeOutcome>("revoke_trusted_client", { id, force });
+    return apiFetchJson<RevokeOutcome>(`/trusted-clients/${id}?force=${force}`, { method: "DELETE" });
+  },
   async getRawHomescreen() {
     if (isDesktop) return tauriInvoke<string>("get_raw_homescreen");
     return apiFetchText("/homescreen");
   },
-  async getRawMediaPage(page: number, sinceDays: number) {
-    if (isDesktop) return tauriInvoke<string>("get_raw_media_page", { page, sinceDays });
-    return apiFetchText(`/media?page=${page}&sinceDays=${sinceDays}`);
+  async getRawMediaPage(page: number, sinceDays: number, pageSize?: number) {
+    if (isDesktop) return tauriInvoke<string>("get_raw_media_page", { page, sinceDays, pageSize });
+    const size = pageSize != null ? `&pageSize=${pageSize}` : "";
+    return apiFetchText(`/media?page=${page}&sinceDays=${sinceDays}${size}`);
+  },
+  async searchLocalMedia(filter: MediaFilter = {}) {
+    if (isDesktop) return tauriInvoke<MediaSearchPage>("search_local_media", { filter });
+    const params = new URLSearchParams();
+    if (filter.from) params.set("from", filter.from);
+    if (filter.to) params.set("to", filter.to);
+    if (filter.camera_id != null) params.set("cameraId", String(filter.camera_id));
+    if (filter.network_id != null) params.set("networkId", String(filter.network_id));
+    if (filter.text) params.set("text", filter.text);
+    if (filter.include_deleted) params.set("includeDeleted", "true");
+    if (filter.page != null) params.set("page", String(filter.page));
+    if (filter.page_size != null) params.set("pageSize", String(filter.page_size));
+    return apiFetchJson<MediaSearchPage>(`/media/index?${params}`);
+  },
+  async getTimeline(sinceDays?: number, gapSecs?: number) {
+    if (isDesktop) return tauriInvoke<TimelineEvent[]>("get_timeline", { sinceDays, gapSecs });
+    const params = new URLSearchParams();
+    if (sinceDays != null) params.set("sinceDays", String(sinceDays));
+    if (gapSecs != null) params.set("gap_secs", String(gapSecs));
+    return apiFetchJson<TimelineEvent[]>(`/timeline?${params}`);
+  },
+  async rebuildMediaIndex() {
+    if (isDesktop) return tauriInvoke<number>("rebuild_media_index");
+    return apiFetchJson<number>("/media/index/rebuild", { method: "POST" });
+  },
+  async setNetworkArm(networkId: number, arm: boolean, timeoutSecs?: number) {
+    if (isDesktop) return tauriInvoke<ArmVerification>("set_network_arm", { networkId, arm, timeoutSecs });
+    return apiFetchJson<ArmVerification>("/set-arm", { method: "POST", body: JSON.stringify({ networkId, arm, timeoutSecs }) });
+  },
+  async setAllNetworksArm(arm: boolean) {
+    if (isDesktop) return tauriInvoke<NetworkArmResult[]>("set_all_networks_arm", { arm });
+    return apiFetchJson<NetworkArmResult[]>("/set-arm-all", { method: "POST", body: JSON.stringify({ arm }) });
+  },
+  async disarmFor(networkId: number, minutes: number) {
+    if (isDesktop) return tauriInvoke<TemporaryDisarm>("disarm_for", { networkId, minutes });
+    return apiFetchJson<TemporaryDisarm>("/disarm-temporary", { method: "POST", body: JSON.stringify({ networkId, minutes }) });
+  },
+  async getPendingRearms() {
+    if (isDesktop) return tauriInvoke<PendingRearm[]>("get_pending_rearms");
+    return apiFetchJson<PendingRearm[]>("/disarm-temporary");
+  },
+  async extendRearm(networkId: number, minutes: number) {
+    if (isDesktop) return tauriInvoke<PendingRearm>("extend_rearm", { networkId, minutes });
+    return apiFetchJson<PendingRearm>("/disarm-temporary/extend", { method: "POST", body: JSON.stringify({ networkId, minutes }) });
+  },
+  async cancelRearm(networkId: number) {
+    if (isDesktop) return tauriInvoke<PendingRearm>("cancel_rearm", { networkId });
+    return apiFetchJson<PendingRearm>(`/disarm-temporary/${networkId}`, { method: "DELETE" });
+  },
+  async getFirmwareReport() {
+    if (isDesktop) return tauriInvoke<FirmwareRecord[]>("get_firmware_report");
+    return apiFetchJson<FirmwareRecord[]>("/firmware");
+  },
+  async runSelfTest() {
+    if (isDesktop) return tauriInvoke<SelfTestReport>("run_self_test");
+    return apiFetchJson<SelfTestReport>("/self-test");
+  },
+  async setLogLevel(target: LogTarget, level: LogLevel, durationSecs: number) {
+    if (isDesktop) return tauriInvoke<LevelOverride>("set_log_level", { target, level, durationSecs });
+    return apiFetchJson<LevelOverride>("/log-level", { method: "POST", body: JSON.stringify({ target, level, durationSecs }) });
+  },
+  async startDebugCapture(targets: LogTarget[], durationSecs: number) {
+    if (isDesktop) return tauriInvoke<string>("start_debug_capture", { targets, durationSecs });
+    const res = await apiFetchJson<{ path: string }>("/debug-capture", { method: "POST", body: JSON.stringify({ targets, durationSecs }) });
+    return res.path;
+  },
+  async getBatteryHistory(cameraId: number, days?: number) {
+    if (isDesktop) return tauriInvoke<BatteryHistory>("get_battery_history", { cameraId, days });
+    const params = new URLSearchParams({ camera_id: String(cameraId) });
+    if (days != null) params.set("days", String(days));
+    return apiFetchJson<BatteryHistory>(`/battery-history?${params}`);
+  },
+  /** `from`/`to` are RFC 3339; the range defaults to the last 28 days. */
+  async getActivityHeatmap(cameraId: number, from?: string, to?: string) {
+    if (isDesktop) return tauriInvoke<ActivityHeatmap>("get_activity_heatmap", { cameraId, from, to });
+    const params = new URLSearchParams({ cameraId: String(cameraId) });
+    if (from) params.set("from", from);
+    if (to) params.set("to", to);
+    return apiFetchJson<ActivityHeatmap>(`/heatmap?${params}`);
+  },
+  async getUsage() {
+    if (isDesktop) return tauriInvoke<UsageReport>("get_usage");
+    return apiFetchJson<UsageReport>("/usage");
+  },
+  /** `queued` only matters on the server; the desktop app queues anything over `delete_batch_size`. */
+  async deleteMediaItems(items: any[], deferSecs?: number, queued = false) {
+    if (isDesktop) return tauriInvoke<DeleteResult[]>("delete_media_items", { items, deferSecs });
+    const query = queued ? "?queued=true" : "";
+    return apiFetchJson<DeleteResult[]>(`/delete-media${query}`, { method: "POST", body: JSON.stringify({ items, deferSecs }) });
+  },
+  async getDeleteQueue() {
+    if (isDesktop) return tauriInvoke<DeleteProgress[]>("get_delete_queue");
+    return apiFetchJson<DeleteProgress[]>("/delete-media/queue");
+  },
+  async cancelDeleteJob(jobId: string) {
+    if (isDesktop) return tauriInvoke<DeleteProgress>("cancel_delete_job", { jobId });
+    return apiFetchJson<DeleteProgress>(`/delete-media/queue/${encodeURIComponent(jobId)}`, { method: "DELETE" });
+  },
+  /** Desktop only: a queued delete finished a batch or was cancelled. Poll `getDeleteQueue` on the server. */
+  async onDeleteProgress(handler: (progress: DeleteProgress) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<DeleteProgress>("delete-progress", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  /** Server only: a whole-account takeout runs in the background; poll `getTakeout`. */
+  async startTakeout(useArchiveStore = false) {
+    if (isDesktop) throw new Error("Takeout is only available on the server");
+    return apiFetchJson<TakeoutProgress>("/takeout", { method: "POST", body: JSON.stringify({ useArchiveStore }) });
+  },
+  async getTakeout(id: string) {
+    if (isDesktop) throw new Error("Takeout is only available on the server");
+    return apiFetchJson<TakeoutProgress>(`/takeout/${encodeURIComponent(id)}`);
+  },
+  async cancelTakeout(id: string) {
+    if (isDesktop) throw new Error("Takeout is only available on the server");
+    return apiFetchJson<TakeoutProgress>(`/takeout/${encodeURIComponent(id)}`, { method: "DELETE" });
+  },
+  async undoDelete(ids: number[]) {
+    if (isDesktop) return tauriInvoke<number[]>("undo_delete", { ids });
+    return apiFetchJson<number[]>("/delete-media/undo", { method: "POST", body: JSON.stringify({ ids }) });
+  },
+  /** Requires `diagnostics_enabled`. */
+  async diagnoseMedia(item: any) {
+    if (isDesktop) return tauriInvoke<MediaDiagnosis>("diagnose_media", { item });
+    return apiFetchJson<MediaDiagnosis>("/diagnose-media", { method: "POST", body: JSON.stringify(item) });
+  },
+  async previewWebhook(preview: { format?: WebhookTarget["format"]; template?: string; event?: unknown }) {
+    if (isDesktop) throw new Error("Webhooks are only sent by the hosted server");
+    return apiFetchJson<WebhookPayload>("/webhooks/preview", { method: "POST", body: JSON.stringify(preview) });
   },
-  async setNetworkArm(networkId: number, arm: boolean) {
-    if (isDesktop) return tauriInvoke<string>("set_network_arm", { networkId, arm });
-    return apiFetchText("/set-arm", { method: "POST", body: JSON.stringify({ networkId, arm }) });
+  /** Sends a sample alert to `target`, or to every configured notifier when omitted. */
+  async testNotify(target?: NotifierTarget, kind?: AlertKind) {
+    if (isDesktop) throw new Error("Push notifications are only sent by the hosted server");
+    return apiFetchJson<NotifyResult[]>("/notify/test", { method: "POST", body: JSON.stringify({ target, kind }) });
   },
-  async deleteMediaItems(items: any[]) {
-    if (isDesktop) return tauriInvoke<number[]>("delete_media_items", { items });
-    return apiFetchJson<number[]>("/delete-media", { method: "POST", body: JSON.stringify({ items }) });
+  async getCleanupQueue() {
+    if (isDesktop) return tauriInvoke<CleanupEntry[]>("get_cleanup_queue");
+    return apiFetchJson<CleanupEntry[]>("/cleanup-queue");
+  },
+  async listArchive() {
+    if (isDesktop) return tauriInvoke<StoredClip[]>("list_archive");
+    return apiFetchJson<StoredClip[]>("/archive/list");
+  },
+  async getPendingDeletions() {
+    if (isDesktop) return tauriInvoke<PendingDeletion[]>("get_pending_deletions");
+    return apiFetchJson<PendingDeletion[]>("/delete-media/pending");
+  },
+  async markWatched(ids: number[]) {
+    if (isDesktop) return tauriInvoke<void>("mark_watched", { ids });
+    await apiFetchText("/media/watched", { method: "POST", body: JSON.stringify({ ids }) });
   },
   async getThumbnailBase64(path: string) {
     if (isDesktop) return tauriInvoke<string>("get_thumbnail_base64", { path });
     return apiFetchText(`/thumbnail-base64?path=${encodeURIComponent(path)}`);
   },
+  /** Like `getThumbnailBase64`, plus where the image came from so stale tiles can be marked. */
+  async getThumbnail(path: string) {
+    if (isDesktop) return tauriInvoke<ThumbnailData>("get_thumbnail", { path });
+    const res = await fetch(`${apiBase}/thumbnail-base64?path=${encodeURIComponent(path)}`);
+    if (!res.ok) {
+      throw await responseError(res);
+    }
+    const cache = (res.headers.get("X-Blink-Cache") ?? "fresh") as ThumbnailCacheStatus;
+    return { data_url: await res.text(), cache } as ThumbnailData;
+  },
+  /** Warms the thumbnail cache for a whole grid at once; on desktop each tile also gets `onThumbnailReady` or `onThumbnailFailed` as it finishes. */
+  async prefetchThumbnails(paths: string[]) {
+    if (isDesktop) return tauriInvoke<ThumbnailPrefetchResult[]>("prefetch_thumbnails", { paths });
+    return apiFetchJson<ThumbnailPrefetchResult[]>("/thumbnails/prefetch", { method: "POST", body: JSON.stringify({ paths }) });
+  },
+  async getThumbnailUrl(path: string) {
+    if (isDesktop) return tauriInvoke<string>("get_thumbnail_url", { path });
+    return apiFetchText(`/thumbnail-base64?path=${encodeURIComponent(path)}`);
+  },
+  /** Where a `<video>` plays a clip from: a signed localhost URL on desktop, the proxy route on the web. */
+  async getClipUrl(url: string) {
+    if (isDesktop) return tauriInvoke<string>("get_clip_url", { url });
+    return `${apiBase}/proxy?url=${encodeURIComponent(url)}`;
+  },
+  async getThumbnailCacheStats() {
+    return tauriInvoke<ThumbnailCacheStats>("get_thumbnail_cache_stats");
+  },
+  async clearThumbnailCache() {
+    return tauriInvoke<number>("clear_thumbnail_cache");
+  },
+  async refreshCameraThumbnail(networkId: number, cameraId: number, productType: string, thumbnail?: string) {
+    if (isDesktop) return tauriInvoke<void>("refresh_camera_thumbnail", { networkId, cameraId, productType, thumbnail });
+    await apiFetchText("/camera-thumbnail", { method: "POST", body: JSON.stringify({ networkId, cameraId, productType }) });
+  },
   async getCameraConfig(networkId: number, cameraId: number, productType: string) {
     if (isDesktop) return tauriInvoke<any>("get_camera_config", { networkId, cameraId, productType });
     return apiFetchJson<any>(`/camera-config?networkId=${networkId}&cameraId=${cameraId}&productType=${encodeURIComponent(productType)}`);
   },
-  async updateCameraConfig(networkId: number, cameraId: number, productType: string, config: any) {
-    if (isDesktop) return tauriInvoke<void>("update_camera_config", { networkId, cameraId, productType, config });
-    await apiFetchText("/camera-config", { method: "POST", body: JSON.stringify({ networkId, cameraId, productType, config }) });
+  /** Unknown keys and out-of-range values are refused before anything reaches Blink unless `allowUnknown`. */
+  async updateCameraConfig(networkId: number, cameraId: number, productType: string, config: any, allowUnknown = false) {
+    if (isDesktop) return tauriInvoke<void>("update_camera_config", { networkId, cameraId, productType, config, allowUnknown });
+    await apiFetchText("/camera-config", { method: "POST", body: JSON.stringify({ networkId, cameraId, productType, config, allowUnknown }) });
+  },
+  /** Desktop only, and only with `advanced_settings_enabled`. */
+  async setDiscoveredSetting(path: string, value: boolean | number | string) {
+    return tauriInvoke<DiscoveredSettingResult>("set_discovered_setting", { path, value });
+  },
+  async getLiveviewSettings(networkId: number) {
+    if (isDesktop) return tauriInvoke<LiveviewSetting[]>("get_liveview_settings", { networkId });
+    return apiFetchJson<LiveviewSetting[]>(`/liveview-settings?networkId=${networkId}`);
+  },
+  /** `deviceId` picks the device for per-device flags when the network has several. */
+  async setLiveviewSetting(networkId: number, flag: LiveviewFlag, value: boolean | number, deviceId?: number) {
+    if (isDesktop) return tauriInvoke<LiveviewSettingWrite>("set_liveview_setting", { networkId, flag, deviceId, value });
+    return apiFetchJson<LiveviewSettingWrite>("/liveview-settings", { method: "POST", body: JSON.stringify({ networkId, flag, deviceId, value }) });
+  },
+  async getMotionNotifications(networkId: number) {
+    if (isDesktop) return tauriInvoke<MotionNotifications>("get_motion_notifications", { networkId });
+    return apiFetchJson<MotionNotifications>(`/motion-notifications?networkId=${networkId}`);
+  },
+  async setMotionNotifications(networkId: number, enabled: boolean) {
+    if (isDesktop) return tauriInvoke<MotionNotificationWrite>("set_motion_notifications", { networkId, enabled });
+    return apiFetchJson<MotionNotificationWrite>("/motion-notifications", { method: "POST", body: JSON.stringify({ networkId, enabled }) });
+  },
+  async getSyncModuleStorage(networkId: number, syncModuleId: number) {
+    if (isDesktop) return tauriInvoke<LocalStorageStatus>("get_sync_module_storage", { networkId, syncModuleId });
+    return apiFetchJson<LocalStorageStatus>(`/sync-module-storage?networkId=${networkId}&syncModuleId=${syncModuleId}`);
+  },
+  async ejectUsb(networkId: number, syncModuleId: number) {
+    if (isDesktop) return tauriInvoke<UsbEjectResult>("eject_usb", { networkId, syncModuleId });
+    return apiFetchJson<UsbEjectResult>("/sync-module-storage/eject", { method: "POST", body: JSON.stringify({ networkId, syncModuleId }) });
+  },
+  async getNetworkSettings(networkId: number) {
+    if (isDesktop) return tauriInvoke<NetworkSettings>("get_network_settings", { networkId });
+    return apiFetchJson<NetworkSettings>(`/network-settings?networkId=${networkId}`);
+  },
+  async updateNetworkSettings(networkId: number, patch: NetworkSettingsPatch) {
+    if (isDesktop) return tauriInvoke<NetworkSettings>("update_network_settings", { networkId, patch });
+    return apiFetchJson<NetworkSettings>("/network-settings", { method: "POST", body: JSON.stringify({ networkId, ...patch }) });
+  },
+  async getNotificationConfig() {
+    if (isDesktop) return tauriInvoke<NotificationConfig>("get_notification_config");
+    return apiFetchJson<NotificationConfig>("/notifications");
+  },
+  /** Send the whole object from `getNotificationConfig` back; it replaces the account's settings. */
+  async updateNotificationConfig(config: NotificationConfig) {
+    if (isDesktop) return tauriInvoke<void>("update_notification_config", { config });
+    await apiFetchText("/notifications", { method: "POST", body: JSON.stringify(config) });
   },
   async resolveNotificationIcon() {
     if (isDesktop) {
@@ -152,6 +1213,158 @@ const apiClient = {
     if (Notification.permission !== "granted") return;
     new Notification(payload.title, { body: payload.body, icon: payload.icon });
   },
+  async getMotionAlerts() {
+    return tauriInvoke<MotionAlertState>("get_motion_alerts");
+  },
+  async setMotionAlerts(enabled: boolean, intervalSecs?: number) {
+    return tauriInvoke<MotionAlertState>("set_motion_alerts", { enabled, intervalSecs });
+  },
+  async getCloseToTray() {
+    return tauriInvoke<boolean>("get_close_to_tray");
+  },
+  async setCloseToTray(enabled: boolean) {
+    return tauriInvoke<void>("set_close_to_tray", { enabled });
+  },
+  async getSettings() {
+    if (isDesktop) return tauriInvoke<Settings>("get_settings");
+    return apiFetchJson<Settings>("/settings");
+  },
+  async updateSettings(patch: Partial<Settings>) {
+    if (isDesktop) return tauriInvoke<Settings>("update_settings", { patch });
+    return apiFetchJson<Settings>("/settings", { method: "PATCH", body: JSON.stringify(patch) });
+  },
+  async getCameraPrefs() {
+    if (isDesktop) return tauriInvoke<Record<string, CameraPrefs>>("get_camera_prefs");
+    return apiFetchJson<Record<string, CameraPrefs>>("/camera-prefs");
+  },
+  async setCameraPrefs(cameraId: number, prefs: CameraPrefs) {
+    if (isDesktop) return tauriInvoke<Record<string, CameraPrefs>>("set_camera_prefs", { cameraId, prefs });
+    return apiFetchJson<Record<string, CameraPrefs>>(`/camera-prefs/${cameraId}`, { method: "PUT", body: JSON.stringify(prefs) });
+  },
+  async getAuditLog(limit?: number, filter: AuditFilter = {}) {
+    if (isDesktop) return tauriInvoke<AuditEntry[]>("get_audit_log", { limit, filter });
+    const params = new URLSearchParams();
+    if (limit != null) params.set("limit", String(limit));
+    if (filter.action) params.set("action", filter.action);
+    if (filter.target) params.set("target", filter.target);
+    if (filter.source) params.set("source", filter.source);
+    if (filter.since) params.set("since", filter.since);
+    if (filter.until) params.set("until", filter.until);
+    if (filter.failed_only) params.set("failedOnly", "true");
+    return apiFetchJson<AuditEntry[]>(`/audit?${params}`);
+  },
+  async getSchedules() {
+    if (isDesktop) return tauriInvoke<ScheduleOverview>("get_schedules");
+    return apiFetchJson<ScheduleOverview>("/schedules");
+  },
+  async saveScheduleEntry(entry: ScheduleEntry) {
+    if (isDesktop) return tauriInvoke<ScheduleOverview>("save_schedule_entry", { entry });
+    return apiFetchJson<ScheduleOverview>("/schedules", { method: "POST", body: JSON.stringify(entry) });
+  },
+  async deleteScheduleEntry(id: string) {
+    if (isDesktop) return tauriInvoke<ScheduleOverview>("delete_schedule_entry", { id });
+    return apiFetchJson<ScheduleOverview>(`/schedules/${encodeURIComponent(id)}`, { method: "DELETE" });
+  },
+  async onScheduleExecuted(handler: (run: ScheduleRun) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<ScheduleRun>("schedule-executed", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onLiveStats(handler: (session: LiveSessionInfo) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<LiveSessionInfo>("live-stats", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  /** Desktop only; on the server the same payload is a `live_expiring` event and `expires_at` on the session. */
+  async onLiveExpiring(handler: (expiring: LiveExpiring) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<LiveExpiring>("live-expiring", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onThumbnailUpdated(handler: (run: ThumbnailRefreshRun) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<ThumbnailRefreshRun>("thumbnail-updated", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onThumbnailReady(handler: (ready: ThumbnailReady) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<ThumbnailReady>("thumbnail-ready", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onThumbnailFailed(handler: (failed: ThumbnailFailed) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<ThumbnailFailed>("thumbnail-failed", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onSettingsChanged(handler: (settings: Settings) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<Settings>("settings-changed", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onAuthExpired(handler: (error: CommandError) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<CommandErrorPayload>("auth-expired", (event) => {
+      handler(new CommandError(event.payload));
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async onMotionClips(handler: (clips: MotionClip[]) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<MotionClip[]>("motion-clips", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
   async onDownloadProgress(handler: (event: DownloadProgressEvent) => void) {
     if (!isDesktop) {
       return () => {};
@@ -163,22 +1376,98 @@ const apiClient = {
       unlisten();
     };
   },
-  async downloadClip(options: DownloadOptions): Promise<boolean> {
-    const { url, defaultFileName, downloadId, onProgress } = options;
+  async enqueueDownloads(items: { item: any; dir: string }[]) {
+    return tauriInvoke<string[]>("enqueue_downloads", { items });
+  },
+  /** Folder picker whose choice the desktop app allows `enqueueDownloads` to write to. */
+  async chooseDownloadDir() {
+    return tauriInvoke<string | null>("choose_download_dir");
+  },
+  async getDownloadJobs() {
+    return tauriInvoke<DownloadJob[]>("get_download_jobs");
+  },
+  async clearDownloadJobs() {
+    return tauriInvoke<void>("clear_download_jobs");
+  },
+  async pauseDownloads() {
+    return tauriInvoke<void>("pause_downloads");
+  },
+  async resumeDownloads() {
+    return tauriInvoke<void>("resume_downloads");
+  },
+  async cancelDownloadJob(jobId: string) {
+    return tauriInvoke<boolean>("cancel_download_job", { jobId });
+  },
+  async setDownloadConcurrency(limit: number) {
+    return tauriInvoke<void>("set_download_concurrency", { limit });
+  },
+  async onDownloadQueue(handler: (event: DownloadQueueEvent) => void) {
+    if (!isDesktop) {
+      return () => {};
+    }
+    const unlisten = await tauriListen<DownloadQueueEvent>("download-queue", (event) => {
+      handler(event.payload);
+    });
+    return () => {
+      unlisten();
+    };
+  },
+  async exportClipsZip(items: any[], compression: "store" | "deflate" = "store") {
     if (isDesktop) {
-      const { save } = await import("@tauri-apps/plugin-dialog");
-      const path = await save({
-        defaultPath: defaultFileName,
-        filters: [{ name: "Video", extensions: ["mp4"] }]
+      // The dialog runs on the Rust side so the chosen folder is granted for exports.
+      const destPath = await tauriInvoke<string | null>("choose_download_path", {
+        defaultName: "blink-export.zip",
+        filterName: "ZIP archive",
+        extensions: ["zip"]
       });
+      if (!destPath) return false;
+      // The native dialog already asked before replacing an existing file.
+      await tauriInvoke("export_clips_zip", { items, destPath, compression, overwrite: true });
+      return true;
+    }
+    const res = await fetch(`${apiBase}/export`, {
+      method: "POST",
+      headers: { "Content-Type": "application/json" },
+      body: JSON.stringify({ items, compression })
+    });
+    if (!res.ok) {
+      const message = await res.text();
+      throw new Error(message || res.statusText);
+    }
+    const blob = await res.blob();
+    const link = document.createElement("a");
+    const objectUrl = URL.createObjectURL(blob);
+    link.href = objectUrl;
+    link.download = "blink-export.zip";
+    document.body.appendChild(link);
+    link.click();
+    link.remove();
+    setTimeout(() => URL.revokeObjectURL(objectUrl), 1000);
+    return true;
+  },
+  async cancelDownload(downloadId: string) {
+    if (!isDesktop) return false;
+    return tauriInvoke<boolean>("cancel_download", { downloadId });
+  },
+  async downloadClip(options: DownloadOptions): Promise<boolean> {
+    const { item, url, defaultFileName, downloadId, onProgress } = options;
+    if (isDesktop) {
+      // The dialog runs on the Rust side so the chosen folder is granted for downloads.
+      const defaultName = await tauriInvoke<string>("clip_file_name", { item }).catch(() => defaultFileName);
+      const path = await tauriInvoke<string | null>("choose_download_path", { defaultName });
       if (!path) return false;
       const id = downloadId ?? (
         typeof crypto !== "undefined" && "randomUUID" in crypto
           ? crypto.randomUUID()
           : `download-${Date.now()}`
       );
-      await tauriInvoke("download_clip_with_progress", { url, path, download_id: id });
-      return true;
+      return tauriInvoke<boolean>("download_clip_with_progress", {
+        item: { ...item, media: url },
+        path,
+        download_id: id,
+        // The native dialog already asked before replacing an existing file.
+        overwrite: true
+      });
     }
 
     const res = await fetch(`${apiBase}/proxy?url=${encodeURIComponent(url)}`);